@@ -0,0 +1,55 @@
+//! Times parsing and evaluating rules with large inline `is in` lists.
+//!
+//! Run with `cargo bench --bench large_list`.
+
+use engine::runner::evaluator::evaluate;
+use engine::runner::options::EvaluationOptions;
+use engine::runner::parser::{parse_rules_with_limits, ParseLimits};
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 5;
+
+fn rule_with_list(size: usize) -> String {
+    let items: Vec<String> = (0..size).map(|i| format!("\"code-{}\"", i)).collect();
+    format!(
+        r#"A **user** is allowed if __code__ of **user** is in [{}]."#,
+        items.join(", ")
+    )
+}
+
+fn average(f: impl Fn()) -> Duration {
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    started.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let limits = ParseLimits {
+        max_list_elements: usize::MAX,
+        ..ParseLimits::default()
+    };
+    // The last element, so evaluation scans the whole list
+    let data = |size: usize| json!({"user": {"code": format!("code-{}", size - 1)}});
+
+    for size in [1_000, 10_000, 40_000, 100_000] {
+        let input = rule_with_list(size);
+        let parse = average(|| {
+            parse_rules_with_limits(&input, &limits).unwrap();
+        });
+
+        let rule_set = parse_rules_with_limits(&input, &limits).unwrap();
+        let json = data(size);
+        let evaluate = average(|| {
+            let outcome = evaluate(&rule_set, &json, &EvaluationOptions::new());
+            assert!(outcome.outcomes["allowed"]);
+        });
+
+        println!(
+            "{:>7} elements: parse {:>10.2?}  evaluate {:>10.2?}",
+            size, parse, evaluate
+        );
+    }
+}
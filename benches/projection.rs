@@ -0,0 +1,118 @@
+//! Compares the memory and time of parsing a 50 MB payload whole with keeping only the
+//! three fields a policy reads, see `RuleSet::data_projection`.
+//!
+//! Run with `cargo bench --bench projection`.
+
+use engine::runner::evaluator::evaluate;
+use engine::runner::options::{EvaluationOptions, DEFAULT_MAX_SCANNED_KEYS};
+use engine::runner::parser::parse_rules;
+use serde::de::DeserializeSeed;
+use serde_json::{json, Value};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Counts the bytes allocated and the most allocated at once
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let pointer = unsafe { System.alloc(layout) };
+        if !pointer.is_null() {
+            let now = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        pointer
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(pointer, layout) };
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const TARGET_BYTES: usize = 50 * 1024 * 1024;
+
+const POLICY: &str = r#"
+A **claim** is approved
+  if the __amount__ of the **claim** is less than 10000
+  and the __currency__ of the **claim** is in ["GBP", "EUR"]
+  and the __status__ of the **holder** is equal to "active".
+"#;
+
+/// A claim with the three fields the policy reads, among event records filling it to about
+/// `TARGET_BYTES`
+fn payload() -> String {
+    let event = |i: usize| {
+        json!({
+            "id": i,
+            "kind": "page_view",
+            "at": "2024-05-01T10:00:00Z",
+            "attributes": {"path": format!("/claims/{}/documents", i), "agent": "Mozilla/5.0 (X11; Linux x86_64)"},
+            "notes": "lorem ipsum dolor sit amet, consectetur adipiscing elit"
+        })
+    };
+    let size = event(0).to_string().len() + 1;
+    let events: Vec<Value> = (0..TARGET_BYTES / size).map(event).collect();
+    json!({
+        "claim": {"amount": 4200, "currency": "GBP", "events": events},
+        "holder": {"status": "active", "address": {"country": "GB", "lines": ["1 High St"]}},
+    })
+    .to_string()
+}
+
+/// How long `f` takes and the most it allocates beyond what was allocated before it
+fn measure<T>(f: impl FnOnce() -> T) -> (T, Duration, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let started = Instant::now();
+    let value = f();
+    let elapsed = started.elapsed();
+    (value, elapsed, PEAK.load(Ordering::Relaxed) - before)
+}
+
+fn kib(bytes: usize) -> f64 {
+    bytes as f64 / 1024.0
+}
+
+fn main() {
+    let rule_set = parse_rules(POLICY).unwrap();
+    let projection = rule_set
+        .data_projection()
+        .expect("the policy reads only known paths");
+    let text = payload();
+    println!("payload: {:.0} KiB", kib(text.len()));
+
+    let (full, full_time, full_peak) = measure(|| serde_json::from_str::<Value>(&text).unwrap());
+    let (pruned, pruned_time, pruned_peak) = measure(|| {
+        let mut deserializer = serde_json::Deserializer::from_str(&text);
+        projection
+            .seed(DEFAULT_MAX_SCANNED_KEYS)
+            .deserialize(&mut deserializer)
+            .unwrap()
+    });
+
+    let options = EvaluationOptions::new();
+    let full_outcome = evaluate(&rule_set, &full, &options);
+    let pruned_outcome = evaluate(&rule_set, &pruned, &options);
+    assert!(full_outcome.result);
+    assert_eq!(pruned_outcome.result, full_outcome.result);
+    assert_eq!(pruned_outcome.outcomes, full_outcome.outcomes);
+
+    println!(
+        "full:      parse {:>10.2?}  peak {:>10.1} KiB",
+        full_time,
+        kib(full_peak)
+    );
+    println!(
+        "projected: parse {:>10.2?}  peak {:>10.1} KiB",
+        pruned_time,
+        kib(pruned_peak)
+    );
+}
@@ -6,6 +6,7 @@ fn main() {
         "pests/rules.pest",
         "pests/conditions.pest",
         "pests/values.pest",
+        "pests/entries.pest",
     ];
 
     let mut combined = String::new();
@@ -26,4 +27,55 @@ fn main() {
 
     // And rerun if the output file is deleted (optional but good practice)
     println!("cargo:rerun-if-changed=pests/grammar.pest");
+
+    embed_git_sha();
+
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// Sets `ENGINE_GIT_SHA` to the commit being built, for `runner::version`. A build outside a
+/// checkout, such as the Docker image, can pass it in the environment instead; without either
+/// it is left unset
+fn embed_git_sha() {
+    println!("cargo:rerun-if-env-changed=ENGINE_GIT_SHA");
+    let given = std::env::var("ENGINE_GIT_SHA").ok();
+    let sha = given.filter(|sha| !sha.is_empty()).or_else(|| {
+        let output = std::process::Command::new("git")
+            .args(["rev-parse", "--short=12", "HEAD"])
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    });
+    if let Some(sha) = sha {
+        println!("cargo:rustc-env=ENGINE_GIT_SHA={sha}");
+    }
+    // A new commit moves HEAD or the branch it points at
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        if std::path::Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+}
+
+/// Generates the tonic service and prost messages for the gRPC API.
+/// Uses the vendored protoc so builds don't depend on a system install.
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("Failed to locate vendored protoc");
+    let well_known =
+        protoc_bin_vendored::include_path().expect("Failed to locate protobuf include path");
+    std::env::set_var("PROTOC", protoc);
+
+    tonic_build::configure()
+        .compile_protos(
+            &["proto/engine.proto"],
+            &[std::path::Path::new("proto"), well_known.as_path()],
+        )
+        .expect("Failed to compile proto/engine.proto");
+
+    println!("cargo:rerun-if-changed=proto/engine.proto");
 }
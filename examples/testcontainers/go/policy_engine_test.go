@@ -159,8 +159,8 @@ func TestPolicyEngineConnection(t *testing.T) {
 	assert.NoError(t, err)
 	assert.NotNil(t, response)
 
-	// The result should be false because the data structure doesn't match exactly
-	// (we need "Person": {"age": 70} for this rule to work)
+	// Without "Person": {"age": 70} the engine reads the payload as if sent under Person,
+	// with a legacy_flat_data warning, until that compatibility mode is removed
 	t.Logf("Policy evaluation result: %+v", response)
 }
 
@@ -256,7 +256,7 @@ func TestMultiplePolicies(t *testing.T) {
 			name: "Access granted for admin",
 			rule: "A **User** gets access if the __role__ of the **User** is equal to \"admin\".",
 			data: map[string]interface{}{"role": "admin"},
-			want: false, // Will be false due to data structure mismatch, but test runs
+			want: true, // Read as if sent under User, with a legacy_flat_data warning
 		},
 	}
 
@@ -0,0 +1,219 @@
+//! Requests and responses of the engine's HTTP API, as a client sees them. Only what a
+//! contract test asserts on is typed; the trace and other nested reports stay `Value` so a
+//! field added on the server doesn't break the harness
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// `POST /`, the engine's `RuleDataPackage`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EvaluationRequest {
+    pub rule: String,
+    pub data: Value,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub context: Value,
+    /// Outcome or label to evaluate when the policy has no single global rule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcomes: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub lenient: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub validate_data: bool,
+    /// `"verbose"` explains in the trace why properties weren't found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_verbosity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+}
+
+impl EvaluationRequest {
+    pub fn new(rule: &str, data: Value) -> Self {
+        Self {
+            rule: rule.to_string(),
+            data,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_entry(mut self, entry: &str) -> Self {
+        self.entry = Some(entry.to_string());
+        self
+    }
+
+    pub fn with_context(mut self, context: Value) -> Self {
+        self.context = context;
+        self
+    }
+
+    pub fn with_validate_data(mut self, validate_data: bool) -> Self {
+        self.validate_data = validate_data;
+        self
+    }
+
+    /// The same request without the rule, for `POST /policies/{id}/evaluate`
+    pub fn for_stored_policy(&self) -> Value {
+        let mut body = serde_json::to_value(self).expect("request serializes");
+        if let Some(fields) = body.as_object_mut() {
+            fields.remove("rule");
+        }
+        body
+    }
+}
+
+/// The answer to `POST /` and `POST /policies/{id}/evaluate`
+#[derive(Debug, Clone, Deserialize)]
+pub struct EvaluationResponse {
+    pub schema_version: u32,
+    pub result: bool,
+    pub error: Option<String>,
+    /// Where the grammar rejected the policy
+    pub parse_error: Option<ParseErrorDetail>,
+    /// Where the policy failed to parse
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+    pub trace: Option<Value>,
+    /// Result of each labelled rule the evaluation reached
+    pub labels: Option<HashMap<String, bool>>,
+    /// Rules that could be the entry point, when there isn't exactly one
+    pub candidates: Option<Vec<OutcomeCandidate>>,
+    pub decision_fingerprint: Option<String>,
+    /// Why the data was rejected when `validate_data` is set
+    pub violations: Option<Vec<Value>>,
+    pub limit_exceeded: Option<LimitViolation>,
+    /// Rules being evaluated when a reference loop or the depth limit stopped evaluation
+    pub call_path: Option<Vec<String>>,
+    #[serde(default)]
+    pub warnings: Vec<Value>,
+    pub evaluations_used: Option<usize>,
+    #[serde(default)]
+    pub rule: Vec<String>,
+    pub data: Option<Value>,
+}
+
+/// A problem with a policy. `code` is stable across engine versions, where `message` may be
+/// reworded, so contract tests should match on it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Diagnostic {
+    pub code: String,
+    pub message: String,
+    /// Set on parse errors
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// `error` or `warning` on validator findings
+    pub severity: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ParseErrorDetail {
+    pub line: usize,
+    pub column: usize,
+    pub snippet: String,
+    /// What the grammar would have accepted there
+    pub expected: Vec<String>,
+    pub found: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct OutcomeCandidate {
+    pub outcome: String,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LimitViolation {
+    pub limit: String,
+    pub value: usize,
+    pub max: usize,
+}
+
+/// `POST /parse`
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseRequest {
+    pub rule: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ParseResponse {
+    pub valid: bool,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub outcomes: Vec<String>,
+    pub global_rule: Option<GlobalRule>,
+    /// Validator findings on a policy that parsed, or where it didn't
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+    pub stats: Option<Value>,
+    pub limit_exceeded: Option<LimitViolation>,
+    /// The data paths the rules read and the declared outcome types
+    pub contract: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum GlobalRule {
+    Found { outcome: String },
+    Missing { candidates: Vec<OutcomeCandidate> },
+    Multiple { candidates: Vec<OutcomeCandidate> },
+}
+
+/// `POST /batch/stream`, one policy against many payloads
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchRequest {
+    pub rule: String,
+    pub data: Vec<Value>,
+    pub parallel: bool,
+    /// Traces sent with the first this many failing items
+    pub max_traces: usize,
+}
+
+/// A server-sent event of a batch stream
+#[derive(Debug, Clone)]
+pub enum BatchEvent {
+    Item(BatchItem),
+    Summary(BatchSummary),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchItem {
+    pub index: usize,
+    pub result: bool,
+    pub error: Option<String>,
+    pub labels: Option<HashMap<String, bool>>,
+    pub trace: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BatchSummary {
+    pub total: usize,
+    pub processed: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub errors: usize,
+    pub cancelled: bool,
+}
+
+/// `POST /policies`
+#[derive(Debug, Clone, Serialize)]
+pub struct StorePolicyRequest {
+    pub id: String,
+    pub rule: String,
+}
+
+/// The answer to `POST /policies`, or the error when the policy was refused
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoredPolicy {
+    pub id: Option<String>,
+    pub version: Option<u32>,
+    pub stats: Option<Value>,
+    pub error: Option<String>,
+}
+
+/// The answer to `DELETE /policies/{id}`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DeletedPolicy {
+    pub id: String,
+    pub versions: u32,
+}
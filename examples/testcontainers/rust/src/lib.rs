@@ -1,113 +1,445 @@
-use serde_json::json;
-use testcontainers::{clients::Cli, images::generic::GenericImage, Container};
+//! Runs the policy engine for integration tests and talks to it with typed requests.
+//!
+//! `Engine::from_env` connects to the engine at `ENGINE_URL` when it is set, or with the
+//! `docker` feature starts the engine image in a container that lives as long as the
+//! `Engine`. Without either it returns `None`, so a test can skip rather than fail where
+//! there is no engine to test against.
 
-pub struct PolicyEngineContainer<'a> {
-    container: Container<'a, GenericImage>,
-    port: u16,
+pub mod api;
+
+pub use api::*;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::Duration;
+
+#[cfg(feature = "docker")]
+use testcontainers::{
+    core::{IntoContainerPort, WaitFor},
+    runners::AsyncRunner,
+    ContainerAsync, GenericImage, ImageExt,
+};
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Base URL of a running engine to test against instead of starting one
+pub const ENGINE_URL_VAR: &str = "ENGINE_URL";
+/// Image the `docker` feature starts, `DEFAULT_IMAGE` unless set
+pub const ENGINE_IMAGE_VAR: &str = "ENGINE_IMAGE";
+pub const DEFAULT_IMAGE: &str = "policy-engine:latest";
+/// Port the engine listens on inside its image
+pub const ENGINE_PORT: u16 = 3000;
+/// Header naming the tenant when the engine runs with a tenants file
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+/// How long `Engine::connect` waits for `/health` to answer
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An HTTP status and the decoded body
+#[derive(Debug, Clone)]
+pub struct Reply<T> {
+    pub status: u16,
+    pub body: T,
 }
 
-impl<'a> PolicyEngineContainer<'a> {
-    pub fn new(docker: &'a Cli) -> Self {
-        let image = GenericImage::new("policy-engine", "latest")
-            .with_exposed_port(3000)
+/// A running engine and a client for it
+pub struct Engine {
+    base_url: String,
+    client: reqwest::Client,
+    tenant: Option<String>,
+    #[cfg(feature = "docker")]
+    _container: Option<ContainerAsync<GenericImage>>,
+}
+
+impl Engine {
+    /// The engine at `ENGINE_URL`, else a container of `ENGINE_IMAGE` with the `docker`
+    /// feature, else `None`
+    pub async fn from_env() -> Result<Option<Self>> {
+        if let Ok(url) = std::env::var(ENGINE_URL_VAR) {
+            return Self::connect(&url).await.map(Some);
+        }
+        #[cfg(feature = "docker")]
+        {
+            let image =
+                std::env::var(ENGINE_IMAGE_VAR).unwrap_or_else(|_| DEFAULT_IMAGE.to_string());
+            Self::start(&image).await.map(Some)
+        }
+        #[cfg(not(feature = "docker"))]
+        Ok(None)
+    }
+
+    /// An engine already running at `base_url`, once its `/health` answers
+    pub async fn connect(base_url: &str) -> Result<Self> {
+        let engine = Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+            tenant: None,
+            #[cfg(feature = "docker")]
+            _container: None,
+        };
+        engine.wait_until_ready().await?;
+        Ok(engine)
+    }
+
+    /// Starts `image` (`name:tag`) and waits until it serves requests. The container is
+    /// removed when the `Engine` is dropped
+    #[cfg(feature = "docker")]
+    pub async fn start(image: &str) -> Result<Self> {
+        let (name, tag) = match image.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => (name, tag),
+            _ => (image, "latest"),
+        };
+        let container = GenericImage::new(name, tag)
+            .with_exposed_port(ENGINE_PORT.tcp())
+            .with_wait_for(WaitFor::message_on_stdout("Listening on"))
             .with_env_var("FF_ENV_ID", "test-env")
             .with_env_var("FF_AGENT_ID", "test-agent")
             .with_env_var("FF_PROJECT_ID", "test-project")
-            .with_wait_for(testcontainers::core::WaitFor::message_on_stdout("Server running"));
+            .start()
+            .await?;
+        let host = container.get_host().await?;
+        let port = container.get_host_port_ipv4(ENGINE_PORT).await?;
+
+        let mut engine = Self::connect(&format!("http://{}:{}", host, port)).await?;
+        engine._container = Some(container);
+        Ok(engine)
+    }
 
-        let container = docker.run(image);
-        let port = container.get_host_port_ipv4(3000);
+    /// Sends requests as `tenant`, for an engine with a tenants file
+    pub fn with_tenant(mut self, tenant: &str) -> Self {
+        self.tenant = Some(tenant.to_string());
+        self
+    }
 
-        Self { container, port }
+    pub fn base_url(&self) -> &str {
+        &self.base_url
     }
 
-    pub fn base_url(&self) -> String {
-        format!("http://localhost:{}", self.port)
+    /// `POST /`
+    pub async fn evaluate(&self, request: &EvaluationRequest) -> Result<Reply<EvaluationResponse>> {
+        self.post("/", request).await
     }
 
-    pub async fn evaluate_policy(&self, rule: &str, data: serde_json::Value, trace: bool) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
-        let client = reqwest::Client::new();
-        let request_body = json!({
-            "rule": rule,
-            "data": data,
-            "trace": trace
-        });
+    /// `POST /parse`
+    pub async fn parse(&self, rule: &str) -> Result<Reply<ParseResponse>> {
+        let request = ParseRequest {
+            rule: rule.to_string(),
+        };
+        self.post("/parse", &request).await
+    }
 
-        let response = client
-            .post(&self.base_url())
-            .json(&request_body)
+    /// `POST /batch/stream`, with the whole stream read. Events come in the order sent: the
+    /// items, then the summary
+    pub async fn batch(&self, request: &BatchRequest) -> Result<Reply<Vec<BatchEvent>>> {
+        let response = self
+            .request(reqwest::Method::POST, "/batch/stream")
+            .json(request)
             .send()
             .await?;
+        let status = response.status().as_u16();
+        let text = response.text().await?;
+        Ok(Reply {
+            status,
+            body: parse_batch_events(&text)?,
+        })
+    }
+
+    /// `POST /policies`. Each store of an id is its next version
+    pub async fn store_policy(&self, id: &str, rule: &str) -> Result<Reply<StoredPolicy>> {
+        let request = StorePolicyRequest {
+            id: id.to_string(),
+            rule: rule.to_string(),
+        };
+        self.post("/policies", &request).await
+    }
+
+    /// `POST /policies/{id}/evaluate`, with the request's rule left out
+    pub async fn evaluate_policy(
+        &self,
+        id: &str,
+        request: &EvaluationRequest,
+    ) -> Result<Reply<EvaluationResponse>> {
+        let path = format!("/policies/{}/evaluate", id);
+        self.post(&path, &request.for_stored_policy()).await
+    }
 
-        let result = response.json::<serde_json::Value>().await?;
-        Ok(result)
+    /// `DELETE /policies/{id}`, `None` when the engine answers with an error
+    pub async fn delete_policy(&self, id: &str) -> Result<Reply<Option<DeletedPolicy>>> {
+        let path = format!("/policies/{}", id);
+        let response = self.request(reqwest::Method::DELETE, &path).send().await?;
+        let status = response.status().as_u16();
+        let body = if response.status().is_success() {
+            Some(response.json().await?)
+        } else {
+            None
+        };
+        Ok(Reply { status, body })
     }
+
+    async fn post<B, T>(&self, path: &str, body: &B) -> Result<Reply<T>>
+    where
+        B: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        let response = self
+            .request(reqwest::Method::POST, path)
+            .json(body)
+            .send()
+            .await?;
+        let status = response.status().as_u16();
+        let text = response.text().await?;
+        let body = serde_json::from_str(&text)
+            .map_err(|e| format!("{} answered {} with {}: {}", path, status, text, e))?;
+        Ok(Reply { status, body })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .client
+            .request(method, format!("{}{}", self.base_url, path));
+        match &self.tenant {
+            Some(tenant) => request.header(TENANT_HEADER, tenant),
+            None => request,
+        }
+    }
+
+    async fn wait_until_ready(&self) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + READY_TIMEOUT;
+        let url = format!("{}/health", self.base_url);
+        loop {
+            match self.client.get(&url).send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                _ if tokio::time::Instant::now() >= deadline => {
+                    return Err(format!("{} didn't become healthy", url).into())
+                }
+                _ => tokio::time::sleep(Duration::from_millis(200)).await,
+            }
+        }
+    }
+}
+
+/// The `item` and `summary` events of a batch stream
+pub fn parse_batch_events(stream: &str) -> Result<Vec<BatchEvent>> {
+    let mut events = Vec::new();
+    for block in stream.split("\n\n") {
+        let mut name = None;
+        let mut data = String::new();
+        for line in block.lines() {
+            if let Some(value) = line.strip_prefix("event:") {
+                name = Some(value.trim());
+            } else if let Some(value) = line.strip_prefix("data:") {
+                data.push_str(value.trim_start());
+            }
+        }
+        match name {
+            Some("item") => events.push(BatchEvent::Item(serde_json::from_str(&data)?)),
+            Some("summary") => events.push(BatchEvent::Summary(serde_json::from_str(&data)?)),
+            Some(other) => return Err(format!("unexpected {} event: {}", other, data).into()),
+            // Keep-alive comments
+            None => {}
+        }
+    }
+    Ok(events)
 }
 
+/// The engine's API as clients rely on it. Each test skips when there is no engine, see
+/// `Engine::from_env`
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use crate::{BatchEvent, BatchRequest, Engine, EvaluationRequest, GlobalRule};
     use serde_json::json;
-    use testcontainers::clients::Cli;
+
+    const SENIOR_DISCOUNT: &str = "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 65.";
+
+    async fn engine() -> Option<Engine> {
+        let engine = Engine::from_env().await.expect("engine is reachable");
+        if engine.is_none() {
+            eprintln!("skipped: set ENGINE_URL or enable the docker feature");
+        }
+        engine
+    }
 
     #[tokio::test]
-    async fn test_senior_discount_policy() {
-        let docker = Cli::default();
-        let policy_engine = PolicyEngineContainer::new(&docker);
+    async fn test_evaluation_decides_by_the_data() {
+        let Some(engine) = engine().await else {
+            return;
+        };
 
-        let rule = "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 65.";
-        let data = json!({
-            "age": 70
-        });
+        for (age, expected) in [(70, true), (45, false)] {
+            let request = EvaluationRequest::new(SENIOR_DISCOUNT, json!({"Person": {"age": age}}));
+            let reply = engine.evaluate(&request).await.unwrap();
+            assert_eq!(reply.status, 200);
+            assert_eq!(reply.body.result, expected, "age {}", age);
+            assert_eq!(reply.body.error, None);
+            assert!(reply.body.trace.is_some());
+            assert_eq!(reply.body.evaluations_used, Some(1));
+        }
+    }
 
-        let result = policy_engine
-            .evaluate_policy(rule, data, true)
-            .await
-            .expect("Policy evaluation failed");
+    #[tokio::test]
+    async fn test_labelled_rules_report_their_results() {
+        let Some(engine) = engine().await else {
+            return;
+        };
+        let rule = r#"An **Order** gets expedited_shipping
+      if the **Order** passes the size check
+      and §member is valid.
 
-        // Check that the result contains senior_discount
-        assert!(result.to_string().contains("senior_discount"));
-        println!("Policy evaluation result: {}", serde_json::to_string_pretty(&result).unwrap());
+    An **Order** passes the size check
+      if the __total__ of the **Order** is greater than 100.
+
+    member. An **Order** passes the membership check
+      if the __membership__ of the **Order** is in ["gold", "platinum"]."#;
+
+        let request = EvaluationRequest::new(
+            rule,
+            json!({"Order": {"total": 150.0, "membership": "silver"}}),
+        );
+        let reply = engine.evaluate(&request).await.unwrap();
+        assert_eq!(reply.status, 200);
+        assert!(!reply.body.result);
+        let labels = reply.body.labels.expect("labels are reported");
+        assert_eq!(labels.get("member"), Some(&false));
     }
 
     #[tokio::test]
-    async fn test_expedited_shipping_policy() {
-        let docker = Cli::default();
-        let policy_engine = PolicyEngineContainer::new(&docker);
-
-        let rule = r#"An **Order** gets expedited_shipping if the __total__ of the **Order** is greater than 100 and the __membership_level__ of the **Customer** is in ["gold", "platinum"]."#;
-        let data = json!({
-            "total": 150.00,
-            "Customer": {
-                "membership_level": "gold"
-            }
-        });
+    async fn test_parse_reports_outcomes_and_diagnostics() {
+        let Some(engine) = engine().await else {
+            return;
+        };
 
-        let result = policy_engine
-            .evaluate_policy(rule, data, false)
+        let reply = engine.parse(SENIOR_DISCOUNT).await.unwrap();
+        assert_eq!(reply.status, 200);
+        assert!(reply.body.valid);
+        assert_eq!(reply.body.outcomes, ["senior_discount"]);
+        assert_eq!(
+            reply.body.global_rule,
+            Some(GlobalRule::Found {
+                outcome: "senior_discount".to_string()
+            })
+        );
+
+        let reply = engine
+            .parse("A **Person** gets senior_discount if.")
             .await
-            .expect("Policy evaluation failed");
+            .unwrap();
+        assert_eq!(reply.status, 400);
+        assert!(!reply.body.valid);
+        assert!(reply.body.error.is_some());
 
-        assert!(result.to_string().contains("expedited_shipping"));
+        // A policy that doesn't parse is located by code, line and column
+        let request = EvaluationRequest {
+            schema_version: Some(2),
+            ..EvaluationRequest::new("A **Person** gets senior_discount if.", json!({}))
+        };
+        let reply = engine.evaluate(&request).await.unwrap();
+        assert_eq!(reply.status, 400);
+        let diagnostic = &reply.body.diagnostics[0];
+        assert!(!diagnostic.code.is_empty());
+        assert_eq!(diagnostic.line, Some(1));
+        assert!(reply.body.trace.is_none());
     }
 
     #[tokio::test]
-    async fn test_policy_rejection() {
-        let docker = Cli::default();
-        let policy_engine = PolicyEngineContainer::new(&docker);
+    async fn test_several_entry_points_need_choosing() {
+        let Some(engine) = engine().await else {
+            return;
+        };
+        let rule = r#"A **user** gets access if the __role__ of the **user** is equal to "admin".
 
-        let rule = "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 65.";
-        let data = json!({
-            "age": 45  // Below threshold
-        });
+    A **user** gets premium if the __plan__ of the **user** is equal to "gold"."#;
+        let data = json!({"user": {"role": "admin", "plan": "silver"}});
 
-        let result = policy_engine
-            .evaluate_policy(rule, data, true)
+        let reply = engine
+            .evaluate(&EvaluationRequest::new(rule, data.clone()))
             .await
-            .expect("Policy evaluation failed");
+            .unwrap();
+        assert_eq!(reply.status, 422);
+        let mut candidates: Vec<String> = reply
+            .body
+            .candidates
+            .expect("candidates are listed")
+            .into_iter()
+            .map(|c| c.outcome)
+            .collect();
+        candidates.sort();
+        assert_eq!(candidates, ["access", "premium"]);
+
+        let request = EvaluationRequest::new(rule, data).with_entry("access");
+        let reply = engine.evaluate(&request).await.unwrap();
+        assert_eq!(reply.status, 200);
+        assert!(reply.body.result);
+    }
+
+    #[tokio::test]
+    async fn test_batch_stream_ends_with_a_summary() {
+        let Some(engine) = engine().await else {
+            return;
+        };
+        let request = BatchRequest {
+            rule: SENIOR_DISCOUNT.to_string(),
+            data: vec![
+                json!({"Person": {"age": 70}}),
+                json!({"Person": {"age": 30}}),
+                json!({"Person": {"age": 65}}),
+            ],
+            parallel: false,
+            max_traces: 0,
+        };
+
+        let reply = engine.batch(&request).await.unwrap();
+        assert_eq!(reply.status, 200);
+        let mut results = Vec::new();
+        let mut summary = None;
+        for event in reply.body {
+            match event {
+                BatchEvent::Item(item) => results.push((item.index, item.result)),
+                BatchEvent::Summary(s) => summary = Some(s),
+            }
+        }
+        results.sort();
+        assert_eq!(results, [(0, true), (1, false), (2, true)]);
+        let summary = summary.expect("the stream ends with a summary");
+        assert_eq!(
+            (
+                summary.total,
+                summary.passed,
+                summary.failed,
+                summary.errors
+            ),
+            (3, 2, 1, 0)
+        );
+        assert!(!summary.cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_stored_policies_are_versioned_and_deleted() {
+        let Some(engine) = engine().await else {
+            return;
+        };
+        let id = format!("testkit-senior-{}", std::process::id());
+        let senior_60 = SENIOR_DISCOUNT.replace("65", "60");
+
+        let first = engine.store_policy(&id, SENIOR_DISCOUNT).await.unwrap();
+        assert_eq!(first.status, 201);
+        assert_eq!(first.body.version, Some(1));
+        let second = engine.store_policy(&id, &senior_60).await.unwrap();
+        assert_eq!(second.body.version, Some(2));
 
-        // Should not contain senior_discount for age < 65
-        assert!(!result.to_string().contains("senior_discount") || 
-                result.to_string().contains("false"));
+        // The latest version answers
+        let request = EvaluationRequest::new("", json!({"Person": {"age": 62}}));
+        let reply = engine.evaluate_policy(&id, &request).await.unwrap();
+        assert_eq!(reply.status, 200);
+        assert!(reply.body.result);
+
+        let refused = engine.store_policy(&id, "not a policy").await.unwrap();
+        assert_eq!(refused.status, 400);
+        assert!(refused.body.error.is_some());
+
+        let deleted = engine.delete_policy(&id).await.unwrap();
+        assert_eq!(deleted.status, 200);
+        assert_eq!(deleted.body.map(|d| d.versions), Some(2));
+        assert_eq!(engine.delete_policy(&id).await.unwrap().status, 404);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,529 @@
+#[cfg(test)]
+mod tests {
+    use crate::batch::{
+        approximate_size, handle_batch, handle_batch_stream, run_batch, BatchEvent, BatchOptions,
+        BatchSummary, MemoryBudget, Overloaded,
+    };
+    use crate::runner::model::RuleSet;
+    use crate::runner::parser::parse_rules;
+    use axum::{routing::post, Router};
+    use serde_json::{json, Value};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicIsize, Ordering};
+    use tokio::sync::mpsc;
+    use tokio_util::sync::CancellationToken;
+
+    /// Counts the bytes allocated by threads that turned counting on, so a test can measure
+    /// the peak of what a batch holds. Threads hand memory to each other through the result
+    /// channel, so the sender and the receiver both count
+    struct CountingAllocator;
+
+    thread_local! {
+        static COUNTING: Cell<bool> = const { Cell::new(false) };
+    }
+    static ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+    static PEAK: AtomicIsize = AtomicIsize::new(0);
+
+    fn track(delta: isize) {
+        if COUNTING.try_with(Cell::get).unwrap_or(false) {
+            let now = ALLOCATED.fetch_add(delta, Ordering::SeqCst) + delta;
+            PEAK.fetch_max(now, Ordering::SeqCst);
+        }
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                track(layout.size() as isize);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            track(-(layout.size() as isize));
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// Runs a batch against a budget that never refuses
+    fn run(
+        rule_set: &RuleSet,
+        data: Vec<Value>,
+        options: &BatchOptions,
+        token: &CancellationToken,
+        tx: &mpsc::Sender<BatchEvent>,
+    ) -> BatchSummary {
+        let budget = MemoryBudget::new(usize::MAX);
+        let mut reservation = budget.reserve(0).unwrap();
+        run_batch(rule_set, data, options, &mut reservation, token, tx)
+    }
+
+    const SENIOR_RULE: &str = "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 65.";
+
+    async fn start_server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/batch", post(handle_batch))
+            .route("/batch/stream", post(handle_batch_stream));
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    /// Splits an SSE body into (event name, JSON data) pairs, skipping keep-alive comments
+    fn parse_events(body: &str) -> Vec<(String, Value)> {
+        body.split("\n\n")
+            .filter_map(|block| {
+                let mut name = None;
+                let mut data = None;
+                for line in block.lines() {
+                    if let Some(value) = line.strip_prefix("event:") {
+                        name = Some(value.trim().to_string());
+                    } else if let Some(value) = line.strip_prefix("data:") {
+                        data = Some(serde_json::from_str(value.trim()).unwrap());
+                    }
+                }
+                Some((name?, data?))
+            })
+            .collect()
+    }
+
+    async fn stream_batch(parallel: bool) -> Vec<(String, Value)> {
+        let base = start_server().await;
+        let ages = [70, 30, 65, 12, 90, 64];
+        let payload = json!({
+            "rule": SENIOR_RULE,
+            "data": ages.iter().map(|age| json!({"Person": {"age": age}})).collect::<Vec<_>>(),
+            "parallel": parallel
+        });
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/batch/stream", base))
+            .header("content-type", "application/json")
+            .body(payload.to_string())
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert!(response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/event-stream"));
+
+        parse_events(&response.text().await.unwrap())
+    }
+
+    fn assert_events(events: &[(String, Value)]) {
+        let expected = [true, false, true, false, true, false];
+        assert_eq!(events.len(), expected.len() + 1);
+
+        for (index, ((name, data), result)) in events.iter().zip(expected).enumerate() {
+            assert_eq!(name, "item");
+            assert_eq!(data["index"], index);
+            assert_eq!(data["result"], result);
+        }
+
+        let (name, summary) = events.last().unwrap();
+        assert_eq!(name, "summary");
+        assert_eq!(summary["total"], 6);
+        assert_eq!(summary["processed"], 6);
+        assert_eq!(summary["passed"], 3);
+        assert_eq!(summary["failed"], 3);
+        assert_eq!(summary["errors"], 0);
+        assert_eq!(summary["cancelled"], false);
+    }
+
+    #[tokio::test]
+    async fn test_batch_stream_events_in_order() {
+        assert_events(&stream_batch(false).await);
+    }
+
+    #[tokio::test]
+    async fn test_batch_stream_parallel_keeps_order() {
+        assert_events(&stream_batch(true).await);
+    }
+
+    #[tokio::test]
+    async fn test_batch_stream_parse_error() {
+        let base = start_server().await;
+        let response = reqwest::Client::new()
+            .post(format!("{}/batch/stream", base))
+            .header("content-type", "application/json")
+            .body(json!({"rule": "nonsense", "data": [{}]}).to_string())
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 400);
+    }
+
+    async fn post_batch(base: &str, payload: Value) -> (u16, Value) {
+        let response = reqwest::Client::new()
+            .post(format!("{}/batch", base))
+            .header("content-type", "application/json")
+            .body(payload.to_string())
+            .send()
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let text = response.text().await.unwrap();
+        (status, serde_json::from_str(&text).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_batch_answers_every_result_with_a_summary() {
+        let base = start_server().await;
+        let (status, body) = post_batch(
+            &base,
+            json!({
+                "rule": "A **user** is valid if the number of __items__ of **user** is greater than 1.",
+                "data": [
+                    {"user": {"items": [1, 2]}},
+                    {"user": {"items": "not a list"}},
+                    {"user": {"items": []}}
+                ]
+            }),
+        )
+        .await;
+        assert_eq!(status, 200);
+
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0]["index"], 0);
+        assert_eq!(results[0]["result"], true);
+        // One item failing to evaluate doesn't stop the others
+        assert!(results[1]["error"].is_string());
+        assert_eq!(results[2]["result"], false);
+        assert!(results.iter().all(|item| item.get("trace").is_none()));
+
+        let summary = &body["summary"];
+        assert_eq!(summary["total"], 3);
+        assert_eq!(summary["passed"], 1);
+        assert_eq!(summary["failed"], 1);
+        assert_eq!(summary["errors"], 1);
+        assert_eq!(summary["traces_omitted"], 2);
+
+        let (status, body) = post_batch(
+            &base,
+            json!({"rule": SENIOR_RULE, "data": [{"Person": {"age": 30}}], "max_traces": 1}),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert!(body["results"][0]["trace"].is_object());
+
+        let (status, _) = post_batch(&base, json!({"rule": "nonsense", "data": [{}]})).await;
+        assert_eq!(status, 400);
+    }
+
+    #[tokio::test]
+    async fn test_batch_items_for_another_policy_are_refused() {
+        let base = start_server().await;
+        let data = json!([{"Person": {"age": 70}}, {"Customer": {"age": 70}}]);
+        let (status, body) = post_batch(&base, json!({"rule": SENIOR_RULE, "data": data})).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["results"][0]["result"], true);
+        assert_eq!(
+            body["results"][1]["error"],
+            "No selectors matched: expected one of [Person]; data contains [Customer]"
+        );
+        assert_eq!(body["summary"]["errors"], 1);
+
+        let (status, body) = post_batch(
+            &base,
+            json!({"rule": SENIOR_RULE, "data": data, "allow_disjoint_data": true}),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert!(body["results"][1].get("error").is_none());
+        assert_eq!(body["results"][1]["result"], false);
+    }
+
+    #[test]
+    fn test_run_batch_item_error_does_not_abort() {
+        let rule_set = parse_rules(
+            "A **user** is valid if the number of __items__ of **user** is greater than 1.",
+        )
+        .unwrap();
+        let data = vec![
+            json!({"user": {"items": [1, 2]}}),
+            json!({"user": {"items": "not a list"}}),
+            json!({"user": {"items": [1, 2, 3]}}),
+        ];
+        let (tx, mut rx) = mpsc::channel(16);
+
+        let summary = run(
+            &rule_set,
+            data,
+            &BatchOptions::default(),
+            &CancellationToken::new(),
+            &tx,
+        );
+
+        assert_eq!(summary.processed, 3);
+        assert_eq!(summary.passed, 2);
+        assert_eq!(summary.failed, 0);
+        assert_eq!(summary.errors, 1);
+
+        let mut items = Vec::new();
+        while let Ok(BatchEvent::Item(item)) = rx.try_recv() {
+            items.push(item);
+        }
+        assert_eq!(items.len(), 3);
+        assert!(items[1].error.is_some());
+    }
+
+    #[test]
+    fn test_summary_counts_each_item_once() {
+        let rule_set = parse_rules(
+            "A **user** is valid if the number of __items__ of **user** is greater than 1.",
+        )
+        .unwrap();
+        let data = vec![
+            json!({"user": {"items": [1, 2]}}),
+            json!({"user": {"items": "not a list"}}),
+            json!({"user": {"items": []}}),
+            json!({"user": {"items": 7}}),
+        ];
+        let (tx, _rx) = mpsc::channel(16);
+
+        let summary = run(
+            &rule_set,
+            data,
+            &BatchOptions::default(),
+            &CancellationToken::new(),
+            &tx,
+        );
+
+        assert_eq!((summary.passed, summary.failed, summary.errors), (1, 1, 2));
+        assert_eq!(
+            summary.passed + summary.failed + summary.errors,
+            summary.processed
+        );
+    }
+
+    #[test]
+    fn test_run_batch_stops_when_cancelled() {
+        let rule_set = parse_rules(SENIOR_RULE).unwrap();
+        let data = vec![json!({"Person": {"age": 70}}); 10];
+        let (tx, _rx) = mpsc::channel(16);
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let summary = run(&rule_set, data, &BatchOptions::default(), &token, &tx);
+
+        assert_eq!(
+            summary,
+            BatchSummary {
+                total: 10,
+                cancelled: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_run_batch_cancels_when_receiver_dropped() {
+        let rule_set = parse_rules(SENIOR_RULE).unwrap();
+        let data = vec![json!({"Person": {"age": 70}}); 10];
+        let (tx, rx) = mpsc::channel(16);
+        drop(rx);
+        let token = CancellationToken::new();
+
+        let summary = run(&rule_set, data, &BatchOptions::default(), &token, &tx);
+
+        assert!(token.is_cancelled());
+        assert!(summary.cancelled);
+        assert_eq!(summary.processed, 1);
+    }
+
+    #[test]
+    fn test_run_batch_keeps_traces_of_the_first_failures() {
+        let rule_set = parse_rules(SENIOR_RULE).unwrap();
+        let data = [70, 30, 65, 12, 90, 64]
+            .iter()
+            .map(|age| json!({"Person": {"age": age}}))
+            .collect();
+        let (tx, mut rx) = mpsc::channel(16);
+        let options = BatchOptions {
+            chunk_size: 4,
+            max_traces: 2,
+            ..BatchOptions::default()
+        };
+
+        let summary = run(&rule_set, data, &options, &CancellationToken::new(), &tx);
+
+        let mut traced = Vec::new();
+        while let Ok(BatchEvent::Item(item)) = rx.try_recv() {
+            if item.trace.is_some() {
+                traced.push(item.index);
+            }
+        }
+        assert_eq!(traced, [1, 3]);
+        assert_eq!(summary.processed, 6);
+        assert_eq!(summary.traces_omitted, 1);
+    }
+
+    #[test]
+    fn test_run_batch_adds_up_execution_stats() {
+        let rule_set = parse_rules(SENIOR_RULE).unwrap();
+        let data = [70, 30, 65]
+            .iter()
+            .map(|age| json!({"Person": {"age": age}}))
+            .collect::<Vec<_>>();
+        let (tx, mut rx) = mpsc::channel(16);
+
+        let summary = run(
+            &rule_set,
+            data.clone(),
+            &BatchOptions::default(),
+            &CancellationToken::new(),
+            &tx,
+        );
+        assert_eq!(summary.execution_stats, None);
+        while let Ok(event) = rx.try_recv() {
+            if let BatchEvent::Item(item) = event {
+                assert_eq!(item.execution_stats, None);
+            }
+        }
+
+        let options = BatchOptions {
+            stats: true,
+            ..BatchOptions::default()
+        };
+        let summary = run(&rule_set, data, &options, &CancellationToken::new(), &tx);
+        let mut items = 0;
+        while let Ok(BatchEvent::Item(item)) = rx.try_recv() {
+            assert_eq!(item.execution_stats.unwrap().rules_evaluated, 1);
+            items += 1;
+        }
+        assert_eq!(items, 3);
+        let total = summary.execution_stats.unwrap();
+        assert_eq!(total.rules_evaluated, 3);
+        assert_eq!(total.conditions_evaluated, 3);
+        assert_eq!(total.properties_resolved, 3);
+        assert_eq!(total.data_resolver_calls, 6);
+        assert_eq!(total.max_reference_depth, 1);
+    }
+
+    #[test]
+    fn test_memory_budget_refuses_over_its_ceiling() {
+        let budget = MemoryBudget::new(100);
+        let mut first = budget.reserve(60).unwrap();
+        assert_eq!(
+            budget.reserve(50).unwrap_err(),
+            Overloaded {
+                in_flight_bytes: 60,
+                requested_bytes: 50,
+                max_in_flight_bytes: 100,
+            }
+        );
+
+        first.release(20);
+        let second = budget.reserve(50).unwrap();
+        assert_eq!(budget.in_flight.load(Ordering::SeqCst), 90);
+
+        drop(first);
+        drop(second);
+        assert_eq!(budget.in_flight.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_run_batch_hands_back_its_reservation() {
+        let rule_set = parse_rules(SENIOR_RULE).unwrap();
+        let data: Vec<Value> = vec![json!({"Person": {"age": 70}}); 10];
+        let size: usize = data.iter().map(approximate_size).sum();
+        let budget = MemoryBudget::new(size);
+        let mut reservation = budget.reserve(size).unwrap();
+        assert!(budget.reserve(1).is_err());
+        let (tx, _rx) = mpsc::channel(16);
+        let options = BatchOptions {
+            chunk_size: 3,
+            ..BatchOptions::default()
+        };
+
+        run_batch(
+            &rule_set,
+            data,
+            &options,
+            &mut reservation,
+            &CancellationToken::new(),
+            &tx,
+        );
+
+        assert_eq!(budget.in_flight.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_approximate_size_is_the_serialized_length() {
+        let value = json!({"a": [true, null, "xy"], "b": {"c": false}});
+        assert_eq!(
+            approximate_size(&value),
+            serde_json::to_string(&value).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_large_batch_peak_allocation_is_bounded_by_the_chunk() {
+        let rule_set = parse_rules(SENIOR_RULE).unwrap();
+        let options = BatchOptions {
+            chunk_size: 100,
+            max_traces: 5,
+            ..BatchOptions::default()
+        };
+        let (tx, mut rx) = mpsc::channel(32);
+        let receiver = std::thread::spawn(move || {
+            COUNTING.with(|counting| counting.set(true));
+            let mut traces = 0;
+            while let Some(event) = rx.blocking_recv() {
+                if let BatchEvent::Item(item) = event {
+                    traces += usize::from(item.trace.is_some());
+                }
+            }
+            traces
+        });
+
+        COUNTING.with(|counting| counting.set(true));
+        let start = ALLOCATED.load(Ordering::SeqCst);
+        let notes = "x".repeat(200);
+        let data: Vec<Value> = (0..20_000)
+            .map(|index| json!({"Person": {"age": index % 100, "notes": notes}}))
+            .collect();
+        let baseline = ALLOCATED.load(Ordering::SeqCst);
+        let data_bytes = baseline - start;
+        PEAK.store(baseline, Ordering::SeqCst);
+
+        let summary = run(&rule_set, data, &options, &CancellationToken::new(), &tx);
+        drop(tx);
+        let traces = receiver.join().unwrap();
+
+        let peak = PEAK.load(Ordering::SeqCst) - baseline;
+        let remaining = ALLOCATED.load(Ordering::SeqCst) - start;
+        COUNTING.with(|counting| counting.set(false));
+
+        assert_eq!(summary.processed, 20_000);
+        assert_eq!(traces, 5);
+        assert_eq!(summary.traces_omitted, summary.failed - 5);
+        // The batch never holds more than a chunk's evaluations on top of its data, and
+        // the data is gone by the end
+        assert!(
+            peak < data_bytes / 10,
+            "peak {} for {} bytes of data",
+            peak,
+            data_bytes
+        );
+        assert!(
+            remaining < data_bytes / 10,
+            "{} of {} bytes still held",
+            remaining,
+            data_bytes
+        );
+    }
+}
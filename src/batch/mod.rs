@@ -0,0 +1,553 @@
+//! One rule set evaluated against many payloads: `POST /batch` and `POST /batch/stream`.
+//!
+//! `POST /batch` takes the same body and answers once every item is evaluated, with `results` (each
+//! item's `index`, `result`, `error` and `labels`) and the `summary`, which counts each item once
+//! in `passed`, `failed` or `errors`.
+//!
+//! `POST /batch/stream` takes `"max_traces": 50` to send traces with the first 50 items that fail
+//! or error; other items carry none, and the summary counts the failures whose trace was left out
+//! as `traces_omitted`. The in-flight ceiling counts each item's approximate serialized size
+//! (`batch::approximate_size`) from when the batch is accepted until its chunk is evaluated.
+
+mod lib;
+
+use crate::config;
+use crate::runner::evaluator::evaluate;
+use crate::runner::model::RuleSet;
+use crate::runner::options::EvaluationOptions;
+use crate::runner::outcome::EvalStats;
+use crate::runner::parser::parse_rules_with_limits;
+use crate::runner::trace::RuleSetTrace;
+use crate::tenants::{check_rule_size, Tenant, TenantError};
+use crate::{parse_limits, service_options};
+use axum::{
+    extract::Json,
+    http::{header, HeaderValue, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tokio_util::sync::CancellationToken;
+
+/// One rule evaluated against many data payloads
+#[derive(Deserialize)]
+pub struct BatchRequest {
+    pub rule: String,
+    pub data: Vec<Value>,
+    #[serde(default)]
+    pub parallel: bool,
+    /// Traces sent with items that fail or error, for the first this many of them. None by
+    /// default
+    #[serde(default)]
+    pub max_traces: usize,
+    /// Answer with each item's `execution_stats` and their totals in the summary
+    #[serde(default)]
+    pub stats: bool,
+    /// Evaluate items holding none of the selectors the rules read, which error otherwise as
+    /// they do on `POST /`
+    #[serde(default)]
+    pub allow_disjoint_data: bool,
+}
+
+/// How `run_batch` works through the items
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    pub parallel: bool,
+    /// Items evaluated before their results are sent and their data dropped, see
+    /// `config::BatchSettings`
+    pub chunk_size: usize,
+    /// See `BatchRequest::max_traces`
+    pub max_traces: usize,
+    /// See `BatchRequest::stats`
+    pub stats: bool,
+    /// What every item is evaluated with
+    pub evaluation: EvaluationOptions,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            parallel: false,
+            chunk_size: config::BatchSettings::default().chunk_size,
+            max_traces: 0,
+            stats: false,
+            evaluation: config::current().evaluation_options(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BatchItemResult {
+    pub index: usize,
+    pub result: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<HashMap<String, bool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace: Option<RuleSetTrace>,
+    /// What evaluating the item took, with `stats`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution_stats: Option<EvalStats>,
+}
+
+impl BatchItemResult {
+    fn failed(&self) -> bool {
+        !self.result || self.error.is_some()
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct BatchSummary {
+    pub total: usize,
+    pub processed: usize,
+    pub passed: usize,
+    /// Items that evaluated to false without an error
+    pub failed: usize,
+    /// Items that failed to evaluate, counted in neither `passed` nor `failed`
+    pub errors: usize,
+    pub cancelled: bool,
+    /// Items that failed after `max_traces` traces were sent, so went without theirs
+    pub traces_omitted: usize,
+    /// The items' `execution_stats` added up, see `EvalStats::merge`, with `stats`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution_stats: Option<EvalStats>,
+}
+
+impl BatchSummary {
+    pub fn record(&mut self, item: &BatchItemResult) {
+        self.processed += 1;
+        if item.error.is_some() {
+            self.errors += 1;
+        } else if item.result {
+            self.passed += 1;
+        } else {
+            self.failed += 1;
+        }
+        if let (Some(total), Some(stats)) = (&mut self.execution_stats, &item.execution_stats) {
+            total.merge(stats);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BatchEvent {
+    Item(BatchItemResult),
+    Summary(BatchSummary),
+}
+
+/// Evaluates a single payload, resolving the global outcome the same way `POST /` does
+pub fn evaluate_item(
+    rule_set: &RuleSet,
+    index: usize,
+    data: &Value,
+    options: &EvaluationOptions,
+) -> BatchItemResult {
+    let outcome = evaluate(rule_set, data, options);
+    BatchItemResult {
+        index,
+        result: outcome.result,
+        labels: outcome.labels_if_any(),
+        error: outcome.error.map(|error| error.to_string()),
+        trace: outcome.trace,
+        execution_stats: Some(outcome.stats),
+    }
+}
+
+/// Evaluates every item in order, sending each result as it completes followed by a summary.
+///
+/// Items are taken `chunk_size` at a time. Once a chunk is evaluated its data is dropped and
+/// handed back to `reservation`, then its results are sent, so a slow reader holds back
+/// evaluation rather than letting results pile up. Traces are kept for the first
+/// `max_traces` items that fail and dropped for every other item.
+///
+/// When `parallel` is set items are evaluated in windows sized to the available cores, but
+/// results are still emitted in index order. The token is checked between windows and is
+/// tripped if the receiver goes away, so remaining work is skipped.
+pub fn run_batch(
+    rule_set: &RuleSet,
+    data: Vec<Value>,
+    options: &BatchOptions,
+    reservation: &mut Reservation<'_>,
+    token: &CancellationToken,
+    tx: &mpsc::Sender<BatchEvent>,
+) -> BatchSummary {
+    let width = if options.parallel {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    } else {
+        1
+    };
+
+    let mut summary = BatchSummary {
+        total: data.len(),
+        execution_stats: options.stats.then(EvalStats::default),
+        ..Default::default()
+    };
+    let mut traces_sent = 0;
+    let mut items = data.into_iter();
+    let mut base = 0;
+
+    while !token.is_cancelled() {
+        let chunk: Vec<Value> = items.by_ref().take(options.chunk_size.max(1)).collect();
+        if chunk.is_empty() {
+            break;
+        }
+
+        let results = evaluate_chunk(rule_set, &chunk, base, width, &options.evaluation, token);
+        base += chunk.len();
+        let held: usize = chunk.iter().map(approximate_size).sum();
+        drop(chunk);
+        reservation.release(held);
+
+        for mut item in results {
+            if !options.stats {
+                item.execution_stats = None;
+            }
+            summary.record(&item);
+            if item.failed() && traces_sent < options.max_traces {
+                traces_sent += 1;
+            } else {
+                if item.failed() {
+                    summary.traces_omitted += 1;
+                }
+                item.trace = None;
+            }
+            if tx.blocking_send(BatchEvent::Item(item)).is_err() {
+                // Client is gone, nobody will read the rest
+                token.cancel();
+                break;
+            }
+        }
+    }
+
+    summary.cancelled = token.is_cancelled() && summary.processed < summary.total;
+    let _ = tx.blocking_send(BatchEvent::Summary(summary.clone()));
+    summary
+}
+
+/// Evaluates a chunk in windows of `width` items, stopping between windows once cancelled
+fn evaluate_chunk(
+    rule_set: &RuleSet,
+    chunk: &[Value],
+    base: usize,
+    width: usize,
+    options: &EvaluationOptions,
+    token: &CancellationToken,
+) -> Vec<BatchItemResult> {
+    let mut results = Vec::with_capacity(chunk.len());
+
+    for (window_index, window) in chunk.chunks(width).enumerate() {
+        if token.is_cancelled() {
+            break;
+        }
+
+        let base = base + window_index * width;
+        if window.len() == 1 {
+            results.push(evaluate_item(rule_set, base, &window[0], options));
+            continue;
+        }
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = window
+                .iter()
+                .enumerate()
+                .map(|(offset, item)| {
+                    scope.spawn(move || evaluate_item(rule_set, base + offset, item, options))
+                })
+                .collect();
+            results.extend(
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("batch worker panicked")),
+            );
+        });
+    }
+
+    results
+}
+
+/// Roughly how many bytes `value` takes serialized as JSON, without serializing it
+pub fn approximate_size(value: &Value) -> usize {
+    match value {
+        Value::Null | Value::Bool(true) => 4,
+        Value::Bool(false) => 5,
+        Value::Number(_) => 8,
+        Value::String(text) => text.len() + 2,
+        Value::Array(items) => {
+            let separators = items.len().saturating_sub(1);
+            2 + separators + items.iter().map(approximate_size).sum::<usize>()
+        }
+        Value::Object(entries) => {
+            let separators = entries.len().saturating_sub(1);
+            let members: usize = entries
+                .iter()
+                .map(|(key, value)| key.len() + 3 + approximate_size(value))
+                .sum();
+            2 + separators + members
+        }
+    }
+}
+
+/// Approximate bytes of batch data held by running batches, against a ceiling
+#[derive(Debug)]
+pub struct MemoryBudget {
+    max_bytes: usize,
+    in_flight: AtomicUsize,
+}
+
+/// Why a batch was refused: taking it on would go over the budget
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overloaded {
+    pub in_flight_bytes: usize,
+    pub requested_bytes: usize,
+    pub max_in_flight_bytes: usize,
+}
+
+impl MemoryBudget {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Reserves `bytes`, or refuses when they would take the budget over its ceiling
+    pub fn reserve(&self, bytes: usize) -> Result<Reservation<'_>, Overloaded> {
+        let mut current = self.in_flight.load(Ordering::Acquire);
+        loop {
+            let next = current.saturating_add(bytes);
+            if next > self.max_bytes {
+                return Err(Overloaded {
+                    in_flight_bytes: current,
+                    requested_bytes: bytes,
+                    max_in_flight_bytes: self.max_bytes,
+                });
+            }
+            match self.in_flight.compare_exchange_weak(
+                current,
+                next,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    return Ok(Reservation {
+                        budget: self,
+                        bytes,
+                    })
+                }
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+/// Bytes reserved for one batch, handed back as its items are dropped and in full when the
+/// reservation is dropped
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: usize,
+}
+
+impl Reservation<'_> {
+    /// Hands back up to `bytes` of the reservation
+    pub fn release(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.bytes);
+        self.bytes -= bytes;
+        self.budget.in_flight.fetch_sub(bytes, Ordering::AcqRel);
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.release(self.bytes);
+    }
+}
+
+/// The budget every batch reserves from, from `batch` in the server config
+fn budget() -> &'static MemoryBudget {
+    static BUDGET: OnceLock<MemoryBudget> = OnceLock::new();
+    BUDGET.get_or_init(|| MemoryBudget::new(config::current().batch.max_in_flight_bytes))
+}
+
+/// 503 with `Retry-After` for a batch the budget can't take on now
+fn overloaded_response(overloaded: Overloaded) -> Response {
+    let body = serde_json::json!({
+        "error": format!(
+            "Batch memory ceiling reached: {} bytes in flight, {} requested, {} allowed",
+            overloaded.in_flight_bytes,
+            overloaded.requested_bytes,
+            overloaded.max_in_flight_bytes
+        ),
+        "code": "overloaded",
+        "in_flight_bytes": overloaded.in_flight_bytes,
+        "requested_bytes": overloaded.requested_bytes,
+        "max_in_flight_bytes": overloaded.max_in_flight_bytes,
+    });
+    let mut response = (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+    response
+}
+
+/// A batch that passed its checks and holds its share of the budget, ready for `run_batch`
+pub struct AcceptedBatch {
+    pub rule_set: Arc<RuleSet>,
+    pub data: Vec<Value>,
+    pub options: BatchOptions,
+    pub reservation: Reservation<'static>,
+}
+
+/// Why a batch wasn't accepted
+#[derive(Debug)]
+pub enum Rejection {
+    Tenant(TenantError),
+    /// The rule doesn't parse
+    Invalid(String),
+    Overloaded(Overloaded),
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        match self {
+            Rejection::Tenant(error) => error.into_response(),
+            Rejection::Invalid(error) => (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "error": error })),
+            )
+                .into_response(),
+            Rejection::Overloaded(overloaded) => overloaded_response(overloaded),
+        }
+    }
+}
+
+/// Checks the rule against the tenant's quota, parses it and reserves the batch's data from
+/// the budget, as every batch endpoint does before running it
+pub fn accept(
+    tenant: &Option<Extension<Tenant>>,
+    request: BatchRequest,
+) -> Result<AcceptedBatch, Rejection> {
+    check_rule_size(tenant, &request.rule).map_err(Rejection::Tenant)?;
+    let rule_set = parse_rules_with_limits(&request.rule, parse_limits())
+        .map_err(|error| Rejection::Invalid(error.to_string()))?
+        .freeze();
+
+    let requested = request.data.iter().map(approximate_size).sum();
+    let reservation = budget().reserve(requested).map_err(Rejection::Overloaded)?;
+    Ok(AcceptedBatch {
+        rule_set,
+        data: request.data,
+        options: BatchOptions {
+            parallel: request.parallel,
+            chunk_size: config::current().batch.chunk_size,
+            max_traces: request.max_traces,
+            stats: request.stats,
+            evaluation: service_options().with_reject_disjoint_data(!request.allow_disjoint_data),
+        },
+        reservation,
+    })
+}
+
+/// Every item's result and the summary, as `POST /batch` answers them
+#[derive(Serialize, Debug)]
+pub struct BatchResponse {
+    pub results: Vec<BatchItemResult>,
+    pub summary: BatchSummary,
+}
+
+/// `POST /batch` - evaluates a batch and answers with every item's result and the summary at
+/// once. Refused as a batch stream would be
+pub async fn handle_batch(
+    tenant: Option<Extension<Tenant>>,
+    Json(request): Json<BatchRequest>,
+) -> Result<Json<BatchResponse>, Response> {
+    let AcceptedBatch {
+        rule_set,
+        data,
+        options,
+        mut reservation,
+    } = accept(&tenant, request).map_err(IntoResponse::into_response)?;
+
+    // Dropping the response (client disconnect) drops the receiver, which cancels the rest
+    let (tx, mut rx) = mpsc::channel(32);
+    let worker = tokio::task::spawn_blocking(move || {
+        run_batch(
+            &rule_set,
+            data,
+            &options,
+            &mut reservation,
+            &CancellationToken::new(),
+            &tx,
+        )
+    });
+
+    let mut results = Vec::new();
+    while let Some(event) = rx.recv().await {
+        if let BatchEvent::Item(item) = event {
+            results.push(item);
+        }
+    }
+    let summary = worker.await.map_err(|error| {
+        let body = serde_json::json!({ "error": format!("Batch worker stopped: {}", error) });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+    })?;
+    Ok(Json(BatchResponse { results, summary }))
+}
+
+/// `POST /batch/stream` - evaluates a batch and streams progress as server-sent events. A
+/// batch that would take the data held by running batches over `max_in_flight_bytes` is
+/// refused with 503
+pub async fn handle_batch_stream(
+    tenant: Option<Extension<Tenant>>,
+    Json(request): Json<BatchRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Response> {
+    let AcceptedBatch {
+        rule_set,
+        data,
+        options,
+        mut reservation,
+    } = accept(&tenant, request).map_err(IntoResponse::into_response)?;
+
+    let (tx, rx) = mpsc::channel(32);
+    let token = CancellationToken::new();
+
+    let worker_token = token.clone();
+    tokio::task::spawn_blocking(move || {
+        run_batch(
+            &rule_set,
+            data,
+            &options,
+            &mut reservation,
+            &worker_token,
+            &tx,
+        );
+    });
+
+    // Dropping the stream (client disconnect) cancels the remaining work
+    let guard = token.drop_guard();
+    let stream = ReceiverStream::new(rx).map(move |event| {
+        let _guard = &guard;
+        Ok(to_sse_event(event))
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+fn to_sse_event(event: BatchEvent) -> Event {
+    let built = match event {
+        BatchEvent::Item(item) => Event::default().event("item").json_data(item),
+        BatchEvent::Summary(summary) => Event::default().event("summary").json_data(summary),
+    };
+    built.unwrap_or_else(|e| Event::default().event("error").data(e.to_string()))
+}
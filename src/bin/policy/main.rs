@@ -0,0 +1,552 @@
+//! Command line tools for working with policies and their evaluation traces.
+//!
+//! ```text
+//! policy check <rules.txt> [--json] [--cache-dir=DIR]
+//! policy fmt <rules.txt>... [--check] [--width=N]
+//! policy trace <trace.json> [--csv]
+//! policy trace-diff <before.json> <after.json> [--json]
+//! policy replay <bundle.json> [--json]
+//! policy conformance <dir> [--json]
+//! policy debug <rules.txt> <data.json> [--break <rule or condition id>]...
+//! policy sensitivity <rules.txt> <data.json> <perturbations.json> [--json]
+//! ```
+//!
+//! `check` resolves `Include "name".` statements from files, relative to the including file.
+//! With `--cache-dir` it loads the policy compiled there, or compiles it there.
+//!
+//! `debug` evaluates the data, stopping before each rule named with `--break`, by outcome
+//! (`passes the theory test`) or label, and after each condition whose id is given, or at
+//! every rule and condition without `--break`. At each stop it prints what the evaluation
+//! has read so far and asks whether to continue, step, skip the rule or abort.
+//!
+//! `sensitivity` reads `{"paths": [{"path": "$.person.score", "deltas": [1, 5]}]}` and reports
+//! which of the changes flip the decision and the smallest change to each path that does.
+
+use engine::runner::compiled::PolicyCache;
+use engine::runner::conformance::run_directory;
+use engine::runner::debug::{DebugAction, DebugHook, DebugView};
+use engine::runner::diff::diff_traces;
+use engine::runner::error::RuleError;
+use engine::runner::evaluator::evaluate;
+use engine::runner::formatter::{format_rules_with_options, FormatOptions};
+use engine::runner::include::{parse_rule_set_with_loader, FileLoader};
+use engine::runner::model::{Condition, ConditionGroup, Rule, RuleSet};
+use engine::runner::options::EvaluationOptions;
+use engine::runner::parser::ParseLimits;
+use engine::runner::replay::ReplayBundle;
+use engine::runner::sensitivity::{sensitivity_with_options, PerturbationSpec};
+use engine::runner::trace::{ConditionTrace, RuleSetTrace};
+use engine::runner::validator::{validate_rule_set, Diagnostic, Severity};
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const USAGE: &str = "usage: policy check <rules.txt> [--json] [--cache-dir=DIR]
+       policy fmt <rules.txt>... [--check] [--width=N]
+       policy trace <trace.json> [--csv]
+       policy trace-diff <before.json> <after.json> [--json]
+       policy replay <bundle.json> [--json]
+       policy conformance <dir> [--json]
+       policy debug <rules.txt> <data.json> [--break <rule or condition id>]...
+       policy sensitivity <rules.txt> <data.json> <perturbations.json> [--json]";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("check") => check(&args[1..]),
+        Some("fmt") => fmt(&args[1..]),
+        Some("trace") => trace(&args[1..]),
+        Some("trace-diff") => trace_diff(&args[1..]),
+        Some("replay") => replay(&args[1..]),
+        Some("conformance") => conformance(&args[1..]),
+        Some("debug") => debug(&args[1..]),
+        Some("sensitivity") => sensitivity(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Parses a policy file, with the files it includes, and prints every problem found. Exits 0
+/// when it parses without errors and 1 otherwise
+fn check(args: &[String]) -> Result<ExitCode, String> {
+    let json_output = args.iter().any(|arg| arg == "--json");
+    let cache = args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("--cache-dir="))
+        .map(PolicyCache::new);
+    let files: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    let [path] = files.as_slice() else {
+        return Err(USAGE.to_string());
+    };
+
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let loader = FileLoader::for_file(path);
+    let limits = ParseLimits::default();
+    let parsed = match &cache {
+        Some(cache) => cache
+            .parse_rule_set_with_loader_cached(&text, &limits, &loader)
+            .map(|parsed| {
+                if let Some(warning) = parsed.warning {
+                    eprintln!("warning: {}", warning);
+                }
+                parsed.rule_set
+            }),
+        None => parse_rule_set_with_loader(&text, &limits, &loader),
+    };
+    let (error, diagnostics) = match parsed {
+        Ok(rule_set) => (None, validate_rule_set(&rule_set)),
+        Err(error) => match parse_failure_diagnostics(&error) {
+            Some(diagnostics) => (None, diagnostics.to_vec()),
+            None => (Some(error.to_string()), Vec::new()),
+        },
+    };
+    let failed = error.is_some()
+        || diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error);
+
+    if json_output {
+        let output = serde_json::json!({
+            "valid": !failed,
+            "error": error,
+            "diagnostics": diagnostics,
+        });
+        let output = serde_json::to_string_pretty(&output).map_err(|e| e.to_string())?;
+        println!("{}", output);
+    } else {
+        if let Some(error) = &error {
+            println!("{}: {}", path, error);
+        }
+        for diagnostic in &diagnostics {
+            println!("{}", describe_diagnostic(path, diagnostic));
+        }
+    }
+
+    Ok(if failed {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Every broken statement of a document that failed to parse, looking through the included
+/// documents the failure was in
+fn parse_failure_diagnostics(error: &RuleError) -> Option<&[Diagnostic]> {
+    match error {
+        RuleError::InIncludedDocument { error, .. } => parse_failure_diagnostics(error),
+        error => error.parse_diagnostics(),
+    }
+}
+
+/// A diagnostic as `path:line:column: severity[code]: message`, the path being the included
+/// file the diagnostic is in, if it is in one
+fn describe_diagnostic(path: &str, diagnostic: &Diagnostic) -> String {
+    let path = diagnostic.document.as_deref().unwrap_or(path);
+    let severity = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    match &diagnostic.position {
+        Some(pos) => format!(
+            "{}:{}:{}: {}[{}]: {}",
+            path, pos.line, pos.start, severity, diagnostic.code, diagnostic.message
+        ),
+        None => format!(
+            "{}: {}[{}]: {}",
+            path, severity, diagnostic.code, diagnostic.message
+        ),
+    }
+}
+
+/// Rewrites policy files in the canonical layout. With `--check` nothing is written and the
+/// command exits 1 when any file would change
+fn fmt(args: &[String]) -> Result<ExitCode, String> {
+    let check_only = args.iter().any(|arg| arg == "--check");
+    let mut options = FormatOptions::default();
+    for arg in args {
+        if let Some(width) = arg.strip_prefix("--width=") {
+            options.max_width = width
+                .parse()
+                .map_err(|_| format!("--width: not a number: {}", width))?;
+        }
+    }
+    let files: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    if files.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let mut unformatted = false;
+    for path in files {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let formatted =
+            format_rules_with_options(&text, &options).map_err(|e| format!("{}: {}", path, e))?;
+        if formatted == text {
+            continue;
+        }
+
+        unformatted = true;
+        if check_only {
+            println!("{} is not formatted", path);
+        } else {
+            fs::write(path, formatted).map_err(|e| format!("{}: {}", path, e))?;
+            println!("formatted {}", path);
+        }
+    }
+
+    Ok(if check_only && unformatted {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// Prints a trace, as CSV rows with `--csv` and as JSON otherwise
+fn trace(args: &[String]) -> Result<ExitCode, String> {
+    let csv_output = args.iter().any(|arg| arg == "--csv");
+    let files: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    let [path] = files.as_slice() else {
+        return Err(USAGE.to_string());
+    };
+
+    let trace = read_trace(path)?;
+    if csv_output {
+        print!("{}", trace.to_csv());
+    } else {
+        let output = serde_json::to_string_pretty(&trace).map_err(|e| e.to_string())?;
+        println!("{}", output);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Prints the differences between two traces. Exits 0 when they match and 1 when they differ
+fn trace_diff(args: &[String]) -> Result<ExitCode, String> {
+    let json_output = args.iter().any(|arg| arg == "--json");
+    let files: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    let [before, after] = files.as_slice() else {
+        return Err(USAGE.to_string());
+    };
+
+    let diff = diff_traces(&read_trace(before)?, &read_trace(after)?);
+
+    if json_output {
+        let output = serde_json::to_string_pretty(&diff).map_err(|e| e.to_string())?;
+        println!("{}", output);
+    } else {
+        print!("{}", diff);
+    }
+
+    Ok(if diff.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+/// Re-runs a recorded decision and reports how the result differs. Exits 0 when it matches
+/// and 1 when it diverges
+fn replay(args: &[String]) -> Result<ExitCode, String> {
+    let json_output = args.iter().any(|arg| arg == "--json");
+    let files: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    let [path] = files.as_slice() else {
+        return Err(USAGE.to_string());
+    };
+
+    let bundle = read_bundle(path)?;
+    let report = bundle.compare(&bundle.replay());
+
+    if json_output {
+        let output = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+        println!("{}", output);
+    } else {
+        print!("{}", report);
+    }
+
+    Ok(if report.matches() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+/// Runs every `*.case.json` conformance case in a directory against the engine. Exits 0 when
+/// all of them pass and 1 otherwise
+fn conformance(args: &[String]) -> Result<ExitCode, String> {
+    let json_output = args.iter().any(|arg| arg == "--json");
+    let dirs: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    let [dir] = dirs.as_slice() else {
+        return Err(USAGE.to_string());
+    };
+
+    let report = run_directory(Path::new(dir)).map_err(|e| format!("{}: {}", dir, e))?;
+
+    if json_output {
+        let output = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+        println!("{}", output);
+    } else {
+        print!("{}", report);
+    }
+
+    Ok(if report.passed() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+/// Evaluates data against a policy, stopping at its breakpoints to show how far the
+/// evaluation has got. Exits 0 when the evaluation finishes and 1 when it fails or is aborted
+fn debug(args: &[String]) -> Result<ExitCode, String> {
+    let mut breakpoints = Vec::new();
+    let mut files = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--break" {
+            breakpoints.push(args.next().ok_or(USAGE)?.as_str());
+        } else if let Some(breakpoint) = arg.strip_prefix("--break=") {
+            breakpoints.push(breakpoint);
+        } else if !arg.starts_with("--") {
+            files.push(arg);
+        }
+    }
+    let [rules_path, data_path] = files.as_slice() else {
+        return Err(USAGE.to_string());
+    };
+
+    let text = fs::read_to_string(rules_path).map_err(|e| format!("{}: {}", rules_path, e))?;
+    let rule_set = parse_rule_set_with_loader(
+        &text,
+        &ParseLimits::default(),
+        &FileLoader::for_file(rules_path),
+    )
+    .map_err(|e| format!("{}: {}", rules_path, e))?;
+    let data = read_json(data_path)?;
+
+    let debugger = Debugger::new(&rule_set, &breakpoints)?;
+    let options = EvaluationOptions {
+        debug_hook: Some(Arc::new(debugger)),
+        ..EvaluationOptions::new()
+    };
+    let outcome = evaluate(&rule_set, &data, &options);
+
+    println!("result: {}", outcome.result);
+    for (outcome, result) in &outcome.outcomes {
+        println!("  {}: {}", outcome, result);
+    }
+    Ok(match &outcome.error {
+        Some(error) => {
+            println!("error: {}", error);
+            ExitCode::from(1)
+        }
+        None => ExitCode::SUCCESS,
+    })
+}
+
+/// Reports how far the numbers and dates of the data can move before the decision flips.
+/// Exits 0 when none of the listed changes flips it and 1 when one does
+fn sensitivity(args: &[String]) -> Result<ExitCode, String> {
+    let json_output = args.iter().any(|arg| arg == "--json");
+    let files: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    let [rules_path, data_path, spec_path] = files.as_slice() else {
+        return Err(USAGE.to_string());
+    };
+
+    let text = fs::read_to_string(rules_path).map_err(|e| format!("{}: {}", rules_path, e))?;
+    let rule_set = parse_rule_set_with_loader(
+        &text,
+        &ParseLimits::default(),
+        &FileLoader::for_file(rules_path),
+    )
+    .map_err(|e| format!("{}: {}", rules_path, e))?;
+    let data = read_json(data_path)?;
+    let spec: PerturbationSpec = serde_json::from_value(read_json(spec_path)?)
+        .map_err(|e| format!("{}: not a perturbation spec: {}", spec_path, e))?;
+
+    let report = sensitivity_with_options(&rule_set, &data, &spec, &EvaluationOptions::new());
+
+    if json_output {
+        let output = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+        println!("{}", output);
+    } else {
+        print!("{}", report);
+    }
+
+    Ok(if report.flipped() {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// The `DebugHook` of `policy debug`, asking on the terminal what to do at each stop
+#[derive(Debug)]
+struct Debugger {
+    /// Outcomes of the rules to stop before
+    rules: Vec<String>,
+    /// Ids of the conditions to stop after
+    conditions: Vec<String>,
+    /// Stop at the next rule or condition, breakpoint or not
+    stepping: AtomicBool,
+}
+
+impl Debugger {
+    /// Takes each breakpoint as a rule when one answers to it, as written or without the verb
+    /// in front (`passes the theory test`), and otherwise as a condition id
+    fn new(rule_set: &RuleSet, breakpoints: &[&str]) -> Result<Self, String> {
+        let mut debugger = Debugger {
+            rules: Vec::new(),
+            conditions: Vec::new(),
+            stepping: AtomicBool::new(breakpoints.is_empty()),
+        };
+        let condition_ids: Vec<&str> = rule_set
+            .rules
+            .iter()
+            .flat_map(|rule| condition_ids(&rule.conditions))
+            .collect();
+        for breakpoint in breakpoints {
+            let unverbed = breakpoint.split_once(' ').map(|(_, rest)| rest);
+            let rule = [Some(*breakpoint), unverbed]
+                .into_iter()
+                .flatten()
+                .find_map(|name| {
+                    rule_set
+                        .get_rule(name)
+                        .or_else(|| rule_set.get_rule_by_label(name))
+                });
+            match rule {
+                Some(rule) => debugger.rules.push(rule.outcome.clone()),
+                None if condition_ids.contains(breakpoint) => {
+                    debugger.conditions.push(breakpoint.to_string())
+                }
+                None => return Err(format!("--break: no rule or condition '{}'", breakpoint)),
+            }
+        }
+        Ok(debugger)
+    }
+
+    /// Prints the stop and asks what to do until it gets an answer. The end of the input
+    /// continues to the end of the evaluation
+    fn stop(&self, view: &DebugView) -> DebugAction {
+        println!("  call path: {}", view.call_path.join(" > "));
+        for trace in view.finished_rules() {
+            println!("  finished: {} = {}", trace.outcome.value, trace.result);
+        }
+        for (path, value) in view.resolved_values() {
+            println!("  read: {} = {}", path, value);
+        }
+
+        let stdin = io::stdin();
+        loop {
+            print!("(c)ontinue, (s)tep, s(k)ip rule, (a)bort? ");
+            let _ = io::stdout().flush();
+            let mut answer = String::new();
+            if stdin.lock().read_line(&mut answer).unwrap_or(0) == 0 {
+                println!();
+                self.stepping.store(false, Ordering::Relaxed);
+                return DebugAction::Continue;
+            }
+            let (stepping, action) = match answer.trim() {
+                "c" | "continue" => (false, DebugAction::Continue),
+                "s" | "step" => (true, DebugAction::Continue),
+                "k" | "skip" => (false, DebugAction::SkipRule),
+                "a" | "abort" => (false, DebugAction::Abort),
+                _ => continue,
+            };
+            self.stepping.store(stepping, Ordering::Relaxed);
+            return action;
+        }
+    }
+}
+
+impl DebugHook for Debugger {
+    fn on_rule_start(&self, rule: &Rule, view: &DebugView) -> DebugAction {
+        if !self.stepping.load(Ordering::Relaxed) && !self.rules.contains(&rule.outcome) {
+            return DebugAction::Continue;
+        }
+        println!("before rule: {} ({})", rule.name(), rule.selector);
+        self.stop(view)
+    }
+
+    fn on_condition_evaluated(
+        &self,
+        rule: &Rule,
+        condition: &ConditionTrace,
+        view: &DebugView,
+    ) -> DebugAction {
+        let id = condition.id().map_or("", |id| id.0.as_str());
+        if !self.stepping.load(Ordering::Relaxed) && !self.conditions.iter().any(|c| c == id) {
+            return DebugAction::Continue;
+        }
+        println!(
+            "after condition {} of {}: {}",
+            id,
+            rule.name(),
+            condition.result()
+        );
+        if let ConditionTrace::Comparison(comparison) = condition {
+            println!(
+                "  {} = {}, {} {}",
+                comparison.property.path,
+                comparison.property.value,
+                comparison.operator,
+                comparison.expected_value()
+            );
+        }
+        self.stop(view)
+    }
+}
+
+/// Ids of `conditions` and of the conditions in their groups
+fn condition_ids(conditions: &[ConditionGroup]) -> Vec<&str> {
+    conditions
+        .iter()
+        .flat_map(|group| {
+            let mut ids = vec![group.id.0.as_str()];
+            if let Condition::Group(members) = &group.condition {
+                ids.extend(condition_ids(members));
+            }
+            ids
+        })
+        .collect()
+}
+
+fn read_json(path: &str) -> Result<Value, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Reads a bundle from a file holding either a bare bundle or the response that carried it
+fn read_bundle(path: &str) -> Result<ReplayBundle, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut json: Value = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+
+    if let Some(bundle) = json.get_mut("replay") {
+        json = bundle.take();
+    }
+
+    serde_json::from_value(json).map_err(|e| format!("{}: not a replay bundle: {}", path, e))
+}
+
+/// Reads a trace from a file holding either a bare trace or a full evaluation response
+fn read_trace(path: &str) -> Result<RuleSetTrace, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut json: Value = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+
+    if let Some(trace) = json.get_mut("trace") {
+        json = trace.take();
+    }
+
+    serde_json::from_value(json).map_err(|e| format!("{}: not a trace: {}", path, e))
+}
@@ -0,0 +1,207 @@
+#[cfg(test)]
+mod tests {
+    use crate::change::handle_evaluate_change;
+    use crate::runner::model::Condition;
+    use crate::runner::parser::parse_rules;
+    use axum::{routing::post, Router};
+    use serde_json::{json, Value};
+
+    const LIMIT_INCREASE: &str = "\
+A **change** is approved
+  if the __limit__ of the **after.account** is greater than __limit__ of the **before.account**
+  and the __approver__ of the **request** is not equal to __requester__ of the **request**.";
+
+    async fn start_server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/evaluate-change", post(handle_evaluate_change));
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn evaluate_change(base: &str, body: Value) -> (u16, Value) {
+        let response = reqwest::Client::new()
+            .post(format!("{}/evaluate-change", base))
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let text = response.text().await.unwrap();
+        (status, serde_json::from_str(&text).unwrap())
+    }
+
+    fn change(before: u32, after: u32, approver: &str) -> Value {
+        json!({
+            "rule": LIMIT_INCREASE,
+            "before": {"account": {"limit": before}},
+            "after": {"account": {"limit": after}},
+            "request": {"requester": "ana", "approver": approver},
+        })
+    }
+
+    #[test]
+    fn test_conditions_compare_across_documents() {
+        let rule_set = parse_rules(LIMIT_INCREASE).unwrap();
+        let selectors: Vec<_> = rule_set.rules[0]
+            .conditions
+            .iter()
+            .map(|group| match &group.condition {
+                Condition::Comparison(comparison) => (
+                    comparison
+                        .left_property_path
+                        .as_ref()
+                        .unwrap()
+                        .selector
+                        .clone(),
+                    comparison
+                        .right_property_path
+                        .as_ref()
+                        .unwrap()
+                        .selector
+                        .clone(),
+                ),
+                _ => panic!("Expected a comparison"),
+            })
+            .collect();
+        assert_eq!(
+            selectors,
+            vec![
+                ("after.account".to_string(), "before.account".to_string()),
+                ("request".to_string(), "request".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_change_is_evaluated_across_documents() {
+        let base = start_server().await;
+
+        let (status, body) = evaluate_change(&base, change(100, 500, "ben")).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["result"], true);
+        assert_eq!(body["data"]["before"]["account"]["limit"], 100);
+
+        let (_, body) = evaluate_change(&base, change(500, 100, "ben")).await;
+        assert_eq!(body["result"], false);
+        // Approving your own request doesn't count
+        let (_, body) = evaluate_change(&base, change(100, 500, "ana")).await;
+        assert_eq!(body["result"], false);
+    }
+
+    #[tokio::test]
+    async fn test_trace_paths_name_their_document() {
+        let base = start_server().await;
+        let (_, body) = evaluate_change(&base, change(100, 500, "ben")).await;
+
+        let conditions = &body["trace"]["execution"][0]["conditions"];
+        let limit = &conditions[0];
+        assert_eq!(limit["property"]["path"], "$.after.account.limit");
+        assert_eq!(limit["property"]["document"], "after");
+        assert_eq!(limit["value"]["path"], "$.before.account.limit");
+        assert_eq!(limit["value"]["document"], "before");
+        assert_eq!(conditions[1]["property"]["document"], "request");
+        assert_eq!(conditions[1]["value"]["document"], "request");
+    }
+
+    #[tokio::test]
+    async fn test_trace_as_csv() {
+        let base = start_server().await;
+        let mut body = change(500, 100, "ben");
+        body["trace_format"] = json!("csv");
+        let response = reqwest::Client::new()
+            .post(format!("{}/evaluate-change", base))
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/csv; charset=utf-8"
+        );
+
+        let csv = response.text().await.unwrap();
+        let rows: Vec<&str> = csv.split_terminator("\r\n").collect();
+        assert_eq!(rows.len(), 3);
+        assert!(rows[0].starts_with("rule,label,condition,path,"));
+        assert!(
+            rows[1].contains(",$.after.account.limit,is greater than,500.0,data at $.before.account.limit,100,false,true"),
+            "{}",
+            rows[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_document_is_missing_data() {
+        // A change that opens the account has no before state to raise the limit over
+        let base = start_server().await;
+        let (status, body) = evaluate_change(
+            &base,
+            json!({
+                "rule": LIMIT_INCREASE,
+                "after": {"account": {"limit": 500}},
+                "request": {"requester": "ana", "approver": "ben"},
+            }),
+        )
+        .await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["result"], false);
+        assert!(body["data"].get("before").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_parse_errors_by_schema_version() {
+        let base = start_server().await;
+        let mut body = change(100, 500, "ben");
+        body["rule"] = json!("A **change** is approved\n  if the __limit__ of the **after.account** is greater than.");
+
+        // Version 1, the default, still fabricates a trace pointing at the failing line
+        let (status, response) = evaluate_change(&base, body.clone()).await;
+        assert_eq!(status, 400);
+        assert_eq!(response["schema_version"], 1);
+        assert_eq!(response["diagnostics"][0]["line"], 2);
+        let execution = &response["trace"]["execution"][0];
+        assert_eq!(execution["outcome"]["value"], "parse_failed");
+        assert_eq!(
+            execution["conditions"][0]["property"]["path"],
+            "$.rule_syntax.line_2"
+        );
+
+        body["schema_version"] = json!(2);
+        let (status, response) = evaluate_change(&base, body).await;
+        assert_eq!(status, 400);
+        assert_eq!(response["schema_version"], 2);
+        assert!(response.get("trace").is_none(), "{}", response);
+        let diagnostic = &response["diagnostics"][0];
+        assert_eq!(diagnostic["line"], 2);
+        assert_eq!(diagnostic["column"], 60);
+        assert_eq!(
+            diagnostic["snippet"],
+            "  if the __limit__ of the **after.account** is greater than."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_data_root_is_a_structured_bad_request() {
+        let base = start_server().await;
+        let mut body = change(100, 500, "ben");
+        body["data_root"] = json!("$.request.approver");
+
+        let (status, response) = evaluate_change(&base, body).await;
+        assert_eq!(status, 400);
+        assert_eq!(response["result"], false);
+        assert_eq!(
+            response["invalid_data_root"],
+            json!({
+                "root": "$.request.approver",
+                "problem": "not_an_object",
+                "found": "a string",
+            })
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! `POST /evaluate-change`, for change-control policies.
+//!
+//! Change-control policies compare a proposed state with the current one. `POST /evaluate-change`
+//! takes `before`, `after` and `request` documents as top-level fields instead of `data`, merges
+//! them under those keys and evaluates as `POST /` does, with the same options. Rules read each
+//! document through its selector, comparing across documents with a property on the right:
+//!
+//! ```text
+//! A **change** is approved
+//!   if the __limit__ of the **after.account** is greater than __limit__ of the **before.account**
+//!   and the __approver__ of the **request** is not equal to __requester__ of the **request**.
+//! ```
+//!
+//! A document left out (nothing `before` a creation) is missing data rather than null. Trace
+//! properties and property values read from a document carry its name as `document`
+//! (`RuleSetTrace::tag_documents`).
+
+mod lib;
+
+use crate::decision::DecisionMap;
+use crate::runner::options::{LegacyFlatData, ListScalarMode, TraceVerbosity};
+use crate::runner::trace::TraceFormat;
+use crate::tenants::{check_rule_size, Tenant, TenantError};
+use crate::traces::TraceDelivery;
+use crate::{run_package, EchoData, RuleDataPackage};
+use axum::{extract::Json, response::Response, Extension};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// The reserved selectors each document of a change is read through, as in
+/// `the __limit__ of the **after.account**`
+pub const DOCUMENTS: [&str; 3] = ["before", "after", "request"];
+
+/// A proposed change: the current state, the state after it and the request making it, each
+/// as its own document
+#[derive(Deserialize)]
+pub struct ChangeRequest {
+    pub rule: String,
+    /// The state before the change. Left out when the change creates something
+    pub before: Option<Value>,
+    /// The proposed state. Left out when the change deletes something
+    pub after: Option<Value>,
+    /// Who asked for the change and why
+    pub request: Option<Value>,
+    #[serde(default)]
+    pub context: Value,
+    pub entry: Option<String>,
+    pub outcomes: Option<Vec<String>>,
+    #[serde(default)]
+    pub include_outcomes: bool,
+    #[serde(default)]
+    pub include_formatted_rules: bool,
+    #[serde(default)]
+    pub lenient: bool,
+    #[serde(default)]
+    pub validate_data: bool,
+    #[serde(default)]
+    pub strict_references: bool,
+    #[serde(default)]
+    pub list_scalar_mode: ListScalarMode,
+    #[serde(default)]
+    pub range_objects: bool,
+    #[serde(default)]
+    pub versions_as_strings: bool,
+    #[serde(default)]
+    pub case_sensitive_strings: bool,
+    #[serde(default)]
+    pub as_of: Option<chrono::NaiveDate>,
+    pub data_root: Option<String>,
+    #[serde(default)]
+    pub echo_data: EchoData,
+    #[serde(default)]
+    pub record_replay: bool,
+    #[serde(default)]
+    pub trace_format: TraceFormat,
+    #[serde(default)]
+    pub trace_verbosity: TraceVerbosity,
+    #[serde(default)]
+    pub stats: bool,
+    pub schema_version: Option<u32>,
+    #[serde(default)]
+    pub include_tags: Vec<String>,
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+    pub decision_map: Option<DecisionMap>,
+}
+
+impl ChangeRequest {
+    /// The documents merged under their reserved selectors. A document that wasn't sent is
+    /// missing data, not null
+    pub fn data(&self) -> Value {
+        let documents = DOCUMENTS
+            .into_iter()
+            .zip([&self.before, &self.after, &self.request]);
+        let mut data = Map::new();
+        for (name, document) in documents {
+            if let Some(document) = document {
+                data.insert(name.to_string(), document.clone());
+            }
+        }
+        Value::Object(data)
+    }
+
+    fn into_package(self) -> RuleDataPackage {
+        RuleDataPackage {
+            data: self.data(),
+            rule: self.rule,
+            context: self.context,
+            entry: self.entry,
+            outcomes: self.outcomes,
+            include_outcomes: self.include_outcomes,
+            include_formatted_rules: self.include_formatted_rules,
+            lenient: self.lenient,
+            validate_data: self.validate_data,
+            // A change that creates or deletes something leaves a document out on purpose
+            allow_disjoint_data: true,
+            strict_references: self.strict_references,
+            // The documents are always sent under their names
+            legacy_flat_data: LegacyFlatData::Off,
+            list_scalar_mode: self.list_scalar_mode,
+            range_objects: self.range_objects,
+            versions_as_strings: self.versions_as_strings,
+            case_sensitive_strings: self.case_sensitive_strings,
+            as_of: self.as_of,
+            data_root: self.data_root,
+            echo_data: self.echo_data,
+            record_replay: self.record_replay,
+            trace: TraceDelivery::Inline,
+            trace_format: self.trace_format,
+            trace_verbosity: self.trace_verbosity,
+            stats: self.stats,
+            schema_version: self.schema_version,
+            include_tags: self.include_tags,
+            exclude_tags: self.exclude_tags,
+            decision_map: self.decision_map,
+            dry_run: false,
+        }
+    }
+}
+
+/// `POST /evaluate-change`: evaluates a policy against the `before`, `after` and `request`
+/// documents of a change and tags every traced path with the document it read
+pub async fn handle_evaluate_change(
+    tenant: Option<Extension<Tenant>>,
+    Json(change): Json<ChangeRequest>,
+) -> Result<Response, TenantError> {
+    check_rule_size(&tenant, &change.rule)?;
+
+    let format = change.trace_format;
+    let decision_map = change.decision_map.clone();
+    let (status, mut response) = run_package(change.into_package()).await;
+    if let Some(trace) = &mut response.trace {
+        trace.tag_documents(&DOCUMENTS);
+    }
+    Ok(response.into_http(status, format, decision_map.as_ref()))
+}
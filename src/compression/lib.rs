@@ -0,0 +1,138 @@
+#[cfg(test)]
+mod tests {
+    use crate::batch::handle_batch_stream;
+    use crate::compression::with_compression;
+    use crate::config::{JobSettings, ServerConfig, TraceSettings};
+    use crate::jobs::Jobs;
+    use crate::rate_limit::RateLimiter;
+    use crate::tenants::Tenants;
+    use crate::traces::Traces;
+    use crate::{handle_run, AppState};
+    use axum::{routing::post, Router};
+    use flate2::read::GzDecoder;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use serde_json::{json, Value};
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+
+    const SENIOR_RULE: &str = "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 65.";
+
+    async fn start_server(max_body_bytes: usize) -> String {
+        let state = AppState {
+            tenants: Arc::new(Tenants::single()),
+            rate_limiter: Arc::new(RateLimiter::new(None)),
+            config: Arc::new(ServerConfig::default()),
+            jobs: Arc::new(Jobs::from_settings(&JobSettings::default())),
+            traces: Arc::new(Traces::from_settings(&TraceSettings::default())),
+        };
+        let app = Router::new()
+            .route("/", post(handle_run))
+            .route("/batch/stream", post(handle_batch_stream));
+        let app = with_compression(app, max_body_bytes).with_state(state);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn gunzip(bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut out).unwrap();
+        out
+    }
+
+    fn post_gzipped(url: String, body: &Value) -> reqwest::RequestBuilder {
+        reqwest::Client::new()
+            .post(url)
+            .header("content-type", "application/json")
+            .header("content-encoding", "gzip")
+            .body(gzip(body.to_string().as_bytes()))
+    }
+
+    #[tokio::test]
+    async fn test_gzipped_batch_request() {
+        let base = start_server(1024 * 1024).await;
+        let data: Vec<Value> = [70, 30, 65]
+            .iter()
+            .map(|age| json!({"Person": {"age": age}}))
+            .collect();
+        let payload = json!({"rule": SENIOR_RULE, "data": data});
+
+        let response = post_gzipped(format!("{}/batch/stream", base), &payload)
+            .header("accept-encoding", "gzip")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        // Event streams are left uncompressed so events arrive as they are sent
+        assert!(response.headers().get("content-encoding").is_none());
+        let body = response.text().await.unwrap();
+        assert!(
+            body.contains(r#""total":3,"processed":3,"passed":2,"failed":1"#),
+            "{}",
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compressed_trace_matches_uncompressed() {
+        let base = start_server(1024 * 1024).await;
+        let payload = json!({"rule": SENIOR_RULE, "data": {"Person": {"age": 70}}});
+        let send = |encoding: Option<&'static str>| {
+            let mut request = reqwest::Client::new()
+                .post(format!("{}/", base))
+                .header("content-type", "application/json")
+                .body(payload.to_string());
+            if let Some(encoding) = encoding {
+                request = request.header("accept-encoding", encoding);
+            }
+            request.send()
+        };
+
+        let plain = send(None).await.unwrap();
+        assert!(plain.headers().get("content-encoding").is_none());
+        let plain: Value = serde_json::from_slice(&plain.bytes().await.unwrap()).unwrap();
+
+        let compressed = send(Some("gzip")).await.unwrap();
+        assert_eq!(compressed.headers()["content-encoding"], "gzip");
+        let decoded: Value =
+            serde_json::from_slice(&gunzip(&compressed.bytes().await.unwrap())).unwrap();
+
+        assert_eq!(decoded, plain);
+        assert_eq!(decoded["result"], true);
+        assert!(decoded["trace"].is_object());
+
+        let brotli = send(Some("br")).await.unwrap();
+        assert_eq!(brotli.headers()["content-encoding"], "br");
+    }
+
+    #[tokio::test]
+    async fn test_decompressed_size_is_limited() {
+        let base = start_server(64 * 1024).await;
+        // Compresses to about 1KB but expands well past the limit
+        let payload = json!({
+            "rule": SENIOR_RULE,
+            "data": {"Person": {"age": 70, "padding": " ".repeat(1024 * 1024)}},
+        });
+        let compressed = gzip(payload.to_string().as_bytes());
+        assert!(compressed.len() < 64 * 1024);
+
+        let response = post_gzipped(format!("{}/", base), &payload)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 413);
+    }
+}
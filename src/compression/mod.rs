@@ -0,0 +1,23 @@
+mod lib;
+
+use axum::{extract::DefaultBodyLimit, Router};
+use tower_http::compression::CompressionLayer;
+use tower_http::decompression::RequestDecompressionLayer;
+
+/// axum's own default request body limit, used unless `max_body_bytes` is configured
+pub const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Compresses responses per `Accept-Encoding` (gzip, br) and accepts `Content-Encoding: gzip`
+/// request bodies.
+///
+/// The body limit is enforced by the extractors on the decompressed stream, so a small
+/// compressed body can't expand past it. Server-sent events are left uncompressed.
+pub fn with_compression<S>(router: Router<S>, max_body_bytes: usize) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router
+        .layer(DefaultBodyLimit::max(max_body_bytes))
+        .layer(RequestDecompressionLayer::new())
+        .layer(CompressionLayer::new())
+}
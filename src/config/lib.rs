@@ -0,0 +1,265 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::{handle_configz, IpNetwork, ServerConfig, ADMIN_KEY_HEADER};
+    use axum::{routing::get, Router};
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    const CONFIG: &str = r#"
+        port = 8080
+        max_body_bytes = 1024
+
+        [limits]
+        max_rules = 20
+
+        [rate_limit]
+        rps = 5.0
+
+        [configz]
+        admin_key = "s3cret"
+    "#;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    fn write_config(name: &str, text: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("engine-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_defaults_without_file_or_env() {
+        let config = ServerConfig::resolve(&[], vars(&[])).unwrap();
+        assert_eq!(config, ServerConfig::default());
+        assert_eq!(config.port, 3000);
+        assert!(config.rate_limit_config().is_none());
+    }
+
+    #[test]
+    fn test_example_config_holds_the_defaults() {
+        let example = ServerConfig::from_toml(include_str!("../../engine.example.toml")).unwrap();
+        assert_eq!(example, ServerConfig::default());
+    }
+
+    #[test]
+    fn test_file_overrides_defaults_and_env_overrides_file() {
+        let path = write_config("precedence", CONFIG);
+
+        let config = ServerConfig::resolve(&[], vars(&[("CONFIG_PATH", &path)])).unwrap();
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.max_body_bytes, 1024);
+        assert_eq!(config.limits.max_rules, Some(20));
+        // Left out of the file, so still the default
+        assert_eq!(config.grpc_port, 50051);
+        assert_eq!(config.rate_limit_config().unwrap().burst, 5);
+
+        let config = ServerConfig::resolve(
+            &[],
+            vars(&[("CONFIG_PATH", &path), ("PORT", "9090"), ("MAX_RULES", "3")]),
+        )
+        .unwrap();
+        assert_eq!(config.port, 9090);
+        assert_eq!(config.limits.max_rules, Some(3));
+        assert_eq!(config.max_body_bytes, 1024);
+    }
+
+    #[test]
+    fn test_evaluation_options_take_the_configured_limits() {
+        let config = ServerConfig::resolve(
+            &[],
+            vars(&[
+                ("MAX_DATA_DEPTH", "8"),
+                ("MAX_SCANNED_KEYS", "50"),
+                ("MAX_EVALUATION_DEPTH", "4"),
+                ("MAX_CALL_PATH_FRAMES", "3"),
+            ]),
+        )
+        .unwrap();
+        let options = config.evaluation_options();
+        assert_eq!(options.max_data_depth, 8);
+        assert_eq!(options.max_scanned_keys, 50);
+        assert_eq!(options.max_reference_depth, 4);
+        assert_eq!(options.max_call_path_frames, 3);
+        assert_eq!(
+            options.max_condition_evaluations,
+            config.limits.max_condition_evaluations
+        );
+    }
+
+    #[test]
+    fn test_config_flag_overrides_config_path() {
+        let flag = write_config("flag", "port = 1111");
+        let env = write_config("env", "port = 2222");
+
+        let args = vec!["--config".to_string(), flag.clone()];
+        let config = ServerConfig::resolve(&args, vars(&[("CONFIG_PATH", &env)])).unwrap();
+        assert_eq!(config.port, 1111);
+
+        let args = vec![format!("--config={}", flag)];
+        assert_eq!(ServerConfig::resolve(&args, vars(&[])).unwrap().port, 1111);
+
+        let error = ServerConfig::resolve(&["--config".to_string()], vars(&[])).unwrap_err();
+        assert_eq!(error, "--config needs a file path");
+    }
+
+    #[test]
+    fn test_invalid_settings_are_all_reported() {
+        let path = write_config(
+            "invalid",
+            r#"
+            [rate_limit]
+            rps = -2.0
+
+            [configz]
+            allow_networks = ["10.0.0.0/8", "10.0.0.0/33", "intranet"]
+
+            [webhook]
+            url = "hooks.example.com"
+        "#,
+        );
+        let error = ServerConfig::resolve(
+            &[],
+            vars(&[
+                ("CONFIG_PATH", &path),
+                ("MAX_RULES", "-1"),
+                ("PORT", "http"),
+            ]),
+        )
+        .unwrap_err();
+        let problems: Vec<&str> = error.lines().collect();
+        assert_eq!(
+            problems,
+            vec![
+                "PORT: 'http' is not a valid port number",
+                "MAX_RULES: '-1' is not a valid whole number of 0 or more",
+                "rate_limit.rps must be 0 or more, got -2; leave it out to turn rate limiting off",
+                "configz.allow_networks: '10.0.0.0/33' has an invalid prefix length; it must be 0 to 32",
+                "configz.allow_networks: 'intranet' is not an IP address or CIDR block",
+                "webhook.url must be an http:// or https:// URL, got 'hooks.example.com'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_and_mistyped_settings_are_rejected() {
+        let error = ServerConfig::from_toml("max_rules = 3").unwrap_err();
+        assert!(error.contains("unknown field `max_rules`"), "{}", error);
+
+        let error = ServerConfig::from_toml("[limits]\nmax_rules = -3").unwrap_err();
+        assert!(error.contains("max_rules"), "{}", error);
+
+        let config = ServerConfig::from_toml(
+            "[limits]\nlist_warning_elements = 100\nmax_list_elements = 10",
+        )
+        .unwrap();
+        assert_eq!(config.validate().len(), 1);
+    }
+
+    #[test]
+    fn test_redacted_hides_secrets() {
+        let config = ServerConfig::resolve(
+            &[],
+            vars(&[
+                ("CONFIGZ_ADMIN_KEY", "s3cret"),
+                ("FF_AGENT_ID", "agent-1"),
+                ("WEBHOOK_SECRET", "s3cret"),
+            ]),
+        )
+        .unwrap();
+        let redacted = config.redacted();
+        assert_eq!(redacted["configz"]["admin_key"], "[redacted]");
+        assert_eq!(redacted["webhook"]["secret"], "[redacted]");
+        assert_eq!(redacted["flags"]["agent_id"], "[redacted]");
+        assert_eq!(redacted["flags"]["project_id"], "default-project");
+        assert!(!redacted.to_string().contains("s3cret"));
+    }
+
+    #[test]
+    fn test_ip_network_contains() {
+        let network = IpNetwork::from_str("10.1.0.0/16").unwrap();
+        assert!(network.contains("10.1.200.3".parse().unwrap()));
+        assert!(!network.contains("10.2.0.1".parse().unwrap()));
+        // IPv4 clients on a dual stack socket
+        assert!(network.contains("::ffff:10.1.0.9".parse().unwrap()));
+
+        let everyone = IpNetwork::from_str("0.0.0.0/0").unwrap();
+        assert!(everyone.contains("192.168.1.1".parse().unwrap()));
+        let host = IpNetwork::from_str("::1").unwrap();
+        assert!(host.contains("::1".parse().unwrap()));
+        assert!(!host.contains("127.0.0.1".parse().unwrap()));
+    }
+
+    async fn start_server(config: ServerConfig) -> String {
+        let app = Router::new()
+            .route("/configz", get(handle_configz))
+            .with_state(Arc::new(config));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap();
+        });
+        format!("http://{}/configz", addr)
+    }
+
+    async fn get_configz(url: &str, key: Option<&str>) -> (u16, String) {
+        let mut request = reqwest::Client::new().get(url);
+        if let Some(key) = key {
+            request = request.header(ADMIN_KEY_HEADER, key);
+        }
+        let response = request.send().await.unwrap();
+        (response.status().as_u16(), response.text().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_configz_is_off_by_default() {
+        let url = start_server(ServerConfig::default()).await;
+        assert_eq!(get_configz(&url, Some("anything")).await.0, 404);
+    }
+
+    #[tokio::test]
+    async fn test_configz_needs_the_admin_key() {
+        let mut config = ServerConfig::default();
+        config.configz.admin_key = Some("s3cret".to_string());
+        let url = start_server(config).await;
+
+        let (status, body) = get_configz(&url, None).await;
+        assert_eq!(status, 403);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["code"], "forbidden");
+        assert_eq!(get_configz(&url, Some("guess")).await.0, 403);
+
+        let (status, body) = get_configz(&url, Some("s3cret")).await;
+        assert_eq!(status, 200);
+        assert!(!body.contains("s3cret"));
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["port"], 3000);
+    }
+
+    #[tokio::test]
+    async fn test_configz_allows_listed_networks() {
+        let mut config = ServerConfig::default();
+        config.configz.allow_networks = vec!["127.0.0.0/8".to_string()];
+        let url = start_server(config).await;
+        assert_eq!(get_configz(&url, None).await.0, 200);
+
+        let mut config = ServerConfig::default();
+        config.configz.allow_networks = vec!["10.0.0.0/8".to_string()];
+        let url = start_server(config).await;
+        assert_eq!(get_configz(&url, None).await.0, 403);
+    }
+}
@@ -0,0 +1,661 @@
+//! Server settings from defaults, a TOML file and env vars.
+//!
+//! Every setting can also come from a TOML file given as `--config engine.toml` (or `CONFIG_PATH`);
+//! see the commented `engine.example.toml`. Env vars override the file, and the file overrides the
+//! defaults. Invalid settings (a bad CIDR, a negative limit, an unknown key) stop the server at
+//! startup with one line per problem. `GET /configz` shows the effective config with secrets
+//! redacted; it is off unless `[configz]` sets an `admin_key` (sent as `x-admin-key`, or
+//! `CONFIGZ_ADMIN_KEY`) or `allow_networks`.
+
+mod lib;
+
+use crate::compression::DEFAULT_MAX_BODY_BYTES;
+use crate::rate_limit::RateLimitConfig;
+use crate::runner::options::{
+    EvaluationOptions, DEFAULT_MAX_CALL_PATH_FRAMES, DEFAULT_MAX_CONDITION_EVALUATIONS,
+    DEFAULT_MAX_DATA_DEPTH, DEFAULT_MAX_REFERENCE_DEPTH, DEFAULT_MAX_SCANNED_KEYS,
+};
+use crate::runner::parser::ParseLimits;
+use crate::runner::stats::ComplexityLimits;
+use axum::{
+    extract::{ConnectInfo, Extension, Json, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+/// Env var naming the config file when `--config` isn't given
+pub const CONFIG_PATH_VAR: &str = "CONFIG_PATH";
+
+/// Header carrying `configz.admin_key`
+pub const ADMIN_KEY_HEADER: &str = "x-admin-key";
+
+const REDACTED: &str = "[redacted]";
+
+/// Server settings, read from a TOML file (see `engine.example.toml`) with env vars taking
+/// precedence over the file and the file over the defaults
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// HTTP port, `PORT`
+    pub port: u16,
+    /// gRPC port when built with the `grpc` feature, `GRPC_PORT`
+    pub grpc_port: u16,
+    /// Request body limit after decompression, `MAX_BODY_BYTES`
+    pub max_body_bytes: usize,
+    /// Reject JSON bodies with a key repeated in the same object, instead of keeping the last
+    /// of them, see `ingest::duplicate_keys_layer`. `REJECT_DUPLICATE_KEYS`
+    pub reject_duplicate_keys: bool,
+    /// Tenants file, see `TenantsConfig`. `TENANTS_CONFIG`
+    pub tenants_config: Option<String>,
+    /// Directory of `.txt` policies checked by `--self-test`. `POLICY_DIR`
+    pub policy_dir: Option<String>,
+    /// Directory of compiled policies, so policies are parsed once across restarts, see
+    /// `runner::compiled::PolicyCache`. Off when unset. `POLICY_CACHE_DIR`
+    pub policy_cache_dir: Option<String>,
+    pub limits: LimitSettings,
+    pub rate_limit: RateLimitSettings,
+    pub batch: BatchSettings,
+    pub jobs: JobSettings,
+    pub traces: TraceSettings,
+    pub flags: FlagSettings,
+    pub configz: ConfigzSettings,
+    pub webhook: WebhookSettings,
+}
+
+/// Parse and complexity limits for accepted policies
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitSettings {
+    /// `LIST_WARNING_ELEMENTS`
+    pub list_warning_elements: usize,
+    /// `MAX_LIST_ELEMENTS`
+    pub max_list_elements: usize,
+    /// `MAX_RULES`, unlimited when unset
+    pub max_rules: Option<usize>,
+    /// `MAX_CONDITIONS`, unlimited when unset
+    pub max_conditions: Option<usize>,
+    /// `MAX_REFERENCE_DEPTH`, unlimited when unset
+    pub max_reference_depth: Option<usize>,
+    /// `MAX_COST`, unlimited when unset
+    pub max_cost: Option<usize>,
+    /// Conditions one evaluation may evaluate before it fails. `MAX_CONDITION_EVALUATIONS`
+    pub max_condition_evaluations: usize,
+    /// How deep into the data a property path may read. `MAX_DATA_DEPTH`
+    pub max_data_depth: usize,
+    /// Largest object searched key by key for a differently written name. `MAX_SCANNED_KEYS`
+    pub max_scanned_keys: usize,
+    /// How deep rule references may nest while evaluating. `MAX_EVALUATION_DEPTH`
+    pub max_evaluation_depth: usize,
+    /// Rules an error message names before cutting the call path. `MAX_CALL_PATH_FRAMES`
+    pub max_call_path_frames: usize,
+}
+
+/// Per-client token buckets, off unless `rps` is set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitSettings {
+    /// `RATE_LIMIT_RPS`
+    pub rps: Option<f64>,
+    /// `RATE_LIMIT_BURST`, one second's worth of requests when unset
+    pub burst: Option<u32>,
+}
+
+/// Memory bounds of `POST /batch/stream`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatchSettings {
+    /// Items evaluated before their results are sent and their data dropped.
+    /// `BATCH_CHUNK_SIZE`
+    pub chunk_size: usize,
+    /// Approximate serialized size of batch items held across every running batch; a batch
+    /// that would go over is refused with 503. `BATCH_MAX_IN_FLIGHT_BYTES`
+    pub max_in_flight_bytes: usize,
+}
+
+/// Batches run in the background through `POST /jobs`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobSettings {
+    /// Jobs run at once; the rest wait queued. `JOB_WORKERS`
+    pub workers: usize,
+    /// How long a finished job can still be polled. `JOB_TTL_SECS`
+    pub ttl_secs: u64,
+}
+
+/// Traces answered by reference with `"trace": "stored"`, see `traces::Traces`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraceSettings {
+    /// Directory traces are written to, so they outlive a restart; kept in memory when unset.
+    /// `TRACE_STORE_DIR`
+    pub dir: Option<String>,
+    /// How long a stored trace can still be fetched. `TRACE_TTL_SECS`
+    pub ttl_secs: u64,
+}
+
+/// Feature flag service credentials, and what policies take for flags it doesn't report
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlagSettings {
+    /// `FF_ENV_ID`
+    pub environment_id: String,
+    /// `FF_AGENT_ID`
+    pub agent_id: String,
+    /// `FF_PROJECT_ID`
+    pub project_id: String,
+    /// State of a `flag "name"` condition whose flag the service doesn't report.
+    /// `FF_UNKNOWN_DEFAULT`
+    pub unknown_default: bool,
+}
+
+/// Who may read `GET /configz`. With neither set the endpoint is off
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigzSettings {
+    /// Sent in `x-admin-key`. `CONFIGZ_ADMIN_KEY`
+    pub admin_key: Option<String>,
+    /// CIDR blocks whose clients need no key, e.g. `10.0.0.0/8`
+    pub allow_networks: Vec<String>,
+}
+
+/// Where policy and shadow events are POSTed, see `events::WebhookSink`. A tenant with its own
+/// `webhook` in the tenants file uses that instead
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookSettings {
+    /// Off when unset, unless a tenant names its own. `WEBHOOK_URL`
+    pub url: Option<String>,
+    /// Key the `x-engine-signature` HMAC is made with. `WEBHOOK_SECRET`
+    pub secret: Option<String>,
+    /// Events waiting to be sent; the oldest is dropped when more arrive. `WEBHOOK_QUEUE_SIZE`
+    pub queue_size: usize,
+    /// Tries per event, counting the first. `WEBHOOK_MAX_ATTEMPTS`
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for each one after
+    pub retry_delay_ms: u64,
+    /// Time one attempt may take
+    pub timeout_ms: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: 3000,
+            grpc_port: 50051,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            reject_duplicate_keys: false,
+            tenants_config: None,
+            policy_dir: None,
+            policy_cache_dir: None,
+            limits: LimitSettings::default(),
+            rate_limit: RateLimitSettings::default(),
+            batch: BatchSettings::default(),
+            jobs: JobSettings::default(),
+            traces: TraceSettings::default(),
+            flags: FlagSettings::default(),
+            configz: ConfigzSettings::default(),
+            webhook: WebhookSettings::default(),
+        }
+    }
+}
+
+impl Default for LimitSettings {
+    fn default() -> Self {
+        let parse = ParseLimits::default();
+        Self {
+            list_warning_elements: parse.list_warning_elements,
+            max_list_elements: parse.max_list_elements,
+            max_rules: None,
+            max_conditions: None,
+            max_reference_depth: None,
+            max_cost: None,
+            max_condition_evaluations: DEFAULT_MAX_CONDITION_EVALUATIONS,
+            max_data_depth: DEFAULT_MAX_DATA_DEPTH,
+            max_scanned_keys: DEFAULT_MAX_SCANNED_KEYS,
+            max_evaluation_depth: DEFAULT_MAX_REFERENCE_DEPTH,
+            max_call_path_frames: DEFAULT_MAX_CALL_PATH_FRAMES,
+        }
+    }
+}
+
+impl Default for BatchSettings {
+    fn default() -> Self {
+        Self {
+            chunk_size: 256,
+            max_in_flight_bytes: 256 * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for JobSettings {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            ttl_secs: 3600,
+        }
+    }
+}
+
+impl Default for TraceSettings {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            ttl_secs: 86400,
+        }
+    }
+}
+
+impl Default for FlagSettings {
+    fn default() -> Self {
+        Self {
+            environment_id: "default-env".to_string(),
+            agent_id: "default-agent".to_string(),
+            project_id: "default-project".to_string(),
+            unknown_default: false,
+        }
+    }
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            url: None,
+            secret: None,
+            queue_size: 1000,
+            max_attempts: 5,
+            retry_delay_ms: 500,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::from_toml(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// The effective config: the file named by `--config` in `args` or by `CONFIG_PATH`,
+    /// overridden by env vars read through `var`, then validated. Every problem found is
+    /// listed, one per line
+    pub fn resolve(args: &[String], var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let path = config_path_arg(args)?.or_else(|| var(CONFIG_PATH_VAR));
+        let mut config = match path {
+            Some(path) => Self::load(&path)?,
+            None => Self::default(),
+        };
+
+        let mut problems = config.apply_env(var);
+        problems.extend(config.validate());
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(problems.join("\n"))
+        }
+    }
+
+    /// Replaces settings with the env vars that are set, returning those that don't parse
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut env = EnvOverrides {
+            var: &var,
+            problems: Vec::new(),
+        };
+
+        env.set("PORT", &mut self.port);
+        env.set("GRPC_PORT", &mut self.grpc_port);
+        env.set("MAX_BODY_BYTES", &mut self.max_body_bytes);
+        env.set("REJECT_DUPLICATE_KEYS", &mut self.reject_duplicate_keys);
+        env.set_some("TENANTS_CONFIG", &mut self.tenants_config);
+        env.set_some("POLICY_DIR", &mut self.policy_dir);
+        env.set_some("POLICY_CACHE_DIR", &mut self.policy_cache_dir);
+
+        let limits = &mut self.limits;
+        env.set("LIST_WARNING_ELEMENTS", &mut limits.list_warning_elements);
+        env.set("MAX_LIST_ELEMENTS", &mut limits.max_list_elements);
+        env.set_some("MAX_RULES", &mut limits.max_rules);
+        env.set_some("MAX_CONDITIONS", &mut limits.max_conditions);
+        env.set_some("MAX_REFERENCE_DEPTH", &mut limits.max_reference_depth);
+        env.set_some("MAX_COST", &mut limits.max_cost);
+        env.set(
+            "MAX_CONDITION_EVALUATIONS",
+            &mut limits.max_condition_evaluations,
+        );
+        env.set("MAX_DATA_DEPTH", &mut limits.max_data_depth);
+        env.set("MAX_SCANNED_KEYS", &mut limits.max_scanned_keys);
+        env.set("MAX_EVALUATION_DEPTH", &mut limits.max_evaluation_depth);
+        env.set("MAX_CALL_PATH_FRAMES", &mut limits.max_call_path_frames);
+
+        env.set_some("RATE_LIMIT_RPS", &mut self.rate_limit.rps);
+        env.set_some("RATE_LIMIT_BURST", &mut self.rate_limit.burst);
+
+        env.set("BATCH_CHUNK_SIZE", &mut self.batch.chunk_size);
+        env.set(
+            "BATCH_MAX_IN_FLIGHT_BYTES",
+            &mut self.batch.max_in_flight_bytes,
+        );
+
+        env.set("JOB_WORKERS", &mut self.jobs.workers);
+        env.set("JOB_TTL_SECS", &mut self.jobs.ttl_secs);
+
+        env.set_some("TRACE_STORE_DIR", &mut self.traces.dir);
+        env.set("TRACE_TTL_SECS", &mut self.traces.ttl_secs);
+
+        env.set("FF_ENV_ID", &mut self.flags.environment_id);
+        env.set("FF_AGENT_ID", &mut self.flags.agent_id);
+        env.set("FF_PROJECT_ID", &mut self.flags.project_id);
+        env.set("FF_UNKNOWN_DEFAULT", &mut self.flags.unknown_default);
+
+        env.set_some("CONFIGZ_ADMIN_KEY", &mut self.configz.admin_key);
+
+        env.set_some("WEBHOOK_URL", &mut self.webhook.url);
+        env.set_some("WEBHOOK_SECRET", &mut self.webhook.secret);
+        env.set("WEBHOOK_QUEUE_SIZE", &mut self.webhook.queue_size);
+        env.set("WEBHOOK_MAX_ATTEMPTS", &mut self.webhook.max_attempts);
+
+        env.problems
+    }
+
+    /// Settings that parse but can't work, each with what to change
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.max_body_bytes == 0 {
+            problems.push("max_body_bytes must be above 0".to_string());
+        }
+        if self.limits.list_warning_elements > self.limits.max_list_elements {
+            problems.push(format!(
+                "limits.list_warning_elements ({}) is above limits.max_list_elements ({}), so long lists would be rejected before they are warned about",
+                self.limits.list_warning_elements, self.limits.max_list_elements
+            ));
+        }
+        if let Some(rps) = self.rate_limit.rps {
+            if !rps.is_finite() || rps < 0.0 {
+                problems.push(format!(
+                    "rate_limit.rps must be 0 or more, got {}; leave it out to turn rate limiting off",
+                    rps
+                ));
+            }
+        }
+        if self.rate_limit.burst == Some(0) {
+            problems.push("rate_limit.burst must be at least 1".to_string());
+        }
+        if self.limits.max_condition_evaluations == 0 {
+            problems.push("limits.max_condition_evaluations must be at least 1".to_string());
+        }
+        if self.limits.max_data_depth == 0 {
+            problems.push("limits.max_data_depth must be at least 1".to_string());
+        }
+        if self.limits.max_evaluation_depth == 0 {
+            problems.push("limits.max_evaluation_depth must be at least 1".to_string());
+        }
+        if self.batch.chunk_size == 0 {
+            problems.push("batch.chunk_size must be at least 1".to_string());
+        }
+        if self.batch.max_in_flight_bytes == 0 {
+            problems.push("batch.max_in_flight_bytes must be above 0".to_string());
+        }
+        if self.jobs.workers == 0 {
+            problems.push("jobs.workers must be at least 1".to_string());
+        }
+        if self.jobs.ttl_secs == 0 {
+            problems.push("jobs.ttl_secs must be at least 1".to_string());
+        }
+        if self.traces.ttl_secs == 0 {
+            problems.push("traces.ttl_secs must be at least 1".to_string());
+        }
+        for network in &self.configz.allow_networks {
+            if let Err(problem) = IpNetwork::from_str(network) {
+                problems.push(format!("configz.allow_networks: {}", problem));
+            }
+        }
+        if let Some(url) = &self.webhook.url {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                problems.push(format!(
+                    "webhook.url must be an http:// or https:// URL, got '{}'",
+                    url
+                ));
+            }
+        }
+        if self.webhook.queue_size == 0 {
+            problems.push("webhook.queue_size must be at least 1".to_string());
+        }
+        if self.webhook.max_attempts == 0 {
+            problems.push("webhook.max_attempts must be at least 1".to_string());
+        }
+
+        problems
+    }
+
+    pub fn parse_limits(&self) -> ParseLimits {
+        ParseLimits {
+            list_warning_elements: self.limits.list_warning_elements,
+            max_list_elements: self.limits.max_list_elements,
+        }
+    }
+
+    pub fn complexity_limits(&self) -> ComplexityLimits {
+        ComplexityLimits {
+            max_rules: self.limits.max_rules,
+            max_conditions: self.limits.max_conditions,
+            max_reference_depth: self.limits.max_reference_depth,
+            max_cost: self.limits.max_cost,
+        }
+    }
+
+    /// Options every evaluation the server runs starts from, with the configured limits
+    pub fn evaluation_options(&self) -> EvaluationOptions {
+        EvaluationOptions::new()
+            .with_max_condition_evaluations(self.limits.max_condition_evaluations)
+            .with_max_data_depth(self.limits.max_data_depth)
+            .with_max_scanned_keys(self.limits.max_scanned_keys)
+            .with_max_reference_depth(self.limits.max_evaluation_depth)
+            .with_max_call_path_frames(self.limits.max_call_path_frames)
+    }
+
+    /// Token bucket settings, `None` when rate limiting is off
+    pub fn rate_limit_config(&self) -> Option<RateLimitConfig> {
+        let rps = self.rate_limit.rps.filter(|rps| *rps > 0.0)?;
+        let burst = self.rate_limit.burst.unwrap_or(rps.ceil() as u32).max(1);
+        Some(RateLimitConfig { rps, burst })
+    }
+
+    /// The config as JSON with secrets replaced by `[redacted]`
+    pub fn redacted(&self) -> Value {
+        let mut config = self.clone();
+        if config.configz.admin_key.is_some() {
+            config.configz.admin_key = Some(REDACTED.to_string());
+        }
+        config.flags.agent_id = REDACTED.to_string();
+        if config.webhook.secret.is_some() {
+            config.webhook.secret = Some(REDACTED.to_string());
+        }
+        serde_json::to_value(config).expect("config serializes to JSON")
+    }
+}
+
+/// The path given as `--config <path>` or `--config=<path>`
+fn config_path_arg(args: &[String]) -> Result<Option<String>, String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Ok(Some(path.to_string()));
+        }
+        if arg == "--config" {
+            return args
+                .next()
+                .map(|path| Some(path.clone()))
+                .ok_or_else(|| "--config needs a file path".to_string());
+        }
+    }
+    Ok(None)
+}
+
+struct EnvOverrides<'a, F> {
+    var: &'a F,
+    problems: Vec<String>,
+}
+
+impl<F: Fn(&str) -> Option<String>> EnvOverrides<'_, F> {
+    fn parse<T: FromStr>(&mut self, name: &str) -> Option<T> {
+        let value = (self.var)(name)?;
+        match value.trim().parse() {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                self.problems.push(format!(
+                    "{}: '{}' is not a valid {}",
+                    name,
+                    value,
+                    type_description::<T>()
+                ));
+                None
+            }
+        }
+    }
+
+    fn set<T: FromStr>(&mut self, name: &str, setting: &mut T) {
+        if let Some(value) = self.parse(name) {
+            *setting = value;
+        }
+    }
+
+    fn set_some<T: FromStr>(&mut self, name: &str, setting: &mut Option<T>) {
+        if let Some(value) = self.parse(name) {
+            *setting = Some(value);
+        }
+    }
+}
+
+/// What an env var of type `T` has to hold, for error messages
+fn type_description<T>() -> &'static str {
+    match std::any::type_name::<T>() {
+        "u16" => "port number",
+        "u32" | "usize" => "whole number of 0 or more",
+        "f64" => "number",
+        "bool" => "true or false",
+        _ => "value",
+    }
+}
+
+/// A CIDR block such as `10.0.0.0/8` or `fd00::/8`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match text.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (text, None),
+        };
+        let address: IpAddr = address
+            .parse()
+            .map_err(|_| format!("'{}' is not an IP address or CIDR block", text))?;
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            None => max_prefix,
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(|| {
+                    format!(
+                        "'{}' has an invalid prefix length; it must be 0 to {}",
+                        text, max_prefix
+                    )
+                })?,
+        };
+        Ok(Self { address, prefix })
+    }
+}
+
+impl IpNetwork {
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl ConfigzSettings {
+    fn enabled(&self) -> bool {
+        self.admin_key.is_some() || !self.allow_networks.is_empty()
+    }
+
+    /// Whether a request with these headers from `client` may read the config
+    fn allows(&self, headers: &HeaderMap, client: Option<IpAddr>) -> bool {
+        let key = headers
+            .get(ADMIN_KEY_HEADER)
+            .and_then(|value| value.to_str().ok());
+        if let (Some(expected), Some(key)) = (&self.admin_key, key) {
+            if expected == key {
+                return true;
+            }
+        }
+        client.is_some_and(|client| {
+            self.allow_networks.iter().any(|network| {
+                IpNetwork::from_str(network).is_ok_and(|network| network.contains(client))
+            })
+        })
+    }
+}
+
+static CURRENT: OnceLock<ServerConfig> = OnceLock::new();
+
+/// Makes `config` the one `current` returns. Only the first call has an effect
+pub fn install(config: ServerConfig) {
+    let _ = CURRENT.set(config);
+}
+
+/// The installed config, or the defaults with env overrides when none was installed
+pub fn current() -> &'static ServerConfig {
+    CURRENT.get_or_init(|| {
+        ServerConfig::resolve(&[], |name| std::env::var(name).ok())
+            .unwrap_or_else(|problems| panic!("Invalid server config:\n{}", problems))
+    })
+}
+
+/// `GET /configz` - the effective config with secrets redacted, for an admin key or an
+/// allowed network. Not found when `configz` allows no one
+pub async fn handle_configz(
+    State(config): State<Arc<ServerConfig>>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+) -> Response {
+    if !config.configz.enabled() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let client = connect_info.map(|Extension(ConnectInfo(address))| address.ip());
+    if !config.configz.allows(&headers, client) {
+        let body = serde_json::json!({
+            "error": format!("Send the admin key in {}", ADMIN_KEY_HEADER),
+            "code": "forbidden",
+        });
+        return (StatusCode::FORBIDDEN, Json(body)).into_response();
+    }
+    Json(config.redacted()).into_response()
+}
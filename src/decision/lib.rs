@@ -0,0 +1,335 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::{JobSettings, ServerConfig, TraceSettings};
+    use crate::jobs::Jobs;
+    use crate::rate_limit::RateLimiter;
+    use crate::runner::version::{ENGINE_VERSION, LANGUAGE_VERSION};
+    use crate::tenants::Tenants;
+    use crate::traces::Traces;
+    use crate::{handle_run, handle_version, AppState};
+    use axum::routing::{get, post};
+    use axum::Router;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    const ADULT_RULE: &str =
+        "A **user** is an adult if the __age__ of the **user** is greater than or equal to 18.";
+
+    async fn start_server() -> String {
+        let state = AppState {
+            tenants: Arc::new(Tenants::single()),
+            rate_limiter: Arc::new(RateLimiter::new(None)),
+            config: Arc::new(ServerConfig::default()),
+            jobs: Arc::new(Jobs::from_settings(&JobSettings::default())),
+            traces: Arc::new(Traces::from_settings(&TraceSettings::default())),
+        };
+        let app = Router::new()
+            .route("/", post(handle_run))
+            .route("/version", get(handle_version))
+            .with_state(state);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn run(base: &str, body: Value) -> (u16, Value) {
+        let response = reqwest::Client::new()
+            .post(format!("{}/", base))
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let text = response.text().await.unwrap();
+        (status, serde_json::from_str(&text).unwrap())
+    }
+
+    fn gateway_map() -> Value {
+        json!({
+            "true": {"status": 200},
+            "false": {"status": 403, "message_from": "explanation"}
+        })
+    }
+
+    #[tokio::test]
+    async fn test_mapped_decision_answers_with_its_status() {
+        let base = start_server().await;
+        let (status, body) = run(
+            &base,
+            json!({
+                "rule": ADULT_RULE,
+                "data": {"user": {"age": 30}},
+                "decision_map": gateway_map()
+            }),
+        )
+        .await;
+
+        assert_eq!(status, 200);
+        assert_eq!(body["decision"], "allow");
+        assert!(body.get("message").is_none());
+        assert_eq!(body["decision_fingerprint"].as_str().unwrap().len(), 64);
+        assert!(body.get("trace").is_none() && body.get("result").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_denied_decision_explains_itself() {
+        let base = start_server().await;
+        let (status, body) = run(
+            &base,
+            json!({
+                "rule": ADULT_RULE,
+                "data": {"user": {"age": 16}},
+                "decision_map": gateway_map()
+            }),
+        )
+        .await;
+
+        assert_eq!(status, 403);
+        let fingerprint = body["decision_fingerprint"].as_str().unwrap().to_string();
+        assert_eq!(
+            body,
+            json!({
+                "decision": "deny",
+                "message": "an adult: $.user.age is greater than or equal to 18.0, was 16",
+                "decision_fingerprint": fingerprint
+            })
+        );
+
+        // A fixed message is used as is, and stands in when there's nothing to explain
+        let (status, body) = run(
+            &base,
+            json!({
+                "rule": ADULT_RULE,
+                "data": {"user": {"age": 16}},
+                "decision_map": {"false": {"status": 401, "message": "Adults only"}}
+            }),
+        )
+        .await;
+        assert_eq!(status, 401);
+        assert_eq!(body["message"], "Adults only");
+    }
+
+    #[tokio::test]
+    async fn test_unmapped_decision_keeps_the_full_response() {
+        let base = start_server().await;
+        let (status, body) = run(
+            &base,
+            json!({"rule": ADULT_RULE, "data": {"user": {"age": 16}}}),
+        )
+        .await;
+
+        assert_eq!(status, 200);
+        assert_eq!(body["result"], false);
+        assert!(body.get("trace").is_some());
+        assert!(body.get("decision").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_undecided_requests_keep_their_error() {
+        let base = start_server().await;
+        let (status, body) = run(
+            &base,
+            json!({
+                "rule": "A **user** is an adult if",
+                "data": {},
+                "decision_map": gateway_map()
+            }),
+        )
+        .await;
+        assert_eq!(status, 400);
+        assert!(body.get("error").is_some());
+        assert!(body.get("decision").is_none());
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/", base))
+            .header("content-type", "application/json")
+            .body(
+                json!({
+                    "rule": ADULT_RULE,
+                    "data": {},
+                    "decision_map": {"false": {"status": 42}}
+                })
+                .to_string(),
+            )
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 422);
+        assert!(response
+            .text()
+            .await
+            .unwrap()
+            .contains("42 is not an HTTP status code"));
+    }
+
+    const TAGGED_RULES: &str = "\
+A **driver** gets a driving licence
+  if the **driver** passes the age test.
+
+Tags: kyc.
+age.check. A **driver** passes the age test
+if the __age__ of the **driver** is greater than or equal to 17.
+";
+
+    #[tokio::test]
+    async fn test_schema_version_1_echoes_the_rule_lines() {
+        let base = start_server().await;
+        let data = json!({"driver": {"age": 18}});
+        let (status, body) = run(&base, json!({"rule": TAGGED_RULES, "data": data})).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["schema_version"], 1);
+        assert_eq!(
+            body["rule"],
+            json!(TAGGED_RULES.lines().collect::<Vec<_>>())
+        );
+        assert!(body.get("rules").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_schema_version_2_lists_the_parsed_rules() {
+        let base = start_server().await;
+        let data = json!({"driver": {"age": 18}});
+        let (status, body) = run(
+            &base,
+            json!({"rule": TAGGED_RULES, "data": data, "schema_version": 2}),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert!(body.get("rule").is_none());
+        assert_eq!(
+            body["rules"],
+            json!([
+                {"outcome": "driving licence", "start_line": 1, "end_line": 2},
+                {"label": "age.check", "outcome": "age test", "start_line": 4, "end_line": 6}
+            ])
+        );
+
+        let (_, body) = run(
+            &base,
+            json!({
+                "rule": TAGGED_RULES,
+                "data": data,
+                "schema_version": 2,
+                "include_formatted_rules": true
+            }),
+        )
+        .await;
+        assert_eq!(
+            body["rules"][1]["formatted"],
+            "Tags: kyc.\nage.check. A **driver** passes the age test\n  \
+             if the __age__ of the **driver** is greater than or equal to 17."
+        );
+
+        // A policy that doesn't parse has no rules to list
+        let (status, body) = run(
+            &base,
+            json!({"rule": "A **driver** is", "data": data, "schema_version": 2}),
+        )
+        .await;
+        assert_eq!(status, 400);
+        assert!(body.get("rule").is_none() && body.get("rules").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_responses_carry_the_engine_version() {
+        let base = start_server().await;
+        let version = reqwest::get(format!("{}/version", base))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let version: Value = serde_json::from_str(&version).unwrap();
+        assert_eq!(version["engine_version"], ENGINE_VERSION);
+        assert_eq!(version["language_version"], LANGUAGE_VERSION);
+
+        // Decisions and parse errors alike say which engine answered, as `/version` does
+        for rule in [ADULT_RULE, "A **user** is"] {
+            let (_, body) = run(&base, json!({"rule": rule, "data": {"user": {"age": 30}}})).await;
+            for field in ["engine_version", "git_sha", "language_version"] {
+                assert_eq!(body.get(field), version.get(field), "{} of {}", field, rule);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_trace_streams_the_json_trace_a_rule_at_a_time() {
+        let base = start_server().await;
+        let rule = format!(
+            "{}\n\nA **user** gets a pass if the **user** is an adult.",
+            ADULT_RULE
+        );
+        let request = json!({"rule": rule, "data": {"user": {"age": 16}}});
+        let (_, whole) = run(&base, request.clone()).await;
+
+        let mut streamed = request;
+        streamed["trace_format"] = json!("ndjson");
+        let response = reqwest::Client::new()
+            .post(format!("{}/", base))
+            .header("content-type", "application/json")
+            .body(streamed.to_string())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+        let text = response.text().await.unwrap();
+        let mut lines: Vec<Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        // The head is the JSON response without its trace
+        let mut head = lines.remove(0);
+        assert_eq!(head["line"], "result");
+        head.as_object_mut().unwrap().remove("line");
+        let mut rest = whole.clone();
+        rest.as_object_mut().unwrap().remove("trace");
+        assert_eq!(head, rest);
+
+        let summary = lines.pop().unwrap();
+        assert_eq!(summary["line"], "summary");
+        assert_eq!(summary["rules"], 2);
+        assert_eq!(lines[1]["parents"], json!([0]));
+        let execution: Vec<Value> = lines
+            .into_iter()
+            .map(|mut line| {
+                let fields = line.as_object_mut().unwrap();
+                for linkage in ["line", "id", "parents"] {
+                    fields.remove(linkage);
+                }
+                line
+            })
+            .collect();
+        assert_eq!(json!({"execution": execution}), whole["trace"]);
+    }
+
+    #[tokio::test]
+    async fn test_parse_errors_are_located() {
+        let base = start_server().await;
+        let rule = "A **user** gets access\n  if the __age__ of the **user** is greater than 18\n  and the __role__ of the **user** is equl to \"admin\".";
+        let (status, body) = run(&base, json!({"rule": rule, "data": {"user": {"age": 30}}})).await;
+
+        assert_eq!(status, 400, "{}", body);
+        assert_eq!(
+            body["parse_error"],
+            json!({
+                "line": 3,
+                "column": 36,
+                "snippet": "  and the __role__ of the **user** is equl to \"admin\".",
+                "expected": ["predicate", "arithmetic operator"],
+                "found": "is"
+            })
+        );
+        assert!(body["error"].as_str().unwrap().starts_with("Parse error: "));
+        // The version 1 trace points at the same line
+        assert_eq!(
+            body["trace"]["execution"][0]["conditions"][0]["property"]["value"]["failed_at_line"],
+            3
+        );
+    }
+}
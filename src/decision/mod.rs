@@ -0,0 +1,114 @@
+//! Decisions answered the way a gateway's auth subrequest expects.
+//!
+//! `"decision_map": {"true": {"status": 200}, "false": {"status": 403, "message_from":
+//! "explanation"}}` (on `POST /`, `/policies/{id}/evaluate` and `/evaluate-change`) answers a
+//! decision the way a gateway's auth subrequest expects (nginx `auth_request`, Envoy `ext_authz`):
+//! the mapped status and a body of `decision` (`allow` or `deny`), `message` and
+//! `decision_fingerprint`. An unmapped side defaults to 200 or 403. `message` is the fixed text, or
+//! with `"message_from": "explanation"` the `RuleSetTrace::explanation` of the failed checks on the
+//! failing path, falling back to the fixed text. Requests that can't be decided keep their 4xx
+//! error response, so the gateway fails closed. Without a map the response is unchanged.
+
+mod lib;
+
+use axum::{
+    extract::Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+/// How to answer a decision as a gateway's auth subrequest expects, nginx `auth_request` or
+/// Envoy `ext_authz` style: a status per result and a short body instead of the full
+/// response. Sent as `decision_map`, e.g.
+/// `{"true": {"status": 200}, "false": {"status": 403, "message_from": "explanation"}}`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DecisionMap {
+    /// How to answer a passed decision, 200 unless set
+    #[serde(rename = "true", default = "DecisionAnswer::allow")]
+    pub allow: DecisionAnswer,
+    /// How to answer a failed decision, 403 unless set
+    #[serde(rename = "false", default = "DecisionAnswer::deny")]
+    pub deny: DecisionAnswer,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DecisionAnswer {
+    #[serde(deserialize_with = "status_code")]
+    pub status: StatusCode,
+    /// A fixed message for the body
+    pub message: Option<String>,
+    /// Where to take the message from instead, falling back to `message` when that has none
+    pub message_from: Option<MessageSource>,
+}
+
+impl DecisionAnswer {
+    fn allow() -> Self {
+        DecisionAnswer {
+            status: StatusCode::OK,
+            message: None,
+            message_from: None,
+        }
+    }
+
+    fn deny() -> Self {
+        DecisionAnswer {
+            status: StatusCode::FORBIDDEN,
+            ..DecisionAnswer::allow()
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageSource {
+    /// The failed checks the decision traces back to, see `RuleSetTrace::explanation`
+    Explanation,
+}
+
+fn status_code<'de, D: Deserializer<'de>>(deserializer: D) -> Result<StatusCode, D::Error> {
+    let code = u16::deserialize(deserializer)?;
+    StatusCode::from_u16(code)
+        .map_err(|_| de::Error::custom(format!("{} is not an HTTP status code", code)))
+}
+
+/// The body of a mapped decision
+#[derive(Serialize, Debug, PartialEq)]
+pub struct DecisionBody {
+    /// `allow` or `deny`
+    pub decision: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decision_fingerprint: Option<String>,
+}
+
+impl DecisionMap {
+    /// Answers `result` with its mapped status. `explanation` is only worked out when the
+    /// answer takes its message from it
+    pub fn respond(
+        &self,
+        result: bool,
+        explanation: impl FnOnce() -> Option<String>,
+        decision_fingerprint: Option<String>,
+    ) -> Response {
+        let (answer, decision) = if result {
+            (&self.allow, "allow")
+        } else {
+            (&self.deny, "deny")
+        };
+        let message = match answer.message_from {
+            Some(MessageSource::Explanation) => explanation(),
+            None => None,
+        }
+        .or_else(|| answer.message.clone());
+        let body = DecisionBody {
+            decision,
+            message,
+            decision_fingerprint,
+        };
+        (answer.status, Json(body)).into_response()
+    }
+}
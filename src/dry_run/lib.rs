@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::{JobSettings, ServerConfig, TraceSettings};
+    use crate::dry_run::DRY_RUN_HEADER;
+    use crate::jobs::Jobs;
+    use crate::rate_limit::RateLimiter;
+    use crate::shadow::{AuditSink, ShadowRecord};
+    use crate::tenants::{routes, tenant_layer, Tenants, TENANT_HEADER};
+    use crate::traces::Traces;
+    use crate::{handle_run, AppState};
+    use axum::{middleware, routing::post, Router};
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const SENIOR: &str = "A **Person** gets senior_discount\n  if the __age__ of the **Person** is greater than or equal to 65\n  and the **Person** passes the membership check.";
+
+    #[derive(Debug, Default)]
+    struct CapturingSink(Mutex<Vec<ShadowRecord>>);
+
+    impl AuditSink for CapturingSink {
+        fn record(&self, record: &ShadowRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    async fn start_server(tenants: Arc<Tenants>) -> String {
+        let state = AppState {
+            tenants: tenants.clone(),
+            rate_limiter: Arc::new(RateLimiter::new(None)),
+            config: Arc::new(ServerConfig::default()),
+            jobs: Arc::new(Jobs::from_settings(&JobSettings::default())),
+            traces: Arc::new(Traces::from_settings(&TraceSettings::default())),
+        };
+        let app = Router::new()
+            .route("/", post(handle_run))
+            .merge(routes())
+            .route_layer(middleware::from_fn_with_state(tenants, tenant_layer))
+            .with_state(state);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn send(url: String, body: Value, dry_run_header: bool) -> (u16, Value) {
+        let mut request = reqwest::Client::new()
+            .post(url)
+            .header(TENANT_HEADER, "default")
+            .header("content-type", "application/json");
+        if dry_run_header {
+            request = request.header(DRY_RUN_HEADER, "true");
+        }
+        let response = request.body(body.to_string()).send().await.unwrap();
+        let status = response.status().as_u16();
+        let text = response.text().await.unwrap();
+        (status, serde_json::from_str(&text).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_explains_the_request_without_evaluating_it() {
+        let base = start_server(Arc::new(Tenants::single())).await;
+        let request = json!({
+            "rule": SENIOR,
+            "data": {"person": {"Age": 70}},
+            "lenient": true,
+        });
+
+        let (status, body) = send(format!("{}/", base), request.clone(), true).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["dry_run"], true);
+        assert!(body.get("result").is_none());
+        assert!(body.get("trace").is_none());
+        assert_eq!(body["policy"]["cache"], "disabled");
+        assert_eq!(body["policy"]["hash"].as_str().unwrap().len(), 64);
+        assert_eq!(body["options"]["lenient"], true);
+        assert_eq!(body["options"]["reject_disjoint_data"], true);
+        assert_eq!(body["options"]["schema_version"], 1);
+        assert_eq!(
+            body["selectors"],
+            json!([{"selector": "Person", "found": true}])
+        );
+        assert_eq!(body["unresolved_references"][0]["rule"], "senior_discount");
+        assert_eq!(
+            body["unresolved_references"][0]["rule_name"],
+            "passes the membership check"
+        );
+        let sample = &body["plan"]["order"][0]["conditions"][0]["paths"][0]["sample"];
+        assert_eq!(sample["path"], "$.person.Age");
+        assert_eq!(sample["found"], true);
+
+        // The body flag asks for the same, and the hash is of the text alone
+        let mut flagged = request.clone();
+        flagged["dry_run"] = json!(true);
+        let (_, flagged) = send(format!("{}/", base), flagged, false).await;
+        assert_eq!(flagged["policy"], body["policy"]);
+
+        // Without either, the request is evaluated
+        let (status, body) = send(format!("{}/", base), request, false).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["result"], true);
+        assert!(body.get("dry_run").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_answers_with_the_evaluations_error_status() {
+        let base = start_server(Arc::new(Tenants::single())).await;
+
+        let (status, body) = send(
+            format!("{}/", base),
+            json!({"rule": "A **Person** gets", "data": {}}),
+            true,
+        )
+        .await;
+        assert_eq!(status, 400, "{}", body);
+        assert!(!body["diagnostics"].as_array().unwrap().is_empty());
+        assert!(body.get("plan").is_none());
+
+        // Data for another policy is reported with the plan that shows why
+        let (status, body) = send(
+            format!("{}/", base),
+            json!({"rule": SENIOR, "data": {"vehicle": {"age": 3}}}),
+            true,
+        )
+        .await;
+        assert_eq!(status, 422, "{}", body);
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .contains("No selectors matched"));
+        assert_eq!(body["selectors"][0]["found"], false);
+        assert_eq!(
+            body["plan"]["order"][0]["conditions"][0]["paths"][0]["sample"]["found"],
+            false
+        );
+
+        let (status, body) = send(
+            format!("{}/", base),
+            json!({"rule": SENIOR, "data": {"person": {}}, "data_root": "$.envelope"}),
+            true,
+        )
+        .await;
+        assert_eq!(status, 400, "{}", body);
+        assert_eq!(body["invalid_data_root"]["root"], "$.envelope");
+        assert!(body.get("selectors").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_of_a_stored_policy_records_nothing() {
+        let sink = Arc::new(CapturingSink::default());
+        let tenants = Arc::new(Tenants::single().with_audit_sink(sink.clone()));
+        let base = start_server(tenants.clone()).await;
+        for _ in 0..2 {
+            let (status, body) = send(
+                format!("{}/policies", base),
+                json!({"id": "senior", "rule": SENIOR}),
+                false,
+            )
+            .await;
+            assert_eq!(status, 201, "{}", body);
+        }
+
+        let request = json!({
+            "data": {"Person": {"age": 70}},
+            "shadow": {"id": "senior", "version": 1},
+        });
+        let (status, body) = send(
+            format!("{}/policies/senior/evaluate", base),
+            request.clone(),
+            true,
+        )
+        .await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["policy"]["id"], "senior");
+        assert_eq!(body["policy"]["version"], 2);
+        assert_eq!(body["policy"]["cache"], "stored");
+        assert!(body.get("result").is_none());
+
+        let mut flagged = request;
+        flagged["dry_run"] = json!(true);
+        let (status, body) =
+            send(format!("{}/policies/senior/evaluate", base), flagged, false).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["dry_run"], true);
+
+        // Neither ran the shadow, so nothing reached the audit sink or its counters
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(sink.0.lock().unwrap().is_empty());
+        let metrics = tenants.shadows().metrics();
+        assert!(
+            metrics.contains("engine_shadow_evaluations_total 0\n"),
+            "{}",
+            metrics
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! Requests explained rather than evaluated.
+//!
+//! A request to `POST /` or `POST /policies/{id}/evaluate` with an `x-engine-dry-run: true` header,
+//! or `"dry_run": true` in the body, is explained rather than evaluated. The answer has `"dry_run":
+//! true`, and its `policy` holds the stored `id` and `version` when there is one. `policy.hash` is
+//! the `PolicyCache::key` of the text, and `policy.cache` predicts `hit`, `miss`, `disabled` or,
+//! for a stored policy, `stored`. `options` lists the effective options: the replay options plus
+//! `trace_verbosity`, `max_condition_evaluations`, `unknown_flag_default` and `schema_version`.
+//! `selectors` says whether the payload, under any `data_root`, holds each root selector.
+//! `unresolved_references` lists references that name no rule, and `plan` is the `compile_plan`
+//! with its paths resolved against the payload. No condition is evaluated: no compiled artifact is
+//! written, flags aren't asked for and a `shadow` isn't run, so the audit sink and shadow counters
+//! don't see it; only `engine_requests_total` counts the request. Problems the evaluation would
+//! stop at before its first condition, such as a parse error, a complexity limit, a bad
+//! `data_root`, disjoint or invalid data, or a missing entry, come back with the status and fields
+//! the evaluation would give. `trace_format` and `decision_map` don't apply.
+
+mod lib;
+
+use crate::runner::compiled::PolicyCache;
+use crate::runner::error::{DataRootError, OutcomeCandidate, RuleError};
+use crate::runner::evaluator::{legacy_flat_data, resolve_entry_rules};
+use crate::runner::model::RuleSet;
+use crate::runner::options::{EvaluationOptions, TraceVerbosity};
+use crate::runner::parser::{parse_error_diagnostics, parse_rule_set_with_limits, ParseDiagnostic};
+use crate::runner::plan::{compile_plan, EvaluationPlan, UnresolvedReference};
+use crate::runner::replay::ReplayOptions;
+use crate::runner::schema::{disjoint_data, holds_selector, root_selectors, DataViolation};
+use crate::runner::stats::LimitViolation;
+use crate::runner::utils::resolve_data_root;
+use crate::shadow::PolicyVersion;
+use crate::{complexity_limits, error_status, parse_limits, policy_cache, RuleDataPackage};
+use axum::{
+    extract::Json,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+/// Asks `POST /` or `POST /policies/{id}/evaluate` to explain how it would treat the request
+/// instead of evaluating it, like `"dry_run": true` in the body
+pub const DRY_RUN_HEADER: &str = "x-engine-dry-run";
+
+/// Whether a request with these headers and body flag asks for a dry run
+pub fn requested(headers: &HeaderMap, flag: bool) -> bool {
+    flag || headers
+        .get(DRY_RUN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+}
+
+/// How a request would be evaluated, worked out up to the first condition: the policy and
+/// whether its compiled artifact would be loaded, the options in effect, the selectors the
+/// payload holds and the plan with its lookups resolved against the payload. Problems found
+/// on the way are reported as the evaluation would report them
+#[derive(Serialize, Debug)]
+pub struct DryRunReport {
+    /// Always true, telling the report apart from an evaluation response
+    dry_run: bool,
+    policy: PolicyReport,
+    options: EffectiveOptions,
+    /// The error the evaluation would stop with before evaluating a condition
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    diagnostics: Vec<ParseDiagnostic>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    candidates: Option<Vec<OutcomeCandidate>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit_exceeded: Option<LimitViolation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    invalid_data_root: Option<DataRootError>,
+    /// Why the data would be rejected when `validate_data` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    violations: Option<Vec<DataViolation>>,
+    /// The selectors the rules read from and whether the payload holds each, see
+    /// `schema::root_selectors`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    selectors: Vec<SelectorMatch>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unresolved_references: Vec<UnresolvedReference>,
+    /// With every path looked up in the payload, see `EvaluationPlan::resolve_sample`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plan: Option<EvaluationPlan>,
+}
+
+#[derive(Serialize, Debug)]
+struct PolicyReport {
+    /// The stored policy the request names
+    #[serde(flatten)]
+    stored: Option<PolicyVersion>,
+    /// `PolicyCache::key` of the rule text under the configured parse limits
+    hash: String,
+    cache: CachePrediction,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum CachePrediction {
+    /// The compiled artifact of the text would be loaded
+    Hit,
+    /// The text would be parsed and its artifact stored
+    Miss,
+    /// No `policy_cache_dir` is configured, so the text would be parsed
+    Disabled,
+    /// A stored policy, parsed once when it was stored
+    Stored,
+}
+
+/// The options the evaluation would run with, request flags and server limits together
+#[derive(Serialize, Debug)]
+struct EffectiveOptions {
+    #[serde(flatten)]
+    decision: ReplayOptions,
+    trace_verbosity: TraceVerbosity,
+    max_condition_evaluations: usize,
+    unknown_flag_default: bool,
+    schema_version: u32,
+}
+
+#[derive(Serialize, Debug)]
+struct SelectorMatch {
+    selector: String,
+    found: bool,
+}
+
+impl DryRunReport {
+    /// The report as a JSON response, whatever `trace_format` or `decision_map` the request
+    /// set
+    pub fn into_http(self, status: StatusCode) -> Response {
+        (status, Json(self)).into_response()
+    }
+
+    /// Records the first error the evaluation would stop with
+    fn fail(&mut self, status: &mut StatusCode, error: RuleError) {
+        if self.error.is_none() {
+            *status = error_status(&error);
+            self.error = Some(error.to_string());
+            self.candidates = error.global_rule_candidates().map(<[_]>::to_vec);
+        }
+    }
+}
+
+/// Explains how `package` would be evaluated with `options` without evaluating it, parsing
+/// the rule unless `stored` names the stored policy it was parsed from. The status is the one
+/// the evaluation would answer with for the problems found before the first condition.
+///
+/// Nothing is recorded: compiled artifacts aren't written, flags aren't asked for and no
+/// shadow evaluation runs
+pub fn explain(
+    package: &RuleDataPackage,
+    stored: Option<(PolicyVersion, &RuleSet)>,
+    options: &EvaluationOptions,
+) -> (StatusCode, DryRunReport) {
+    let limits = parse_limits();
+    let options = package.evaluation_options(options);
+    let cache = match (&stored, policy_cache()) {
+        (Some(_), _) => CachePrediction::Stored,
+        (None, Some(cache)) if cache.would_hit(&package.rule, limits) => CachePrediction::Hit,
+        (None, Some(_)) => CachePrediction::Miss,
+        (None, None) => CachePrediction::Disabled,
+    };
+    let (stored, stored_rule_set) = stored.unzip();
+    let mut report = DryRunReport {
+        dry_run: true,
+        policy: PolicyReport {
+            stored,
+            hash: PolicyCache::key(&package.rule, limits),
+            cache,
+        },
+        options: EffectiveOptions {
+            decision: package.replay_options(),
+            trace_verbosity: options.trace_verbosity,
+            max_condition_evaluations: options.max_condition_evaluations,
+            unknown_flag_default: options.unknown_flag_default,
+            schema_version: package.schema_version(),
+        },
+        error: None,
+        diagnostics: Vec::new(),
+        candidates: None,
+        limit_exceeded: None,
+        invalid_data_root: None,
+        violations: None,
+        selectors: Vec::new(),
+        unresolved_references: Vec::new(),
+        plan: None,
+    };
+    let mut status = StatusCode::OK;
+
+    let parsed;
+    let rule_set = match stored_rule_set {
+        Some(rule_set) => rule_set,
+        None => match parse_rule_set_with_limits(&package.rule, limits) {
+            Ok(rule_set) => {
+                parsed = rule_set;
+                &parsed
+            }
+            Err(error) => {
+                report.diagnostics = parse_error_diagnostics(&error, &package.rule, limits);
+                report.fail(&mut status, error);
+                return (status, report);
+            }
+        },
+    };
+
+    if let Err(violation) = complexity_limits().check(&rule_set.stats()) {
+        report.limit_exceeded = Some(violation.clone());
+        report.fail(&mut status, RuleError::ComplexityLimitExceeded(violation));
+    }
+
+    let data = match &options.data_root {
+        Some(root) => match resolve_data_root(&package.data, root) {
+            Ok(data) => Some(data),
+            Err(error) => {
+                report.invalid_data_root = Some(error.clone());
+                report.fail(&mut status, RuleError::InvalidDataRoot(error));
+                None
+            }
+        },
+        None => Some(&package.data),
+    };
+    if options.strict_references {
+        if let Err(error) = rule_set.validate_references() {
+            report.fail(&mut status, error);
+        }
+    }
+    // Read as the evaluation would read it
+    let wrapped = data.and_then(|data| legacy_flat_data(rule_set, data, &options));
+    let data = wrapped.as_ref().map(|(_, wrapped)| wrapped).or(data);
+    let mut plan = compile_plan(rule_set);
+    if let Some(data) = data {
+        report.selectors = root_selectors(rule_set)
+            .into_iter()
+            .map(|selector| SelectorMatch {
+                found: holds_selector(rule_set, data, &selector),
+                selector,
+            })
+            .collect();
+        if options.reject_disjoint_data {
+            if let Some(disjoint) = disjoint_data(rule_set, data) {
+                report.fail(&mut status, RuleError::DisjointData(disjoint));
+            }
+        }
+        if options.validate_input {
+            let violations = rule_set.validate_data(data);
+            if !violations.is_empty() {
+                report.violations = Some(violations.clone());
+                report.fail(&mut status, RuleError::InvalidData { violations });
+            }
+        }
+        plan.resolve_sample(data);
+    }
+    if let Err(error) = resolve_entry_rules(rule_set, &options) {
+        report.fail(&mut status, error);
+    }
+    report.unresolved_references = rule_set.unresolved_references();
+    report.plan = Some(plan);
+    (status, report)
+}
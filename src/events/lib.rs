@@ -0,0 +1,301 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::WebhookSettings;
+    use crate::events::{
+        sign, Event, EventKind, EventSink, WebhookSink, WebhookTarget, DELIVERY_HEADER,
+        EVENT_HEADER, SIGNATURE_HEADER,
+    };
+    use crate::shadow::PolicyVersion;
+    use crate::tenants::{routes, tenant_layer, Tenants, TENANT_HEADER};
+    use axum::{extract::State, http::HeaderMap, http::StatusCode, middleware, routing::post};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const SENIOR_65: &str = "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 65.";
+    const SENIOR_60: &str = "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 60.";
+
+    #[derive(Debug, Default)]
+    struct CapturingSink(Mutex<Vec<Event>>);
+
+    impl EventSink for CapturingSink {
+        fn send(&self, event: &Event) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    impl CapturingSink {
+        /// Waits for `count` events, as shadow events are sent from a background task
+        async fn events(&self, count: usize) -> Vec<Event> {
+            for _ in 0..100 {
+                if self.0.lock().unwrap().len() >= count {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    fn registered(id: &str, version: u32) -> EventKind {
+        EventKind::PolicyRegistered {
+            policy: PolicyVersion {
+                id: id.to_string(),
+                version,
+            },
+        }
+    }
+
+    fn settings(url: Option<String>) -> WebhookSettings {
+        WebhookSettings {
+            url,
+            secret: Some("s3cret".to_string()),
+            retry_delay_ms: 10,
+            ..WebhookSettings::default()
+        }
+    }
+
+    async fn send(method: reqwest::Method, url: String, body: Option<Value>) -> (u16, Value) {
+        let mut request = reqwest::Client::new()
+            .request(method, url)
+            .header(TENANT_HEADER, "default");
+        if let Some(body) = body {
+            request = request
+                .header("content-type", "application/json")
+                .body(body.to_string());
+        }
+        let response = request.send().await.unwrap();
+        let status = response.status().as_u16();
+        let text = response.text().await.unwrap();
+        (status, serde_json::from_str(&text).unwrap_or(Value::Null))
+    }
+
+    #[test]
+    fn test_signature_is_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // Keys longer than a block are hashed first
+        assert_eq!(
+            sign(&"k".repeat(100), b"x"),
+            "sha256=8c1858bff6cf9cb73708b4b54f43bca6e1b8b4c3380760dc009001b265b64c6f"
+        );
+        assert_ne!(sign("s3cret", b"{}"), sign("s3cret", b"{ }"));
+    }
+
+    #[test]
+    fn test_event_schema() {
+        let event = Event::new("team-a", registered("senior", 3));
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "policy_registered");
+        assert_eq!(json["tenant"], "team-a");
+        assert_eq!(json["policy"], json!({"id": "senior", "version": 3}));
+        assert!(json["time"].is_string());
+        assert_eq!(event.kind_name(), "policy_registered");
+
+        let event = Event::new(
+            "team-a",
+            EventKind::PolicyDeleted {
+                id: "senior".to_string(),
+                versions: 3,
+            },
+        );
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "policy_deleted");
+        assert_eq!(json["versions"], 3);
+    }
+
+    #[test]
+    fn test_full_queue_drops_the_oldest_event() {
+        let mut tenants = HashMap::new();
+        tenants.insert(
+            "team-a".to_string(),
+            WebhookTarget {
+                url: "http://team-a.example.com/hook".to_string(),
+                secret: None,
+            },
+        );
+        let settings = WebhookSettings {
+            queue_size: 2,
+            ..WebhookSettings::default()
+        };
+        // Not started, so nothing leaves the queue
+        let sink = WebhookSink::from_settings(&settings, tenants).unwrap();
+
+        for version in 1..=3 {
+            sink.send(&Event::new("team-a", registered("senior", version)));
+        }
+        // No global webhook for other tenants
+        sink.send(&Event::new("team-b", registered("senior", 1)));
+
+        let waiting = sink.waiting();
+        assert_eq!(waiting.iter().map(|d| d.id).collect::<Vec<_>>(), vec![2, 3]);
+        assert!(waiting[1].body.contains("\"version\":3"));
+        assert_eq!(waiting[1].target.url, "http://team-a.example.com/hook");
+        let metrics = sink.metrics();
+        assert!(
+            metrics.contains("engine_webhook_events_dropped_total 1\n"),
+            "{}",
+            metrics
+        );
+
+        assert!(WebhookSink::from_settings(&WebhookSettings::default(), HashMap::new()).is_none());
+    }
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+    /// Answers 500 to the first attempt and 200 after
+    async fn flaky_webhook(
+        State(received): State<Received>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        let mut received = received.lock().unwrap();
+        received.push((headers, body));
+        if received.len() == 1 {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::OK
+        }
+    }
+
+    async fn start_webhook(received: Received) -> String {
+        let app = axum::Router::new()
+            .route("/hook", post(flaky_webhook))
+            .with_state(received);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}/hook", addr)
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_are_retried_with_a_signed_body() {
+        let received = Received::default();
+        let url = start_webhook(received.clone()).await;
+        let sink = WebhookSink::from_settings(&settings(Some(url)), HashMap::new())
+            .unwrap()
+            .start();
+
+        sink.send(&Event::new("default", registered("senior", 1)));
+        for _ in 0..100 {
+            if sink
+                .metrics()
+                .contains("engine_webhook_deliveries_total{result=\"delivered\"} 1\n")
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (first, _) = &received[0];
+        let (headers, body) = &received[1];
+        assert_eq!(headers[DELIVERY_HEADER], first[DELIVERY_HEADER]);
+        assert_eq!(headers[EVENT_HEADER], "policy_registered");
+        assert_eq!(
+            headers[SIGNATURE_HEADER].to_str().unwrap(),
+            sign("s3cret", body.as_bytes())
+        );
+        let event: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(event["policy"]["id"], "senior");
+        let metrics = sink.metrics();
+        assert!(
+            metrics.contains("engine_webhook_deliveries_total{result=\"failed\"} 0\n"),
+            "{}",
+            metrics
+        );
+    }
+
+    #[tokio::test]
+    async fn test_policy_changes_and_shadow_divergence_are_sent() {
+        let sink = Arc::new(CapturingSink::default());
+        let tenants = Arc::new(Tenants::single().with_event_sink(sink.clone()));
+        let app = routes()
+            .route_layer(middleware::from_fn_with_state(
+                tenants.clone(),
+                tenant_layer,
+            ))
+            .with_state(tenants);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        for rule in [SENIOR_60, SENIOR_65] {
+            let url = format!("{}/policies", base);
+            let (status, _) = send(
+                reqwest::Method::POST,
+                url,
+                Some(json!({"id": "senior", "rule": rule})),
+            )
+            .await;
+            assert_eq!(status, 201);
+        }
+        // Version 1 decides 62 differently
+        let (status, _) = send(
+            reqwest::Method::POST,
+            format!("{}/policies/senior/evaluate", base),
+            Some(json!({
+                "data": {"Person": {"age": 62}},
+                "shadow": {"id": "senior", "version": 1},
+            })),
+        )
+        .await;
+        assert_eq!(status, 200);
+        let events = sink.events(3).await;
+
+        let (status, body) = send(
+            reqwest::Method::DELETE,
+            format!("{}/policies/senior", base),
+            None,
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body, json!({"id": "senior", "versions": 2}));
+        let (status, _) = send(
+            reqwest::Method::DELETE,
+            format!("{}/policies/senior", base),
+            None,
+        )
+        .await;
+        assert_eq!(status, 404);
+
+        let kinds: Vec<&EventKind> = events.iter().map(|e| &e.kind).collect();
+        assert_eq!(
+            kinds[..2],
+            [&registered("senior", 1), &registered("senior", 2)]
+        );
+        match &events[2].kind {
+            EventKind::ShadowDiverged {
+                policy,
+                shadow,
+                result,
+                shadow_result,
+                ..
+            } => {
+                assert_eq!((policy.version, shadow.version), (2, 1));
+                assert_eq!((*result, *shadow_result), (false, true));
+            }
+            other => panic!("expected a shadow divergence, got {:?}", other),
+        }
+        let events = sink.events(4).await;
+        assert_eq!(events.len(), 4);
+        assert!(events.iter().all(|e| e.tenant == "default"));
+        assert_eq!(
+            events[3].kind,
+            EventKind::PolicyDeleted {
+                id: "senior".to_string(),
+                versions: 2,
+            }
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! Policy and shadow events sent to webhooks.
+//!
+//! With `[webhook] url` in the server config, or a `webhook` table under a tenant in the tenants
+//! file (`[tenants.webhook]` with `url` and optional `secret`, used instead of the global one),
+//! policy changes and shadow divergences are POSTed as JSON events:
+//!
+//! ```json
+//! {"tenant": "checkout", "time": "2025-01-01T12:00:00Z", "type": "policy_registered", "policy": {"id": "senior", "version": 2}}
+//! {"tenant": "checkout", "time": "...", "type": "policy_deleted", "id": "senior", "versions": 2}
+//! {"tenant": "checkout", "time": "...", "type": "shadow_diverged", "policy": {"id": "senior", "version": 2}, "shadow": {"id": "senior", "version": 1}, "result": false, "shadow_result": true}
+//! ```
+//!
+//! A divergence adds `error` or `shadow_error` when that evaluation failed. Storing a new version
+//! of an id is a `policy_registered` event; the server has no reload, so there is no separate event
+//! for one. Each request carries `x-engine-event` (the type), `x-engine-delivery` (the same number
+//! across retries) and, with a `secret`, `x-engine-signature: sha256=<hex HMAC-SHA256 of the
+//! body>`. Events wait in a queue of `queue_size` and are sent by one background task, retrying
+//! 5xx, 429 and connection errors up to `max_attempts` with a doubling delay, so a slow webhook
+//! never holds up a request; when the queue is full the oldest event is dropped. `GET /metrics`
+//! counts `engine_webhook_deliveries_total{result}` and `engine_webhook_events_dropped_total`.
+//! Tests swap the webhook for any `EventSink` with `Tenants::with_event_sink`.
+
+mod lib;
+
+use crate::config::WebhookSettings;
+use crate::shadow::{PolicyVersion, ShadowRecord};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// `sha256=` and the hex HMAC-SHA256 of the body under the webhook's secret
+pub const SIGNATURE_HEADER: &str = "x-engine-signature";
+/// The event's `type`
+pub const EVENT_HEADER: &str = "x-engine-event";
+/// Counts up per event and stays the same across retries, so a receiver can drop repeats
+pub const DELIVERY_HEADER: &str = "x-engine-delivery";
+
+const HMAC_BLOCK_BYTES: usize = 64;
+
+/// Something that happened to a tenant's stored policies, POSTed as JSON to its webhook
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Event {
+    pub tenant: String,
+    pub time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    /// A policy was stored, either a new id or the next version of an existing one
+    PolicyRegistered { policy: PolicyVersion },
+    /// Every version of a policy was removed
+    PolicyDeleted { id: String, versions: u32 },
+    /// A shadow evaluation decided differently from the policy that answered the request
+    ShadowDiverged {
+        policy: PolicyVersion,
+        shadow: PolicyVersion,
+        result: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        shadow_result: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        shadow_error: Option<String>,
+    },
+}
+
+impl Event {
+    pub fn new(tenant: &str, kind: EventKind) -> Self {
+        Self {
+            tenant: tenant.to_string(),
+            time: Utc::now(),
+            kind,
+        }
+    }
+
+    /// The divergence of a shadow record, `None` when the two evaluations agreed
+    pub fn shadow_diverged(record: &ShadowRecord) -> Option<Self> {
+        record.diverged.then(|| {
+            Self::new(
+                &record.tenant,
+                EventKind::ShadowDiverged {
+                    policy: record.policy.clone(),
+                    shadow: record.shadow.clone(),
+                    result: record.result,
+                    error: record.error.clone(),
+                    shadow_result: record.shadow_result,
+                    shadow_error: record.shadow_error.clone(),
+                },
+            )
+        })
+    }
+
+    pub fn kind_name(&self) -> &'static str {
+        match self.kind {
+            EventKind::PolicyRegistered { .. } => "policy_registered",
+            EventKind::PolicyDeleted { .. } => "policy_deleted",
+            EventKind::ShadowDiverged { .. } => "shadow_diverged",
+        }
+    }
+}
+
+/// Where events go. `send` is called while handling a request, so it must not wait on the
+/// network
+pub trait EventSink: fmt::Debug + Send + Sync {
+    fn send(&self, event: &Event);
+
+    /// Counters in the Prometheus text format
+    fn metrics(&self) -> String {
+        String::new()
+    }
+}
+
+/// A webhook endpoint, global in the server config or per tenant in the tenants file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookTarget {
+    pub url: String,
+    /// Signs each body, see `SIGNATURE_HEADER`. Unsigned when left out
+    pub secret: Option<String>,
+}
+
+/// `sha256=<hex>`, the HMAC-SHA256 of `body` keyed with `secret`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut key = secret.as_bytes().to_vec();
+    if key.len() > HMAC_BLOCK_BYTES {
+        key = Sha256::digest(&key).to_vec();
+    }
+    key.resize(HMAC_BLOCK_BYTES, 0);
+
+    let pad = |byte: u8| -> Vec<u8> { key.iter().map(|k| k ^ byte).collect() };
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(body)
+        .finalize();
+    let outer = Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize();
+    let hex: String = outer.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256={}", hex)
+}
+
+/// An event serialized for one webhook, waiting to be sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    pub id: u64,
+    pub event: &'static str,
+    pub target: WebhookTarget,
+    pub body: String,
+}
+
+/// Deliveries waiting for the sender. When full, the oldest is dropped to make room, so a
+/// slow or unreachable webhook costs at most `capacity` events and never blocks a request
+#[derive(Debug)]
+struct DeliveryQueue {
+    capacity: usize,
+    waiting: Mutex<VecDeque<Delivery>>,
+    ready: Notify,
+    dropped: AtomicU64,
+}
+
+impl DeliveryQueue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            waiting: Mutex::new(VecDeque::new()),
+            ready: Notify::new(),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    fn push(&self, delivery: Delivery) {
+        let mut waiting = self.waiting.lock().unwrap();
+        while waiting.len() >= self.capacity {
+            waiting.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        waiting.push_back(delivery);
+        drop(waiting);
+        self.ready.notify_one();
+    }
+
+    async fn pop(&self) -> Delivery {
+        loop {
+            if let Some(delivery) = self.waiting.lock().unwrap().pop_front() {
+                return delivery;
+            }
+            // A push between the check and here leaves a permit, so this doesn't miss it
+            self.ready.notified().await;
+        }
+    }
+}
+
+/// POSTs each event to the webhook of its tenant, or to the global webhook when the tenant
+/// has none, from a background task started by `start`
+#[derive(Debug)]
+pub struct WebhookSink {
+    global: Option<WebhookTarget>,
+    tenants: HashMap<String, WebhookTarget>,
+    max_attempts: u32,
+    retry_delay: Duration,
+    timeout: Duration,
+    queue: DeliveryQueue,
+    next_id: AtomicU64,
+    delivered: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl WebhookSink {
+    /// `None` when neither the settings nor any tenant name a webhook
+    pub fn from_settings(
+        settings: &WebhookSettings,
+        tenants: HashMap<String, WebhookTarget>,
+    ) -> Option<Self> {
+        let global = settings.url.as_ref().map(|url| WebhookTarget {
+            url: url.clone(),
+            secret: settings.secret.clone(),
+        });
+        if global.is_none() && tenants.is_empty() {
+            return None;
+        }
+        Some(Self {
+            global,
+            tenants,
+            max_attempts: settings.max_attempts.max(1),
+            retry_delay: Duration::from_millis(settings.retry_delay_ms),
+            timeout: Duration::from_millis(settings.timeout_ms),
+            queue: DeliveryQueue::new(settings.queue_size),
+            next_id: AtomicU64::new(1),
+            delivered: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        })
+    }
+
+    /// Spawns the sender. Must be called from within the runtime
+    pub fn start(self) -> Arc<Self> {
+        let sink = Arc::new(self);
+        let sender = sink.clone();
+        tokio::spawn(async move {
+            let client = reqwest::Client::builder()
+                .timeout(sender.timeout)
+                .build()
+                .expect("webhook client builds");
+            loop {
+                let delivery = sender.queue.pop().await;
+                let counter = if sender.deliver(&client, &delivery).await {
+                    &sender.delivered
+                } else {
+                    &sender.failed
+                };
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        sink
+    }
+
+    /// Deliveries not yet taken by the sender, oldest first
+    #[allow(dead_code)]
+    pub fn waiting(&self) -> Vec<Delivery> {
+        self.queue.waiting.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Sends until the webhook answers 2xx, retrying server errors, 429 and connection
+    /// failures with the delay doubling each time. Other answers aren't retried
+    async fn deliver(&self, client: &reqwest::Client, delivery: &Delivery) -> bool {
+        let mut delay = self.retry_delay;
+        for attempt in 1..=self.max_attempts {
+            let mut request = client
+                .post(&delivery.target.url)
+                .header("content-type", "application/json")
+                .header(EVENT_HEADER, delivery.event)
+                .header(DELIVERY_HEADER, delivery.id)
+                .body(delivery.body.clone());
+            if let Some(secret) = &delivery.target.secret {
+                request = request.header(SIGNATURE_HEADER, sign(secret, delivery.body.as_bytes()));
+            }
+
+            let retry = match request.send().await {
+                Ok(response) if response.status().is_success() => return true,
+                Ok(response) => {
+                    let status = response.status();
+                    status.is_server_error() || status.as_u16() == 429
+                }
+                Err(_) => true,
+            };
+            if !retry || attempt == self.max_attempts {
+                break;
+            }
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+        false
+    }
+}
+
+impl EventSink for WebhookSink {
+    fn send(&self, event: &Event) {
+        let Some(target) = self.tenants.get(&event.tenant).or(self.global.as_ref()) else {
+            return;
+        };
+        let Ok(body) = serde_json::to_string(event) else {
+            return;
+        };
+        self.queue.push(Delivery {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            event: event.kind_name(),
+            target: target.clone(),
+            body,
+        });
+    }
+
+    fn metrics(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE engine_webhook_deliveries_total counter");
+        for (result, counter) in [("delivered", &self.delivered), ("failed", &self.failed)] {
+            let _ = writeln!(
+                out,
+                "engine_webhook_deliveries_total{{result=\"{}\"}} {}",
+                result,
+                counter.load(Ordering::Relaxed)
+            );
+        }
+        let _ = writeln!(out, "# TYPE engine_webhook_events_dropped_total counter");
+        let _ = writeln!(
+            out,
+            "engine_webhook_events_dropped_total {}",
+            self.queue.dropped.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
@@ -0,0 +1,187 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::{JobSettings, ServerConfig, TraceSettings};
+    use crate::grpc::proto::policy_engine_client::PolicyEngineClient;
+    use crate::grpc::proto::policy_engine_server::PolicyEngineServer;
+    use crate::grpc::proto::{EvaluateOptions, EvaluateRequest, ParseRequest};
+    use crate::grpc::{struct_to_json, EngineService};
+    use crate::jobs::Jobs;
+    use crate::rate_limit::RateLimiter;
+    use crate::tenants::Tenants;
+    use crate::traces::Traces;
+    use crate::AppState;
+    use prost_types::value::Kind;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tokio_stream::StreamExt;
+    use tonic::transport::Channel;
+
+    fn json_to_proto_value(value: Value) -> prost_types::Value {
+        let kind = match value {
+            Value::Null => Kind::NullValue(0),
+            Value::Bool(b) => Kind::BoolValue(b),
+            Value::Number(n) => Kind::NumberValue(n.as_f64().unwrap()),
+            Value::String(s) => Kind::StringValue(s),
+            Value::Array(items) => Kind::ListValue(prost_types::ListValue {
+                values: items.into_iter().map(json_to_proto_value).collect(),
+            }),
+            Value::Object(_) => Kind::StructValue(json_to_struct(value)),
+        };
+        prost_types::Value { kind: Some(kind) }
+    }
+
+    fn json_to_struct(value: Value) -> prost_types::Struct {
+        match value {
+            Value::Object(map) => prost_types::Struct {
+                fields: map
+                    .into_iter()
+                    .map(|(k, v)| (k, json_to_proto_value(v)))
+                    .collect(),
+            },
+            _ => panic!("Expected a JSON object"),
+        }
+    }
+
+    async fn start_server() -> PolicyEngineClient<Channel> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = AppState {
+            tenants: Arc::new(Tenants::single()),
+            rate_limiter: Arc::new(RateLimiter::new(None)),
+            config: Arc::new(ServerConfig::default()),
+            jobs: Arc::new(Jobs::from_settings(&JobSettings::default())),
+            traces: Arc::new(Traces::from_settings(&TraceSettings::default())),
+        };
+
+        tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(PolicyEngineServer::new(EngineService::new(state)))
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await
+                .unwrap();
+        });
+
+        PolicyEngineClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap()
+    }
+
+    fn senior_request(age: i64, include_trace: bool) -> EvaluateRequest {
+        EvaluateRequest {
+            rule: "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 65.".to_string(),
+            data: Some(json_to_struct(json!({"Person": {"age": age}}))),
+            options: Some(EvaluateOptions { include_trace }),
+        }
+    }
+
+    #[test]
+    fn test_struct_to_json_round_trip() {
+        let original = json!({
+            "Person": {
+                "age": 70,
+                "height": 1.82,
+                "name": "Alice",
+                "active": true,
+                "tags": ["a", "b"],
+                "nickname": null
+            }
+        });
+
+        assert_eq!(struct_to_json(json_to_struct(original.clone())), original);
+    }
+
+    #[tokio::test]
+    async fn test_grpc_evaluate_round_trip() {
+        let mut client = start_server().await;
+
+        let passing = client
+            .evaluate(senior_request(70, true))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(passing.result);
+        assert!(passing.error.is_none());
+
+        let trace: Value = serde_json::from_slice(&passing.trace_json).unwrap();
+        assert_eq!(trace["execution"][0]["outcome"]["value"], "senior_discount");
+
+        let failing = client
+            .evaluate(senior_request(60, false))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!failing.result);
+        assert!(failing.trace_json.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_grpc_evaluate_parse_error() {
+        let mut client = start_server().await;
+
+        let response = client
+            .evaluate(EvaluateRequest {
+                rule: "This is not a rule".to_string(),
+                data: Some(json_to_struct(json!({}))),
+                options: None,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert!(!response.result);
+        assert!(response.error.unwrap().contains("Parse error"));
+    }
+
+    #[tokio::test]
+    async fn test_grpc_parse() {
+        let mut client = start_server().await;
+
+        let valid = client
+            .parse(ParseRequest {
+                rule: r#"
+                A **driver** passes the test
+                  if the **driver** passes the age check.
+
+                A **driver** passes the age check
+                  if the __age__ of the **driver** is at least 17.
+                "#
+                .to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(valid.valid);
+        assert_eq!(valid.outcomes.len(), 2);
+        assert_eq!(valid.global_outcome.as_deref(), Some("test"));
+
+        let invalid = client
+            .parse(ParseRequest {
+                rule: "nonsense".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!invalid.valid);
+        assert!(invalid.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_grpc_evaluate_batch_stream() {
+        let mut client = start_server().await;
+
+        let requests = tokio_stream::iter(vec![
+            senior_request(70, false),
+            senior_request(30, false),
+            senior_request(65, false),
+        ]);
+
+        let mut responses = client.evaluate_batch(requests).await.unwrap().into_inner();
+        let mut results = Vec::new();
+        while let Some(item) = responses.next().await {
+            results.push(item.unwrap().result);
+        }
+
+        assert_eq!(results, vec![true, false, true]);
+    }
+}
@@ -0,0 +1,204 @@
+mod lib;
+
+use crate::runner::options::{LegacyFlatData, ListScalarMode, TraceVerbosity};
+use crate::runner::parser::parse_rules_with_limits;
+use crate::runner::trace::TraceFormat;
+use crate::runner::utils::find_global_rule;
+use crate::traces::TraceDelivery;
+use crate::{
+    evaluate_package, parse_limits, service_options, AppState, EchoData, EvaluationResponse,
+    RuleDataPackage,
+};
+use prost_types::value::Kind;
+use serde_json::{Map, Number, Value};
+use std::pin::Pin;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status, Streaming};
+
+pub mod proto {
+    tonic::include_proto!("engine.v1");
+}
+
+use proto::policy_engine_server::{PolicyEngine, PolicyEngineServer};
+use proto::{EvaluateRequest, EvaluationResult, ParseRequest, ParseResult};
+
+/// gRPC front end sharing the HTTP server's state and evaluation path
+pub struct EngineService {
+    #[allow(dead_code)]
+    state: AppState,
+}
+
+impl EngineService {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+}
+
+/// Runs the gRPC server on a bound `listener` until the shared shutdown channel fires
+pub async fn serve(
+    listener: TcpListener,
+    state: AppState,
+    shutdown_rx: watch::Receiver<bool>,
+) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(PolicyEngineServer::new(EngineService::new(state)))
+        .serve_with_incoming_shutdown(
+            TcpListenerStream::new(listener),
+            crate::shutdown_signal(shutdown_rx),
+        )
+        .await
+}
+
+type EvaluationResultStream = Pin<Box<dyn Stream<Item = Result<EvaluationResult, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl PolicyEngine for EngineService {
+    async fn evaluate(
+        &self,
+        request: Request<EvaluateRequest>,
+    ) -> Result<Response<EvaluationResult>, Status> {
+        Ok(Response::new(evaluate_request(request.into_inner()).await))
+    }
+
+    async fn parse(&self, request: Request<ParseRequest>) -> Result<Response<ParseResult>, Status> {
+        let request = request.into_inner();
+        let result = match parse_rules_with_limits(&request.rule, parse_limits()) {
+            Ok(rule_set) => ParseResult {
+                valid: true,
+                error: None,
+                outcomes: rule_set.rules.iter().map(|r| r.outcome.clone()).collect(),
+                global_outcome: find_global_rule(&rule_set.rules)
+                    .ok()
+                    .map(|r| r.outcome.clone()),
+            },
+            Err(error) => ParseResult {
+                valid: false,
+                error: Some(error.to_string()),
+                outcomes: Vec::new(),
+                global_outcome: None,
+            },
+        };
+        Ok(Response::new(result))
+    }
+
+    type EvaluateBatchStream = EvaluationResultStream;
+
+    async fn evaluate_batch(
+        &self,
+        request: Request<Streaming<EvaluateRequest>>,
+    ) -> Result<Response<Self::EvaluateBatchStream>, Status> {
+        let mut inbound = request.into_inner();
+        let (tx, rx) = mpsc::channel(32);
+
+        tokio::spawn(async move {
+            loop {
+                let item = match inbound.message().await {
+                    Ok(Some(item)) => Ok(evaluate_request(item).await),
+                    Ok(None) => break,
+                    Err(status) => Err(status),
+                };
+                let failed = item.is_err();
+                // Stop when the client has gone away or the inbound stream errored
+                if tx.send(item).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+}
+
+/// Evaluates one request on the blocking pool, as `POST /` does
+async fn evaluate_request(request: EvaluateRequest) -> EvaluationResult {
+    let include_trace = request.options.map(|o| o.include_trace).unwrap_or(false);
+    let package = RuleDataPackage {
+        rule: request.rule,
+        data: request.data.map(struct_to_json).unwrap_or(Value::Null),
+        context: Value::Null,
+        entry: None,
+        outcomes: None,
+        include_outcomes: false,
+        include_formatted_rules: false,
+        lenient: false,
+        validate_data: false,
+        allow_disjoint_data: true,
+        strict_references: false,
+        legacy_flat_data: LegacyFlatData::Off,
+        list_scalar_mode: ListScalarMode::default(),
+        range_objects: false,
+        versions_as_strings: false,
+        case_sensitive_strings: false,
+        as_of: None,
+        data_root: None,
+        echo_data: EchoData::default(),
+        record_replay: false,
+        trace: TraceDelivery::Inline,
+        trace_format: TraceFormat::default(),
+        trace_verbosity: TraceVerbosity::default(),
+        stats: false,
+        schema_version: None,
+        include_tags: Vec::new(),
+        exclude_tags: Vec::new(),
+        decision_map: None,
+        dry_run: false,
+    };
+    let options = service_options();
+    let (_status, response) =
+        tokio::task::spawn_blocking(move || evaluate_package(&package, &options))
+            .await
+            .expect("evaluation task panicked");
+    to_evaluation_result(response, include_trace)
+}
+
+fn to_evaluation_result(response: EvaluationResponse, include_trace: bool) -> EvaluationResult {
+    let trace_json = match (&response.trace, include_trace) {
+        (Some(trace), true) => serde_json::to_vec(trace).unwrap_or_default(),
+        _ => Vec::new(),
+    };
+
+    EvaluationResult {
+        result: response.result,
+        error: response.error,
+        labels: response.labels.unwrap_or_default(),
+        trace_json,
+        engine_version: response.engine.engine_version.to_string(),
+        git_sha: response.engine.git_sha.map(str::to_string),
+        language_version: response.engine.language_version.to_string(),
+    }
+}
+
+/// Converts a protobuf Struct into the JSON shape the evaluator expects
+pub fn struct_to_json(data: prost_types::Struct) -> Value {
+    Value::Object(
+        data.fields
+            .into_iter()
+            .map(|(key, value)| (key, proto_value_to_json(value)))
+            .collect::<Map<String, Value>>(),
+    )
+}
+
+fn proto_value_to_json(value: prost_types::Value) -> Value {
+    match value.kind {
+        None | Some(Kind::NullValue(_)) => Value::Null,
+        Some(Kind::BoolValue(b)) => Value::Bool(b),
+        Some(Kind::NumberValue(n)) => {
+            // Struct numbers are always doubles, keep integral values as JSON integers
+            if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+                Value::Number(Number::from(n as i64))
+            } else {
+                Number::from_f64(n)
+                    .map(Value::Number)
+                    .unwrap_or(Value::Null)
+            }
+        }
+        Some(Kind::StringValue(s)) => Value::String(s),
+        Some(Kind::StructValue(s)) => struct_to_json(s),
+        Some(Kind::ListValue(list)) => {
+            Value::Array(list.values.into_iter().map(proto_value_to_json).collect())
+        }
+    }
+}
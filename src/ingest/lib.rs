@@ -0,0 +1,132 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::ServerConfig;
+    use crate::ingest::{duplicate_keys_layer, read_projected, IngestError};
+    use crate::runner::options::DEFAULT_MAX_SCANNED_KEYS;
+    use crate::runner::parser::parse_rules;
+    use crate::runner::projection::DataProjection;
+    use axum::{body::Body, extract::Json, middleware, routing::post, Router};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    fn projection() -> DataProjection {
+        parse_rules("A **user** is adult if the __age__ of the **user** is at least 18.")
+            .unwrap()
+            .data_projection()
+            .unwrap()
+    }
+
+    async fn read(body: &str, limit: usize) -> Result<Value, IngestError> {
+        read_projected(
+            Body::from(body.to_string()),
+            projection(),
+            DEFAULT_MAX_SCANNED_KEYS,
+            limit,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_only_data_is_projected() {
+        let body = json!({
+            "data": {"user": {"age": 40, "name": "Ann"}, "orders": [1, 2]},
+            "context": {"user": {"name": "Ann"}},
+            "lenient": true
+        });
+        let request = read(&body.to_string(), 1024).await.unwrap();
+        assert_eq!(
+            request,
+            json!({
+                "data": {"user": {"age": 40}},
+                "context": {"user": {"name": "Ann"}},
+                "lenient": true
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_and_oversized_bodies_are_rejected() {
+        assert!(matches!(
+            read(r#"{"data": {"user": "#, 1024).await,
+            Err(IngestError::Syntax(_))
+        ));
+        assert!(matches!(
+            read(r#"{"data": {}} trailing"#, 1024).await,
+            Err(IngestError::Syntax(_))
+        ));
+        assert!(matches!(
+            read("[1, 2]", 1024).await,
+            Err(IngestError::Data(_))
+        ));
+
+        let body = json!({"data": {"padding": "x".repeat(100)}}).to_string();
+        assert!(matches!(
+            read(&body, 50).await,
+            Err(IngestError::TooLarge { limit: 50 })
+        ));
+    }
+    async fn start_server(reject_duplicate_keys: bool) -> String {
+        let config = ServerConfig {
+            reject_duplicate_keys,
+            max_body_bytes: 100,
+            ..ServerConfig::default()
+        };
+        let app = Router::new()
+            .route("/", post(|Json(body): Json<Value>| async { Json(body) }))
+            .route_layer(middleware::from_fn_with_state(
+                Arc::new(config),
+                duplicate_keys_layer,
+            ));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn send(url: &str, content_type: &str, body: &str) -> (u16, String) {
+        let response = reqwest::Client::new()
+            .post(url)
+            .header("content-type", content_type)
+            .body(body.to_string())
+            .send()
+            .await
+            .unwrap();
+        (response.status().as_u16(), response.text().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_keys_are_rejected_only_when_configured() {
+        let body = r#"{"data": {"user": {"age": 20, "age": 30}}, "items": [{"id": 1, "id": 1}]}"#;
+
+        let permissive = start_server(false).await;
+        let (status, text) = send(&permissive, "application/json", body).await;
+        assert_eq!(status, 200);
+        assert_eq!(
+            serde_json::from_str::<Value>(&text).unwrap()["data"]["user"]["age"],
+            30
+        );
+
+        let strict = start_server(true).await;
+        let (status, text) = send(&strict, "application/json; charset=utf-8", body).await;
+        assert_eq!(status, 400, "{}", text);
+        let error: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(error["code"], "duplicate_keys");
+        assert_eq!(error["pointers"], json!(["/data/user/age", "/items/0/id"]));
+        assert!(error["error"]
+            .as_str()
+            .unwrap()
+            .contains("/data/user/age, /items/0/id"));
+
+        // Bodies without repeats, and bodies that aren't JSON, reach the handler
+        let (status, text) = send(&strict, "application/json", r#"{"age": 20}"#).await;
+        assert_eq!((status, text.as_str()), (200, r#"{"age":20}"#));
+        let (status, _) = send(&strict, "text/plain", body).await;
+        assert_eq!(status, 415);
+
+        let (status, text) = send(&strict, "application/json", &"[1]".repeat(50)).await;
+        assert_eq!(status, 413, "{}", text);
+    }
+}
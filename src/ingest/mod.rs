@@ -0,0 +1,239 @@
+//! Reading request bodies: projected ingest and duplicate key checks.
+//!
+//! `POST /policies/{id}/evaluate?ingest=projected` reads the body as it streams in, keeping only
+//! the paths the stored policy reads. When the policy has no projection it falls back to a full
+//! parse; the `x-data-ingest` response header says `projected` or `full`. The echoed data, replay
+//! bundle and decision fingerprint are built from the kept data, and the trace's unscanned-keys
+//! warning can differ. `data_root` and `shadow` read data the projection doesn't keep, so they are
+//! rejected with 400 `projection_unsupported`. `POST /` isn't covered, since its policy comes in
+//! the same body.
+//!
+//! JSON bodies that repeat a key in the same object (`{"age": 20, "age": 30}`) keep the last value,
+//! as serde_json reads them. With `reject_duplicate_keys` set, `ingest::duplicate_keys_layer` reads
+//! every JSON body sent to a tenant route whole and refuses one with a repeat: 400
+//! `duplicate_keys`, with the RFC 6901 `pointers` of the repeated keys (`/data/user/age`,
+//! `/items/0/id`). The check is `runner::utils::check_duplicate_keys`, which library users can call
+//! on their own payloads. A body over `max_body_bytes` gets 413 before it is checked, and
+//! `?ingest=projected` then streams from the checked body.
+
+mod lib;
+
+use crate::config::ServerConfig;
+use crate::runner::projection::{DataProjection, ProjectionSeed};
+use crate::runner::utils::check_duplicate_keys;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Json, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::de::{DeserializeOwned, DeserializeSeed, Deserializer, MapAccess, Visitor};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::io::{self, BufReader};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+use tokio_util::io::{StreamReader, SyncIoBridge};
+
+/// Response header naming how the request's payload was read: `projected` or `full`
+pub const INGEST_HEADER: &str = "x-data-ingest";
+
+/// How to read the payload of a stored-policy evaluation, from `?ingest=`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ingest {
+    /// The whole body is read, then parsed
+    #[default]
+    Full,
+    /// Only the paths the policy reads are kept, as the body streams in, see
+    /// `RuleSet::data_projection`
+    Projected,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct IngestQuery {
+    #[serde(default)]
+    pub ingest: Ingest,
+}
+
+#[derive(Debug)]
+pub enum IngestError {
+    /// The body isn't JSON
+    Syntax(serde_json::Error),
+    /// The body is JSON but not the request
+    Data(serde_json::Error),
+    TooLarge {
+        limit: usize,
+    },
+    /// A request field that reads data the projection doesn't keep
+    Unsupported(&'static str),
+    /// The JSON pointers of keys repeated in the same object, see `check_duplicate_keys`
+    DuplicateKeys(Vec<String>),
+}
+
+impl IngestError {
+    fn from_json(error: serde_json::Error, limit: usize) -> Self {
+        match error.classify() {
+            serde_json::error::Category::Io
+                if error.io_error_kind() == Some(io::ErrorKind::FileTooLarge) =>
+            {
+                IngestError::TooLarge { limit }
+            }
+            serde_json::error::Category::Data => IngestError::Data(error),
+            _ => IngestError::Syntax(error),
+        }
+    }
+}
+
+impl IntoResponse for IngestError {
+    fn into_response(self) -> Response {
+        let mut pointers = None;
+        let (status, code, message) = match self {
+            IngestError::Syntax(error) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_json",
+                format!("Failed to parse the request body as JSON: {}", error),
+            ),
+            IngestError::Data(error) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_request",
+                format!("Failed to deserialize the JSON body: {}", error),
+            ),
+            IngestError::TooLarge { limit } => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "body_too_large",
+                format!("Request body is larger than the limit of {} bytes", limit),
+            ),
+            IngestError::Unsupported(field) => (
+                StatusCode::BAD_REQUEST,
+                "projection_unsupported",
+                format!("'{}' can't be used with ingest=projected", field),
+            ),
+            IngestError::DuplicateKeys(duplicates) => {
+                let message = format!(
+                    "The request body repeats a key in the same object at {}",
+                    duplicates.join(", ")
+                );
+                pointers = Some(duplicates);
+                (StatusCode::BAD_REQUEST, "duplicate_keys", message)
+            }
+        };
+        let mut body = serde_json::json!({ "error": message, "code": code });
+        if let Some(pointers) = pointers {
+            body["pointers"] = serde_json::json!(pointers);
+        }
+        (status, Json(body)).into_response()
+    }
+}
+
+/// With `reject_duplicate_keys` set, reads JSON request bodies whole and refuses those that
+/// repeat a key in the same object, which would otherwise keep the last value, with 400
+/// `duplicate_keys` and the `pointers` of the repeats. Bodies of other types pass through, and
+/// `?ingest=projected` then reads the checked body rather than the connection
+pub async fn duplicate_keys_layer(
+    State(config): State<Arc<ServerConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_json = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().ends_with("json"));
+    if !config.reject_duplicate_keys || !is_json {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let limit = config.max_body_bytes;
+    let bytes = match to_bytes(body, limit).await {
+        Ok(bytes) => bytes,
+        Err(_) => return IngestError::TooLarge { limit }.into_response(),
+    };
+    // Bodies that aren't UTF-8 are left for the extractor to refuse
+    if let Ok(text) = std::str::from_utf8(&bytes) {
+        let duplicates = check_duplicate_keys(text);
+        if !duplicates.is_empty() {
+            return IngestError::DuplicateKeys(duplicates).into_response();
+        }
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+/// Reads a JSON request object as it streams in, building its `data` field as `projection`
+/// keeps it and the other fields whole. No more than `limit` bytes are read
+pub async fn read_projected<T>(
+    body: Body,
+    projection: DataProjection,
+    max_scanned_keys: usize,
+    limit: usize,
+) -> Result<T, IngestError>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let mut read = 0;
+    let chunks = body.into_data_stream().map(move |chunk| {
+        let chunk = chunk.map_err(io::Error::other)?;
+        read += chunk.len();
+        if read > limit {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                "request body too large",
+            ));
+        }
+        Ok(chunk)
+    });
+    let reader = SyncIoBridge::new(StreamReader::new(chunks));
+
+    tokio::task::spawn_blocking(move || {
+        let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+        let seed = RequestSeed {
+            data: projection.seed(max_scanned_keys),
+        };
+        let fields = seed
+            .deserialize(&mut deserializer)
+            .and_then(|fields| deserializer.end().map(|()| fields))
+            .map_err(|error| IngestError::from_json(error, limit))?;
+        serde_json::from_value(Value::Object(fields)).map_err(IngestError::Data)
+    })
+    .await
+    .expect("ingest task panicked")
+}
+
+/// A request object with its `data` read through `data`
+struct RequestSeed<'a> {
+    data: ProjectionSeed<'a>,
+}
+
+impl<'de> DeserializeSeed<'de> for RequestSeed<'_> {
+    type Value = Map<String, Value>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RequestSeed<'_> {
+    type Value = Map<String, Value>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a request object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut fields = Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let value = if key == "data" {
+                map.next_value_seed(self.data)?
+            } else {
+                map.next_value::<Value>()?
+            };
+            fields.insert(key, value);
+        }
+        Ok(fields)
+    }
+}
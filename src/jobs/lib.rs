@@ -0,0 +1,178 @@
+#[cfg(test)]
+mod tests {
+    use crate::jobs::{routes, Jobs, MemoryJobStore};
+    use reqwest::Method;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const ADULT_RULE: &str =
+        "A **user** is an adult if the __age__ of the **user** is greater than or equal to 18.";
+
+    async fn start_server(workers: usize, ttl: Duration) -> String {
+        let jobs = Jobs::new(Arc::new(MemoryJobStore::default()), workers, ttl);
+        let app = routes().with_state(Arc::new(jobs));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn send(method: Method, url: String, body: Option<Value>) -> (u16, Value) {
+        let mut request = reqwest::Client::new().request(method, url);
+        if let Some(body) = body {
+            request = request
+                .header("content-type", "application/json")
+                .body(body.to_string());
+        }
+        let response = request.send().await.unwrap();
+        let status = response.status().as_u16();
+        let text = response.text().await.unwrap();
+        (status, serde_json::from_str(&text).unwrap())
+    }
+
+    async fn submit(base: &str, body: Value) -> String {
+        let (status, body) = send(Method::POST, format!("{}/jobs", base), Some(body)).await;
+        assert_eq!(status, 202, "{}", body);
+        body["job_id"].as_str().unwrap().to_string()
+    }
+
+    /// Polls the job until `done` holds for it
+    async fn poll_until(base: &str, id: &str, done: impl Fn(&Value) -> bool) -> Value {
+        for _ in 0..2000 {
+            let (status, job) = send(Method::GET, format!("{}/jobs/{}", base, id), None).await;
+            assert_eq!(status, 200, "{}", job);
+            if done(&job) {
+                return job;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("job {} never got there", id);
+    }
+
+    /// A batch slow enough to still be running when it is cancelled: every item misses
+    /// every one of many alternatives
+    fn slow_batch() -> Value {
+        let alternatives: Vec<String> = (0..300)
+            .map(|n| format!("the __code__ of the **user** is equal to \"c{}\"", n))
+            .collect();
+        let rule = format!("A **user** is flagged if {}.", alternatives.join(" or "));
+        let data: Vec<Value> = (0..20_000)
+            .map(|_| json!({"user": {"code": "x"}}))
+            .collect();
+        json!({ "rule": rule, "data": data })
+    }
+
+    #[tokio::test]
+    async fn test_job_runs_to_completion() {
+        let base = start_server(2, Duration::from_secs(60)).await;
+        let (status, body) = send(
+            Method::POST,
+            format!("{}/jobs", base),
+            Some(json!({
+                "rule": ADULT_RULE,
+                "data": [{"user": {"age": 30}}, {"user": {"age": 16}}, {"user": {"age": 18}}]
+            })),
+        )
+        .await;
+        assert_eq!(status, 202);
+        let id = body["job_id"].as_str().unwrap();
+
+        let job = poll_until(&base, id, |job| job["status"] == "completed").await;
+        assert_eq!(job["job_id"], id);
+        assert_eq!(job["progress"]["total"], 3);
+        assert_eq!(job["progress"]["processed"], 3);
+        assert_eq!(job["progress"]["passed"], 2);
+        assert_eq!(job["progress"]["failed"], 1);
+        let results: Vec<bool> = job["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["result"].as_bool().unwrap())
+            .collect();
+        assert_eq!(results, vec![true, false, true]);
+
+        // A finished job is removed by DELETE
+        let (status, _) = send(Method::DELETE, format!("{}/jobs/{}", base, id), None).await;
+        assert_eq!(status, 200);
+        let (status, _) = send(Method::GET, format!("{}/jobs/{}", base, id), None).await;
+        assert_eq!(status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_job_cancelled_mid_run_stops_early() {
+        let base = start_server(1, Duration::from_secs(60)).await;
+        let running = submit(&base, slow_batch()).await;
+        // Only one worker, so this one waits its turn
+        let queued = submit(&base, json!({"rule": ADULT_RULE, "data": [{}]})).await;
+
+        poll_until(&base, &running, |job| {
+            job["status"] == "running" && job["progress"]["processed"].as_u64().unwrap() > 0
+        })
+        .await;
+        let (status, job) = send(Method::DELETE, format!("{}/jobs/{}", base, running), None).await;
+        assert_eq!(status, 200);
+        assert_eq!(job["status"], "cancelled");
+
+        let job = poll_until(&base, &running, |job| job["progress"]["cancelled"] == true).await;
+        assert_eq!(job["status"], "cancelled");
+        assert!(job["progress"]["processed"].as_u64().unwrap() < 20_000);
+        assert!(job.get("results").is_none());
+
+        // The worker it freed picks up the queued job
+        let job = poll_until(&base, &queued, |job| job["status"] == "completed").await;
+        assert_eq!(job["progress"]["processed"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_queued_job_cancelled_never_runs() {
+        let base = start_server(1, Duration::from_secs(60)).await;
+        let running = submit(&base, slow_batch()).await;
+        let queued = submit(&base, json!({"rule": ADULT_RULE, "data": [{}]})).await;
+
+        let (status, job) = send(Method::DELETE, format!("{}/jobs/{}", base, queued), None).await;
+        assert_eq!(status, 200);
+        assert_eq!(job["status"], "cancelled");
+        assert_eq!(job["progress"]["processed"], 0);
+
+        send(Method::DELETE, format!("{}/jobs/{}", base, running), None).await;
+    }
+
+    #[tokio::test]
+    async fn test_unknown_job_is_not_found() {
+        let base = start_server(1, Duration::from_secs(60)).await;
+        for method in [Method::GET, Method::DELETE] {
+            let (status, body) = send(method, format!("{}/jobs/nope", base), None).await;
+            assert_eq!(status, 404);
+            assert_eq!(body["code"], "job_not_found");
+            assert_eq!(body["error"], "No job with id 'nope'");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_finished_job_expires_after_its_ttl() {
+        let base = start_server(1, Duration::from_millis(200)).await;
+        let id = submit(&base, json!({"rule": ADULT_RULE, "data": [{}]})).await;
+        poll_until(&base, &id, |job| job["status"] == "completed").await;
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let (status, _) = send(Method::GET, format!("{}/jobs/{}", base, id), None).await;
+        assert_eq!(status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_batch_is_never_queued() {
+        let base = start_server(1, Duration::from_secs(60)).await;
+        let (status, body) = send(
+            Method::POST,
+            format!("{}/jobs", base),
+            Some(json!({"rule": "A **user** is an adult if", "data": [{}]})),
+        )
+        .await;
+        assert_eq!(status, 400);
+        assert!(body.get("job_id").is_none());
+    }
+}
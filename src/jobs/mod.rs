@@ -0,0 +1,356 @@
+//! Batches run in the background: `POST /jobs`, `GET /jobs/{id}` and `DELETE /jobs/{id}`.
+//!
+//! `POST /jobs` takes a `/batch/stream` body and answers 202 with `{"job_id": ...}` (and a
+//! `Location`) as soon as the batch is accepted; the batch then waits `queued` for one of
+//! `JOB_WORKERS` workers. `GET /jobs/{id}` shows `status` (`queued`, `running`, `completed`,
+//! `failed` or `cancelled`), `progress` counted like the batch summary and, once completed, every
+//! item's `results`. `DELETE /jobs/{id}` cancels a queued or running job, which stops at the next
+//! chunk, and removes a finished one. Jobs are only visible to the tenant that submitted them, and
+//! are dropped `JOB_TTL_SECS` after they finish. They live in a `jobs::JobStore`, `MemoryJobStore`
+//! unless `Jobs::new` is given another, so they are lost on restart.
+
+mod lib;
+
+use crate::batch::{
+    self, run_batch, AcceptedBatch, BatchEvent, BatchItemResult, BatchRequest, BatchSummary,
+};
+use crate::config::JobSettings;
+use crate::tenants::Tenant;
+use axum::{
+    extract::{FromRef, Json, Path, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Router,
+};
+use dashmap::DashMap;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{mpsc, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+/// Where a job is in its life
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// Waiting for a worker
+    Queued,
+    Running,
+    Completed,
+    /// The worker stopped without finishing, see `Job::error`
+    Failed,
+    /// Stopped by `DELETE /jobs/{id}`
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+/// A batch run in the background, as `GET /jobs/{id}` shows it
+#[derive(Serialize, Debug, Clone)]
+pub struct Job {
+    pub job_id: String,
+    pub status: JobStatus,
+    /// Items evaluated so far, counted as the batch summary counts them
+    pub progress: BatchSummary,
+    /// Every item's result, once completed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub results: Option<Vec<BatchItemResult>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Tenant that submitted the job; no other tenant sees it
+    #[serde(skip)]
+    pub tenant: Option<String>,
+    /// When the job finished, which its time to live counts from
+    #[serde(skip)]
+    pub finished_at: Option<Instant>,
+}
+
+impl Job {
+    fn finish(&mut self, status: JobStatus) {
+        self.status = status;
+        self.finished_at = Some(Instant::now());
+    }
+}
+
+/// Where jobs are kept between polls. Jobs are written from their worker as it progresses
+pub trait JobStore: fmt::Debug + Send + Sync {
+    fn insert(&self, job: Job);
+    fn get(&self, id: &str) -> Option<Job>;
+    /// Applies `change` to the job, if it is still stored
+    fn update(&self, id: &str, change: &mut dyn FnMut(&mut Job));
+    fn remove(&self, id: &str) -> Option<Job>;
+    /// Drops the jobs that finished before `cutoff`
+    fn expire(&self, cutoff: Instant);
+}
+
+/// Keeps jobs in memory, so they are lost on restart
+#[derive(Debug, Default)]
+pub struct MemoryJobStore {
+    jobs: DashMap<String, Job>,
+}
+
+impl JobStore for MemoryJobStore {
+    fn insert(&self, job: Job) {
+        self.jobs.insert(job.job_id.clone(), job);
+    }
+
+    fn get(&self, id: &str) -> Option<Job> {
+        self.jobs.get(id).map(|job| job.clone())
+    }
+
+    fn update(&self, id: &str, change: &mut dyn FnMut(&mut Job)) {
+        if let Some(mut job) = self.jobs.get_mut(id) {
+            change(&mut job);
+        }
+    }
+
+    fn remove(&self, id: &str) -> Option<Job> {
+        self.jobs.remove(id).map(|(_, job)| job)
+    }
+
+    fn expire(&self, cutoff: Instant) {
+        self.jobs
+            .retain(|_, job| job.finished_at.is_none_or(|finished| finished >= cutoff));
+    }
+}
+
+/// The job store, the workers jobs wait for and the tokens that cancel running jobs
+#[derive(Debug)]
+pub struct Jobs {
+    store: Arc<dyn JobStore>,
+    workers: Arc<Semaphore>,
+    tokens: DashMap<String, CancellationToken>,
+    ttl: Duration,
+}
+
+impl Jobs {
+    pub fn new(store: Arc<dyn JobStore>, workers: usize, ttl: Duration) -> Self {
+        Self {
+            store,
+            workers: Arc::new(Semaphore::new(workers)),
+            tokens: DashMap::new(),
+            ttl,
+        }
+    }
+
+    /// Keeps jobs in memory, with the workers and time to live of `jobs` in the server config
+    pub fn from_settings(settings: &JobSettings) -> Self {
+        Self::new(
+            Arc::new(MemoryJobStore::default()),
+            settings.workers,
+            Duration::from_secs(settings.ttl_secs),
+        )
+    }
+
+    /// Queues `batch` and starts it once a worker is free
+    pub fn submit(self: &Arc<Self>, tenant: Option<String>, batch: AcceptedBatch) -> Job {
+        self.expire();
+        let job = Job {
+            job_id: new_job_id(),
+            status: JobStatus::Queued,
+            progress: BatchSummary {
+                total: batch.data.len(),
+                ..Default::default()
+            },
+            results: None,
+            error: None,
+            tenant,
+            finished_at: None,
+        };
+        let token = CancellationToken::new();
+        self.tokens.insert(job.job_id.clone(), token.clone());
+        self.store.insert(job.clone());
+
+        let jobs = self.clone();
+        let id = job.job_id.clone();
+        tokio::spawn(async move {
+            jobs.run(&id, batch, token).await;
+            jobs.tokens.remove(&id);
+        });
+        job
+    }
+
+    async fn run(&self, id: &str, batch: AcceptedBatch, token: CancellationToken) {
+        let permit = tokio::select! {
+            permit = self.workers.clone().acquire_owned() => match permit {
+                Ok(permit) => permit,
+                Err(_) => return,
+            },
+            // Cancelled while queued, `cancel` already marked it
+            _ = token.cancelled() => return,
+        };
+        self.store.update(id, &mut |job| {
+            if job.status == JobStatus::Queued {
+                job.status = JobStatus::Running;
+            }
+        });
+
+        let (tx, mut rx) = mpsc::channel(32);
+        let worker_token = token.clone();
+        let worker = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let AcceptedBatch {
+                rule_set,
+                data,
+                options,
+                mut reservation,
+            } = batch;
+            run_batch(
+                &rule_set,
+                data,
+                &options,
+                &mut reservation,
+                &worker_token,
+                &tx,
+            )
+        });
+
+        let mut results = Vec::new();
+        while let Some(event) = rx.recv().await {
+            if let BatchEvent::Item(item) = event {
+                self.store.update(id, &mut |job| job.progress.record(&item));
+                results.push(item);
+            }
+        }
+
+        let mut results = Some(results);
+        match worker.await {
+            Ok(summary) => self.store.update(id, &mut |job| {
+                job.progress = summary.clone();
+                if job.status == JobStatus::Running {
+                    job.results = results.take();
+                    job.finish(JobStatus::Completed);
+                }
+            }),
+            Err(error) => self.store.update(id, &mut |job| {
+                job.error = Some(format!("Job worker stopped: {}", error));
+                job.finish(JobStatus::Failed);
+            }),
+        }
+    }
+
+    /// The job, when `tenant` submitted it and it hasn't expired
+    pub fn get(&self, tenant: Option<&str>, id: &str) -> Option<Job> {
+        self.expire();
+        self.store
+            .get(id)
+            .filter(|job| job.tenant.as_deref() == tenant)
+    }
+
+    /// Cancels a queued or running job, or removes a finished one
+    pub fn cancel(&self, tenant: Option<&str>, id: &str) -> Option<Job> {
+        let job = self.get(tenant, id)?;
+        if job.status.is_finished() {
+            return self.store.remove(id);
+        }
+
+        self.store.update(id, &mut |job| {
+            if !job.status.is_finished() {
+                job.finish(JobStatus::Cancelled);
+            }
+        });
+        if let Some(token) = self.tokens.get(id) {
+            token.cancel();
+        }
+        self.store.get(id)
+    }
+
+    fn expire(&self) {
+        if let Some(cutoff) = Instant::now().checked_sub(self.ttl) {
+            self.store.expire(cutoff);
+        }
+    }
+}
+
+/// Unique within the process and hard to guess across it
+fn new_job_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    let digest = Sha256::new()
+        .chain_update(nanos.to_le_bytes())
+        .chain_update(COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes())
+        .chain_update(std::process::id().to_le_bytes())
+        .finalize();
+    digest[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn job_not_found(id: &str) -> Response {
+    let body = serde_json::json!({
+        "error": format!("No job with id '{}'", id),
+        "code": "job_not_found",
+    });
+    (StatusCode::NOT_FOUND, Json(body)).into_response()
+}
+
+fn tenant_id(tenant: &Option<Extension<Tenant>>) -> Option<String> {
+    tenant.as_ref().map(|Extension(tenant)| tenant.id.clone())
+}
+
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Arc<Jobs>: FromRef<S>,
+{
+    Router::new()
+        .route("/jobs", post(handle_submit))
+        .route("/jobs/{id}", get(handle_get).delete(handle_cancel))
+}
+
+/// `POST /jobs` - takes a `POST /batch/stream` body and answers 202 with the `job_id` to poll
+/// at once, running the batch in the background. Refused like a batch stream would be
+async fn handle_submit(
+    State(jobs): State<Arc<Jobs>>,
+    tenant: Option<Extension<Tenant>>,
+    Json(request): Json<BatchRequest>,
+) -> Result<Response, Response> {
+    let batch = batch::accept(&tenant, request).map_err(IntoResponse::into_response)?;
+    let job = jobs.submit(tenant_id(&tenant), batch);
+
+    let location = format!("/jobs/{}", job.job_id);
+    let mut response = (
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({ "job_id": job.job_id })),
+    )
+        .into_response();
+    if let Ok(location) = HeaderValue::from_str(&location) {
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+    Ok(response)
+}
+
+/// `GET /jobs/{id}` - the job's status and progress, and its results once completed
+async fn handle_get(
+    State(jobs): State<Arc<Jobs>>,
+    tenant: Option<Extension<Tenant>>,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, Response> {
+    jobs.get(tenant_id(&tenant).as_deref(), &id)
+        .map(Json)
+        .ok_or_else(|| job_not_found(&id))
+}
+
+/// `DELETE /jobs/{id}` - cancels a queued or running job, which stops between chunks, or
+/// removes a finished one
+async fn handle_cancel(
+    State(jobs): State<Arc<Jobs>>,
+    tenant: Option<Extension<Tenant>>,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, Response> {
+    jobs.cancel(tenant_id(&tenant).as_deref(), &id)
+        .map(Json)
+        .ok_or_else(|| job_not_found(&id))
+}
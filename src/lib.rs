@@ -1,11 +1,42 @@
+//! The policy engine library: parses the rule DSL and evaluates rule sets against JSON data
+//! with a trace of every decision, see `runner::evaluator::evaluate`.
+//!
+//! # Features
+//!
+//! | Feature  | Default | Enables |
+//! |----------|---------|---------|
+//! | `server` | yes     | The `engine` HTTP server binary, with axum, tokio, compression and the flags client |
+//! | `grpc`   | no      | The gRPC front end of the server, with tonic and prost. Implies `server` |
+//!
+//! The library itself needs none of them: `runner` only depends on pest, serde, serde_json,
+//! chrono, thiserror, sha2 and indexmap. Embedders, such as a lambda or a WASM build, can
+//! depend on the crate with `default-features = false`, and `cargo test --no-default-features`
+//! checks that build.
+
 pub mod runner;
 
+pub use runner::utils::render_json_path;
+pub use runner::version::version;
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use runner::evaluator::evaluate_rule_set;
+    use indexmap::IndexMap;
+    use runner::error::RuleError;
+    use runner::evaluator::evaluate;
+    use runner::model::RuleSet;
+    use runner::options::EvaluationOptions;
     use runner::parser::parse_rules;
-    use serde_json::json;
+    use serde_json::{json, Value};
+
+    /// Each rule's result with the default options, failing the test on an evaluation error
+    fn evaluate_outcomes(rule_set: &RuleSet, data: &Value) -> IndexMap<String, bool> {
+        let outcome = evaluate(rule_set, data, &EvaluationOptions::default());
+        if let Some(error) = outcome.error {
+            panic!("evaluation failed: {}", error);
+        }
+        outcome.outcomes
+    }
 
     #[test]
     fn test_greater_than_or_equal() {
@@ -21,7 +52,7 @@ mod tests {
                 "age": 70
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["senior_discount"]);
 
         let json_false = json!({
@@ -29,7 +60,7 @@ mod tests {
                 "age": 60
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["senior_discount"]);
     }
 
@@ -47,7 +78,7 @@ mod tests {
                 "age": 10
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["child_discount"]);
 
         let json_false = json!({
@@ -55,7 +86,7 @@ mod tests {
                 "age": 15
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["child_discount"]);
     }
 
@@ -73,7 +104,7 @@ mod tests {
                 "amount": 1337
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["flagged"]);
 
         let json_false = json!({
@@ -81,7 +112,7 @@ mod tests {
                 "amount": 1000
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["flagged"]);
     }
 
@@ -99,7 +130,7 @@ mod tests {
                 "status": "completed"
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["normal"]);
 
         let json_false = json!({
@@ -107,7 +138,7 @@ mod tests {
                 "status": "flagged"
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["normal"]);
     }
 
@@ -126,7 +157,7 @@ mod tests {
                 "expiryDate": "2023-12-31"
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["active"]);
 
         // Test case where condition is false
@@ -135,7 +166,7 @@ mod tests {
                 "expiryDate": "2022-12-31"
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["active"]);
     }
 
@@ -154,7 +185,7 @@ A **Document** is archived
                 "creationDate": "2019-06-15"
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
 
         //println!("Trace: {:#?}", trace_true);
 
@@ -175,7 +206,7 @@ A **Document** is archived
                 "category": "electronics"
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["on_sale"]);
 
         let json_false = json!({
@@ -183,7 +214,7 @@ A **Document** is archived
                 "category": "furniture"
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["on_sale"]);
     }
 
@@ -201,7 +232,7 @@ A **Document** is archived
                 "category": "furniture"
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["full_price"]);
 
         let json_false = json!({
@@ -209,7 +240,7 @@ A **Document** is archived
                 "category": "electronics"
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["full_price"]);
     }
 
@@ -227,7 +258,7 @@ A **Document** is archived
                 "content": "This is an urgent message"
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["flagged"]);
 
         let json_false = json!({
@@ -235,7 +266,7 @@ A **Document** is archived
                 "content": "This is a normal message"
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["flagged"]);
     }
 
@@ -255,7 +286,7 @@ A **Document** is archived
                 "age": 70
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["senior_discount"]);
 
         let json_false = json!({
@@ -263,7 +294,7 @@ A **Document** is archived
                 "age": 60
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["senior_discount"]);
     }
 
@@ -281,8 +312,7 @@ A **Document** is archived
                 "firstName": "John"
             }
         });
-        let (results_true, _trace_true) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["discount"]);
 
         let json_false = serde_json::json!({
@@ -290,8 +320,7 @@ A **Document** is archived
                 "firstName": "Jane"
             }
         });
-        let (results_false, _trace_false) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["discount"]);
     }
 
@@ -310,9 +339,8 @@ A **Document** is archived
                 "drivingTestScore": 60
             }
         });
-        let (result_true, _trace_true) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_true).unwrap();
-        assert!(result_true["a full driving license"]);
+        let result_true = evaluate_outcomes(&rule_set, &json_true);
+        assert!(result_true["full driving license"]);
 
         let json_false = serde_json::json!({
             "Person": {
@@ -320,9 +348,8 @@ A **Document** is archived
                 "drivingTestScore": 59
             }
         });
-        let (result_false, _trace_false) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_false).unwrap();
-        assert!(!result_false["a full driving license"]);
+        let result_false = evaluate_outcomes(&rule_set, &json_false);
+        assert!(!result_false["full driving license"]);
     }
 
     #[test]
@@ -344,9 +371,8 @@ A **Document** is archived
                 "drivingTestScore": 60
             }
         });
-        let (result_true, _trace_true) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_true).unwrap();
-        assert!(result_true["a full driving license"]);
+        let result_true = evaluate_outcomes(&rule_set, &json_true);
+        assert!(result_true["full driving license"]);
 
         let json_false = serde_json::json!({
             "Person": {
@@ -354,9 +380,8 @@ A **Document** is archived
                 "drivingTestScore": 59
             }
         });
-        let (result_false, _trace_false) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_false).unwrap();
-        assert!(!result_false["a full driving license"]);
+        let result_false = evaluate_outcomes(&rule_set, &json_false);
+        assert!(!result_false["full driving license"]);
     }
 
     #[test]
@@ -378,9 +403,8 @@ A **Document** is archived
                 "drivingTestScore": 60
             }
         });
-        let (result_true, _trace_true) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_true).unwrap();
-        assert!(result_true["a full driving license"]);
+        let result_true = evaluate_outcomes(&rule_set, &json_true);
+        assert!(result_true["full driving license"]);
 
         let json_true_or = serde_json::json!({
             "Person": {
@@ -388,9 +412,8 @@ A **Document** is archived
                 "drivingTestScore": 50
             }
         });
-        let (result_true, _trace_true) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_true_or).unwrap();
-        assert!(result_true["a full driving license"]);
+        let result_true = evaluate_outcomes(&rule_set, &json_true_or);
+        assert!(result_true["full driving license"]);
 
         let json_false = serde_json::json!({
             "Person": {
@@ -398,9 +421,8 @@ A **Document** is archived
                 "drivingTestScore": 59
             }
         });
-        let (result_false, _trace_false) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_false).unwrap();
-        assert!(!result_false["a full driving license"]);
+        let result_false = evaluate_outcomes(&rule_set, &json_false);
+        assert!(!result_false["full driving license"]);
     }
 
     #[test]
@@ -421,9 +443,8 @@ A **Document** is archived
                 "drivingTestScore": 60
             }
         });
-        let (result_true, _trace_true) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_true).unwrap();
-        assert!(result_true["a full driving license"]);
+        let result_true = evaluate_outcomes(&rule_set, &json_true);
+        assert!(result_true["full driving license"]);
 
         let json_false = serde_json::json!({
             "Person": {
@@ -431,9 +452,8 @@ A **Document** is archived
                 "drivingTestScore": 59
             }
         });
-        let (result_false, _trace_false) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_false).unwrap();
-        assert!(!result_false["a full driving license"]);
+        let result_false = evaluate_outcomes(&rule_set, &json_false);
+        assert!(!result_false["full driving license"]);
     }
 
     #[test]
@@ -458,9 +478,8 @@ A **Document** is archived
                 "theory": 5
             }
         });
-        let (result_true, _trace_true) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_true).unwrap();
-        assert!(result_true["a full driving license"]);
+        let result_true = evaluate_outcomes(&rule_set, &json_true);
+        assert!(result_true["full driving license"]);
 
         let json_false = serde_json::json!({
             "Person": {
@@ -471,79 +490,78 @@ A **Document** is archived
                 "theory": 5
             }
         });
-        let (result_false, _trace_false) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &json_false).unwrap();
-        assert!(!result_false["a full driving license"]);
+        let result_false = evaluate_outcomes(&rule_set, &json_false);
+        assert!(!result_false["full driving license"]);
     }
 
-    // #[test]
-    // fn test_wierd_ref() {
-    //     let rule_text = r#"
-    //     An **employee** is Zoom Setup Aligned
-    //       if **employee** is covered by at least one rule.
-    //
-    //     An **employee** is covered by at least one rule
-    //       if **employee** satisfies rule 1 - No Zoom Profile
-    //       or **employee** satisfies next-Criteria 2.
-    //
-    //     An  **employee** satisfies rule 1 - No Zoom Profile
-    //       if __zoom setup__ of the **employee** is equal to "No Zoom Account".
-    //
-    //     1.Banker.Model. An **employee** satisfies next-Criteria 2
-    //       if **employee** satisfies rule 2 - Banker Model
-    //       or **employee** satisfies next-Criteria 3.
-    //
-    //     An **employee** satisfies rule 2 - Banker Model
-    //       if __banker model list__ of the **employee** is equal to "Yes".
-    //
-    //     An **employee** satisfies next-Criteria 3
-    //       if **employee** satisfies rule 3 - Recorded Zoom
-    //       or **employee** satisfies next-Criteria 4.
-    //
-    //     An **employee** satisfies rule 3 - Recorded Zoom
-    //       if __zoom setup__ of the **employee** is equal to "Recorded Zoom"
-    //       and __zoom profile__ of the **employee** is equal to "Recorded Zoom".
-    //
-    //     An **employee** satisfies next-Criteria 4
-    //       if **employee** satisfies rule 4 - Standard Zoom
-    //       or **employee** satisfies next-Criteria 5.
-    //
-    //     An **employee** satisfies rule 4 - Standard Zoom
-    //       if __zoom setup__ of the **employee** is equal to "Standard Zoom"
-    //       and __zoom profile__ of the **employee** is equal to "Standard Zoom".
-    //
-    //     An **employee** satisfies next-Criteria 5
-    //       if **employee** satisfies rule 5 - Disclaimer Zoom.
-    //
-    //     An **employee** satisfies rule 5 - Disclaimer Zoom
-    //       if __zoom setup__ of the **employee** is equal to "Disclaimer Zoom"
-    //       and __zoom profile__ of the **employee** is equal to "Disclaimer Zoom".
-    //     "#;
-    //     let rule_set = runner::parser::parse_rules(rule_text).unwrap();
-    //     let json_true = serde_json::json!({
-    //       "employee": {
-    //         "soeId": "JM78873",
-    //         "name": "Joey",
-    //         "ZoomProfile": "Recorded Zoom",
-    //         "BankerModelList": "No",
-    //         "ZoomSetup": "Recorded Zoom",
-    //       }
-    //     });
-    //     let (result_true, _trace_true) = runner::evaluator::evaluate_rule_set(&rule_set, &json_true).unwrap();
-    //     assert!(result_true["Zoom Setup Aligned"]);
-    //
-    //     let json_false = serde_json::json!({
-    //       "employee": {
-    //         "soeId": "JM78873",
-    //         "name": "Joey",
-    //         "ZoomProfile": "Recorded Zoom",
-    //         "BankerModelList": "No",
-    //         "ZoomSetup": "Beep",
-    //       }
-    //     });
-    //     let (result_false, _trace_false) = runner::evaluator::evaluate_rule_set(&rule_set, &json_false).unwrap();
-    //     assert!(!result_false["Zoom Setup Aligned"]);
-    // }
+    #[test]
+    fn test_wierd_ref() {
+        let rule_text = r#"
+        An **employee** is Zoom Setup Aligned
+          if **employee** is covered by at least one rule.
+    
+        An **employee** is covered by at least one rule
+          if **employee** satisfies rule 1 - No Zoom Profile
+          or **employee** satisfies next-Criteria 2.
+    
+        An  **employee** satisfies rule 1 - No Zoom Profile
+          if __zoom setup__ of the **employee** is equal to "No Zoom Account".
+    
+        1.Banker.Model. An **employee** satisfies next-Criteria 2
+          if **employee** satisfies rule 2 - Banker Model
+          or **employee** satisfies next-Criteria 3.
+    
+        An **employee** satisfies rule 2 - Banker Model
+          if __banker model list__ of the **employee** is equal to "Yes".
+    
+        An **employee** satisfies next-Criteria 3
+          if **employee** satisfies rule 3 - Recorded Zoom
+          or **employee** satisfies next-Criteria 4.
+    
+        An **employee** satisfies rule 3 - Recorded Zoom
+          if __zoom setup__ of the **employee** is equal to "Recorded Zoom"
+          and __zoom profile__ of the **employee** is equal to "Recorded Zoom".
+    
+        An **employee** satisfies next-Criteria 4
+          if **employee** satisfies rule 4 - Standard Zoom
+          or **employee** satisfies next-Criteria 5.
+    
+        An **employee** satisfies rule 4 - Standard Zoom
+          if __zoom setup__ of the **employee** is equal to "Standard Zoom"
+          and __zoom profile__ of the **employee** is equal to "Standard Zoom".
+    
+        An **employee** satisfies next-Criteria 5
+          if **employee** satisfies rule 5 - Disclaimer Zoom.
+    
+        An **employee** satisfies rule 5 - Disclaimer Zoom
+          if __zoom setup__ of the **employee** is equal to "Disclaimer Zoom"
+          and __zoom profile__ of the **employee** is equal to "Disclaimer Zoom".
+        "#;
+        let rule_set = runner::parser::parse_rules(rule_text).unwrap();
+        let json_true = serde_json::json!({
+          "employee": {
+            "soeId": "JM78873",
+            "name": "Joey",
+            "ZoomProfile": "Recorded Zoom",
+            "BankerModelList": "No",
+            "ZoomSetup": "Recorded Zoom",
+          }
+        });
+        let result_true = evaluate_outcomes(&rule_set, &json_true);
+        assert!(result_true["Zoom Setup Aligned"]);
+
+        let json_false = serde_json::json!({
+          "employee": {
+            "soeId": "JM78873",
+            "name": "Joey",
+            "ZoomProfile": "Recorded Zoom",
+            "BankerModelList": "No",
+            "ZoomSetup": "Beep",
+          }
+        });
+        let result_false = evaluate_outcomes(&rule_set, &json_false);
+        assert!(!result_false["Zoom Setup Aligned"]);
+    }
 
     #[test]
     fn test_chained_property_access_success() {
@@ -570,8 +588,7 @@ A **Document** is archived
         });
 
         // Parse the rules
-        let (result_true, _trace_true) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &data_true).unwrap();
+        let result_true = evaluate_outcomes(&rule_set, &data_true);
         assert!(result_true["can access a system"]);
 
         let data_false = json!({
@@ -588,8 +605,7 @@ A **Document** is archived
         });
 
         // Evaluate the rules
-        let (result_false, _trace_false) =
-            runner::evaluator::evaluate_rule_set(&rule_set, &data_false).unwrap();
+        let result_false = evaluate_outcomes(&rule_set, &data_false);
         assert!(!result_false["can access a system"]);
     }
 
@@ -611,12 +627,11 @@ A **Document** is archived
         let rule_set = parse_rules(rule_text).expect("Failed to parse rules");
 
         // Evaluate the rules
-        let (results, _trace) =
-            evaluate_rule_set(&rule_set, &data).expect("Failed to evaluate rules");
+        let results = evaluate_outcomes(&rule_set, &data);
 
         // Check that the user passes the test
         assert!(
-            results.get("the test").unwrap_or(&false),
+            results.get("test").unwrap_or(&false),
             "User should pass the test"
         );
     }
@@ -635,7 +650,7 @@ A **Document** is archived
                 "name": "bobby socks"  // 11 characters
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["valid"]);
 
         // Test case where name length is not greater than 5
@@ -644,7 +659,7 @@ A **Document** is archived
                 "name": "bob"  // 3 characters
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["valid"]);
     }
 
@@ -662,7 +677,7 @@ A **Document** is archived
                 "items": [1, 2, 3, 4, 5]  // 5 items
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["valid"]);
 
         // Test case where array length is not greater than 3
@@ -671,7 +686,7 @@ A **Document** is archived
                 "items": [1, 2]  // 2 items
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["valid"]);
     }
 
@@ -689,7 +704,7 @@ A **Document** is archived
                 "items": [1, 2, 3, 4, 5]  // 5 items
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["valid"]);
 
         // Test case where array length is not greater than 3
@@ -698,7 +713,7 @@ A **Document** is archived
                 "items": [1, 2]  // 2 items
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["valid"]);
 
         // Test case where "number of" is used on a string - should return an error
@@ -707,17 +722,30 @@ A **Document** is archived
                 "items": "tester"  // string, not an array
             }
         });
-        let result = evaluate_rule_set(&rule_set, &json_error);
-        assert!(result.is_err());
-        // Optionally check that the error message indicates the invalid use of "number of" on a string
-        if let Err(error) = result {
-            let error_msg = error.to_string();
-            assert!(
-                error_msg.contains("number of")
-                    || error_msg.contains("array")
-                    || error_msg.contains("string")
-            );
-        }
+        let outcome = evaluate(&rule_set, &json_error, &EvaluationOptions::default());
+        let error_msg = outcome.error.unwrap().to_string();
+        assert!(
+            error_msg.contains("Cannot take the number of a string at $.user.items"),
+            "{}",
+            error_msg
+        );
+        assert!(error_msg.contains("use 'length of' to count characters"));
+    }
+
+    #[test]
+    fn test_number_of_object_counts_keys() {
+        let rule_text = r#"
+        A **user** is valid if the number of __roles__ of **user** is equal to 2.
+        "#;
+
+        let rule_set = parse_rules(rule_text).unwrap();
+        let json = json!({
+            "user": {
+                "roles": {"admin": true, "editor": false}
+            }
+        });
+        let results = evaluate_outcomes(&rule_set, &json);
+        assert!(results["valid"]);
     }
 
     #[test]
@@ -734,7 +762,7 @@ A **Document** is archived
                 "password": "secure12"  // 8 characters
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["validation"]);
 
         // Test case where password length doesn't equal 8
@@ -743,7 +771,7 @@ A **Document** is archived
                 "password": "short"  // 5 characters
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["validation"]);
     }
 
@@ -763,7 +791,7 @@ A **Document** is archived
                 }
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["valid"]);
 
         // Test case with nested property having insufficient length
@@ -774,7 +802,7 @@ A **Document** is archived
                 }
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["valid"]);
     }
 
@@ -792,7 +820,7 @@ A **Document** is archived
                 "items": []  // 0 items
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["items"]);
 
         // Test case with non-empty array
@@ -801,7 +829,7 @@ A **Document** is archived
                 "items": ["one"]  // 1 item
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["items"]);
     }
 
@@ -820,7 +848,7 @@ A **Document** is archived
                 "name": "ValidName"  // 9 characters, between 3 and 20
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["valid"]);
 
         // Test case too short
@@ -829,8 +857,7 @@ A **Document** is archived
                 "name": "AB"  // 2 characters, less than 3
             }
         });
-        let (results_false, _trace_false) =
-            evaluate_rule_set(&rule_set, &json_false_short).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false_short);
         assert!(!results_false["valid"]);
 
         // Test case too long
@@ -839,7 +866,7 @@ A **Document** is archived
                 "name": "ThisNameIsWayTooLongToBeValid"  // 30 characters, more than 20
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false_long).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false_long);
         assert!(!results_false["valid"]);
     }
 
@@ -861,7 +888,7 @@ A **Document** is archived
                 }
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["sufficient data"]);
 
         // Test case with object having 2 or fewer properties
@@ -872,7 +899,7 @@ A **Document** is archived
                 }
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["sufficient data"]);
     }
 
@@ -890,7 +917,7 @@ A **Document** is archived
                 "value": null
             }
         });
-        let (results_null, _trace_null) = evaluate_rule_set(&rule_set, &json_null).unwrap();
+        let results_null = evaluate_outcomes(&rule_set, &json_null);
         assert!(!results_null["valid"]); // Should fail gracefully, length of null is 0
 
         // Test case with missing property
@@ -899,8 +926,7 @@ A **Document** is archived
                 "other_field": "value"
             }
         });
-        let (results_missing, _trace_missing) =
-            evaluate_rule_set(&rule_set, &json_missing).unwrap();
+        let results_missing = evaluate_outcomes(&rule_set, &json_missing);
         assert!(!results_missing["valid"]); // Should fail when property doesn't exist
     }
 
@@ -918,7 +944,7 @@ A **Document** is archived
                 "name": "ValidName"  // 9 characters
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["valid"]);
     }
 
@@ -949,7 +975,7 @@ A **Document** is archived
                 }
             });
 
-            let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+            let results = evaluate_outcomes(&rule_set, &json_data);
             assert_eq!(
                 results["valid"],
                 expected,
@@ -975,7 +1001,8 @@ A **Document** is archived
 
         let rule = &rule_set.rules[0];
         assert_eq!(rule.selector, "user");
-        assert_eq!(rule.outcome, "the test");
+        assert_eq!(rule.outcome, "test");
+        assert_eq!(rule.outcome_display, "the test");
         assert_eq!(rule.conditions.len(), 1);
     }
 
@@ -995,7 +1022,7 @@ A **user** is valid if __status__ of **user** is equal to "active".
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(results["valid"]);
     }
 
@@ -1013,10 +1040,40 @@ A **user** is valid if __status__ of **user** is equal to "active".
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(results["premium"]);
     }
 
+    #[test]
+    fn test_boolean_shorthands() {
+        let rule_set = parse_rules(
+            "A **driver** needs a supervisor\n  if the **driver** has a __provisional__\n  and the __supervised__ of the **driver** is false.",
+        )
+        .unwrap();
+        let options = EvaluationOptions::new();
+        let decide = |provisional: serde_json::Value, supervised: serde_json::Value| {
+            let data = json!({"driver": {"provisional": provisional, "supervised": supervised}});
+            evaluate(&rule_set, &data, &options).result
+        };
+
+        assert!(decide(json!(true), json!(false)));
+        assert!(!decide(json!(false), json!(false)));
+        assert!(!decide(json!(true), json!(true)));
+        // Only the boolean is true, not a truthy string
+        assert!(!decide(json!("yes"), json!(false)));
+
+        // With data validation a non-boolean is rejected outright
+        let data = json!({"driver": {"provisional": "yes", "supervised": false}});
+        let strict = EvaluationOptions::new().with_validate_input(true);
+        match evaluate(&rule_set, &data, &strict).error {
+            Some(RuleError::InvalidData { violations }) => {
+                assert_eq!(violations.len(), 1);
+                assert_eq!(violations[0].path, "$.driver.provisional");
+            }
+            other => panic!("Expected invalid data, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_existing_date_comparison_still_works() {
         let input = r#"A **user** is eligible if __birth_date__ of **user** is earlier than date(2000-01-01)."#;
@@ -1031,7 +1088,7 @@ A **user** is valid if __status__ of **user** is equal to "active".
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(results["eligible"]);
     }
 
@@ -1051,7 +1108,7 @@ A **user** is valid if __status__ of **user** is equal to "active".
                 "name": "bobby socks"  // 11 characters
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["valid"]);
 
         // Test with short name
@@ -1060,7 +1117,7 @@ A **user** is valid if __status__ of **user** is equal to "active".
                 "name": "bob"  // 3 characters
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false);
         assert!(!results_false["valid"]);
     }
 
@@ -1083,7 +1140,7 @@ A **user** is valid if __age__ of **user** is greater than 18
                 "name": "John Doe"
             }
         });
-        let (results_true, _trace_true) = evaluate_rule_set(&rule_set, &json_true).unwrap();
+        let results_true = evaluate_outcomes(&rule_set, &json_true);
         assert!(results_true["valid"]);
 
         // Age condition false
@@ -1093,7 +1150,7 @@ A **user** is valid if __age__ of **user** is greater than 18
                 "name": "John Doe"
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false_age).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false_age);
         assert!(!results_false["valid"]);
 
         // Name length condition false
@@ -1103,7 +1160,7 @@ A **user** is valid if __age__ of **user** is greater than 18
                 "name": "Jo"
             }
         });
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_false_name).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_false_name);
         assert!(!results_false["valid"]);
     }
 
@@ -1119,7 +1176,7 @@ A **user** is valid if __age__ of **user** is greater than 18
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(
             results["premium"],
             "Should match exact property name 'is_premium'"
@@ -1138,7 +1195,7 @@ A **user** is valid if __age__ of **user** is greater than 18
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(
             results["premium"],
             "Should match transformed property name 'isPremium'"
@@ -1157,7 +1214,7 @@ A **user** is valid if __age__ of **user** is greater than 18
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(
             results["premium"],
             "Should match case-insensitive property name 'IS_PREMIUM'"
@@ -1176,7 +1233,7 @@ A **user** is valid if __age__ of **user** is greater than 18
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(
             results["premium"],
             "Should match case-insensitive transformed property name 'ISPREMIUM'"
@@ -1194,7 +1251,7 @@ A **user** is valid if __age__ of **user** is greater than 18
                 "first_name": "John"
             }
         });
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_exact).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_exact);
         assert!(results["valid"], "Should match exact 'first_name'");
 
         // Test with camelCase
@@ -1203,7 +1260,7 @@ A **user** is valid if __age__ of **user** is greater than 18
                 "firstName": "John"
             }
         });
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_camel).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_camel);
         assert!(results["valid"], "Should match camelCase 'firstName'");
     }
 
@@ -1218,7 +1275,7 @@ A **user** is valid if __age__ of **user** is greater than 18
                 "firstName": "John"
             }
         });
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_camel).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_camel);
         assert!(
             results["valid"],
             "Should transform 'first name' to 'firstName'"
@@ -1238,7 +1295,7 @@ A **user** is valid if __age__ of **user** is greater than 18
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(
             results["valid"],
             "Should prefer exact match 'is_premium' over 'isPremium'"
@@ -1259,7 +1316,7 @@ A **user** is valid if __age__ of **user** is greater than 18
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(
             results["valid"],
             "Should handle nested property transformations"
@@ -1278,7 +1335,7 @@ A **user** is valid if __age__ of **user** is greater than 18
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(
             results["young"],
             "Should handle date comparison with transformed property"
@@ -1297,7 +1354,7 @@ A **user** is valid if __age__ of **user** is greater than 18
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(
             results["valid"],
             "Should handle length calculation with transformed property"
@@ -1316,7 +1373,7 @@ A **user** is valid if __age__ of **user** is greater than 18
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(
             !results["valid"],
             "Should return false when property is not found"
@@ -1336,7 +1393,7 @@ A **user** is valid if __age__ of **user** is greater than 18
                 "min_age": 18
             }
         });
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(results["valid"]);
     }
 
@@ -1473,7 +1530,7 @@ A **login** passes the password tests
                 "password": "<PASSWORD>"
             }
         });
-        let (results_good, _trace_good) = evaluate_rule_set(&rule_set, &json_good).unwrap();
+        let results_good = evaluate_outcomes(&rule_set, &json_good);
         assert!(results_good["valid"]);
 
         let json_bad_username = json!({
@@ -1482,8 +1539,7 @@ A **login** passes the password tests
                 "password": "<PASSWORD>"
             }
         });
-        let (results_bad_username, _trace_bad_username) =
-            evaluate_rule_set(&rule_set, &json_bad_username).unwrap();
+        let results_bad_username = evaluate_outcomes(&rule_set, &json_bad_username);
         assert!(!results_bad_username["valid"]);
     }
 
@@ -1501,7 +1557,7 @@ A **bob** passes the test if __name__ of **user** is equal to "bob"."#;
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(
             results["valid"],
             "Bob should be valid when user name is bob"
@@ -1513,7 +1569,7 @@ A **bob** passes the test if __name__ of **user** is equal to "bob"."#;
             }
         });
 
-        let (results_false, _trace_false) = evaluate_rule_set(&rule_set, &json_data_false).unwrap();
+        let results_false = evaluate_outcomes(&rule_set, &json_data_false);
         assert!(
             !results_false["valid"],
             "Bob should not be valid when user name is alice"
@@ -1578,8 +1634,8 @@ A **driving test** has taken the test in the time period
             }
           }
         });
-        let (results_good, _trace_good) = evaluate_rule_set(&rule_set, &json_good).unwrap();
-        assert!(results_good["a driving licence"]);
+        let results_good = evaluate_outcomes(&rule_set, &json_good);
+        assert!(results_good["driving licence"]);
 
         let json_bad = json!({
           "drivingTest": {
@@ -1603,14 +1659,12 @@ A **driving test** has taken the test in the time period
             }
           }
         });
-        let (results_bad, _trace_bad) = evaluate_rule_set(&rule_set, &json_bad).unwrap();
-        assert!(!results_bad["a driving licence"]);
+        let results_bad = evaluate_outcomes(&rule_set, &json_bad);
+        assert!(!results_bad["driving licence"]);
     }
 
     #[test]
     fn test_labels_in_evaluation_result() {
-        use runner::evaluator::evaluate_rule_set_with_trace;
-
         let rules = r#"
 A **driver** gets a driving licence
   if §driver.test is valid.
@@ -1626,13 +1680,13 @@ driver.test. A **driver** passes the age test
         });
 
         let result = parse_rules(rules).unwrap();
-        let eval_result = evaluate_rule_set_with_trace(&result, &data);
+        let outcome = evaluate(&result, &data, &EvaluationOptions::default());
 
-        assert!(eval_result.result.is_ok());
-        assert!(eval_result.trace.is_some());
+        assert!(outcome.error.is_none());
+        assert!(outcome.trace.is_some());
 
         // Check that the trace contains labeled rules
-        let trace = eval_result.trace.unwrap();
+        let trace = outcome.trace.unwrap();
         let mut found_label = false;
         for rule_trace in &trace.execution {
             if let Some(label) = &rule_trace.label {
@@ -1650,8 +1704,6 @@ driver.test. A **driver** passes the age test
 
     #[test]
     fn test_dollar_label_reference_evaluation() {
-        use runner::evaluator::evaluate_rule_set_with_trace;
-
         let rules = r#"
 A **user** gets access
   if $admin is valid
@@ -1672,10 +1724,7 @@ manager. A **user** is manager
         });
 
         let result = parse_rules(rules).unwrap();
-        let eval_result = evaluate_rule_set_with_trace(&result, &admin_data);
-
-        assert!(eval_result.result.is_ok());
-        let results = eval_result.result.unwrap();
+        let results = evaluate_outcomes(&result, &admin_data);
         assert!(results["access"], "Admin should get access");
 
         // Test with manager role
@@ -1685,9 +1734,7 @@ manager. A **user** is manager
             }
         });
 
-        let eval_result2 = evaluate_rule_set_with_trace(&result, &manager_data);
-        assert!(eval_result2.result.is_ok());
-        let results2 = eval_result2.result.unwrap();
+        let results2 = evaluate_outcomes(&result, &manager_data);
         assert!(results2["access"], "Manager should get access");
 
         // Test with neither role
@@ -1697,9 +1744,7 @@ manager. A **user** is manager
             }
         });
 
-        let eval_result3 = evaluate_rule_set_with_trace(&result, &user_data);
-        assert!(eval_result3.result.is_ok());
-        let results3 = eval_result3.result.unwrap();
+        let results3 = evaluate_outcomes(&result, &user_data);
         assert!(!results3["access"], "Regular user should not get access");
     }
 
@@ -1759,8 +1804,8 @@ A **driver** has taken the test in the time period
             }
           }
         });
-        let (results_good, _trace_good) = evaluate_rule_set(&rule_set, &json_good).unwrap();
-        assert!(results_good["a driving licence"]);
+        let results_good = evaluate_outcomes(&rule_set, &json_good);
+        assert!(results_good["driving licence"]);
 
         let json_bad = json!({
           "drivingTest": {
@@ -1784,8 +1829,8 @@ A **driver** has taken the test in the time period
             }
           }
         });
-        let (results_bad, _trace_bad) = evaluate_rule_set(&rule_set, &json_bad).unwrap();
-        assert!(!results_bad["a driving licence"]);
+        let results_bad = evaluate_outcomes(&rule_set, &json_bad);
+        assert!(!results_bad["driving licence"]);
     }
 
     #[test]
@@ -1804,7 +1849,7 @@ A **driver** has taken the test in the time period
                 }
             }
         });
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_success).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_success);
         assert!(results["valid"]);
 
         // Test failure case
@@ -1815,7 +1860,7 @@ A **driver** has taken the test in the time period
                 }
             }
         });
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_failure).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_failure);
         assert!(!results["valid"]);
     }
 
@@ -1837,8 +1882,8 @@ A **driver** has taken the test in the time period
                 }
             }
         });
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_success).unwrap();
-        assert!(results["the test"]);
+        let results = evaluate_outcomes(&rule_set, &json_success);
+        assert!(results["test"]);
 
         // Test failure case
         let json_failure = json!({
@@ -1850,8 +1895,8 @@ A **driver** has taken the test in the time period
                 }
             }
         });
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_failure).unwrap();
-        assert!(!results["the test"]);
+        let results = evaluate_outcomes(&rule_set, &json_failure);
+        assert!(!results["test"]);
     }
 
     #[test]
@@ -1870,7 +1915,7 @@ A **driver** has taken the test in the time period
                 }
             }
         });
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_test).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_test);
         assert!(results["valid"]);
     }
 
@@ -1887,7 +1932,7 @@ A **driver** has taken the test in the time period
                 "name": "John"
             }
         });
-        let (results1, _trace1) = evaluate_rule_set(&rule_set1, &json1).unwrap();
+        let results1 = evaluate_outcomes(&rule_set1, &json1);
         assert!(results1["valid"]);
 
         // Test nested selectors
@@ -1903,7 +1948,7 @@ A **driver** has taken the test in the time period
                 }
             }
         });
-        let (results2, _trace2) = evaluate_rule_set(&rule_set2, &json2).unwrap();
+        let results2 = evaluate_outcomes(&rule_set2, &json2);
         assert!(results2["valid"]);
     }
 
@@ -1922,19 +1967,14 @@ A **driver** has taken the test in the time period
             }
         });
 
-        let result = evaluate_rule_set(&rule_set, &json_missing);
-        match result {
-            Ok((results, _)) => {
-                // The evaluation succeeded but the result should be false
-                // since the path doesn't exist
-                assert!(
-                    !results["valid"],
-                    "Should return false when nested path doesn't exist"
-                );
-            }
-            Err(_) => {
-                // Also acceptable - immediate error is fine
-            }
+        let outcome = evaluate(&rule_set, &json_missing, &EvaluationOptions::default());
+        if outcome.error.is_none() {
+            // The evaluation succeeded but the result should be false
+            // since the path doesn't exist; an immediate error is also fine
+            assert!(
+                !outcome.outcomes["valid"],
+                "Should return false when nested path doesn't exist"
+            );
         }
     }
 
@@ -1956,14 +1996,11 @@ A **driver** has taken the test in the time period
             }
         });
 
-        let eval_result =
-            crate::runner::evaluator::evaluate_rule_set_with_trace(&rule_set, &json_data);
-        assert!(eval_result.result.is_ok());
-
-        let results = eval_result.result.unwrap();
-        assert!(results["valid"]);
+        let outcome = evaluate(&rule_set, &json_data, &EvaluationOptions::default());
+        assert!(outcome.error.is_none());
+        assert!(outcome.outcomes["valid"]);
 
-        let trace = eval_result.trace.unwrap();
+        let trace = outcome.trace.unwrap();
         let rule_trace = &trace.execution[0];
         let condition_trace = &rule_trace.conditions[0];
 
@@ -1979,6 +2016,36 @@ A **driver** has taken the test in the time period
         }
     }
 
+    #[test]
+    fn test_trace_path_brackets_keys_with_spaces() {
+        let rule_text = r#"
+        A **user** is valid if __center__ of **driving test** is equal to "Manchester".
+        "#;
+
+        let rule_set = parse_rules(rule_text).unwrap();
+        let json_data = json!({
+            "driving test": {
+                "center": "Manchester"
+            }
+        });
+
+        let outcome = evaluate(&rule_set, &json_data, &EvaluationOptions::default());
+        assert!(outcome.outcomes["valid"]);
+
+        let trace = outcome.trace.unwrap();
+        if let runner::trace::ConditionTrace::Comparison(comp_trace) =
+            &trace.execution[0].conditions[0]
+        {
+            assert_eq!(comp_trace.property.path, "$['driving test'].center");
+            assert_eq!(
+                comp_trace.property.path,
+                render_json_path("driving test", &[], "center")
+            );
+        } else {
+            panic!("Expected comparison trace");
+        }
+    }
+
     #[test]
     fn test_age_comparison_operators() {
         // Test older than operator
@@ -1995,7 +2062,7 @@ A **driver** has taken the test in the time period
                 "date_of_birth": "2000-01-01"
             }
         });
-        let (results_driver, _trace) = evaluate_rule_set(&rule_set, &json_driver).unwrap();
+        let results_driver = evaluate_outcomes(&rule_set, &json_driver);
         assert!(results_driver["allowed to drive"]);
 
         // Test young driver who is 16 years old (not allowed to drive)
@@ -2004,7 +2071,7 @@ A **driver** has taken the test in the time period
                 "date_of_birth": "2008-01-01"
             }
         });
-        let (results_young, _trace) = evaluate_rule_set(&rule_set, &json_young_driver).unwrap();
+        let results_young = evaluate_outcomes(&rule_set, &json_young_driver);
         assert!(!results_young["allowed to drive"]);
 
         // Test younger than operator
@@ -2021,7 +2088,7 @@ A **driver** has taken the test in the time period
                 "date_of_birth": "2020-06-15"
             }
         });
-        let (results_child, _trace) = evaluate_rule_set(&rule_set_child, &json_child).unwrap();
+        let results_child = evaluate_outcomes(&rule_set_child, &json_child);
         assert!(results_child["child discount"]);
 
         // Test teenager who is 14 years old (not a child for discount)
@@ -2030,7 +2097,7 @@ A **driver** has taken the test in the time period
                 "date_of_birth": "2010-01-01"
             }
         });
-        let (results_teen, _trace) = evaluate_rule_set(&rule_set_child, &json_teen).unwrap();
+        let results_teen = evaluate_outcomes(&rule_set_child, &json_teen);
         assert!(!results_teen["child discount"]);
 
         // Test combined age rules with single golden rule
@@ -2054,7 +2121,7 @@ A **driver** has taken the test in the time period
                 "date_of_birth": "1950-03-20"
             }
         });
-        let (results_senior, _trace) = evaluate_rule_set(&rule_set_combined, &json_senior).unwrap();
+        let results_senior = evaluate_outcomes(&rule_set_combined, &json_senior);
         assert!(results_senior["age_appropriate_benefit"]);
 
         // Test toddler who is 3 years old (gets benefit)
@@ -2063,8 +2130,7 @@ A **driver** has taken the test in the time period
                 "date_of_birth": "2021-06-15"
             }
         });
-        let (results_toddler, _trace) =
-            evaluate_rule_set(&rule_set_combined, &json_toddler).unwrap();
+        let results_toddler = evaluate_outcomes(&rule_set_combined, &json_toddler);
         assert!(results_toddler["age_appropriate_benefit"]);
 
         // Test middle-aged person who is 40 years old (no benefit)
@@ -2073,7 +2139,7 @@ A **driver** has taken the test in the time period
                 "date_of_birth": "1984-03-20"
             }
         });
-        let (results_middle, _trace) = evaluate_rule_set(&rule_set_combined, &json_middle).unwrap();
+        let results_middle = evaluate_outcomes(&rule_set_combined, &json_middle);
         assert!(!results_middle["age_appropriate_benefit"]);
     }
 
@@ -2351,7 +2417,7 @@ A **driver** has taken the test in the time period
     //         });
     //
     //         let rule_set = result.unwrap();
-    //         let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+    //         let results = evaluate_outcomes(&rule_set, &json_data);
     //         assert!(results["university admission"], "Student should get university admission");
     //     }
 
@@ -2491,7 +2557,7 @@ advisor.approval. A **student** has advisor approval
                 "content": "This is a class about classification and assistance"
             }
         });
-        let (results_class, _) = evaluate_rule_set(&rule_set, &json_class).unwrap();
+        let results_class = evaluate_outcomes(&rule_set, &json_class);
         assert!(
             !results_class["inappropriate"],
             "Should not match 'ass' within 'class'"
@@ -2503,7 +2569,7 @@ advisor.approval. A **student** has advisor approval
                 "content": "That person is an ass and very rude"
             }
         });
-        let (results_standalone, _) = evaluate_rule_set(&rule_set, &json_standalone).unwrap();
+        let results_standalone = evaluate_outcomes(&rule_set, &json_standalone);
         assert!(
             results_standalone["inappropriate"],
             "Should match 'ass' as standalone word"
@@ -2515,7 +2581,7 @@ advisor.approval. A **student** has advisor approval
                 "content": "What an ass!"
             }
         });
-        let (results_punctuation, _) = evaluate_rule_set(&rule_set, &json_punctuation).unwrap();
+        let results_punctuation = evaluate_outcomes(&rule_set, &json_punctuation);
         assert!(
             results_punctuation["inappropriate"],
             "Should match 'ass' with punctuation boundary"
@@ -2527,7 +2593,7 @@ advisor.approval. A **student** has advisor approval
                 "content": "ass is not a nice word"
             }
         });
-        let (results_start, _) = evaluate_rule_set(&rule_set, &json_start).unwrap();
+        let results_start = evaluate_outcomes(&rule_set, &json_start);
         assert!(
             results_start["inappropriate"],
             "Should match 'ass' at start of string"
@@ -2559,7 +2625,7 @@ advisor.approval. A **student** has advisor approval
                 }
             });
 
-            let (results, _) = evaluate_rule_set(&rule_set, &json).unwrap();
+            let results = evaluate_outcomes(&rule_set, &json);
             assert_eq!(
                 results["flagged"],
                 should_match,
@@ -2593,6 +2659,13 @@ advisor.approval. A **student** has advisor approval
         let error = result.err().unwrap();
         let error_msg = error.to_string();
 
+        // The error is structured so callers can offer the candidates as entry points
+        let candidates = error.global_rule_candidates().unwrap();
+        assert!(matches!(error, RuleError::MultipleGlobalRules { .. }));
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].outcome, "access");
+        assert_eq!(candidates[1].outcome, "premium_features");
+
         // Check that the error message lists both rules
         assert!(
             error_msg.contains("'access'") && error_msg.contains("'premium_features'"),
@@ -2648,7 +2721,7 @@ A **student** meets undergraduate program requirements
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json_data).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json_data);
         assert!(results["admission"], "Student should get admission");
     }
 
@@ -2851,7 +2924,7 @@ A **user** {}
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json);
         assert!(
             results["eligible"],
             "Should evaluate to true when advisor.agreement.faculty_sponsor_confirmed is true"
@@ -2883,12 +2956,12 @@ A **user** {}
 
         // Test syntax 1 (direct path)
         let rule_set1 = parse_rules(rule_text1).unwrap();
-        let (results1, _) = evaluate_rule_set(&rule_set1, &json).unwrap();
+        let results1 = evaluate_outcomes(&rule_set1, &json);
         assert!(results1["eligible"], "Direct path syntax should work");
 
         // Test syntax 2 (in the syntax) - this is currently failing
         let rule_set2 = parse_rules(rule_text2).unwrap();
-        let (results2, _trace2) = evaluate_rule_set(&rule_set2, &json).unwrap();
+        let results2 = evaluate_outcomes(&rule_set2, &json);
         assert!(
             results2["eligible"],
             "'in the' syntax should work the same as direct path"
@@ -2923,10 +2996,149 @@ A **user** {}
             }
         });
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json);
         assert!(
             results["complete"],
             "Should handle deeply nested selectors with dots"
         );
     }
+
+    #[test]
+    fn test_accessed_paths_project_only_what_was_read() {
+        let rule_text = r#"
+        A **user** is approved
+          if the __balance__ of the **account** in the **user** is greater than __limit__ of the **account** in the **user**
+          and the number of __orders__ of **user** is greater than 1.
+        "#;
+        let rule_set = parse_rules(rule_text).unwrap();
+        let json = json!({
+            "user": {
+                "name": "Ann",
+                "account": {"balance": 500, "limit": 100, "iban": "GB00"},
+                "orders": [{"id": 1}, {"id": 2}],
+            },
+            "session": {"token": "secret"},
+        });
+
+        let outcome = evaluate(&rule_set, &json, &EvaluationOptions::default());
+        assert!(outcome.outcomes["approved"]);
+
+        let paths = outcome.trace.unwrap().accessed_paths(&json);
+        assert_eq!(
+            paths,
+            vec![
+                "$.user.account.balance",
+                "$.user.account.limit",
+                "$.user.orders"
+            ]
+        );
+        assert_eq!(
+            runner::utils::project_paths(&json, &paths),
+            json!({
+                "user": {
+                    "account": {"balance": 500, "limit": 100},
+                    "orders": [{"id": 1}, {"id": 2}],
+                },
+            })
+        );
+    }
+
+    fn invoice_data() -> serde_json::Value {
+        json!({
+            "order": {
+                "invoice": {
+                    "number": "INV-1",
+                    "line": [
+                        {"sku": "A-1", "amount": 20, "tags": ["gift"]},
+                        {"sku": "B-2", "amount": 5, "tags": []},
+                        {"sku": "C-3", "tags": ["fragile", "gift"]}
+                    ]
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_each_fans_out_over_a_list_mid_chain() {
+        let rule_text = r#"
+        A **order** is priced
+          if the number of __amount__ of each __line__ of the __invoice__ of the **order** is equal to 2
+          and the __sku__ of each __line__ of the __invoice__ of the **order** contains "B-2"
+          and the number of __tags__ of each __line__ of the __invoice__ of the **order** is equal to 3.
+        "#;
+        let rule_set = parse_rules(rule_text).unwrap();
+        let outcome = evaluate(&rule_set, &invoice_data(), &EvaluationOptions::default());
+        assert!(outcome.outcomes["priced"]);
+        let trace = outcome.trace.unwrap();
+
+        let paths: Vec<&str> = trace.execution[0]
+            .conditions
+            .iter()
+            .map(|condition| match condition {
+                runner::trace::ConditionTrace::Comparison(c) => c.property.path.as_str(),
+                _ => panic!("Expected comparison"),
+            })
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "$.order.invoice.line[*].amount.number",
+                "$.order.invoice.line[*].sku",
+                "$.order.invoice.line[*].tags.number",
+            ]
+        );
+
+        // What was read projects to the per-element values only
+        let data = invoice_data();
+        let projected = runner::utils::project_paths(&data, &trace.accessed_paths(&data));
+        assert_eq!(
+            projected["order"]["invoice"]["line"][2],
+            json!({"sku": "C-3", "tags": ["fragile", "gift"]})
+        );
+        assert!(projected["order"]["invoice"].get("number").is_none());
+    }
+
+    #[test]
+    fn test_nested_each_flattens_and_plain_chains_do_not_fan_out() {
+        let nested = parse_rules(
+            r#"A **order** is complete
+              if the number of __sku__ of each __item__ of each __line__ of the **order** is equal to 3
+              and the __sku__ of each __item__ of each __line__ of the **order** contains "Z"."#,
+        )
+        .unwrap();
+        let data = json!({
+            "order": {
+                "line": [
+                    {"item": [{"sku": "X"}, {"sku": "Y"}]},
+                    {"item": [{"sku": "Z"}]},
+                    {"note": "no items"}
+                ]
+            }
+        });
+        let results = evaluate_outcomes(&nested, &data);
+        assert!(results["complete"]);
+
+        // Without `each`, a list mid-chain is still a missing property
+        let direct = parse_rules(
+            "A **order** is direct if the __amount__ of the __line__ of the __invoice__ of the **order** is greater than 1.",
+        )
+        .unwrap();
+        let results = evaluate_outcomes(&direct, &invoice_data());
+        assert!(!results["direct"]);
+
+        let not_a_list = parse_rules(
+            "A **order** is odd if the __x__ of each __invoice__ of the **order** is equal to 1.",
+        )
+        .unwrap();
+        let error = evaluate(&not_a_list, &invoice_data(), &EvaluationOptions::default())
+            .error
+            .unwrap();
+        assert!(
+            error
+                .to_string()
+                .contains("'each' needs a list at $.order.invoice but found an object"),
+            "{}",
+            error
+        );
+    }
 }
@@ -1,74 +1,841 @@
+mod batch;
+mod change;
+mod compression;
+mod config;
+mod decision;
+mod dry_run;
+mod events;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod ingest;
+mod jobs;
+mod rate_limit;
 mod runner;
+mod self_test;
+mod shadow;
+mod tenants;
+mod traces;
 
 use axum::{
-    extract::{Json, State},
-    http::StatusCode,
+    extract::{FromRef, Json, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
     routing::{get, post},
-    Router,
+    Extension, Router,
 };
+use config::{FlagSettings, ServerConfig};
+use decision::DecisionMap;
+use events::WebhookSink;
 use flags_rs::{Auth, Client};
-use runner::evaluator::evaluate_rule_set_with_trace;
-use runner::parser::parse_rules;
-use runner::trace::RuleSetTrace;
+use indexmap::IndexMap;
+use jobs::Jobs;
+use rate_limit::RateLimiter;
+use runner::compiled::PolicyCache;
+use runner::error::{DataRootError, OutcomeCandidate, ParseErrorDetail, RuleError};
+use runner::evaluator::{evaluate, evaluate_condition, AggregateOperation, CountOperation};
+use runner::fingerprint::fingerprint_with_context;
+use runner::formatter::format_rules;
+use runner::model::{ComparisonOperator, RuleSet};
+use runner::options::{
+    EvaluationOptions, FlagProvider, LegacyFlatData, ListScalarMode, TraceVerbosity,
+};
+use runner::outcome::{EvalStats, EvaluationOutcome, EvaluationWarning};
+use runner::parser::{
+    parse_error_diagnostics, parse_rule_set_with_limits, parse_rules_with_limits, ParseDiagnostic,
+    ParseLimits,
+};
+use runner::plan::{compile_plan, UnresolvedReference};
+use runner::replay::{Decision, ReplayBundle, ReplayOptions};
+use runner::schema::{policy_contract, DataViolation, DisjointData, PolicyContract};
+use runner::sensitivity::{sensitivity_with_options, PerturbationSpec};
+use runner::stats::{ComplexityLimits, LimitViolation, RuleSetStats};
+use runner::trace::{ConditionTrace, RuleSetTrace, TraceFormat};
+use runner::utils::{find_global_rule, project_paths};
+use runner::validator::{
+    validate_rule_set_with_limits, validate_rules_with_limits, Diagnostic, ValidationReport,
+};
+use runner::version::{version, EngineVersion, LANGUAGE_VERSION};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::env;
+use std::fmt;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use tenants::{check_rule_size, Tenant, TenantError, Tenants, DEFAULT_TENANT};
+use tokio::sync::watch;
+use traces::{trace_url, TraceDelivery, Traces, TRACE_NOT_STORED};
 
 #[derive(Deserialize)]
 struct RuleDataPackage {
     rule: String,
     data: Value,
+    /// Facts about the request, such as the channel or region, that rules read through
+    /// `**context**` and that aren't part of `data`
+    #[serde(default)]
+    context: Value,
+    /// Outcome or label to evaluate when the rule set has no single global rule
+    entry: Option<String>,
+    /// Several outcomes to evaluate; the result passes only when all of them do
+    outcomes: Option<Vec<String>>,
+    /// Answer with the result of every rule that ran, by outcome, not just the global one
+    #[serde(default)]
+    include_outcomes: bool,
+    /// Give each of the `rules` in the response its canonical text, from schema version 2
+    #[serde(default)]
+    include_formatted_rules: bool,
+    /// Count null as 0 in `length of` / `number of` instead of treating it as missing
+    #[serde(default)]
+    lenient: bool,
+    /// Check the data has the paths and types the rules read before evaluating
+    #[serde(default)]
+    validate_data: bool,
+    /// Evaluate data holding none of the selectors the rules read, which is refused unless
+    /// set as most likely meant for another policy
+    #[serde(default)]
+    allow_disjoint_data: bool,
+    /// Refuse to evaluate a policy with a rule reference that names no rule, instead of
+    /// reading it from the data or letting it pass as free text
+    #[serde(default)]
+    strict_references: bool,
+    /// Read data holding the properties of the rule set's only selector at the top level as
+    /// if sent under it, with a deprecation warning: `true`, `false` or `"auto"`, the default,
+    /// which does when it holds some of the properties the rules read
+    #[serde(default = "LegacyFlatData::request_default")]
+    legacy_flat_data: LegacyFlatData,
+    /// What single-value comparisons do with a property holding a list
+    #[serde(default)]
+    list_scalar_mode: ListScalarMode,
+    /// Compare with `{"min": x, "max": y}` objects as numeric ranges
+    #[serde(default)]
+    range_objects: bool,
+    /// Leave strings like `2.14.3` unordered rather than order them as versions
+    #[serde(default)]
+    versions_as_strings: bool,
+    /// Compare strings minding case with `is equal to`, `is in` and `contains` too, as
+    /// `is exactly equal to` does
+    #[serde(default)]
+    case_sensitive_strings: bool,
+    /// Date `is within`, `is older than` and `is younger than` count from instead of today,
+    /// e.g. `2025-01-15`
+    #[serde(default)]
+    as_of: Option<chrono::NaiveDate>,
+    /// JSONPath of the object within `data` that selectors read from, e.g.
+    /// `$.envelope.payload`
+    data_root: Option<String>,
+    /// How much of `data` to send back in the response
+    #[serde(default)]
+    echo_data: EchoData,
+    /// Attach a bundle that `policy replay` can re-run
+    #[serde(default)]
+    record_replay: bool,
+    /// `"stored"` keeps the trace in the trace store and answers with a `trace_ref` to fetch
+    /// it by instead
+    #[serde(default)]
+    trace: TraceDelivery,
+    /// `"csv"` answers with the trace alone as CSV rows
+    #[serde(default)]
+    trace_format: TraceFormat,
+    /// `"verbose"` explains in the trace why properties weren't found
+    #[serde(default)]
+    trace_verbosity: TraceVerbosity,
+    /// Answer with `execution_stats`: what the evaluation did and how long it took
+    #[serde(default)]
+    stats: bool,
+    /// Response layout to answer with, 1 unless set; see `SCHEMA_VERSION`
+    schema_version: Option<u32>,
+    /// Rule tags to evaluate, skipping tagged rules without one of them
+    #[serde(default)]
+    include_tags: Vec<String>,
+    /// Rule tags to skip
+    #[serde(default)]
+    exclude_tags: Vec<String>,
+    /// Answer with a status and short body per result instead of the full response
+    decision_map: Option<DecisionMap>,
+    /// Explain how the request would be evaluated instead of evaluating it, like the
+    /// `x-engine-dry-run` header; see `dry_run::explain`
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum EchoData {
+    /// The whole payload
+    #[default]
+    All,
+    /// Nothing
+    None,
+    /// Only the paths the evaluation read
+    Accessed,
+}
+
+impl RuleDataPackage {
+    fn entries(&self) -> Vec<String> {
+        self.entry
+            .iter()
+            .chain(self.outcomes.iter().flatten())
+            .cloned()
+            .collect()
+    }
+
+    /// `options` with the entries and flags this request sets
+    fn evaluation_options(&self, options: &EvaluationOptions) -> EvaluationOptions {
+        options
+            .clone()
+            .with_entries(self.entries())
+            .with_lenient(self.lenient)
+            .with_validate_input(self.validate_data)
+            .with_reject_disjoint_data(!self.allow_disjoint_data)
+            .with_strict_references(self.strict_references)
+            .with_legacy_flat_data(self.legacy_flat_data)
+            .with_context(self.context.clone())
+            .with_list_scalar_mode(self.list_scalar_mode)
+            .with_range_objects(self.range_objects)
+            .with_versions_as_strings(self.versions_as_strings)
+            .with_case_sensitive_strings(self.case_sensitive_strings)
+            .with_reference_date(self.as_of)
+            .with_data_root(self.data_root.clone())
+            .with_trace_verbosity(self.trace_verbosity)
+            .with_include_tags(self.include_tags.clone())
+            .with_exclude_tags(self.exclude_tags.clone())
+    }
+
+    /// The request options that change the decision, as a replay bundle records them
+    fn replay_options(&self) -> ReplayOptions {
+        ReplayOptions {
+            entries: self.entries(),
+            lenient: self.lenient,
+            validate_data: self.validate_data,
+            reject_disjoint_data: !self.allow_disjoint_data,
+            strict_references: self.strict_references,
+            legacy_flat_data: self.legacy_flat_data,
+            list_scalar_mode: self.list_scalar_mode,
+            range_objects: self.range_objects,
+            versions_as_strings: self.versions_as_strings,
+            case_sensitive_strings: self.case_sensitive_strings,
+            // The date the bundle was evaluated on, so replaying it later decides the same
+            as_of: Some(
+                self.as_of
+                    .unwrap_or_else(|| chrono::Utc::now().date_naive()),
+            ),
+            data_root: self.data_root.clone(),
+            context: self.context.clone(),
+            include_tags: self.include_tags.clone(),
+            exclude_tags: self.exclude_tags.clone(),
+        }
+    }
+
+    /// The layout to answer with: version 1 unless the request asks for another one this
+    /// engine knows, and the latest when it asks for a newer one
+    fn schema_version(&self) -> u32 {
+        self.schema_version.unwrap_or(1).clamp(1, SCHEMA_VERSION)
+    }
+
+    /// The submitted text as the schema version echoes it: split into lines in version 1,
+    /// left out from version 2, which lists the parsed `rules` instead
+    fn echoed_rule(&self) -> Option<Vec<String>> {
+        (self.schema_version() < 2).then(|| self.rule.lines().map(String::from).collect())
+    }
+
+    /// The rules of `rule_set` in the order they are written, from schema version 2
+    fn rule_summaries(&self, rule_set: &RuleSet) -> Option<Vec<RuleSummary>> {
+        if self.schema_version() < 2 {
+            return None;
+        }
+        let mut summaries: Vec<RuleSummary> = rule_set
+            .rules
+            .iter()
+            // Rules of included documents aren't in the submitted text
+            .filter(|rule| rule.document.is_none())
+            .filter_map(|rule| {
+                let position = rule.position.as_ref()?;
+                let text = self.rule.get(position.start..position.end)?;
+                let end_line = position.line + text.trim_end().matches('\n').count();
+                let formatted = if self.include_formatted_rules {
+                    format_rules(text).ok()
+                } else {
+                    None
+                };
+                Some(RuleSummary {
+                    label: rule.label.clone(),
+                    outcome: rule.outcome.clone(),
+                    start_line: position.line,
+                    end_line,
+                    formatted: formatted.map(|formatted| formatted.trim_end().to_string()),
+                })
+            })
+            .collect();
+        summaries.sort_by_key(|summary| summary.start_line);
+        Some(summaries)
+    }
+
+    /// The data to send back, as chosen by `echo_data`
+    fn echoed_data(&self, trace: Option<&RuleSetTrace>) -> Option<Value> {
+        match self.echo_data {
+            EchoData::All => Some(self.data.clone()),
+            EchoData::None => None,
+            EchoData::Accessed => {
+                let paths = trace.map(|t| t.accessed_paths(&self.data));
+                Some(project_paths(&self.data, &paths.unwrap_or_default()))
+            }
+        }
+    }
+}
+
+/// Latest response layout. Version 2 reports parse errors in `diagnostics` alone, where
+/// version 1, the default, also fabricates a trace for them, and lists the parsed `rules` in
+/// place of the submitted text split into lines
+const SCHEMA_VERSION: u32 = 2;
+
+/// A parsed rule and the lines of the submitted text it takes up, so a client can find the
+/// rule a trace entry belongs to
+#[derive(Serialize, Debug)]
+struct RuleSummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    /// The outcome as results and labels are keyed by it
+    outcome: String,
+    /// 1-based, the `Tags:` line included
+    start_line: usize,
+    /// 1-based, the line with the closing period
+    end_line: usize,
+    /// The rule in the canonical layout, with `include_formatted_rules`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    formatted: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
 struct EvaluationResponse {
+    /// The layout this response follows, as the request's `schema_version` asked
+    schema_version: u32,
+    /// The engine and rule language versions that answered
+    #[serde(flatten)]
+    engine: EngineVersion,
     result: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     error: Option<String>,
+    /// Where the grammar rejected the policy, and what it expected there
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_error: Option<ParseErrorDetail>,
+    /// Where the policy failed to parse
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    diagnostics: Vec<ParseDiagnostic>,
     #[serde(skip_serializing_if = "Option::is_none")]
     trace: Option<RuleSetTrace>,
+    /// The stored trace in place of `trace`, with `"trace": "stored"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_ref: Option<String>,
+    /// Where `GET` serves the stored trace
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_url: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     labels: Option<HashMap<String, bool>>,
-    rule: Vec<String>,
+    /// Result of every rule that ran by outcome in rule order, when `include_outcomes` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outcomes: Option<IndexMap<String, bool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    candidates: Option<Vec<OutcomeCandidate>>,
+    /// SHA-256 of the canonical rule text, data, engine and rule language versions and decision
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decision_fingerprint: Option<String>,
+    /// Why the data was rejected when `validate_data` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    violations: Option<Vec<DataViolation>>,
+    /// The complexity limit the policy exceeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit_exceeded: Option<LimitViolation>,
+    /// Why `data_root` couldn't be evaluated against
+    #[serde(skip_serializing_if = "Option::is_none")]
+    invalid_data_root: Option<DataRootError>,
+    /// The selectors the rules read and the keys the data has instead, when it has none of
+    /// them and `allow_disjoint_data` isn't set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disjoint_data: Option<DisjointData>,
+    /// The rule references that name no rule, with `strict_references`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unresolved_references: Option<Vec<UnresolvedReference>>,
+    /// The rules being evaluated, outermost first, when a reference loop or the reference
+    /// depth limit stopped the evaluation. Whole, where `error` may cut it short
+    #[serde(skip_serializing_if = "Option::is_none")]
+    call_path: Option<Vec<String>>,
+    /// Conditions decided without comparing their values
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<EvaluationWarning>,
+    /// Conditions the evaluation evaluated, against `limits.max_condition_evaluations`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    evaluations_used: Option<usize>,
+    /// What the evaluation did and how long it took, when the request sets `stats`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    execution_stats: Option<EvalStats>,
+    /// The submitted text split into lines, in schema version 1 only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule: Option<Vec<String>>,
+    /// Every parsed rule in the order written, from schema version 2
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rules: Option<Vec<RuleSummary>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replay: Option<ReplayBundle>,
+}
+
+impl EvaluationResponse {
+    /// The response in the requested format. A CSV response is the trace alone, so one
+    /// without a trace (a policy that didn't parse) stays JSON, as does an NDJSON one.
+    ///
+    /// With a `decision_map`, a decision is answered as it maps. A request that couldn't be
+    /// decided keeps its error response, which gateways treat as a failed subrequest
+    fn into_http(
+        self,
+        status: StatusCode,
+        format: TraceFormat,
+        decision_map: Option<&DecisionMap>,
+    ) -> Response {
+        if let (Some(map), StatusCode::OK) = (decision_map, status) {
+            let trace = self.trace.as_ref();
+            return map.respond(
+                self.result,
+                || trace.and_then(RuleSetTrace::explanation),
+                self.decision_fingerprint,
+            );
+        }
+        match (format, &self.trace) {
+            (TraceFormat::Csv, Some(trace)) => (
+                status,
+                [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+                trace.to_csv(),
+            )
+                .into_response(),
+            (TraceFormat::Ndjson, Some(_)) => self.into_ndjson(status),
+            _ => (status, Json(self)).into_response(),
+        }
+    }
+
+    /// The response as NDJSON streamed a line at a time: the response without its trace
+    /// under `"line": "result"`, then the `RuleSetTrace::write_ndjson` lines. The lines are
+    /// written on a blocking thread as the body is read, so a large trace is never held as
+    /// one string
+    fn into_ndjson(mut self, status: StatusCode) -> Response {
+        #[derive(Serialize)]
+        struct Head<'a> {
+            line: &'static str,
+            #[serde(flatten)]
+            response: &'a EvaluationResponse,
+        }
+
+        let trace = self.trace.take();
+        let (tx, rx) = tokio::sync::mpsc::channel(32);
+        tokio::task::spawn_blocking(move || {
+            let mut body = NdjsonBody {
+                tx,
+                line: Vec::new(),
+            };
+            let head = Head {
+                line: "result",
+                response: &self,
+            };
+            let written = serde_json::to_writer(&mut body, &head)
+                .map_err(std::io::Error::from)
+                .and_then(|()| body.write_all(b"\n"))
+                .and_then(|()| match &trace {
+                    Some(trace) => trace.write_ndjson(&mut body),
+                    None => body.flush(),
+                });
+            if let Err(e) = written {
+                // A client that went away closes the channel; anything else ends the body
+                if e.kind() != std::io::ErrorKind::BrokenPipe {
+                    let _ = body.tx.blocking_send(Err(e));
+                }
+            }
+        });
+
+        (
+            status,
+            [(header::CONTENT_TYPE, "application/x-ndjson")],
+            axum::body::Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx)),
+        )
+            .into_response()
+    }
+}
+
+/// The writing end of a streamed NDJSON body, sending the lines written as they end
+struct NdjsonBody {
+    tx: tokio::sync::mpsc::Sender<std::io::Result<axum::body::Bytes>>,
+    line: Vec<u8>,
+}
+
+impl NdjsonBody {
+    fn send(&mut self, chunk: Vec<u8>) -> std::io::Result<()> {
+        self.tx
+            .blocking_send(Ok(chunk.into()))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+    }
+}
+
+impl std::io::Write for NdjsonBody {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        if let Some(end) = self.line.iter().rposition(|&byte| byte == b'\n') {
+            let rest = self.line.split_off(end + 1);
+            let lines = std::mem::replace(&mut self.line, rest);
+            self.send(lines)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.line.is_empty() {
+            return Ok(());
+        }
+        let rest = std::mem::take(&mut self.line);
+        self.send(rest)
+    }
+}
+
+#[derive(Deserialize)]
+struct ConditionRequest {
+    condition: String,
     data: Value,
+    #[serde(default)]
+    context: Value,
+    #[serde(default)]
+    lenient: bool,
+    #[serde(default)]
+    list_scalar_mode: ListScalarMode,
+    #[serde(default)]
+    case_sensitive_strings: bool,
+    #[serde(default)]
+    as_of: Option<chrono::NaiveDate>,
+}
+
+#[derive(Serialize, Debug)]
+struct ConditionResponse {
+    result: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace: Option<ConditionTrace>,
+}
+
+#[derive(Deserialize)]
+struct ParseRequest {
+    rule: String,
+}
+
+#[derive(Deserialize)]
+struct PlanRequest {
+    rule: String,
+    /// A payload to look the rules' paths up in, showing which names only match loosely
+    sample_data: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct IndexRequest {
+    rule: String,
+}
+
+#[derive(Deserialize)]
+struct ValidateRequest {
+    rule: String,
+}
+
+#[derive(Deserialize)]
+struct SensitivityRequest {
+    rule: String,
+    data: Value,
+    /// `paths`, each with its `deltas` and `step`
+    #[serde(flatten)]
+    spec: PerturbationSpec,
+}
+
+#[derive(Serialize, Debug)]
+struct ParseResponse {
+    valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    outcomes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    global_rule: Option<GlobalRuleStatus>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    diagnostics: Vec<Diagnostic>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<RuleSetStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit_exceeded: Option<LimitViolation>,
+    /// The data paths the rules read and the declared outcome types
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contract: Option<PolicyContract>,
+}
+
+impl ParseResponse {
+    fn invalid(error: String) -> Self {
+        Self {
+            valid: false,
+            error: Some(error),
+            outcomes: Vec::new(),
+            global_rule: None,
+            diagnostics: Vec::new(),
+            stats: None,
+            limit_exceeded: None,
+            contract: None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum GlobalRuleStatus {
+    Found { outcome: String },
+    Missing { candidates: Vec<OutcomeCandidate> },
+    Multiple { candidates: Vec<OutcomeCandidate> },
 }
 
 #[derive(Clone)]
 struct AppState {
-    #[allow(dead_code)]
-    flags_client: Client,
+    tenants: Arc<Tenants>,
+    rate_limiter: Arc<RateLimiter>,
+    config: Arc<ServerConfig>,
+    jobs: Arc<Jobs>,
+    traces: Arc<Traces>,
 }
 
-#[tokio::main]
-async fn main() {
-    let flags_client = Client::builder()
+impl FromRef<AppState> for Arc<Tenants> {
+    fn from_ref(state: &AppState) -> Self {
+        state.tenants.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<ServerConfig> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Jobs> {
+    fn from_ref(state: &AppState) -> Self {
+        state.jobs.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Traces> {
+    fn from_ref(state: &AppState) -> Self {
+        state.traces.clone()
+    }
+}
+
+fn build_flags_client(flags: &FlagSettings) -> Client {
+    Client::builder()
         .with_memory_cache()
         .with_auth(Auth {
-            environment_id: env::var("FF_ENV_ID").unwrap_or_else(|_| "default-env".to_string()),
-            agent_id: env::var("FF_AGENT_ID").unwrap_or_else(|_| "default-agent".to_string()),
-            project_id: env::var("FF_PROJECT_ID").unwrap_or_else(|_| "default-project".to_string()),
+            environment_id: flags.environment_id.clone(),
+            agent_id: flags.agent_id.clone(),
+            project_id: flags.project_id.clone(),
         })
         .build()
-        .unwrap_or_else(|e| panic!("Failed to create flags client: {:?}", e));
+        .unwrap_or_else(|e| panic!("Failed to create flags client: {:?}", e))
+}
 
-    let state = AppState { flags_client };
+/// Client of the flags service, from `flags` in the server config
+fn flags_client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| build_flags_client(&config::current().flags))
+}
 
-    let app = Router::new()
+/// Options every front end evaluates with: the configured limits, flag conditions answered by
+/// the flags service and the unknown flag default. Must be called from within the runtime, and
+/// the evaluation run on the blocking pool
+fn service_options() -> EvaluationOptions {
+    config::current()
+        .evaluation_options()
+        .with_unknown_flag_default(config::current().flags.unknown_default)
+        .with_flags(Arc::new(ServiceFlags::new(flags_client().clone())))
+}
+
+/// Flag states from the flags service for `flag "name" is enabled` conditions. Evaluation runs
+/// on the blocking pool, so a lookup blocks on the service call; the evaluator asks once per
+/// flag per evaluation
+struct ServiceFlags {
+    client: Client,
+    runtime: tokio::runtime::Handle,
+}
+
+impl ServiceFlags {
+    /// Must be called from within the runtime
+    fn new(client: Client) -> Self {
+        Self {
+            client,
+            runtime: tokio::runtime::Handle::current(),
+        }
+    }
+}
+
+impl fmt::Debug for ServiceFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceFlags").finish_non_exhaustive()
+    }
+}
+
+impl FlagProvider for ServiceFlags {
+    fn is_enabled(&self, flag: &str) -> Option<bool> {
+        Some(self.runtime.block_on(self.client.is(flag).enabled()))
+    }
+}
+
+/// List size limits for parsing, from `limits` in the server config
+fn parse_limits() -> &'static ParseLimits {
+    static LIMITS: OnceLock<ParseLimits> = OnceLock::new();
+    LIMITS.get_or_init(|| config::current().parse_limits())
+}
+
+/// Compiled policies for evaluating the rule text of a request, when `policy_cache_dir` is set
+fn policy_cache() -> Option<&'static PolicyCache> {
+    static CACHE: OnceLock<Option<PolicyCache>> = OnceLock::new();
+    CACHE
+        .get_or_init(|| {
+            config::current()
+                .policy_cache_dir
+                .as_ref()
+                .map(PolicyCache::new)
+        })
+        .as_ref()
+}
+
+/// Complexity limits for accepted policies, each unlimited unless configured
+fn complexity_limits() -> &'static ComplexityLimits {
+    static LIMITS: OnceLock<ComplexityLimits> = OnceLock::new();
+    LIMITS.get_or_init(|| config::current().complexity_limits())
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config = ServerConfig::resolve(&args, |name| env::var(name).ok())
+        .unwrap_or_else(|problems| panic!("Invalid server config:\n{}", problems));
+    config::install(config.clone());
+
+    if self_test::requested(&args, |name| env::var(name).ok()) {
+        let passed = match self_test::run(&config) {
+            Ok(report) => {
+                print!("{}", report);
+                report.passed()
+            }
+            Err(error) => {
+                eprintln!("Self-test failed: {}", error);
+                false
+            }
+        };
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    let mut tenants = Tenants::from_config(config.tenants_config.as_deref());
+    if let Some(sink) = WebhookSink::from_settings(&config.webhook, tenants.webhooks()) {
+        tenants = tenants.with_event_sink(sink.start());
+    }
+    let rate_limiter =
+        RateLimiter::new(config.rate_limit_config()).with_api_keys(tenants.api_keys());
+    let state = AppState {
+        tenants: Arc::new(tenants),
+        rate_limiter: Arc::new(rate_limiter),
+        config: Arc::new(config.clone()),
+        jobs: Arc::new(Jobs::from_settings(&config.jobs)),
+        traces: Arc::new(Traces::from_settings(&config.traces)),
+    };
+
+    // Both servers watch the same channel so a single signal drains them together
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        let _ = tokio::signal::ctrl_c().await;
+        let _ = shutdown_tx.send(true);
+    });
+
+    // Both listeners are bound before either serves, so a taken port fails startup
+    let port = config.port;
+    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
+        .await
+        .unwrap_or_else(|e| panic!("Failed to bind HTTP port {}: {}", port, e));
+    #[cfg(feature = "grpc")]
+    let grpc_listener = {
+        let grpc_port = config.grpc_port;
+        tokio::net::TcpListener::bind(format!("0.0.0.0:{}", grpc_port))
+            .await
+            .unwrap_or_else(|e| panic!("Failed to bind gRPC port {}: {}", grpc_port, e))
+    };
+
+    #[cfg(feature = "grpc")]
+    let grpc_server = {
+        println!("gRPC listening on 0.0.0.0:{}", config.grpc_port);
+        tokio::spawn(grpc::serve(
+            grpc_listener,
+            state.clone(),
+            shutdown_rx.clone(),
+        ))
+    };
+
+    // Everything that takes a policy runs as a tenant
+    let tenant_routes = Router::new()
         .route("/", post(handle_run))
+        .route("/parse", post(handle_parse))
+        .route("/plan", post(handle_plan))
+        .route("/index", post(handle_index))
+        .route("/validate", post(handle_validate))
+        .route("/sensitivity", post(handle_sensitivity))
+        .route("/condition", post(handle_condition))
+        .route("/batch", post(batch::handle_batch))
+        .route("/batch/stream", post(batch::handle_batch_stream))
+        .route("/evaluate-change", post(change::handle_evaluate_change))
+        .merge(tenants::routes())
+        .merge(jobs::routes())
+        .merge(traces::routes())
+        .route_layer(middleware::from_fn_with_state(
+            state.config.clone(),
+            ingest::duplicate_keys_layer,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            tenants::tenant_layer,
+        ));
+
+    let app = Router::new()
         .route("/health", get(health_check))
-        .with_state(state);
+        .route("/capabilities", get(handle_capabilities))
+        .route("/version", get(handle_version))
+        .route("/metrics", get(handle_metrics))
+        .route("/configz", get(config::handle_configz))
+        .merge(tenant_routes)
+        .layer(middleware::from_fn_with_state(
+            state.rate_limiter.clone(),
+            rate_limit::rate_limit_layer,
+        ));
+    let app = compression::with_compression(app, config.max_body_bytes).with_state(state);
 
-    let port: u16 = std::env::var("PORT")
-        .unwrap_or_else(|_| "3000".to_string())
-        .parse()
-        .expect("PORT must be a number");
+    println!("Listening on http://0.0.0.0:{}", port);
+    // Client addresses key the rate limiter when no known API key is sent
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal(shutdown_rx))
+    .await
+    .unwrap();
 
-    let addr = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
+    #[cfg(feature = "grpc")]
+    grpc_server
         .await
-        .unwrap();
-    println!("Listening on http://0.0.0.0:{}", port);
-    axum::serve(addr, app).await.unwrap();
+        .expect("gRPC server task panicked")
+        .expect("gRPC server failed");
+}
+
+/// Resolves once the shared shutdown channel has been flipped
+async fn shutdown_signal(mut shutdown_rx: watch::Receiver<bool>) {
+    while !*shutdown_rx.borrow() {
+        if shutdown_rx.changed().await.is_err() {
+            break;
+        }
+    }
 }
 
 async fn health_check() -> (StatusCode, Json<serde_json::Value>) {
@@ -81,116 +848,578 @@ async fn health_check() -> (StatusCode, Json<serde_json::Value>) {
     )
 }
 
+/// Per-tenant request counters and rate limiter state in the Prometheus text format
+async fn handle_metrics(State(state): State<AppState>) -> String {
+    state.tenants.metrics()
+        + &state.tenants.shadows().metrics()
+        + &state.tenants.event_metrics()
+        + &state.rate_limiter.metrics()
+}
+
+/// `GET /version` - the engine and rule language versions stamped on every response
+async fn handle_version() -> Json<EngineVersion> {
+    Json(version())
+}
+
+/// Describes the operators, counting forms and aggregates this engine understands
+async fn handle_capabilities() -> Json<Value> {
+    let operators: Vec<String> = ComparisonOperator::ALL
+        .iter()
+        .map(|operator| match operator.value_suffix() {
+            Some(suffix) => format!("{} <duration> {}", operator, suffix),
+            None => operator.to_string(),
+        })
+        .collect();
+
+    let samples = [
+        ("string", serde_json::json!("")),
+        ("array", serde_json::json!([])),
+        ("object", serde_json::json!({})),
+        ("number", serde_json::json!(0)),
+        ("boolean", serde_json::json!(false)),
+    ];
+    let mut counting = serde_json::Map::new();
+    for operation in CountOperation::ALL {
+        let mut counts = serde_json::Map::new();
+        for (type_name, sample) in &samples {
+            counts.insert(type_name.to_string(), operation.unit(sample).into());
+        }
+        counts.insert(
+            "null".to_string(),
+            "0 when lenient, otherwise missing".into(),
+        );
+        counting.insert(operation.phrase().to_string(), Value::Object(counts));
+    }
+
+    Json(serde_json::json!({
+        "language_version": LANGUAGE_VERSION,
+        "operators": operators,
+        "shorthands": {
+            "is true": "is equal to true",
+            "is false": "is equal to false",
+            "the **selector** has a __property__":
+                "the __property__ of the **selector** is equal to true",
+            "the **selector** is in the N% sample by __property__":
+                "the hash bucket of __property__ of the **selector** is less than N",
+        },
+        "counting": counting,
+        "aggregates": AggregateOperation::ALL
+            .iter()
+            .map(|operation| operation.phrase())
+            .collect::<Vec<_>>(),
+    }))
+}
+
+/// `POST /` - evaluates a rule text against a payload, or with `x-engine-dry-run: true` or
+/// `"dry_run": true` explains how it would without evaluating it
 async fn handle_run(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
+    tenant: Option<Extension<Tenant>>,
+    headers: HeaderMap,
     Json(package): Json<RuleDataPackage>,
-) -> (StatusCode, Json<EvaluationResponse>) {
-    match parse_rules(&package.rule) {
-        Ok(rule_set) => {
-            let evaluation_result = evaluate_rule_set_with_trace(&rule_set, &package.data);
-
-            // Extract labels from trace if available
-            let mut labels = HashMap::new();
-            if let Some(trace) = &evaluation_result.trace {
-                for rule_trace in &trace.execution {
-                    if let Some(label) = &rule_trace.label {
-                        labels.insert(label.to_string(), rule_trace.result);
-                    }
-                }
-            }
+) -> Result<Response, TenantError> {
+    check_rule_size(&tenant, &package.rule)?;
+    if dry_run::requested(&headers, package.dry_run) {
+        let (status, report) = dry_run::explain(&package, None, &service_options());
+        return Ok(report.into_http(status));
+    }
+    let format = package.trace_format;
+    let delivery = package.trace;
+    let decision_map = package.decision_map.clone();
+    let (status, mut response) = run_package(package).await;
+    let tenant_id = tenant
+        .as_ref()
+        .map_or(DEFAULT_TENANT, |tenant| tenant.id.as_str());
+    state
+        .tenants
+        .record_legacy_flat_data(tenant_id, &response.warnings);
+    // A mapped decision answers without the trace, but may explain itself from it
+    if delivery == TraceDelivery::Stored && decision_map.is_none() {
+        let owner = tenant.as_ref().map(|Extension(tenant)| tenant.id.clone());
+        store_trace(state.traces.clone(), owner, &mut response).await;
+    }
+    Ok(response.into_http(status, format, decision_map.as_ref()))
+}
+
+/// Swaps the trace of `response` for a reference to it in the trace store. A trace that can't
+/// be stored stays in the response with a warning, so the decision is never lost
+async fn store_trace(
+    traces: Arc<Traces>,
+    tenant: Option<String>,
+    response: &mut EvaluationResponse,
+) {
+    let Some(trace) = response.trace.take() else {
+        return;
+    };
+    let fingerprint = response.decision_fingerprint.clone();
+    let (trace, stored) = tokio::task::spawn_blocking(move || {
+        let stored = traces.store(tenant.as_deref(), fingerprint.as_deref(), &trace);
+        (trace, stored)
+    })
+    .await
+    .expect("trace store task panicked");
+    match stored {
+        Ok(trace_ref) => {
+            response.trace_url = Some(trace_url(&trace_ref));
+            response.trace_ref = Some(trace_ref.0);
+        }
+        Err(e) => {
+            response.trace = Some(trace);
+            response.warnings.push(EvaluationWarning {
+                condition: None,
+                path: String::new(),
+                message: format!("Trace couldn't be stored and is sent inline: {}", e),
+                code: Some(TRACE_NOT_STORED),
+            });
+        }
+    }
+}
+
+/// Evaluates a package on the blocking pool, stopping early if the caller goes away
+async fn run_package(package: RuleDataPackage) -> (StatusCode, EvaluationResponse) {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    // axum drops this future when the client disconnects, which trips the flag
+    let _guard = CancelOnDrop(cancelled.clone());
+    let options = service_options().with_cancellation(cancelled);
+
+    tokio::task::spawn_blocking(move || evaluate_package(&package, &options))
+        .await
+        .expect("evaluation task panicked")
+}
+
+/// Sets the cancellation flag when dropped so an abandoned evaluation stops early
+struct CancelOnDrop(Arc<AtomicBool>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
 
-            let rule = package.rule.lines().map(String::from).collect();
-
-            match evaluation_result.result {
-                Ok(results) => {
-                    // Find the global rule to get its outcome
-                    let global_rule = match crate::runner::utils::find_global_rule(&rule_set.rules)
-                    {
-                        Ok(rule) => rule,
-                        Err(_) => {
-                            // If no global rule found, fall back to first result
-                            let result = results.values().next().cloned().unwrap_or(false);
-                            let response = EvaluationResponse {
-                                result,
-                                error: None,
-                                trace: evaluation_result.trace,
-                                labels: if labels.is_empty() {
-                                    None
-                                } else {
-                                    Some(labels)
-                                },
-                                rule,
-                                data: package.data.clone(),
-                            };
-                            return (StatusCode::OK, Json(response));
-                        }
-                    };
-
-                    // Get the result for the global rule's outcome
-                    let result = results.get(&global_rule.outcome).cloned().unwrap_or(false);
-                    let response = EvaluationResponse {
-                        result,
-                        error: None,
-                        trace: evaluation_result.trace,
-                        labels: if labels.is_empty() {
-                            None
-                        } else {
-                            Some(labels)
-                        },
-                        rule,
-                        data: package.data.clone(),
-                    };
-                    (StatusCode::OK, Json(response))
+/// Parses and evaluates a rule package, shared by the HTTP and gRPC front ends
+fn evaluate_package(
+    package: &RuleDataPackage,
+    options: &EvaluationOptions,
+) -> (StatusCode, EvaluationResponse) {
+    evaluate_parsed_package(package, None, options)
+}
+
+/// Same as `evaluate_package`, evaluating `rule_set` when the package's rule was parsed
+/// already, as a stored policy is with its includes resolved
+fn evaluate_parsed_package(
+    package: &RuleDataPackage,
+    rule_set: Option<&RuleSet>,
+    options: &EvaluationOptions,
+) -> (StatusCode, EvaluationResponse) {
+    let (status, mut response) = match rule_set {
+        Some(rule_set) => decide_rule_set(package, rule_set, options),
+        None => decide_package(package, options),
+    };
+    if package.record_replay {
+        response.replay = Some(replay_bundle(package, &response));
+    }
+    (status, response)
+}
+
+/// A bundle that reproduces `response` with `policy replay`. It holds the same data the
+/// response echoes when that is the accessed projection, otherwise the whole payload
+fn replay_bundle(package: &RuleDataPackage, response: &EvaluationResponse) -> ReplayBundle {
+    let (data, projected) = match (package.echo_data, &response.data) {
+        (EchoData::Accessed, Some(projection)) => (projection.clone(), true),
+        _ => (package.data.clone(), false),
+    };
+    let options = package.replay_options();
+    let decision = Decision {
+        result: response.result,
+        error: response.error.clone(),
+        labels: response
+            .labels
+            .iter()
+            .flatten()
+            .map(|(k, v)| (k.clone(), *v))
+            .collect(),
+        decision_fingerprint: response.decision_fingerprint.clone(),
+        trace: response.trace.clone(),
+    };
+    ReplayBundle::record(&package.rule, data, projected, options, decision)
+}
+
+fn decide_package(
+    package: &RuleDataPackage,
+    options: &EvaluationOptions,
+) -> (StatusCode, EvaluationResponse) {
+    let schema_version = package.schema_version();
+    // An explicit entry or outcome list disambiguates rule sets without a single global rule
+    let entries = package.entries();
+    let parsed = match policy_cache() {
+        Some(cache) => cache
+            .parse_rule_set_cached(&package.rule, parse_limits())
+            .and_then(|parsed| {
+                if let Some(warning) = parsed.warning {
+                    eprintln!("{}", warning);
                 }
-                Err(error) => {
-                    // KEY IMPROVEMENT: Now we include trace even on errors!
-                    // This allows API users to see where the error occurred in the evaluation process
-                    // without having to look through logs.
-                    let response = EvaluationResponse {
-                        result: false,
-                        error: Some(error.to_string()),
-                        trace: evaluation_result.trace, // This preserves the evaluation trace even on failure!
-                        labels: if labels.is_empty() {
-                            None
-                        } else {
-                            Some(labels)
-                        },
-                        rule,
-                        data: package.data.clone(),
-                    };
-                    (StatusCode::BAD_REQUEST, Json(response))
+                if entries.is_empty() {
+                    find_global_rule(&parsed.rule_set.rules)?;
                 }
-            }
-        }
-        Err(parse_error) => {
-            let rule = package.rule.lines().map(String::from).collect();
+                Ok(parsed.rule_set)
+            }),
+        None if entries.is_empty() => parse_rules_with_limits(&package.rule, parse_limits()),
+        None => parse_rule_set_with_limits(&package.rule, parse_limits()),
+    };
 
-            // Even for parse errors, create a basic trace showing what we attempted to parse
-            let parse_trace = create_parse_error_trace(&parse_error, &package.rule);
+    match parsed {
+        Ok(rule_set) => decide_rule_set(package, &rule_set, options),
+        Err(parse_error) => {
+            let diagnostics = parse_error_diagnostics(&parse_error, &package.rule, parse_limits());
+            let detail = parse_error.parse_detail().cloned();
+            let trace = (schema_version < 2)
+                .then(|| create_parse_error_trace(detail.as_ref(), &diagnostics, &package.rule));
 
             let response = EvaluationResponse {
+                schema_version,
+                engine: version(),
                 result: false,
                 error: Some(parse_error.to_string()),
-                trace: Some(parse_trace), // Always include trace, even for parse errors!
+                parse_error: detail,
+                diagnostics,
+                trace,
+                trace_ref: None,
+                trace_url: None,
                 labels: None,
-                rule,
-                data: package.data.clone(),
+                outcomes: None,
+                candidates: parse_error.global_rule_candidates().map(<[_]>::to_vec),
+                decision_fingerprint: None,
+                violations: None,
+                limit_exceeded: None,
+                invalid_data_root: None,
+                disjoint_data: None,
+                unresolved_references: None,
+                call_path: None,
+                warnings: Vec::new(),
+                evaluations_used: None,
+                execution_stats: None,
+                rule: package.echoed_rule(),
+                // Nothing parsed, so there are no rules to list
+                rules: None,
+                // The parse error trace doesn't read the data
+                data: package.echoed_data(None),
+                replay: None,
+            };
+            (error_status(&parse_error), response)
+        }
+    }
+}
+
+fn decide_rule_set(
+    package: &RuleDataPackage,
+    rule_set: &RuleSet,
+    options: &EvaluationOptions,
+) -> (StatusCode, EvaluationResponse) {
+    let schema_version = package.schema_version();
+    let options = package.evaluation_options(options);
+    let outcome = match complexity_limits().check(&rule_set.stats()) {
+        Ok(()) => evaluate(rule_set, &package.data, &options),
+        Err(violation) => {
+            EvaluationOutcome::failure(RuleError::ComplexityLimitExceeded(violation), None)
+        }
+    };
+
+    let decision_fingerprint = match outcome.error {
+        None => Some(fingerprint_with_context(
+            rule_set,
+            &package.data,
+            &package.context,
+            options.today(),
+            &outcome,
+        )),
+        Some(_) => None,
+    };
+    let response = EvaluationResponse {
+        schema_version,
+        engine: version(),
+        result: outcome.result,
+        error: outcome.error.as_ref().map(ToString::to_string),
+        data: package.echoed_data(outcome.trace.as_ref()),
+        labels: outcome.labels_if_any(),
+        outcomes: package
+            .include_outcomes
+            .then(|| outcome.outcomes_in_rule_order(rule_set)),
+        candidates: outcome
+            .error
+            .as_ref()
+            .and_then(RuleError::global_rule_candidates)
+            .map(<[_]>::to_vec),
+        decision_fingerprint,
+        violations: match &outcome.error {
+            Some(RuleError::InvalidData { violations }) => Some(violations.clone()),
+            _ => None,
+        },
+        limit_exceeded: match &outcome.error {
+            Some(RuleError::ComplexityLimitExceeded(violation)) => Some(violation.clone()),
+            _ => None,
+        },
+        invalid_data_root: match &outcome.error {
+            Some(RuleError::InvalidDataRoot(error)) => Some(error.clone()),
+            _ => None,
+        },
+        disjoint_data: match &outcome.error {
+            Some(RuleError::DisjointData(disjoint)) => Some(disjoint.clone()),
+            _ => None,
+        },
+        unresolved_references: match &outcome.error {
+            Some(RuleError::UnresolvedReferences { references }) => Some(references.clone()),
+            _ => None,
+        },
+        call_path: outcome
+            .error
+            .as_ref()
+            .and_then(RuleError::call_path)
+            .map(<[_]>::to_vec),
+        warnings: outcome.warnings,
+        evaluations_used: Some(outcome.evaluations_used),
+        execution_stats: package.stats.then_some(outcome.stats),
+        parse_error: None,
+        diagnostics: Vec::new(),
+        // Kept on errors too, so callers see where evaluation stopped
+        trace: outcome.trace,
+        trace_ref: None,
+        trace_url: None,
+        rule: package.echoed_rule(),
+        rules: package.rule_summaries(rule_set),
+        replay: None,
+    };
+    let status = outcome.error.as_ref().map_or(StatusCode::OK, error_status);
+    (status, response)
+}
+
+/// Ambiguous entry points, data that doesn't fit the rules and over-complex policies or
+/// evaluations are well-formed but unprocessable
+fn error_status(error: &RuleError) -> StatusCode {
+    if error.global_rule_candidates().is_some()
+        || matches!(
+            error,
+            RuleError::InvalidData { .. }
+                | RuleError::DisjointData(_)
+                | RuleError::UnresolvedReferences { .. }
+                | RuleError::InvalidOutcome { .. }
+                | RuleError::ComplexityLimitExceeded(_)
+                | RuleError::BudgetExceeded { .. }
+                | RuleError::DataTooDeep { .. }
+                | RuleError::UnexpectedObject { .. }
+                | RuleError::DivisionByZero { .. }
+                | RuleError::MaxDepthExceeded { .. }
+        )
+    {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+async fn handle_parse(
+    tenant: Option<Extension<Tenant>>,
+    Json(request): Json<ParseRequest>,
+) -> Result<(StatusCode, Json<ParseResponse>), TenantError> {
+    check_rule_size(&tenant, &request.rule)?;
+
+    let response = match parse_rule_set_with_limits(&request.rule, parse_limits()) {
+        Ok(rule_set) => {
+            let global_rule = match find_global_rule(&rule_set.rules) {
+                Ok(rule) => GlobalRuleStatus::Found {
+                    outcome: rule.outcome.clone(),
+                },
+                Err(RuleError::NoGlobalRule { candidates }) => {
+                    GlobalRuleStatus::Missing { candidates }
+                }
+                Err(RuleError::MultipleGlobalRules { candidates }) => {
+                    GlobalRuleStatus::Multiple { candidates }
+                }
+                Err(error) => {
+                    return Ok((
+                        StatusCode::BAD_REQUEST,
+                        Json(ParseResponse::invalid(error.to_string())),
+                    ))
+                }
+            };
+
+            let stats = rule_set.stats();
+            if let Err(violation) = complexity_limits().check(&stats) {
+                let error = RuleError::ComplexityLimitExceeded(violation.clone());
+                let response = ParseResponse {
+                    stats: Some(stats),
+                    limit_exceeded: Some(violation),
+                    ..ParseResponse::invalid(error.to_string())
+                };
+                return Ok((error_status(&error), Json(response)));
+            }
+
+            let response = ParseResponse {
+                valid: true,
+                error: None,
+                outcomes: rule_set.rules.iter().map(|r| r.outcome.clone()).collect(),
+                global_rule: Some(global_rule),
+                diagnostics: validate_rule_set_with_limits(&rule_set, parse_limits()),
+                stats: Some(stats),
+                limit_exceeded: None,
+                contract: Some(policy_contract(&rule_set)),
+            };
+            (StatusCode::OK, Json(response))
+        }
+        Err(error) => {
+            let response = ParseResponse {
+                diagnostics: error.parse_diagnostics().unwrap_or_default().to_vec(),
+                ..ParseResponse::invalid(error.to_string())
             };
             (StatusCode::BAD_REQUEST, Json(response))
         }
+    };
+    Ok(response)
+}
+
+/// `POST /plan` - how a policy would be evaluated, for review before it is stored
+async fn handle_plan(
+    tenant: Option<Extension<Tenant>>,
+    Json(request): Json<PlanRequest>,
+) -> Result<(StatusCode, Json<Value>), TenantError> {
+    check_rule_size(&tenant, &request.rule)?;
+
+    let rule_set = match parse_rule_set_with_limits(&request.rule, parse_limits()) {
+        Ok(rule_set) => rule_set,
+        Err(error) => {
+            let body = serde_json::json!({
+                "error": error.to_string(),
+                "diagnostics": error.parse_diagnostics().unwrap_or_default(),
+            });
+            return Ok((StatusCode::BAD_REQUEST, Json(body)));
+        }
+    };
+    let mut plan = compile_plan(&rule_set);
+    if let Some(sample) = &request.sample_data {
+        plan.resolve_sample(sample);
     }
+    Ok((StatusCode::OK, Json(serde_json::json!(plan))))
+}
+
+/// `POST /validate` - the errors and warnings of a policy, without data, for an authoring UI
+/// checking as the user types. A policy that doesn't parse is still a 200, reported as invalid
+async fn handle_validate(
+    tenant: Option<Extension<Tenant>>,
+    Json(request): Json<ValidateRequest>,
+) -> Result<Json<ValidationReport>, TenantError> {
+    check_rule_size(&tenant, &request.rule)?;
+    Ok(Json(validate_rules_with_limits(
+        &request.rule,
+        parse_limits(),
+    )))
+}
+
+/// `POST /index` - where a policy reads each property and references each rule, for "find
+/// references" in the authoring UI
+async fn handle_index(
+    tenant: Option<Extension<Tenant>>,
+    Json(request): Json<IndexRequest>,
+) -> Result<(StatusCode, Json<Value>), TenantError> {
+    check_rule_size(&tenant, &request.rule)?;
+
+    let rule_set = match parse_rule_set_with_limits(&request.rule, parse_limits()) {
+        Ok(rule_set) => rule_set,
+        Err(error) => {
+            let body = serde_json::json!({
+                "error": error.to_string(),
+                "diagnostics": error.parse_diagnostics().unwrap_or_default(),
+            });
+            return Ok((StatusCode::BAD_REQUEST, Json(body)));
+        }
+    };
+    Ok((
+        StatusCode::OK,
+        Json(serde_json::json!(rule_set.usage_index())),
+    ))
+}
+
+/// `POST /sensitivity` - which small changes to a payload's numbers and dates flip its
+/// decision, see `sensitivity_with_options`
+async fn handle_sensitivity(
+    tenant: Option<Extension<Tenant>>,
+    Json(request): Json<SensitivityRequest>,
+) -> Result<(StatusCode, Json<Value>), TenantError> {
+    check_rule_size(&tenant, &request.rule)?;
+
+    let rule_set = match parse_rule_set_with_limits(&request.rule, parse_limits()) {
+        Ok(rule_set) => rule_set,
+        Err(error) => {
+            let body = serde_json::json!({
+                "error": error.to_string(),
+                "diagnostics": error.parse_diagnostics().unwrap_or_default(),
+            });
+            return Ok((StatusCode::BAD_REQUEST, Json(body)));
+        }
+    };
+    let options = service_options();
+    let report = tokio::task::spawn_blocking(move || {
+        sensitivity_with_options(&rule_set, &request.data, &request.spec, &options)
+    })
+    .await
+    .expect("sensitivity task panicked");
+    Ok((StatusCode::OK, Json(serde_json::json!(report))))
+}
+
+/// `POST /condition` - evaluates one condition on its own for the authoring UI
+async fn handle_condition(
+    tenant: Option<Extension<Tenant>>,
+    Json(request): Json<ConditionRequest>,
+) -> Result<(StatusCode, Json<ConditionResponse>), TenantError> {
+    check_rule_size(&tenant, &request.condition)?;
+
+    let options = config::current()
+        .evaluation_options()
+        .with_lenient(request.lenient)
+        .with_context(request.context)
+        .with_list_scalar_mode(request.list_scalar_mode)
+        .with_case_sensitive_strings(request.case_sensitive_strings)
+        .with_reference_date(request.as_of);
+    let response = match evaluate_condition(&request.condition, &request.data, &options) {
+        Ok((result, trace)) => ConditionResponse {
+            result,
+            error: None,
+            trace: Some(trace),
+        },
+        Err(error) => {
+            let response = ConditionResponse {
+                result: false,
+                error: Some(error.to_string()),
+                trace: None,
+            };
+            return Ok((error_status(&error), Json(response)));
+        }
+    };
+    Ok((StatusCode::OK, Json(response)))
 }
 
-/// Creates a trace showing parse error information
+/// The trace schema version 1 responses fabricate for a parse error, so clients that only
+/// read traces see the failing line: where the grammar rejected the text, or the first of
+/// `diagnostics` for a rule that parsed but isn't valid. Version 2 reports `diagnostics`
+/// instead
 fn create_parse_error_trace(
-    parse_error: &runner::error::RuleError,
+    detail: Option<&ParseErrorDetail>,
+    diagnostics: &[ParseDiagnostic],
     rule_text: &str,
 ) -> RuleSetTrace {
     use runner::trace::*;
 
-    // Extract line information from parse error if possible
-    let error_line = extract_line_from_parse_error(&parse_error.to_string());
-    let error_location = find_error_location(rule_text, error_line);
+    let failed_at = detail
+        .map(|detail| (detail.line, detail.snippet.len()))
+        .or_else(|| {
+            diagnostics
+                .first()
+                .map(|diagnostic| (diagnostic.line, diagnostic.snippet.len()))
+        });
+    let error_line = failed_at.map(|(line, _)| line);
+    let error_location = failed_at.map(|(line, end)| runner::model::SourcePosition {
+        line,
+        start: 0,
+        end,
+    });
 
     // Create a synthetic rule trace showing where parsing failed
     let parse_trace = RuleTrace {
@@ -203,7 +1432,8 @@ fn create_parse_error_trace(
             value: "parse_failed".to_string(),
             pos: error_location,
         },
-        conditions: vec![ConditionTrace::Comparison(ComparisonTrace {
+        conditions: vec![ConditionTrace::Comparison(Box::new(ComparisonTrace {
+            id: None,
             selector: SelectorTrace {
                 value: "rule_syntax".to_string(),
                 pos: None,
@@ -215,68 +1445,55 @@ fn create_parse_error_trace(
                     "rule_length": rule_text.lines().count()
                 }),
                 path: format!("$.rule_syntax.line_{}", error_line.unwrap_or(0)),
+                document: None,
+                missed_key: None,
+                warning: None,
             },
             operator: runner::model::ComparisonOperator::EqualTo,
             value: ValueTrace {
                 value: serde_json::json!("invalid"),
                 value_type: "string".to_string(),
                 pos: None,
+                path: None,
+                document: None,
+                seconds: None,
+                source: None,
             },
             evaluation_details: Some(ComparisonEvaluationTrace {
                 left_value: TypedValue {
                     value: serde_json::json!("invalid_syntax"),
                     value_type: "parse_error".to_string(),
+                    source: None,
                 },
                 right_value: TypedValue {
                     value: serde_json::json!("valid_syntax"),
                     value_type: "expectation".to_string(),
+                    source: None,
                 },
                 comparison_result: false,
+                warning: None,
+                list_mode: None,
+                deciding_element: None,
+                duration_seconds: None,
+                window: None,
+                cutoff: None,
+                reference_date: None,
+                quantifier: None,
+                matched_elements: None,
+                case_sensitive: None,
+                unexpected_object: None,
             }),
             result: false,
-        })],
+            inner_result: None,
+            left_expression: None,
+            right_expression: None,
+        }))],
         result: false,
+        skipped: false,
+        error: None,
     };
 
     RuleSetTrace {
         execution: vec![parse_trace],
     }
 }
-
-/// Extract line number from parse error message
-fn extract_line_from_parse_error(error_msg: &str) -> Option<usize> {
-    // Parse error messages typically contain line numbers like "--> 13:5"
-    if let Some(arrow_pos) = error_msg.find("-->") {
-        let after_arrow = &error_msg[arrow_pos + 3..];
-        if let Some(colon_pos) = after_arrow.find(':') {
-            let line_part = &after_arrow[..colon_pos].trim();
-            line_part.parse::<usize>().ok()
-        } else {
-            None
-        }
-    } else {
-        None
-    }
-}
-
-/// Find the source position of the error in the rule text
-fn find_error_location(
-    rule_text: &str,
-    error_line: Option<usize>,
-) -> Option<runner::model::SourcePosition> {
-    if let Some(line_num) = error_line {
-        let lines: Vec<&str> = rule_text.lines().collect();
-        if line_num > 0 && line_num <= lines.len() {
-            let line_content = lines[line_num - 1]; // Convert to 0-based index
-            Some(runner::model::SourcePosition {
-                line: line_num,
-                start: 0,
-                end: line_content.len(),
-            })
-        } else {
-            None
-        }
-    } else {
-        None
-    }
-}
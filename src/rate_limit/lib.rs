@@ -0,0 +1,130 @@
+#[cfg(test)]
+mod tests {
+    use crate::rate_limit::{rate_limit_layer, RateLimitConfig, RateLimiter};
+    use crate::tenants::API_KEY_HEADER;
+    use axum::{middleware, routing::get, Router};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    async fn start_server(limiter: Arc<RateLimiter>) -> String {
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route("/health", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(limiter, rate_limit_layer));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn get_status(
+        client: &reqwest::Client,
+        url: &str,
+        key: Option<&str>,
+    ) -> reqwest::Response {
+        let mut request = client.get(url);
+        if let Some(key) = key {
+            request = request.header(API_KEY_HEADER, key);
+        }
+        request.send().await.unwrap()
+    }
+
+    fn limiter(rps: f64, burst: u32) -> Arc<RateLimiter> {
+        let api_keys = [("a", "team-a"), ("b", "team-b")]
+            .iter()
+            .map(|(key, tenant)| (key.to_string(), tenant.to_string()))
+            .collect();
+        Arc::new(RateLimiter::new(Some(RateLimitConfig { rps, burst })).with_api_keys(api_keys))
+    }
+
+    #[tokio::test]
+    async fn test_burst_then_429_then_recovery() {
+        // Slow enough that no token refills while the burst is spent
+        let limiter = limiter(1.0, 3);
+        let base = start_server(limiter.clone()).await;
+        let client = reqwest::Client::new();
+
+        for _ in 0..3 {
+            assert_eq!(get_status(&client, &base, Some("a")).await.status(), 200);
+        }
+
+        let limited = get_status(&client, &base, Some("a")).await;
+        assert_eq!(limited.status(), 429);
+        assert_eq!(limited.headers()["retry-after"], "1");
+        let body: serde_json::Value = serde_json::from_str(&limited.text().await.unwrap()).unwrap();
+        assert_eq!(body["code"], "rate_limited");
+
+        // Another key has its own bucket
+        assert_eq!(get_status(&client, &base, Some("b")).await.status(), 200);
+
+        // One token refills every second
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(get_status(&client, &base, Some("a")).await.status(), 200);
+        assert_eq!(get_status(&client, &base, Some("a")).await.status(), 429);
+
+        let metrics = limiter.metrics();
+        assert!(
+            metrics.contains("engine_rate_limit_buckets 2\n"),
+            "{}",
+            metrics
+        );
+        assert!(
+            metrics.contains("engine_rate_limited_total 2\n"),
+            "{}",
+            metrics
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exempt_paths_and_disabled_limiter() {
+        let base = start_server(limiter(1.0, 1)).await;
+        let client = reqwest::Client::new();
+        for _ in 0..5 {
+            assert_eq!(
+                get_status(&client, &format!("{}/health", base), None)
+                    .await
+                    .status(),
+                200
+            );
+        }
+        // Callers without a key share their address's bucket
+        assert_eq!(get_status(&client, &base, None).await.status(), 200);
+        assert_eq!(get_status(&client, &base, None).await.status(), 429);
+
+        let unlimited = start_server(Arc::new(RateLimiter::new(None))).await;
+        for _ in 0..20 {
+            assert_eq!(get_status(&client, &unlimited, None).await.status(), 200);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_api_keys_share_the_address_bucket() {
+        let base = start_server(limiter(1.0, 1)).await;
+        let client = reqwest::Client::new();
+        let status = |key| get_status(&client, &base, key);
+
+        assert_eq!(status(Some("invented-1")).await.status(), 200);
+        assert_eq!(status(Some("invented-2")).await.status(), 429);
+        assert_eq!(status(None).await.status(), 429);
+        // A tenant's key still has its own bucket
+        assert_eq!(status(Some("a")).await.status(), 200);
+    }
+
+    #[test]
+    fn test_new_keys_share_one_bucket_over_the_cap() {
+        let limiter = RateLimiter {
+            max_buckets: 2,
+            ..RateLimiter::new(Some(RateLimitConfig { rps: 1.0, burst: 1 }))
+        };
+        assert!(limiter.acquire("ip:10.0.0.1").is_ok());
+        assert!(limiter.acquire("ip:10.0.0.2").is_ok());
+        assert!(limiter.acquire("ip:10.0.0.3").is_ok());
+        assert!(limiter.acquire("ip:10.0.0.4").is_err());
+        assert_eq!(limiter.buckets.len(), 3);
+        // Keys already tracked keep their buckets
+        assert!(limiter.acquire("ip:10.0.0.1").is_err());
+    }
+}
@@ -0,0 +1,168 @@
+mod lib;
+
+use crate::tenants::API_KEY_HEADER;
+use axum::{
+    extract::{ConnectInfo, Json, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dashmap::DashMap;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Paths probes and scrapers hit that are never limited
+pub const EXEMPT_PATHS: [&str; 3] = ["/health", "/livez", "/metrics"];
+
+/// Buckets are swept once this many keys are tracked, dropping the ones that have refilled
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// Most buckets tracked. Once a sweep can't get below it, new keys share `OVERFLOW_KEY`
+const MAX_BUCKETS: usize = 100_000;
+
+const OVERFLOW_KEY: &str = "overflow";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitConfig {
+    /// Tokens added to each bucket per second
+    pub rps: f64,
+    /// Bucket capacity, the number of requests a key can make at once
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets keyed by tenant or client IP, shared by every connection
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: Option<RateLimitConfig>,
+    /// Tenant id of each API key a tenant holds. Other keys are limited by client IP, so
+    /// inventing keys doesn't buy a fresh bucket
+    api_keys: HashMap<String, String>,
+    buckets: DashMap<String, Bucket>,
+    max_buckets: usize,
+    limited: AtomicU64,
+}
+
+impl RateLimiter {
+    pub fn new(config: Option<RateLimitConfig>) -> Self {
+        Self {
+            config,
+            api_keys: HashMap::new(),
+            buckets: DashMap::new(),
+            max_buckets: MAX_BUCKETS,
+            limited: AtomicU64::new(0),
+        }
+    }
+
+    /// Limits requests with one of `api_keys` by the tenant holding it, see
+    /// `Tenants::api_keys`
+    pub fn with_api_keys(mut self, api_keys: HashMap<String, String>) -> Self {
+        self.api_keys = api_keys;
+        self
+    }
+
+    /// Takes a token for `key`, or returns how many seconds until one is available
+    pub fn acquire(&self, key: &str) -> Result<(), u64> {
+        let Some(config) = self.config else {
+            return Ok(());
+        };
+
+        if self.buckets.len() > SWEEP_THRESHOLD.min(self.max_buckets) {
+            self.sweep(config);
+        }
+        let key = if self.buckets.len() >= self.max_buckets && !self.buckets.contains_key(key) {
+            OVERFLOW_KEY
+        } else {
+            key
+        };
+
+        let now = Instant::now();
+        let mut bucket = self.buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: f64::from(config.burst),
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * config.rps).min(f64::from(config.burst));
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            self.limited.fetch_add(1, Ordering::Relaxed);
+            Err(((1.0 - bucket.tokens) / config.rps).ceil().max(1.0) as u64)
+        }
+    }
+
+    fn sweep(&self, config: RateLimitConfig) {
+        let now = Instant::now();
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * config.rps < f64::from(config.burst)
+        });
+    }
+
+    /// Tracked buckets and rejected requests in the Prometheus text format
+    pub fn metrics(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE engine_rate_limit_buckets gauge");
+        let _ = writeln!(out, "engine_rate_limit_buckets {}", self.buckets.len());
+        let _ = writeln!(out, "# TYPE engine_rate_limited_total counter");
+        let _ = writeln!(
+            out,
+            "engine_rate_limited_total {}",
+            self.limited.load(Ordering::Relaxed)
+        );
+        out
+    }
+    /// The tenant of a known API key, otherwise the client IP
+    fn client_key(&self, request: &Request) -> String {
+        let tenant = request
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|key| self.api_keys.get(key));
+        if let Some(tenant) = tenant {
+            return format!("tenant:{}", tenant);
+        }
+        match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+            None => "ip:unknown".to_string(),
+        }
+    }
+}
+
+/// Rejects requests over the caller's rate with 429 and `Retry-After`
+pub async fn rate_limit_layer(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if EXEMPT_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    match limiter.acquire(&limiter.client_key(&request)) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            let body = serde_json::json!({
+                "error": format!("Rate limit exceeded, retry in {} seconds", retry_after),
+                "code": "rate_limited",
+            });
+            let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            response
+        }
+    }
+}
@@ -0,0 +1,233 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::compiled::{PolicyCache, ARTIFACT_SUFFIX};
+    use crate::runner::evaluator::evaluate;
+    use crate::runner::include::{LoadError, RuleLoader};
+    use crate::runner::options::EvaluationOptions;
+    use crate::runner::parser::ParseLimits;
+    use crate::runner::version::{ENGINE_VERSION, LANGUAGE_VERSION};
+    use serde_json::{json, Value};
+    use std::cell::RefCell;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const LICENCE: &str = r#"A **driver** gets a driving licence
+  if the **driver** passes the age test
+  and §vision is valid.
+
+A **driver** passes the age test (also known as "is old enough")
+  if the __age__ of the **driver** is at least 18.
+
+vision. A **driver** passes the eyesight checks
+  if the __vision__ of the **driver** is greater than 0.5."#;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("engine-compiled-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn artifacts(dir: &PathBuf) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_miss_then_hit() {
+        let dir = cache_dir("hit");
+        let cache = PolicyCache::new(&dir);
+
+        assert!(!cache.would_hit(LICENCE, &ParseLimits::default()));
+        let parsed = cache.parse_rules_cached(LICENCE).unwrap();
+        assert!(!parsed.hit);
+        assert_eq!(parsed.warning, None);
+        let names = artifacts(&dir);
+        assert_eq!(names.len(), 1);
+        assert!(names[0].ends_with(ARTIFACT_SUFFIX));
+
+        assert!(cache.would_hit(LICENCE, &ParseLimits::default()));
+        let loaded = cache.parse_rules_cached(LICENCE).unwrap();
+        assert!(loaded.hit);
+        assert_eq!(loaded.warning, None);
+        assert_eq!(loaded.rule_set.source, LICENCE);
+        assert!(loaded.rule_set.get_rule("is old enough").is_some());
+        assert!(loaded.rule_set.get_rule_by_label("vision").is_some());
+
+        for data in [
+            json!({"driver": {"age": 20, "vision": 0.8}}),
+            json!({"driver": {"age": 16, "vision": 0.8}}),
+        ] {
+            let options = EvaluationOptions::new();
+            let fresh = evaluate(&parsed.rule_set, &data, &options);
+            let cached = evaluate(&loaded.rule_set, &data, &options);
+            assert_eq!(cached.result, fresh.result);
+            assert_eq!(cached.outcomes, fresh.outcomes);
+            assert_eq!(
+                serde_json::to_value(&cached.trace).unwrap(),
+                serde_json::to_value(&fresh.trace).unwrap()
+            );
+        }
+
+        // Other limits may not accept the same text, so they have their own artifact
+        let limits = ParseLimits {
+            max_list_elements: 10,
+            ..ParseLimits::default()
+        };
+        assert!(!cache.parse_rule_set_cached(LICENCE, &limits).unwrap().hit);
+        assert_eq!(artifacts(&dir).len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_artifact_is_parsed_again_and_replaced() {
+        let dir = cache_dir("corrupt");
+        let cache = PolicyCache::new(&dir);
+        let path = cache.artifact_path(LICENCE, &ParseLimits::default());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "{\"format\": 1, \"engine_ver").unwrap();
+
+        assert!(!cache.would_hit(LICENCE, &ParseLimits::default()));
+        let parsed = cache.parse_rules_cached(LICENCE).unwrap();
+        assert!(!parsed.hit);
+        let warning = parsed.warning.unwrap();
+        assert!(warning.contains("is corrupt"), "{}", warning);
+        assert!(warning.contains("parsed the policy instead"), "{}", warning);
+
+        assert!(cache.would_hit(LICENCE, &ParseLimits::default()));
+        let loaded = cache.parse_rules_cached(LICENCE).unwrap();
+        assert!(loaded.hit);
+        assert_eq!(artifacts(&dir).len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_artifact_of_another_engine_version_is_replaced() {
+        let dir = cache_dir("version");
+        let cache = PolicyCache::new(&dir);
+        cache.parse_rules_cached(LICENCE).unwrap();
+
+        let path = cache.artifact_path(LICENCE, &ParseLimits::default());
+        let mut artifact: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        artifact["engine_version"] = json!("0.0.1");
+        // A different layout must not be read as this version's rule set
+        artifact["rule_set"] = json!({"rules": "not a list"});
+        fs::write(&path, artifact.to_string()).unwrap();
+
+        assert!(!cache.would_hit(LICENCE, &ParseLimits::default()));
+        let parsed = cache.parse_rules_cached(LICENCE).unwrap();
+        assert!(!parsed.hit);
+        let warning = parsed.warning.unwrap();
+        assert!(
+            warning.contains("was compiled by engine 0.0.1 (format 1)"),
+            "{}",
+            warning
+        );
+        assert!(cache.parse_rules_cached(LICENCE).unwrap().hit);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_artifact_of_another_language_version_is_refused() {
+        let dir = cache_dir("language");
+        let cache = PolicyCache::new(&dir);
+        cache.parse_rules_cached(LICENCE).unwrap();
+
+        let path = cache.artifact_path(LICENCE, &ParseLimits::default());
+        let mut artifact: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(artifact["engine_version"], ENGINE_VERSION);
+        assert_eq!(artifact["language_version"], LANGUAGE_VERSION);
+
+        // Refused even though this engine version wrote it, as is one from before languages
+        // were versioned
+        for stamped in [Some("99.0"), None] {
+            let fields = artifact.as_object_mut().unwrap();
+            match stamped {
+                Some(language) => fields.insert("language_version".to_string(), json!(language)),
+                None => fields.remove("language_version"),
+            };
+            fs::write(&path, artifact.to_string()).unwrap();
+
+            assert!(!cache.would_hit(LICENCE, &ParseLimits::default()));
+            let parsed = cache.parse_rules_cached(LICENCE).unwrap();
+            assert!(!parsed.hit);
+            let expected = format!(
+                "was compiled for rule language {}, which engine {} (rule language {}) doesn't read",
+                stamped.unwrap_or("unversioned"),
+                ENGINE_VERSION,
+                LANGUAGE_VERSION
+            );
+            let warning = parsed.warning.unwrap();
+            assert!(warning.contains(&expected), "{}", warning);
+            // The artifact written in its place is this language's
+            assert!(cache.parse_rules_cached(LICENCE).unwrap().hit);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_policies_that_fail_to_parse_are_not_stored() {
+        let dir = cache_dir("error");
+        let cache = PolicyCache::new(&dir);
+        assert!(cache
+            .parse_rules_cached("A **driver** gets a licence if.")
+            .is_err());
+        assert!(!dir.exists());
+    }
+
+    struct Documents(RefCell<String>);
+
+    impl RuleLoader for Documents {
+        fn load(&self, name: &str) -> Result<String, LoadError> {
+            match name {
+                "age-checks" => Ok(self.0.borrow().clone()),
+                _ => Err(LoadError::NotFound(name.to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_changed_included_document_is_parsed_again() {
+        let dir = cache_dir("include");
+        let cache = PolicyCache::new(&dir);
+        let text = "Include \"age-checks\".\n\nA **driver** gets a driving licence\n  if the **driver** passes the age test.";
+        let loader = Documents(RefCell::new(
+            "A **driver** passes the age test\n  if the __age__ of the **driver** is at least 18."
+                .to_string(),
+        ));
+        let limits = ParseLimits::default();
+
+        assert!(
+            !cache
+                .parse_rule_set_with_loader_cached(text, &limits, &loader)
+                .unwrap()
+                .hit
+        );
+        assert!(
+            cache
+                .parse_rule_set_with_loader_cached(text, &limits, &loader)
+                .unwrap()
+                .hit
+        );
+
+        *loader.0.borrow_mut() =
+            "A **driver** passes the age test\n  if the __age__ of the **driver** is at least 21."
+                .to_string();
+        let parsed = cache
+            .parse_rule_set_with_loader_cached(text, &limits, &loader)
+            .unwrap();
+        assert!(!parsed.hit);
+        assert_eq!(parsed.warning, None);
+        let outcome = evaluate(
+            &parsed.rule_set,
+            &json!({"driver": {"age": 19}}),
+            &EvaluationOptions::new(),
+        );
+        assert!(!outcome.result);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+//! Parsed policies stored as compiled artifacts.
+//!
+//! `RuleSet` serializes to its rules and documents; deserializing rebuilds the lookup maps.
+//! This module stores parsed sets as compiled policies:
+//! `PolicyCache::parse_rule_set_cached(text, limits)` (and `parse_rule_set_with_loader_cached`;
+//! `parse_rules_cached` in tests) hashes the text with the parse limits, loads `<hash>.policy.json`
+//! when this engine version (`ENGINE_VERSION`, `ARTIFACT_FORMAT`) wrote it for a rule language it
+//! reads (`version::language_compatible`: the same major, no newer minor; an artifact without
+//! `language_version` is refused too), and otherwise parses and writes one through a temporary file
+//! and a rename. A corrupt artifact, one of another version, or one that can't be written costs a
+//! parse and a `warning`, never an error; a changed included document is a plain miss. Outcome
+//! declaration positions aren't stored. `policy_cache_dir` (`POLICY_CACHE_DIR`) turns it on for the
+//! rule text of evaluation requests, for `POST /policies` (with the loader of the tenant's
+//! policies) and for `--self-test`, which compiles every policy of `policy_dir` and marks those
+//! loaded from it `(cached)`; `policy check --cache-dir=DIR` does the same from the CLI.
+
+mod lib;
+
+use crate::runner::error::RuleError;
+use crate::runner::include::{parse_rule_set_with_loader, RuleLoader};
+use crate::runner::model::RuleSet;
+use crate::runner::parser::{parse_rule_set_with_limits, ParseLimits};
+use crate::runner::version::{language_compatible, ENGINE_VERSION, GIT_SHA, LANGUAGE_VERSION};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Layout of a compiled policy, bumped when a stored `RuleSet` changes shape between releases
+/// of the same engine version
+pub const ARTIFACT_FORMAT: u32 = 1;
+
+/// File name suffix of a compiled policy
+pub const ARTIFACT_SUFFIX: &str = ".policy.json";
+
+/// A directory of compiled policies: parsed rule sets stored under a hash of their text, so a
+/// cold start loads them instead of parsing again. Artifacts are only used when this engine
+/// version wrote them for a rule language it reads; anything unusable is parsed again and
+/// replaced, with a warning
+#[derive(Debug, Clone)]
+pub struct PolicyCache {
+    dir: PathBuf,
+}
+
+/// A rule set got through a `PolicyCache`
+#[derive(Debug)]
+pub struct CachedParse {
+    pub rule_set: RuleSet,
+    /// Loaded from a compiled artifact rather than parsed
+    pub hit: bool,
+    /// Why an artifact couldn't be used or stored. The policy was parsed regardless
+    pub warning: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Artifact<'a> {
+    format: u32,
+    engine_version: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git_sha: Option<&'a str>,
+    language_version: &'a str,
+    rule_set: &'a RuleSet,
+}
+
+/// Read first, so an artifact of another version isn't deserialized as this one's `RuleSet`
+#[derive(Deserialize)]
+struct ArtifactHeader {
+    format: u32,
+    engine_version: String,
+    /// Missing from artifacts written before languages were versioned
+    #[serde(default)]
+    language_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StoredArtifact {
+    rule_set: RuleSet,
+}
+
+impl PolicyCache {
+    /// The directory is created when the first artifact is written
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `parser::parse_rules` through the cache
+    #[cfg(test)]
+    pub fn parse_rules_cached(&self, text: &str) -> Result<CachedParse, RuleError> {
+        let parsed = self.parse_rule_set_cached(text, &ParseLimits::default())?;
+        crate::runner::utils::find_global_rule(&parsed.rule_set.rules)?;
+        Ok(parsed)
+    }
+
+    /// `parser::parse_rule_set_with_limits` through the cache
+    pub fn parse_rule_set_cached(
+        &self,
+        text: &str,
+        limits: &ParseLimits,
+    ) -> Result<CachedParse, RuleError> {
+        self.load_or_parse(
+            text,
+            limits,
+            |_| true,
+            || parse_rule_set_with_limits(text, limits),
+        )
+    }
+
+    /// `include::parse_rule_set_with_loader` through the cache. A stored set is used only while
+    /// every document it included still loads to the text it was compiled from
+    pub fn parse_rule_set_with_loader_cached(
+        &self,
+        text: &str,
+        limits: &ParseLimits,
+        loader: &dyn RuleLoader,
+    ) -> Result<CachedParse, RuleError> {
+        self.load_or_parse(
+            text,
+            limits,
+            |rule_set| {
+                rule_set.included.iter().all(|document| {
+                    loader
+                        .load(&document.name)
+                        .is_ok_and(|source| source == document.source)
+                })
+            },
+            || parse_rule_set_with_loader(text, limits, loader),
+        )
+    }
+
+    /// The key the artifact of `text` parsed under `limits` is stored under, a SHA-256 of
+    /// both. The limits are part of the key, as they decide whether the text parses at all
+    pub fn key(text: &str, limits: &ParseLimits) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(format!(
+            "{} {}\n",
+            limits.list_warning_elements, limits.max_list_elements
+        ));
+        hasher.update(text.as_bytes());
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Where the artifact of `text` parsed under `limits` is stored, see `key`
+    pub fn artifact_path(&self, text: &str, limits: &ParseLimits) -> PathBuf {
+        self.dir
+            .join(format!("{}{}", Self::key(text, limits), ARTIFACT_SUFFIX))
+    }
+
+    /// Whether `parse_rule_set_cached` would load `text` from its artifact rather than parse
+    /// it. Nothing is parsed or written
+    pub fn would_hit(&self, text: &str, limits: &ParseLimits) -> bool {
+        matches!(
+            read_artifact(&self.artifact_path(text, limits), text),
+            Ok(Some(_))
+        )
+    }
+
+    fn load_or_parse(
+        &self,
+        text: &str,
+        limits: &ParseLimits,
+        current: impl Fn(&RuleSet) -> bool,
+        parse: impl FnOnce() -> Result<RuleSet, RuleError>,
+    ) -> Result<CachedParse, RuleError> {
+        let path = self.artifact_path(text, limits);
+        let mut warning = match read_artifact(&path, text) {
+            Ok(Some(rule_set)) if current(&rule_set) => {
+                return Ok(CachedParse {
+                    rule_set,
+                    hit: true,
+                    warning: None,
+                })
+            }
+            Ok(_) => None,
+            Err(problem) => Some(format!(
+                "compiled policy {} {}, parsed the policy instead",
+                path.display(),
+                problem
+            )),
+        };
+
+        let rule_set = parse()?;
+        if let Err(problem) = self.write_artifact(&path, &rule_set) {
+            warning.get_or_insert(format!(
+                "couldn't store compiled policy {}: {}",
+                path.display(),
+                problem
+            ));
+        }
+        Ok(CachedParse {
+            rule_set,
+            hit: false,
+            warning,
+        })
+    }
+
+    /// Writes to a temporary file in the directory and renames it over the artifact, so a
+    /// concurrent reader sees the old artifact or the new one, never part of one
+    fn write_artifact(&self, path: &Path, rule_set: &RuleSet) -> Result<(), String> {
+        static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+        let json = serde_json::to_vec(&Artifact {
+            format: ARTIFACT_FORMAT,
+            engine_version: ENGINE_VERSION,
+            git_sha: GIT_SHA,
+            language_version: LANGUAGE_VERSION,
+            rule_set,
+        })
+        .map_err(|e| e.to_string())?;
+        // JSON writes a number that isn't finite as null, which wouldn't load again
+        if serde_json::from_slice::<StoredArtifact>(&json).is_err() {
+            return Err("the policy has a value that can't be stored".to_string());
+        }
+
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        let temporary = path.with_extension(format!(
+            "tmp.{}.{}",
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = fs::write(&temporary, &json).and_then(|_| fs::rename(&temporary, path));
+        if let Err(error) = written {
+            let _ = fs::remove_file(&temporary);
+            return Err(error.to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The rule set stored at `path`, `None` when there is no artifact. An artifact that can't be
+/// used is an error saying why
+fn read_artifact(path: &Path, text: &str) -> Result<Option<RuleSet>, String> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(format!("can't be read ({})", error)),
+    };
+
+    let header: ArtifactHeader =
+        serde_json::from_slice(&bytes).map_err(|e| format!("is corrupt ({})", e))?;
+    // Checked first: a policy of another language may not mean the same, whichever engine
+    // compiled it
+    let language = header.language_version.as_deref().unwrap_or("unversioned");
+    if !language_compatible(language) {
+        return Err(format!(
+            "was compiled for rule language {}, which engine {} (rule language {}) doesn't read",
+            language, ENGINE_VERSION, LANGUAGE_VERSION
+        ));
+    }
+    if header.engine_version != ENGINE_VERSION || header.format != ARTIFACT_FORMAT {
+        return Err(format!(
+            "was compiled by engine {} (format {}), not {} (format {})",
+            header.engine_version, header.format, ENGINE_VERSION, ARTIFACT_FORMAT
+        ));
+    }
+
+    let stored: StoredArtifact =
+        serde_json::from_slice(&bytes).map_err(|e| format!("is corrupt ({})", e))?;
+    if stored.rule_set.source != text {
+        return Err("is corrupt (it holds another policy)".to_string());
+    }
+    Ok(Some(stored.rule_set))
+}
@@ -0,0 +1,81 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::conformance::{run_case, ConformanceCase};
+    use serde_json::json;
+
+    fn case(value: serde_json::Value) -> ConformanceCase {
+        serde_json::from_value(value).unwrap()
+    }
+
+    const LICENCE: &str = "A **driver** gets a licence\n  if the **driver** passes the age test.\n\nage.test. A **driver** passes the age test\n  if the __age__ of the **driver** is at least 18.";
+
+    #[test]
+    fn test_met_expectations_pass() {
+        let failures = run_case(&case(json!({
+            "description": "licence",
+            "rules": LICENCE,
+            "evaluations": [
+                {
+                    "data": {"driver": {"age": 20}},
+                    "result": true,
+                    "results": {"age test": true},
+                    "labels": {"age.test": true}
+                },
+                {"data": {"driver": {"age": 16}}, "result": false}
+            ]
+        })));
+        assert!(failures.is_empty(), "{:?}", failures);
+    }
+
+    #[test]
+    fn test_each_unmet_expectation_is_reported() {
+        let failures = run_case(&case(json!({
+            "description": "licence",
+            "rules": LICENCE,
+            "evaluations": [{
+                "description": "adult",
+                "data": {"driver": {"age": 20}},
+                "result": false,
+                "results": {"eye test": true},
+                "labels": {"age.test": false},
+                "error": "not found"
+            }]
+        })));
+        assert_eq!(
+            failures,
+            [
+                "evaluation 1 (adult): expected an error containing 'not found', got none",
+                "evaluation 1 (adult): expected result false, got true",
+                "evaluation 1 (adult): expected 'eye test' to be true, but it wasn't evaluated",
+                "evaluation 1 (adult): expected label 'age.test' to be false, got true",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_error_expectations() {
+        let broken = json!({
+            "description": "broken",
+            "rules": "A **driver** gets a licence if.",
+            "parse_error": "expected"
+        });
+        assert!(run_case(&case(broken.clone())).is_empty());
+
+        let mut parses = broken;
+        parses["rules"] = json!(LICENCE);
+        assert_eq!(
+            run_case(&case(parses)),
+            ["expected a parse error containing 'expected', but the rules parsed"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        let result = serde_json::from_value::<ConformanceCase>(json!({
+            "description": "typo",
+            "rules": LICENCE,
+            "evaluations": [{"data": {}, "resutl": true}]
+        }));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,235 @@
+//! The language conformance suite runner.
+//!
+//! `fixtures/*.case.json` is the language conformance suite, shipped with the crate for other
+//! implementations (schema in `fixtures/README.md`): a policy, payloads with options, and the
+//! expected result, per-outcome results, labels, or an error or parse error message fragment.
+//! `runner::conformance::run_directory` runs a directory through `parse_rule_set` and `evaluate`;
+//! `tests/conformance.rs` runs `fixtures/` and `policy conformance <dir>` runs any directory. New
+//! language features land with a case here.
+
+mod lib;
+
+use crate::runner::error::RuleError;
+use crate::runner::evaluator::evaluate;
+use crate::runner::parser::parse_rule_set;
+use crate::runner::replay::ReplayOptions;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// File name suffix of a conformance case
+pub const CASE_SUFFIX: &str = ".case.json";
+
+/// One `*.case.json` file: a policy and what evaluating it against each payload must decide.
+/// The schema is documented in `fixtures/README.md`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConformanceCase {
+    pub description: String,
+    pub rules: String,
+    /// Set when the rules must fail to parse, to text the error message contains
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parse_error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub evaluations: Vec<CaseEvaluation>,
+}
+
+/// One payload of a case and the expected decision. Only what is given is checked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CaseEvaluation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub data: Value,
+    #[serde(default)]
+    pub options: ReplayOptions,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<bool>,
+    /// Results of evaluated rules by normalized outcome
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub results: BTreeMap<String, bool>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, bool>,
+    /// Set when the evaluation must fail, to text the error message contains
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What running one case found
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaseReport {
+    /// File name within the directory
+    pub name: String,
+    /// Every expectation that wasn't met, empty when the case passed
+    pub failures: Vec<String>,
+}
+
+impl CaseReport {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Per-case results of a conformance run, in file name order
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConformanceReport {
+    pub cases: Vec<CaseReport>,
+}
+
+impl ConformanceReport {
+    /// True when every case passed. A directory without cases fails, as it is more likely a
+    /// wrong path than an empty suite
+    pub fn passed(&self) -> bool {
+        !self.cases.is_empty() && self.cases.iter().all(CaseReport::passed)
+    }
+}
+
+/// Runs every `*.case.json` file in `dir`. A file that isn't a valid case fails as a case
+pub fn run_directory(dir: &Path) -> Result<ConformanceReport, RuleError> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_case = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().ends_with(CASE_SUFFIX));
+        if path.is_file() && is_case {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let cases = paths
+        .iter()
+        .map(|path| {
+            let name = path
+                .file_name()
+                .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+            let failures = match read_case(path) {
+                Ok(case) => run_case(&case),
+                Err(error) => vec![error],
+            };
+            CaseReport { name, failures }
+        })
+        .collect();
+    Ok(ConformanceReport { cases })
+}
+
+fn read_case(path: &Path) -> Result<ConformanceCase, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&text).map_err(|e| format!("not a conformance case: {}", e))
+}
+
+/// Parses the case's rules and evaluates each payload, returning every expectation that
+/// wasn't met
+pub fn run_case(case: &ConformanceCase) -> Vec<String> {
+    let mut failures = Vec::new();
+
+    let rule_set = match (parse_rule_set(&case.rules), &case.parse_error) {
+        (Ok(_), Some(expected)) => {
+            failures.push(format!(
+                "expected a parse error containing '{}', but the rules parsed",
+                expected
+            ));
+            return failures;
+        }
+        (Err(error), Some(expected)) => {
+            let message = error.to_string();
+            if !message.contains(expected.as_str()) {
+                failures.push(format!(
+                    "expected a parse error containing '{}', got: {}",
+                    expected, message
+                ));
+            }
+            return failures;
+        }
+        (Err(error), None) => {
+            failures.push(format!("failed to parse: {}", error));
+            return failures;
+        }
+        (Ok(rule_set), None) => rule_set,
+    };
+
+    for (index, expected) in case.evaluations.iter().enumerate() {
+        let name = match &expected.description {
+            Some(description) => format!("evaluation {} ({})", index + 1, description),
+            None => format!("evaluation {}", index + 1),
+        };
+        let outcome = evaluate(
+            &rule_set,
+            &expected.data,
+            &expected.options.to_evaluation_options(),
+        );
+
+        match (&outcome.error, &expected.error) {
+            (None, Some(text)) => failures.push(format!(
+                "{}: expected an error containing '{}', got none",
+                name, text
+            )),
+            (Some(error), Some(text)) if !error.to_string().contains(text.as_str()) => failures
+                .push(format!(
+                    "{}: expected an error containing '{}', got: {}",
+                    name, text, error
+                )),
+            (Some(error), None) => failures.push(format!("{}: unexpected error: {}", name, error)),
+            _ => {}
+        }
+
+        if let Some(result) = expected.result {
+            if outcome.result != result {
+                failures.push(format!(
+                    "{}: expected result {}, got {}",
+                    name, result, outcome.result
+                ));
+            }
+        }
+        for (outcome_name, result) in &expected.results {
+            match outcome.outcomes.get(outcome_name) {
+                Some(actual) if actual == result => {}
+                Some(actual) => failures.push(format!(
+                    "{}: expected '{}' to be {}, got {}",
+                    name, outcome_name, result, actual
+                )),
+                None => failures.push(format!(
+                    "{}: expected '{}' to be {}, but it wasn't evaluated",
+                    name, outcome_name, result
+                )),
+            }
+        }
+        for (label, result) in &expected.labels {
+            match outcome.labels.get(label) {
+                Some(actual) if actual == result => {}
+                Some(actual) => failures.push(format!(
+                    "{}: expected label '{}' to be {}, got {}",
+                    name, label, result, actual
+                )),
+                None => failures.push(format!(
+                    "{}: expected label '{}' to be {}, but it wasn't evaluated",
+                    name, label, result
+                )),
+            }
+        }
+    }
+
+    failures
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for case in &self.cases {
+            let status = if case.passed() { "PASS" } else { "FAIL" };
+            writeln!(f, "{} {}", status, case.name)?;
+            for failure in &case.failures {
+                writeln!(f, "  {}", failure)?;
+            }
+        }
+        let passed = self.cases.iter().filter(|case| case.passed()).count();
+        if self.cases.is_empty() {
+            writeln!(f, "No cases found")
+        } else {
+            writeln!(f, "{} of {} cases passed", passed, self.cases.len())
+        }
+    }
+}
@@ -0,0 +1,213 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::debug::{DebugAction, DebugHook, DebugView};
+    use crate::runner::error::RuleError;
+    use crate::runner::evaluator::evaluate;
+    use crate::runner::model::Rule;
+    use crate::runner::options::EvaluationOptions;
+    use crate::runner::parser::parse_rules;
+    use crate::runner::trace::ConditionTrace;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    const LICENCE: &str = "\
+A **driver** gets a driving licence
+  if the **driver** passes the age test
+  and the __theory score__ of the **driver** is greater than or equal to 43.
+
+A **driver** passes the age test
+  if the __age__ of the **driver** is greater than or equal to 17
+  or the __supervised__ of the **driver** is equal to true.";
+
+    /// Records every call and answers with the action scripted for the rule or condition
+    /// called about, `Continue` otherwise
+    #[derive(Debug, Default)]
+    struct ScriptedHook {
+        script: Vec<(String, DebugAction)>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl ScriptedHook {
+        fn with(script: &[(&str, DebugAction)]) -> Arc<Self> {
+            Arc::new(Self {
+                script: script
+                    .iter()
+                    .map(|(call, action)| (call.to_string(), *action))
+                    .collect(),
+                calls: Mutex::default(),
+            })
+        }
+
+        fn answer(&self, call: String) -> DebugAction {
+            let action = self
+                .script
+                .iter()
+                .find(|(scripted, _)| *scripted == call)
+                .map_or(DebugAction::Continue, |(_, action)| *action);
+            self.calls.lock().unwrap().push(call);
+            action
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl DebugHook for ScriptedHook {
+        fn on_rule_start(&self, rule: &Rule, view: &DebugView) -> DebugAction {
+            assert_eq!(view.call_path.last().map(String::as_str), Some(rule.name()));
+            assert!(view.conditions.is_empty());
+            self.answer(format!("start {}", rule.outcome))
+        }
+
+        fn on_condition_evaluated(
+            &self,
+            rule: &Rule,
+            condition: &ConditionTrace,
+            view: &DebugView,
+        ) -> DebugAction {
+            assert!(
+                condition.id().is_some(),
+                "conditions are traced with their id"
+            );
+            self.answer(format!(
+                "{} condition {} {}",
+                rule.outcome,
+                view.conditions.len() + 1,
+                condition.result()
+            ))
+        }
+    }
+
+    fn run(
+        hook: &Arc<ScriptedHook>,
+        data: serde_json::Value,
+    ) -> crate::runner::outcome::EvaluationOutcome {
+        let rule_set = parse_rules(LICENCE).unwrap();
+        let options = EvaluationOptions {
+            debug_hook: Some(hook.clone()),
+            ..EvaluationOptions::new()
+        };
+        evaluate(&rule_set, &data, &options)
+    }
+
+    #[test]
+    fn test_hook_is_called_as_rules_start_and_conditions_are_evaluated() {
+        let hook = ScriptedHook::with(&[]);
+        let outcome = run(
+            &hook,
+            json!({"driver": {"age": 16, "supervised": true, "theory score": 45}}),
+        );
+        assert!(outcome.result, "{:?}", outcome.error);
+        assert_eq!(
+            hook.calls(),
+            vec![
+                "start driving licence",
+                // The reference evaluates the rule it names before it is reported itself
+                "start age test",
+                "age test condition 1 false",
+                "age test condition 2 true",
+                "driving licence condition 1 true",
+                "driving licence condition 2 true",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skip_rule_leaves_the_rule_out() {
+        let data = json!({"driver": {"age": 30, "theory score": 45}});
+
+        // Skipped as it starts, the age test is left out of the licence like a tag-filtered
+        // rule, and has no outcome
+        let hook = ScriptedHook::with(&[("start age test", DebugAction::SkipRule)]);
+        let outcome = run(&hook, data.clone());
+        assert!(outcome.result);
+        assert!(!outcome.outcomes.contains_key("age test"));
+        let trace = outcome.trace.unwrap();
+        assert!(trace.execution[0].conditions[0].is_skipped());
+        assert!(trace.execution[1].skipped);
+        assert!(!hook.calls().iter().any(|call| call.starts_with("age test")));
+
+        // Skipped after its first condition, the rest isn't evaluated
+        let hook = ScriptedHook::with(&[("age test condition 1 true", DebugAction::SkipRule)]);
+        let outcome = run(&hook, data);
+        assert!(outcome.result);
+        assert_eq!(outcome.evaluations_used, 3);
+        let trace = outcome.trace.unwrap();
+        assert!(trace.execution[1].skipped);
+        assert!(!trace.execution[1].result);
+        assert_eq!(trace.execution[1].conditions.len(), 1);
+    }
+
+    #[test]
+    fn test_abort_stops_the_evaluation() {
+        let data = json!({"driver": {"age": 30, "theory score": 45}});
+        for call in ["start age test", "age test condition 1 true"] {
+            let hook = ScriptedHook::with(&[(call, DebugAction::Abort)]);
+            let outcome = run(&hook, data.clone());
+            assert!(
+                matches!(outcome.error, Some(RuleError::Cancelled)),
+                "{}",
+                call
+            );
+            assert_eq!(hook.calls().last().map(String::as_str), Some(call));
+        }
+    }
+
+    #[test]
+    fn test_view_shows_finished_rules_and_values_read() {
+        #[derive(Debug, Default)]
+        struct Snapshot(Mutex<Vec<(Vec<String>, Vec<String>)>>);
+
+        impl DebugHook for Snapshot {
+            fn on_condition_evaluated(
+                &self,
+                _: &Rule,
+                _: &ConditionTrace,
+                view: &DebugView,
+            ) -> DebugAction {
+                let finished = view
+                    .finished_rules()
+                    .iter()
+                    .map(|trace| format!("{} {}", trace.outcome.value, trace.result))
+                    .collect();
+                let values = view
+                    .resolved_values()
+                    .into_iter()
+                    .map(|(path, value)| format!("{} = {}", path, value))
+                    .collect();
+                self.0.lock().unwrap().push((finished, values));
+                DebugAction::Continue
+            }
+        }
+
+        let rule_set = parse_rules(LICENCE).unwrap();
+        let hook = Arc::new(Snapshot::default());
+        let options = EvaluationOptions {
+            debug_hook: Some(hook.clone()),
+            ..EvaluationOptions::new()
+        };
+        let data = json!({"driver": {"age": 16, "supervised": true, "theory score": 45}});
+        evaluate(&rule_set, &data, &options);
+
+        let snapshots = hook.0.lock().unwrap();
+        // The age test's second condition sees its first
+        assert_eq!(snapshots[1].0, Vec::<String>::new());
+        assert_eq!(snapshots[1].1, vec!["$.driver.age = 16"]);
+        // The licence's last sees the finished age test, and no values read before it in
+        // its own rule but the reference
+        assert_eq!(snapshots[3].0, vec!["the age test true"]);
+        assert!(snapshots[3].1.is_empty());
+    }
+
+    #[test]
+    fn test_options_without_a_hook_evaluate_as_before() {
+        let rule_set = parse_rules(LICENCE).unwrap();
+        let data = json!({"driver": {"age": 30, "theory score": 45}});
+        let plain = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        let hooked = run(&ScriptedHook::with(&[]), data);
+        assert_eq!(plain.result, hooked.result);
+        assert_eq!(plain.outcomes, hooked.outcomes);
+        assert_eq!(plain.evaluations_used, hooked.evaluations_used);
+    }
+}
@@ -0,0 +1,120 @@
+//! Stepping through an evaluation.
+//!
+//! `EvaluationOptions::debug_hook`, a `DebugHook`, is called as each rule starts (`on_rule_start`)
+//! and after each condition that isn't a group (`on_condition_evaluated`, with its trace), with a
+//! `DebugView` of the call path, the conditions evaluated before, the finished rules and the values
+//! read. It answers `DebugAction::Continue`, `SkipRule` (the rule is traced as skipped, like a
+//! tag-filtered one) or `Abort` (`RuleError::Cancelled`). Without a hook the evaluator only checks
+//! the `Option`. `policy debug <rules> <data> --break "passes the theory test"` stops before the
+//! rules and after the condition ids given (at every step without `--break`) and asks on the
+//! terminal what to do.
+
+mod lib;
+
+use crate::runner::evaluator::RuleMemo;
+use crate::runner::model::Rule;
+use crate::runner::trace::{ConditionTrace, ExpressionTrace, RuleTrace};
+use serde_json::Value;
+use std::fmt;
+
+/// What an evaluation does once a `DebugHook` callback returns
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DebugAction {
+    #[default]
+    Continue,
+    /// Leaves the rule out as the tag filters do: it is traced as skipped with the conditions
+    /// evaluated so far, has no outcome, and a reference to it is left out of its rule's
+    /// conditions
+    SkipRule,
+    /// Stops the evaluation with `RuleError::Cancelled`
+    Abort,
+}
+
+/// Stepping through an evaluation, as `policy debug` does. The evaluator only calls it when
+/// `EvaluationOptions::debug_hook` is set. A rule taken from the memo isn't evaluated again,
+/// so each rule is started at most once per evaluation
+pub trait DebugHook: fmt::Debug + Send + Sync {
+    /// Before the first condition of `rule`
+    fn on_rule_start(&self, rule: &Rule, view: &DebugView) -> DebugAction {
+        let _ = (rule, view);
+        DebugAction::Continue
+    }
+
+    /// After each condition of `rule` that evaluated without error, with its trace. The
+    /// conditions of a group are reported one by one and the group itself isn't
+    fn on_condition_evaluated(
+        &self,
+        rule: &Rule,
+        condition: &ConditionTrace,
+        view: &DebugView,
+    ) -> DebugAction {
+        let _ = (rule, condition, view);
+        DebugAction::Continue
+    }
+}
+
+/// How far an evaluation has got when a `DebugHook` is called
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct DebugView<'a> {
+    /// Names of the rules being evaluated, outermost first, ending with the current one
+    pub call_path: &'a [String],
+    /// Traces of the conditions evaluated before, in the current rule or the group being
+    /// evaluated in it
+    pub conditions: &'a [ConditionTrace],
+    memo: &'a RuleMemo,
+}
+
+#[allow(dead_code)]
+impl<'a> DebugView<'a> {
+    pub(crate) fn new(
+        call_path: &'a [String],
+        conditions: &'a [ConditionTrace],
+        memo: &'a RuleMemo,
+    ) -> Self {
+        Self {
+            call_path,
+            conditions,
+            memo,
+        }
+    }
+
+    /// Traces of the rules the evaluation has finished, in the order of the rule set
+    pub fn finished_rules(&self) -> Vec<&'a RuleTrace> {
+        let mut finished: Vec<_> = self.memo.iter().collect();
+        finished.sort_by_key(|(index, _)| **index);
+        finished.into_iter().map(|(_, (_, trace))| trace).collect()
+    }
+
+    /// The values `conditions` read from the data, by path, in the order they were read. A
+    /// property read on both sides of a comparison is listed with each, and a side doing
+    /// arithmetic lists each property it read
+    pub fn resolved_values(&self) -> Vec<(&'a str, &'a Value)> {
+        let mut values = Vec::new();
+        let operands = |values: &mut Vec<(&'a str, &'a Value)>, expression: &'a ExpressionTrace| {
+            for operand in expression.operands() {
+                if let Some(path) = &operand.path {
+                    values.push((path.as_str(), &operand.value));
+                }
+            }
+        };
+        for condition in self.conditions.iter().flat_map(ConditionTrace::leaves) {
+            if let ConditionTrace::Comparison(comparison) = condition {
+                match &comparison.left_expression {
+                    Some(expression) => operands(&mut values, expression),
+                    None => values.push((
+                        comparison.property.path.as_str(),
+                        &comparison.property.value,
+                    )),
+                }
+                match (&comparison.right_expression, &comparison.value.path) {
+                    (Some(expression), _) => operands(&mut values, expression),
+                    (None, Some(path)) => values.push((path.as_str(), comparison.expected_value())),
+                    (None, None) => {}
+                }
+            }
+        }
+        values
+    }
+}
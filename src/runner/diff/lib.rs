@@ -0,0 +1,120 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::diff::{diff_traces, Change, ConditionDiff};
+    use crate::runner::evaluator::evaluate;
+    use crate::runner::options::EvaluationOptions;
+    use crate::runner::parser::parse_rules;
+    use crate::runner::trace::RuleSetTrace;
+    use serde_json::{json, Value};
+
+    const RULES: &str = r#"
+    A **user** passes the check
+      if the __age__ of the **user** is greater than 18
+      and the **user** passes the membership.
+
+    A **user** passes the membership
+      if the __plan__ of the **user** is equal to "gold".
+    "#;
+
+    fn trace_for(rules: &str, data: Value) -> RuleSetTrace {
+        let rule_set = parse_rules(rules).unwrap();
+        evaluate(&rule_set, &data, &EvaluationOptions::new())
+            .trace
+            .unwrap()
+    }
+
+    #[test]
+    fn test_identical_traces_have_no_diff() {
+        let data = json!({"user": {"age": 30, "plan": "gold"}});
+        let diff = diff_traces(&trace_for(RULES, data.clone()), &trace_for(RULES, data));
+
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "No differences\n");
+    }
+
+    #[test]
+    fn test_result_flip_and_changed_value() {
+        let before = trace_for(RULES, json!({"user": {"age": 30, "plan": "gold"}}));
+        let after = trace_for(RULES, json!({"user": {"age": 16, "plan": "gold"}}));
+        let diff = diff_traces(&before, &after);
+
+        assert_eq!(diff.rules.len(), 1);
+        let rule = &diff.rules[0];
+        assert_eq!(rule.outcome, "the check");
+        assert_eq!(
+            rule.result,
+            Some(Change {
+                before: true,
+                after: false
+            })
+        );
+        assert_eq!(
+            rule.conditions,
+            vec![ConditionDiff::Changed {
+                index: 0,
+                path: "$.user.age".to_string(),
+                value: Some(Change {
+                    before: json!(30),
+                    after: json!(16)
+                }),
+                result: Some(Change {
+                    before: true,
+                    after: false
+                }),
+            }]
+        );
+
+        let text = diff.to_string();
+        assert!(text.contains("rule 'the check': true -> false"), "{}", text);
+        assert!(text.contains("condition 1 ($.user.age): 30 -> 16 [true -> false]"));
+    }
+
+    #[test]
+    fn test_conditions_and_rules_present_on_one_side() {
+        let before = trace_for(RULES, json!({"user": {"age": 30, "plan": "gold"}}));
+        let after = trace_for(
+            r#"
+            A **user** passes the check
+              if the __age__ of the **user** is greater than 18.
+            "#,
+            json!({"user": {"age": 30}}),
+        );
+        let diff = diff_traces(&before, &after);
+
+        assert_eq!(diff.only_in_before, vec!["the membership".to_string()]);
+        assert!(diff.only_in_after.is_empty());
+        assert_eq!(
+            diff.rules[0].conditions,
+            vec![ConditionDiff::OnlyInBefore {
+                index: 1,
+                path: "passes the membership".to_string()
+            }]
+        );
+        assert_eq!(diff.rules[0].result, None);
+    }
+
+    #[test]
+    fn test_diff_serializes_and_traces_round_trip() {
+        let before = trace_for(RULES, json!({"user": {"age": 30, "plan": "gold"}}));
+        let after = trace_for(RULES, json!({"user": {"age": 30, "plan": "silver"}}));
+
+        // Traces written to disk can be read back and compared
+        let reloaded: RuleSetTrace =
+            serde_json::from_value(serde_json::to_value(&before).unwrap()).unwrap();
+        assert!(diff_traces(&before, &reloaded).is_empty());
+
+        let diff = serde_json::to_value(diff_traces(&reloaded, &after)).unwrap();
+        let membership = diff["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|rule| rule["outcome"] == "the membership")
+            .unwrap();
+        assert_eq!(
+            membership["result"],
+            json!({"before": true, "after": false})
+        );
+        assert_eq!(membership["conditions"][0]["change"], "changed");
+        assert_eq!(membership["conditions"][0]["path"], "$.user.plan");
+    }
+}
@@ -0,0 +1,237 @@
+mod lib;
+
+use crate::runner::trace::{ConditionTrace, RuleSetTrace, RuleTrace};
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+/// Differences between two traces of the same policy, e.g. for two payloads or engine versions
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct TraceDiff {
+    /// Rules present in both traces that differ, in the order of the first trace
+    pub rules: Vec<RuleDiff>,
+    /// Outcomes of rules only evaluated in the first trace
+    pub only_in_before: Vec<String>,
+    /// Outcomes of rules only evaluated in the second trace
+    pub only_in_after: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RuleDiff {
+    pub outcome: String,
+    /// Set when the rule's result flipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Change<bool>>,
+    pub conditions: Vec<ConditionDiff>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Change<T> {
+    pub before: T,
+    pub after: T,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum ConditionDiff {
+    /// The condition was evaluated in both traces but its left value or result differs
+    Changed {
+        index: usize,
+        path: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<Change<Value>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        result: Option<Change<bool>>,
+    },
+    OnlyInBefore {
+        index: usize,
+        path: String,
+    },
+    OnlyInAfter {
+        index: usize,
+        path: String,
+    },
+}
+
+impl TraceDiff {
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.only_in_before.is_empty() && self.only_in_after.is_empty()
+    }
+}
+
+/// Compares two traces, aligning rules by outcome and conditions by index and path
+#[allow(dead_code)]
+pub fn diff_traces(before: &RuleSetTrace, after: &RuleSetTrace) -> TraceDiff {
+    let mut diff = TraceDiff::default();
+
+    for before_rule in &before.execution {
+        let outcome = &before_rule.outcome.value;
+        match find_rule(after, outcome) {
+            Some(after_rule) => {
+                if let Some(rule_diff) = diff_rule(before_rule, after_rule) {
+                    diff.rules.push(rule_diff);
+                }
+            }
+            None => diff.only_in_before.push(outcome.clone()),
+        }
+    }
+
+    for after_rule in &after.execution {
+        if find_rule(before, &after_rule.outcome.value).is_none() {
+            diff.only_in_after.push(after_rule.outcome.value.clone());
+        }
+    }
+
+    diff
+}
+
+fn find_rule<'a>(trace: &'a RuleSetTrace, outcome: &str) -> Option<&'a RuleTrace> {
+    trace
+        .execution
+        .iter()
+        .find(|rule| rule.outcome.value == outcome)
+}
+
+fn diff_rule(before: &RuleTrace, after: &RuleTrace) -> Option<RuleDiff> {
+    let mut conditions = Vec::new();
+    // Conditions in groups are compared one by one, as if written without the parentheses
+    let before_conditions: Vec<&ConditionTrace> = before.leaves().collect();
+    let after_conditions: Vec<&ConditionTrace> = after.leaves().collect();
+
+    for index in 0..before_conditions.len().max(after_conditions.len()) {
+        match (
+            before_conditions.get(index).copied(),
+            after_conditions.get(index).copied(),
+        ) {
+            (Some(old), Some(new)) if condition_path(old) == condition_path(new) => {
+                let value = change(condition_value(old), condition_value(new));
+                let result = change(old.result(), new.result());
+                if value.is_some() || result.is_some() {
+                    conditions.push(ConditionDiff::Changed {
+                        index,
+                        path: condition_path(old),
+                        value,
+                        result,
+                    });
+                }
+            }
+            // A different condition at the same index is reported as removed and added
+            (Some(old), Some(new)) => {
+                conditions.push(ConditionDiff::OnlyInBefore {
+                    index,
+                    path: condition_path(old),
+                });
+                conditions.push(ConditionDiff::OnlyInAfter {
+                    index,
+                    path: condition_path(new),
+                });
+            }
+            (Some(old), None) => conditions.push(ConditionDiff::OnlyInBefore {
+                index,
+                path: condition_path(old),
+            }),
+            (None, Some(new)) => conditions.push(ConditionDiff::OnlyInAfter {
+                index,
+                path: condition_path(new),
+            }),
+            (None, None) => {}
+        }
+    }
+
+    let result = change(before.result, after.result);
+    if result.is_none() && conditions.is_empty() {
+        return None;
+    }
+
+    Some(RuleDiff {
+        outcome: before.outcome.value.clone(),
+        result,
+        conditions,
+    })
+}
+
+fn change<T: PartialEq>(before: T, after: T) -> Option<Change<T>> {
+    if before == after {
+        None
+    } else {
+        Some(Change { before, after })
+    }
+}
+
+/// JSONPath for comparisons, the referenced rule name for rule references, `flag "name"`
+/// for flags and the id for groups
+fn condition_path(condition: &ConditionTrace) -> String {
+    match condition {
+        ConditionTrace::Comparison(comparison) => comparison.property.path.clone(),
+        ConditionTrace::RuleReference(reference) => reference.rule_name.clone(),
+        ConditionTrace::Flag(flag) => format!("flag \"{}\"", flag.flag),
+        ConditionTrace::Group(group) => group
+            .id
+            .as_ref()
+            .map_or_else(String::new, ToString::to_string),
+    }
+}
+
+fn condition_value(condition: &ConditionTrace) -> Value {
+    match condition {
+        ConditionTrace::Comparison(comparison) => comparison.property.value.clone(),
+        ConditionTrace::RuleReference(reference) => reference
+            .property_check
+            .as_ref()
+            .map_or(Value::Null, |check| check.property_value.clone()),
+        ConditionTrace::Flag(flag) => Value::Bool(flag.enabled),
+        ConditionTrace::Group(group) => Value::Bool(group.result),
+    }
+}
+
+impl fmt::Display for TraceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No differences");
+        }
+
+        for rule in &self.rules {
+            write!(f, "rule '{}'", rule.outcome)?;
+            match &rule.result {
+                Some(result) => writeln!(f, ": {} -> {}", result.before, result.after)?,
+                None => writeln!(f, ": result unchanged")?,
+            }
+
+            for condition in &rule.conditions {
+                match condition {
+                    ConditionDiff::Changed {
+                        index,
+                        path,
+                        value,
+                        result,
+                    } => {
+                        write!(f, "  condition {} ({})", index + 1, path)?;
+                        if let Some(value) = value {
+                            write!(f, ": {} -> {}", value.before, value.after)?;
+                        }
+                        if let Some(result) = result {
+                            write!(f, " [{} -> {}]", result.before, result.after)?;
+                        }
+                        writeln!(f)?;
+                    }
+                    ConditionDiff::OnlyInBefore { index, path } => {
+                        writeln!(f, "  condition {} ({}): only in before", index + 1, path)?
+                    }
+                    ConditionDiff::OnlyInAfter { index, path } => {
+                        writeln!(f, "  condition {} ({}): only in after", index + 1, path)?
+                    }
+                }
+            }
+        }
+
+        for outcome in &self.only_in_before {
+            writeln!(f, "rule '{}': only in before", outcome)?;
+        }
+        for outcome in &self.only_in_after {
+            writeln!(f, "rule '{}': only in after", outcome)?;
+        }
+
+        Ok(())
+    }
+}
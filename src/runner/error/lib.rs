@@ -1,13 +1,18 @@
 #[cfg(test)]
 mod tests {
-    use crate::runner::error::RuleError;
+    use crate::runner::error::{describe_call_path, DataRootError, DataRootProblem, RuleError};
+    use crate::runner::model::{ComparisonOperator, ConditionId, OutcomeType};
+    use crate::runner::plan::UnresolvedReference;
+    use crate::runner::schema::DisjointData;
+    use crate::runner::stats::LimitViolation;
+    use crate::runner::trace::UnexpectedObject;
     use serde_json;
     use std::io;
 
     #[test]
     fn test_parse_error_creation() {
-        let error = RuleError::ParseError("Invalid syntax".to_string());
-        assert!(matches!(error, RuleError::ParseError(_)));
+        let error = RuleError::parse_error("Invalid syntax".to_string());
+        assert!(matches!(error, RuleError::ParseError { .. }));
 
         let error_message = format!("{}", error);
         assert_eq!(error_message, "Parse error: Invalid syntax");
@@ -15,7 +20,7 @@ mod tests {
 
     #[test]
     fn test_parse_error_debug() {
-        let error = RuleError::ParseError("Missing semicolon".to_string());
+        let error = RuleError::parse_error("Missing semicolon".to_string());
         let debug_output = format!("{:?}", error);
         assert!(debug_output.contains("ParseError"));
         assert!(debug_output.contains("Missing semicolon"));
@@ -135,9 +140,9 @@ mod tests {
 
     #[test]
     fn test_error_equality() {
-        let error1 = RuleError::ParseError("test".to_string());
-        let error2 = RuleError::ParseError("test".to_string());
-        let error3 = RuleError::ParseError("different".to_string());
+        let error1 = RuleError::parse_error("test".to_string());
+        let error2 = RuleError::parse_error("test".to_string());
+        let error3 = RuleError::parse_error("different".to_string());
         let error4 = RuleError::EvaluationError("test".to_string());
 
         // Note: RuleError doesn't derive PartialEq, so we test display equality instead
@@ -166,7 +171,7 @@ mod tests {
         assert!(rule_error.source().is_some());
 
         // Test that string-based errors don't have a source
-        let parse_error = RuleError::ParseError("test".to_string());
+        let parse_error = RuleError::parse_error("test".to_string());
         assert!(parse_error.source().is_none());
     }
 
@@ -214,11 +219,96 @@ mod tests {
     #[test]
     fn test_all_error_variants_display() {
         let errors = vec![
-            RuleError::ParseError("parse issue".to_string()),
+            RuleError::parse_error("parse issue".to_string()),
+            RuleError::ParseErrors {
+                diagnostics: vec![],
+            },
             RuleError::EvaluationError("eval issue".to_string()),
             RuleError::TypeError("type issue".to_string()),
             RuleError::IoError(io::Error::new(io::ErrorKind::NotFound, "io issue")),
             RuleError::JsonError(serde_json::from_str::<serde_json::Value>("invalid").unwrap_err()),
+            RuleError::Cancelled,
+            RuleError::InfiniteLoop {
+                call_path: vec![
+                    "licence".to_string(),
+                    "age".to_string(),
+                    "licence".to_string(),
+                ],
+                max_frames: 10,
+            },
+            RuleError::MaxDepthExceeded {
+                call_path: vec!["licence".to_string(), "age".to_string()],
+                limit: 1,
+                max_frames: 10,
+            },
+            RuleError::BudgetExceeded {
+                evaluations: 100,
+                rule: "licence".to_string(),
+            },
+            RuleError::DataTooDeep {
+                path: "$.user.history".to_string(),
+                limit: 64,
+            },
+            RuleError::NonFiniteNumber {
+                path: "$.user.score".to_string(),
+            },
+            RuleError::DivisionByZero {
+                path: Some("$.order.item_count".to_string()),
+            },
+            RuleError::ListComparedWithScalar {
+                path: "$.user.scores".to_string(),
+                operator: ComparisonOperator::GreaterThan,
+            },
+            RuleError::UnexpectedObject {
+                path: "$.student.degree".to_string(),
+                object: UnexpectedObject {
+                    keys: vec!["completion_status".to_string(), "honours".to_string()],
+                    more_keys: 0,
+                    suggestion: Some("completion_status".to_string()),
+                },
+            },
+            RuleError::InvalidData { violations: vec![] },
+            RuleError::DisjointData(DisjointData {
+                expected: vec!["drivingTest".to_string(), "driver".to_string()],
+                found: vec!["order".to_string(), "customer".to_string()],
+            }),
+            RuleError::UnresolvedReferences {
+                references: vec![UnresolvedReference {
+                    rule: "licence".to_string(),
+                    id: ConditionId::from_ordinal("licence", 1),
+                    rule_name: "passes the eye tset".to_string(),
+                    position: None,
+                }],
+            },
+            RuleError::InvalidOutcome {
+                outcome: "tier".to_string(),
+                value: serde_json::Value::Bool(true),
+                declared: OutcomeType::OneOf {
+                    values: vec!["gold".into()],
+                },
+            },
+            RuleError::InvalidDataRoot(DataRootError {
+                root: "$.envelope.payload".to_string(),
+                problem: DataRootProblem::NotAnObject {
+                    found: "an array".to_string(),
+                },
+            }),
+            RuleError::ComplexityLimitExceeded(LimitViolation {
+                limit: "rules",
+                value: 2,
+                max: 1,
+            }),
+            RuleError::NoGlobalRule { candidates: vec![] },
+            RuleError::MultipleGlobalRules { candidates: vec![] },
+            RuleError::IncludeFailed {
+                name: "common/age-checks".to_string(),
+                line: 1,
+                reason: "no document named 'common/age-checks'".to_string(),
+            },
+            RuleError::InIncludedDocument {
+                document: "common/age-checks".to_string(),
+                error: Box::new(RuleError::parse_error("parse issue".to_string())),
+            },
         ];
 
         for error in errors {
@@ -232,13 +322,80 @@ mod tests {
             // Display should not contain "Error" at the start (since thiserror handles this)
             // but should contain the error type description
             match error {
-                RuleError::ParseError(_) => assert!(display_str.starts_with("Parse error:")),
+                RuleError::ParseError { .. } => assert!(display_str.starts_with("Parse error:")),
+                RuleError::ParseErrors { .. } => assert!(display_str.starts_with("Parse errors:")),
                 RuleError::EvaluationError(_) => {
                     assert!(display_str.starts_with("Evaluation error:"))
                 }
                 RuleError::TypeError(_) => assert!(display_str.starts_with("Type error:")),
                 RuleError::IoError(_) => assert!(display_str.starts_with("IO error:")),
                 RuleError::JsonError(_) => assert!(display_str.starts_with("JSON error:")),
+                RuleError::Cancelled => assert_eq!(display_str, "Evaluation cancelled"),
+                RuleError::InfiniteLoop { .. } => assert_eq!(
+                    display_str,
+                    "Infinite loop detected in rule evaluation: licence -> age -> licence"
+                ),
+                RuleError::MaxDepthExceeded { .. } => assert_eq!(
+                    display_str,
+                    "Rule references nest deeper than the limit of 1: licence -> age"
+                ),
+                RuleError::BudgetExceeded { .. } => assert_eq!(
+                    display_str,
+                    "Evaluation budget exceeded: the limit of 100 condition evaluations was reached while evaluating 'licence'"
+                ),
+                RuleError::DataTooDeep { .. } => assert_eq!(
+                    display_str,
+                    "$.user.history is nested deeper than the limit of 64 levels"
+                ),
+                RuleError::NonFiniteNumber { .. } => {
+                    assert_eq!(display_str, "$.user.score is not a finite number")
+                }
+                RuleError::DivisionByZero { .. } => {
+                    assert_eq!(display_str, "Division by zero: $.order.item_count is 0")
+                }
+                RuleError::ListComparedWithScalar { .. } => assert_eq!(
+                    display_str,
+                    "$.user.scores is a list, but 'is greater than' compares single values"
+                ),
+                RuleError::UnexpectedObject { .. } => assert_eq!(
+                    display_str,
+                    "$.student.degree is an object with the keys completion_status, honours, not a single value; did you mean $.student.degree.completion_status?"
+                ),
+                RuleError::InvalidData { .. } => assert!(display_str.starts_with("Invalid data:")),
+                RuleError::DisjointData(_) => assert_eq!(
+                    display_str,
+                    "No selectors matched: expected one of [drivingTest, driver]; data contains [order, customer]"
+                ),
+                RuleError::UnresolvedReferences { .. } => assert_eq!(
+                    display_str,
+                    "Unresolved rule references: 'passes the eye tset' in 'licence'"
+                ),
+                RuleError::InvalidOutcome { .. } => assert_eq!(
+                    display_str,
+                    "Outcome 'tier' is true, but it is declared as one of [\"gold\"]"
+                ),
+                RuleError::InvalidDataRoot(_) => assert_eq!(
+                    display_str,
+                    "Invalid data root '$.envelope.payload': found an array where an object was expected"
+                ),
+                RuleError::ComplexityLimitExceeded(_) => assert_eq!(
+                    display_str,
+                    "Policy too complex: rules is 2, above the limit of 1"
+                ),
+                RuleError::NoGlobalRule { .. } => {
+                    assert!(display_str.starts_with("No global rule found"))
+                }
+                RuleError::MultipleGlobalRules { .. } => {
+                    assert!(display_str.starts_with("Multiple global rules found"))
+                }
+                RuleError::IncludeFailed { .. } => assert_eq!(
+                    display_str,
+                    "Can't include 'common/age-checks' on line 1: no document named 'common/age-checks'"
+                ),
+                RuleError::InIncludedDocument { .. } => assert_eq!(
+                    display_str,
+                    "In included 'common/age-checks': Parse error: parse issue"
+                ),
             }
         }
     }
@@ -252,4 +409,32 @@ mod tests {
         assert_send::<RuleError>();
         assert_sync::<RuleError>();
     }
+
+    #[test]
+    fn test_long_call_paths_are_cut_in_the_middle() {
+        let path: Vec<String> = (1..=50).map(|n| format!("rule {}", n)).collect();
+
+        assert_eq!(
+            describe_call_path(&path, 4),
+            "rule 1 -> rule 2 -> … 46 more frames … -> rule 49 -> rule 50"
+        );
+        assert_eq!(
+            describe_call_path(&path[..4], 3),
+            "rule 1 -> rule 2 -> … 1 more frame … -> rule 4"
+        );
+        assert_eq!(
+            describe_call_path(&path[..4], 4),
+            "rule 1 -> rule 2 -> rule 3 -> rule 4"
+        );
+
+        let error = RuleError::InfiniteLoop {
+            call_path: path.clone(),
+            max_frames: 4,
+        };
+        assert_eq!(error.call_path(), Some(&path[..]));
+        assert!(error
+            .to_string()
+            .ends_with("… 46 more frames … -> rule 49 -> rule 50"));
+        assert_eq!(RuleError::Cancelled.call_path(), None);
+    }
 }
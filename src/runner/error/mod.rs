@@ -1,12 +1,41 @@
+//! Errors of parsing and evaluating policies.
+//!
+//! A property holding an object where the operator compares single values, usually a path one
+//! property short (`the __degree__ of ...` for `degree.completion_status`), fails with
+//! `RuleError::UnexpectedObject`, answered with 422. The error names the object's first keys
+//! (`trace::MAX_LISTED_KEYS`) and suggests the key whose value is of the literal's kind, preferring
+//! one that would hold the comparison: `did you mean
+//! $.transcripts.undergraduate.degree.completion_status?`. With `"lenient": true` the comparison is
+//! false instead, with a warning whose `code` is `unexpected_object`. Either way the trace shows
+//! the object typed `object`, and `evaluation_details.unexpected_object` holds the keys and
+//! suggestion. Emptiness checks still judge the object itself.
+
 mod lib;
 
-use crate::runner::trace::{ConditionTrace, RuleSetTrace, RuleTrace};
+use crate::runner::model::{ComparisonOperator, OutcomeType, SourcePosition};
+use crate::runner::plan::UnresolvedReference;
+use crate::runner::schema::{DataViolation, DisjointData};
+use crate::runner::stats::LimitViolation;
+use crate::runner::trace::{ConditionTrace, RuleSetTrace, RuleTrace, UnexpectedObject};
+use crate::runner::utils::rebase_json_path;
+use crate::runner::validator::Diagnostic;
+use serde::Serialize;
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum RuleError {
-    #[error("Parse error: {0}")]
-    ParseError(String),
+    /// A document that doesn't parse. `detail` locates it when the grammar rejected the
+    /// text, rather than a rule that parsed being invalid. Boxed, as every evaluation frame
+    /// holds a `RuleError`'s worth of stack
+    #[error("Parse error: {message}")]
+    ParseError {
+        message: String,
+        detail: Option<Box<ParseErrorDetail>>,
+    },
+
+    #[error("Parse errors: {}", describe_diagnostics(.diagnostics))]
+    ParseErrors { diagnostics: Vec<Diagnostic> },
 
     #[error("Evaluation error: {0}")]
     EvaluationError(String),
@@ -19,6 +48,334 @@ pub enum RuleError {
 
     #[error("JSON error: {0}")]
     JsonError(#[from] serde_json::Error),
+
+    #[error("Evaluation cancelled")]
+    Cancelled,
+
+    /// A rule was reached again through its own references. `call_path` holds the rules
+    /// being evaluated, outermost first, ending with the one reached again; the message shows
+    /// at most `max_frames` of them
+    #[error(
+        "Infinite loop detected in rule evaluation: {}",
+        describe_call_path(.call_path, *.max_frames)
+    )]
+    InfiniteLoop {
+        call_path: Vec<String>,
+        max_frames: usize,
+    },
+
+    /// Rule references nested past `EvaluationOptions::max_reference_depth`. `call_path`
+    /// holds the rules being evaluated, outermost first, ending with the one that went over
+    #[error(
+        "Rule references nest deeper than the limit of {limit}: {}",
+        describe_call_path(.call_path, *.max_frames)
+    )]
+    MaxDepthExceeded {
+        call_path: Vec<String>,
+        limit: usize,
+        max_frames: usize,
+    },
+
+    /// The evaluation reached `EvaluationOptions::max_condition_evaluations`. `rule` is the
+    /// rule whose next condition would have gone over
+    #[error(
+        "Evaluation budget exceeded: the limit of {evaluations} condition evaluations was reached while evaluating '{rule}'"
+    )]
+    BudgetExceeded { evaluations: usize, rule: String },
+
+    /// A property path read past `EvaluationOptions::max_data_depth`. `path` is as far as
+    /// the read got
+    #[error("{path} is nested deeper than the limit of {limit} levels")]
+    DataTooDeep { path: String, limit: usize },
+
+    /// A compared number is NaN or infinite, e.g. a literal too large for a float or a
+    /// duration that overflows when converted to seconds
+    #[error("{path} is not a finite number")]
+    NonFiniteNumber { path: String },
+
+    /// An arithmetic step divided by zero: by the property at `path`, or by a literal 0
+    #[error(
+        "Division by zero: {}",
+        .path.as_ref().map_or("divided by a literal 0".to_string(), |path| format!("{} is 0", path))
+    )]
+    DivisionByZero { path: Option<String> },
+
+    #[error("{path} is a list, but '{operator}' compares single values")]
+    ListComparedWithScalar {
+        path: String,
+        operator: ComparisonOperator,
+    },
+
+    /// A compared property held an object where the operator compares single values. Lenient
+    /// mode makes the comparison false with a warning instead
+    #[error("{}{}", .object.describe(.path), .object.hint(.path))]
+    UnexpectedObject {
+        path: String,
+        object: UnexpectedObject,
+    },
+
+    #[error("Invalid data: {}", describe_violations(.violations))]
+    InvalidData { violations: Vec<DataViolation> },
+
+    /// With `reject_disjoint_data`, a payload holding none of the selectors the rules read
+    #[error("{0}")]
+    DisjointData(DisjointData),
+
+    /// With `strict_references`, rule references that resolve to no rule, in the order
+    /// written
+    #[error(
+        "Unresolved rule references: {}",
+        describe_unresolved_references(.references)
+    )]
+    UnresolvedReferences {
+        references: Vec<UnresolvedReference>,
+    },
+
+    /// With `validate_input`, an evaluated outcome the rule set's `Outcome ... is ...`
+    /// declaration doesn't admit
+    #[error("Outcome '{outcome}' is {value}, but it is declared as {declared}")]
+    InvalidOutcome {
+        outcome: String,
+        value: serde_json::Value,
+        declared: OutcomeType,
+    },
+
+    /// The `data_root` option doesn't point at an object of the data
+    #[error("Invalid data root '{}': {}", .0.root, .0.problem)]
+    InvalidDataRoot(DataRootError),
+
+    #[error(
+        "Policy too complex: {} is {}, above the limit of {}",
+        .0.limit, .0.value, .0.max
+    )]
+    ComplexityLimitExceeded(LimitViolation),
+
+    /// An `Include "name".` that couldn't be resolved: there is no loader, the loader
+    /// failed, or the include closes a cycle or nests too deep
+    #[error("Can't include '{name}' on line {line}: {reason}")]
+    IncludeFailed {
+        name: String,
+        line: usize,
+        reason: String,
+    },
+
+    /// An included document failed to parse or to resolve its own includes
+    #[error("In included '{document}': {error}")]
+    InIncludedDocument {
+        document: String,
+        error: Box<RuleError>,
+    },
+
+    #[error("No global rule found: every rule is referenced by another rule")]
+    NoGlobalRule { candidates: Vec<OutcomeCandidate> },
+
+    #[error(
+        "Multiple global rules found: {}. There should be only one golden rule that is not referenced by other rules.",
+        describe_candidates(.candidates)
+    )]
+    MultipleGlobalRules { candidates: Vec<OutcomeCandidate> },
+}
+
+impl RuleError {
+    /// Candidate entry rules carried by the global rule errors
+    pub fn global_rule_candidates(&self) -> Option<&[OutcomeCandidate]> {
+        match self {
+            RuleError::NoGlobalRule { candidates }
+            | RuleError::MultipleGlobalRules { candidates } => Some(candidates),
+            _ => None,
+        }
+    }
+
+    /// The rules being evaluated when a reference loop or the reference depth limit stopped
+    /// the evaluation, outermost first
+    pub fn call_path(&self) -> Option<&[String]> {
+        match self {
+            RuleError::InfiniteLoop { call_path, .. }
+            | RuleError::MaxDepthExceeded { call_path, .. } => Some(call_path),
+            _ => None,
+        }
+    }
+
+    /// Rewrites the data paths the error names as paths from the document a data root was
+    /// resolved in, see `EvaluationOptions::data_root`
+    pub fn rebase_paths(&mut self, root: &str) {
+        match self {
+            RuleError::NonFiniteNumber { path }
+            | RuleError::DataTooDeep { path, .. }
+            | RuleError::UnexpectedObject { path, .. }
+            | RuleError::ListComparedWithScalar { path, .. } => {
+                *path = rebase_json_path(root, path);
+            }
+            RuleError::DivisionByZero { path: Some(path) } => {
+                *path = rebase_json_path(root, path);
+            }
+            RuleError::InvalidData { violations } => {
+                for violation in violations {
+                    violation.path = rebase_json_path(root, &violation.path);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// A `ParseError` without a position, for a rule that parsed but isn't valid
+    pub fn parse_error(message: impl Into<String>) -> Self {
+        RuleError::ParseError {
+            message: message.into(),
+            detail: None,
+        }
+    }
+
+    /// Every broken statement found by a parse that failed in more than one place
+    pub fn parse_diagnostics(&self) -> Option<&[Diagnostic]> {
+        match self {
+            RuleError::ParseErrors { diagnostics } => Some(diagnostics),
+            _ => None,
+        }
+    }
+
+    /// Where the grammar rejected the document, for a `ParseError` it did
+    pub fn parse_detail(&self) -> Option<&ParseErrorDetail> {
+        match self {
+            RuleError::ParseError { detail, .. } => detail.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Where the grammar rejected a document, and what it would have taken there
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ParseErrorDetail {
+    /// 1-based line of the document
+    pub line: usize,
+    /// 1-based column within the line, in characters
+    pub column: usize,
+    /// The line the error is on
+    pub snippet: String,
+    /// What the grammar would have accepted there, e.g. `comparison operator`
+    pub expected: Vec<String>,
+    /// The word found there instead, `end of input` past the last one
+    pub found: String,
+}
+
+/// Why the data under a `data_root` can't be evaluated
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DataRootError {
+    /// The root as the request gave it
+    pub root: String,
+    #[serde(flatten)]
+    pub problem: DataRootProblem,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "problem", rename_all = "snake_case")]
+pub enum DataRootProblem {
+    /// Not a JSONPath of keys and indices, such as `$.envelope.payload`
+    InvalidPath,
+    /// The path doesn't resolve in the data
+    NotFound,
+    /// The path resolves to something selectors can't look keys up in
+    NotAnObject { found: String },
+}
+
+impl fmt::Display for DataRootProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataRootProblem::InvalidPath => {
+                write!(
+                    f,
+                    "not a JSONPath of keys and indices like $.envelope.payload"
+                )
+            }
+            DataRootProblem::NotFound => write!(f, "nothing in the data at this path"),
+            DataRootProblem::NotAnObject { found } => {
+                write!(f, "found {} where an object was expected", found)
+            }
+        }
+    }
+}
+
+/// A rule that could serve as the entry point of a rule set
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct OutcomeCandidate {
+    pub outcome: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<SourcePosition>,
+}
+
+impl OutcomeCandidate {
+    pub fn from_rule(rule: &crate::runner::model::Rule) -> Self {
+        Self {
+            outcome: rule.outcome.clone(),
+            label: rule.label.clone(),
+            position: rule.position.clone(),
+        }
+    }
+}
+
+/// The rules of a call path joined with arrows. A path longer than `max_frames` keeps its
+/// first and last frames, which show where it started and what it ran into, and says how
+/// many are left out between them
+pub fn describe_call_path(call_path: &[String], max_frames: usize) -> String {
+    if call_path.len() <= max_frames.max(2) {
+        return call_path.join(" -> ");
+    }
+    let tail = max_frames.max(2) / 2;
+    let head = max_frames.max(2) - tail;
+    let omitted = call_path.len() - head - tail;
+    format!(
+        "{} -> … {} more frame{} … -> {}",
+        call_path[..head].join(" -> "),
+        omitted,
+        if omitted == 1 { "" } else { "s" },
+        call_path[call_path.len() - tail..].join(" -> ")
+    )
+}
+
+fn describe_candidates(candidates: &[OutcomeCandidate]) -> String {
+    candidates
+        .iter()
+        .map(|c| match &c.label {
+            Some(label) => format!("'{}' ({})", label, c.outcome),
+            None => format!("'{}'", c.outcome),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_diagnostics(diagnostics: &[Diagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(|d| match &d.position {
+            Some(pos) => format!("line {}:{}: {}", pos.line, pos.start, d.message),
+            None => d.message.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn describe_unresolved_references(references: &[UnresolvedReference]) -> String {
+    references
+        .iter()
+        .map(|r| match &r.position {
+            Some(pos) => format!(
+                "'{}' in '{}' on line {}:{}",
+                r.rule_name, r.rule, pos.line, pos.start
+            ),
+            None => format!("'{}' in '{}'", r.rule_name, r.rule),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_violations(violations: &[DataViolation]) -> String {
+    violations
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
 }
 
 /// Enhanced evaluation result that includes traces even on failure
@@ -125,6 +482,8 @@ impl PartialRuleTrace {
             },
             conditions: self.conditions,
             result: self.result.unwrap_or(false),
+            skipped: false,
+            error: self.error,
         }
     }
 }
@@ -1,24 +1,40 @@
 #[cfg(test)]
 mod tests {
+    use crate::runner::debug::{DebugAction, DebugHook, DebugView};
     use crate::runner::error::RuleError;
     use crate::runner::evaluator::{
-        compare_contains, compare_dates_earlier, compare_dates_later, compare_equal,
-        compare_in_list, compare_is_empty, compare_is_not_empty, compare_not_equal,
-        compare_not_in_list, compare_numbers_gt, compare_numbers_gte, compare_numbers_lt,
-        compare_numbers_lte, compare_older_than, compare_younger_than, convert_json_to_rule_value,
-        evaluate_comparison_condition, evaluate_rule, evaluate_rule_set,
-        evaluate_rule_set_with_trace, evaluate_rule_with_trace, extract_value_from_json,
-        find_effective_selector,
+        aggregate_value, compare_contains, compare_dates_earlier, compare_dates_later,
+        compare_equal, compare_in_list, compare_is_empty, compare_is_not_empty,
+        compare_json_is_empty, compare_not_equal, compare_not_in_list, compare_numbers_gt,
+        compare_numbers_gte, compare_numbers_lt, compare_numbers_lte, compare_older_than,
+        compare_younger_than, convert_json_to_rule_value, count_value, evaluate,
+        evaluate_comparison_condition, evaluate_condition, evaluate_rule, evaluate_rule_with_trace,
+        evaluate_with_memo, extract_value_from_json, find_effective_selector, hash_bucket,
+        AggregateOperation, CountOperation, DataLookup, EvaluationContext, RuleMemo,
     };
     use crate::runner::model::{
-        ComparisonCondition, ComparisonOperator, Condition, ConditionGroup, ConditionOperator,
-        Duration, PositionedValue, PropertyChainElement, PropertyPath, Rule,
+        ComparisonCondition, ComparisonOperator, Condition, ConditionGroup, ConditionId,
+        ConditionOperator, Duration, PositionedValue, PropertyChainElement, PropertyPath, Rule,
         RuleReferenceCondition, RuleSet, RuleValue, TimeUnit,
     };
-    use crate::runner::parser::parse_rules;
+    use crate::runner::options::{EvaluationOptions, FlagProvider, TraceVerbosity};
+    use crate::runner::parser::{parse_rule_set, parse_rules};
+    use crate::runner::trace::ConditionTrace;
     use chrono::NaiveDate;
-    use serde_json::json;
+    use indexmap::IndexMap;
+    use serde_json::{json, Value};
     use std::collections::{HashMap, HashSet};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Barrier};
+
+    /// Each rule's result with the default options, failing the test on an evaluation error
+    fn evaluate_outcomes(rule_set: &RuleSet, data: &Value) -> IndexMap<String, bool> {
+        let outcome = evaluate(rule_set, data, &EvaluationOptions::default());
+        if let Some(error) = outcome.error {
+            panic!("evaluation failed: {}", error);
+        }
+        outcome.outcomes
+    }
 
     // Basic comparison tests (existing)
     #[test]
@@ -50,9 +66,9 @@ mod tests {
         let str2 = RuleValue::String("hello".to_string());
         let str3 = RuleValue::String("world".to_string());
 
-        assert_eq!(compare_equal(&str1, &str2).unwrap(), true);
-        assert_eq!(compare_equal(&str1, &str3).unwrap(), false);
-        assert_eq!(compare_not_equal(&str1, &str3).unwrap(), true);
+        assert_eq!(compare_equal(&str1, &str2, false).unwrap(), true);
+        assert_eq!(compare_equal(&str1, &str3, false).unwrap(), false);
+        assert_eq!(compare_not_equal(&str1, &str3, false).unwrap(), true);
     }
 
     #[test]
@@ -63,12 +79,12 @@ mod tests {
             RuleValue::String("banana".to_string()),
         ]);
 
-        assert_eq!(compare_in_list(&value, &list).unwrap(), true);
-        assert_eq!(compare_contains(&list, &value).unwrap(), true);
+        assert_eq!(compare_in_list(&value, &list, false).unwrap(), true);
+        assert_eq!(compare_contains(&list, &value, false).unwrap(), true);
 
         let missing = RuleValue::String("orange".to_string());
-        assert_eq!(compare_in_list(&missing, &list).unwrap(), false);
-        assert_eq!(compare_not_in_list(&missing, &list).unwrap(), true);
+        assert_eq!(compare_in_list(&missing, &list, false).unwrap(), false);
+        assert_eq!(compare_not_in_list(&missing, &list, false).unwrap(), true);
     }
 
     #[test]
@@ -90,26 +106,29 @@ mod tests {
 
         // Test older than
         assert_eq!(
-            compare_older_than(&old_date, &sixty_five_years).unwrap(),
+            compare_older_than(&old_date, &sixty_five_years, today).unwrap(),
             true
         ); // 75 > 65
         assert_eq!(
-            compare_older_than(&teen_date, &eighteen_years).unwrap(),
+            compare_older_than(&teen_date, &eighteen_years, today).unwrap(),
             true
         ); // 19 > 18
-        assert_eq!(compare_older_than(&young_date, &five_years).unwrap(), false); // 3 < 5
+        assert_eq!(
+            compare_older_than(&young_date, &five_years, today).unwrap(),
+            false
+        ); // 3 < 5
 
         // Test younger than
         assert_eq!(
-            compare_younger_than(&young_date, &five_years).unwrap(),
+            compare_younger_than(&young_date, &five_years, today).unwrap(),
             true
         ); // 3 < 5
         assert_eq!(
-            compare_younger_than(&teen_date, &eighteen_years).unwrap(),
+            compare_younger_than(&teen_date, &eighteen_years, today).unwrap(),
             false
         ); // 19 > 18
         assert_eq!(
-            compare_younger_than(&old_date, &sixty_five_years).unwrap(),
+            compare_younger_than(&old_date, &sixty_five_years, today).unwrap(),
             false
         ); // 75 > 65
 
@@ -117,9 +136,12 @@ mod tests {
         let date_str = RuleValue::String("2010-06-15".to_string());
         let twelve_years = RuleValue::Duration(Duration::new(12.0, TimeUnit::Years));
 
-        assert_eq!(compare_older_than(&date_str, &twelve_years).unwrap(), true);
         assert_eq!(
-            compare_younger_than(&date_str, &eighteen_years).unwrap(),
+            compare_older_than(&date_str, &twelve_years, today).unwrap(),
+            true
+        );
+        assert_eq!(
+            compare_younger_than(&date_str, &eighteen_years, today).unwrap(),
             true
         );
     }
@@ -167,21 +189,22 @@ mod tests {
             "User": {"name": "Jane"},
             "userProfile": {"age": 30}
         });
+        let mut lookup = DataLookup::new(&EvaluationOptions::new());
 
         // Test exact match
-        let result = find_effective_selector("user", &json).unwrap();
-        assert_eq!(result, Some("user".to_string()));
+        let result = find_effective_selector("user", &json, &mut lookup).unwrap();
+        assert_eq!(result, Some("user"));
 
         // Test case-insensitive match - should return actual key from JSON
-        let result = find_effective_selector("USER", &json).unwrap();
-        assert_eq!(result, Some("User".to_string()));
+        let result = find_effective_selector("USER", &json, &mut lookup).unwrap();
+        assert_eq!(result, Some("User"));
 
         // Test camelCase transformation
-        let result = find_effective_selector("user profile", &json).unwrap();
-        assert_eq!(result, Some("userProfile".to_string()));
+        let result = find_effective_selector("user profile", &json, &mut lookup).unwrap();
+        assert_eq!(result, Some("userProfile"));
 
         // Test non-existent selector
-        let result = find_effective_selector("nonexistent", &json).unwrap();
+        let result = find_effective_selector("nonexistent", &json, &mut lookup).unwrap();
         assert_eq!(result, None);
     }
 
@@ -240,6 +263,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -263,6 +290,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -280,9 +311,13 @@ mod tests {
         });
 
         let rule = Rule {
+            index: None,
             label: Some("test rule".to_string()),
             selector: "user".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::Comparison(ComparisonCondition {
                     selector: PositionedValue {
@@ -300,11 +335,18 @@ mod tests {
                     },
                     left_property_path: None,
                     right_property_path: None,
+                    date_offset: None,
+                    today: false,
+                    left_expression: None,
+                    right_expression: None,
                     property_chain: None,
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "adult".to_string(),
+            outcome_display: "adult".to_string(),
             position: None,
         };
 
@@ -319,6 +361,11 @@ mod tests {
             label_map,
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
         let (result, _trace) = evaluate_rule(
             &rule,
@@ -342,9 +389,13 @@ mod tests {
         });
 
         let rule = Rule {
+            index: None,
             label: Some("adult active user".to_string()),
             selector: "user".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![
                 ConditionGroup {
                     condition: Condition::Comparison(ComparisonCondition {
@@ -363,9 +414,15 @@ mod tests {
                         },
                         left_property_path: None,
                         right_property_path: None,
+                        date_offset: None,
+                        today: false,
+                        left_expression: None,
+                        right_expression: None,
                         property_chain: None,
                     }),
                     operator: None, // Remove the operator from the first condition
+                    id: ConditionId::from_ordinal("test", 1),
+                    negated: false,
                 },
                 ConditionGroup {
                     condition: Condition::Comparison(ComparisonCondition {
@@ -384,12 +441,19 @@ mod tests {
                         },
                         left_property_path: None,
                         right_property_path: None,
+                        date_offset: None,
+                        today: false,
+                        left_expression: None,
+                        right_expression: None,
                         property_chain: None,
                     }),
                     operator: Some(ConditionOperator::And), // Move the operator to the second condition
+                    id: ConditionId::from_ordinal("test", 2),
+                    negated: false,
                 },
             ],
             outcome: "valid_user".to_string(),
+            outcome_display: "valid_user".to_string(),
             position: None,
         };
 
@@ -405,6 +469,11 @@ mod tests {
             label_map,
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
         let (result, _trace) = evaluate_rule(
             &rule,
@@ -427,9 +496,13 @@ mod tests {
         });
 
         let rule = Rule {
+            index: None,
             label: Some("eligible user".to_string()),
             selector: "user".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![
                 ConditionGroup {
                     condition: Condition::Comparison(ComparisonCondition {
@@ -448,9 +521,15 @@ mod tests {
                         },
                         left_property_path: None,
                         right_property_path: None,
+                        date_offset: None,
+                        today: false,
+                        left_expression: None,
+                        right_expression: None,
                         property_chain: None,
                     }),
                     operator: None,
+                    id: ConditionId::from_ordinal("test", 1),
+                    negated: false,
                 },
                 ConditionGroup {
                     condition: Condition::Comparison(ComparisonCondition {
@@ -469,12 +548,19 @@ mod tests {
                         },
                         left_property_path: None,
                         right_property_path: None,
+                        date_offset: None,
+                        today: false,
+                        left_expression: None,
+                        right_expression: None,
                         property_chain: None,
                     }),
                     operator: Some(ConditionOperator::Or),
+                    id: ConditionId::from_ordinal("test", 2),
+                    negated: false,
                 },
             ],
             outcome: "eligible".to_string(),
+            outcome_display: "eligible".to_string(),
             position: None,
         };
 
@@ -490,6 +576,11 @@ mod tests {
             label_map,
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
         let (result, _trace) = evaluate_rule(
             &rule,
@@ -511,9 +602,13 @@ mod tests {
 
         // Create referenced rule
         let age_rule = Rule {
+            index: Some(0),
             label: Some("age check".to_string()),
             selector: "user".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::Comparison(ComparisonCondition {
                     selector: PositionedValue {
@@ -531,19 +626,30 @@ mod tests {
                     },
                     left_property_path: None,
                     right_property_path: None,
+                    date_offset: None,
+                    today: false,
+                    left_expression: None,
+                    right_expression: None,
                     property_chain: None,
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "adult".to_string(),
+            outcome_display: "adult".to_string(),
             position: None,
         };
 
         // Create main rule that references the age rule
         let main_rule = Rule {
+            index: Some(1),
             label: Some("main rule".to_string()),
             selector: "global".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::RuleReference(RuleReferenceCondition {
                     selector: PositionedValue {
@@ -556,8 +662,11 @@ mod tests {
                     },
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "global".to_string(),
+            outcome_display: "global".to_string(),
             position: None,
         };
 
@@ -578,6 +687,11 @@ mod tests {
             label_map,
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
 
         let (result, _trace) = evaluate_rule(
@@ -598,9 +712,13 @@ mod tests {
         });
 
         let age_rule = Rule {
+            index: Some(0),
             label: Some("age check".to_string()),
             selector: "user".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::Comparison(ComparisonCondition {
                     selector: PositionedValue {
@@ -618,18 +736,29 @@ mod tests {
                     },
                     left_property_path: None,
                     right_property_path: None,
+                    date_offset: None,
+                    today: false,
+                    left_expression: None,
+                    right_expression: None,
                     property_chain: None,
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "adult".to_string(),
+            outcome_display: "adult".to_string(),
             position: None,
         };
 
         let global_rule = Rule {
+            index: Some(1),
             label: Some("global rule".to_string()),
             selector: "global".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::RuleReference(RuleReferenceCondition {
                     selector: PositionedValue {
@@ -642,8 +771,11 @@ mod tests {
                     },
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "global".to_string(),
+            outcome_display: "global".to_string(),
             position: None,
         };
 
@@ -661,9 +793,14 @@ mod tests {
             label_map,
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json);
 
         assert_eq!(results.get("global"), Some(&true));
         assert_eq!(results.get("adult"), Some(&true));
@@ -693,11 +830,19 @@ mod tests {
             left_property_path: Some(PropertyPath {
                 selector: "user".to_string(),
                 properties: vec!["age".to_string()],
+                fan_out: Vec::new(),
+                elements: Vec::new(),
             }),
             right_property_path: Some(PropertyPath {
                 selector: "requirement".to_string(),
                 properties: vec!["minAge".to_string()],
+                fan_out: Vec::new(),
+                elements: Vec::new(),
             }),
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -733,6 +878,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: Some(vec![
                 PropertyChainElement::Property("profile".to_string()),
                 PropertyChainElement::Property("settings".to_string()),
@@ -753,8 +902,9 @@ mod tests {
         });
 
         // Test case-insensitive selector access
-        let result = find_effective_selector("user", &json).unwrap();
-        assert_eq!(result, Some("User".to_string()));
+        let mut lookup = DataLookup::new(&EvaluationOptions::new());
+        let result = find_effective_selector("user", &json, &mut lookup).unwrap();
+        assert_eq!(result, Some("User"));
 
         // Test case-insensitive property access
         let result = extract_value_from_json(&json, "User", "name").unwrap();
@@ -779,8 +929,8 @@ mod tests {
         let needle = RuleValue::String("World".to_string());
         let missing = RuleValue::String("Missing".to_string());
 
-        assert_eq!(compare_contains(&haystack, &needle).unwrap(), true);
-        assert_eq!(compare_contains(&haystack, &missing).unwrap(), false);
+        assert_eq!(compare_contains(&haystack, &needle, false).unwrap(), true);
+        assert_eq!(compare_contains(&haystack, &missing, false).unwrap(), false);
     }
 
     #[test]
@@ -796,14 +946,29 @@ mod tests {
         let bool_val = RuleValue::Boolean(true);
         let missing_val = RuleValue::String("orange".to_string());
 
-        assert_eq!(compare_contains(&mixed_list, &string_val).unwrap(), true);
-        assert_eq!(compare_contains(&mixed_list, &number_val).unwrap(), true);
-        assert_eq!(compare_contains(&mixed_list, &bool_val).unwrap(), true);
-        assert_eq!(compare_contains(&mixed_list, &missing_val).unwrap(), false);
+        assert_eq!(
+            compare_contains(&mixed_list, &string_val, false).unwrap(),
+            true
+        );
+        assert_eq!(
+            compare_contains(&mixed_list, &number_val, false).unwrap(),
+            true
+        );
+        assert_eq!(
+            compare_contains(&mixed_list, &bool_val, false).unwrap(),
+            true
+        );
+        assert_eq!(
+            compare_contains(&mixed_list, &missing_val, false).unwrap(),
+            false
+        );
 
-        assert_eq!(compare_in_list(&string_val, &mixed_list).unwrap(), true);
         assert_eq!(
-            compare_not_in_list(&missing_val, &mixed_list).unwrap(),
+            compare_in_list(&string_val, &mixed_list, false).unwrap(),
+            true
+        );
+        assert_eq!(
+            compare_not_in_list(&missing_val, &mixed_list, false).unwrap(),
             true
         );
     }
@@ -833,6 +998,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -856,6 +1025,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -874,7 +1047,7 @@ mod tests {
         assert!(result.is_err());
 
         // Should error when trying to compare incompatible types for equality
-        let result = compare_equal(&string_val, &number_val);
+        let result = compare_equal(&string_val, &number_val, false);
         assert!(result.is_err());
     }
 
@@ -891,9 +1064,13 @@ mod tests {
 
         // Rule 1: A **person** follows rule 1 if age >= 18 and follows rule 2
         let rule1 = Rule {
+            index: Some(0),
             label: None,
             selector: "person".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![
                 ConditionGroup {
                     condition: Condition::Comparison(ComparisonCondition {
@@ -912,9 +1089,15 @@ mod tests {
                         },
                         left_property_path: None,
                         right_property_path: None,
+                        date_offset: None,
+                        today: false,
+                        left_expression: None,
+                        right_expression: None,
                         property_chain: None,
                     }),
                     operator: None,
+                    id: ConditionId::from_ordinal("test", 1),
+                    negated: false,
                 },
                 ConditionGroup {
                     condition: Condition::RuleReference(RuleReferenceCondition {
@@ -928,17 +1111,24 @@ mod tests {
                         },
                     }),
                     operator: Some(ConditionOperator::And),
+                    id: ConditionId::from_ordinal("test", 2),
+                    negated: false,
                 },
             ],
             outcome: "rule 1".to_string(),
+            outcome_display: "rule 1".to_string(),
             position: None,
         };
 
         // Rule 2: A **person** follows rule 2 if driving_test_score >= 60 and follows rule 3
         let rule2 = Rule {
+            index: Some(1),
             label: None,
             selector: "person".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![
                 ConditionGroup {
                     condition: Condition::Comparison(ComparisonCondition {
@@ -957,9 +1147,15 @@ mod tests {
                         },
                         left_property_path: None,
                         right_property_path: None,
+                        date_offset: None,
+                        today: false,
+                        left_expression: None,
+                        right_expression: None,
                         property_chain: None,
                     }),
                     operator: None,
+                    id: ConditionId::from_ordinal("test", 1),
+                    negated: false,
                 },
                 ConditionGroup {
                     condition: Condition::RuleReference(RuleReferenceCondition {
@@ -973,17 +1169,24 @@ mod tests {
                         },
                     }),
                     operator: Some(ConditionOperator::And),
+                    id: ConditionId::from_ordinal("test", 2),
+                    negated: false,
                 },
             ],
             outcome: "rule 2".to_string(),
+            outcome_display: "rule 2".to_string(),
             position: None,
         };
 
         // Rule 3: A **person** follows rule 3 if passes eye test and follows rule 1 (CYCLE!)
         let rule3 = Rule {
+            index: Some(2),
             label: None,
             selector: "person".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![
                 ConditionGroup {
                     condition: Condition::RuleReference(RuleReferenceCondition {
@@ -997,6 +1200,8 @@ mod tests {
                         },
                     }),
                     operator: None,
+                    id: ConditionId::from_ordinal("test", 1),
+                    negated: false,
                 },
                 ConditionGroup {
                     condition: Condition::RuleReference(RuleReferenceCondition {
@@ -1010,17 +1215,24 @@ mod tests {
                         },
                     }),
                     operator: Some(ConditionOperator::And),
+                    id: ConditionId::from_ordinal("test", 2),
+                    negated: false,
                 },
             ],
             outcome: "rule 3".to_string(),
+            outcome_display: "rule 3".to_string(),
             position: None,
         };
 
         // Global rule that starts the evaluation
         let global_rule = Rule {
+            index: Some(3),
             label: None,
             selector: "person".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::RuleReference(RuleReferenceCondition {
                     selector: PositionedValue {
@@ -1033,8 +1245,11 @@ mod tests {
                     },
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "full driving license".to_string(),
+            outcome_display: "full driving license".to_string(),
             position: None,
         };
 
@@ -1051,23 +1266,33 @@ mod tests {
             label_map: HashMap::new(),
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
 
         // Test that cycle detection catches the infinite loop
-        let result = evaluate_rule_set(&rule_set, &json);
-
-        match result {
-            Err(RuleError::EvaluationError(msg)) => {
-                // Check that the error message contains information about the cycle
-                assert!(msg.contains("Infinite loop detected"));
-                assert!(msg.contains("rule 1"));
-                assert!(msg.contains("rule 2"));
-                assert!(msg.contains("rule 3"));
+        let outcome = evaluate(&rule_set, &json, &EvaluationOptions::default());
+
+        match outcome.error {
+            Some(RuleError::InfiniteLoop { call_path, .. }) => {
+                assert_eq!(
+                    call_path,
+                    [
+                        "full driving license",
+                        "rule 1",
+                        "rule 2",
+                        "rule 3",
+                        "rule 1"
+                    ]
+                );
             }
-            Ok(_) => {
+            None => {
                 panic!("Expected infinite loop error, but evaluation succeeded");
             }
-            Err(other_error) => {
+            Some(other_error) => {
                 panic!("Expected infinite loop error, but got: {:?}", other_error);
             }
         }
@@ -1118,6 +1343,74 @@ mod tests {
         assert!(compare_is_not_empty(&number_value).is_err());
         assert!(compare_is_not_empty(&boolean_value).is_err());
         assert!(compare_is_not_empty(&date_value).is_err());
+
+        // Read from JSON, objects and null can be empty but the rest still can't
+        assert!(compare_json_is_empty(&json!({})).unwrap());
+        assert!(!compare_json_is_empty(&json!({"source": "web"})).unwrap());
+        assert!(compare_json_is_empty(&Value::Null).unwrap());
+        assert!(!compare_json_is_empty(&json!([{"sku": "A"}])).unwrap());
+        assert!(compare_json_is_empty(&json!(42)).is_err());
+        assert!(compare_json_is_empty(&json!(false)).is_err());
+        assert!(compare_json_is_empty(&json!("2023-01-01")).is_err());
+    }
+
+    #[test]
+    fn test_empty_checks_on_objects_and_null() {
+        use crate::runner::trace::ConditionTrace;
+
+        let empty = "the __metadata__ of the **order** is empty";
+        let not_empty = "the __metadata__ of the **order** is not empty";
+        let check = |condition: &str, data: Value| {
+            let (result, trace) =
+                evaluate_condition(condition, &data, &EvaluationOptions::new()).unwrap();
+            let ConditionTrace::Comparison(comparison) = trace else {
+                panic!("Expected a comparison trace");
+            };
+            let kind = comparison
+                .evaluation_details
+                .map(|details| details.left_value.value_type);
+            (result, kind)
+        };
+
+        for (metadata, is_empty, kind) in [
+            (json!({}), true, "object"),
+            (json!({"source": "web"}), false, "object"),
+            (Value::Null, true, "null"),
+            (json!([]), true, "list"),
+            (json!([{"sku": "A"}]), false, "list"),
+            (json!(""), true, "string"),
+        ] {
+            let data = json!({"order": {"metadata": metadata}});
+            let kind = Some(kind.to_string());
+            assert_eq!(check(empty, data.clone()), (is_empty, kind.clone()));
+            assert_eq!(check(not_empty, data), (!is_empty, kind));
+        }
+
+        // Missing data passes neither, as with other operators
+        let missing = json!({"order": {"total": 5}});
+        assert_eq!(check(empty, missing.clone()), (false, None));
+        assert_eq!(check(not_empty, missing), (false, None));
+
+        for (metadata, kind) in [
+            (json!(42), "a number"),
+            (json!(true), "a boolean"),
+            (json!("2023-01-01"), "a date"),
+        ] {
+            let data = json!({"order": {"metadata": metadata}});
+            let error = evaluate_condition(empty, &data, &EvaluationOptions::new()).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "Type error: Cannot check whether $.order.metadata is empty: it is {}, and only strings, lists, objects and null can be empty",
+                    kind
+                )
+            );
+        }
+
+        // After a longer chain too
+        let chained = "the __tags__ of the __details__ of the **order** is not empty";
+        let data = json!({"order": {"details": {"tags": {"gift": true}}}});
+        assert_eq!(check(chained, data), (true, Some("object".to_string())));
     }
 
     #[test]
@@ -1131,9 +1424,13 @@ mod tests {
 
         // Create a rule that references another rule but doesn't create a cycle
         let age_check_rule = Rule {
+            index: Some(0),
             label: None,
             selector: "person".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::Comparison(ComparisonCondition {
                     selector: PositionedValue {
@@ -1151,18 +1448,29 @@ mod tests {
                     },
                     left_property_path: None,
                     right_property_path: None,
+                    date_offset: None,
+                    today: false,
+                    left_expression: None,
+                    right_expression: None,
                     property_chain: None,
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "is adult".to_string(),
+            outcome_display: "is adult".to_string(),
             position: None,
         };
 
         let main_rule = Rule {
+            index: Some(1),
             label: None,
             selector: "person".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![
                 ConditionGroup {
                     condition: Condition::RuleReference(RuleReferenceCondition {
@@ -1176,6 +1484,8 @@ mod tests {
                         },
                     }),
                     operator: None,
+                    id: ConditionId::from_ordinal("test", 1),
+                    negated: false,
                 },
                 ConditionGroup {
                     condition: Condition::Comparison(ComparisonCondition {
@@ -1194,12 +1504,19 @@ mod tests {
                         },
                         left_property_path: None,
                         right_property_path: None,
+                        date_offset: None,
+                        today: false,
+                        left_expression: None,
+                        right_expression: None,
                         property_chain: None,
                     }),
                     operator: Some(ConditionOperator::And),
+                    id: ConditionId::from_ordinal("test", 2),
+                    negated: false,
                 },
             ],
             outcome: "can drive".to_string(),
+            outcome_display: "can drive".to_string(),
             position: None,
         };
 
@@ -1213,20 +1530,21 @@ mod tests {
             label_map: HashMap::new(),
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
 
         // This should succeed without any cycle detection errors
-        let result = evaluate_rule_set(&rule_set, &json);
+        let outcome = evaluate(&rule_set, &json, &EvaluationOptions::default());
 
-        match result {
-            Ok((results, _trace)) => {
-                assert_eq!(results.get("can drive"), Some(&true));
-                assert_eq!(results.get("is adult"), Some(&true));
-            }
-            Err(e) => {
-                panic!("Valid rule evaluation should not fail: {:?}", e);
-            }
+        if let Some(e) = outcome.error {
+            panic!("Valid rule evaluation should not fail: {:?}", e);
         }
+        assert_eq!(outcome.outcomes.get("can drive"), Some(&true));
+        assert_eq!(outcome.outcomes.get("is adult"), Some(&true));
     }
 
     // ERROR TRACE TESTS - Testing the new error tracing functionality
@@ -1241,9 +1559,13 @@ mod tests {
         });
 
         let rule = Rule {
+            index: Some(0),
             label: Some("test rule".to_string()),
             selector: "nonexistent_selector".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::Comparison(ComparisonCondition {
                     selector: PositionedValue {
@@ -1261,11 +1583,18 @@ mod tests {
                     },
                     left_property_path: None,
                     right_property_path: None,
+                    date_offset: None,
+                    today: false,
+                    left_expression: None,
+                    right_expression: None,
                     property_chain: None,
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "adult".to_string(),
+            outcome_display: "adult".to_string(),
             position: None,
         };
 
@@ -1279,16 +1608,21 @@ mod tests {
             label_map: HashMap::new(),
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
 
         // Test with trace-preserving evaluation
-        let result = evaluate_rule_set_with_trace(&rule_set, &json);
+        let outcome = evaluate(&rule_set, &json, &EvaluationOptions::default());
 
         // Should succeed but with false result (missing selectors return false, not error)
-        assert!(result.is_success());
-        assert!(result.trace.is_some());
+        assert!(outcome.error.is_none());
+        assert!(outcome.trace.is_some());
 
-        let trace = result.unwrap_trace();
+        let trace = outcome.trace.unwrap();
         assert_eq!(trace.execution.len(), 1);
         assert_eq!(trace.execution[0].result, false);
         assert_eq!(trace.execution[0].outcome.value, "adult");
@@ -1305,9 +1639,13 @@ mod tests {
         });
 
         let rule = Rule {
+            index: Some(0),
             label: Some("age check".to_string()),
             selector: "user".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::Comparison(ComparisonCondition {
                     selector: PositionedValue {
@@ -1325,11 +1663,18 @@ mod tests {
                     },
                     left_property_path: None,
                     right_property_path: None,
+                    date_offset: None,
+                    today: false,
+                    left_expression: None,
+                    right_expression: None,
                     property_chain: None,
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "adult".to_string(),
+            outcome_display: "adult".to_string(),
             position: None,
         };
 
@@ -1343,20 +1688,116 @@ mod tests {
             label_map: HashMap::new(),
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
 
         // Test with trace-preserving evaluation
-        let result = evaluate_rule_set_with_trace(&rule_set, &json);
+        let outcome = evaluate(&rule_set, &json, &EvaluationOptions::default());
 
         // Should succeed but with false result (missing properties return false, not error)
-        assert!(result.is_success());
-        assert!(result.trace.is_some());
+        assert!(outcome.error.is_none());
+        assert!(outcome.trace.is_some());
 
-        let trace = result.unwrap_trace();
+        let trace = outcome.trace.unwrap();
         assert_eq!(trace.execution.len(), 1);
         assert_eq!(trace.execution[0].result, false);
         assert_eq!(trace.execution[0].outcome.value, "adult");
         assert_eq!(trace.execution[0].conditions.len(), 1);
+        assert_eq!(
+            trace.execution[0].conditions[0].id(),
+            Some(&rule_set.rules[0].conditions[0].id)
+        );
+    }
+
+    #[test]
+    fn test_label_only_cycle_is_detected() {
+        let rules = r#"
+A **Person** gets access
+  if §first.check is valid.
+
+first.check. A **Person** passes the identity check
+  if §second.check is valid.
+
+second.check. A **Person** passes the address check
+  if §first.check is valid.
+"#;
+        let rule_set = parse_rules(rules).unwrap();
+        let json = json!({"Person": {"age": 30}});
+
+        match evaluate(&rule_set, &json, &EvaluationOptions::default()).error {
+            Some(RuleError::InfiniteLoop { call_path, .. }) => {
+                assert_eq!(
+                    call_path,
+                    ["access", "first.check", "second.check", "first.check"]
+                );
+            }
+            other => panic!("Expected infinite loop error, but got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reference_trace_records_how_it_resolved() {
+        use crate::runner::trace::{ConditionTrace, ReferenceResolution};
+
+        let rules = r#"
+A **Person** gets access
+  if the **Person** identity check
+  and §address.check is valid
+  and the **Person** passes the background screening
+  and the **Person** passes the driving test
+  and the **Person** passes the eye exam.
+
+A **Person** gets identity check
+  if the __age__ of the **Person** is greater than 17.
+
+address.check. A **Person** has the address verified
+  if the __postcode__ of the **Person** is not empty.
+
+A **Person** gets background screening
+  if the __age__ of the **Person** is less than 120.
+"#;
+        let rule_set = parse_rules(rules).unwrap();
+        let json = json!({"Person": {"age": 30, "postcode": "AB1", "drivingTestPassed": true}});
+
+        let evaluation = evaluate(&rule_set, &json, &EvaluationOptions::default());
+        assert!(evaluation.outcomes["access"]);
+        let resolutions: Vec<_> = evaluation.trace.unwrap().execution[0]
+            .conditions
+            .iter()
+            .map(|condition| match condition {
+                ConditionTrace::RuleReference(reference) => reference.resolution.clone(),
+                _ => panic!("Expected rule reference trace"),
+            })
+            .collect();
+        assert_eq!(
+            resolutions,
+            vec![
+                ReferenceResolution::ExactOutcome,
+                ReferenceResolution::Label,
+                ReferenceResolution::FuzzyOutcome {
+                    matched: "background screening".to_string()
+                },
+                ReferenceResolution::InferredProperty {
+                    property: "drivingTestPassed".to_string()
+                },
+                ReferenceResolution::Unresolved,
+            ]
+        );
+
+        // A label reference that names no rule fails unresolved
+        let rule_set = parse_rules("A **Person** gets access\n  if §missing is valid.").unwrap();
+        let evaluation = evaluate(&rule_set, &json, &EvaluationOptions::default());
+        assert!(!evaluation.outcomes["access"]);
+        match &evaluation.trace.unwrap().execution[0].conditions[0] {
+            ConditionTrace::RuleReference(reference) => {
+                assert_eq!(reference.resolution, ReferenceResolution::Unresolved)
+            }
+            _ => panic!("Expected rule reference trace"),
+        }
     }
 
     #[test]
@@ -1370,9 +1811,13 @@ mod tests {
 
         // Create cyclic rules that will cause infinite loop
         let rule1 = Rule {
+            index: Some(0),
             label: None,
             selector: "person".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![
                 ConditionGroup {
                     condition: Condition::Comparison(ComparisonCondition {
@@ -1391,9 +1836,15 @@ mod tests {
                         },
                         left_property_path: None,
                         right_property_path: None,
+                        date_offset: None,
+                        today: false,
+                        left_expression: None,
+                        right_expression: None,
                         property_chain: None,
                     }),
                     operator: None,
+                    id: ConditionId::from_ordinal("test", 1),
+                    negated: false,
                 },
                 ConditionGroup {
                     condition: Condition::RuleReference(RuleReferenceCondition {
@@ -1407,16 +1858,23 @@ mod tests {
                         },
                     }),
                     operator: Some(ConditionOperator::And),
+                    id: ConditionId::from_ordinal("test", 2),
+                    negated: false,
                 },
             ],
             outcome: "rule 1".to_string(),
+            outcome_display: "rule 1".to_string(),
             position: None,
         };
 
         let rule2 = Rule {
+            index: Some(1),
             label: None,
             selector: "person".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::RuleReference(RuleReferenceCondition {
                     selector: PositionedValue {
@@ -1429,15 +1887,22 @@ mod tests {
                     },
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "rule 2".to_string(),
+            outcome_display: "rule 2".to_string(),
             position: None,
         };
 
         let global_rule = Rule {
+            index: Some(2),
             label: None,
             selector: "person".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::RuleReference(RuleReferenceCondition {
                     selector: PositionedValue {
@@ -1450,8 +1915,11 @@ mod tests {
                     },
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "global".to_string(),
+            outcome_display: "global".to_string(),
             position: None,
         };
 
@@ -1466,24 +1934,32 @@ mod tests {
             label_map: HashMap::new(),
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
 
         // Test with trace-preserving evaluation
-        let result = evaluate_rule_set_with_trace(&rule_set, &json);
+        let outcome = evaluate(&rule_set, &json, &EvaluationOptions::default());
 
         // Should have error but also trace
-        assert!(result.is_failure());
-        assert!(result.trace.is_some());
+        assert!(outcome.error.is_some());
+        assert!(outcome.trace.is_some());
 
         // Verify error is about infinite loop
-        if let Err(RuleError::EvaluationError(msg)) = &result.result {
-            assert!(msg.contains("Infinite loop detected"));
+        if let Some(error @ RuleError::InfiniteLoop { .. }) = &outcome.error {
+            assert_eq!(
+                error.call_path().unwrap(),
+                ["global", "rule 1", "rule 2", "rule 1"]
+            );
         } else {
             panic!("Expected infinite loop error");
         }
 
         // Verify trace contains partial execution information
-        let trace = result.unwrap_trace();
+        let trace = outcome.trace.unwrap();
         assert!(!trace.execution.is_empty());
 
         // The trace should contain the partial evaluation before the loop was detected
@@ -1502,9 +1978,13 @@ mod tests {
 
         // Create a rule that references a non-existent rule
         let main_rule = Rule {
+            index: Some(0),
             label: Some("main rule".to_string()),
             selector: "user".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::RuleReference(RuleReferenceCondition {
                     selector: PositionedValue {
@@ -1517,15 +1997,22 @@ mod tests {
                     },
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "result".to_string(),
+            outcome_display: "result".to_string(),
             position: None,
         };
 
         let global_rule = Rule {
+            index: Some(1),
             label: None,
             selector: "global".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::RuleReference(RuleReferenceCondition {
                     selector: PositionedValue {
@@ -1538,8 +2025,11 @@ mod tests {
                     },
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "global".to_string(),
+            outcome_display: "global".to_string(),
             position: None,
         };
 
@@ -1553,16 +2043,21 @@ mod tests {
             label_map: HashMap::new(),
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
 
         // Test with trace-preserving evaluation
-        let result = evaluate_rule_set_with_trace(&rule_set, &json);
+        let outcome = evaluate(&rule_set, &json, &EvaluationOptions::default());
 
         // Should succeed (non-existent rules default to false, not error)
-        assert!(result.is_success());
-        assert!(result.trace.is_some());
+        assert!(outcome.error.is_none());
+        assert!(outcome.trace.is_some());
 
-        let trace = result.unwrap_trace();
+        let trace = outcome.trace.unwrap();
         // Should have traces for both rules
         assert_eq!(trace.execution.len(), 2);
 
@@ -1577,6 +2072,40 @@ mod tests {
         assert_eq!(main_trace.result, false);
     }
 
+    #[test]
+    fn test_strict_references_reject_references_to_no_rule() {
+        let rule_set = parse_rules(
+            "A **user** is approved\n  if the **user** passes the age chek\n  and §kyc.2 is valid\n  and the **user** passes the identity check.\n\nA **user** passes the full identity check\n  if the __verified__ of the **user** is equal to true.",
+        )
+        .unwrap();
+        let json = json!({"user": {"verified": true}});
+
+        // Lenient by default: the unknown outcome passes as free text, the label fails
+        let lenient = evaluate(&rule_set, &json, &EvaluationOptions::new());
+        assert!(lenient.error.is_none());
+        assert!(!lenient.result);
+
+        let strict = evaluate(
+            &rule_set,
+            &json,
+            &EvaluationOptions::new().with_strict_references(true),
+        );
+        let Some(RuleError::UnresolvedReferences { references }) = &strict.error else {
+            panic!("expected unresolved references, got {:?}", strict.error);
+        };
+        // The partial match of `full identity check` resolves as it does when evaluating
+        let names: Vec<_> = references.iter().map(|r| r.rule_name.as_str()).collect();
+        assert_eq!(names, ["passes the age chek", "kyc.2"]);
+        assert!(references.iter().all(|r| r.rule == "approved"));
+        assert_eq!(references[0].position.as_ref().unwrap().line, 2);
+        assert_eq!(references[1].position.as_ref().unwrap().line, 3);
+        assert_eq!(
+            strict.error.as_ref().unwrap().to_string(),
+            "Unresolved rule references: 'passes the age chek' in 'approved' on line 2:19, 'kyc.2' in 'approved' on line 3:8"
+        );
+        assert!(strict.trace.unwrap().execution.is_empty());
+    }
+
     #[test]
     fn test_error_trace_preserves_successful_conditions_before_failure() {
         let json = json!({
@@ -1588,9 +2117,13 @@ mod tests {
         });
 
         let rule = Rule {
+            index: Some(0),
             label: Some("complex rule".to_string()),
             selector: "user".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![
                 ConditionGroup {
                     condition: Condition::Comparison(ComparisonCondition {
@@ -1609,9 +2142,15 @@ mod tests {
                         },
                         left_property_path: None,
                         right_property_path: None,
+                        date_offset: None,
+                        today: false,
+                        left_expression: None,
+                        right_expression: None,
                         property_chain: None,
                     }),
                     operator: None,
+                    id: ConditionId::from_ordinal("test", 1),
+                    negated: false,
                 },
                 ConditionGroup {
                     condition: Condition::Comparison(ComparisonCondition {
@@ -1630,9 +2169,15 @@ mod tests {
                         },
                         left_property_path: None,
                         right_property_path: None,
+                        date_offset: None,
+                        today: false,
+                        left_expression: None,
+                        right_expression: None,
                         property_chain: None,
                     }),
                     operator: Some(ConditionOperator::And),
+                    id: ConditionId::from_ordinal("test", 2),
+                    negated: false,
                 },
                 ConditionGroup {
                     condition: Condition::Comparison(ComparisonCondition {
@@ -1651,12 +2196,19 @@ mod tests {
                         },
                         left_property_path: None,
                         right_property_path: None,
+                        date_offset: None,
+                        today: false,
+                        left_expression: None,
+                        right_expression: None,
                         property_chain: None,
                     }),
                     operator: Some(ConditionOperator::And),
+                    id: ConditionId::from_ordinal("test", 3),
+                    negated: false,
                 },
             ],
             outcome: "qualified".to_string(),
+            outcome_display: "qualified".to_string(),
             position: None,
         };
 
@@ -1670,16 +2222,21 @@ mod tests {
             label_map: HashMap::new(),
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
 
         // Test with trace-preserving evaluation
-        let result = evaluate_rule_set_with_trace(&rule_set, &json);
+        let outcome = evaluate(&rule_set, &json, &EvaluationOptions::default());
 
         // Should succeed but with false result (missing score property)
-        assert!(result.is_success());
-        assert!(result.trace.is_some());
+        assert!(outcome.error.is_none());
+        assert!(outcome.trace.is_some());
 
-        let trace = result.unwrap_trace();
+        let trace = outcome.trace.unwrap();
         assert_eq!(trace.execution.len(), 1);
 
         let rule_trace = &trace.execution[0];
@@ -1721,9 +2278,13 @@ mod tests {
         });
 
         let rule = Rule {
+            index: None,
             label: Some("age check".to_string()),
             selector: "user".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::Comparison(ComparisonCondition {
                     selector: PositionedValue {
@@ -1741,11 +2302,18 @@ mod tests {
                     },
                     left_property_path: None,
                     right_property_path: None,
+                    date_offset: None,
+                    today: false,
+                    left_expression: None,
+                    right_expression: None,
                     property_chain: None,
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "adult".to_string(),
+            outcome_display: "adult".to_string(),
             position: None,
         };
 
@@ -1755,18 +2323,20 @@ mod tests {
             label_map: HashMap::new(),
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
 
-        let mut evaluation_stack = HashSet::new();
-        let mut call_path = Vec::new();
-
         // Test individual rule evaluation with trace
+        let options = EvaluationOptions::default();
         let result = evaluate_rule_with_trace(
             &rule,
             &json,
             &rule_set,
-            &mut evaluation_stack,
-            &mut call_path,
+            &mut EvaluationContext::new(&options),
         );
 
         // Should succeed
@@ -1804,6 +2374,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -1827,6 +2401,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -1860,6 +2438,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -1883,6 +2465,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -1906,6 +2492,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -1938,6 +2528,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -1961,6 +2555,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -1997,6 +2595,10 @@ mod tests {
             },
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
             property_chain: None,
         };
 
@@ -2005,7 +2607,7 @@ mod tests {
     }
 
     #[test]
-    fn test_comparison_of_trace_preserving_vs_regular_evaluation() {
+    fn test_memoised_evaluation_matches_fresh_evaluation() {
         let json = json!({
             "user": {
                 "age": 25,
@@ -2014,9 +2616,13 @@ mod tests {
         });
 
         let rule = Rule {
+            index: Some(0),
             label: Some("user check".to_string()),
             selector: "user".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::Comparison(ComparisonCondition {
                     selector: PositionedValue {
@@ -2034,18 +2640,29 @@ mod tests {
                     },
                     left_property_path: None,
                     right_property_path: None,
+                    date_offset: None,
+                    today: false,
+                    left_expression: None,
+                    right_expression: None,
                     property_chain: None,
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "adult".to_string(),
+            outcome_display: "adult".to_string(),
             position: None,
         };
 
         let global_rule = Rule {
+            index: Some(1),
             label: None,
             selector: "global".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::RuleReference(RuleReferenceCondition {
                     selector: PositionedValue {
@@ -2058,8 +2675,11 @@ mod tests {
                     },
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "global".to_string(),
+            outcome_display: "global".to_string(),
             position: None,
         };
 
@@ -2073,39 +2693,37 @@ mod tests {
             label_map: HashMap::new(),
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
 
-        // Test both evaluation methods
-        let regular_result = evaluate_rule_set(&rule_set, &json);
-        let trace_result = evaluate_rule_set_with_trace(&rule_set, &json);
+        // A second evaluation through the same memo takes the kept rules as they were
+        let options = EvaluationOptions::default();
+        let fresh = evaluate(&rule_set, &json, &options);
+        let mut memo = RuleMemo::new();
+        evaluate_with_memo(&rule_set, &json, &options, &mut memo);
+        assert!(!memo.is_empty());
+        let memoised = evaluate_with_memo(&rule_set, &json, &options, &mut memo);
 
         // Both should succeed with the same results
-        assert!(regular_result.is_ok());
-        assert!(trace_result.is_success());
-
-        let (regular_results, regular_trace) = regular_result.unwrap();
+        assert!(fresh.error.is_none());
+        assert!(memoised.error.is_none());
+        assert_eq!(fresh.outcomes, memoised.outcomes);
 
         // Both should have traces, and they should be similar
-        assert!(trace_result.trace.is_some());
-        assert!(trace_result.is_success());
-
-        let trace_results = trace_result.result.as_ref().unwrap();
-        let enhanced_trace = trace_result.trace.as_ref().unwrap();
-
-        // Results should be identical
-        assert_eq!(&regular_results, trace_results);
-
-        assert_eq!(
-            regular_trace.execution.len(),
-            enhanced_trace.execution.len()
-        );
+        let fresh_trace = fresh.trace.unwrap();
+        let memoised_trace = memoised.trace.unwrap();
+        assert_eq!(fresh_trace.execution.len(), memoised_trace.execution.len());
         assert_eq!(
-            regular_trace.execution[0].result,
-            enhanced_trace.execution[0].result
+            fresh_trace.execution[0].result,
+            memoised_trace.execution[0].result
         );
         assert_eq!(
-            regular_trace.execution[1].result,
-            enhanced_trace.execution[1].result
+            fresh_trace.execution[1].result,
+            memoised_trace.execution[1].result
         );
     }
 
@@ -2150,8 +2768,7 @@ mod tests {
             }
         });
 
-        let (results, _) = evaluate_rule_set(&rule_set, &json_recent)
-            .expect("Should evaluate successfully with recent date");
+        let results = evaluate_outcomes(&rule_set, &json_recent);
         assert_eq!(
             *results.get("valid").unwrap(),
             true,
@@ -2165,8 +2782,7 @@ mod tests {
             }
         });
 
-        let (results, _) = evaluate_rule_set(&rule_set, &json_old)
-            .expect("Should evaluate successfully with old date");
+        let results = evaluate_outcomes(&rule_set, &json_old);
         assert_eq!(
             *results.get("valid").unwrap(),
             false,
@@ -2181,8 +2797,7 @@ mod tests {
             }
         });
 
-        let (results, _) = evaluate_rule_set(&rule_set, &json_exact)
-            .expect("Should evaluate successfully with exact date");
+        let results = evaluate_outcomes(&rule_set, &json_exact);
         assert_eq!(
             *results.get("valid").unwrap(),
             true,
@@ -2214,10 +2829,7 @@ mod tests {
                 }
             });
 
-            let (results, _) = evaluate_rule_set(&rule_set, &json_test).expect(&format!(
-                "Should evaluate successfully with {}",
-                duration_str
-            ));
+            let results = evaluate_outcomes(&rule_set, &json_test);
             assert_eq!(
                 *results.get("valid").unwrap(),
                 true,
@@ -2234,10 +2846,7 @@ mod tests {
                 }
             });
 
-            let (results, _) = evaluate_rule_set(&rule_set, &json_old).expect(&format!(
-                "Should evaluate successfully with old {}",
-                duration_str
-            ));
+            let results = evaluate_outcomes(&rule_set, &json_old);
             assert_eq!(
                 *results.get("valid").unwrap(),
                 false,
@@ -2267,8 +2876,7 @@ mod tests {
             }
         });
 
-        let (results, _) =
-            evaluate_rule_set(&rule_set, &json).expect("Should evaluate mixed rules successfully");
+        let results = evaluate_outcomes(&rule_set, &json);
 
         assert_eq!(
             *results.get("eligible").unwrap(),
@@ -2285,8 +2893,7 @@ mod tests {
             }
         });
 
-        let (results, _) =
-            evaluate_rule_set(&rule_set, &json_old).expect("Should evaluate with old date");
+        let results = evaluate_outcomes(&rule_set, &json_old);
 
         // The old date should cause the date check to fail, making the overall eligible result false
         assert_eq!(
@@ -2310,9 +2917,13 @@ mod tests {
         });
 
         let age_rule = Rule {
+            index: Some(0),
             label: Some("age check".to_string()),
             selector: "user".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::Comparison(ComparisonCondition {
                     selector: PositionedValue {
@@ -2330,18 +2941,29 @@ mod tests {
                     },
                     left_property_path: None,
                     right_property_path: None,
+                    date_offset: None,
+                    today: false,
+                    left_expression: None,
+                    right_expression: None,
                     property_chain: None,
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "adult".to_string(),
+            outcome_display: "adult".to_string(),
             position: None,
         };
 
         let main_rule = Rule {
+            index: Some(1),
             label: Some("main rule".to_string()),
             selector: "global".to_string(),
             selector_pos: None,
+            aliases: Vec::new(),
+            document: None,
+            tags: Vec::new(),
             conditions: vec![ConditionGroup {
                 condition: Condition::RuleReference(RuleReferenceCondition {
                     selector: PositionedValue {
@@ -2354,8 +2976,11 @@ mod tests {
                     },
                 }),
                 operator: None,
+                id: ConditionId::from_ordinal("test", 1),
+                negated: false,
             }],
             outcome: "global".to_string(),
+            outcome_display: "global".to_string(),
             position: None,
         };
 
@@ -2373,11 +2998,2639 @@ mod tests {
             label_map,
             cache: crate::runner::model::PerformanceCache::new(),
             selector_mappings: std::collections::HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         };
 
-        let (results, _trace) = evaluate_rule_set(&rule_set, &json).unwrap();
+        let results = evaluate_outcomes(&rule_set, &json);
 
         assert_eq!(results.get("global"), Some(&true));
         assert_eq!(results.get("adult"), Some(&true));
     }
+
+    fn large_synthetic_rule_set(size: usize) -> String {
+        let checks: Vec<String> = (0..size)
+            .map(|i| format!("the **user** passes check {:05}", i))
+            .collect();
+        let mut text = format!(
+            "A **user** passes everything if {}.\n",
+            checks.join("\n  and ")
+        );
+        for i in 0..size {
+            text.push_str(&format!(
+                "A **user** passes check {:05} if the __score__ of the **user** is greater than {}.\n",
+                i, i
+            ));
+        }
+        text
+    }
+
+    #[test]
+    fn test_evaluation_cancelled_before_start() {
+        let rule_set = parse_rules(&large_synthetic_rule_set(3)).unwrap();
+        let json = json!({"user": {"score": 10}});
+        let flag = Arc::new(AtomicBool::new(true));
+        let options = EvaluationOptions::new().with_cancellation(flag);
+
+        let outcome = evaluate(&rule_set, &json, &options);
+
+        assert!(matches!(outcome.error, Some(RuleError::Cancelled)));
+        let trace = outcome.trace.unwrap();
+        assert_eq!(trace.execution.len(), 1);
+        assert!(trace.execution[0].conditions.is_empty());
+        assert!(!trace.execution[0].result);
+    }
+
+    /// Stops the evaluation after its first condition until another thread has cancelled it
+    #[derive(Debug)]
+    struct PauseAfterFirstCondition {
+        paused: AtomicBool,
+        handover: Barrier,
+    }
+
+    impl DebugHook for PauseAfterFirstCondition {
+        fn on_condition_evaluated(
+            &self,
+            _: &Rule,
+            _: &ConditionTrace,
+            _: &DebugView,
+        ) -> DebugAction {
+            if !self.paused.swap(true, Ordering::Relaxed) {
+                // Once to let the canceller run, once more when it has set the flag
+                self.handover.wait();
+                self.handover.wait();
+            }
+            DebugAction::Continue
+        }
+    }
+
+    #[test]
+    fn test_evaluation_cancelled_from_another_thread() {
+        let size = 200;
+        let rule_set = parse_rules(&large_synthetic_rule_set(size)).unwrap();
+        let json = json!({"user": {"score": size * 2}});
+
+        let flag = Arc::new(AtomicBool::new(false));
+        let hook = Arc::new(PauseAfterFirstCondition {
+            paused: AtomicBool::new(false),
+            handover: Barrier::new(2),
+        });
+        let options = EvaluationOptions {
+            debug_hook: Some(hook.clone()),
+            ..EvaluationOptions::new().with_cancellation(flag.clone())
+        };
+        let canceller = std::thread::spawn(move || {
+            hook.handover.wait();
+            flag.store(true, Ordering::Relaxed);
+            hook.handover.wait();
+        });
+
+        let outcome = evaluate(&rule_set, &json, &options);
+        canceller.join().unwrap();
+
+        assert!(matches!(outcome.error, Some(RuleError::Cancelled)));
+        let trace = outcome.trace.unwrap();
+        let evaluated: usize = trace.execution.iter().map(|t| t.conditions.len()).sum();
+        assert_eq!(evaluated, 1, "evaluation should stop at the next condition");
+    }
+
+    #[test]
+    fn test_wide_rule_set_trips_a_tiny_condition_budget() {
+        let rule_set = parse_rules(&large_synthetic_rule_set(50)).unwrap();
+        let json = json!({"user": {"score": 100}});
+
+        // Each check is one condition of the top rule and one of its own
+        let outcome = evaluate(&rule_set, &json, &EvaluationOptions::new());
+        assert!(outcome.result);
+        assert_eq!(outcome.evaluations_used, 100);
+        let exact = EvaluationOptions::new().with_max_condition_evaluations(100);
+        assert!(evaluate(&rule_set, &json, &exact).error.is_none());
+
+        let tiny = EvaluationOptions::new().with_max_condition_evaluations(9);
+        let outcome = evaluate(&rule_set, &json, &tiny);
+        assert!(!outcome.result);
+        match &outcome.error {
+            Some(RuleError::BudgetExceeded { evaluations, rule }) => {
+                assert_eq!(*evaluations, 9);
+                assert_eq!(rule, "check 00004");
+            }
+            other => panic!("Expected BudgetExceeded, got {:?}", other),
+        }
+        assert_eq!(outcome.evaluations_used, 9);
+
+        // The trace shows how far evaluation got: four decided checks and the one that tripped
+        let trace = outcome.trace.unwrap();
+        assert_eq!(trace.execution.len(), 1);
+        assert_eq!(trace.execution[0].conditions.len(), 5);
+    }
+
+    /// `step 000` references `step 001` and so on, `size` rules deep
+    fn reference_chain(size: usize) -> String {
+        let mut text = String::new();
+        for i in 0..size - 1 {
+            text.push_str(&format!(
+                "A **user** passes step {:03} if the **user** passes step {:03}.\n",
+                i,
+                i + 1
+            ));
+        }
+        text.push_str(&format!(
+            "A **user** passes step {:03} if the __score__ of the **user** is greater than 0.\n",
+            size - 1
+        ));
+        text
+    }
+
+    #[test]
+    fn test_deep_reference_chain_stops_at_the_depth_limit() {
+        let rule_set = parse_rules(&reference_chain(40)).unwrap();
+        let json = json!({"user": {"score": 10}});
+
+        let outcome = evaluate(&rule_set, &json, &EvaluationOptions::new());
+        match &outcome.error {
+            Some(RuleError::MaxDepthExceeded {
+                call_path, limit, ..
+            }) => {
+                assert_eq!(*limit, 32);
+                assert_eq!(call_path.len(), 33);
+                assert_eq!(call_path[0], "step 000");
+                assert_eq!(call_path[32], "step 032");
+            }
+            other => panic!("Expected MaxDepthExceeded, got {:?}", other),
+        }
+        let message = outcome.error.unwrap().to_string();
+        assert!(
+            message.ends_with(
+                "step 000 -> step 001 -> step 002 -> step 003 -> step 004 -> … 23 more frames … -> step 028 -> step 029 -> step 030 -> step 031 -> step 032"
+            ),
+            "{}",
+            message
+        );
+
+        let shallow = parse_rules(&reference_chain(5)).unwrap();
+        let limited = EvaluationOptions::new().with_max_reference_depth(5);
+        assert!(evaluate(&shallow, &json, &limited).result);
+        let limited = limited.with_max_reference_depth(4);
+        let outcome = evaluate(&shallow, &json, &limited);
+        assert_eq!(
+            outcome
+                .error
+                .as_ref()
+                .and_then(RuleError::call_path)
+                .unwrap(),
+            ["step 000", "step 001", "step 002", "step 003", "step 004"]
+        );
+    }
+
+    #[test]
+    fn test_trace_cuts_the_call_path_unless_verbose() {
+        let rule_set = parse_rules(&reference_chain(10)).unwrap();
+        let json = json!({"user": {"score": 10}});
+        let options = EvaluationOptions::new()
+            .with_max_reference_depth(6)
+            .with_max_call_path_frames(2);
+
+        // The rule evaluation started from records why it stopped
+        let trace_error = |options: &EvaluationOptions| {
+            let trace = evaluate(&rule_set, &json, options).trace.unwrap();
+            assert_eq!(trace.execution.len(), 1);
+            trace.execution[0].error.clone().unwrap()
+        };
+
+        assert_eq!(
+            trace_error(&options),
+            "Condition evaluation failed: Rule references nest deeper than the limit of 6: step 000 -> … 5 more frames … -> step 006"
+        );
+        assert_eq!(
+            trace_error(&options.with_trace_verbosity(TraceVerbosity::Verbose)),
+            "Condition evaluation failed: Rule references nest deeper than the limit of 6: step 000 -> step 001 -> step 002 -> step 003 -> step 004 -> step 005 -> step 006"
+        );
+    }
+
+    #[test]
+    fn test_explicit_entries_disambiguate_multiple_globals() {
+        use crate::runner::parser::parse_rule_set;
+
+        let rule_text = r#"
+        A **user** gets access
+          if the __role__ of the **user** is equal to "admin".
+
+        A **user** gets premium
+          if the __plan__ of the **user** is equal to "gold".
+        "#;
+        let rule_set = parse_rule_set(rule_text).unwrap();
+        let json = json!({"user": {"role": "admin", "plan": "silver"}});
+
+        // Without an entry the ambiguity is reported with the candidates
+        let ambiguous = evaluate(&rule_set, &json, &EvaluationOptions::new());
+        assert!(matches!(
+            ambiguous.error,
+            Some(RuleError::MultipleGlobalRules { .. })
+        ));
+
+        let options = EvaluationOptions::new().with_entries(vec!["access".to_string()]);
+        let results = evaluate(&rule_set, &json, &options).outcomes;
+        assert_eq!(results.get("access"), Some(&true));
+        assert_eq!(results.get("premium"), None);
+
+        let options = EvaluationOptions::new()
+            .with_entries(vec!["access".to_string(), "premium".to_string()]);
+        let results = evaluate(&rule_set, &json, &options).outcomes;
+        assert_eq!(results.get("access"), Some(&true));
+        assert_eq!(results.get("premium"), Some(&false));
+
+        let options = EvaluationOptions::new().with_entries(vec!["missing".to_string()]);
+        let outcome = evaluate(&rule_set, &json, &options);
+        assert!(outcome
+            .error
+            .unwrap()
+            .to_string()
+            .contains("Entry rule 'missing' not found"));
+    }
+
+    #[test]
+    fn test_count_value_matrix() {
+        use CountOperation::{LengthOf, NumberOf};
+
+        let cases = vec![
+            (LengthOf, json!("héllo"), Some(5.0)),
+            (LengthOf, json!([1, 2, 3]), Some(3.0)),
+            (LengthOf, json!({"a": 1, "b": 2}), Some(2.0)),
+            (LengthOf, json!(""), Some(0.0)),
+            (NumberOf, json!([1, 2, 3]), Some(3.0)),
+            (NumberOf, json!({"a": 1, "b": 2}), Some(2.0)),
+            (NumberOf, json!([]), Some(0.0)),
+        ];
+        for (operation, value, expected) in cases {
+            for lenient in [false, true] {
+                let counted = count_value(operation, &value, "$.user.field", lenient).unwrap();
+                assert_eq!(counted, expected, "{} {}", operation.phrase(), value);
+            }
+        }
+
+        let uncountable = vec![
+            (LengthOf, json!(42)),
+            (LengthOf, json!(true)),
+            (NumberOf, json!("tester")),
+            (NumberOf, json!(42)),
+            (NumberOf, json!(false)),
+        ];
+        for (operation, value) in uncountable {
+            for lenient in [false, true] {
+                let error = count_value(operation, &value, "$.user.field", lenient).unwrap_err();
+                assert!(matches!(error, RuleError::TypeError(_)));
+                assert!(error.to_string().contains("$.user.field"));
+                assert!(error.to_string().contains(operation.phrase()));
+            }
+        }
+
+        for operation in CountOperation::ALL {
+            assert_eq!(
+                count_value(operation, &json!(null), "$.user.field", false).unwrap(),
+                None
+            );
+            assert_eq!(
+                count_value(operation, &json!(null), "$.user.field", true).unwrap(),
+                Some(0.0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_number_of_string_error_suggests_length_of() {
+        let error = count_value(
+            CountOperation::NumberOf,
+            &json!("tester"),
+            "$.user.items",
+            false,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Type error: Cannot take the number of a string at $.user.items: \
+             'number of' counts array elements or object keys, use 'length of' to count characters"
+        );
+    }
+
+    #[test]
+    fn test_null_count_only_zero_when_lenient() {
+        let rule_set = parse_rules(
+            r#"A **user** is valid if the number of __items__ of **user** is less than 3."#,
+        )
+        .unwrap();
+        let json = json!({"user": {"items": null}});
+
+        let strict = evaluate(&rule_set, &json, &EvaluationOptions::new());
+        assert_eq!(strict.outcomes.get("valid"), Some(&false));
+
+        let options = EvaluationOptions::new().with_lenient(true);
+        let lenient = evaluate(&rule_set, &json, &options);
+        assert_eq!(lenient.outcomes.get("valid"), Some(&true));
+    }
+
+    #[test]
+    fn test_aggregate_value_matrix() {
+        use AggregateOperation::{Average, Maximum, Minimum, Sum};
+
+        let amounts = json!([120, 80.5, -20, 300]);
+        for (operation, expected) in [
+            (Sum, Some(480.5)),
+            (Minimum, Some(-20.0)),
+            (Maximum, Some(300.0)),
+            (Average, Some(120.125)),
+        ] {
+            let aggregate = aggregate_value(operation, &amounts, "$.order.amounts", false);
+            assert_eq!(aggregate.unwrap(), expected, "{}", operation.phrase());
+        }
+
+        // Only the sum of nothing is a number; null reads as nothing when lenient
+        for (operation, empty) in [
+            (Sum, Some(0.0)),
+            (Minimum, None),
+            (Maximum, None),
+            (Average, None),
+        ] {
+            let path = "$.order.amounts";
+            assert_eq!(
+                aggregate_value(operation, &json!([]), path, false).unwrap(),
+                empty
+            );
+            assert_eq!(
+                aggregate_value(operation, &json!(null), path, false).unwrap(),
+                None
+            );
+            assert_eq!(
+                aggregate_value(operation, &json!(null), path, true).unwrap(),
+                empty
+            );
+        }
+
+        for value in [json!(42), json!("12"), json!({"a": 1})] {
+            for operation in AggregateOperation::ALL {
+                let error =
+                    aggregate_value(operation, &value, "$.order.amounts", true).unwrap_err();
+                assert!(matches!(error, RuleError::TypeError(_)));
+                assert!(
+                    error.to_string().contains("takes an array of numbers"),
+                    "{}",
+                    error
+                );
+            }
+        }
+        let error =
+            aggregate_value(Sum, &json!([1, "2", 3]), "$.order.amounts", false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Type error: Cannot take the sum of $.order.amounts: element 1 is a string, not a number"
+        );
+    }
+
+    #[test]
+    fn test_aggregates_compare_and_trace_the_computed_number() {
+        use crate::runner::trace::ConditionTrace;
+
+        let rule_set = parse_rules(
+            "A **student** passes the year\n  \
+             if the average of __scores__ of the **student** is at least 70\n  \
+             and the minimum of __scores__ of the **student** is greater than 40\n  \
+             and the sum of __amount__ of each __fees__ of the **student** is no more than 1000.",
+        )
+        .unwrap();
+        let options = EvaluationOptions::new();
+
+        let data = json!({"student": {
+            "scores": [72, 65, 90],
+            "fees": [{"amount": 400}, {"amount": 550}],
+        }});
+        let outcome = evaluate(&rule_set, &data, &options);
+        assert!(outcome.result);
+        let trace = outcome.trace.unwrap();
+        let computed: Vec<_> = trace.execution[0]
+            .conditions
+            .iter()
+            .map(|condition| {
+                let ConditionTrace::Comparison(comparison) = condition else {
+                    panic!("Expected a comparison");
+                };
+                let details = comparison.evaluation_details.as_ref().unwrap();
+                assert_eq!(details.left_value.value, comparison.property.value);
+                (
+                    comparison.property.path.as_str(),
+                    comparison.property.value.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            computed,
+            vec![
+                ("$.student.scores.average", json!(75.66666666666667)),
+                ("$.student.scores.minimum", json!(65.0)),
+                ("$.student.fees[*].amount.sum", json!(950.0)),
+            ]
+        );
+
+        let low = json!({"student": {"scores": [72, 35, 90], "fees": []}});
+        assert!(!evaluate(&rule_set, &low, &options).result);
+
+        // No scores have no average, so the condition fails like a missing property
+        let none = json!({"student": {"scores": [], "fees": []}});
+        let outcome = evaluate(&rule_set, &none, &options);
+        assert!(!outcome.result && outcome.error.is_none());
+
+        let text = json!({"student": {"scores": [72, "A"], "fees": []}});
+        let error = evaluate(&rule_set, &text, &options).error.unwrap();
+        assert!(
+            error.to_string().contains("element 1 is a string"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_element_steps_read_one_element_of_a_list() {
+        use crate::runner::trace::ConditionTrace;
+
+        let rule_set = parse_rules(
+            "A **candidate** is on track\n  \
+             if the __status__ of the first of __attempts__ of the **candidate** is equal to \"passed\"\n  \
+             and the __score__ of the last of the __attempts__ of the **candidate** is at least 50\n  \
+             and the 2nd of __referees__ of the **candidate** is not empty.",
+        )
+        .unwrap();
+        let options = EvaluationOptions::new();
+        let paths = |outcome: &crate::runner::outcome::EvaluationOutcome| -> Vec<(String, Value)> {
+            outcome.trace.as_ref().unwrap().execution[0]
+                .conditions
+                .iter()
+                .map(|condition| {
+                    let ConditionTrace::Comparison(comparison) = condition else {
+                        panic!("Expected a comparison");
+                    };
+                    (
+                        comparison.property.path.clone(),
+                        comparison.property.value.clone(),
+                    )
+                })
+                .collect()
+        };
+
+        let data = json!({"candidate": {
+            "attempts": [
+                {"status": "passed", "score": 40},
+                {"status": "failed", "score": 75},
+            ],
+            "referees": ["Ann", "Bo"],
+        }});
+        let outcome = evaluate(&rule_set, &data, &options);
+        assert!(outcome.result);
+        assert_eq!(
+            paths(&outcome),
+            vec![
+                (
+                    "$.candidate.attempts[0].status".to_string(),
+                    json!("passed")
+                ),
+                ("$.candidate.attempts[-1].score".to_string(), json!(75)),
+                ("$.candidate.referees[1]".to_string(), json!("Bo")),
+            ]
+        );
+
+        // Elements the list doesn't have fail their condition, saying why
+        let short = json!({"candidate": {
+            "attempts": [{"status": "passed", "score": 90}],
+            "referees": ["Ann"],
+        }});
+        let outcome = evaluate(&rule_set, &short, &options);
+        assert!(!outcome.result && outcome.error.is_none());
+        let ConditionTrace::Comparison(third) = &outcome.trace.unwrap().execution[0].conditions[2]
+        else {
+            panic!("Expected a comparison");
+        };
+        assert_eq!(third.property.path, "$.candidate.referees[1]");
+        assert_eq!(
+            third.property.warning.as_deref(),
+            Some("$.candidate.referees has 1 element, so it has no 2nd element")
+        );
+
+        let empty = json!({"candidate": {"attempts": [], "referees": []}});
+        let outcome = evaluate(&rule_set, &empty, &options);
+        assert!(!outcome.result && outcome.error.is_none());
+
+        let error = evaluate(
+            &rule_set,
+            &json!({"candidate": {"attempts": {"status": "passed"}}}),
+            &options,
+        )
+        .error
+        .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Type error: 'the 1st of' needs a list at $.candidate.attempts but found an object"
+        );
+    }
+
+    #[test]
+    fn test_element_steps_combine_with_each_and_counts() {
+        use crate::runner::trace::ConditionTrace;
+
+        let rule_set = parse_rules(
+            "A **school** is busy\n  \
+             if the __score__ of the first of __results__ of each __students__ of the **school** contains 90\n  \
+             and the number of __pupils__ of the last of __classes__ of the **school** is at least 2\n  \
+             and the sum of __fees__ of the 1st of __terms__ of the **school** is greater than 100.",
+        )
+        .unwrap();
+        let data = json!({"school": {
+            "students": [
+                {"results": [{"score": 90}, {"score": 10}]},
+                {"results": []},
+                {"results": [{"score": 70}]},
+            ],
+            "classes": [{"pupils": ["a"]}, {"pupils": ["b", "c"]}],
+            "terms": [{"fees": [60, 50]}, {"fees": [1]}],
+        }});
+        let outcome = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        assert!(outcome.result, "{:?}", outcome.error);
+        let paths: Vec<_> = outcome.trace.unwrap().execution[0]
+            .conditions
+            .iter()
+            .map(|condition| {
+                let ConditionTrace::Comparison(comparison) = condition else {
+                    panic!("Expected a comparison");
+                };
+                (
+                    comparison.property.path.clone(),
+                    comparison.property.value.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                (
+                    "$.school.students[*].results[0].score".to_string(),
+                    json!([90, 70])
+                ),
+                ("$.school.classes[-1].pupils.number".to_string(), json!(2.0)),
+                ("$.school.terms[0].fees.sum".to_string(), json!(110.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_references_resolve_through_aliases() {
+        use crate::runner::evaluator::evaluate;
+        use crate::runner::trace::{ConditionTrace, ReferenceResolution};
+
+        let rule_set = parse_rules(
+            r#"A **driver** gets a licence
+  if the **driver** has passed an eye test
+  and the **driver** meets the vision requirement
+  and the **driver** passes the eye test.
+
+A **driver** passes the eye test (also known as "has passed an eye test", "meets the vision requirement")
+  if the __vision__ of the **driver** is greater than 0.5."#,
+        )
+        .unwrap();
+        assert_eq!(
+            rule_set.rules[1]
+                .aliases
+                .iter()
+                .map(|alias| alias.value.as_str())
+                .collect::<Vec<_>>(),
+            vec!["has passed an eye test", "meets the vision requirement"]
+        );
+        assert_eq!(rule_set.rules[1].outcome, "eye test");
+
+        let outcome = evaluate(
+            &rule_set,
+            &json!({"driver": {"vision": 0.8}}),
+            &EvaluationOptions::new(),
+        );
+        assert!(outcome.result, "{:?}", outcome.error);
+        assert_eq!(outcome.outcomes.len(), 2);
+
+        let trace = outcome.trace.unwrap();
+        let references: Vec<_> = trace.execution[0]
+            .conditions
+            .iter()
+            .map(|condition| match condition {
+                ConditionTrace::RuleReference(reference) => (
+                    reference.rule_name.as_str(),
+                    reference.referenced_rule_outcome.as_deref(),
+                    reference.resolution.clone(),
+                ),
+                _ => panic!("Expected a reference"),
+            })
+            .collect();
+        assert_eq!(
+            references,
+            vec![
+                (
+                    "has passed an eye test",
+                    Some("eye test"),
+                    ReferenceResolution::Alias
+                ),
+                (
+                    "meets the vision requirement",
+                    Some("eye test"),
+                    ReferenceResolution::Alias
+                ),
+                (
+                    "passes the eye test",
+                    Some("eye test"),
+                    ReferenceResolution::FuzzyOutcome {
+                        matched: "eye test".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_date_comparisons_with_grace_periods() {
+        use crate::runner::evaluator::evaluate;
+        use crate::runner::trace::ConditionTrace;
+
+        let literal = parse_rules(
+            "An **invoice** is on time \
+             if the __paid on__ of the **invoice** is earlier than 2024-01-31 plus 1 month.",
+        )
+        .unwrap();
+        let property = parse_rules(
+            "An **invoice** is on time \
+             if the __paid on__ of the **invoice** is earlier than \
+             the __due date__ of the **invoice** plus 14 days.",
+        )
+        .unwrap();
+        let options = EvaluationOptions::new();
+
+        // Jan 31 plus a month is Feb 29 in a leap year
+        for (paid_on, on_time) in [("2024-02-28", true), ("2024-02-29", false)] {
+            let outcome = evaluate(&literal, &json!({"invoice": {"paidOn": paid_on}}), &options);
+            assert_eq!(outcome.result, on_time, "{}", paid_on);
+            let trace = outcome.trace.unwrap();
+            let ConditionTrace::Comparison(comparison) = &trace.execution[0].conditions[0] else {
+                panic!("Expected a comparison");
+            };
+            // The trace shows the shifted threshold
+            assert_eq!(comparison.value.value, json!("2024-02-29"));
+        }
+
+        for (due, paid_on, on_time) in [
+            ("2024-02-20", "2024-03-04", true),
+            ("2024-02-20", "2024-03-05", false),
+            // Without a leap day the same grace period ends a day later
+            ("2023-02-20", "2023-03-05", true),
+            ("2023-02-20", "2023-03-06", false),
+        ] {
+            let data = json!({"invoice": {"dueDate": due, "paidOn": paid_on}});
+            let outcome = evaluate(&property, &data, &options);
+            assert_eq!(outcome.result, on_time, "{} {}", due, paid_on);
+        }
+
+        // The shifted property is traced in the comparison's details
+        let data = json!({"invoice": {"dueDate": "2024-02-20", "paidOn": "2024-03-04"}});
+        let trace = evaluate(&property, &data, &options).trace.unwrap();
+        let ConditionTrace::Comparison(comparison) = &trace.execution[0].conditions[0] else {
+            panic!("Expected a comparison");
+        };
+        assert_eq!(comparison.value.path.as_deref(), Some("$.invoice.dueDate"));
+        let details = serde_json::to_value(&comparison.evaluation_details).unwrap();
+        assert!(details.to_string().contains("2024-03-05"), "{}", details);
+
+        let outcome = evaluate(
+            &property,
+            &json!({"invoice": {"dueDate": 3, "paidOn": "2024-03-04"}}),
+            &options,
+        );
+        assert!(matches!(outcome.error, Some(RuleError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_today_is_the_reference_date() {
+        use crate::runner::evaluator::evaluate;
+        use crate::runner::trace::ConditionTrace;
+
+        let rule_set = parse_rules(
+            "An **invoice** is recent \
+             if the __due date__ of the **invoice** is later than today minus 14 days.",
+        )
+        .unwrap();
+        let options =
+            EvaluationOptions::new().with_reference_date(NaiveDate::from_ymd_opt(2025, 1, 20));
+
+        for (due, recent) in [("2025-01-10", true), ("2025-01-06", false)] {
+            let outcome = evaluate(&rule_set, &json!({"invoice": {"dueDate": due}}), &options);
+            assert!(outcome.error.is_none(), "{:?}", outcome.error);
+            assert_eq!(outcome.result, recent, "{}", due);
+            let trace = outcome.trace.unwrap();
+            let ConditionTrace::Comparison(comparison) = &trace.execution[0].conditions[0] else {
+                panic!("Expected a comparison");
+            };
+            assert_eq!(comparison.value.value, json!("2025-01-06"));
+        }
+
+        // Unshifted, and without `as_of` it is the day of the evaluation
+        let rule_set = parse_rules(
+            "An **invoice** is due \
+             if the __due date__ of the **invoice** is earlier than today.",
+        )
+        .unwrap();
+        let data = json!({"invoice": {"dueDate": "2025-01-19"}});
+        assert!(evaluate(&rule_set, &data, &options).result);
+        let data = json!({"invoice": {"dueDate": "2025-01-20"}});
+        assert!(!evaluate(&rule_set, &data, &options).result);
+        assert!(evaluate(&rule_set, &data, &EvaluationOptions::new()).result);
+    }
+
+    #[test]
+    fn test_only_whole_day_offsets_of_dates_parse() {
+        for condition in [
+            "is earlier than 2024-01-31 plus 3 hours",
+            "is later than today plus 3 hours",
+            "is later than today minus 14",
+            "is equal to today's date",
+            "is earlier than 2024-01-31 plus 1.5 months",
+            "is greater than 18 plus 2 days",
+        ] {
+            let text = format!(
+                "An **invoice** is on time if the __paid on__ of the **invoice** {}.",
+                condition
+            );
+            assert!(parse_rules(&text).is_err(), "{}", condition);
+        }
+    }
+
+    #[test]
+    fn test_hash_bucket_values_are_pinned() {
+        // Other systems reproduce samples from these, so they must never change
+        assert_eq!(hash_bucket("user-1"), 42);
+        assert_eq!(hash_bucket("user-2"), 37);
+        assert_eq!(hash_bucket("alice"), 42);
+        assert_eq!(hash_bucket("42"), 21);
+        assert_eq!(hash_bucket("true"), 82);
+        assert_eq!(hash_bucket(""), 24);
+    }
+
+    #[test]
+    fn test_sample_forms_compare_the_hash_bucket() {
+        use crate::runner::evaluator::evaluate;
+        use crate::runner::trace::ConditionTrace;
+
+        let sample = parse_rules(
+            "A **user** gets the new checkout if the **user** is in the 40% sample by __user id__.",
+        )
+        .unwrap();
+        let bucket = parse_rules(
+            "A **user** gets the new checkout \
+             if the hash bucket of __user id__ of the **user** is less than 40.",
+        )
+        .unwrap();
+        let options = EvaluationOptions::new();
+
+        for (id, bucket_value, included) in [
+            (json!("user-1"), 42, false),
+            (json!("user-2"), 37, true),
+            // Numbers are hashed as written, so 42 shares the bucket of "42"
+            (json!(42), 21, true),
+        ] {
+            let data = json!({"user": {"userId": id}});
+            for rule_set in [&sample, &bucket] {
+                let outcome = evaluate(rule_set, &data, &options);
+                assert_eq!(outcome.result, included, "{}", id);
+                let trace = outcome.trace.unwrap();
+                let ConditionTrace::Comparison(comparison) = &trace.execution[0].conditions[0]
+                else {
+                    panic!("Expected a comparison");
+                };
+                assert_eq!(comparison.property.path, "$.user.userId.hash_bucket");
+                assert_eq!(comparison.property.value, json!(bucket_value as f64));
+            }
+        }
+
+        // Null ids aren't in any sample
+        let outcome = evaluate(&sample, &json!({"user": {"userId": null}}), &options);
+        assert!(!outcome.result && outcome.error.is_none());
+
+        let outcome = evaluate(&sample, &json!({"user": {"userId": [1]}}), &options);
+        let error = outcome.error.unwrap();
+        assert!(matches!(error, RuleError::TypeError(_)));
+        assert!(error.to_string().contains("$.user.userId"), "{}", error);
+    }
+
+    #[test]
+    fn test_sample_size_is_a_whole_percentage() {
+        for size in ["150", "2.5"] {
+            let text = format!(
+                "A **user** gets the new checkout if the **user** is in the {}% sample by __user id__.",
+                size
+            );
+            let error = parse_rules(&text).unwrap_err();
+            assert!(error.to_string().contains("whole percentages"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_condition_fragments() {
+        use crate::runner::trace::ConditionTrace;
+
+        let data = json!({
+            "user": {
+                "age": 30,
+                "role": "admin",
+                "account": {"balance": 120, "limit": 100},
+            }
+        });
+        let options = EvaluationOptions::new();
+
+        let (result, trace) = evaluate_condition(
+            "the __age__ of the **user** is greater than 18",
+            &data,
+            &options,
+        )
+        .unwrap();
+        assert!(result);
+        match trace {
+            ConditionTrace::Comparison(comparison) => {
+                assert_eq!(comparison.property.path, "$.user.age");
+                // Positions point into the fragment itself
+                let pos = comparison.value.pos.unwrap();
+                assert_eq!((pos.line, pos.start), (1, 45));
+            }
+            _ => panic!("Expected comparison trace"),
+        }
+
+        let chained = "the __balance__ of the __account__ of the **user** is greater than __limit__ of the __account__ of the **user**.";
+        assert!(evaluate_condition(chained, &data, &options).unwrap().0);
+
+        let listed = r#"the __role__ of the **user** is in ["admin", "owner"]"#;
+        assert!(evaluate_condition(listed, &data, &options).unwrap().0);
+        let not_listed = r#"the __role__ of the **user** is not in ["admin", "owner"]"#;
+        assert!(!evaluate_condition(not_listed, &data, &options).unwrap().0);
+    }
+
+    #[test]
+    fn test_evaluate_condition_errors_are_relative_to_the_fragment() {
+        let error = evaluate_condition(
+            "the __age__ of the **user** is bigger than 18",
+            &json!({}),
+            &EvaluationOptions::new(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, RuleError::ParseError { .. }));
+        assert!(error.to_string().contains(" --> 1:29"), "{}", error);
+
+        // A whole rule is not a condition
+        assert!(evaluate_condition(
+            "A **user** is adult if the __age__ of the **user** is greater than 18.",
+            &json!({}),
+            &EvaluationOptions::new(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_context_is_read_apart_from_the_data() {
+        use crate::runner::trace::ConditionTrace;
+
+        let rule_set = parse_rules(
+            r#"A **order** is approved if the __total__ of the **order** is less than 500 or the __channel__ of the **context** is equal to "branch"."#,
+        )
+        .unwrap();
+        let data = json!({"order": {"total": 900}});
+
+        let branch = EvaluationOptions::new().with_context(json!({"channel": "branch"}));
+        let web = EvaluationOptions::new().with_context(json!({"channel": "web"}));
+        let approved =
+            |options: &EvaluationOptions| evaluate(&rule_set, &data, options).outcomes["approved"];
+        assert!(approved(&branch));
+        assert!(!approved(&web));
+
+        let trace = evaluate(&rule_set, &data, &branch).trace.unwrap();
+        match &trace.execution[0].conditions[1] {
+            ConditionTrace::Comparison(comparison) => {
+                assert_eq!(comparison.property.path, "$context.channel");
+                assert_eq!(comparison.property.value, json!("branch"));
+            }
+            _ => panic!("Expected comparison trace"),
+        }
+
+        // A context key in the data is not the context
+        let posing = json!({"order": {"total": 900}, "context": {"channel": "branch"}});
+        let outcome = evaluate(&rule_set, &posing, &web);
+        assert!(!outcome.outcomes["approved"]);
+    }
+
+    #[test]
+    fn test_missing_context_is_missing_data() {
+        let rule_set = parse_rules(
+            r#"A **request** is internal if the __region__ of the **context** is equal to "eu"."#,
+        )
+        .unwrap();
+        let data = json!({"context": {"region": "eu"}});
+
+        // Without a context the rule reads as if the data had no `context` at all
+        let evaluation = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        let expected = evaluate(&rule_set, &json!({}), &EvaluationOptions::new());
+        assert_eq!(evaluation.outcomes, expected.outcomes);
+        assert_eq!(
+            format!("{:?}", evaluation.error),
+            format!("{:?}", expected.error)
+        );
+
+        let (result, _trace) = evaluate_condition(
+            r#"the __region__ of the **context** is equal to "eu""#,
+            &data,
+            &EvaluationOptions::new().with_context(json!({"region": "eu"})),
+        )
+        .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn test_non_finite_literal_is_never_compared() {
+        use crate::runner::trace::ConditionTrace;
+
+        // Too many digits for a float, so the literal parses to infinity
+        let huge = format!("1{}", "0".repeat(400));
+        let rule_set = parse_rules(&format!(
+            "A **user** is low risk if the __score__ of the **user** is less than {}.",
+            huge
+        ))
+        .unwrap();
+        let data = json!({"user": {"score": 5}});
+
+        let strict = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        match strict.error {
+            Some(RuleError::NonFiniteNumber { path }) => assert_eq!(path, "the rule's value"),
+            other => panic!("Expected NonFiniteNumber, got {:?}", other),
+        }
+
+        let lenient = evaluate(
+            &rule_set,
+            &data,
+            &EvaluationOptions::new().with_lenient(true),
+        );
+        assert!(!lenient.outcomes["low risk"]);
+        let trace = lenient.trace.unwrap();
+        match &trace.execution[0].conditions[0] {
+            ConditionTrace::Comparison(comparison) => {
+                let details = comparison.evaluation_details.as_ref().unwrap();
+                assert_eq!(details.right_value.value, json!("Infinity"));
+                assert_eq!(
+                    details.warning.as_deref(),
+                    Some("the rule's value is not a finite number, so the comparison is false")
+                );
+                // The trace still serializes to valid JSON
+                let text = serde_json::to_string(&trace).unwrap();
+                assert!(serde_json::from_str::<serde_json::Value>(&text).is_ok());
+            }
+            _ => panic!("Expected comparison trace"),
+        }
+    }
+
+    #[test]
+    fn test_duration_overflowing_to_infinity_is_never_compared() {
+        // The amount is finite but overflows once converted to seconds, which used to make
+        // every date younger than it
+        let rule_set = parse_rules(&format!(
+            "A **user** is new if the __joined__ of the **user** is younger than 1{} centuries.",
+            "0".repeat(300)
+        ))
+        .unwrap();
+        let data = json!({"user": {"joined": "2020-01-01"}});
+
+        let strict = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        assert!(matches!(
+            strict.error,
+            Some(RuleError::NonFiniteNumber { .. })
+        ));
+
+        let lenient = evaluate(
+            &rule_set,
+            &data,
+            &EvaluationOptions::new().with_lenient(true),
+        );
+        assert!(!lenient.outcomes["new"]);
+    }
+
+    #[test]
+    fn test_list_compared_with_scalar_operator_matrix() {
+        use crate::runner::options::ListScalarMode;
+        use crate::runner::trace::ConditionTrace;
+
+        let data = json!({"user": {
+            "scores": [3, 7, 12],
+            "tags": ["Gold", "silver"],
+            "logins": ["2024-01-05", "2024-03-10"],
+        }});
+        // (condition, any result and deciding element, all result and deciding element)
+        let cases = [
+            (
+                "the __scores__ of the **user** is greater than 10",
+                (true, Some(2)),
+                (false, Some(0)),
+            ),
+            (
+                "the __scores__ of the **user** is greater than 1",
+                (true, Some(0)),
+                (true, None),
+            ),
+            (
+                "the __scores__ of the **user** is less than 2",
+                (false, None),
+                (false, Some(0)),
+            ),
+            (
+                "the __scores__ of the **user** is greater than or equal to 3",
+                (true, Some(0)),
+                (true, None),
+            ),
+            (
+                "the __scores__ of the **user** is less than or equal to 7",
+                (true, Some(0)),
+                (false, Some(2)),
+            ),
+            (
+                r#"the __tags__ of the **user** is equal to "gold""#,
+                (true, Some(0)),
+                (false, Some(1)),
+            ),
+            (
+                r#"the __tags__ of the **user** is not equal to "bronze""#,
+                (true, Some(0)),
+                (true, None),
+            ),
+            (
+                r#"the __tags__ of the **user** is exactly equal to "gold""#,
+                (false, None),
+                (false, Some(0)),
+            ),
+            (
+                "the __logins__ of the **user** is later than date(2024-02-01)",
+                (true, Some(1)),
+                (false, Some(0)),
+            ),
+            (
+                "the __logins__ of the **user** is earlier than date(2024-12-31)",
+                (true, Some(0)),
+                (true, None),
+            ),
+        ];
+
+        for (condition, any, all) in cases {
+            let error =
+                evaluate_condition(condition, &data, &EvaluationOptions::new()).unwrap_err();
+            assert!(
+                matches!(&error, RuleError::ListComparedWithScalar { path, .. } if path.starts_with("$.user.")),
+                "{}: {:?}",
+                condition,
+                error
+            );
+
+            for (mode, (expected, deciding)) in [
+                (ListScalarMode::AnyElement, any),
+                (ListScalarMode::AllElements, all),
+            ] {
+                let options = EvaluationOptions::new().with_list_scalar_mode(mode);
+                let (result, trace) = evaluate_condition(condition, &data, &options).unwrap();
+                assert_eq!(result, expected, "{} with {:?}", condition, mode);
+                let ConditionTrace::Comparison(comparison) = trace else {
+                    panic!("Expected comparison trace");
+                };
+                let details = comparison.evaluation_details.unwrap();
+                assert_eq!(details.list_mode, Some(mode));
+                assert_eq!(
+                    details.deciding_element, deciding,
+                    "{} with {:?}",
+                    condition, mode
+                );
+            }
+        }
+
+        // An empty list passes neither way
+        let empty = json!({"user": {"scores": []}});
+        for mode in [ListScalarMode::AnyElement, ListScalarMode::AllElements] {
+            let options = EvaluationOptions::new().with_list_scalar_mode(mode);
+            let condition = "the __scores__ of the **user** is greater than 1";
+            assert!(!evaluate_condition(condition, &empty, &options).unwrap().0);
+        }
+    }
+
+    fn range_condition(condition: &str, data: &serde_json::Value) -> Result<bool, RuleError> {
+        let options = EvaluationOptions::new().with_range_objects(true);
+        evaluate_condition(condition, data, &options).map(|(result, _)| result)
+    }
+
+    #[test]
+    fn test_range_objects_bound_comparisons() {
+        let config = |range: serde_json::Value| json!({"config": {"allowedAge": range}});
+        let closed = config(json!({"min": 17, "max": 70}));
+        let within = "the __age__ of the **user** is within the __allowed age__ of the **config**";
+        let above =
+            "the __age__ of the **user** is greater than the __allowed age__ of the **config**";
+        let below =
+            "the __age__ of the **user** is less than the __allowed age__ of the **config**";
+
+        let cases = [
+            (within, 17, true),
+            (within, 70, true),
+            (within, 16, false),
+            (within, 71, false),
+            (above, 71, true),
+            (above, 70, false),
+            (below, 16, true),
+            (below, 17, false),
+        ];
+        for (condition, age, expected) in cases {
+            let mut data = closed.clone();
+            data["user"] = json!({"age": age});
+            assert_eq!(
+                range_condition(condition, &data).unwrap(),
+                expected,
+                "{} {}",
+                condition,
+                age
+            );
+        }
+
+        // Open-ended ranges: a missing or null bound leaves that end open
+        let mut data = config(json!({"min": 17}));
+        data["user"] = json!({"age": 120});
+        assert!(range_condition(within, &data).unwrap());
+        assert!(!range_condition(above, &data).unwrap());
+        let mut data = config(json!({"min": null, "max": 70}));
+        data["user"] = json!({"age": -5});
+        assert!(range_condition(within, &data).unwrap());
+        assert!(!range_condition(below, &data).unwrap());
+    }
+
+    #[test]
+    fn test_range_objects_are_opt_in_and_traced() {
+        use crate::runner::trace::ConditionTrace;
+
+        let data = json!({
+            "user": {"age": 30},
+            "config": {"allowedAge": {"min": 17, "max": 70}},
+        });
+        let within = "the __age__ of the **user** is within the __allowed age__ of the **config**";
+
+        // Without the option an object can't be compared at all
+        let error = evaluate_condition(within, &data, &EvaluationOptions::new()).unwrap_err();
+        assert!(matches!(error, RuleError::TypeError(_)), "{:?}", error);
+
+        let options = EvaluationOptions::new().with_range_objects(true);
+        let (result, trace) = evaluate_condition(within, &data, &options).unwrap();
+        assert!(result);
+        let ConditionTrace::Comparison(comparison) = trace else {
+            panic!("Expected a comparison trace");
+        };
+        let details = comparison.evaluation_details.unwrap();
+        assert_eq!(details.right_value.value_type, "range");
+        assert_eq!(details.right_value.value, json!({"min": 17.0, "max": 70.0}));
+        assert_eq!(
+            comparison.value.path.as_deref(),
+            Some("$.config.allowedAge")
+        );
+
+        let equal = "the __age__ of the **user** is equal to the __allowed age__ of the **config**";
+        let error = range_condition(equal, &data).unwrap_err();
+        assert!(
+            error.to_string().contains("can't compare with a range"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_objects_with_other_keys_are_not_ranges() {
+        use crate::runner::evaluator::NumericRange;
+
+        assert_eq!(
+            NumericRange::from_json(&json!({"max": 3})),
+            Some(NumericRange {
+                min: None,
+                max: Some(3.0)
+            })
+        );
+        assert!(NumericRange::from_json(&json!({"min": 1, "unit": "years"})).is_none());
+        assert!(NumericRange::from_json(&json!({"min": "1"})).is_none());
+        assert!(NumericRange::from_json(&json!({})).is_none());
+        assert!(NumericRange::from_json(&json!({"min": null})).is_none());
+        assert!(NumericRange::from_json(&json!([1, 2])).is_none());
+    }
+
+    #[test]
+    fn test_data_root_reads_selectors_from_a_nested_object() {
+        use crate::runner::evaluator::evaluate;
+
+        let rule_set = parse_rules(
+            "A **Person** gets a licence if the __age__ of the **Person** is at least 18.",
+        )
+        .unwrap();
+        let data = json!({
+            "envelope": {
+                "meta": {"source": "crm"},
+                "payload": {"Person": {"age": 30}},
+                "history": [{"Person": {"age": 12}}],
+            }
+        });
+
+        let options = EvaluationOptions::new().with_data_root(Some("$.envelope.payload".into()));
+        let outcome = evaluate(&rule_set, &data, &options);
+        assert!(outcome.result, "{:?}", outcome.error);
+        // Paths still start at the top of the payload
+        let trace = serde_json::to_value(outcome.trace.unwrap()).unwrap();
+        assert_eq!(
+            trace["execution"][0]["conditions"][0]["property"]["path"],
+            "$.envelope.payload.Person.age"
+        );
+
+        let options = EvaluationOptions::new().with_data_root(Some("$.envelope.history[0]".into()));
+        assert!(!evaluate(&rule_set, &data, &options).result);
+
+        // Without a root the selector isn't at the top
+        assert!(!evaluate(&rule_set, &data, &EvaluationOptions::new()).result);
+
+        let condition = "the __age__ of the **Person** is at least 18";
+        let options = EvaluationOptions::new().with_data_root(Some("$.envelope.payload".into()));
+        let (result, _) = evaluate_condition(condition, &data, &options).unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn test_data_root_must_be_an_object_in_the_data() {
+        use crate::runner::error::{DataRootError, DataRootProblem};
+        use crate::runner::evaluator::evaluate;
+
+        let rule_set = parse_rules(
+            "A **Person** gets a licence if the __age__ of the **Person** is at least 18.",
+        )
+        .unwrap();
+        let data = json!({"envelope": {"payload": [{"Person": {"age": 30}}]}});
+        let root_error = |root: &str| {
+            let options = EvaluationOptions::new().with_data_root(Some(root.to_string()));
+            let outcome = evaluate(&rule_set, &data, &options);
+            assert!(!outcome.result);
+            assert!(outcome.trace.is_none());
+            match outcome.error {
+                Some(RuleError::InvalidDataRoot(error)) => error,
+                other => panic!("Expected an invalid data root, got {:?}", other),
+            }
+        };
+
+        assert_eq!(
+            root_error("$.envelope.payload"),
+            DataRootError {
+                root: "$.envelope.payload".to_string(),
+                problem: DataRootProblem::NotAnObject {
+                    found: "an array".to_string()
+                },
+            }
+        );
+        assert_eq!(
+            root_error("$.envelope.body").problem,
+            DataRootProblem::NotFound
+        );
+        assert_eq!(root_error("envelope").problem, DataRootProblem::InvalidPath);
+        assert_eq!(
+            root_error("$.envelope.payload[*]").problem,
+            DataRootProblem::InvalidPath
+        );
+        assert!(
+            evaluate(
+                &rule_set,
+                &data,
+                &EvaluationOptions::new().with_data_root(Some("$.envelope.payload[0]".into()))
+            )
+            .result
+        );
+    }
+
+    #[test]
+    fn test_rule_referenced_by_label_and_outcome_is_traced_once() {
+        use crate::runner::evaluator::evaluate;
+        use crate::runner::trace::{ConditionTrace, ReferenceResolution};
+
+        let rule_set = parse_rules(
+            "A **driver** gets a driving licence\n  if the **driver** passes the age test\n  and §driver.age is valid.\n\n\
+             driver.age. A **driver** passes the age test\n  if the __age__ of the **driver** is greater than or equal to 18.",
+        )
+        .unwrap();
+        let outcome = evaluate(
+            &rule_set,
+            &json!({"driver": {"age": 30}}),
+            &EvaluationOptions::new(),
+        );
+        assert!(outcome.result, "{:?}", outcome.error);
+
+        let trace = outcome.trace.unwrap();
+        let references: Vec<_> = trace.execution[0]
+            .conditions
+            .iter()
+            .map(|condition| match condition {
+                ConditionTrace::RuleReference(reference) => (
+                    reference.referenced_rule_outcome.clone().unwrap(),
+                    reference.resolution.clone(),
+                ),
+                _ => panic!("Expected a rule reference"),
+            })
+            .collect();
+        assert_eq!(
+            references,
+            vec![
+                (
+                    "age test".to_string(),
+                    ReferenceResolution::FuzzyOutcome {
+                        matched: "age test".to_string()
+                    }
+                ),
+                ("age test".to_string(), ReferenceResolution::Label),
+            ]
+        );
+
+        // Both references share one node, which carries the label and the outcome
+        assert_eq!(trace.execution.len(), 2);
+        let shared = &trace.execution[1];
+        assert_eq!(shared.label.as_deref(), Some("driver.age"));
+        assert_eq!(shared.outcome.value, "the age test");
+        let age_comparisons = trace
+            .execution
+            .iter()
+            .flat_map(|rule| &rule.conditions)
+            .filter(|condition| {
+                matches!(condition, ConditionTrace::Comparison(comparison)
+                    if comparison.property.path == "$.driver.age")
+            })
+            .count();
+        assert_eq!(age_comparisons, 1);
+    }
+
+    #[test]
+    fn test_frozen_rule_set_evaluates_the_same_across_threads() {
+        let rule_set = parse_rules(
+            r#"
+A **driver** gets a driving licence
+  if the **driver** passes the age test
+  and the **driver** passes the eyesight checks
+  and §vision is valid.
+
+A **driver** passes the age test
+  if the __age__ of the **driver** is greater than or equal to 18.
+
+vision. A **driver** passes the eyesight checks
+  if the __vision__ of the **driver** is greater than 0.5
+  or the __glasses__ of the **driver** is equal to true.
+"#,
+        )
+        .unwrap()
+        .freeze();
+        let data = |i: usize| json!({"driver": {"age": 14 + i % 8, "vision": (i % 5) as f64 / 4.0, "glasses": i.is_multiple_of(3)}});
+        let expected: Vec<_> = (0..64)
+            .map(|i| {
+                let outcome = evaluate(&rule_set, &data(i), &EvaluationOptions::new());
+                (outcome.result, outcome.outcomes)
+            })
+            .collect();
+
+        let threads: Vec<_> = (0..16)
+            .map(|_| {
+                let rule_set = Arc::clone(&rule_set);
+                std::thread::spawn(move || {
+                    (0..64)
+                        .map(|i| {
+                            let outcome = evaluate(&rule_set, &data(i), &EvaluationOptions::new());
+                            (outcome.result, outcome.outcomes)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), expected);
+        }
+        assert!(expected.iter().any(|(result, _)| *result));
+        assert!(expected.iter().any(|(result, _)| !*result));
+    }
+
+    /// Flag states for tests, counting how often each was asked for
+    #[derive(Debug, Default)]
+    struct StubFlags {
+        enabled: HashMap<String, bool>,
+        calls: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl StubFlags {
+        fn with(flags: &[(&str, bool)]) -> Arc<Self> {
+            Arc::new(Self {
+                enabled: flags.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+                calls: Default::default(),
+            })
+        }
+    }
+
+    impl FlagProvider for StubFlags {
+        fn is_enabled(&self, flag: &str) -> Option<bool> {
+            self.calls.lock().unwrap().push(flag.to_string());
+            self.enabled.get(flag).copied()
+        }
+    }
+
+    const BETA_CHECKOUT: &str = r#"
+A **user** gets beta checkout
+  if flag "new-checkout" is enabled
+  and the __age__ of the **user** is at least 18.
+
+A **user** gets the classic checkout
+  if flag "new-checkout" is disabled.
+"#;
+
+    #[test]
+    fn test_flag_conditions_follow_the_provider() {
+        use crate::runner::trace::ConditionTrace;
+
+        let rule_set = parse_rule_set(BETA_CHECKOUT).unwrap();
+        let data = json!({"user": {"age": 30}});
+        let entries = vec!["beta checkout".to_string(), "classic checkout".to_string()];
+
+        for enabled in [true, false] {
+            let flags = StubFlags::with(&[("new-checkout", enabled)]);
+            let options = EvaluationOptions::new()
+                .with_entries(entries.clone())
+                .with_flags(flags.clone());
+            let outcome = evaluate(&rule_set, &data, &options);
+            assert!(outcome.error.is_none(), "{:?}", outcome.error);
+            assert_eq!(outcome.outcomes["beta checkout"], enabled);
+            assert_eq!(outcome.outcomes["classic checkout"], !enabled);
+            assert!(outcome.warnings.is_empty());
+            // Both rules read the flag, the provider is asked once
+            assert_eq!(*flags.calls.lock().unwrap(), ["new-checkout"]);
+
+            let trace = outcome.trace.unwrap();
+            let ConditionTrace::Flag(flag) = &trace.execution[0].conditions[0] else {
+                panic!("expected a flag trace");
+            };
+            assert_eq!(flag.flag, "new-checkout");
+            assert!(flag.expected);
+            assert_eq!(flag.enabled, enabled);
+            assert!(flag.known);
+            assert_eq!(flag.result, enabled);
+        }
+    }
+
+    #[test]
+    fn test_unknown_flags_take_the_default_with_a_warning() {
+        let rule_set = parse_rule_set(BETA_CHECKOUT).unwrap();
+        let data = json!({"user": {"age": 30}});
+        let entries = vec!["beta checkout".to_string()];
+
+        let options = EvaluationOptions::new()
+            .with_entries(entries.clone())
+            .with_flags(StubFlags::with(&[]));
+        let outcome = evaluate(&rule_set, &data, &options);
+        assert!(!outcome.result);
+        assert_eq!(outcome.warnings.len(), 1);
+        assert_eq!(
+            outcome.warnings[0].message,
+            "flag 'new-checkout' is unknown, taken as disabled"
+        );
+
+        // Without a provider every flag is unknown
+        let options = EvaluationOptions::new()
+            .with_entries(entries)
+            .with_unknown_flag_default(true);
+        let outcome = evaluate(&rule_set, &data, &options);
+        assert!(outcome.result);
+        assert_eq!(
+            outcome.warnings[0].message,
+            "flag 'new-checkout' is unknown, taken as enabled"
+        );
+    }
+
+    /// `levels` lists nested one inside the next around a number
+    fn nested_lists(levels: usize) -> Value {
+        let mut value = json!(1);
+        for _ in 0..levels {
+            value = Value::Array(vec![value]);
+        }
+        value
+    }
+
+    #[test]
+    fn test_deeply_nested_data_is_refused() {
+        let rule_set = parse_rules(
+            "A **user** gets access\n  if the number of __history__ of the **user** is at least 1\n  or the __name__ of the **user** is equal to \"ada\".",
+        )
+        .unwrap();
+        let options = EvaluationOptions::new();
+
+        let outcome = evaluate(
+            &rule_set,
+            &json!({"user": {"history": nested_lists(1000), "name": "ada"}}),
+            &options,
+        );
+        match &outcome.error {
+            Some(RuleError::DataTooDeep { path, limit }) => {
+                assert_eq!(path, "$.user.history");
+                assert_eq!(*limit, 64);
+            }
+            other => panic!("Expected DataTooDeep, got {:?}", other),
+        }
+
+        // Reads that stay under the limit are unaffected, however deep the document goes
+        // elsewhere: two keys and 62 lists fit in 64 levels
+        let outcome = evaluate(
+            &rule_set,
+            &json!({"user": {"history": nested_lists(62), "name": "ada"}}),
+            &options,
+        );
+        assert!(outcome.error.is_none(), "{:?}", outcome.error);
+        assert!(outcome.result);
+        let outcome = evaluate(
+            &rule_set,
+            &json!({"user": {"history": nested_lists(63)}}),
+            &options,
+        );
+        assert!(matches!(outcome.error, Some(RuleError::DataTooDeep { .. })));
+
+        let shallow = EvaluationOptions::new().with_max_data_depth(1);
+        let outcome = evaluate(&rule_set, &json!({"user": {"history": 1}}), &shallow);
+        match &outcome.error {
+            Some(RuleError::DataTooDeep { path, limit }) => {
+                assert_eq!(path, "$.user.history");
+                assert_eq!(*limit, 1);
+            }
+            other => panic!("Expected DataTooDeep, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_large_objects_are_not_scanned_for_other_spellings() {
+        let rule_set = parse_rules(
+            "A **driver** passes the score check\n  if the __driving score__ of the **driver** is at least 60.",
+        )
+        .unwrap();
+        let options = EvaluationOptions::new().with_max_scanned_keys(100);
+        let driver = |keys: usize, score_key: &str| {
+            let mut object = serde_json::Map::new();
+            for i in 0..keys {
+                object.insert(format!("field {}", i), json!(i));
+            }
+            object.insert(score_key.to_string(), json!(75));
+            json!({ "driver": object })
+        };
+
+        // Small objects are scanned as before
+        let outcome = evaluate(&rule_set, &driver(10, "driving_score"), &options);
+        assert!(outcome.result);
+        assert!(outcome.warnings.is_empty());
+
+        // A large one still matches the name as written or camelCased
+        let outcome = evaluate(&rule_set, &driver(200, "drivingScore"), &options);
+        assert!(outcome.result);
+        assert!(outcome.warnings.is_empty());
+
+        // But isn't searched for any other spelling, and says so
+        let outcome = evaluate(&rule_set, &driver(200, "driving_score"), &options);
+        assert!(outcome.error.is_none());
+        assert!(!outcome.result);
+        assert_eq!(outcome.warnings.len(), 1);
+        assert_eq!(outcome.warnings[0].path, "$.driver['driving score']");
+        assert_eq!(
+            outcome.warnings[0].message,
+            "'driving score' was only looked for as 'driving score' and 'drivingScore': its object has 201 keys, more than the 100 searched for other spellings"
+        );
+    }
+
+    const TAGGED_RULES: &str = r#"
+A **customer** is onboarded
+  if the **customer** passes the identity check
+  and the **customer** passes the screening
+  or the **customer** is a staff member.
+
+Tags: kyc.
+A **customer** passes the identity check
+  if the __document__ of the **customer** is equal to "verified".
+
+Tags: fraud, Screening.
+A **customer** passes the screening
+  if the __risk score__ of the **customer** is less than 50.
+
+Tags: hr.
+A **customer** is a staff member
+  if the __employee__ of the **customer** is equal to true.
+"#;
+
+    #[test]
+    fn test_tag_filters_change_the_decision() {
+        let rule_set = parse_rules(TAGGED_RULES).unwrap();
+        assert_eq!(rule_set.rules[2].tags, vec!["fraud", "screening"]);
+        let data =
+            json!({"customer": {"document": "verified", "riskScore": 80, "employee": false}});
+        let decide = |options: EvaluationOptions| evaluate(&rule_set, &data, &options);
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+
+        let outcome = decide(EvaluationOptions::new());
+        assert!(!outcome.result);
+        assert_eq!(outcome.outcomes.len(), 4);
+
+        // Without the screening, the identity check decides its side of the `or`
+        let outcome = decide(EvaluationOptions::new().with_exclude_tags(tags(&["FRAUD"])));
+        assert!(outcome.result, "{:?}", outcome.error);
+        assert!(!outcome.outcomes.contains_key("passes the screening"));
+        let trace = outcome.trace.unwrap();
+        assert!(trace.execution.iter().any(|rule| rule.skipped));
+        assert!(!trace.execution[0].skipped);
+
+        // Only kyc: the screening and staff rules are skipped, untagged rules still run
+        let outcome = decide(EvaluationOptions::new().with_include_tags(tags(&["kyc"])));
+        assert!(outcome.result);
+        assert_eq!(outcome.outcomes.len(), 2);
+
+        // Only fraud: the screening fails, and nothing else is left to pass
+        let outcome = decide(EvaluationOptions::new().with_include_tags(tags(&["fraud"])));
+        assert!(!outcome.result);
+
+        // Exclusion wins over inclusion
+        let options = EvaluationOptions::new()
+            .with_include_tags(tags(&["kyc", "screening"]))
+            .with_exclude_tags(tags(&["screening"]));
+        assert!(decide(options).result);
+    }
+
+    #[test]
+    fn test_a_rule_with_every_condition_skipped_is_skipped() {
+        let rule_set = parse_rules(TAGGED_RULES).unwrap();
+        let data = json!({"customer": {"document": "verified", "riskScore": 10}});
+        let options = EvaluationOptions::new().with_exclude_tags(vec![
+            "kyc".to_string(),
+            "fraud".to_string(),
+            "hr".to_string(),
+        ]);
+
+        let outcome = evaluate(&rule_set, &data, &options);
+        assert!(outcome.error.is_none());
+        // The global rule decided nothing, so the result is false, as for a missing entry
+        assert!(!outcome.result);
+        assert!(outcome.outcomes.is_empty());
+        let trace = outcome.trace.unwrap();
+        assert!(trace.execution.iter().all(|rule| rule.skipped));
+        let reference = serde_json::to_value(&trace.execution[0].conditions[0]).unwrap();
+        assert_eq!(reference["skipped"], true);
+    }
+
+    const NEGATED_RULES: &str = r#"
+A **person** gets a provisional licence
+  if the **person** does not pass the practical driving test
+  and not the __banned__ of the **person** is equal to true
+  or the __age__ of the **person** is less than 17.
+
+A **person** passes the practical driving test
+  if the __practical score__ of the **person** is at least 40.
+"#;
+
+    #[test]
+    fn test_negated_references_and_comparisons() {
+        let rule_set = parse_rules(NEGATED_RULES).unwrap();
+        assert!(rule_set.rules[0].conditions[0].negated);
+        assert!(rule_set.rules[0].conditions[1].negated);
+        assert!(!rule_set.rules[0].conditions[2].negated);
+        let decide = |person: Value| {
+            evaluate(
+                &rule_set,
+                &json!({ "person": person }),
+                &EvaluationOptions::new(),
+            )
+        };
+
+        // Failed the test and not banned
+        let outcome = decide(json!({"practicalScore": 20, "banned": false, "age": 30}));
+        assert!(outcome.error.is_none());
+        assert!(outcome.result);
+
+        // Passed the test: the negated reference fails, and so does the `and` run
+        let outcome = decide(json!({"practicalScore": 45, "banned": false, "age": 30}));
+        assert!(!outcome.result);
+        let trace = serde_json::to_value(outcome.trace.unwrap()).unwrap();
+        let reference = &trace["execution"][0]["conditions"][0];
+        assert_eq!(reference["inner_result"], true);
+        assert_eq!(reference["result"], false);
+        assert_eq!(
+            reference["referenced_rule_outcome"],
+            "practical driving test"
+        );
+        let comparison = &trace["execution"][0]["conditions"][1];
+        assert_eq!(comparison["inner_result"], false);
+        assert_eq!(comparison["result"], true);
+        // Not negated, so left out
+        assert!(trace["execution"][0]["conditions"][2]
+            .get("inner_result")
+            .is_none());
+
+        // Banned: the negated comparison fails, but the `or` still lets the young through
+        assert!(!decide(json!({"practicalScore": 20, "banned": true, "age": 30})).result);
+        assert!(decide(json!({"practicalScore": 20, "banned": true, "age": 16})).result);
+    }
+
+    #[test]
+    fn test_double_negation_cancels_out() {
+        let rules = "A **person** gets a licence if not the **person** does not pass the test.\n\n\
+                     A **person** passes the test if the __score__ of the **person** is at least 40.";
+        let rule_set = parse_rules(rules).unwrap();
+        assert!(!rule_set.rules[0].conditions[0].negated);
+        let data = json!({"person": {"score": 45}});
+        assert!(evaluate(&rule_set, &data, &EvaluationOptions::new()).result);
+    }
+
+    #[test]
+    fn test_negated_single_condition() {
+        let data = json!({"user": {"role": "guest"}});
+        let (result, trace) = evaluate_condition(
+            r#"not the __role__ of the **user** is equal to "admin""#,
+            &data,
+            &EvaluationOptions::new(),
+        )
+        .unwrap();
+        assert!(result);
+        assert!(trace.is_negated());
+    }
+
+    #[test]
+    fn test_grouped_conditions_bind_before_the_rest() {
+        const FLAT: &str = r#"A **user** gets access if the __age__ of the **user** is at least 18 and the __role__ of the **user** is equal to "admin" or the __vip__ of the **user** is equal to true."#;
+        const GROUPED: &str = r#"A **user** gets access if the __age__ of the **user** is at least 18 and (the __role__ of the **user** is equal to "admin" or the __vip__ of the **user** is equal to true)."#;
+        let flat = parse_rules(FLAT).unwrap();
+        let grouped = parse_rules(GROUPED).unwrap();
+        let options = EvaluationOptions::new();
+
+        // An underage VIP gets in when AND binds first, not when the group does
+        let minor_vip = json!({"user": {"age": 16, "role": "guest", "vip": true}});
+        assert!(evaluate(&flat, &minor_vip, &options).result);
+        assert!(!evaluate(&grouped, &minor_vip, &options).result);
+
+        for (user, expected) in [
+            (json!({"age": 30, "role": "admin", "vip": false}), true),
+            (json!({"age": 30, "role": "guest", "vip": true}), true),
+            (json!({"age": 30, "role": "guest", "vip": false}), false),
+        ] {
+            let data = json!({ "user": user });
+            let outcome = evaluate(&grouped, &data, &options);
+            assert_eq!(outcome.result, expected, "{}", data);
+            assert_eq!(outcome.evaluations_used, 3);
+        }
+
+        // `not` in front of a group negates what the group came to
+        let negated = parse_rules(
+            r#"A **user** gets access if not (the __role__ of the **user** is equal to "banned" or the __age__ of the **user** is less than 18)."#,
+        )
+        .unwrap();
+        assert!(
+            evaluate(
+                &negated,
+                &json!({"user": {"role": "x", "age": 20}}),
+                &options
+            )
+            .result
+        );
+        assert!(
+            !evaluate(
+                &negated,
+                &json!({"user": {"role": "x", "age": 10}}),
+                &options
+            )
+            .result
+        );
+    }
+
+    #[test]
+    fn test_grouped_conditions_trace_their_branches() {
+        let rule_set = parse_rules(
+            r#"A **user** gets access if the __age__ of the **user** is at least 18 and (the __role__ of the **user** is equal to "admin" or the __vip__ of the **user** is equal to true and the __active__ of the **user** is equal to true)."#,
+        )
+        .unwrap();
+        let data = json!({"user": {"age": 30, "role": "guest", "vip": true, "active": true}});
+        let outcome = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        assert!(outcome.result);
+
+        let trace = serde_json::to_value(outcome.trace.unwrap()).unwrap();
+        let conditions = &trace["execution"][0]["conditions"];
+        assert_eq!(conditions.as_array().unwrap().len(), 2);
+        let group = &conditions[1];
+        let members = &rule_set.rules[0].conditions[1];
+        let Condition::Group(members) = &members.condition else {
+            panic!("expected a group");
+        };
+        assert_eq!(group["id"], json!(rule_set.rules[0].conditions[1].id));
+        assert_eq!(group["result"], true);
+        let results: Vec<&Value> = group["conditions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|member| &member["result"])
+            .collect();
+        assert_eq!(results, [false, true, true]);
+        // The second branch, vip and active, is the one that held
+        assert_eq!(group["branch"], json!([members[1].id, members[2].id]));
+    }
+
+    #[test]
+    fn test_between_includes_its_bounds_and_strictly_between_does_not() {
+        let options = EvaluationOptions::new();
+        let age = |condition: &str, age: serde_json::Value| {
+            let data = json!({"person": {"age": age}});
+            evaluate_condition(condition, &data, &options).map(|(result, _)| result)
+        };
+        let between = "the __age__ of the **person** is between 18 and 65";
+        let strictly = "the __age__ of the **person** is strictly between 18 and 65";
+
+        for (value, inclusive, exclusive) in [
+            (json!(17), false, false),
+            (json!(18), true, false),
+            (json!(40.5), true, true),
+            (json!(65), true, false),
+            (json!(66), false, false),
+        ] {
+            assert_eq!(age(between, value.clone()).unwrap(), inclusive, "{}", value);
+            assert_eq!(
+                age(strictly, value.clone()).unwrap(),
+                exclusive,
+                "{}",
+                value
+            );
+        }
+        // A string isn't a number between them
+        assert!(!age(between, json!("forty")).unwrap_or(false));
+    }
+
+    #[test]
+    fn test_between_compares_dates_and_traces_both_bounds() {
+        use crate::runner::trace::ConditionTrace;
+
+        let options = EvaluationOptions::new();
+        let condition =
+            "the __expiry date__ of the **subscription** is between 2024-01-01 and 2024-12-31";
+        let expiry = |date: &str| {
+            let data = json!({"subscription": {"expiry date": date}});
+            evaluate_condition(condition, &data, &options).unwrap()
+        };
+
+        assert!(expiry("2024-01-01").0);
+        assert!(expiry("2024-12-31").0);
+        assert!(!expiry("2025-01-01").0);
+
+        let (result, trace) = expiry("2024-06-15");
+        assert!(result);
+        let ConditionTrace::Comparison(comparison) = trace else {
+            panic!("Expected a comparison trace");
+        };
+        assert_eq!(comparison.value.value_type, "range");
+        assert_eq!(
+            comparison.value.value,
+            json!({"min": "2024-01-01", "max": "2024-12-31"})
+        );
+        let details = comparison.evaluation_details.unwrap();
+        assert_eq!(details.right_value.value_type, "range");
+        assert_eq!(details.left_value.value, json!("2024-06-15"));
+    }
+
+    #[test]
+    fn test_versions_order_component_by_component() {
+        use crate::runner::evaluator::Version;
+        use std::cmp::Ordering::{Equal, Greater, Less};
+
+        let pairs = [
+            ("2.9.0", "2.14.3", Less),
+            ("2.14.3", "2.14.3", Equal),
+            ("10.0", "9.99.99", Greater),
+            // Missing components count as zero
+            ("1.2", "1.2.0", Equal),
+            ("1.2", "1.2.0.1", Less),
+            ("1.02.0", "1.2", Equal),
+            // A pre-release comes before its release, and after the release before it
+            ("1.0.0-rc.1", "1.0.0", Less),
+            ("1.0.0-rc.1", "0.9.9", Greater),
+            // Pre-release identifiers: numbers as numbers, before text, fewer before more
+            ("1.0.0-alpha", "1.0.0-alpha.1", Less),
+            ("1.0.0-alpha.1", "1.0.0-alpha.beta", Less),
+            ("1.0.0-beta.2", "1.0.0-beta.11", Less),
+            ("1.0.0-beta", "1.0.0-alpha.9", Greater),
+            ("1.0.0-rc.1", "1.0.0-RC.1", Greater),
+            // Build metadata doesn't order
+            ("1.0.0+build.7", "1.0.0+build.3", Equal),
+            ("1.0.0-rc.1+build.1", "1.0.0", Less),
+        ];
+        for (left, right, expected) in pairs {
+            let (l, r) = (
+                Version::parse(left).unwrap(),
+                Version::parse(right).unwrap(),
+            );
+            assert_eq!(l.order(&r), expected, "{} against {}", left, right);
+            assert_eq!(
+                r.order(&l),
+                expected.reverse(),
+                "{} against {}",
+                right,
+                left
+            );
+        }
+
+        for text in [
+            "2",
+            "2.",
+            ".2",
+            "2..1",
+            "v2.1",
+            "2.1-",
+            "2.1-rc..1",
+            "2.x",
+            "2024-01-01",
+        ] {
+            assert!(Version::parse(text).is_none(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_ordering_operators_compare_version_strings() {
+        use crate::runner::trace::ConditionTrace;
+
+        let condition = "the __agent version__ of the **device** is at least \"2.14.3\"";
+        let version = |version: &str, options: &EvaluationOptions| {
+            let data = json!({"device": {"agent version": version}});
+            evaluate_condition(condition, &data, options).unwrap()
+        };
+        let options = EvaluationOptions::new();
+
+        assert!(!version("2.9.0", &options).0);
+        assert!(version("2.14.3", &options).0);
+        assert!(version("2.14.10", &options).0);
+        assert!(!version("2.14.3-rc.1", &options).0);
+
+        let (_, trace) = version("3.0", &options);
+        let ConditionTrace::Comparison(comparison) = trace else {
+            panic!("Expected a comparison trace");
+        };
+        let details = comparison.evaluation_details.unwrap();
+        assert_eq!(details.left_value.value_type, "version");
+        assert_eq!(details.right_value.value_type, "version");
+        assert!(details.comparison_result);
+
+        // A string that isn't a version isn't ordered, and neither are versions when opted out
+        assert!(!version("latest", &options).0);
+        let strings = EvaluationOptions::new().with_versions_as_strings(true);
+        let (result, trace) = version("3.0", &strings);
+        assert!(!result);
+        let ConditionTrace::Comparison(comparison) = trace else {
+            panic!("Expected a comparison trace");
+        };
+        assert!(comparison.evaluation_details.is_none());
+
+        // Equality stays a string comparison
+        let equal = "the __agent version__ of the **device** is equal to \"1.2\"";
+        let data = json!({"device": {"agent version": "1.2.0"}});
+        assert!(!evaluate_condition(equal, &data, &options).unwrap().0);
+    }
+
+    fn quantified(
+        condition: &str,
+        data: &serde_json::Value,
+    ) -> (bool, crate::runner::trace::ComparisonEvaluationTrace) {
+        use crate::runner::trace::ConditionTrace;
+
+        let (result, trace) = evaluate_condition(condition, data, &EvaluationOptions::new())
+            .unwrap_or_else(|error| panic!("{}: {}", condition, error));
+        let ConditionTrace::Comparison(comparison) = trace else {
+            panic!("Expected a comparison trace");
+        };
+        (result, comparison.evaluation_details.unwrap())
+    }
+
+    #[test]
+    fn test_quantified_conditions_over_list_elements() {
+        let data = json!({"order": {"items": [{"price": 10}, {"price": 200}, {"price": 40}]}});
+        let all = "all of the __items__ of the **order** have a __price__ less than 100";
+        let any = "any of the __items__ of the **order** have a __price__ that is greater than 50";
+        let none = "none of the __items__ of the **order** has a __price__ greater than 500";
+
+        let (result, details) = quantified(all, &data);
+        assert!(!result);
+        assert_eq!(
+            details.quantifier,
+            Some(crate::runner::model::Quantifier::All)
+        );
+        assert_eq!(details.matched_elements, Some(2));
+        // The element that failed `all`
+        assert_eq!(details.deciding_element, Some(1));
+        assert_eq!(details.left_value.value, json!([10, 200, 40]));
+
+        let (result, details) = quantified(any, &data);
+        assert!(result);
+        assert_eq!(details.matched_elements, Some(1));
+        assert_eq!(details.deciding_element, Some(1));
+
+        let (result, details) = quantified(none, &data);
+        assert!(result);
+        assert_eq!(details.matched_elements, Some(0));
+        assert_eq!(details.deciding_element, None);
+
+        // The elements themselves, and negation
+        let scores = json!({"student": {"scores": [35, 80]}});
+        assert!(
+            quantified(
+                "any of the __scores__ of the **student** is less than 40",
+                &scores
+            )
+            .0
+        );
+        let (result, _) = evaluate_condition(
+            "not none of the __scores__ of the **student** is less than 40",
+            &scores,
+            &EvaluationOptions::new(),
+        )
+        .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn test_quantified_conditions_on_empty_and_missing_lists() {
+        let empty = json!({"order": {"items": []}});
+        let all = "all of the __items__ of the **order** have a __price__ less than 100";
+        let any = "any of the __items__ of the **order** have a __price__ less than 100";
+        let none = "none of the __items__ of the **order** have a __price__ less than 100";
+
+        // Every element of none holds it, one of none doesn't, and none of none does
+        for (condition, expected) in [(all, true), (any, false), (none, true)] {
+            let (result, details) = quantified(condition, &empty);
+            assert_eq!(result, expected, "{}", condition);
+            assert_eq!(details.matched_elements, Some(0));
+            assert_eq!(details.deciding_element, None);
+        }
+
+        // An element without the property doesn't hold the comparison
+        let partial = json!({"order": {"items": [{"price": 10}, {"name": "gift"}]}});
+        let (result, details) = quantified(all, &partial);
+        assert!(!result);
+        assert_eq!(details.deciding_element, Some(1));
+        assert!(quantified(none, &json!({"order": {"items": [{"name": "gift"}]}})).0);
+
+        // A missing list fails whatever the quantifier
+        let missing = json!({"order": {}});
+        for condition in [all, any, none] {
+            let (result, _) =
+                evaluate_condition(condition, &missing, &EvaluationOptions::new()).unwrap();
+            assert!(!result, "{}", condition);
+        }
+
+        let error = evaluate_condition(
+            all,
+            &json!({"order": {"items": 3}}),
+            &EvaluationOptions::new(),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("needs a list"), "{}", error);
+    }
+
+    #[test]
+    fn test_case_sensitive_strings_option() {
+        use crate::runner::trace::ConditionTrace;
+
+        let data =
+            json!({"user": {"status": "Active", "tags": ["Gold", "EU"], "bio": "Lives in Paris"}});
+        let sensitive = EvaluationOptions::new().with_case_sensitive_strings(true);
+        let compare = |condition: &str, options: &EvaluationOptions| {
+            let (result, trace) = evaluate_condition(condition, &data, options)
+                .unwrap_or_else(|error| panic!("{}: {}", condition, error));
+            let ConditionTrace::Comparison(comparison) = trace else {
+                panic!("Expected a comparison trace");
+            };
+            (
+                result,
+                comparison.evaluation_details.unwrap().case_sensitive,
+            )
+        };
+
+        let cases = [
+            (
+                r#"the __status__ of the **user** is equal to "active""#,
+                true,
+            ),
+            (
+                r#"the __status__ of the **user** is not equal to "active""#,
+                false,
+            ),
+            (
+                r#"the __status__ of the **user** is in ["active", "pending"]"#,
+                true,
+            ),
+            (
+                r#"the __status__ of the **user** is not in ["active"]"#,
+                false,
+            ),
+            (r#"the __tags__ of the **user** contains "gold""#, true),
+            (r#"the __bio__ of the **user** contains "paris""#, true),
+        ];
+        for (condition, ignoring_case) in cases {
+            assert_eq!(
+                compare(condition, &EvaluationOptions::new()),
+                (ignoring_case, Some(false)),
+                "{}",
+                condition
+            );
+            assert_eq!(
+                compare(condition, &sensitive),
+                (!ignoring_case, Some(true)),
+                "{}",
+                condition
+            );
+        }
+
+        // Written in the same case, strings still match
+        let exact = r#"the __status__ of the **user** is equal to "Active""#;
+        assert_eq!(compare(exact, &sensitive), (true, Some(true)));
+        // `is exactly equal to` minds case either way, and numbers have no case to mind
+        let exactly = r#"the __status__ of the **user** is exactly equal to "active""#;
+        assert_eq!(
+            compare(exactly, &EvaluationOptions::new()),
+            (false, Some(true))
+        );
+        let number = json!({"user": {"age": 30}});
+        let (_, trace) = evaluate_condition(
+            "the __age__ of the **user** is equal to 30",
+            &number,
+            &sensitive,
+        )
+        .unwrap();
+        let ConditionTrace::Comparison(comparison) = trace else {
+            panic!("Expected a comparison trace");
+        };
+        assert_eq!(comparison.evaluation_details.unwrap().case_sensitive, None);
+
+        // A quantified condition compares each element in the same mode
+        let quantified = r#"any of the __tags__ of the **user** is equal to "gold""#;
+        assert_eq!(
+            compare(quantified, &EvaluationOptions::new()),
+            (true, Some(false))
+        );
+        assert_eq!(compare(quantified, &sensitive), (false, Some(true)));
+    }
+
+    #[test]
+    fn test_not_within_and_ago_operators() {
+        use crate::runner::trace::{ConditionTrace, DateWindow};
+
+        let options =
+            EvaluationOptions::new().with_reference_date(Some("2025-01-15".parse().unwrap()));
+        let decide = |operator: &str, date: &str| {
+            let condition = format!("the __applied__ of the **customer** {}", operator);
+            let data = json!({"customer": {"applied": date}});
+            let (result, trace) = evaluate_condition(&condition, &data, &options)
+                .unwrap_or_else(|error| panic!("{} for {}: {}", condition, date, error));
+            let ConditionTrace::Comparison(comparison) = trace else {
+                panic!("Expected a comparison trace");
+            };
+            (result, comparison.evaluation_details.unwrap())
+        };
+
+        let not_within = "is not within 30 days";
+        let more_than = "is more than 30 days ago";
+        let at_least = "is at least 30 days ago";
+        // 2024-12-16 is 30 days before the reference date, 2025-02-14 30 days after it
+        let cases = [
+            ("2024-11-01", [true, true, true]),
+            ("2024-12-15", [true, true, true]),
+            ("2024-12-16", [false, false, true]),
+            ("2025-01-15", [false, false, false]),
+            ("2025-02-14", [false, false, false]),
+            // A date past the window in the future isn't within 30 days, but isn't long ago
+            ("2025-02-15", [true, false, false]),
+            ("2026-01-01", [true, false, false]),
+        ];
+        for (date, expected) in cases {
+            let results =
+                [not_within, more_than, at_least].map(|operator| decide(operator, date).0);
+            assert_eq!(results, expected, "{}", date);
+            let (within, _) = decide("is within 30 days", date);
+            assert_eq!(within, !expected[0], "{}", date);
+        }
+
+        // The trace shows the window `is not within` rejected and the cutoff of the ago forms
+        let (_, details) = decide(not_within, "2024-11-01");
+        assert_eq!(
+            details.window,
+            Some(DateWindow {
+                start: "2024-12-16".parse().unwrap(),
+                end: "2025-02-14".parse().unwrap(),
+            })
+        );
+        assert_eq!(details.cutoff, None);
+        let (_, details) = decide(more_than, "2024-11-01");
+        assert_eq!(details.cutoff, Some("2024-12-16".parse().unwrap()));
+        assert_eq!(details.reference_date, Some("2025-01-15".parse().unwrap()));
+        assert_eq!(details.window, None);
+
+        // A number isn't read as a date, by these operators or `is within`
+        let data = json!({"customer": {"applied": 12}});
+        for operator in ["is within 30 days", not_within, more_than, at_least] {
+            let condition = format!("the __applied__ of the **customer** {}", operator);
+            let (result, _) = evaluate_condition(&condition, &data, &options).unwrap();
+            assert!(!result, "{}", operator);
+        }
+    }
+
+    #[test]
+    fn test_reference_date_pins_duration_comparisons() {
+        use crate::runner::trace::ConditionTrace;
+
+        let data = json!({"user": {"joined": "2024-12-20", "birth date": "2007-01-15"}});
+        let as_of =
+            |date: &str| EvaluationOptions::new().with_reference_date(Some(date.parse().unwrap()));
+        let compare = |condition: &str, options: &EvaluationOptions| {
+            let (result, trace) = evaluate_condition(condition, &data, options)
+                .unwrap_or_else(|error| panic!("{}: {}", condition, error));
+            let ConditionTrace::Comparison(comparison) = trace else {
+                panic!("Expected a comparison trace");
+            };
+            let reference_date = comparison.evaluation_details.unwrap().reference_date;
+            (result, reference_date.map(|date| date.to_string()))
+        };
+
+        let cases = [
+            (
+                "the __joined__ of the **user** is within 30 days",
+                "2025-01-15",
+                true,
+            ),
+            (
+                "the __joined__ of the **user** is within 30 days",
+                "2025-02-15",
+                false,
+            ),
+            (
+                "the __birth date__ of the **user** is older than 18 years",
+                "2025-01-15",
+                true,
+            ),
+            (
+                "the __birth date__ of the **user** is older than 18 years",
+                "2025-01-14",
+                false,
+            ),
+            (
+                "the __birth date__ of the **user** is younger than 18 years",
+                "2025-01-14",
+                true,
+            ),
+        ];
+        for (condition, date, expected) in cases {
+            assert_eq!(
+                compare(condition, &as_of(date)),
+                (expected, Some(date.to_string())),
+                "{} as of {}",
+                condition,
+                date
+            );
+        }
+
+        // Without one, the trace records the day it was evaluated on
+        let today = chrono::Utc::now().date_naive().to_string();
+        let (_, reference_date) = compare(
+            "the __joined__ of the **user** is within 30 days",
+            &EvaluationOptions::new(),
+        );
+        assert_eq!(reference_date, Some(today));
+        // A comparison that doesn't count from a date doesn't record one
+        let (_, reference_date) = compare(
+            "the __joined__ of the **user** is later than 2024-01-01",
+            &as_of("2025-01-15"),
+        );
+        assert_eq!(reference_date, None);
+    }
+
+    #[test]
+    fn test_object_where_a_value_was_expected() {
+        use crate::runner::outcome::UNEXPECTED_OBJECT;
+        use crate::runner::trace::ConditionTrace;
+
+        // The university policy's academic background check, one `of the __degree__` short
+        let rule_set = parse_rules(
+            "A **student** has sufficient academic background
+  if the __degree__ of the **transcripts.undergraduate** is the same as \"completed\".",
+        )
+        .unwrap();
+        let data = json!({"transcripts": {"undergraduate": {
+            "cumulative_gpa": 3.5,
+            "degree": {"completion_status": "completed", "honours": true, "year": 2024},
+            "credit_hours": 120
+        }}});
+
+        // Strict, it fails naming the keys and the one likely meant
+        let outcome = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        let Some(RuleError::UnexpectedObject { path, object }) = &outcome.error else {
+            panic!("Expected an unexpected object, got {:?}", outcome.error);
+        };
+        assert_eq!(path, "$.transcripts.undergraduate.degree");
+        assert_eq!(object.keys, vec!["completion_status", "honours", "year"]);
+        assert_eq!(object.suggestion.as_deref(), Some("completion_status"));
+        assert!(outcome
+            .error
+            .as_ref()
+            .unwrap()
+            .to_string()
+            .ends_with("did you mean $.transcripts.undergraduate.degree.completion_status?"));
+
+        // Lenient, the condition is false and says why
+        let outcome = evaluate(
+            &rule_set,
+            &data,
+            &EvaluationOptions::new().with_lenient(true),
+        );
+        assert!(!outcome.result);
+        assert!(outcome.error.is_none());
+        assert_eq!(outcome.warnings.len(), 1);
+        assert_eq!(outcome.warnings[0].code, Some(UNEXPECTED_OBJECT));
+        assert_eq!(
+            outcome.warnings[0].path,
+            "$.transcripts.undergraduate.degree"
+        );
+        assert!(outcome.warnings[0]
+            .message
+            .contains("so the comparison is false"));
+
+        // Either way the trace shows the object it read
+        for lenient in [false, true] {
+            let options = EvaluationOptions::new().with_lenient(lenient);
+            let trace = evaluate(&rule_set, &data, &options).trace.unwrap();
+            let ConditionTrace::Comparison(comparison) = &trace.execution[0].conditions[0] else {
+                panic!("Expected a comparison trace");
+            };
+            assert_eq!(
+                comparison.property.value,
+                data["transcripts"]["undergraduate"]["degree"]
+            );
+            let details = comparison.evaluation_details.as_ref().unwrap();
+            assert_eq!(details.left_value.value_type, "object");
+            assert_eq!(
+                details
+                    .unexpected_object
+                    .as_ref()
+                    .unwrap()
+                    .suggestion
+                    .as_deref(),
+                Some("completion_status")
+            );
+        }
+    }
+
+    #[test]
+    fn test_unexpected_object_suggestion_matches_the_literal() {
+        let suggestion = |condition: &str, data: serde_json::Value| match evaluate_condition(
+            condition,
+            &data,
+            &EvaluationOptions::new(),
+        ) {
+            Err(RuleError::UnexpectedObject { object, .. }) => object,
+            other => panic!(
+                "{}: expected an unexpected object, got {:?}",
+                condition, other
+            ),
+        };
+
+        // The key of the literal's kind, and of several the one holding the comparison
+        let grades = json!({"student": {"grades": {"letter": "B", "points": 3.2, "passed": true}}});
+        let cases = [
+            (
+                "the __grades__ of the **student** is at least 3",
+                Some("points"),
+            ),
+            (
+                "the __grades__ of the **student** is equal to true",
+                Some("passed"),
+            ),
+            (
+                r#"the __grades__ of the **student** is in ["A", "B"]"#,
+                Some("letter"),
+            ),
+            (
+                "the __grades__ of the **student** is earlier than 2024-01-01",
+                None,
+            ),
+        ];
+        for (condition, expected) in cases {
+            let object = suggestion(condition, grades.clone());
+            assert_eq!(object.suggestion.as_deref(), expected, "{}", condition);
+        }
+        let names = json!({"student": {"name": {"first": "Ada", "last": "Lovelace"}}});
+        let object = suggestion(
+            r#"the __name__ of the **student** is equal to "Lovelace""#,
+            names,
+        );
+        assert_eq!(object.suggestion.as_deref(), Some("last"));
+
+        // Long objects list their first keys
+        let wide: serde_json::Map<String, serde_json::Value> =
+            (0..15).map(|i| (format!("k{:02}", i), json!(i))).collect();
+        let object = suggestion(
+            "the __scores__ of the **student** is at least 3",
+            json!({"student": {"scores": wide}}),
+        );
+        assert_eq!(object.keys.len(), 10);
+        assert_eq!(object.more_keys, 5);
+        assert!(object
+            .describe("$.student.scores")
+            .ends_with("k09 and 5 more, not a single value"));
+        // Emptiness is judged on the object itself
+        let (empty, _) = evaluate_condition(
+            "the __scores__ of the **student** is not empty",
+            &json!({"student": {"scores": {"math": 3}}}),
+            &EvaluationOptions::new(),
+        )
+        .unwrap();
+        assert!(empty);
+    }
+
+    #[test]
+    fn test_arithmetic_is_worked_out_left_to_right() {
+        use crate::runner::trace::ConditionTrace;
+
+        // (40 + 35) * 2 is 150, where precedence would make it 110
+        let data = json!({"exam": {"theory": 40, "practical": 35}});
+        let (result, trace) = evaluate_condition(
+            "the __theory__ of the **exam** plus the __practical__ of the **exam** multiplied by 2 is at least 150",
+            &data,
+            &EvaluationOptions::new(),
+        )
+        .unwrap();
+        assert!(result);
+        let ConditionTrace::Comparison(comparison) = trace else {
+            panic!("Expected comparison trace");
+        };
+        assert_eq!(comparison.property.value, json!(150.0));
+        assert_eq!(
+            comparison.property.path,
+            "$.exam.theory plus $.exam.practical multiplied by 2"
+        );
+        let expression = comparison.left_expression.unwrap();
+        assert_eq!(expression.first.value, json!(40));
+        let steps: Vec<(f64, f64)> = expression
+            .steps
+            .iter()
+            .map(|step| (step.operand.value.as_f64().unwrap(), step.result))
+            .collect();
+        assert_eq!(steps, vec![(35.0, 75.0), (2.0, 150.0)]);
+        assert_eq!(expression.result, 150.0);
+    }
+
+    #[test]
+    fn test_arithmetic_on_the_right_hand_side() {
+        use crate::runner::trace::ConditionTrace;
+
+        let data = json!({"order": {"total": 110, "shipping": 5}, "customer": {"limit": 100}});
+        let (result, trace) = evaluate_condition(
+            "the __total__ of the **order** is less than the __limit__ of the **customer** multiplied by 1.2",
+            &data,
+            &EvaluationOptions::new(),
+        )
+        .unwrap();
+        assert!(result);
+        let ConditionTrace::Comparison(comparison) = trace else {
+            panic!("Expected comparison trace");
+        };
+        assert_eq!(comparison.value.value, json!(120.0));
+        assert_eq!(
+            comparison.right_expression.unwrap().to_string(),
+            "$.customer.limit multiplied by 1.2"
+        );
+
+        // Both sides at once
+        let (result, _) = evaluate_condition(
+            "the __total__ of the **order** minus the __shipping__ of the **order** is greater than the __limit__ of the **customer** plus 10",
+            &data,
+            &EvaluationOptions::new(),
+        )
+        .unwrap();
+        assert!(!result);
+
+        // A date shifted by a duration is still a date offset, not arithmetic
+        let (result, _) = evaluate_condition(
+            "the __start__ of the **trip** is later than the __booked__ of the **trip** plus 14 days",
+            &json!({"trip": {"start": "2024-03-01", "booked": "2024-02-01"}}),
+            &EvaluationOptions::new(),
+        )
+        .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn test_arithmetic_takes_only_numbers() {
+        let result = evaluate_condition(
+            "the __theory__ of the **exam** plus 10 is at least 50",
+            &json!({"exam": {"theory": "forty"}}),
+            &EvaluationOptions::new(),
+        );
+        match result {
+            Err(RuleError::TypeError(message)) => assert_eq!(
+                message,
+                "Arithmetic takes numbers, but $.exam.theory is a string"
+            ),
+            other => panic!("Expected TypeError, got {:?}", other),
+        }
+
+        // A missing operand fails the comparison, naming the operand
+        let (result, trace) = evaluate_condition(
+            "the __theory__ of the **exam** plus the __bonus__ of the **exam** is at least 50",
+            &json!({"exam": {"theory": 60}}),
+            &EvaluationOptions::new(),
+        )
+        .unwrap();
+        assert!(!result);
+        let crate::runner::trace::ConditionTrace::Comparison(comparison) = trace else {
+            panic!("Expected comparison trace");
+        };
+        assert_eq!(comparison.property.path, "$.exam.bonus");
+    }
+
+    #[test]
+    fn test_arithmetic_refuses_to_divide_by_zero() {
+        let condition =
+            "the __total__ of the **order** divided by the __items__ of the **order** is at least 10";
+        let result = evaluate_condition(
+            condition,
+            &json!({"order": {"total": 100, "items": 0}}),
+            &EvaluationOptions::new(),
+        );
+        match result {
+            Err(RuleError::DivisionByZero { path }) => {
+                assert_eq!(path.as_deref(), Some("$.order.items"))
+            }
+            other => panic!("Expected DivisionByZero, got {:?}", other),
+        }
+
+        let (result, _) = evaluate_condition(
+            condition,
+            &json!({"order": {"total": 100, "items": 4}}),
+            &EvaluationOptions::new(),
+        )
+        .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn test_percentage_of_a_property() {
+        use crate::runner::trace::{ConditionTrace, ValueSource};
+
+        let condition = "the __attended sessions__ of the **student** is at least 80% of the __total sessions__ of the **course**";
+        let data = json!({"student": {"attendedSessions": 16}, "course": {"totalSessions": 20}});
+        let (result, trace) =
+            evaluate_condition(condition, &data, &EvaluationOptions::new()).unwrap();
+        assert!(result);
+        let ConditionTrace::Comparison(comparison) = trace else {
+            panic!("Expected comparison trace");
+        };
+        // The threshold is compared, and the value it was worked out from kept beside it
+        assert_eq!(comparison.value.value, json!(16.0));
+        assert_eq!(
+            comparison.value.source,
+            Some(ValueSource::Computed {
+                expression: "80% of $.course.totalSessions".to_string()
+            })
+        );
+        let expression = comparison.right_expression.unwrap();
+        assert_eq!(expression.first.value, json!(20));
+        let percentage = expression.percentage.unwrap();
+        assert_eq!((percentage.percent, percentage.of), (80.0, 20.0));
+        assert_eq!(expression.result, 16.0);
+
+        let data = json!({"student": {"attendedSessions": 15}, "course": {"totalSessions": 20}});
+        let (result, _) = evaluate_condition(condition, &data, &EvaluationOptions::new()).unwrap();
+        assert!(!result);
+    }
+
+    #[test]
+    fn test_percentage_of_zero_and_over_a_hundred() {
+        let condition = "the __attended sessions__ of the **student** is at least 80% of the __total sessions__ of the **course**";
+        // 80% of nothing is nothing, which anything reaches
+        let data = json!({"student": {"attendedSessions": 0}, "course": {"totalSessions": 0}});
+        let (result, _) = evaluate_condition(condition, &data, &EvaluationOptions::new()).unwrap();
+        assert!(result);
+
+        let condition = "the __revenue__ of the **branch** is greater than 150% of the __target__ of the **branch**";
+        let data = json!({"branch": {"revenue": 160, "target": 100}});
+        let (result, _) = evaluate_condition(condition, &data, &EvaluationOptions::new()).unwrap();
+        assert!(result);
+        let data = json!({"branch": {"revenue": 150, "target": 100}});
+        let (result, _) = evaluate_condition(condition, &data, &EvaluationOptions::new()).unwrap();
+        assert!(!result);
+
+        // A missing value fails the comparison as a missing property does
+        let (result, _) = evaluate_condition(
+            condition,
+            &json!({"branch": {"revenue": 150}}),
+            &EvaluationOptions::new(),
+        )
+        .unwrap();
+        assert!(!result);
+    }
 }
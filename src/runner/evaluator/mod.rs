@@ -1,42 +1,403 @@
+//! Evaluates rule sets against JSON data, see `evaluate`.
+//!
+//! `evaluate_condition` parses a lone condition (via `parse_condition`) and evaluates it, served as
+//! `POST /condition` with `{"condition": "...", "data": {...}}` for instant feedback while
+//! authoring.
+//!
+//! `each` in a property chain marks a list: the rest of the chain is read from every element and
+//! the condition sees the list of values (`$.Order.invoice.line[*].sku` in traces). Elements the
+//! chain doesn't resolve in are skipped, and nested `each` steps flatten into one list. A list
+//! reached mid-chain without `each` is a missing property, as before.
+//!
+//! `is empty` and `is not empty` take a string, a list, an object (empty without keys) or null,
+//! which is empty; they follow any property path, `each` steps included. A missing property passes
+//! neither, like other operators, and a number, boolean or date fails with a type error naming the
+//! path. The trace types `left_value` with the kind judged (`string`, `list`, `object` or `null`).
+//!
+//! `the __age__ of the **person** is between 18 and 65` checks a number or date against two literal
+//! bounds, both included; `is strictly between` leaves them out. The bounds are both numbers or
+//! both dates with the lower first, or the rule fails to parse, and they are held as one
+//! `RuleValue::Range`, shown in the trace as `{"min", "max"}` typed `range`. `number of`, `length
+//! of` and `hash bucket of` take it too.
+//!
+//! Strings with at least two dotted numeric components, such as `"2.14.3"` or `"1.0.0-rc.1"`, are
+//! ordered as versions (`evaluator::Version`) when both sides of `is greater than`, `is less than`,
+//! `is at least` or `is no more than` hold one. Components compare as numbers, with a missing one
+//! counting as zero, a pre-release orders before its release as in semver, and `+build` metadata is
+//! ignored. Both sides are then typed `version` in the trace. Other strings aren't ordered: the
+//! comparison is false. `"versions_as_strings": true` turns version ordering off, so versions
+//! become unordered strings again; there is no lexicographic ordering. Equality is unaffected.
+//!
+//! `is equal to`, `is not equal to`, `is in`, `is not in` and `contains` compare strings ignoring
+//! case, and `is exactly equal to` minds it. `"case_sensitive_strings": true`
+//! (`EvaluationOptions::case_sensitive_strings`, also taken by `/condition` and recorded in replay
+//! bundles) makes all of them mind case, for policies where every string comparison must. The trace
+//! of a comparison of strings with one of those operators records the mode as `case_sensitive` in
+//! its `evaluation_details`.
+//!
+//! `all of the __items__ of the **order** have a __price__ less than 100` holds a comparison to
+//! every element of a list (`Condition::Quantified`); `any of` needs one element to match and `none
+//! of` needs none to. The element property can be followed by `that` and a full predicate (`has a
+//! __weight__ that is greater than 1`), and a list of scalars takes the predicate directly (`none
+//! of the __tags__ of the **order** is equal to "oversize"`). An empty list passes `all` and `none`
+//! and fails `any`; an element without the property doesn't match, and a missing list fails the
+//! condition. The trace is a comparison on `items[*].price` with the list typed `list`, plus
+//! `quantifier`, `matched_elements` and the `deciding_element` the result turned on.
+//!
+//! `the sum of __amounts__ of the **order** is greater than 100` compares a number computed from an
+//! array of numbers; `minimum of`, `maximum of` and `average of` work the same way, and each can
+//! follow `each` steps (`the sum of __amount__ of each __lines__ of the **order**`). The sum of an
+//! empty array is 0 and the others have no value, so their condition fails like a missing property;
+//! null is missing unless `"lenient": true`, when it reads as an empty array. A property that isn't
+//! an array, or an element that isn't a number, is a type error (`evaluator::aggregate_value`). The
+//! trace shows the computed number at `$.order.amounts.sum` (`.minimum`, `.maximum`, `.average`),
+//! and `GET /capabilities` lists them under `aggregates`.
+//!
+//! `the __status__ of the first of __attempts__ of the **candidate**` reads one element of a list
+//! (`PropertyChainElement::Index`, zero-based); `second` to `fifth` and `1st`, `2nd`, `3rd`,
+//! `11th`… count the same way and `the last of` takes the last element
+//! (`PropertyChainElement::Last`). A position can mark any list in the chain but not the root
+//! selector, and it combines with `each` and the computed properties (`the number of __pupils__ of
+//! the last of __classes__ of the **school**`). A list without that element fails the condition
+//! like a missing property, with a trace `warning` saying how many elements it has; a property that
+//! isn't a list is a type error. Trace paths show the index, `$.candidate.attempts[0].status`, and
+//! the last element as `[-1]` so that a session update appending to the list re-evaluates the rule.
+//!
+//! With `"range_objects": true` a property on the right of a comparison that holds only numeric
+//! `min` and/or `max` (`"allowedAge": {"min": 17, "max": 70}`) is read as a range
+//! (`evaluator::NumericRange`): `is within the __allowed age__ of the **config**` checks min ≤
+//! value ≤ max and `is not within` the opposite, `is greater than` (or equal) compares with `max`
+//! and `is less than` (or equal) with `min`. A bound that is left out or null leaves that end open,
+//! and comparing with an open end is false; other operators fail. The trace's `right_value` holds
+//! the bounds, typed `range`.
+//!
+//! A rule reference resolves to the rule with that outcome or alias, then the rule with that label,
+//! then a rule whose outcome partly matches (`evaluator::resolve_rule`, also used by the stats).
+//! With no rule, a reference with a selector checks a data property named like it (`passes the
+//! driving test` reads `drivingTestPassed` and similar), and failing that passes as free text; a
+//! label reference fails. The reference's trace node records which of these happened as
+//! `resolution`: `{"kind": "exact_outcome"}`, `"alias"` (`referenced_rule_outcome` is the canonical
+//! outcome), `"label"`, `"fuzzy_outcome"` with `matched`, `"inferred_property"` with `property`, or
+//! `"unresolved"`.
+//!
+//! `not` in front of any condition (`and not the __banned__ of the **person** is equal to true`),
+//! or `does not` / `doesn't` in a rule reference (`the **person** does not pass the practical
+//! driving test`), negates it: `ConditionGroup::negated`, applied in `evaluate_rule_with_trace`
+//! before the `and`/`or` grouping. Both together cancel out. A negated reference names its rule
+//! with the verb conjugated back (`does not pass the test` looks up `passes the test`). The trace
+//! keeps the condition's own result in `inner_result` next to the negated `result`; the CSV trace
+//! writes the operator as `not ...` and the explanation prefixes `not`. A reference to a skipped
+//! rule is left out, not negated. `not` is part of the condition's canonical text, so of its id,
+//! and the contradictory/redundant lint ignores negated comparisons.
+//!
+//! Property lookups are guarded against adversarial payloads by `DataLookup` in the evaluator. A
+//! path may read no deeper than `EvaluationOptions::max_data_depth` (`DEFAULT_MAX_DATA_DEPTH`, 64)
+//! levels, counting the keys it walks and the nesting of the value it reaches (measured without
+//! recursion); deeper fails with `RuleError::DataTooDeep` naming the path, 422 on the server.
+//! Objects with more than `max_scanned_keys` (`DEFAULT_MAX_SCANNED_KEYS`, 10,000) keys aren't
+//! searched key by key for a differently written name: only the name as written and camelCased
+//! match, and a miss sets `PropertyTrace::warning`, which the outcome lists in `warnings`. The
+//! server uses the defaults.
+//!
+//! `length of` counts string characters, array elements and object keys; `number of` counts array
+//! elements and object keys and errors on strings. Both error on numbers and booleans. A null
+//! property is treated as missing unless the request sets `"lenient": true`, in which case it
+//! counts as 0. `GET /capabilities` lists the supported operators and this counting matrix.
+//!
+//! `the hash bucket of __user id__ of the **user**` is a number from 0 to 99 computed from the
+//! property, and `the **user** is in the 5% sample by __user id__` is `the hash bucket of __user
+//! id__ of the **user** is less than 5` (whole percentages 0–100). The bucket is
+//! `evaluator::hash_bucket`: SHA-256 of `engine.hash_bucket.v1:` plus the key, first 8 bytes
+//! big-endian, modulo 100. Strings are hashed as their text, numbers and booleans as written in
+//! JSON; null is missing and arrays or objects are a type error. The trace shows the bucket at
+//! `$.user.userId.hash_bucket`. The pinned values in `test_hash_bucket_values_are_pinned` must
+//! never change, since other systems reproduce samples from them.
+//!
+//! Durations are traced as written (`"value": "2 years", "type": "duration"`) with the normalized
+//! length next to it in `seconds`, and the comparison's `evaluation_details` repeat it as
+//! `duration_seconds`. `within` checks the date against a window of whole days either side of
+//! today, traced as `window: {start, end}`; a duration too long for the window to be a date fails
+//! the evaluation.
+//!
+//! `is not within 90 days` accepts the dates `is within` rejects, before or after the window, so a
+//! date far in the future is not within it either; its trace shows the same `window`. `is more than
+//! 90 days ago` only looks back: it accepts the dates before the reference date less the duration
+//! (counted in whole days, as the window is), and `is at least 90 days ago` that date too. A future
+//! date is never either. Their trace shows that date as `cutoff`. The grammar tries `ago_operator ~
+//! duration_literal ~ "ago"` before the other predicates, so `is at least 18` is still a number
+//! comparison. `ComparisonOperator::value_suffix` is the `ago` written after the duration, which
+//! explanations, validator messages and `GET /capabilities` (`is more than <duration> ago`) add
+//! back.
+//!
+//! Numbers that aren't finite are never compared. Payload numbers always are (serde_json rejects
+//! `1e999` without `arbitrary_precision`), but a literal with too many digits parses to infinity
+//! and a large duration can overflow when converted to seconds. Such a comparison fails with
+//! `RuleError::NonFiniteNumber { path }`, or with `"lenient": true` is false with a `warning` in
+//! its `evaluation_details`. Traces write these values as `"NaN"`, `"Infinity"` or `"-Infinity"`.
+
 mod lib;
 
+use crate::runner::debug::{DebugAction, DebugView};
 use crate::runner::error::{EvaluationResult, PartialRuleTrace, RuleError};
 use crate::runner::model::{
-    ComparisonCondition, ComparisonOperator, Condition, ConditionOperator, PerformanceCache,
-    PropertyChainElement, Rule, RuleReferenceCondition, RuleSet, RuleValue,
+    constants, ComparisonCondition, ComparisonOperator, Condition, ConditionGroup,
+    ConditionOperator, DateOffset, Duration, FlagCondition, PerformanceCache, PropertyChainElement,
+    QuantifiedCondition, Quantifier, Rule, RuleExpression, RuleReferenceCondition, RuleSet,
+    RuleValue,
+};
+use crate::runner::options::{
+    EvaluationOptions, LegacyFlatData, ListScalarMode, TraceVerbosity, DEFAULT_MAX_CALL_PATH_FRAMES,
 };
+use crate::runner::outcome::{EvalStats, EvaluationOutcome, EvaluationWarning, LEGACY_FLAT_DATA};
+use crate::runner::parser::parse_condition;
+use crate::runner::schema::{disjoint_data, flat_data_selector};
 use crate::runner::trace::{
-    ComparisonEvaluationTrace, ComparisonTrace, ConditionTrace, OutcomeTrace, PropertyCheckTrace,
-    PropertyTrace, RuleReferenceTrace, RuleSetTrace, RuleTrace, SelectorTrace, TypedValue,
+    number_value, ArithmeticStepTrace, ComparisonEvaluationTrace, ComparisonTrace, ConditionTrace,
+    DateWindow, ExpressionTrace, FlagTrace, GroupTrace, OperandTrace, OutcomeTrace,
+    PercentageTrace, PropertyCheckTrace, PropertyTrace, ReferenceResolution, RuleReferenceTrace,
+    RuleSetTrace, RuleTrace, SelectorTrace, TypedValue, UnexpectedObject, ValueTrace,
+    MAX_LISTED_KEYS,
 };
 
-use crate::runner::utils::{names_match, transform_property_name};
+use crate::runner::utils::{
+    find_global_rule, json_path_from_keys, names_match, normalize_outcome, push_json_path_key,
+    push_json_path_step, render_json_path, resolve_data_root, resolve_json_path,
+    transform_property_name,
+};
 use chrono::NaiveDate;
+use indexmap::IndexMap;
 use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// The data as rules see it: the request context under the reserved `**context**` selector in
+/// place of any key of the data that would match it, so the data can't pose as the context
+fn with_context<'a>(data: &'a Value, options: &EvaluationOptions) -> Cow<'a, Value> {
+    let shadowed = data.as_object().is_some_and(|object| {
+        object
+            .keys()
+            .any(|key| names_match(constants::CONTEXT_SELECTOR, key))
+    });
+    if options.context.is_null() && !shadowed {
+        return Cow::Borrowed(data);
+    }
 
-impl RuleError {
-    pub fn infinite_loop_error(cycle_path: Vec<String>) -> Self {
-        RuleError::EvaluationError(format!(
-            "Infinite loop detected in rule evaluation: {} -> {}",
-            cycle_path.join(" -> "),
-            cycle_path[0]
-        ))
+    let mut object = data.as_object().cloned().unwrap_or_default();
+    object.retain(|key, _| !names_match(constants::CONTEXT_SELECTOR, key));
+    if !options.context.is_null() {
+        object.insert(
+            constants::CONTEXT_SELECTOR.to_string(),
+            options.context.clone(),
+        );
     }
+    Cow::Owned(Value::Object(object))
 }
 
-#[allow(dead_code)]
-pub fn evaluate_rule_set_with_trace(
+/// The overall result: the global rule's outcome, or every requested entry passing
+fn entry_result(
+    rule_set: &RuleSet,
+    options: &EvaluationOptions,
+    results: &IndexMap<String, bool>,
+) -> Result<bool, RuleError> {
+    if options.entries.is_empty() {
+        let global_rule = find_global_rule(&rule_set.rules)?;
+        return Ok(results.get(&global_rule.outcome).cloned().unwrap_or(false));
+    }
+
+    Ok(options.entries.iter().all(|entry| {
+        rule_set
+            .get_rule(entry)
+            .or_else(|| rule_set.get_rule_by_label(entry))
+            .and_then(|rule| results.get(&rule.outcome))
+            .cloned()
+            .unwrap_or(false)
+    }))
+}
+
+/// Parses and evaluates a single condition against `data`, for instant feedback while one
+/// is being written. References to other rules can't resolve here, so they are checked as
+/// properties of the selector the way an unknown rule name is
+pub fn evaluate_condition(
+    condition_text: &str,
+    data: &Value,
+    options: &EvaluationOptions,
+) -> Result<(bool, ConditionTrace), RuleError> {
+    let (condition, negated) = parse_condition(condition_text)?;
+    let data = with_context(data_root(data, options)?, options);
+    let mut evaluated = evaluate_condition_with_trace(
+        &condition,
+        &data,
+        &RuleSet::default(),
+        &mut EvaluationContext::new(options),
+    )
+    .map(|(result, mut trace)| {
+        if negated {
+            trace.negate();
+        }
+        (result != negated, trace)
+    })
+    .map_err(|(error, _trace)| error);
+    if let Some(root) = &options.data_root {
+        match &mut evaluated {
+            Ok((_, trace)) => {
+                for leaf in trace.leaves_mut() {
+                    if let ConditionTrace::Comparison(comparison) = leaf {
+                        comparison.rebase_paths(root);
+                    }
+                }
+            }
+            Err(error) => error.rebase_paths(root),
+        }
+    }
+    evaluated
+}
+
+/// The part of `data` the options point selectors at, see `EvaluationOptions::data_root`
+fn data_root<'a>(data: &'a Value, options: &EvaluationOptions) -> Result<&'a Value, RuleError> {
+    match &options.data_root {
+        Some(root) => resolve_data_root(data, root).map_err(RuleError::InvalidDataRoot),
+        None => Ok(data),
+    }
+}
+
+/// The selector `data` is read under and `data` wrapped under it, when
+/// `EvaluationOptions::legacy_flat_data` reads it so
+pub(crate) fn legacy_flat_data(
+    rule_set: &RuleSet,
+    data: &Value,
+    options: &EvaluationOptions,
+) -> Option<(String, Value)> {
+    let selector = match options.legacy_flat_data {
+        LegacyFlatData::Off => None,
+        LegacyFlatData::On => flat_data_selector(rule_set, data, true),
+        LegacyFlatData::Auto => flat_data_selector(rule_set, data, false),
+    }?;
+    let wrapped = json!({ selector.as_str(): data });
+    Some((selector, wrapped))
+}
+
+/// Evaluates a rule set against `data`: from the global rule, or from every entry the options
+/// name, then every rule those reference. The outcome keeps the trace even when evaluation fails
+pub fn evaluate(
+    rule_set: &RuleSet,
+    data: &Value,
+    options: &EvaluationOptions,
+) -> EvaluationOutcome {
+    evaluate_with_memo(rule_set, data, options, &mut RuleMemo::new())
+}
+
+/// `evaluate`, taking the rules in `memo` as already evaluated and leaving every rule it
+/// evaluates there, so a later call only evaluates the rules taken out of it
+pub(crate) fn evaluate_with_memo(
+    rule_set: &RuleSet,
+    data: &Value,
+    options: &EvaluationOptions,
+    memo: &mut RuleMemo,
+) -> EvaluationOutcome {
+    let data = match data_root(data, options) {
+        Ok(data) => data,
+        Err(error) => return EvaluationOutcome::failure(error, None),
+    };
+    let (flat_selector, wrapped) = legacy_flat_data(rule_set, data, options).unzip();
+    let data = wrapped.as_ref().unwrap_or(data);
+    let started = Instant::now();
+    let mut context = EvaluationContext::new(options);
+    context.memo = std::mem::take(memo);
+    let mut evaluation = evaluate_rules(rule_set, data, &mut context);
+    *memo = std::mem::take(&mut context.memo);
+    context.stats.wall_time_micros = started.elapsed().as_micros().try_into().unwrap_or(u64::MAX);
+    if options.trace_verbosity == TraceVerbosity::Verbose {
+        if let Some(trace) = &mut evaluation.trace {
+            trace.explain_missed_keys(&with_context(data, options));
+        }
+    }
+    if let Some(root) = &options.data_root {
+        if let Some(trace) = &mut evaluation.trace {
+            trace.rebase_paths(root);
+        }
+        if let Err(error) = &mut evaluation.result {
+            error.rebase_paths(root);
+        }
+    }
+    let mut outcome = EvaluationOutcome {
+        evaluations_used: context.condition_evaluations,
+        stats: context.stats,
+        ..EvaluationOutcome::default()
+    };
+    if let Some(selector) = flat_selector {
+        outcome.warnings.push(EvaluationWarning {
+            condition: None,
+            path: options.data_root.clone().unwrap_or_else(|| "$".to_string()),
+            message: format!(
+                "Read the data as if sent under `{}`. Data without its selector is deprecated \
+                 and won't be read this way in a later release; send it as {{\"{}\": ...}}",
+                selector, selector
+            ),
+            code: Some(LEGACY_FLAT_DATA),
+        });
+    }
+    outcome.set_trace(evaluation.trace);
+
+    match evaluation.result {
+        Ok(outcomes) => {
+            match entry_result(rule_set, options, &outcomes) {
+                Ok(result) => outcome.result = result,
+                Err(error) => outcome.error = Some(error),
+            }
+            outcome.outcomes = outcomes;
+        }
+        Err(error) => outcome.error = Some(error),
+    }
+    outcome
+}
+
+fn evaluate_rules(
     rule_set: &RuleSet,
     json: &Value,
-) -> EvaluationResult<HashMap<String, bool>> {
+    context: &mut EvaluationContext,
+) -> EvaluationResult<IndexMap<String, bool>> {
+    let options = context.options;
     let mut all_traces: Vec<RuleTrace> = Vec::new();
-    let mut results = HashMap::new();
+    let mut results = IndexMap::new();
+    // Rules are told apart by index, so one reached by label and by outcome is one entry
     let mut processed_rules = HashSet::new();
 
-    // Find global rule and handle potential error
-    let global_rule = match crate::runner::utils::find_global_rule(&rule_set.rules) {
-        Ok(rule) => rule,
+    if options.strict_references {
+        if let Err(error) = rule_set.validate_references() {
+            let trace = RuleSetTrace {
+                execution: all_traces,
+            };
+            return EvaluationResult::failure(error, Some(trace));
+        }
+    }
+    if options.reject_disjoint_data {
+        if let Some(disjoint) = disjoint_data(rule_set, json) {
+            let trace = RuleSetTrace {
+                execution: all_traces,
+            };
+            return EvaluationResult::failure(RuleError::DisjointData(disjoint), Some(trace));
+        }
+    }
+    if options.validate_input {
+        let violations = rule_set.validate_data(json);
+        if !violations.is_empty() {
+            let trace = RuleSetTrace {
+                execution: all_traces,
+            };
+            return EvaluationResult::failure(RuleError::InvalidData { violations }, Some(trace));
+        }
+    }
+    let data = with_context(json, options);
+    let json = data.as_ref();
+
+    // Explicit entries take precedence over the inferred global rule
+    let entry_rules = match resolve_entry_rules(rule_set, options) {
+        Ok(rules) => rules,
         Err(error) => {
             // Even if we can't find global rule, return what trace we can
             let trace = RuleSetTrace {
@@ -46,31 +407,29 @@ pub fn evaluate_rule_set_with_trace(
         }
     };
 
-    let mut evaluation_stack = HashSet::new();
-    let mut call_path = Vec::new();
-
-    // Evaluate global rule with trace preservation
-    match evaluate_rule_with_trace(
-        global_rule,
-        json,
-        rule_set,
-        &mut evaluation_stack,
-        &mut call_path,
-    ) {
-        Ok((result, rule_trace)) => {
-            results.insert(global_rule.outcome.clone(), result);
-            all_traces.push(rule_trace);
-            processed_rules.insert(global_rule.outcome.clone());
+    for entry_rule in entry_rules {
+        if !processed_rules.insert(rule_identity(rule_set, entry_rule)) {
+            continue;
         }
-        Err((error, partial_trace)) => {
-            // Convert partial trace and return failure with trace
-            if let Some(trace) = partial_trace {
-                all_traces.push(trace.to_rule_trace());
+
+        // Evaluate entry rule with trace preservation
+        match evaluate_rule_with_trace(entry_rule, json, rule_set, context) {
+            Ok((result, rule_trace)) => {
+                if !rule_trace.skipped {
+                    results.insert(entry_rule.outcome.clone(), result);
+                }
+                all_traces.push(rule_trace);
+            }
+            Err((error, partial_trace)) => {
+                // Convert partial trace and return failure with trace
+                if let Some(trace) = partial_trace {
+                    all_traces.push(trace.to_rule_trace());
+                }
+                let rule_set_trace = RuleSetTrace {
+                    execution: all_traces,
+                };
+                return EvaluationResult::failure(error, Some(rule_set_trace));
             }
-            let rule_set_trace = RuleSetTrace {
-                execution: all_traces,
-            };
-            return EvaluationResult::failure(error, Some(rule_set_trace));
         }
     }
 
@@ -80,14 +439,15 @@ pub fn evaluate_rule_set_with_trace(
         let mut rules_to_process = Vec::new();
         {
             let trace = &all_traces[i];
-            for condition in &trace.conditions {
+            for condition in trace.leaves() {
                 if let ConditionTrace::RuleReference(ref_trace) = condition {
-                    if let Some(outcome) = &ref_trace.referenced_rule_outcome {
-                        if !processed_rules.contains(outcome) {
-                            if let Some(rule) = rule_set.get_rule(outcome) {
-                                rules_to_process.push((outcome.clone(), rule));
-                                processed_rules.insert(outcome.clone());
-                            }
+                    let rule = ref_trace
+                        .referenced_rule_outcome
+                        .as_ref()
+                        .and_then(|outcome| rule_set.get_rule(outcome));
+                    if let Some(rule) = rule {
+                        if processed_rules.insert(rule_identity(rule_set, rule)) {
+                            rules_to_process.push(rule);
                         }
                     }
                 }
@@ -95,19 +455,21 @@ pub fn evaluate_rule_set_with_trace(
         }
 
         // Process collected rules
-        for (outcome, rule) in rules_to_process {
-            let mut sub_evaluation_stack = HashSet::new();
-            let mut sub_call_path = Vec::new();
+        for rule in rules_to_process {
+            if let Err(error) = options.check_cancelled() {
+                let rule_set_trace = RuleSetTrace {
+                    execution: all_traces,
+                };
+                return EvaluationResult::failure(error, Some(rule_set_trace));
+            }
 
-            match evaluate_rule_with_trace(
-                rule,
-                json,
-                rule_set,
-                &mut sub_evaluation_stack,
-                &mut sub_call_path,
-            ) {
+            // The reference that led here evaluated the rule already, so this takes its
+            // result and trace from the memo
+            match evaluate_rule_with_trace(rule, json, rule_set, context) {
                 Ok((sub_result, sub_trace)) => {
-                    results.insert(outcome, sub_result);
+                    if !sub_trace.skipped {
+                        results.insert(rule.outcome.clone(), sub_result);
+                    }
                     all_traces.push(sub_trace);
                 }
                 Err((error, partial_trace)) => {
@@ -129,75 +491,185 @@ pub fn evaluate_rule_set_with_trace(
     let rule_set_trace = RuleSetTrace {
         execution: all_traces,
     };
+    if options.validate_input {
+        if let Err(error) = check_outcomes(rule_set, &results) {
+            return EvaluationResult::failure(error, Some(rule_set_trace));
+        }
+    }
     EvaluationResult::success(results, rule_set_trace)
 }
 
-#[allow(dead_code)]
-pub fn evaluate_rule_set(
-    rule_set: &RuleSet,
-    json: &Value,
-) -> Result<(HashMap<String, bool>, RuleSetTrace), RuleError> {
-    let global_rule = crate::runner::utils::find_global_rule(&rule_set.rules)?;
-    let mut evaluation_stack = HashSet::new();
-    let mut call_path = Vec::new();
-
-    let (result, rule_trace) = evaluate_rule(
-        global_rule,
-        json,
-        rule_set,
-        &mut evaluation_stack,
-        &mut call_path,
-    )?;
-    let mut results = HashMap::new();
-    results.insert(global_rule.outcome.clone(), result);
-
-    let mut all_traces = vec![rule_trace];
-    let mut processed_rules = HashSet::new();
-    processed_rules.insert(global_rule.outcome.clone());
+/// Checks evaluated outcomes against the rule set's outcome declarations
+fn check_outcomes(rule_set: &RuleSet, results: &IndexMap<String, bool>) -> Result<(), RuleError> {
+    for (outcome, &result) in results {
+        let Some(declaration) = rule_set.outcome_declaration(outcome) else {
+            continue;
+        };
+        let value = Value::Bool(result);
+        if !declaration.kind.admits(&value) {
+            return Err(RuleError::InvalidOutcome {
+                outcome: outcome.clone(),
+                value,
+                declared: declaration.kind.clone(),
+            });
+        }
+    }
+    Ok(())
+}
 
-    let mut i = 0;
-    while i < all_traces.len() {
-        let mut rules_to_process = Vec::new();
-        {
-            let trace = &all_traces[i];
-            for condition in &trace.conditions {
-                if let ConditionTrace::RuleReference(ref_trace) = condition {
-                    if let Some(outcome) = &ref_trace.referenced_rule_outcome {
-                        if !processed_rules.contains(outcome) {
-                            if let Some(rule) = rule_set.get_rule(outcome) {
-                                rules_to_process.push((outcome.clone(), rule));
-                                processed_rules.insert(outcome.clone());
-                            }
-                        }
-                    }
+impl RuleSet {
+    /// Finishes a parsed set for sharing between threads: resolves every rule reference up
+    /// front, so concurrent evaluations read `rule_fuzzy_matches` rather than race to fill
+    /// it, and puts the set behind an `Arc`, after which its rules can't change. The caches
+    /// are behind locks and each evaluation keeps its own state in an `EvaluationContext`
+    pub fn freeze(self) -> Arc<RuleSet> {
+        for rule in &self.rules {
+            for group in rule.all_conditions() {
+                if let Condition::RuleReference(reference) = &group.condition {
+                    resolve_rule(reference.rule_name.value.trim(), &self);
                 }
             }
         }
+        Arc::new(self)
+    }
+}
 
-        // Then process the collected rules and modify all_traces
-        for (outcome, rule) in rules_to_process {
-            let mut sub_evaluation_stack = HashSet::new();
-            let mut sub_call_path = Vec::new();
+/// Picks the rules evaluation starts from: the requested entries, or the single global rule
+pub fn resolve_entry_rules<'a>(
+    rule_set: &'a RuleSet,
+    options: &EvaluationOptions,
+) -> Result<Vec<&'a Rule>, RuleError> {
+    if options.entries.is_empty() {
+        return crate::runner::utils::find_global_rule(&rule_set.rules).map(|rule| vec![rule]);
+    }
 
-            let (sub_result, sub_trace) = evaluate_rule(
-                rule,
-                json,
-                rule_set,
-                &mut sub_evaluation_stack,
-                &mut sub_call_path,
-            )?;
-            results.insert(outcome, sub_result);
-            all_traces.push(sub_trace);
+    options
+        .entries
+        .iter()
+        .map(|entry| {
+            rule_set
+                .get_rule(entry)
+                .or_else(|| rule_set.get_rule_by_label(entry))
+                .ok_or_else(|| {
+                    RuleError::EvaluationError(format!("Entry rule '{}' not found", entry))
+                })
+        })
+        .collect()
+}
+
+/// The key a rule takes on the evaluation stack and in the memo: its index in the set, see
+/// `Rule::index`, so a rule reached by label and the same rule reached by outcome are one
+/// entry. A rule evaluated from outside the set can't be referenced, so it shares a key no
+/// set rule uses
+fn rule_identity(rule_set: &RuleSet, rule: &Rule) -> usize {
+    rule_set.index_of(rule).unwrap_or(usize::MAX)
+}
+
+/// Results and traces of the rules an evaluation has finished, by index in the set, so a
+/// rule referenced several times, by label or by outcome, is evaluated and traced once
+pub type RuleMemo = HashMap<usize, (bool, RuleTrace)>;
+
+/// Everything one evaluation changes as it runs. A `RuleSet` is only read while evaluating, so
+/// one set can be shared, e.g. in an `Arc`, by evaluations on many threads, each with its own
+/// context
+#[derive(Debug)]
+pub struct EvaluationContext<'a> {
+    pub options: &'a EvaluationOptions,
+    /// Indices of the rules being evaluated, to detect cycles, see `rule_identity`
+    pub evaluation_stack: HashSet<usize>,
+    /// Names of the rules being evaluated, outermost first, for the cycle error
+    pub call_path: Vec<String>,
+    pub memo: RuleMemo,
+    /// Flag states asked for so far, and whether the provider knew each, so a flag read by
+    /// several conditions has one state for the whole evaluation
+    pub flags: HashMap<String, (bool, bool)>,
+    /// Conditions evaluated so far, against `EvaluationOptions::max_condition_evaluations`
+    pub condition_evaluations: usize,
+    /// What the evaluation has done so far, see `EvalStats`
+    pub stats: EvalStats,
+    /// The debug hook asked to skip the rule being evaluated, so the rest of its conditions
+    /// aren't evaluated, see `DebugAction::SkipRule`
+    skip_rule: bool,
+}
+
+impl<'a> EvaluationContext<'a> {
+    pub fn new(options: &'a EvaluationOptions) -> Self {
+        Self {
+            options,
+            evaluation_stack: HashSet::new(),
+            call_path: Vec::new(),
+            memo: RuleMemo::new(),
+            flags: HashMap::new(),
+            condition_evaluations: 0,
+            stats: EvalStats::default(),
+            skip_rule: false,
         }
+    }
 
-        i += 1;
+    /// Enters `rule`, failing when it is already being evaluated or is one reference deeper
+    /// than `max_reference_depth`
+    fn enter_rule(&mut self, identity: usize, rule: &Rule) -> Result<(), RuleError> {
+        let max_frames = self.options.max_call_path_frames;
+        let call_path = || {
+            let mut path = self.call_path.clone();
+            path.push(rule.name().to_string());
+            path
+        };
+        if self.evaluation_stack.contains(&identity) {
+            return Err(RuleError::InfiniteLoop {
+                call_path: call_path(),
+                max_frames,
+            });
+        }
+        if self.call_path.len() >= self.options.max_reference_depth {
+            return Err(RuleError::MaxDepthExceeded {
+                call_path: call_path(),
+                limit: self.options.max_reference_depth,
+                max_frames,
+            });
+        }
+        self.evaluation_stack.insert(identity);
+        self.call_path.push(rule.name().to_string());
+        self.stats.rules_evaluated += 1;
+        self.stats.max_reference_depth = self.stats.max_reference_depth.max(self.call_path.len());
+        Ok(())
     }
 
-    let rule_set_trace = RuleSetTrace {
-        execution: all_traces,
-    };
+    /// `error` as a rule's trace records it: a call path is written out whole in a verbose
+    /// trace
+    fn trace_error(&self, error: &RuleError) -> String {
+        let full = match error {
+            RuleError::InfiniteLoop { call_path, .. } => RuleError::InfiniteLoop {
+                call_path: call_path.clone(),
+                max_frames: usize::MAX,
+            },
+            RuleError::MaxDepthExceeded {
+                call_path, limit, ..
+            } => RuleError::MaxDepthExceeded {
+                call_path: call_path.clone(),
+                limit: *limit,
+                max_frames: usize::MAX,
+            },
+            _ => return error.to_string(),
+        };
+        match self.options.trace_verbosity {
+            TraceVerbosity::Verbose => full.to_string(),
+            TraceVerbosity::Normal => error.to_string(),
+        }
+    }
 
-    Ok((results, rule_set_trace))
+    /// Counts a condition of `rule` about to be evaluated, failing when the budget is spent
+    fn spend_condition(&mut self, rule: &Rule) -> Result<(), RuleError> {
+        if self.condition_evaluations >= self.options.max_condition_evaluations {
+            return Err(RuleError::BudgetExceeded {
+                evaluations: self.condition_evaluations,
+                rule: rule.name().to_string(),
+            });
+        }
+        self.condition_evaluations += 1;
+        self.stats.conditions_evaluated += 1;
+        Ok(())
+    }
 }
 
 /// Enhanced rule evaluation that preserves traces even on errors
@@ -205,118 +677,313 @@ pub fn evaluate_rule_with_trace(
     model_rule: &Rule,
     json: &Value,
     rule_set: &RuleSet,
-    evaluation_stack: &mut HashSet<String>,
-    call_path: &mut Vec<String>,
+    context: &mut EvaluationContext,
 ) -> Result<(bool, RuleTrace), (RuleError, Option<PartialRuleTrace>)> {
+    let memo_key = rule_set.index_of(model_rule);
+    if let Some((result, trace)) = memo_key.and_then(|index| context.memo.get(&index)) {
+        // Traces of referenced rules are collected from the memo too, which isn't a reference
+        if !context.call_path.is_empty() {
+            context.stats.memo_hits += 1;
+        }
+        return Ok((*result, trace.clone()));
+    }
+
+    if context.options.excludes(model_rule) {
+        let rule_trace = rule_trace(model_rule, Vec::new(), false, true);
+        if let Some(index) = memo_key {
+            context.memo.insert(index, (false, rule_trace.clone()));
+        }
+        return Ok((false, rule_trace));
+    }
+
     // Initialize partial trace to capture progress
     let mut partial_trace = PartialRuleTrace::new(
         model_rule.label.clone(),
         model_rule.selector.clone(),
         model_rule.selector_pos.clone(),
-        model_rule.outcome.clone(),
+        model_rule.outcome_display.clone(),
         model_rule.position.clone(),
     );
 
-    // cycle check
-    let rule_identifier = model_rule.outcome.clone();
-    if evaluation_stack.contains(&rule_identifier) {
-        call_path.push(rule_identifier.clone());
-        let error = RuleError::infinite_loop_error(call_path.clone());
-        partial_trace.set_error(format!("Infinite loop detected: {}", error));
+    // cycle and depth check
+    let rule_identifier = rule_identity(rule_set, model_rule);
+    if let Err(error) = context.enter_rule(rule_identifier, model_rule) {
+        partial_trace.set_error(context.trace_error(&error));
         return Err((error, Some(partial_trace)));
     }
-    evaluation_stack.insert(rule_identifier.clone());
-    call_path.push(rule_identifier.clone());
+
+    if let Some(hook) = &context.options.debug_hook {
+        let view = DebugView::new(&context.call_path, &[], &context.memo);
+        let action = hook.on_rule_start(model_rule, &view);
+        if action != DebugAction::Continue {
+            context.evaluation_stack.remove(&rule_identifier);
+            context.call_path.pop();
+        }
+        match action {
+            DebugAction::Continue => {}
+            DebugAction::SkipRule => {
+                let rule_trace = rule_trace(model_rule, Vec::new(), false, true);
+                if let Some(index) = memo_key {
+                    context.memo.insert(index, (false, rule_trace.clone()));
+                }
+                return Ok((false, rule_trace));
+            }
+            DebugAction::Abort => {
+                partial_trace.set_error(RuleError::Cancelled.to_string());
+                return Err((RuleError::Cancelled, Some(partial_trace)));
+            }
+        }
+    }
 
     // evaluate each condition, collect results and traces
-    let mut results = Vec::new();
-    let mut ops = Vec::new();
     let mut condition_traces = Vec::new();
+    let evaluated = evaluate_conditions(
+        &model_rule.conditions,
+        Some(model_rule),
+        json,
+        rule_set,
+        context,
+        &mut condition_traces,
+    );
 
-    for (i, cg) in model_rule.conditions.iter().enumerate() {
-        match evaluate_condition_with_trace(
-            &cg.condition,
-            json,
-            rule_set,
-            evaluation_stack,
-            call_path,
-        ) {
-            Ok((res, trace)) => {
-                results.push(res);
-                partial_trace.add_condition(trace.clone());
-                condition_traces.push(trace);
-            }
-            Err((error, condition_trace)) => {
-                // Add any partial condition trace we have
-                if let Some(trace) = condition_trace {
-                    partial_trace.add_condition(trace);
+    context.evaluation_stack.remove(&rule_identifier);
+    context.call_path.pop();
+    let skipped_by_hook = std::mem::take(&mut context.skip_rule);
+
+    let results = match evaluated {
+        Ok(results) => results,
+        Err((error, message)) => {
+            for trace in condition_traces {
+                partial_trace.add_condition(trace);
+            }
+            partial_trace.set_error(message);
+            return Err((error, Some(partial_trace)));
+        }
+    };
+    let (rule_result, _) = combine_results(&model_rule.conditions, &results);
+    // Every condition was skipped, or the debug hook skipped the rule
+    let skipped = skipped_by_hook || (rule_result.is_none() && !model_rule.conditions.is_empty());
+    let rule_result = rule_result.filter(|_| !skipped_by_hook).unwrap_or(false);
+
+    let rule_trace = rule_trace(model_rule, condition_traces, rule_result, skipped);
+
+    if let Some(index) = memo_key {
+        context
+            .memo
+            .insert(index, (rule_result, rule_trace.clone()));
+    }
+    Ok((rule_result, rule_trace))
+}
+
+/// Evaluates `conditions` in order, each group's conditions in turn, with what each came to,
+/// negation included, or `None` for a skipped one. Conditions of a `rule` spend the budget,
+/// groups themselves don't. Traces are pushed to `traces` as they are made, so a failure
+/// leaves those before it, a group's with what of it was evaluated, and the message the
+/// rule's partial trace records. The debug hook is called after each condition of a `rule`
+/// that isn't a group; when it skips the rule, the conditions after are left unevaluated
+fn evaluate_conditions(
+    conditions: &[ConditionGroup],
+    rule: Option<&Rule>,
+    json: &Value,
+    rule_set: &RuleSet,
+    context: &mut EvaluationContext,
+    traces: &mut Vec<ConditionTrace>,
+) -> Result<Vec<Option<bool>>, (RuleError, String)> {
+    let mut results = Vec::with_capacity(conditions.len());
+    for group in conditions {
+        if context.skip_rule {
+            break;
+        }
+        let evaluated = match &group.condition {
+            Condition::Group(members) => {
+                match evaluate_group(members, rule, json, rule_set, context) {
+                    (Ok(result), trace) => Ok((result, trace)),
+                    (Err(failure), trace) => Err((failure, Some(trace))),
+                }
+            }
+            condition => {
+                if let Some(rule) = rule {
+                    context
+                        .options
+                        .check_cancelled()
+                        .and_then(|()| context.spend_condition(rule))
+                        .map_err(|error| {
+                            let message = error.to_string();
+                            (error, message)
+                        })?;
+                }
+                evaluate_condition_with_trace(condition, json, rule_set, context).map_err(
+                    |(error, trace)| {
+                        let message = format!(
+                            "Condition evaluation failed: {}",
+                            context.trace_error(&error)
+                        );
+                        ((error, message), trace)
+                    },
+                )
+            }
+        };
+
+        match evaluated {
+            Ok((mut result, mut trace)) => {
+                trace.set_id(group.id.clone());
+                // A skipped reference is left out rather than negated
+                if group.negated && !trace.is_skipped() {
+                    trace.negate();
+                    result = !result;
+                }
+                let action = match (&context.options.debug_hook, rule) {
+                    (Some(hook), Some(rule)) if !matches!(group.condition, Condition::Group(_)) => {
+                        let view = DebugView::new(&context.call_path, traces, &context.memo);
+                        hook.on_condition_evaluated(rule, &trace, &view)
+                    }
+                    _ => DebugAction::Continue,
+                };
+                results.push((!trace.is_skipped()).then_some(result));
+                traces.push(trace);
+                match action {
+                    DebugAction::Continue => {}
+                    DebugAction::SkipRule => context.skip_rule = true,
+                    DebugAction::Abort => {
+                        let message = RuleError::Cancelled.to_string();
+                        return Err((RuleError::Cancelled, message));
+                    }
+                }
+            }
+            Err((failure, trace)) => {
+                if let Some(mut trace) = trace {
+                    trace.set_id(group.id.clone());
+                    traces.push(trace);
                 }
-                partial_trace.set_error(format!("Condition evaluation failed: {}", error));
-                evaluation_stack.remove(&rule_identifier);
-                call_path.pop();
-                return Err((error, Some(partial_trace)));
+                return Err(failure);
             }
         }
+    }
+    Ok(results)
+}
 
-        // record the operator that follows this condition
-        if let Some(op) = &cg.operator {
-            ops.push(op.clone());
-        } else if i != 0 {
-            ops.push(ConditionOperator::And);
+/// Evaluates the conditions between a group's parentheses, see `evaluate_conditions`. The
+/// group's trace comes back either way, on failure with what of it was evaluated
+fn evaluate_group(
+    members: &[ConditionGroup],
+    rule: Option<&Rule>,
+    json: &Value,
+    rule_set: &RuleSet,
+    context: &mut EvaluationContext,
+) -> (Result<bool, (RuleError, String)>, ConditionTrace) {
+    let mut traces = Vec::new();
+    match evaluate_conditions(members, rule, json, rule_set, context, &mut traces) {
+        Ok(results) => {
+            let (result, trace) = group_trace(members, &results, traces);
+            (Ok(result), trace)
         }
+        Err(failure) => (Err(failure), group_trace(members, &[], traces).1),
     }
+}
 
-    evaluation_stack.remove(&rule_identifier);
-    call_path.pop();
+/// A group's result and trace from what its conditions came to. It is skipped when every
+/// condition in it was
+fn group_trace(
+    members: &[ConditionGroup],
+    results: &[Option<bool>],
+    traces: Vec<ConditionTrace>,
+) -> (bool, ConditionTrace) {
+    let (result, branch) = combine_results(members, results);
+    let trace = GroupTrace {
+        id: None,
+        conditions: traces,
+        branch: branch.map(|branch| {
+            members[branch]
+                .iter()
+                .map(|member| member.id.clone())
+                .collect()
+        }),
+        result: result.unwrap_or(false),
+        skipped: result.is_none() && !results.is_empty(),
+        inner_result: None,
+    };
+    (trace.result, ConditionTrace::Group(trace))
+}
 
-    // collapse all ANDs first
-    let mut i = 0;
-    while i < ops.len() {
-        if ops[i] == ConditionOperator::And {
-            results[i] = results[i] && results[i + 1];
-            results.remove(i + 1);
-            ops.remove(i);
-        } else {
-            i += 1;
+/// Combines what `conditions` came to, AND binding tighter than OR, so each OR starts a
+/// branch. Skipped conditions are left out of their branch, and a branch left empty out of
+/// the OR; `None` when every condition was skipped. Also the conditions of the first branch
+/// that held, if one did
+fn combine_results(
+    conditions: &[ConditionGroup],
+    results: &[Option<bool>],
+) -> (Option<bool>, Option<Range<usize>>) {
+    // Where each branch starts and what it came to
+    let mut branches: Vec<(usize, Option<bool>)> = vec![(0, None)];
+    for (index, (condition, result)) in conditions.iter().zip(results).enumerate() {
+        if index > 0 && condition.operator == Some(ConditionOperator::Or) {
+            branches.push((index, None));
+        }
+        if let Some(result) = result {
+            let (_, branch) = branches.last_mut().expect("branches start with one");
+            *branch = Some(branch.unwrap_or(true) && *result);
         }
     }
 
-    // fold OR across what remains
-    let rule_result = results.into_iter().fold(false, |acc, next| acc || next);
+    let held = branches
+        .iter()
+        .position(|(_, result)| *result == Some(true))
+        .map(|index| {
+            let end = branches
+                .get(index + 1)
+                .map_or(conditions.len(), |(start, _)| *start);
+            branches[index].0..end
+        });
+    let mut kept = branches
+        .into_iter()
+        .filter_map(|(_, result)| result)
+        .peekable();
+    let result = kept.peek().is_some().then(|| held.is_some());
+    (result, held)
+}
 
-    // build complete trace object
-    let rule_trace = RuleTrace {
-        label: model_rule.label.clone(),
+fn rule_trace(
+    rule: &Rule,
+    conditions: Vec<ConditionTrace>,
+    result: bool,
+    skipped: bool,
+) -> RuleTrace {
+    RuleTrace {
+        label: rule.label.clone(),
         selector: SelectorTrace {
-            value: model_rule.selector.clone(),
-            pos: model_rule.selector_pos.clone(),
+            value: rule.selector.clone(),
+            pos: rule.selector_pos.clone(),
         },
         outcome: OutcomeTrace {
-            value: model_rule.outcome.clone(),
-            pos: model_rule.position.clone(),
+            value: rule.outcome_display.clone(),
+            pos: rule.position.clone(),
         },
-        conditions: condition_traces,
-        result: rule_result,
-    };
-
-    Ok((rule_result, rule_trace))
+        conditions,
+        result,
+        skipped,
+        error: None,
+    }
 }
 
+#[allow(dead_code)]
 pub fn evaluate_rule(
     model_rule: &Rule,
     json: &Value,
     rule_set: &RuleSet,
-    evaluation_stack: &mut HashSet<String>,
+    evaluation_stack: &mut HashSet<usize>,
     call_path: &mut Vec<String>,
 ) -> Result<(bool, RuleTrace), RuleError> {
     // cycle check
-    let rule_identifier = model_rule.outcome.clone();
+    let rule_identifier = rule_identity(rule_set, model_rule);
     if evaluation_stack.contains(&rule_identifier) {
-        call_path.push(rule_identifier.clone());
-        return Err(RuleError::infinite_loop_error(call_path.clone()));
+        call_path.push(model_rule.name().to_string());
+        return Err(RuleError::InfiniteLoop {
+            call_path: call_path.clone(),
+            max_frames: DEFAULT_MAX_CALL_PATH_FRAMES,
+        });
     }
-    evaluation_stack.insert(rule_identifier.clone());
-    call_path.push(rule_identifier.clone());
+    evaluation_stack.insert(rule_identifier);
+    call_path.push(model_rule.name().to_string());
 
     // 1) evaluate each condition, collect its bool and its trace
     let mut results = Vec::new();
@@ -324,9 +991,12 @@ pub fn evaluate_rule(
     let mut condition_traces = Vec::new();
 
     for (i, cg) in model_rule.conditions.iter().enumerate() {
-        let (res, trace) =
-            evaluate_condition(&cg.condition, json, rule_set, evaluation_stack, call_path)?;
-        results.push(res);
+        let (res, mut trace) =
+            evaluate_rule_condition(&cg.condition, json, rule_set, evaluation_stack, call_path)?;
+        if cg.negated {
+            trace.negate();
+        }
+        results.push(res != cg.negated);
         condition_traces.push(trace);
 
         // record the operator that *follows* this condition (None for first)
@@ -367,11 +1037,13 @@ pub fn evaluate_rule(
             pos: model_rule.selector_pos.clone(),
         },
         outcome: OutcomeTrace {
-            value: model_rule.outcome.clone(),
+            value: model_rule.outcome_display.clone(),
             pos: model_rule.position.clone(),
         },
         conditions: condition_traces,
         result: rule_result,
+        skipped: false,
+        error: None,
     };
 
     Ok((rule_result, rule_trace))
@@ -382,8 +1054,7 @@ fn evaluate_condition_with_trace(
     condition: &Condition,
     json: &Value,
     rule_set: &RuleSet,
-    evaluation_stack: &mut HashSet<String>,
-    call_path: &mut Vec<String>,
+    context: &mut EvaluationContext,
 ) -> Result<(bool, ConditionTrace), (RuleError, Option<ConditionTrace>)> {
     match condition {
         Condition::RuleReference(ref_condition) => {
@@ -391,27 +1062,96 @@ fn evaluate_condition_with_trace(
                 ref_condition,
                 json,
                 rule_set,
-                evaluation_stack,
-                call_path,
+                context,
             ) {
                 Ok(result) => Ok(result),
                 Err((error, trace)) => Err((error, trace)),
             }
         }
         Condition::Comparison(comp_condition) => {
-            match evaluate_comparison_condition_with_trace(comp_condition, json) {
-                Ok(result) => Ok(result),
-                Err((error, trace)) => Err((error, trace)),
+            let mut evaluated = evaluate_comparison_condition_with_trace(
+                comp_condition,
+                json,
+                context.options,
+                &mut context.stats,
+            );
+            if let Ok((_, ConditionTrace::Comparison(trace)))
+            | Err((_, Some(ConditionTrace::Comparison(trace)))) = &mut evaluated
+            {
+                trace.record_sources();
+                context.stats.properties_resolved += trace.properties_found();
             }
+            evaluated
         }
+        Condition::Flag(flag) => Ok(evaluate_flag_condition(flag, context)),
+        Condition::Quantified(quantified) => {
+            let evaluated = evaluate_quantified_condition(
+                quantified,
+                json,
+                context.options,
+                &mut context.stats,
+            );
+            if let Ok((_, ConditionTrace::Comparison(trace))) = &evaluated {
+                context.stats.properties_resolved += trace.properties_found();
+            }
+            evaluated.map_err(|error| {
+                let failed_trace = create_failed_comparison_trace(&quantified.comparison, None);
+                (error, Some(failed_trace))
+            })
+        }
+        Condition::Group(members) => match evaluate_group(members, None, json, rule_set, context) {
+            (Ok(result), trace) => Ok((result, trace)),
+            (Err((error, _)), trace) => Err((error, Some(trace))),
+        },
     }
 }
 
-fn evaluate_condition(
+/// Reads the flag from the options' provider, once per evaluation. A flag the provider
+/// doesn't know, or any flag without a provider, takes `unknown_flag_default` with a warning
+fn evaluate_flag_condition(
+    condition: &FlagCondition,
+    context: &mut EvaluationContext,
+) -> (bool, ConditionTrace) {
+    let name = &condition.flag.value;
+    let options = context.options;
+    let (enabled, known) = *context.flags.entry(name.clone()).or_insert_with(|| {
+        match options
+            .flags
+            .as_ref()
+            .and_then(|flags| flags.is_enabled(name))
+        {
+            Some(enabled) => (enabled, true),
+            None => (options.unknown_flag_default, false),
+        }
+    });
+    let warning = (!known).then(|| {
+        format!(
+            "flag '{}' is unknown, taken as {}",
+            name,
+            if enabled { "enabled" } else { "disabled" }
+        )
+    });
+
+    let result = enabled == condition.enabled;
+    let trace = FlagTrace {
+        id: None,
+        flag: name.clone(),
+        expected: condition.enabled,
+        enabled,
+        known,
+        warning,
+        result,
+        inner_result: None,
+    };
+    (result, ConditionTrace::Flag(trace))
+}
+
+#[allow(dead_code)]
+fn evaluate_rule_condition(
     condition: &Condition,
     json: &Value,
     rule_set: &RuleSet,
-    evaluation_stack: &mut HashSet<String>,
+    evaluation_stack: &mut HashSet<usize>,
     call_path: &mut Vec<String>,
 ) -> Result<(bool, ConditionTrace), RuleError> {
     match condition {
@@ -425,107 +1165,142 @@ fn evaluate_condition(
         Condition::Comparison(comp_condition) => {
             evaluate_comparison_condition(comp_condition, json)
         }
+        Condition::Flag(flag) => Ok(evaluate_flag_condition(
+            flag,
+            &mut EvaluationContext::new(&EvaluationOptions::default()),
+        )),
+        Condition::Quantified(quantified) => evaluate_quantified_condition(
+            quantified,
+            json,
+            &EvaluationOptions::default(),
+            &mut EvalStats::default(),
+        ),
+        Condition::Group(members) => {
+            let mut results = Vec::with_capacity(members.len());
+            let mut traces = Vec::with_capacity(members.len());
+            for member in members {
+                let (result, mut trace) = evaluate_rule_condition(
+                    &member.condition,
+                    json,
+                    rule_set,
+                    evaluation_stack,
+                    call_path,
+                )?;
+                trace.set_id(member.id.clone());
+                if member.negated {
+                    trace.negate();
+                }
+                results.push(Some(result != member.negated));
+                traces.push(trace);
+            }
+            Ok(group_trace(members, &results, traces))
+        }
     }
 }
 
-fn evaluate_rule_reference_condition(
-    condition: &RuleReferenceCondition,
-    json: &Value,
-    rule_set: &RuleSet,
-    evaluation_stack: &mut HashSet<String>,
-    call_path: &mut Vec<String>,
-) -> Result<(bool, ConditionTrace), RuleError> {
-    // Handle empty selector case (for label references)
-    if condition.selector.value.is_empty() {
-        // This is a label reference without a selector
-        let rule_name = condition.rule_name.value.trim();
+/// What a rule reference checked: the referenced rule, or failing that an inferred property
+struct ResolvedReference {
+    result: bool,
+    outcome: Option<String>,
+    property_check: Option<PropertyCheckTrace>,
+    resolution: ReferenceResolution,
+    /// The referenced rule was skipped by the tag filters
+    skipped: bool,
+}
 
-        // Try to find and evaluate the referenced rule
-        if let Some((result, outcome)) =
-            try_evaluate_by_rule(rule_name, json, rule_set, evaluation_stack, call_path)?
-        {
-            let rule_reference_trace = RuleReferenceTrace {
-                selector: SelectorTrace {
-                    value: String::new(),
-                    pos: None,
-                },
-                rule_name: condition.rule_name.value.clone(),
-                referenced_rule_outcome: Some(outcome),
-                property_check: None,
-                result,
-            };
-            return Ok((result, ConditionTrace::RuleReference(rule_reference_trace)));
+impl ResolvedReference {
+    fn unresolved(result: bool) -> Self {
+        ResolvedReference {
+            result,
+            outcome: None,
+            property_check: None,
+            resolution: ReferenceResolution::Unresolved,
+            skipped: false,
         }
+    }
+
+    fn into_trace(self, condition: &RuleReferenceCondition) -> (bool, ConditionTrace) {
+        let trace = RuleReferenceTrace {
+            id: None,
+            selector: SelectorTrace {
+                value: condition.selector.value.clone(),
+                pos: condition.selector.pos.clone(),
+            },
+            rule_name: condition.rule_name.value.clone(),
+            referenced_rule_outcome: self.outcome,
+            property_check: self.property_check,
+            resolution: self.resolution,
+            result: self.result,
+            skipped: self.skipped,
+            inner_result: None,
+        };
+        (self.result, ConditionTrace::RuleReference(trace))
+    }
+}
+
+#[allow(dead_code)]
+fn evaluate_rule_reference_condition(
+    condition: &RuleReferenceCondition,
+    json: &Value,
+    rule_set: &RuleSet,
+    evaluation_stack: &mut HashSet<usize>,
+    call_path: &mut Vec<String>,
+) -> Result<(bool, ConditionTrace), RuleError> {
+    let part = condition.rule_name.value.trim();
 
-        // If no rule found, return false
-        return Ok((false, create_failed_rule_reference_trace(condition)));
+    // Handle empty selector case (for label references)
+    if condition.selector.value.is_empty() {
+        // Try to find and evaluate the referenced rule, failing when there is none
+        let resolved = try_evaluate_by_rule(part, json, rule_set, evaluation_stack, call_path)?
+            .unwrap_or_else(|| ResolvedReference::unresolved(false));
+        return Ok(resolved.into_trace(condition));
     }
 
     // Normal case with selector
-    let effective_selector = find_effective_selector(&condition.selector.value, json)?;
+    let mut lookup = DataLookup::new(&EvaluationOptions::default());
+    let effective_selector = find_effective_selector(&condition.selector.value, json, &mut lookup)?;
 
-    let (result, referenced_outcome, property_check) = if effective_selector.is_some() {
+    let resolved = if let Some(effective_selector) = effective_selector {
         // Selector exists in JSON - use it directly
-        let part = condition.rule_name.value.trim();
         evaluate_rule_or_property(
             part,
-            &effective_selector.unwrap(),
+            effective_selector,
             json,
             rule_set,
             evaluation_stack,
             call_path,
         )?
+    } else if let Some(resolved) =
+        // Conceptual selector - first try to find the rule globally (without a specific selector)
+        try_evaluate_by_rule(part, json, rule_set, evaluation_stack, call_path)?
+    {
+        resolved
     } else {
-        // Conceptual selector - try to evaluate the rule without requiring the selector to exist
-        let part = condition.rule_name.value.trim();
-
-        // First, try to find the rule globally (without a specific selector)
-        if let Some((rule_result, outcome)) =
-            try_evaluate_by_rule(part, json, rule_set, evaluation_stack, call_path)?
-        {
-            (rule_result, Some(outcome), None)
-        } else {
-            // If no global rule found, try to evaluate against all available objects in the JSON
-            let mut found_any_match = false;
-            let mut last_outcome = None;
-            let mut last_property_check = None;
-
-            if let Some(obj) = json.as_object() {
-                for (key, _) in obj {
-                    if let Ok((rule_result, outcome, prop_check)) = evaluate_rule_or_property(
-                        part,
-                        key,
-                        json,
-                        rule_set,
-                        evaluation_stack,
-                        call_path,
-                    ) {
-                        if rule_result {
-                            found_any_match = true;
-                            last_outcome = outcome;
-                            last_property_check = prop_check;
-                            break; // Found a match, we can stop
-                        }
+        // If no global rule found, try to evaluate against all available objects in the JSON
+        let mut matched = None;
+
+        if let Some(obj) = json.as_object() {
+            for (key, _) in obj {
+                if let Ok(resolved) = evaluate_rule_or_property(
+                    part,
+                    key,
+                    json,
+                    rule_set,
+                    evaluation_stack,
+                    call_path,
+                ) {
+                    if resolved.result {
+                        matched = Some(resolved);
+                        break; // Found a match, we can stop
                     }
                 }
             }
-
-            (found_any_match, last_outcome, last_property_check)
         }
-    };
 
-    let rule_reference_trace = RuleReferenceTrace {
-        selector: SelectorTrace {
-            value: condition.selector.value.clone(),
-            pos: condition.selector.pos.clone(),
-        },
-        rule_name: condition.rule_name.value.clone(),
-        referenced_rule_outcome: referenced_outcome,
-        property_check,
-        result,
+        matched.unwrap_or_else(|| ResolvedReference::unresolved(false))
     };
 
-    Ok((result, ConditionTrace::RuleReference(rule_reference_trace)))
+    Ok(resolved.into_trace(condition))
 }
 
 #[allow(dead_code)]
@@ -533,142 +1308,87 @@ fn evaluate_rule_reference_condition_with_trace(
     condition: &RuleReferenceCondition,
     json: &Value,
     rule_set: &RuleSet,
-    evaluation_stack: &mut HashSet<String>,
-    call_path: &mut Vec<String>,
+    context: &mut EvaluationContext,
 ) -> Result<(bool, ConditionTrace), (RuleError, Option<ConditionTrace>)> {
+    let part = condition.rule_name.value.trim();
+    let failed = |error| (error, Some(create_failed_rule_reference_trace(condition)));
+
     // Handle empty selector case (for label references)
     if condition.selector.value.is_empty() {
-        let rule_name = condition.rule_name.value.trim();
-
-        // Try to find and evaluate the referenced rule
-        match try_evaluate_by_rule_with_trace(
-            rule_name,
-            json,
-            rule_set,
-            evaluation_stack,
-            call_path,
-        ) {
-            Ok(Some((result, outcome))) => {
-                let rule_reference_trace = RuleReferenceTrace {
-                    selector: SelectorTrace {
-                        value: String::new(),
-                        pos: None,
-                    },
-                    rule_name: condition.rule_name.value.clone(),
-                    referenced_rule_outcome: Some(outcome),
-                    property_check: None,
-                    result,
-                };
-                return Ok((result, ConditionTrace::RuleReference(rule_reference_trace)));
-            }
-            Ok(None) => {
-                // Rule not found
-                return Ok((false, create_failed_rule_reference_trace(condition)));
-            }
-            Err((error, _)) => {
-                // Error during rule evaluation
-                let failed_trace = create_failed_rule_reference_trace(condition);
-                return Err((error, Some(failed_trace)));
-            }
-        }
+        // Try to find and evaluate the referenced rule, failing when there is none
+        let resolved = try_evaluate_by_rule_with_trace(part, json, rule_set, context)
+            .map_err(|(error, _)| failed(error))?
+            .unwrap_or_else(|| ResolvedReference::unresolved(false));
+        return Ok(resolved.into_trace(condition));
     }
 
     // Normal case with selector
-    let effective_selector = match find_effective_selector(&condition.selector.value, json) {
-        Ok(sel) => sel,
-        Err(error) => {
-            let failed_trace = create_failed_rule_reference_trace(condition);
-            return Err((error, Some(failed_trace)));
-        }
-    };
+    let effective_selector = find_effective_selector(
+        &condition.selector.value,
+        json,
+        &mut DataLookup::counted(context.options, &mut context.stats),
+    )
+    .map_err(failed)?;
 
-    let part = condition.rule_name.value.trim();
-    let (result, referenced_outcome, property_check) = if effective_selector.is_some() {
+    let resolved = if let Some(effective_selector) = effective_selector {
         // Selector exists in JSON - use it directly
-        match evaluate_rule_or_property_with_trace(
-            part,
-            &effective_selector.unwrap(),
-            json,
-            rule_set,
-            evaluation_stack,
-            call_path,
-        ) {
-            Ok(result) => result,
-            Err((error, _)) => {
-                let failed_trace = create_failed_rule_reference_trace(condition);
-                return Err((error, Some(failed_trace)));
-            }
-        }
+        evaluate_rule_or_property_with_trace(part, effective_selector, json, rule_set, context)
+            .map_err(|(error, _)| failed(error))?
+    } else if let Some(resolved) =
+        // Conceptual selector - first try to find the rule globally (without a specific selector)
+        try_evaluate_by_rule_with_trace(part, json, rule_set, context)
+                .map_err(|(error, _)| failed(error))?
+    {
+        resolved
     } else {
-        // Conceptual selector - try to evaluate the rule without requiring the selector to exist
-
-        // First, try to find the rule globally (without a specific selector)
-        match try_evaluate_by_rule_with_trace(part, json, rule_set, evaluation_stack, call_path) {
-            Ok(Some((rule_result, outcome))) => (rule_result, Some(outcome), None),
-            Ok(None) => {
-                // If no global rule found, try to evaluate against all available objects in the JSON
-                let mut found_any_match = false;
-                let mut last_outcome = None;
-                let mut last_property_check = None;
-
-                if let Some(obj) = json.as_object() {
-                    for (key, _) in obj {
-                        match evaluate_rule_or_property_with_trace(
-                            part,
-                            key,
-                            json,
-                            rule_set,
-                            evaluation_stack,
-                            call_path,
-                        ) {
-                            Ok((rule_result, outcome, prop_check)) => {
-                                if rule_result {
-                                    found_any_match = true;
-                                    last_outcome = outcome;
-                                    last_property_check = prop_check;
-                                    break; // Found a match, we can stop
-                                }
-                            }
-                            Err(_) => {
-                                // Ignore errors and continue to next key
-                                continue;
-                            }
-                        }
+        // If no global rule found, try to evaluate against all available objects in the JSON
+        let mut matched = None;
+
+        if let Some(obj) = json.as_object() {
+            for (key, _) in obj {
+                context.options.check_cancelled().map_err(failed)?;
+
+                // Errors are ignored and the next key is tried, except a spent budget or references
+                // nested too deep
+                match evaluate_rule_or_property_with_trace(part, key, json, rule_set, context) {
+                    Ok(resolved) if resolved.result => {
+                        matched = Some(resolved);
+                        break; // Found a match, we can stop
                     }
+                    Err((
+                        error @ (RuleError::BudgetExceeded { .. }
+                        | RuleError::MaxDepthExceeded { .. }),
+                        _,
+                    )) => return Err(failed(error)),
+                    _ => {}
                 }
-
-                (found_any_match, last_outcome, last_property_check)
-            }
-            Err((error, _)) => {
-                let failed_trace = create_failed_rule_reference_trace(condition);
-                return Err((error, Some(failed_trace)));
             }
         }
-    };
 
-    let rule_reference_trace = RuleReferenceTrace {
-        selector: SelectorTrace {
-            value: condition.selector.value.clone(),
-            pos: condition.selector.pos.clone(),
-        },
-        rule_name: condition.rule_name.value.clone(),
-        referenced_rule_outcome: referenced_outcome,
-        property_check,
-        result,
+        matched.unwrap_or_else(|| ResolvedReference::unresolved(false))
     };
 
-    Ok((result, ConditionTrace::RuleReference(rule_reference_trace)))
+    Ok(resolved.into_trace(condition))
 }
 
 #[allow(dead_code)]
 fn evaluate_comparison_condition_with_trace(
     condition: &ComparisonCondition,
     json: &Value,
+    options: &EvaluationOptions,
+    stats: &mut EvalStats,
 ) -> Result<(bool, ConditionTrace), (RuleError, Option<ConditionTrace>)> {
     // Check if this is a cross-object comparison
     if let Some(left_path) = &condition.left_property_path {
-        return match evaluate_cross_object_comparison(condition, left_path, json) {
+        return match evaluate_cross_object_comparison(condition, left_path, json, options, stats) {
             Ok(result) => Ok(result),
+            Err(RuleError::UnexpectedObject { path, object }) => {
+                let failed_trace = unexpected_object_trace(condition, json, &path, &object);
+                Err((
+                    RuleError::UnexpectedObject { path, object },
+                    Some(failed_trace),
+                ))
+            }
             Err(error) => {
                 let failed_trace = create_failed_comparison_trace(condition, None);
                 Err((error, Some(failed_trace)))
@@ -678,7 +1398,13 @@ fn evaluate_comparison_condition_with_trace(
 
     // Check if this is a chained property access
     if let Some(property_chain) = &condition.property_chain {
-        return match evaluate_chained_comparison_condition(condition, property_chain, json) {
+        return match evaluate_chained_comparison_condition(
+            condition,
+            property_chain,
+            json,
+            options,
+            stats,
+        ) {
             Ok(result) => Ok(result),
             Err(error) => {
                 let failed_trace = create_failed_comparison_trace(condition, None);
@@ -688,59 +1414,78 @@ fn evaluate_comparison_condition_with_trace(
     }
 
     // Original simple property condition logic
-    let effective_selector = match find_effective_selector(&condition.selector.value, json) {
-        Ok(Some(sel)) => sel,
-        Ok(None) => {
-            return Ok((false, create_failed_comparison_trace(condition, None)));
-        }
-        Err(error) => {
-            let failed_trace = create_failed_comparison_trace(condition, None);
-            return Err((error, Some(failed_trace)));
-        }
-    };
+    let mut lookup = DataLookup::counted(options, stats);
+    let effective_selector =
+        match find_effective_selector(&condition.selector.value, json, &mut lookup) {
+            Ok(Some(sel)) => sel,
+            Ok(None) => {
+                return Ok((false, create_failed_comparison_trace(condition, None)));
+            }
+            Err(error) => {
+                let failed_trace = create_failed_comparison_trace(condition, None);
+                return Err((error, Some(failed_trace)));
+            }
+        };
 
     // Check if property exists
     let property_value = json
-        .get(&effective_selector)
+        .get(effective_selector)
         .and_then(|obj| obj.get(&condition.property.value));
 
     if property_value.is_none() {
         return Ok((
             false,
-            create_failed_comparison_trace(condition, Some(&effective_selector)),
+            create_failed_comparison_trace(condition, Some(effective_selector)),
         ));
     }
 
     // Extract and evaluate the comparison
     let json_value =
-        match extract_value_from_json(json, &effective_selector, &condition.property.value) {
+        match extract_value_from_json(json, effective_selector, &condition.property.value) {
             Ok(value) => value,
             Err(error) => {
                 let failed_trace =
-                    create_failed_comparison_trace(condition, Some(&effective_selector));
+                    create_failed_comparison_trace(condition, Some(effective_selector));
                 return Err((error, Some(failed_trace)));
             }
         };
 
-    let (comparison_result, evaluation_details) =
-        match perform_comparison(&json_value, &condition.operator, &condition.value.value) {
-            Ok(result) => result,
-            Err(error) => {
-                let failed_trace =
-                    create_failed_comparison_trace(condition, Some(&effective_selector));
-                return Err((error, Some(failed_trace)));
-            }
-        };
+    let property_path = json_path_from_keys(&[effective_selector, &condition.property.value]);
+    let operands = Operands {
+        left: &property_path,
+        right: None,
+        lenient: options.lenient,
+        list_mode: options.list_scalar_mode,
+        versions: !options.versions_as_strings,
+        case_sensitive: options.case_sensitive_strings,
+        today: options.today(),
+    };
+    let (comparison_result, evaluation_details) = match perform_comparison(
+        &json_value,
+        &condition.operator,
+        &condition.value.value,
+        operands,
+    ) {
+        Ok(result) => result,
+        Err(error) => {
+            let failed_trace = create_failed_comparison_trace(condition, Some(effective_selector));
+            return Err((error, Some(failed_trace)));
+        }
+    };
 
     // Build the trace
     let comparison_trace = ComparisonTrace {
+        id: None,
         selector: SelectorTrace {
             value: condition.selector.value.clone(),
             pos: condition.selector.pos.clone(),
         },
         property: PropertyTrace {
             value: property_value.unwrap().clone(),
-            path: format!("$.{}.{}", effective_selector, condition.property.value),
+            path: property_path,
+            document: None,
+            missed_key: None,
+            warning: None,
         },
         operator: condition.operator.clone(),
         value: condition
@@ -749,70 +1494,106 @@ fn evaluate_comparison_condition_with_trace(
             .to_value_trace(condition.value.pos.clone()),
         evaluation_details,
         result: comparison_result,
+        inner_result: None,
+        left_expression: None,
+        right_expression: None,
     };
 
     Ok((
         comparison_result,
-        ConditionTrace::Comparison(comparison_trace),
+        ConditionTrace::Comparison(Box::new(comparison_trace)),
     ))
 }
 
+#[allow(dead_code)]
 fn evaluate_rule_or_property(
     rule_name: &str,
     effective_selector: &str,
     json: &Value,
     rule_set: &RuleSet,
-    evaluation_stack: &mut HashSet<String>,
+    evaluation_stack: &mut HashSet<usize>,
     call_path: &mut Vec<String>,
-) -> Result<(bool, Option<String>, Option<PropertyCheckTrace>), RuleError> {
+) -> Result<ResolvedReference, RuleError> {
     // Try to find a matching rule first
-    if let Some((result, outcome)) =
+    if let Some(resolved) =
         try_evaluate_by_rule(rule_name, json, rule_set, evaluation_stack, call_path)?
     {
-        return Ok((result, Some(outcome), None));
+        return Ok(resolved);
     }
 
     // If no rule found, try to evaluate as a property
     if let Some(property_check) = try_evaluate_as_property(rule_name, effective_selector, json)? {
-        let result = evaluate_property_result(&property_check);
-        return Ok((result, None, Some(property_check)));
+        return Ok(inferred_property(property_check));
     }
 
     // If neither rule nor property found, assume true (free text condition)
     // This handles cases like "eye test" where no rule or property exists
     //eprintln!("Info: No rule or property found for '{}' - assuming true", rule_name);
-    Ok((true, None, None))
+    Ok(ResolvedReference::unresolved(true))
 }
 
-fn try_evaluate_by_rule(
+fn inferred_property(property_check: PropertyCheckTrace) -> ResolvedReference {
+    ResolvedReference {
+        result: evaluate_property_result(&property_check),
+        outcome: None,
+        resolution: ReferenceResolution::InferredProperty {
+            property: property_check.property_name.clone(),
+        },
+        property_check: Some(property_check),
+        skipped: false,
+    }
+}
+
+/// Finds the rule a reference names: by exact outcome, then by label, then by a fuzzy outcome
+/// match. The same lookup order is used wherever references are followed
+pub(crate) fn resolve_rule<'a>(
     rule_name: &str,
-    json: &Value,
-    rule_set: &RuleSet,
-    evaluation_stack: &mut HashSet<String>,
-    call_path: &mut Vec<String>,
-) -> Result<Option<(bool, String)>, RuleError> {
-    // Try exact outcome match
+    rule_set: &'a RuleSet,
+) -> Option<(&'a Rule, ReferenceResolution)> {
     if let Some(rule) = rule_set.get_rule(rule_name) {
-        let (result, _) = evaluate_rule(rule, json, rule_set, evaluation_stack, call_path)?;
-        return Ok(Some((result, rule.outcome.clone())));
+        // The outcome map also holds aliases
+        let resolution =
+            if rule.outcome == rule_name || rule.outcome == normalize_outcome(rule_name) {
+                ReferenceResolution::ExactOutcome
+            } else {
+                ReferenceResolution::Alias
+            };
+        return Some((rule, resolution));
     }
-
-    // Try exact label match
     if let Some(rule) = rule_set.get_rule_by_label(rule_name) {
-        let (result, _) = evaluate_rule(rule, json, rule_set, evaluation_stack, call_path)?;
-        return Ok(Some((result, rule.outcome.clone())));
-    }
-
-    // Try fuzzy matching
-    if let Some(rule) = find_rule_fuzzy_match(rule_name, rule_set) {
-        let (result, _) = evaluate_rule(rule, json, rule_set, evaluation_stack, call_path)?;
-        return Ok(Some((result, rule.outcome.clone())));
+        return Some((rule, ReferenceResolution::Label));
     }
+    find_rule_fuzzy_match(rule_name, rule_set).map(|rule| {
+        let matched = rule.outcome.clone();
+        (rule, ReferenceResolution::FuzzyOutcome { matched })
+    })
+}
 
-    Ok(None)
+#[allow(dead_code)]
+fn try_evaluate_by_rule(
+    rule_name: &str,
+    json: &Value,
+    rule_set: &RuleSet,
+    evaluation_stack: &mut HashSet<usize>,
+    call_path: &mut Vec<String>,
+) -> Result<Option<ResolvedReference>, RuleError> {
+    let Some((rule, resolution)) = resolve_rule(rule_name, rule_set) else {
+        return Ok(None);
+    };
+    let (result, _) = evaluate_rule(rule, json, rule_set, evaluation_stack, call_path)?;
+    Ok(Some(ResolvedReference {
+        result,
+        outcome: Some(rule.outcome.clone()),
+        property_check: None,
+        resolution,
+        skipped: false,
+    }))
 }
 
-fn find_rule_fuzzy_match<'a>(rule_name: &str, rule_set: &'a RuleSet) -> Option<&'a Rule> {
+pub(crate) fn find_rule_fuzzy_match<'a>(
+    rule_name: &str,
+    rule_set: &'a RuleSet,
+) -> Option<&'a Rule> {
     // Check cache first
     if let Ok(cache) = rule_set.cache.rule_fuzzy_matches.read() {
         if let Some(cached_outcome) = cache.get(rule_name) {
@@ -892,34 +1673,19 @@ fn try_evaluate_by_rule_with_trace(
     rule_name: &str,
     json: &Value,
     rule_set: &RuleSet,
-    evaluation_stack: &mut HashSet<String>,
-    call_path: &mut Vec<String>,
-) -> Result<Option<(bool, String)>, (RuleError, Option<PartialRuleTrace>)> {
-    // Try exact outcome match
-    if let Some(rule) = rule_set.get_rule(rule_name) {
-        match evaluate_rule_with_trace(rule, json, rule_set, evaluation_stack, call_path) {
-            Ok((result, _)) => return Ok(Some((result, rule.outcome.clone()))),
-            Err((error, partial_trace)) => return Err((error, partial_trace)),
-        }
-    }
-
-    // Try exact label match
-    if let Some(rule) = rule_set.get_rule_by_label(rule_name) {
-        match evaluate_rule_with_trace(rule, json, rule_set, evaluation_stack, call_path) {
-            Ok((result, _)) => return Ok(Some((result, rule.outcome.clone()))),
-            Err((error, partial_trace)) => return Err((error, partial_trace)),
-        }
-    }
-
-    // Try fuzzy matching
-    if let Some(rule) = find_rule_fuzzy_match(rule_name, rule_set) {
-        match evaluate_rule_with_trace(rule, json, rule_set, evaluation_stack, call_path) {
-            Ok((result, _)) => return Ok(Some((result, rule.outcome.clone()))),
-            Err((error, partial_trace)) => return Err((error, partial_trace)),
-        }
-    }
-
-    Ok(None)
+    context: &mut EvaluationContext,
+) -> Result<Option<ResolvedReference>, (RuleError, Option<PartialRuleTrace>)> {
+    let Some((rule, resolution)) = resolve_rule(rule_name, rule_set) else {
+        return Ok(None);
+    };
+    let (result, trace) = evaluate_rule_with_trace(rule, json, rule_set, context)?;
+    Ok(Some(ResolvedReference {
+        result,
+        outcome: Some(rule.outcome.clone()),
+        property_check: None,
+        resolution,
+        skipped: trace.skipped,
+    }))
 }
 
 #[allow(dead_code)]
@@ -928,33 +1694,18 @@ fn evaluate_rule_or_property_with_trace(
     effective_selector: &str,
     json: &Value,
     rule_set: &RuleSet,
-    evaluation_stack: &mut HashSet<String>,
-    call_path: &mut Vec<String>,
-) -> Result<(bool, Option<String>, Option<PropertyCheckTrace>), (RuleError, Option<PartialRuleTrace>)>
-{
+    context: &mut EvaluationContext,
+) -> Result<ResolvedReference, (RuleError, Option<PartialRuleTrace>)> {
     // Try to find a matching rule first
-    match try_evaluate_by_rule_with_trace(rule_name, json, rule_set, evaluation_stack, call_path) {
-        Ok(Some((result, outcome))) => {
-            return Ok((result, Some(outcome), None));
-        }
-        Ok(None) => {
-            // Continue to property evaluation
-        }
-        Err((error, partial_trace)) => {
-            return Err((error, partial_trace));
-        }
+    if let Some(resolved) = try_evaluate_by_rule_with_trace(rule_name, json, rule_set, context)? {
+        return Ok(resolved);
     }
 
     // If no rule found, try to evaluate as a property
     match try_evaluate_as_property(rule_name, effective_selector, json) {
-        Ok(Some(property_check)) => {
-            let result = evaluate_property_result(&property_check);
-            Ok((result, None, Some(property_check)))
-        }
-        Ok(None) => {
-            // If neither rule nor property found, assume true (free text condition)
-            Ok((true, None, None))
-        }
+        Ok(Some(property_check)) => Ok(inferred_property(property_check)),
+        // If neither rule nor property found, assume true (free text condition)
+        Ok(None) => Ok(ResolvedReference::unresolved(true)),
         Err(error) => Err((error, None)),
     }
 }
@@ -1036,27 +1787,239 @@ fn evaluate_property_result(property_check: &PropertyCheckTrace) -> bool {
     }
 }
 
-fn calculate_length_of(value: &Value) -> Result<f64, RuleError> {
-    match value {
-        Value::String(s) => Ok(s.len() as f64),
-        Value::Array(arr) => Ok(arr.len() as f64),
-        Value::Object(obj) => Ok(obj.len() as f64), // Object property count
-        Value::Null => Ok(0.0),
-        _ => Err(RuleError::EvaluationError(format!(
-            "Cannot calculate length of {:?}",
-            value
-        ))),
+/// The two counting forms a condition can apply to a property before comparing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountOperation {
+    LengthOf,
+    NumberOf,
+}
+
+impl CountOperation {
+    pub const ALL: [CountOperation; 2] = [CountOperation::LengthOf, CountOperation::NumberOf];
+
+    pub fn phrase(self) -> &'static str {
+        match self {
+            CountOperation::LengthOf => "length of",
+            CountOperation::NumberOf => "number of",
+        }
+    }
+
+    /// What gets counted for a value, `None` when the operation can't count it.
+    ///
+    /// | value  | length of  | number of |
+    /// |--------|------------|-----------|
+    /// | string | characters | error     |
+    /// | array  | elements   | elements  |
+    /// | object | keys       | keys      |
+    ///
+    /// Null is handled by `count_value` since it depends on the lenient option.
+    pub fn unit(self, value: &Value) -> Option<&'static str> {
+        match (self, value) {
+            (CountOperation::LengthOf, Value::String(_)) => Some("characters"),
+            (_, Value::Array(_)) => Some("elements"),
+            (_, Value::Object(_)) => Some("keys"),
+            _ => None,
+        }
+    }
+}
+
+/// Counts a resolved property for `length of` / `number of`.
+///
+/// Returns `Ok(None)` for null unless `lenient` is set, so a null property behaves
+/// like a missing one; lenient evaluation counts it as 0 instead.
+pub fn count_value(
+    operation: CountOperation,
+    value: &Value,
+    path: &str,
+    lenient: bool,
+) -> Result<Option<f64>, RuleError> {
+    if value.is_null() {
+        return Ok(if lenient { Some(0.0) } else { None });
+    }
+
+    if operation.unit(value).is_none() {
+        let (counts, hint) = match operation {
+            CountOperation::LengthOf => ("string characters, array elements or object keys", ""),
+            CountOperation::NumberOf if value.is_string() => (
+                "array elements or object keys",
+                ", use 'length of' to count characters",
+            ),
+            CountOperation::NumberOf => ("array elements or object keys", ""),
+        };
+        return Err(RuleError::TypeError(format!(
+            "Cannot take the {} {} at {}: '{}' counts {}{}",
+            operation.phrase(),
+            json_type_name(value),
+            path,
+            operation.phrase(),
+            counts,
+            hint
+        )));
+    }
+
+    let count = match value {
+        Value::String(s) => s.chars().count(),
+        Value::Array(arr) => arr.len(),
+        Value::Object(obj) => obj.len(),
+        _ => unreachable!("uncountable values are rejected above"),
+    };
+
+    Ok(Some(count as f64))
+}
+
+/// Prefix hashed ahead of every key. Changing it moves every key to a new bucket
+const HASH_BUCKET_DOMAIN: &str = "engine.hash_bucket.v1:";
+
+/// The sampling bucket of a key, from 0 to 99, as `hash bucket of` and `is in the 5% sample
+/// by` compute it. Every engine version buckets a key the same way, so other systems can
+/// reproduce a sample:
+///
+/// 1. SHA-256 of the UTF-8 bytes of `engine.hash_bucket.v1:` followed by the key
+/// 2. the first 8 bytes of the digest as a big-endian unsigned integer
+/// 3. that integer modulo 100
+///
+/// A string property is hashed as its text. Numbers and booleans are hashed as written in
+/// JSON, so `42` and `"42"` land in the same bucket but `42.0` doesn't
+pub fn hash_bucket(key: &str) -> u8 {
+    let digest = Sha256::digest(format!("{}{}", HASH_BUCKET_DOMAIN, key).as_bytes());
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(prefix) % 100) as u8
+}
+
+/// Buckets a resolved property for `hash bucket of`. Null behaves like a missing property
+fn hash_bucket_value(value: &Value, path: &str) -> Result<Option<f64>, RuleError> {
+    let key = match value {
+        Value::Null => return Ok(None),
+        Value::String(s) => Cow::Borrowed(s.as_str()),
+        Value::Number(_) | Value::Bool(_) => Cow::Owned(value.to_string()),
+        Value::Array(_) | Value::Object(_) => {
+            return Err(RuleError::TypeError(format!(
+                "Cannot take the hash bucket of {} at {}: only strings, numbers and booleans are bucketed",
+                json_type_name(value),
+                path
+            )))
+        }
+    };
+    Ok(Some(hash_bucket(&key) as f64))
+}
+
+/// The numbers a condition can take from an array of numbers before comparing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateOperation {
+    Sum,
+    Minimum,
+    Maximum,
+    Average,
+}
+
+impl AggregateOperation {
+    pub const ALL: [AggregateOperation; 4] = [
+        AggregateOperation::Sum,
+        AggregateOperation::Minimum,
+        AggregateOperation::Maximum,
+        AggregateOperation::Average,
+    ];
+
+    pub fn phrase(self) -> &'static str {
+        match self {
+            AggregateOperation::Sum => "sum of",
+            AggregateOperation::Minimum => "minimum of",
+            AggregateOperation::Maximum => "maximum of",
+            AggregateOperation::Average => "average of",
+        }
+    }
+
+    /// The marker ending the path of a condition that takes this aggregate
+    pub fn marker(self) -> &'static str {
+        match self {
+            AggregateOperation::Sum => constants::SUM_OF_MARKER,
+            AggregateOperation::Minimum => constants::MINIMUM_OF_MARKER,
+            AggregateOperation::Maximum => constants::MAXIMUM_OF_MARKER,
+            AggregateOperation::Average => constants::AVERAGE_OF_MARKER,
+        }
+    }
+
+    /// Appended to the property's path in the trace, as `$.order.amounts.sum`
+    pub fn suffix(self) -> &'static str {
+        match self {
+            AggregateOperation::Sum => "sum",
+            AggregateOperation::Minimum => "minimum",
+            AggregateOperation::Maximum => "maximum",
+            AggregateOperation::Average => "average",
+        }
+    }
+
+    fn of_path(path: &crate::runner::model::PropertyPath) -> Option<Self> {
+        let marker = path.properties.last()?;
+        Self::ALL
+            .into_iter()
+            .find(|operation| operation.marker() == marker)
+    }
+}
+
+/// Takes `sum of`, `minimum of`, `maximum of` or `average of` a resolved property, which must
+/// be an array of numbers.
+///
+/// The sum of an empty array is 0; it has no minimum, maximum or average, so those behave like
+/// a missing property, as null does. Lenient evaluation reads null as an empty array
+pub fn aggregate_value(
+    operation: AggregateOperation,
+    value: &Value,
+    path: &str,
+    lenient: bool,
+) -> Result<Option<f64>, RuleError> {
+    let elements = match value {
+        Value::Array(elements) => elements.as_slice(),
+        Value::Null if lenient => &[],
+        Value::Null => return Ok(None),
+        _ => {
+            return Err(RuleError::TypeError(format!(
+                "Cannot take the {} {} at {}: '{}' takes an array of numbers",
+                operation.phrase(),
+                json_type_name(value),
+                path,
+                operation.phrase()
+            )))
+        }
+    };
+
+    let mut numbers = Vec::with_capacity(elements.len());
+    for (index, element) in elements.iter().enumerate() {
+        match element.as_f64() {
+            Some(number) => numbers.push(number),
+            None => {
+                return Err(RuleError::TypeError(format!(
+                    "Cannot take the {} {}: element {} is {}, not a number",
+                    operation.phrase(),
+                    path,
+                    index,
+                    json_type_name(element)
+                )))
+            }
+        }
+    }
+
+    if numbers.is_empty() {
+        return Ok((operation == AggregateOperation::Sum).then_some(0.0));
     }
+    let aggregate = match operation {
+        AggregateOperation::Sum => numbers.iter().sum(),
+        AggregateOperation::Minimum => numbers.iter().copied().fold(f64::INFINITY, f64::min),
+        AggregateOperation::Maximum => numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        AggregateOperation::Average => numbers.iter().sum::<f64>() / numbers.len() as f64,
+    };
+    Ok(Some(aggregate))
 }
 
-fn calculate_number_of(value: &Value) -> Result<f64, RuleError> {
+fn json_type_name(value: &Value) -> &'static str {
     match value {
-        Value::Array(arr) => Ok(arr.len() as f64),
-        Value::Null => Ok(0.0),
-        _ => Err(RuleError::EvaluationError(format!(
-            "Cannot calculate number of {:?} for non array",
-            value
-        ))),
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
     }
 }
 
@@ -1068,48 +2031,80 @@ fn evaluate_comparison_condition(
 ) -> Result<(bool, ConditionTrace), RuleError> {
     // Check if this is a cross-object comparison
     if let Some(left_path) = &condition.left_property_path {
-        return evaluate_cross_object_comparison(condition, left_path, json);
+        return evaluate_cross_object_comparison(
+            condition,
+            left_path,
+            json,
+            &EvaluationOptions::default(),
+            &mut EvalStats::default(),
+        );
     }
 
     // Check if this is a chained property access
     if let Some(property_chain) = &condition.property_chain {
-        return evaluate_chained_comparison_condition(condition, property_chain, json);
+        return evaluate_chained_comparison_condition(
+            condition,
+            property_chain,
+            json,
+            &EvaluationOptions::default(),
+            &mut EvalStats::default(),
+        );
     }
 
     // Original simple property condition logic
-    let effective_selector = match find_effective_selector(&condition.selector.value, json)? {
-        Some(sel) => sel,
-        None => {
-            return Ok((false, create_failed_comparison_trace(condition, None)));
-        }
-    };
+    let mut lookup = DataLookup::new(&EvaluationOptions::default());
+    let effective_selector =
+        match find_effective_selector(&condition.selector.value, json, &mut lookup)? {
+            Some(sel) => sel,
+            None => {
+                return Ok((false, create_failed_comparison_trace(condition, None)));
+            }
+        };
 
     // Check if property exists
     let property_value = json
-        .get(&effective_selector)
+        .get(effective_selector)
         .and_then(|obj| obj.get(&condition.property.value));
 
     if property_value.is_none() {
         return Ok((
             false,
-            create_failed_comparison_trace(condition, Some(&effective_selector)),
+            create_failed_comparison_trace(condition, Some(effective_selector)),
         ));
     }
 
     // Extract and evaluate the comparison
-    let json_value = extract_value_from_json(json, &effective_selector, &condition.property.value)?;
-    let (comparison_result, evaluation_details) =
-        perform_comparison(&json_value, &condition.operator, &condition.value.value)?;
+    let json_value = extract_value_from_json(json, effective_selector, &condition.property.value)?;
+    let property_path = json_path_from_keys(&[effective_selector, &condition.property.value]);
+    let operands = Operands {
+        left: &property_path,
+        right: None,
+        lenient: false,
+        list_mode: ListScalarMode::default(),
+        versions: true,
+        case_sensitive: false,
+        today: chrono::Utc::now().date_naive(),
+    };
+    let (comparison_result, evaluation_details) = perform_comparison(
+        &json_value,
+        &condition.operator,
+        &condition.value.value,
+        operands,
+    )?;
 
     // Build the trace
     let comparison_trace = ComparisonTrace {
+        id: None,
         selector: SelectorTrace {
             value: condition.selector.value.clone(),
             pos: condition.selector.pos.clone(),
         },
         property: PropertyTrace {
             value: property_value.unwrap().clone(),
-            path: format!("$.{}.{}", effective_selector, condition.property.value),
+            path: property_path,
+            document: None,
+            missed_key: None,
+            warning: None,
         },
         operator: condition.operator.clone(),
         value: condition
@@ -1118,11 +2113,14 @@ fn evaluate_comparison_condition(
             .to_value_trace(condition.value.pos.clone()),
         evaluation_details,
         result: comparison_result,
+        inner_result: None,
+        left_expression: None,
+        right_expression: None,
     };
 
     Ok((
         comparison_result,
-        ConditionTrace::Comparison(comparison_trace),
+        ConditionTrace::Comparison(Box::new(comparison_trace)),
     ))
 }
 
@@ -1130,16 +2128,82 @@ fn evaluate_cross_object_comparison(
     condition: &ComparisonCondition,
     left_path: &crate::runner::model::PropertyPath,
     json: &Value,
+    options: &EvaluationOptions,
+    stats: &mut EvalStats,
+) -> Result<(bool, ConditionTrace), RuleError> {
+    let mut lookup = DataLookup::counted(options, stats);
+    let (result, mut trace) =
+        compare_property_paths(condition, left_path, json, options, &mut lookup)?;
+    if let ConditionTrace::Comparison(comparison) = &mut trace {
+        comparison.property.warning = lookup.warning;
+    }
+    Ok((result, trace))
+}
+
+fn compare_property_paths(
+    condition: &ComparisonCondition,
+    left_path: &crate::runner::model::PropertyPath,
+    json: &Value,
+    options: &EvaluationOptions,
+    lookup: &mut DataLookup,
 ) -> Result<(bool, ConditionTrace), RuleError> {
     if is_length_of_operation(left_path) {
-        return evaluate_length_of_comparison(condition, left_path, json);
+        return evaluate_count_comparison(
+            condition,
+            left_path,
+            json,
+            CountOperation::LengthOf,
+            options,
+            lookup,
+        );
     }
     if is_number_of_operation(left_path) {
-        return evaluate_number_of_comparison(condition, left_path, json);
+        return evaluate_count_comparison(
+            condition,
+            left_path,
+            json,
+            CountOperation::NumberOf,
+            options,
+            lookup,
+        );
+    }
+    if left_path.properties.last().map(String::as_str) == Some(constants::HASH_BUCKET_MARKER) {
+        return evaluate_computed_comparison(
+            condition,
+            left_path,
+            json,
+            "hash_bucket",
+            hash_bucket_value,
+            options,
+            lookup,
+        );
+    }
+
+    if let Some(operation) = AggregateOperation::of_path(left_path) {
+        return evaluate_computed_comparison(
+            condition,
+            left_path,
+            json,
+            operation.suffix(),
+            |value, path| aggregate_value(operation, value, path, options.lenient),
+            options,
+            lookup,
+        );
+    }
+
+    if condition.is_arithmetic() {
+        return compare_arithmetic(condition, left_path, json, options, lookup);
     }
 
-    // Resolve left property path
-    let (left_value, left_path_str) = resolve_property_path(left_path, json)?;
+    // Resolve left property path; `each` steps produce an owned list
+    let left_list;
+    let (left_value, left_path_str) = if left_path.fan_out.is_empty() {
+        resolve_property_path(left_path, json, lookup)?
+    } else {
+        let (list, path_str) = resolve_fanned_out_path(left_path, json, lookup)?;
+        left_list = list;
+        (left_list.as_ref(), path_str)
+    };
     if left_value.is_none() {
         return Ok((
             false,
@@ -1147,12 +2211,49 @@ fn evaluate_cross_object_comparison(
         ));
     }
 
-    let left_rule_value = convert_json_to_rule_value(left_value.unwrap())?;
+    let left_json = left_value.unwrap();
+
+    // A literal shifted by `plus`/`minus` is compared, and traced, as the shifted date
+    let threshold = match (&condition.date_offset, &condition.right_property_path) {
+        (Some(offset), None) => {
+            Cow::Owned(offset_date(&literal_operand(condition, options), offset)?)
+        }
+        _ => literal_operand(condition, options),
+    };
 
-    let (comparison_result, evaluation_details) =
+    let mut right_path_str = None;
+    let (comparison_result, evaluation_details) = if condition.operator.checks_emptiness() {
+        // Judged on the JSON, as null and objects have no `RuleValue`
+        compare_emptiness(left_json, &condition.operator, &threshold, &left_path_str)?
+    } else if let Value::Object(object) = left_json {
+        // Only a literal tells which of its values was meant
+        let literal = condition
+            .right_property_path
+            .is_none()
+            .then_some(&*threshold);
+        let operands = Operands {
+            left: &left_path_str,
+            right: None,
+            lenient: options.lenient,
+            list_mode: options.list_scalar_mode,
+            versions: !options.versions_as_strings,
+            case_sensitive: options.case_sensitive_strings,
+            today: options.today(),
+        };
+        compare_object(object, &condition.operator, &threshold, literal, operands)?
+    } else {
+        let left_rule_value = convert_json_to_rule_value(left_json)?;
         if let Some(right_path) = &condition.right_property_path {
             // Property-to-property comparison
-            let (right_value, _right_path_str) = resolve_property_path(right_path, json)?;
+            let right_list;
+            let (right_value, path_str) = if right_path.fan_out.is_empty() {
+                resolve_property_path(right_path, json, lookup)?
+            } else {
+                let (list, path_str) = resolve_fanned_out_path(right_path, json, lookup)?;
+                right_list = list;
+                (right_list.as_ref(), path_str)
+            };
+            right_path_str = Some(path_str);
 
             if right_value.is_none() {
                 return Ok((
@@ -1161,147 +2262,713 @@ fn evaluate_cross_object_comparison(
                 ));
             }
 
-            let right_rule_value = convert_json_to_rule_value(right_value.unwrap())?;
-            perform_comparison(&left_rule_value, &condition.operator, &right_rule_value)?
+            let operands = Operands {
+                left: &left_path_str,
+                right: right_path_str.as_deref(),
+                lenient: options.lenient,
+                list_mode: options.list_scalar_mode,
+                versions: !options.versions_as_strings,
+                case_sensitive: options.case_sensitive_strings,
+                today: options.today(),
+            };
+            let range = right_value
+                .filter(|_| options.range_objects)
+                .and_then(NumericRange::from_json);
+            match range {
+                Some(range) => {
+                    compare_with_range(&left_rule_value, &condition.operator, range, operands)?
+                }
+                None => {
+                    let mut right_rule_value = convert_json_to_rule_value(right_value.unwrap())?;
+                    if let Some(offset) = &condition.date_offset {
+                        right_rule_value = offset_date(&right_rule_value, offset)?;
+                    }
+                    perform_comparison(
+                        &left_rule_value,
+                        &condition.operator,
+                        &right_rule_value,
+                        operands,
+                    )?
+                }
+            }
         } else {
             // Property-to-value comparison
-            perform_comparison(
-                &left_rule_value,
-                &condition.operator,
-                &condition.value.value,
-            )?
-        };
+            let operands = Operands {
+                left: &left_path_str,
+                right: None,
+                lenient: options.lenient,
+                list_mode: options.list_scalar_mode,
+                versions: !options.versions_as_strings,
+                case_sensitive: options.case_sensitive_strings,
+                today: options.today(),
+            };
+            perform_comparison(&left_rule_value, &condition.operator, &threshold, operands)?
+        }
+    };
 
     // Build the trace
     let comparison_trace = ComparisonTrace {
+        id: None,
         selector: SelectorTrace {
             value: left_path.selector.clone(),
             pos: None,
         },
         property: PropertyTrace {
-            value: left_value.unwrap().clone(),
+            value: left_json.clone(),
             path: left_path_str.clone(),
+            document: None,
+            missed_key: None,
+            warning: None,
         },
         operator: condition.operator.clone(),
-        value: condition
-            .value
-            .value
-            .to_value_trace(condition.value.pos.clone()),
+        value: ValueTrace {
+            path: right_path_str,
+            ..threshold.to_value_trace(condition.value.pos.clone())
+        },
         evaluation_details,
         result: comparison_result,
+        inner_result: None,
+        left_expression: None,
+        right_expression: None,
     };
 
     Ok((
         comparison_result,
-        ConditionTrace::Comparison(comparison_trace),
+        ConditionTrace::Comparison(Box::new(comparison_trace)),
     ))
 }
 
-fn evaluate_length_of_comparison(
+/// A comparison doing arithmetic on either side, see `RuleExpression`. Each side is worked
+/// out to a number before the operator compares them; an operand the data doesn't have fails
+/// the comparison as a missing property does, naming that operand
+fn compare_arithmetic(
     condition: &ComparisonCondition,
     left_path: &crate::runner::model::PropertyPath,
     json: &Value,
+    options: &EvaluationOptions,
+    lookup: &mut DataLookup,
 ) -> Result<(bool, ConditionTrace), RuleError> {
-    let mut actual_path = left_path.clone();
-    actual_path.properties.pop();
-
-    let (target_value, mut path_str) = resolve_property_path(&actual_path, json)?;
-    if target_value.is_none() {
-        path_str = format!("{}.length", path_str);
-        return Ok((
+    let missing = |path: &str| {
+        Ok((
             false,
-            create_failed_comparison_trace_with_path(condition, &path_str),
-        ));
-    }
+            create_failed_comparison_trace_with_path(condition, path),
+        ))
+    };
 
-    // Calculate length
-    let length = calculate_length_of(target_value.unwrap())?;
-    let length_rule_value = RuleValue::Number(length);
+    let left_list;
+    let (left_json, left_path_str, left_expression) = match &condition.left_expression {
+        Some(expression) => match evaluate_expression(expression, json, lookup)? {
+            Ok(trace) => (
+                Cow::Owned(json!(trace.result)),
+                trace.to_string(),
+                Some(trace),
+            ),
+            Err(path) => return missing(&path),
+        },
+        None => {
+            let (value, path_str) = if left_path.fan_out.is_empty() {
+                resolve_property_path(left_path, json, lookup)?
+            } else {
+                let (list, path_str) = resolve_fanned_out_path(left_path, json, lookup)?;
+                left_list = list;
+                (left_list.as_ref(), path_str)
+            };
+            match value {
+                Some(value) => (Cow::Borrowed(value), path_str, None),
+                None => return missing(&path_str),
+            }
+        }
+    };
 
-    // Perform comparison
-    let (comparison_result, evaluation_details) = perform_comparison(
-        &length_rule_value,
-        &condition.operator,
-        &condition.value.value,
-    )?;
+    let mut right_path_str = None;
+    let (threshold, right_expression) =
+        match (&condition.right_expression, &condition.right_property_path) {
+            (Some(expression), _) => match evaluate_expression(expression, json, lookup)? {
+                Ok(trace) => (RuleValue::Number(trace.result), Some(trace)),
+                Err(path) => return missing(&path),
+            },
+            (None, Some(right_path)) => {
+                let (value, path_str) = resolve_fanned_out_path(right_path, json, lookup)?;
+                let Some(value) = value else {
+                    return missing(&path_str);
+                };
+                right_path_str = Some(path_str);
+                (convert_json_to_rule_value(&value)?, None)
+            }
+            (None, None) => (literal_operand(condition, options).into_owned(), None),
+        };
+    let threshold = match &condition.date_offset {
+        Some(offset) => offset_date(&threshold, offset)?,
+        None => threshold,
+    };
 
-    // Build the trace with length information
-    let length_path = format!("{}.length", path_str);
+    let operands = Operands {
+        left: &left_path_str,
+        right: right_path_str.as_deref(),
+        lenient: options.lenient,
+        list_mode: options.list_scalar_mode,
+        versions: !options.versions_as_strings,
+        case_sensitive: options.case_sensitive_strings,
+        today: options.today(),
+    };
+    let (comparison_result, evaluation_details) = if condition.operator.checks_emptiness() {
+        compare_emptiness(&left_json, &condition.operator, &threshold, &left_path_str)?
+    } else {
+        let left = convert_json_to_rule_value(&left_json)?;
+        perform_comparison(&left, &condition.operator, &threshold, operands)?
+    };
+
+    // The value traced is what the right-hand side came to, unless it is another property
+    let value = match &right_path_str {
+        Some(_) => condition
+            .value
+            .value
+            .to_value_trace(condition.value.pos.clone()),
+        None => threshold.to_value_trace(condition.value.pos.clone()),
+    };
     let comparison_trace = ComparisonTrace {
+        id: None,
         selector: SelectorTrace {
             value: left_path.selector.clone(),
             pos: None,
         },
         property: PropertyTrace {
-            value: serde_json::json!(length), // Show the calculated length
-            path: length_path,
+            value: left_json.into_owned(),
+            path: left_path_str,
+            document: None,
+            missed_key: None,
+            warning: None,
         },
         operator: condition.operator.clone(),
-        value: condition
-            .value
-            .value
-            .to_value_trace(condition.value.pos.clone()),
+        value: ValueTrace {
+            path: right_path_str,
+            ..value
+        },
         evaluation_details,
         result: comparison_result,
+        inner_result: None,
+        left_expression,
+        right_expression,
     };
 
     Ok((
         comparison_result,
-        ConditionTrace::Comparison(comparison_trace),
+        ConditionTrace::Comparison(Box::new(comparison_trace)),
     ))
 }
 
-fn evaluate_number_of_comparison(
+/// Works out `expression` left to right, tracing each step. `Err` holds the path of the
+/// first property it read that the data doesn't have, or has as null
+fn evaluate_expression(
+    expression: &RuleExpression,
+    json: &Value,
+    lookup: &mut DataLookup,
+) -> Result<Result<ExpressionTrace, String>, RuleError> {
+    // A percentage of 0 is 0, and one over 100 is more than the value
+    if let RuleExpression::Percentage { percent, of } = expression {
+        let mut trace = match evaluate_expression(of, json, lookup)? {
+            Ok(trace) => trace,
+            Err(path) => return Ok(Err(path)),
+        };
+        trace.percentage = Some(PercentageTrace {
+            percent: *percent,
+            of: trace.result,
+        });
+        trace.result = trace.result * percent / 100.0;
+        if !trace.result.is_finite() {
+            return Err(RuleError::NonFiniteNumber {
+                path: trace.to_string(),
+            });
+        }
+        return Ok(Ok(trace));
+    }
+
+    let RuleExpression::Arithmetic {
+        left,
+        operator,
+        right,
+    } = expression
+    else {
+        return Ok(
+            evaluate_operand(expression, json, lookup)?.map(|(first, result)| ExpressionTrace {
+                first,
+                steps: Vec::new(),
+                percentage: None,
+                result,
+            }),
+        );
+    };
+
+    let mut trace = match evaluate_expression(left, json, lookup)? {
+        Ok(trace) => trace,
+        Err(path) => return Ok(Err(path)),
+    };
+    let (operand, number) = match evaluate_operand(right, json, lookup)? {
+        Ok(operand) => operand,
+        Err(path) => return Ok(Err(path)),
+    };
+    let result = operator
+        .apply(trace.result, number)
+        .ok_or_else(|| RuleError::DivisionByZero {
+            path: operand.path.clone(),
+        })?;
+    trace.steps.push(ArithmeticStepTrace {
+        operator: *operator,
+        operand,
+        result,
+    });
+    if !result.is_finite() {
+        return Err(RuleError::NonFiniteNumber {
+            path: trace.to_string(),
+        });
+    }
+    trace.result = result;
+    Ok(Ok(trace))
+}
+
+/// One number an expression takes: a literal, a property, or an expression nested in
+/// another, which the parser doesn't produce as steps are read left to right
+fn evaluate_operand(
+    expression: &RuleExpression,
+    json: &Value,
+    lookup: &mut DataLookup,
+) -> Result<Result<(OperandTrace, f64), String>, RuleError> {
+    match expression {
+        RuleExpression::Number(number) => Ok(Ok((
+            OperandTrace {
+                value: json!(number),
+                path: None,
+            },
+            *number,
+        ))),
+        RuleExpression::Property(path) => {
+            // `each` reads a list, which the type check below turns down
+            let list;
+            let (value, path_str) = if path.fan_out.is_empty() {
+                resolve_property_path(path, json, lookup)?
+            } else {
+                let (value, path_str) = resolve_fanned_out_path(path, json, lookup)?;
+                list = value;
+                (list.as_ref(), path_str)
+            };
+            let Some(value) = value.filter(|value| !value.is_null()) else {
+                return Ok(Err(path_str));
+            };
+            let Some(number) = value.as_f64() else {
+                return Err(RuleError::TypeError(format!(
+                    "Arithmetic takes numbers, but {} is {}",
+                    path_str,
+                    json_type_name(value)
+                )));
+            };
+            Ok(Ok((
+                OperandTrace {
+                    value: value.clone(),
+                    path: Some(path_str),
+                },
+                number,
+            )))
+        }
+        RuleExpression::Arithmetic { .. } | RuleExpression::Percentage { .. } => {
+            Ok(evaluate_expression(expression, json, lookup)?.map(|trace| {
+                (
+                    OperandTrace {
+                        value: json!(trace.result),
+                        path: None,
+                    },
+                    trace.result,
+                )
+            }))
+        }
+    }
+}
+
+/// An object where a comparison of single values needed one, usually a path that stops a
+/// property short. Fails with `RuleError::UnexpectedObject`, naming its keys and suggesting
+/// the one whose value is of the literal's kind, or in lenient mode is false with a warning
+fn compare_object(
+    object: &serde_json::Map<String, Value>,
+    operator: &ComparisonOperator,
+    right: &RuleValue,
+    literal: Option<&RuleValue>,
+    operands: Operands,
+) -> Result<(bool, Option<ComparisonEvaluationTrace>), RuleError> {
+    let path = operands.left;
+    let candidates: Vec<(&String, RuleValue)> = object
+        .iter()
+        .filter_map(|(key, value)| {
+            let value = convert_json_to_rule_value(value).ok()?;
+            literal
+                .is_some_and(|literal| same_kind(&value, literal))
+                .then_some((key, value))
+        })
+        .collect();
+    // The key that would hold the comparison, else the first of the right kind
+    let holds = |value: &RuleValue| {
+        literal.is_some_and(|literal| {
+            evaluate_comparison(value, operator, literal, operands).unwrap_or(false)
+        })
+    };
+    let suggestion = candidates
+        .iter()
+        .find(|(_, value)| holds(value))
+        .or(candidates.first())
+        .map(|(key, _)| key.to_string());
+    let unexpected = UnexpectedObject {
+        keys: object.keys().take(MAX_LISTED_KEYS).cloned().collect(),
+        more_keys: object.len().saturating_sub(MAX_LISTED_KEYS),
+        suggestion,
+    };
+    if !operands.lenient {
+        return Err(RuleError::UnexpectedObject {
+            path: path.to_string(),
+            object: unexpected,
+        });
+    }
+
+    let details = ComparisonEvaluationTrace {
+        left_value: TypedValue {
+            value: Value::Object(object.clone()),
+            value_type: "object".to_string(),
+            source: None,
+        },
+        right_value: TypedValue::from(right),
+        comparison_result: false,
+        warning: Some(format!(
+            "{}, so the comparison is false{}",
+            unexpected.describe(path),
+            unexpected.hint(path)
+        )),
+        list_mode: None,
+        deciding_element: None,
+        duration_seconds: None,
+        window: None,
+        cutoff: None,
+        reference_date: None,
+        quantifier: None,
+        matched_elements: None,
+        case_sensitive: None,
+        unexpected_object: Some(unexpected),
+    };
+    Ok((false, Some(details)))
+}
+
+/// Whether `value` is of a kind `literal` is compared with: an element's for a list, a
+/// bound's for a range and a date for a duration
+fn same_kind(value: &RuleValue, literal: &RuleValue) -> bool {
+    match literal {
+        RuleValue::List(items) => items.iter().any(|item| same_kind(value, item)),
+        RuleValue::Range(low, _) => same_kind(value, low),
+        RuleValue::Duration(_) => matches!(value, RuleValue::Date(_)),
+        _ => std::mem::discriminant(value) == std::mem::discriminant(literal),
+    }
+}
+
+/// Holds the comparison to every element of the list, element by element. An element that
+/// doesn't have the property read fails it, and a missing list fails the condition whatever
+/// the quantifier. The trace is the comparison's, reading `[*]` from the list, with the
+/// quantifier, how many elements matched and the element that decided it
+fn evaluate_quantified_condition(
+    condition: &QuantifiedCondition,
+    json: &Value,
+    options: &EvaluationOptions,
+    stats: &mut EvalStats,
+) -> Result<(bool, ConditionTrace), RuleError> {
+    let comparison = &condition.comparison;
+    let (list_path, keys) = condition.list_and_keys().ok_or_else(|| {
+        RuleError::EvaluationError(format!("'{} of' has no list to read", condition.quantifier))
+    })?;
+    let mut lookup = DataLookup::counted(options, stats);
+    let (list, list_path_str) = resolve_property_path(&list_path, json, &mut lookup)?;
+    let mut path_str = format!("{}[*]", list_path_str);
+    for key in keys {
+        push_json_path_key(&mut path_str, key);
+    }
+    let Some(list) = list else {
+        return Ok((
+            false,
+            create_failed_comparison_trace_with_path(comparison, &list_path_str),
+        ));
+    };
+    let items = fanned_out_items(list, &list_path_str)?;
+
+    // The right side is read once, from the top of the data rather than each element
+    let mut right_path_str = None;
+    let mut right_expression = None;
+    let threshold = match (
+        &comparison.right_expression,
+        &comparison.right_property_path,
+    ) {
+        (Some(expression), _) => match evaluate_expression(expression, json, &mut lookup)? {
+            Ok(trace) => {
+                let threshold = RuleValue::Number(trace.result);
+                right_expression = Some(trace);
+                threshold
+            }
+            Err(path) => {
+                return Ok((
+                    false,
+                    create_failed_comparison_trace_with_path(comparison, &path),
+                ))
+            }
+        },
+        (None, Some(right_path)) => {
+            let (value, right_str) = resolve_fanned_out_path(right_path, json, &mut lookup)?;
+            let Some(value) = value else {
+                return Ok((
+                    false,
+                    create_failed_comparison_trace_with_path(comparison, &path_str),
+                ));
+            };
+            right_path_str = Some(right_str);
+            let value = convert_json_to_rule_value(&value)?;
+            match &comparison.date_offset {
+                Some(offset) => offset_date(&value, offset)?,
+                None => value,
+            }
+        }
+        (None, None) => match &comparison.date_offset {
+            Some(offset) => offset_date(&literal_operand(comparison, options), offset)?,
+            None => literal_operand(comparison, options).into_owned(),
+        },
+    };
+
+    let mut values = Vec::with_capacity(items.len());
+    let mut matched = Vec::new();
+    for item in items {
+        let (value, _) = resolve_in_element(item, keys, &[], &mut lookup)?;
+        let passes = match value {
+            None => false,
+            Some(value) if comparison.operator.checks_emptiness() => {
+                compare_emptiness(value, &comparison.operator, &threshold, &path_str)?.0
+            }
+            Some(value) => {
+                let operands = Operands {
+                    left: &path_str,
+                    right: right_path_str.as_deref(),
+                    lenient: options.lenient,
+                    list_mode: options.list_scalar_mode,
+                    versions: !options.versions_as_strings,
+                    case_sensitive: options.case_sensitive_strings,
+                    today: options.today(),
+                };
+                let left = convert_json_to_rule_value(value)?;
+                perform_comparison(&left, &comparison.operator, &threshold, operands)?.0
+            }
+        };
+        values.push(value.cloned().unwrap_or(Value::Null));
+        matched.push(passes);
+    }
+
+    let matched_elements = matched.iter().filter(|passes| **passes).count();
+    let (result, deciding_element) = match condition.quantifier {
+        Quantifier::All => {
+            let failed = matched.iter().position(|passes| !passes);
+            (failed.is_none(), failed)
+        }
+        Quantifier::Any => {
+            let passed = matched.iter().position(|passes| *passes);
+            (passed.is_some(), passed)
+        }
+        Quantifier::None => {
+            let passed = matched.iter().position(|passes| *passes);
+            (passed.is_none(), passed)
+        }
+    };
+
+    let left_value = TypedValue {
+        value: Value::Array(values.clone()),
+        value_type: "list".to_string(),
+        source: None,
+    };
+    let details = ComparisonEvaluationTrace {
+        left_value,
+        right_value: TypedValue::from(&threshold),
+        comparison_result: result,
+        warning: None,
+        list_mode: None,
+        deciding_element,
+        duration_seconds: None,
+        window: None,
+        cutoff: None,
+        reference_date: None,
+        quantifier: Some(condition.quantifier),
+        matched_elements: Some(matched_elements),
+        case_sensitive: string_case(
+            &comparison.operator,
+            &[&threshold],
+            options.case_sensitive_strings,
+        ),
+        unexpected_object: None,
+    };
+    let trace = ComparisonTrace {
+        id: None,
+        selector: SelectorTrace {
+            value: list_path.selector.clone(),
+            pos: None,
+        },
+        property: PropertyTrace {
+            value: Value::Array(values),
+            path: path_str,
+            document: None,
+            missed_key: None,
+            warning: lookup.warning.take(),
+        },
+        operator: comparison.operator.clone(),
+        value: ValueTrace {
+            path: right_path_str,
+            ..threshold.to_value_trace(comparison.value.pos.clone())
+        },
+        evaluation_details: Some(details),
+        result,
+        inner_result: None,
+        left_expression: None,
+        right_expression,
+    };
+    Ok((result, ConditionTrace::Comparison(Box::new(trace))))
+}
+
+/// The value on the right of a comparison that has no property there: the literal, or the
+/// reference date for `today`
+fn literal_operand<'a>(
+    condition: &'a ComparisonCondition,
+    options: &EvaluationOptions,
+) -> Cow<'a, RuleValue> {
+    if condition.today {
+        Cow::Owned(RuleValue::Date(options.today()))
+    } else {
+        Cow::Borrowed(&condition.value.value)
+    }
+}
+
+/// The date on the right of a comparison shifted by its `plus`/`minus` offset
+fn offset_date(value: &RuleValue, offset: &DateOffset) -> Result<RuleValue, RuleError> {
+    let date = coerce_to_date(value).ok_or_else(|| {
+        RuleError::TypeError(format!(
+            "Only dates can be shifted by '{}', got {}",
+            offset, value
+        ))
+    })?;
+    let shifted = offset.apply(date).ok_or_else(|| {
+        RuleError::EvaluationError(format!("{} {} is out of range", date, offset))
+    })?;
+    Ok(RuleValue::Date(shifted))
+}
+
+fn evaluate_count_comparison(
     condition: &ComparisonCondition,
     left_path: &crate::runner::model::PropertyPath,
     json: &Value,
+    operation: CountOperation,
+    options: &EvaluationOptions,
+    lookup: &mut DataLookup,
+) -> Result<(bool, ConditionTrace), RuleError> {
+    let suffix = match operation {
+        CountOperation::LengthOf => "length",
+        CountOperation::NumberOf => "number",
+    };
+    evaluate_computed_comparison(
+        condition,
+        left_path,
+        json,
+        suffix,
+        |value, path| count_value(operation, value, path, options.lenient),
+        options,
+        lookup,
+    )
+}
+
+/// Compares a number computed from the property before the path's marker. The trace shows
+/// the computed number at the property's path plus `suffix`
+fn evaluate_computed_comparison(
+    condition: &ComparisonCondition,
+    left_path: &crate::runner::model::PropertyPath,
+    json: &Value,
+    suffix: &str,
+    compute: impl Fn(&Value, &str) -> Result<Option<f64>, RuleError>,
+    options: &EvaluationOptions,
+    lookup: &mut DataLookup,
 ) -> Result<(bool, ConditionTrace), RuleError> {
     let mut actual_path = left_path.clone();
     actual_path.properties.pop();
 
-    let (target_value, mut path_str) = resolve_property_path(&actual_path, json)?;
-    if target_value.is_none() {
-        path_str = format!("{}.length", path_str);
+    let target_list;
+    let (target_value, path_str) = if actual_path.fan_out.is_empty() {
+        resolve_property_path(&actual_path, json, lookup)?
+    } else {
+        let (list, path_str) = resolve_fanned_out_path(&actual_path, json, lookup)?;
+        target_list = list;
+        (target_list.as_ref(), path_str)
+    };
+    let count = match target_value {
+        Some(value) => compute(value, &path_str)?,
+        None => None,
+    };
+
+    let count_path = format!("{}.{}", path_str, suffix);
+    let Some(count) = count else {
         return Ok((
             false,
-            create_failed_comparison_trace_with_path(condition, &path_str),
+            create_failed_comparison_trace_with_path(condition, &count_path),
         ));
-    }
+    };
 
-    // Calculate length
-    let number = calculate_number_of(target_value.unwrap())?;
-    let number_rule_value = RuleValue::Number(number);
+    let (threshold, right_expression) = match &condition.right_expression {
+        Some(expression) => match evaluate_expression(expression, json, lookup)? {
+            Ok(trace) => (RuleValue::Number(trace.result), Some(trace)),
+            Err(path) => {
+                return Ok((
+                    false,
+                    create_failed_comparison_trace_with_path(condition, &path),
+                ))
+            }
+        },
+        None => (condition.value.value.clone(), None),
+    };
 
     // Perform comparison
+    let operands = Operands {
+        left: &count_path,
+        right: None,
+        lenient: options.lenient,
+        list_mode: options.list_scalar_mode,
+        versions: !options.versions_as_strings,
+        case_sensitive: options.case_sensitive_strings,
+        today: options.today(),
+    };
     let (comparison_result, evaluation_details) = perform_comparison(
-        &number_rule_value,
+        &RuleValue::Number(count),
         &condition.operator,
-        &condition.value.value,
+        &threshold,
+        operands,
     )?;
 
-    // Build the trace with length information
-    let number_path = format!("{}.number", path_str);
+    // Build the trace with the calculated count
     let comparison_trace = ComparisonTrace {
+        id: None,
         selector: SelectorTrace {
             value: left_path.selector.clone(),
             pos: None,
         },
         property: PropertyTrace {
-            value: serde_json::json!(number), // Show the calculated length
-            path: number_path,
+            value: serde_json::json!(count),
+            path: count_path,
+            document: None,
+            missed_key: None,
+            warning: None,
         },
         operator: condition.operator.clone(),
-        value: condition
-            .value
-            .value
-            .to_value_trace(condition.value.pos.clone()),
+        value: threshold.to_value_trace(condition.value.pos.clone()),
         evaluation_details,
         result: comparison_result,
+        inner_result: None,
+        left_expression: None,
+        right_expression,
     };
 
     Ok((
         comparison_result,
-        ConditionTrace::Comparison(comparison_trace),
+        ConditionTrace::Comparison(Box::new(comparison_trace)),
     ))
 }
 
@@ -1315,37 +2982,18 @@ fn is_number_of_operation(path: &crate::runner::model::PropertyPath) -> bool {
 fn resolve_property_path<'a>(
     path: &crate::runner::model::PropertyPath,
     json: &'a Value,
+    lookup: &mut DataLookup,
 ) -> Result<(Option<&'a Value>, String), RuleError> {
     let mut path_parts = Vec::new();
     let mut current_value = json;
 
-    // Check if the selector contains dots (nested path)
-    if path.selector.contains('.') {
-        // Handle nested selector path
-        let selector_parts: Vec<&str> = path.selector.split('.').collect();
-        for part in &selector_parts {
-            let effective_part = find_effective_selector(part, current_value)?;
-            if effective_part.is_none() {
-                return Ok((None, format!("$.{}", path.selector)));
-            }
-            let final_part = effective_part.unwrap();
-            current_value = current_value.get(&final_part).ok_or_else(|| {
-                RuleError::EvaluationError(format!("Selector part '{}' not found", final_part))
-            })?;
-            path_parts.push(final_part);
-        }
-    } else {
-        // Handle regular single selector
-        let effective_selector = find_effective_selector(&path.selector, json)?;
-        if effective_selector.is_none() {
-            return Ok((None, format!("$.{}", path.selector)));
-        }
-
-        let final_selector = effective_selector.unwrap();
-        current_value = json.get(&final_selector).ok_or_else(|| {
-            RuleError::EvaluationError(format!("Selector '{}' not found", final_selector))
-        })?;
-        path_parts.push(final_selector); // Use the actual key from JSON
+    // Dots in the selector are nested selectors
+    for part in path.selector.split('.') {
+        let Some((key, value)) = lookup.find_key(current_value, part) else {
+            return Ok((None, selector_json_path(&path.selector)));
+        };
+        current_value = value;
+        path_parts.push(key.clone()); // Use the actual key from JSON
     }
 
     let is_length_of_operator = is_length_of_operation(path);
@@ -1362,113 +3010,233 @@ fn resolve_property_path<'a>(
     // Follow the property chain - properties are already in correct traversal order
     // For "__date of birth__ of **person** of **driving test**", we get properties: ["person", "date of birth"]
     // And we traverse: driving test -> person -> date of birth
-    for property in properties_to_process.iter() {
-        // Check if the property contains dots (nested navigation)
-        if property.contains('.') {
-            // Handle nested property path (e.g., "advisor.agreement")
-            let nested_parts: Vec<&str> = property.split('.').collect();
-            for part in nested_parts {
-                let mut found_property = None;
-                let mut actual_property_name = part.to_string();
-
-                if let Some(prop_value) = current_value.get(part) {
-                    found_property = Some(prop_value);
-                } else if let Some(prop_value) = get_json_value_insensitive(current_value, part) {
-                    found_property = Some(prop_value);
-                    // Find the actual property name in the JSON for path tracking
-                    if let Some(obj) = current_value.as_object() {
-                        for (key, _) in obj {
-                            if names_match(part, key) {
-                                actual_property_name = key.clone();
-                                break;
-                            }
-                        }
-                    }
-                } else {
-                    let transformed_property = transform_property_name(part);
-                    if let Some(prop_value) = current_value.get(&transformed_property) {
-                        found_property = Some(prop_value);
-                        actual_property_name = transformed_property.clone();
-                    } else if let Some(prop_value) =
-                        get_json_value_insensitive(current_value, &transformed_property)
-                    {
-                        found_property = Some(prop_value);
-                        // Find the actual property name in the JSON for path tracking
-                        if let Some(obj) = current_value.as_object() {
-                            for (key, _) in obj {
-                                if names_match(&transformed_property, key) {
-                                    actual_property_name = key.clone();
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
+    let mut path_str = json_path_from_keys(&path_parts);
+    let Some(current_value) = follow_properties(
+        current_value,
+        properties_to_process,
+        &path.elements,
+        path_parts.len(),
+        &mut path_str,
+        lookup,
+    )?
+    else {
+        return Ok((None, path_str));
+    };
 
-                if let Some(prop_value) = found_property {
-                    current_value = prop_value;
-                    path_parts.push(actual_property_name);
-                } else {
-                    return Ok((None, format!("$.{}", path_parts.join("."))));
-                }
-            }
-        } else {
-            // Handle regular property (no dots)
-            let mut found_property = None;
-            let mut actual_property_name = property.clone();
+    if is_length_of_operator {
+        count_value(CountOperation::LengthOf, current_value, &path_str, true)?;
+        return Ok((Some(current_value), format!("{}.length", path_str)));
+    }
 
-            if let Some(prop_value) = current_value.get(property) {
-                found_property = Some(prop_value);
-            } else if let Some(prop_value) = get_json_value_insensitive(current_value, property) {
-                found_property = Some(prop_value);
-                // Find the actual property name in the JSON for path tracking
-                if let Some(obj) = current_value.as_object() {
-                    for (key, _) in obj {
-                        if names_match(property, key) {
-                            actual_property_name = key.clone();
-                            break;
-                        }
-                    }
+    Ok((Some(current_value), path_str))
+}
+
+/// Follows `properties` from `value`, taking one element of the list after each property
+/// `elements` marks, and appends each step to `path`, which is `depth` keys deep already.
+/// `None` when a key or element is missing, with the path up to it: a missing key stays on
+/// the path so the trace shows what wasn't found, and a missing element is noted as well
+fn follow_properties<'a>(
+    mut value: &'a Value,
+    properties: &[String],
+    elements: &[(usize, PropertyChainElement)],
+    mut depth: usize,
+    path: &mut String,
+    lookup: &mut DataLookup,
+) -> Result<Option<&'a Value>, RuleError> {
+    for (i, property) in properties.iter().enumerate() {
+        // Dots in a property are nested properties (e.g., "advisor.agreement")
+        for part in property.split('.') {
+            match lookup.find_key(value, part) {
+                Some((key, found)) => {
+                    push_json_path_key(path, key);
+                    value = found;
                 }
-            } else {
-                let transformed_property = transform_property_name(property);
-                if let Some(prop_value) = current_value.get(&transformed_property) {
-                    found_property = Some(prop_value);
-                    actual_property_name = transformed_property.clone();
-                } else if let Some(prop_value) =
-                    get_json_value_insensitive(current_value, &transformed_property)
-                {
-                    found_property = Some(prop_value);
-                    // Find the actual property name in the JSON for path tracking
-                    if let Some(obj) = current_value.as_object() {
-                        for (key, _) in obj {
-                            if names_match(&transformed_property, key) {
-                                actual_property_name = key.clone();
-                                break;
-                            }
-                        }
-                    }
+                None => {
+                    push_json_path_key(path, part);
+                    return Ok(None);
                 }
             }
+            depth += 1;
+        }
+        for (_, element) in elements.iter().filter(|(at, _)| *at == i) {
+            match pick_element(value, element, path, lookup)? {
+                Some(found) => value = found,
+                None => return Ok(None),
+            }
+            depth += 1;
+        }
+    }
+    lookup.check_depth(path, depth, value)?;
+    Ok(Some(value))
+}
 
-            if let Some(prop_value) = found_property {
-                current_value = prop_value;
-                path_parts.push(actual_property_name);
-            } else {
-                return Ok((None, format!("$.{}", path_parts.join("."))));
+/// The element of the list at `path` that `element` picks, appending the step to `path`.
+/// `None`, noted in the lookup's warning, when the list is too short to have it
+fn pick_element<'a>(
+    list: &'a Value,
+    element: &PropertyChainElement,
+    path: &mut String,
+    lookup: &mut DataLookup,
+) -> Result<Option<&'a Value>, RuleError> {
+    let Some(items) = list.as_array() else {
+        return Err(RuleError::TypeError(format!(
+            "'the {} of' needs a list at {} but found {}",
+            element.ordinal(),
+            path,
+            json_type_name(list)
+        )));
+    };
+    let found = match element {
+        PropertyChainElement::Index(index) => items.get(*index),
+        PropertyChainElement::Last => items.last(),
+        PropertyChainElement::Property(_) | PropertyChainElement::Selector(_) => None,
+    };
+    if found.is_none() && lookup.warning.is_none() {
+        lookup.warning = Some(format!(
+            "{} has {} element{}, so it has no {}",
+            path,
+            items.len(),
+            if items.len() == 1 { "" } else { "s" },
+            match element {
+                PropertyChainElement::Last => "last element".to_string(),
+                element => format!("{} element", element.ordinal()),
             }
+        ));
+    }
+    push_json_path_step(path, element);
+    Ok(found)
+}
+
+/// Resolves a path with `each` steps to the list of values read from every element.
+///
+/// Elements the rest of the chain doesn't resolve in contribute nothing, and nested `each`
+/// steps are flattened into one list. The path reads like `$.order.lines[*].amount`.
+fn resolve_fanned_out_path(
+    path: &crate::runner::model::PropertyPath,
+    json: &Value,
+    lookup: &mut DataLookup,
+) -> Result<(Option<Value>, String), RuleError> {
+    let Some((&split, inner_fan_out)) = path.fan_out.split_first() else {
+        let (value, path_str) = resolve_property_path(path, json, lookup)?;
+        return Ok((value.cloned(), path_str));
+    };
+
+    let head = crate::runner::model::PropertyPath {
+        selector: path.selector.clone(),
+        properties: path.properties[..=split].to_vec(),
+        fan_out: Vec::new(),
+        elements: path.elements_until(split),
+    };
+    let (list, head_path) = resolve_property_path(&head, json, lookup)?;
+
+    // Indices from here on are relative to the properties after the first `each`
+    let rest = &path.properties[split + 1..];
+    let fan_out: Vec<usize> = inner_fan_out.iter().map(|i| i - split - 1).collect();
+    let elements = elements_after(&path.elements, split);
+    let mut path_str = format!("{}[*]", head_path);
+    for (i, key) in rest.iter().enumerate() {
+        push_json_path_key(&mut path_str, key);
+        for (_, element) in elements.iter().filter(|(at, _)| *at == i) {
+            push_json_path_step(&mut path_str, element);
+        }
+        if fan_out.contains(&i) {
+            path_str.push_str("[*]");
         }
     }
 
-    if is_length_of_operator {
-        calculate_length_of(current_value)?;
-        path_parts.push("length".to_string());
-        let path_str = format!("$.{}", path_parts.join("."));
-        return Ok((Some(current_value), path_str));
+    let Some(list) = list else {
+        return Ok((None, head_path));
+    };
+    let mut values = Vec::new();
+    collect_from_elements(
+        fanned_out_items(list, &head_path)?,
+        rest,
+        &fan_out,
+        &elements,
+        &mut values,
+        lookup,
+    )?;
+    Ok((Some(Value::Array(values)), path_str))
+}
+
+/// The element steps after the property at `split`, indexed from the property after it
+fn elements_after(
+    elements: &[(usize, PropertyChainElement)],
+    split: usize,
+) -> Vec<(usize, PropertyChainElement)> {
+    elements
+        .iter()
+        .filter(|(at, _)| *at > split)
+        .map(|(at, element)| (at - split - 1, element.clone()))
+        .collect()
+}
+
+fn fanned_out_items<'a>(list: &'a Value, path: &str) -> Result<&'a [Value], RuleError> {
+    list.as_array().map(Vec::as_slice).ok_or_else(|| {
+        RuleError::TypeError(format!(
+            "'each' needs a list at {} but found {}",
+            path,
+            json_type_name(list)
+        ))
+    })
+}
+
+/// Reads `keys` from every element, fanning out again at each index in `fan_out` and taking
+/// one element at each of `elements`
+fn collect_from_elements(
+    items: &[Value],
+    keys: &[String],
+    fan_out: &[usize],
+    elements: &[(usize, PropertyChainElement)],
+    values: &mut Vec<Value>,
+    lookup: &mut DataLookup,
+) -> Result<(), RuleError> {
+    for item in items {
+        match fan_out.split_first() {
+            None => {
+                if let (Some(value), _) = resolve_in_element(item, keys, elements, lookup)? {
+                    values.push(value.clone());
+                }
+            }
+            Some((&split, inner)) => {
+                let head = &keys[..=split];
+                let head_elements: Vec<_> = elements
+                    .iter()
+                    .filter(|(at, _)| *at <= split)
+                    .cloned()
+                    .collect();
+                if let (Some(list), path) = resolve_in_element(item, head, &head_elements, lookup)?
+                {
+                    let inner: Vec<usize> = inner.iter().map(|i| i - split - 1).collect();
+                    let items = fanned_out_items(list, &path)?;
+                    collect_from_elements(
+                        items,
+                        &keys[split + 1..],
+                        &inner,
+                        &elements_after(elements, split),
+                        values,
+                        lookup,
+                    )?;
+                }
+            }
+        }
     }
+    Ok(())
+}
 
-    let path_str = format!("$.{}", path_parts.join("."));
-    Ok((Some(current_value), path_str))
+/// Follows `keys` inside one list element the way a selector and its properties are followed
+fn resolve_in_element<'a>(
+    item: &'a Value,
+    keys: &[String],
+    elements: &[(usize, PropertyChainElement)],
+    lookup: &mut DataLookup,
+) -> Result<(Option<&'a Value>, String), RuleError> {
+    let mut path = "$".to_string();
+    if keys.is_empty() {
+        return Ok((Some(item), path));
+    }
+    let value = follow_properties(item, keys, elements, 0, &mut path, lookup)?;
+    Ok((value, path))
 }
 
 #[allow(dead_code)]
@@ -1476,6 +3244,8 @@ fn evaluate_chained_comparison_condition(
     condition: &ComparisonCondition,
     property_chain: &[PropertyChainElement],
     json: &Value,
+    options: &EvaluationOptions,
+    stats: &mut EvalStats,
 ) -> Result<(bool, ConditionTrace), RuleError> {
     // Resolve the chained property access
     let (final_value, path) = resolve_chained_property_access(
@@ -1483,6 +3253,7 @@ fn evaluate_chained_comparison_condition(
         &condition.selector.value,
         property_chain,
         json,
+        stats,
     )?;
 
     if final_value.is_none() {
@@ -1494,11 +3265,25 @@ fn evaluate_chained_comparison_condition(
 
     // Extract and evaluate the comparison
     let json_value = convert_json_to_rule_value(final_value.unwrap())?;
-    let (comparison_result, evaluation_details) =
-        perform_comparison(&json_value, &condition.operator, &condition.value.value)?;
+    let operands = Operands {
+        left: &path,
+        right: None,
+        lenient: options.lenient,
+        list_mode: options.list_scalar_mode,
+        versions: !options.versions_as_strings,
+        case_sensitive: options.case_sensitive_strings,
+        today: options.today(),
+    };
+    let (comparison_result, evaluation_details) = perform_comparison(
+        &json_value,
+        &condition.operator,
+        &condition.value.value,
+        operands,
+    )?;
 
     // Build the trace
     let comparison_trace = ComparisonTrace {
+        id: None,
         selector: SelectorTrace {
             value: condition.selector.value.clone(),
             pos: condition.selector.pos.clone(),
@@ -1506,6 +3291,9 @@ fn evaluate_chained_comparison_condition(
         property: PropertyTrace {
             value: final_value.unwrap().clone(),
             path: path.clone(),
+            document: None,
+            missed_key: None,
+            warning: None,
         },
         operator: condition.operator.clone(),
         value: condition
@@ -1514,11 +3302,14 @@ fn evaluate_chained_comparison_condition(
             .to_value_trace(condition.value.pos.clone()),
         evaluation_details,
         result: comparison_result,
+        inner_result: None,
+        left_expression: None,
+        right_expression: None,
     };
 
     Ok((
         comparison_result,
-        ConditionTrace::Comparison(comparison_trace),
+        ConditionTrace::Comparison(Box::new(comparison_trace)),
     ))
 }
 
@@ -1527,48 +3318,43 @@ fn resolve_chained_property_access<'a>(
     final_selector: &str,
     chain: &[PropertyChainElement],
     json: &'a Value,
+    stats: &mut EvalStats,
 ) -> Result<(Option<&'a Value>, String), RuleError> {
     let mut path_parts = Vec::new();
 
     // Start with the final selector
-    let effective_selector = find_effective_selector(final_selector, json)?;
+    let mut lookup = DataLookup::counted(&EvaluationOptions::default(), stats);
+    let effective_selector = find_effective_selector(final_selector, json, &mut lookup)?;
 
     if effective_selector.is_none() {
-        return Ok((None, format!("$.{}", final_selector)));
+        return Ok((None, selector_json_path(final_selector)));
     }
 
     let final_sel = effective_selector.unwrap();
     let mut current_value = json
-        .get(&final_sel)
+        .get(final_sel)
         .ok_or_else(|| RuleError::EvaluationError(format!("Selector '{}' not found", final_sel)))?;
 
-    path_parts.push(final_sel);
+    path_parts.push(final_sel.to_string());
+    let mut path = json_path_from_keys(&path_parts);
 
     // Follow the chain
     for element in chain {
         match element {
-            PropertyChainElement::Property(property) => {
-                if let Some(prop_value) = current_value.get(property) {
-                    current_value = prop_value;
-                    path_parts.push(property.clone());
-                } else if let Some(prop_value) = get_json_value_insensitive(current_value, property)
-                {
-                    current_value = prop_value;
-                    path_parts.push(property.clone());
+            PropertyChainElement::Property(name) | PropertyChainElement::Selector(name) => {
+                if let Some(value) = current_value.get(name) {
+                    current_value = value;
+                } else if let Some(value) = get_json_value_insensitive(current_value, name) {
+                    current_value = value;
                 } else {
-                    return Ok((None, format!("$.{}", path_parts.join("."))));
+                    return Ok((None, path));
                 }
+                push_json_path_key(&mut path, name);
             }
-            PropertyChainElement::Selector(selector) => {
-                if let Some(sel_value) = current_value.get(selector) {
-                    current_value = sel_value;
-                    path_parts.push(selector.clone());
-                } else if let Some(sel_value) = get_json_value_insensitive(current_value, selector)
-                {
-                    current_value = sel_value;
-                    path_parts.push(selector.clone());
-                } else {
-                    return Ok((None, format!("$.{}", path_parts.join("."))));
+            PropertyChainElement::Index(_) | PropertyChainElement::Last => {
+                match pick_element(current_value, element, &mut path, &mut lookup)? {
+                    Some(value) => current_value = value,
+                    None => return Ok((None, path)),
                 }
             }
         }
@@ -1577,16 +3363,14 @@ fn resolve_chained_property_access<'a>(
     // Finally, get the first property
     if let Some(final_prop_value) = current_value.get(first_property) {
         current_value = final_prop_value;
-        path_parts.push(first_property.to_string());
     } else if let Some(final_prop_value) = get_json_value_insensitive(current_value, first_property)
     {
         current_value = final_prop_value;
-        path_parts.push(first_property.to_string());
     } else {
-        return Ok((None, format!("$.{}", path_parts.join("."))));
+        return Ok((None, path));
     }
+    push_json_path_key(&mut path, first_property);
 
-    let path = format!("$.{}", path_parts.join("."));
     Ok((Some(current_value), path))
 }
 
@@ -1638,42 +3422,622 @@ fn convert_json_to_rule_value(value: &Value) -> Result<RuleValue, RuleError> {
     }
 }
 
+/// Where the two sides of a comparison were read from, for reporting a number that isn't
+/// finite
+#[derive(Clone, Copy)]
+struct Operands<'a> {
+    left: &'a str,
+    /// `None` when the right side is written in the rule
+    right: Option<&'a str>,
+    lenient: bool,
+    list_mode: ListScalarMode,
+    /// Order strings shaped like versions as versions, see `Version`
+    versions: bool,
+    /// Compare strings minding case with every equality, list and contains operator, see
+    /// `EvaluationOptions::case_sensitive_strings`
+    case_sensitive: bool,
+    /// The date `is within`, `is older than` and `is younger than` count from, see
+    /// `EvaluationOptions::reference_date`
+    today: NaiveDate,
+}
+
+const LITERAL_OPERAND: &str = "the rule's value";
+
+/// Whether a value holds only finite numbers, durations included once converted to seconds
+fn is_finite_value(value: &RuleValue) -> bool {
+    match value {
+        RuleValue::Number(n) => n.is_finite(),
+        RuleValue::Duration(duration) => duration.to_seconds().is_finite(),
+        RuleValue::List(items) => items.iter().all(is_finite_value),
+        RuleValue::Range(low, high) => is_finite_value(low) && is_finite_value(high),
+        RuleValue::String(_) | RuleValue::Date(_) | RuleValue::Boolean(_) => true,
+    }
+}
+
 fn perform_comparison(
     json_value: &RuleValue,
     operator: &ComparisonOperator,
     value: &RuleValue,
+    operands: Operands,
 ) -> Result<(bool, Option<ComparisonEvaluationTrace>), RuleError> {
-    match evaluate_comparison(json_value, operator, value) {
+    // NaN fails every comparison and infinity passes some, so neither is compared. Strict
+    // mode stops on them; lenient mode makes the comparison false and says why
+    let non_finite = if !is_finite_value(json_value) {
+        Some(operands.left)
+    } else if !is_finite_value(value) {
+        Some(operands.right.unwrap_or(LITERAL_OPERAND))
+    } else {
+        None
+    };
+    if let Some(path) = non_finite {
+        if !operands.lenient {
+            return Err(RuleError::NonFiniteNumber {
+                path: path.to_string(),
+            });
+        }
+        let details = ComparisonEvaluationTrace {
+            left_value: TypedValue::from(json_value),
+            right_value: TypedValue::from(value),
+            comparison_result: false,
+            warning: Some(format!(
+                "{} is not a finite number, so the comparison is false",
+                path
+            )),
+            list_mode: None,
+            deciding_element: None,
+            duration_seconds: None,
+            window: None,
+            cutoff: None,
+            reference_date: None,
+            quantifier: None,
+            matched_elements: None,
+            case_sensitive: None,
+            unexpected_object: None,
+        };
+        return Ok((false, Some(details)));
+    }
+
+    let version_order = |left: &RuleValue| {
+        operands
+            .versions
+            .then(|| compare_versions(left, operator, value))
+            .flatten()
+    };
+
+    if let RuleValue::List(items) = json_value {
+        if operator.compares_single_values() {
+            let passes = |item: &RuleValue| match version_order(item) {
+                Some(result) => result,
+                None => evaluate_comparison(item, operator, value, operands).unwrap_or(false),
+            };
+            let mut right = TypedValue::from(value);
+            if operands.versions && Version::orders(operator, value) {
+                right.value_type = VERSION_TYPE.to_string();
+            }
+            let (result, details) =
+                compare_list_elements(items, json_value, operator, right, operands, passes)?;
+            let details = details.map(|mut details| {
+                details.case_sensitive =
+                    string_case(operator, &[json_value, value], operands.case_sensitive);
+                with_duration(details, operator, value, operands.today)
+            });
+            return Ok((result, details));
+        }
+    }
+
+    // Both sides are typed `version` when they were ordered as versions
+    let (compared, value_type) = match version_order(json_value) {
+        Some(result) => (Ok(result), Some(VERSION_TYPE)),
+        None => (
+            evaluate_comparison(json_value, operator, value, operands),
+            None,
+        ),
+    };
+    let typed = |rule_value: &RuleValue| {
+        let mut typed = TypedValue::from(rule_value);
+        if let Some(value_type) = value_type {
+            typed.value_type = value_type.to_string();
+        }
+        typed
+    };
+    match compared {
         Ok(result) => {
             let details = ComparisonEvaluationTrace {
-                left_value: TypedValue::from(json_value),
-                right_value: TypedValue::from(value),
+                left_value: typed(json_value),
+                right_value: typed(value),
                 comparison_result: result,
+                warning: None,
+                list_mode: None,
+                deciding_element: None,
+                duration_seconds: None,
+                window: None,
+                cutoff: None,
+                reference_date: None,
+                quantifier: None,
+                matched_elements: None,
+                case_sensitive: string_case(
+                    operator,
+                    &[json_value, value],
+                    operands.case_sensitive,
+                ),
+                unexpected_object: None,
             };
-            Ok((result, Some(details)))
+            Ok((
+                result,
+                Some(with_duration(details, operator, value, operands.today)),
+            ))
         }
         Err(_) => Ok((false, None)),
     }
 }
 
+/// Whether `operator` compared strings minding case, when it is one that can ignore case
+/// and one of `values` holds a string. `is exactly equal to` always minds it
+fn string_case(
+    operator: &ComparisonOperator,
+    values: &[&RuleValue],
+    case_sensitive: bool,
+) -> Option<bool> {
+    use ComparisonOperator::*;
+
+    fn holds_string(value: &RuleValue) -> bool {
+        match value {
+            RuleValue::String(_) => true,
+            RuleValue::List(items) => items.iter().any(holds_string),
+            _ => false,
+        }
+    }
+
+    let ignores_case = matches!(operator, EqualTo | NotEqualTo | In | NotIn | Contains);
+    let compares_strings = values.iter().any(|value| holds_string(value));
+    match operator {
+        ExactlyEqualTo if compares_strings => Some(true),
+        _ if ignores_case && compares_strings => Some(case_sensitive),
+        _ => None,
+    }
+}
+
+/// Adds what a duration on the right came to: its seconds, the date it counted from and, for
+/// `is within` and `is not within`, the window, or for `is more than … ago` and `is at least …
+/// ago`, the cutoff
+fn with_duration(
+    mut details: ComparisonEvaluationTrace,
+    operator: &ComparisonOperator,
+    value: &RuleValue,
+    today: NaiveDate,
+) -> ComparisonEvaluationTrace {
+    use ComparisonOperator::*;
+
+    if let RuleValue::Duration(duration) = value {
+        details.duration_seconds = Some(duration.to_seconds());
+        if operator.is_time_dependent() {
+            details.reference_date = Some(today);
+        }
+        match operator {
+            Within | NotWithin => details.window = within_window(duration, today),
+            MoreThanAgo | AtLeastAgo => details.cutoff = ago_cutoff(duration, today),
+            _ => {}
+        }
+    }
+    details
+}
+
+/// Applies a single-value comparison to each element of a list, as `list_scalar_mode` says.
+/// `compare` decides one element against the right operand, `right`
+fn compare_list_elements(
+    items: &[RuleValue],
+    list: &RuleValue,
+    operator: &ComparisonOperator,
+    right: TypedValue,
+    operands: Operands,
+    compare: impl Fn(&RuleValue) -> bool,
+) -> Result<(bool, Option<ComparisonEvaluationTrace>), RuleError> {
+    // Elements are read the way the same value would be read from its own property
+    let passes = |item: &RuleValue| {
+        let date = try_parse_date(item).map(RuleValue::Date);
+        compare(date.as_ref().unwrap_or(item))
+    };
+    let (result, deciding_element) = match operands.list_mode {
+        ListScalarMode::Error => {
+            return Err(RuleError::ListComparedWithScalar {
+                path: operands.left.to_string(),
+                operator: operator.clone(),
+            })
+        }
+        ListScalarMode::AnyElement => match items.iter().position(passes) {
+            Some(index) => (true, Some(index)),
+            None => (false, None),
+        },
+        ListScalarMode::AllElements => match items.iter().position(|item| !passes(item)) {
+            Some(index) => (false, Some(index)),
+            None => (!items.is_empty(), None),
+        },
+    };
+
+    let details = ComparisonEvaluationTrace {
+        left_value: TypedValue::from(list),
+        right_value: right,
+        comparison_result: result,
+        warning: None,
+        list_mode: Some(operands.list_mode),
+        deciding_element,
+        duration_seconds: None,
+        window: None,
+        cutoff: None,
+        reference_date: None,
+        quantifier: None,
+        matched_elements: None,
+        case_sensitive: None,
+        unexpected_object: None,
+    };
+    Ok((result, Some(details)))
+}
+
+/// A `{"min": x, "max": y}` object read as the numbers between its bounds, either of which
+/// may be left out for an open-ended range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumericRange {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl NumericRange {
+    /// The range an object holds when its only keys are `min` and `max` and they are numbers.
+    /// A null bound counts as left out, but at least one bound is needed
+    pub fn from_json(value: &Value) -> Option<Self> {
+        let object = value.as_object()?;
+        if object.keys().any(|key| key != "min" && key != "max") {
+            return None;
+        }
+        let bound = |key: &str| match object.get(key) {
+            None | Some(Value::Null) => Some(None),
+            Some(bound) => bound.as_f64().map(Some),
+        };
+        let range = Self {
+            min: bound("min")?,
+            max: bound("max")?,
+        };
+        (range.min.is_some() || range.max.is_some()).then_some(range)
+    }
+
+    /// Whether `value` is within the range, `is within`, outside it, `is not within`, or above
+    /// or below it, `is greater than` comparing against `max` and `is less than` against `min`.
+    /// Comparing against a bound the range leaves open is false
+    fn passes(
+        &self,
+        value: &RuleValue,
+        operator: &ComparisonOperator,
+        operands: Operands,
+    ) -> Result<bool, RuleError> {
+        let compare = |operator: ComparisonOperator, bound: Option<f64>| match bound {
+            Some(bound) => {
+                evaluate_comparison(value, &operator, &RuleValue::Number(bound), operands)
+            }
+            None => Ok(false),
+        };
+        match operator {
+            ComparisonOperator::NotWithin => Ok(!self.passes(value, &ComparisonOperator::Within, operands)?),
+            ComparisonOperator::Within => {
+                let above_min = self.min.is_none()
+                    || compare(ComparisonOperator::GreaterThanOrEqual, self.min)?;
+                let below_max =
+                    self.max.is_none() || compare(ComparisonOperator::LessThanOrEqual, self.max)?;
+                Ok(above_min && below_max)
+            }
+            ComparisonOperator::GreaterThan | ComparisonOperator::GreaterThanOrEqual => {
+                compare(operator.clone(), self.max)
+            }
+            ComparisonOperator::LessThan | ComparisonOperator::LessThanOrEqual => {
+                compare(operator.clone(), self.min)
+            }
+            _ => Err(RuleError::EvaluationError(format!(
+                "'{}' can't compare with a range; use is within, is not within, is greater than or is less than",
+                operator
+            ))),
+        }
+    }
+
+    /// The bounds as the trace shows them, typed `range`
+    fn to_typed_value(self) -> TypedValue {
+        let mut bounds = serde_json::Map::new();
+        if let Some(min) = self.min {
+            bounds.insert("min".to_string(), number_value(min));
+        }
+        if let Some(max) = self.max {
+            bounds.insert("max".to_string(), number_value(max));
+        }
+        TypedValue {
+            value: Value::Object(bounds),
+            value_type: "range".to_string(),
+            source: None,
+        }
+    }
+}
+
+/// Compares a property with the range another property holds, see `NumericRange::passes`
+fn compare_with_range(
+    json_value: &RuleValue,
+    operator: &ComparisonOperator,
+    range: NumericRange,
+    operands: Operands,
+) -> Result<(bool, Option<ComparisonEvaluationTrace>), RuleError> {
+    let bounds = range.to_typed_value();
+    if let RuleValue::List(items) = json_value {
+        let passes = |item: &RuleValue| range.passes(item, operator, operands).unwrap_or(false);
+        return compare_list_elements(items, json_value, operator, bounds, operands, passes);
+    }
+
+    let result = range.passes(json_value, operator, operands)?;
+    let details = ComparisonEvaluationTrace {
+        left_value: TypedValue::from(json_value),
+        right_value: bounds,
+        comparison_result: result,
+        warning: None,
+        list_mode: None,
+        deciding_element: None,
+        duration_seconds: None,
+        window: None,
+        cutoff: None,
+        reference_date: None,
+        quantifier: None,
+        matched_elements: None,
+        case_sensitive: None,
+        unexpected_object: None,
+    };
+    Ok((result, Some(details)))
+}
+
+/// The trace type of values ordered as versions
+const VERSION_TYPE: &str = "version";
+
+/// A dotted numeric version such as `2.14.3` or `1.0.0-rc.1`, ordered as semver orders them:
+/// component by component, a missing component counting as zero, and a pre-release before
+/// its release. Build metadata after a `+` is ignored
+#[derive(Debug, Clone)]
+pub struct Version {
+    release: Vec<u64>,
+    pre_release: Vec<String>,
+}
+
+impl Version {
+    /// The version a string holds, when it has at least two numeric components
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.split_once('+').map_or(text, |(version, _)| version);
+        let (release, pre_release) = match text.split_once('-') {
+            Some((release, pre_release)) => (release, Some(pre_release)),
+            None => (text, None),
+        };
+
+        let release = release
+            .split('.')
+            .map(|component| {
+                if component.is_empty() || !component.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                component.parse().ok()
+            })
+            .collect::<Option<Vec<u64>>>()?;
+        if release.len() < 2 {
+            return None;
+        }
+
+        let pre_release = match pre_release {
+            Some(pre_release) => {
+                let identifiers: Vec<String> = pre_release.split('.').map(String::from).collect();
+                let valid = |identifier: &String| {
+                    !identifier.is_empty()
+                        && identifier
+                            .bytes()
+                            .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+                };
+                if !identifiers.iter().all(valid) {
+                    return None;
+                }
+                identifiers
+            }
+            None => Vec::new(),
+        };
+
+        Some(Self {
+            release,
+            pre_release,
+        })
+    }
+
+    pub fn order(&self, other: &Self) -> Ordering {
+        let components = self.release.len().max(other.release.len());
+        let component = |version: &Self, index: usize| *version.release.get(index).unwrap_or(&0);
+        let release = (0..components)
+            .map(|index| component(self, index).cmp(&component(other, index)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal);
+
+        release.then_with(
+            || match (self.pre_release.is_empty(), other.pre_release.is_empty()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => self
+                    .pre_release
+                    .iter()
+                    .zip(&other.pre_release)
+                    .map(|(a, b)| compare_pre_release_identifiers(a, b))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or_else(|| self.pre_release.len().cmp(&other.pre_release.len())),
+            },
+        )
+    }
+
+    /// Whether `operator` orders `value` as a version, when version ordering is on
+    pub fn orders(operator: &ComparisonOperator, value: &RuleValue) -> bool {
+        use ComparisonOperator::*;
+
+        matches!(
+            operator,
+            GreaterThanOrEqual | LessThanOrEqual | GreaterThan | LessThan
+        ) && matches!(value, RuleValue::String(s) if Version::parse(s).is_some())
+    }
+}
+
+/// Numeric identifiers compare as numbers and before alphanumeric ones, which compare as text
+fn compare_pre_release_identifiers(a: &str, b: &str) -> Ordering {
+    match (a.parse::<u64>(), b.parse::<u64>()) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+/// The result of an ordering operator when both sides are versions, `None` otherwise
+fn compare_versions(
+    left: &RuleValue,
+    operator: &ComparisonOperator,
+    right: &RuleValue,
+) -> Option<bool> {
+    let (RuleValue::String(left), RuleValue::String(right)) = (left, right) else {
+        return None;
+    };
+    let ordering = Version::parse(left)?.order(&Version::parse(right)?);
+    match operator {
+        ComparisonOperator::GreaterThanOrEqual => Some(ordering.is_ge()),
+        ComparisonOperator::LessThanOrEqual => Some(ordering.is_le()),
+        ComparisonOperator::GreaterThan => Some(ordering.is_gt()),
+        ComparisonOperator::LessThan => Some(ordering.is_lt()),
+        _ => None,
+    }
+}
+
 // ===== Helper Functions =====
 
-fn find_effective_selector(selector: &str, json: &Value) -> Result<Option<String>, RuleError> {
-    // Try exact match first
-    if json.get(selector).is_some() {
-        return Ok(Some(selector.to_string()));
+/// JSONPath of a selector on its own, dots in the selector being nested selectors
+fn selector_json_path(selector: &str) -> String {
+    json_path_from_keys(&selector.split('.').collect::<Vec<_>>())
+}
+
+/// The key of `json` a selector names, found the way properties are, see
+/// `DataLookup::find_key`. The key is borrowed from the data, so nothing is copied while
+/// scanning
+fn find_effective_selector<'a>(
+    selector: &str,
+    json: &'a Value,
+    lookup: &mut DataLookup,
+) -> Result<Option<&'a str>, RuleError> {
+    Ok(lookup.find_key(json, selector).map(|(key, _)| key.as_str()))
+}
+
+/// How far property lookups may go into the data, from `EvaluationOptions`. Guards the
+/// evaluation against adversarial documents: deeply nested values and objects with so many
+/// keys that comparing each with a name would cost more than the evaluation
+struct DataLookup<'s> {
+    max_depth: usize,
+    max_scanned_keys: usize,
+    /// Why a lookup may have come up short, for the trace: the first object too large to scan
+    /// for other spellings of a name, or a list without the element a rule picked from it
+    warning: Option<String>,
+    /// Where keys looked up are counted, see `EvalStats::data_resolver_calls`
+    stats: Option<&'s mut EvalStats>,
+}
+
+impl<'s> DataLookup<'s> {
+    fn new(options: &EvaluationOptions) -> Self {
+        Self {
+            max_depth: options.max_data_depth,
+            max_scanned_keys: options.max_scanned_keys,
+            warning: None,
+            stats: None,
+        }
+    }
+
+    /// A lookup counting the keys it looks up in `stats`
+    fn counted(options: &EvaluationOptions, stats: &'s mut EvalStats) -> Self {
+        Self {
+            stats: Some(stats),
+            ..Self::new(options)
+        }
     }
 
-    // Try fuzzy matching with all JSON keys
-    if let Some(obj) = json.as_object() {
-        for (key, _) in obj {
-            if names_match(selector, key) {
-                return Ok(Some(key.clone())); // Return the actual key from JSON
+    /// Finds `name` among the keys of `value`: as written, then any naming variant, then
+    /// camelCased, then any variant of that. An object with more than `max_scanned_keys` keys
+    /// is only looked up as written and camelCased, noting why in `warning`
+    fn find_key<'a>(&mut self, value: &'a Value, name: &str) -> Option<(&'a String, &'a Value)> {
+        if let Some(stats) = self.stats.as_deref_mut() {
+            stats.data_resolver_calls += 1;
+        }
+        let object = value.as_object()?;
+        if let Some(found) = object.get_key_value(name) {
+            return Some(found);
+        }
+
+        let transformed = transform_property_name(name);
+        if object.len() > self.max_scanned_keys {
+            let found = object.get_key_value(&transformed);
+            if found.is_none() && self.warning.is_none() {
+                self.warning = Some(format!(
+                    "'{}' was only looked for as '{}' and '{}': its object has {} keys, more than the {} searched for other spellings",
+                    name,
+                    name,
+                    transformed,
+                    object.len(),
+                    self.max_scanned_keys
+                ));
             }
+            return found;
         }
+
+        object
+            .iter()
+            .find(|(key, _)| names_match(name, key))
+            .or_else(|| object.get_key_value(&transformed))
+            .or_else(|| {
+                object
+                    .iter()
+                    .find(|(key, _)| names_match(&transformed, key))
+            })
     }
 
-    Ok(None)
+    /// Fails when `value`, reached at `path` through `depth` keys and elements, nests past
+    /// `max_depth`
+    fn check_depth(&self, path: &str, depth: usize, value: &Value) -> Result<(), RuleError> {
+        let Some(room) = self.max_depth.checked_sub(depth) else {
+            return Err(self.too_deep(path));
+        };
+        if nesting_depth(value, room + 1) > room {
+            return Err(self.too_deep(path));
+        }
+        Ok(())
+    }
+
+    fn too_deep(&self, path: &str) -> RuleError {
+        RuleError::DataTooDeep {
+            path: path.to_string(),
+            limit: self.max_depth,
+        }
+    }
+}
+
+/// Levels of lists and objects in `value`, counted without recursion and no further than
+/// `cap`
+fn nesting_depth(value: &Value, cap: usize) -> usize {
+    let mut deepest = 0;
+    let mut pending = vec![(value, 0)];
+    while let Some((value, depth)) = pending.pop() {
+        let children: Box<dyn Iterator<Item = &Value>> = match value {
+            Value::Array(items) => Box::new(items.iter()),
+            Value::Object(object) => Box::new(object.values()),
+            _ => continue,
+        };
+        deepest = deepest.max(depth + 1);
+        if deepest >= cap {
+            return cap;
+        }
+        pending.extend(
+            children
+                .filter(|child| child.is_array() || child.is_object())
+                .map(|child| (child, depth + 1)),
+        );
+    }
+    deepest
 }
 
 #[allow(dead_code)]
@@ -1686,7 +4050,8 @@ fn find_effective_selector_with_mapping(
     let actual_selector = rule_set.resolve_selector(selector);
 
     // Then find the effective selector in JSON
-    find_effective_selector(&actual_selector, json)
+    let mut lookup = DataLookup::new(&EvaluationOptions::default());
+    Ok(find_effective_selector(&actual_selector, json, &mut lookup)?.map(str::to_string))
 }
 
 #[allow(dead_code)]
@@ -1700,7 +4065,7 @@ fn resolve_property_path_with_mapping<'a>(
     // Find effective selector with mapping support
     let effective_selector = find_effective_selector_with_mapping(&path.selector, json, rule_set)?;
     if effective_selector.is_none() {
-        return Ok((None, format!("$.{}", path.selector)));
+        return Ok((None, selector_json_path(&path.selector)));
     }
 
     let final_selector = effective_selector.unwrap();
@@ -1766,26 +4131,17 @@ fn resolve_property_path_with_mapping<'a>(
             current_value = value;
             path_parts.push(actual_property_name);
         } else {
-            let path_so_far = format!("$.{}", path_parts.join("."));
-            return Ok((None, format!("{}.{}", path_so_far, property)));
+            path_parts.push(property.clone());
+            return Ok((None, json_path_from_keys(&path_parts)));
         }
     }
 
-    let final_path = format!("$.{}", path_parts.join("."));
+    let final_path = json_path_from_keys(&path_parts);
     Ok((Some(current_value), final_path))
 }
 
 fn create_failed_rule_reference_trace(condition: &RuleReferenceCondition) -> ConditionTrace {
-    ConditionTrace::RuleReference(RuleReferenceTrace {
-        selector: SelectorTrace {
-            value: condition.selector.value.clone(),
-            pos: condition.selector.pos.clone(),
-        },
-        rule_name: condition.rule_name.value.clone(),
-        referenced_rule_outcome: None,
-        property_check: None,
-        result: false,
-    })
+    ResolvedReference::unresolved(false).into_trace(condition).1
 }
 
 fn create_failed_comparison_trace(
@@ -1793,15 +4149,13 @@ fn create_failed_comparison_trace(
     effective_selector: Option<&str>,
 ) -> ConditionTrace {
     let path = if let Some(sel) = effective_selector {
-        format!("$.{}.{}", sel, condition.property.value)
+        json_path_from_keys(&[sel, condition.property.value.as_str()])
     } else {
-        format!(
-            "$.{}.{}",
-            condition.selector.value, condition.property.value
-        )
+        render_json_path(&condition.selector.value, &[], &condition.property.value)
     };
 
-    ConditionTrace::Comparison(ComparisonTrace {
+    ConditionTrace::Comparison(Box::new(ComparisonTrace {
+        id: None,
         selector: SelectorTrace {
             value: condition.selector.value.clone(),
             pos: condition.selector.pos.clone(),
@@ -1809,6 +4163,9 @@ fn create_failed_comparison_trace(
         property: PropertyTrace {
             value: Value::Null,
             path,
+            document: None,
+            missed_key: None,
+            warning: None,
         },
         operator: condition.operator.clone(),
         value: condition
@@ -1817,14 +4174,18 @@ fn create_failed_comparison_trace(
             .to_value_trace(condition.value.pos.clone()),
         evaluation_details: None,
         result: false,
-    })
+        inner_result: None,
+        left_expression: None,
+        right_expression: None,
+    }))
 }
 
 fn create_failed_comparison_trace_with_path(
     condition: &ComparisonCondition,
     path: &str,
 ) -> ConditionTrace {
-    ConditionTrace::Comparison(ComparisonTrace {
+    ConditionTrace::Comparison(Box::new(ComparisonTrace {
+        id: None,
         selector: SelectorTrace {
             value: condition.selector.value.clone(),
             pos: condition.selector.pos.clone(),
@@ -1832,6 +4193,9 @@ fn create_failed_comparison_trace_with_path(
         property: PropertyTrace {
             value: Value::Null,
             path: path.to_string(),
+            document: None,
+            missed_key: None,
+            warning: None,
         },
         operator: condition.operator.clone(),
         value: condition
@@ -1840,7 +4204,47 @@ fn create_failed_comparison_trace_with_path(
             .to_value_trace(condition.value.pos.clone()),
         evaluation_details: None,
         result: false,
-    })
+        inner_result: None,
+        left_expression: None,
+        right_expression: None,
+    }))
+}
+
+/// The failed comparison of a property holding an object, showing the object read
+fn unexpected_object_trace(
+    condition: &ComparisonCondition,
+    json: &Value,
+    path: &str,
+    object: &UnexpectedObject,
+) -> ConditionTrace {
+    let mut trace = create_failed_comparison_trace_with_path(condition, path);
+    if let ConditionTrace::Comparison(comparison) = &mut trace {
+        let value = resolve_json_path(json, path)
+            .cloned()
+            .unwrap_or(Value::Null);
+        comparison.property.value = value.clone();
+        comparison.evaluation_details = Some(ComparisonEvaluationTrace {
+            left_value: TypedValue {
+                value,
+                value_type: "object".to_string(),
+                source: None,
+            },
+            right_value: TypedValue::from(&condition.value.value),
+            comparison_result: false,
+            warning: None,
+            list_mode: None,
+            deciding_element: None,
+            duration_seconds: None,
+            window: None,
+            cutoff: None,
+            reference_date: None,
+            quantifier: None,
+            matched_elements: None,
+            case_sensitive: None,
+            unexpected_object: Some(object.clone()),
+        });
+    }
+    trace
 }
 
 fn extract_value_from_json(
@@ -1953,9 +4357,12 @@ fn evaluate_comparison(
     left: &RuleValue,
     operator: &ComparisonOperator,
     right: &RuleValue,
+    operands: Operands,
 ) -> Result<bool, RuleError> {
     use ComparisonOperator::*;
 
+    let case_sensitive = operands.case_sensitive;
+
     match operator {
         // Numeric comparisons
         GreaterThanOrEqual => compare_numbers_gte(left, right),
@@ -1964,29 +4371,36 @@ fn evaluate_comparison(
         LessThan => compare_numbers_lt(left, right),
 
         // Equality comparisons
-        EqualTo => compare_equal(left, right),
+        EqualTo => compare_equal(left, right, case_sensitive),
         ExactlyEqualTo => compare_exactly_equal(left, right),
-        NotEqualTo => compare_not_equal(left, right),
+        NotEqualTo => compare_not_equal(left, right, case_sensitive),
 
         // Date comparisons
         LaterThan => compare_dates_later(left, right),
         EarlierThan => compare_dates_earlier(left, right),
 
         // List operations
-        In => compare_in_list(left, right),
-        NotIn => compare_not_in_list(left, right),
-        Contains => compare_contains(left, right),
+        In => compare_in_list(left, right, case_sensitive),
+        NotIn => compare_not_in_list(left, right, case_sensitive),
+        Contains => compare_contains(left, right, case_sensitive),
 
         // Empty checks (only use left operand, ignore right)
         IsEmpty => compare_is_empty(left),
         IsNotEmpty => compare_is_not_empty(left),
 
-        // Duration comparison
-        Within => compare_within(left, right),
+        // Duration comparisons
+        Within => compare_within(left, right, operands.today),
+        NotWithin => compare_within(left, right, operands.today).map(|within| !within),
+        MoreThanAgo => compare_ago(left, right, operands.today, false),
+        AtLeastAgo => compare_ago(left, right, operands.today, true),
 
         // Age comparisons
-        OlderThan => compare_older_than(left, right),
-        YoungerThan => compare_younger_than(left, right),
+        OlderThan => compare_older_than(left, right, operands.today),
+        YoungerThan => compare_younger_than(left, right, operands.today),
+
+        // Range comparisons
+        Between => compare_between(left, right, false),
+        StrictlyBetween => compare_between(left, right, true),
     }
 }
 
@@ -2090,10 +4504,14 @@ fn compare_numbers_lt(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleE
 }
 
 // Equality comparison functions (case-insensitive by default)
-fn compare_equal(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleError> {
+fn compare_equal(
+    left: &RuleValue,
+    right: &RuleValue,
+    case_sensitive: bool,
+) -> Result<bool, RuleError> {
     match (left, right) {
         (RuleValue::Number(l), RuleValue::Number(r)) => Ok(l == r),
-        (RuleValue::String(l), RuleValue::String(r)) => Ok(l.to_lowercase() == r.to_lowercase()),
+        (RuleValue::String(l), RuleValue::String(r)) => Ok(strings_equal(l, r, case_sensitive)),
         (RuleValue::Date(l), RuleValue::Date(r)) => Ok(l == r),
         (RuleValue::Boolean(l), RuleValue::Boolean(r)) => Ok(l == r),
         _ => Err(RuleError::TypeError(format!(
@@ -2117,8 +4535,12 @@ fn compare_exactly_equal(left: &RuleValue, right: &RuleValue) -> Result<bool, Ru
     }
 }
 
-fn compare_not_equal(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleError> {
-    compare_equal(left, right).map(|result| !result)
+fn compare_not_equal(
+    left: &RuleValue,
+    right: &RuleValue,
+    case_sensitive: bool,
+) -> Result<bool, RuleError> {
+    compare_equal(left, right, case_sensitive).map(|result| !result)
 }
 
 // Date comparison functions
@@ -2144,12 +4566,50 @@ fn compare_dates_earlier(left: &RuleValue, right: &RuleValue) -> Result<bool, Ru
     }
 }
 
+// Range comparison functions
+fn compare_between(left: &RuleValue, right: &RuleValue, strict: bool) -> Result<bool, RuleError> {
+    fn between<T: PartialOrd>(value: T, low: T, high: T, strict: bool) -> bool {
+        if strict {
+            low < value && value < high
+        } else {
+            low <= value && value <= high
+        }
+    }
+
+    let RuleValue::Range(low, high) = right else {
+        return Err(RuleError::TypeError(format!(
+            "Between requires a range, got {:?}",
+            right
+        )));
+    };
+    match (left, low.as_ref(), high.as_ref()) {
+        (RuleValue::Number(v), RuleValue::Number(l), RuleValue::Number(h)) => {
+            Ok(between(v, l, h, strict))
+        }
+        _ => match (
+            coerce_to_date(left),
+            coerce_to_date(low),
+            coerce_to_date(high),
+        ) {
+            (Some(v), Some(l), Some(h)) => Ok(between(v, l, h, strict)),
+            _ => Err(RuleError::TypeError(format!(
+                "Between requires numbers or dates, got {:?} and {:?}",
+                left, right
+            ))),
+        },
+    }
+}
+
 // List operation functions
-fn compare_in_list(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleError> {
+fn compare_in_list(
+    left: &RuleValue,
+    right: &RuleValue,
+    case_sensitive: bool,
+) -> Result<bool, RuleError> {
     match right {
         RuleValue::List(items) => {
             for item in items {
-                if is_equal(left, item) {
+                if is_equal(left, item, case_sensitive) {
                     return Ok(true);
                 }
             }
@@ -2161,17 +4621,28 @@ fn compare_in_list(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleErro
     }
 }
 
-fn compare_not_in_list(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleError> {
-    compare_in_list(left, right).map(|result| !result)
+fn compare_not_in_list(
+    left: &RuleValue,
+    right: &RuleValue,
+    case_sensitive: bool,
+) -> Result<bool, RuleError> {
+    compare_in_list(left, right, case_sensitive).map(|result| !result)
 }
 
-fn compare_contains(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleError> {
+fn compare_contains(
+    left: &RuleValue,
+    right: &RuleValue,
+    case_sensitive: bool,
+) -> Result<bool, RuleError> {
     match left {
         RuleValue::String(l) => match right {
             RuleValue::String(r) => {
                 // Convert to lowercase for case-insensitive comparison
-                let haystack = l.to_lowercase();
-                let needle = r.to_lowercase();
+                let (haystack, needle) = if case_sensitive {
+                    (l.clone(), r.clone())
+                } else {
+                    (l.to_lowercase(), r.to_lowercase())
+                };
 
                 // Check if the needle appears as a whole word
                 // We need to check if the match is at a word boundary
@@ -2206,7 +4677,7 @@ fn compare_contains(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleErr
         },
         RuleValue::List(items) => {
             for item in items {
-                if is_equal(item, right) {
+                if is_equal(item, right, case_sensitive) {
                     return Ok(true);
                 }
             }
@@ -2218,24 +4689,33 @@ fn compare_contains(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleErr
     }
 }
 
-// Helper function to check equality without returning Result (case-insensitive for strings)
-fn is_equal(left: &RuleValue, right: &RuleValue) -> bool {
+// Helper function to check equality without returning Result (case-insensitive for strings
+// unless `case_sensitive`)
+fn is_equal(left: &RuleValue, right: &RuleValue, case_sensitive: bool) -> bool {
     match (left, right) {
         (RuleValue::Number(l), RuleValue::Number(r)) => l == r,
-        (RuleValue::String(l), RuleValue::String(r)) => l.to_lowercase() == r.to_lowercase(),
+        (RuleValue::String(l), RuleValue::String(r)) => strings_equal(l, r, case_sensitive),
         (RuleValue::Date(l), RuleValue::Date(r)) => l == r,
         (RuleValue::Boolean(l), RuleValue::Boolean(r)) => l == r,
         _ => false,
     }
 }
 
+fn strings_equal(left: &str, right: &str, case_sensitive: bool) -> bool {
+    if case_sensitive {
+        left == right
+    } else {
+        left.to_lowercase() == right.to_lowercase()
+    }
+}
+
 // Empty check functions
 pub fn compare_is_empty(value: &RuleValue) -> Result<bool, RuleError> {
     match value {
         RuleValue::String(s) => Ok(s.is_empty()),
         RuleValue::List(items) => Ok(items.is_empty()),
         _ => Err(RuleError::TypeError(
-            "IsEmpty only works with strings or lists".to_string(),
+            "IsEmpty only works with strings, lists, objects or null".to_string(),
         )),
     }
 }
@@ -2244,7 +4724,76 @@ pub fn compare_is_not_empty(value: &RuleValue) -> Result<bool, RuleError> {
     compare_is_empty(value).map(|result| !result)
 }
 
-fn compare_within(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleError> {
+/// `compare_is_empty` on a JSON value: an object without keys and null are empty too
+pub fn compare_json_is_empty(value: &Value) -> Result<bool, RuleError> {
+    match value {
+        Value::Null => Ok(true),
+        Value::Object(map) => Ok(map.is_empty()),
+        Value::Array(items) => Ok(items.is_empty()),
+        _ => compare_is_empty(&convert_json_to_rule_value(value)?),
+    }
+}
+
+/// The kind of value an empty check judged, as its trace types it
+fn emptiness_kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => match convert_json_to_rule_value(value) {
+            Ok(RuleValue::Date(_)) => "date",
+            _ => "string",
+        },
+        Value::Array(_) => "list",
+        Value::Object(_) => "object",
+    }
+}
+
+/// `is empty` or `is not empty` on the property at `path`. The trace's left value is typed
+/// with the kind judged; a number, boolean or date can't be empty and fails with a type error
+fn compare_emptiness(
+    value: &Value,
+    operator: &ComparisonOperator,
+    right: &RuleValue,
+    path: &str,
+) -> Result<(bool, Option<ComparisonEvaluationTrace>), RuleError> {
+    let kind = emptiness_kind(value);
+    let empty = compare_json_is_empty(value).map_err(|_| {
+        RuleError::TypeError(format!(
+            "Cannot check whether {} is empty: it is a {}, and only strings, lists, objects and null can be empty",
+            path, kind
+        ))
+    })?;
+    let result = empty != (*operator == ComparisonOperator::IsNotEmpty);
+
+    let details = ComparisonEvaluationTrace {
+        left_value: TypedValue {
+            value: value.clone(),
+            value_type: kind.to_string(),
+            source: None,
+        },
+        right_value: TypedValue::from(right),
+        comparison_result: result,
+        warning: None,
+        list_mode: None,
+        deciding_element: None,
+        duration_seconds: None,
+        window: None,
+        cutoff: None,
+        reference_date: None,
+        quantifier: None,
+        matched_elements: None,
+        case_sensitive: None,
+        unexpected_object: None,
+    };
+    Ok((result, Some(details)))
+}
+
+fn compare_within(
+    left: &RuleValue,
+    right: &RuleValue,
+    today: NaiveDate,
+) -> Result<bool, RuleError> {
     match right {
         RuleValue::Duration(duration) => {
             let date_value = coerce_to_date(left).ok_or_else(|| {
@@ -2254,10 +4803,10 @@ fn compare_within(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleError
                 ))
             })?;
 
-            let now = chrono::Utc::now().naive_utc().date();
-            let diff_days = (date_value - now).num_days().abs() as f64;
-            let duration_days = duration.to_seconds() / 86400.0;
-            Ok(diff_days <= duration_days)
+            let window = within_window(duration, today).ok_or_else(|| {
+                RuleError::EvaluationError(format!("'within {}' is out of range", duration))
+            })?;
+            Ok(window.start <= date_value && date_value <= window.end)
         }
         _ => Err(RuleError::TypeError(
             "Within operator requires a duration as the right operand".to_string(),
@@ -2265,7 +4814,65 @@ fn compare_within(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleError
     }
 }
 
-pub fn compare_older_than(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleError> {
+/// The dates within `duration` of `today`, in whole days either side; a negative duration
+/// accepts none. `None` when the window goes past the dates that can be represented
+fn within_window(duration: &Duration, today: NaiveDate) -> Option<DateWindow> {
+    let days = whole_days(duration)?;
+    Some(DateWindow {
+        start: today.checked_sub_signed(days)?,
+        end: today.checked_add_signed(days)?,
+    })
+}
+
+/// `duration` in whole days, rounded down, `None` when it is too long to count in days
+fn whole_days(duration: &Duration) -> Option<chrono::TimeDelta> {
+    let days = (duration.to_seconds() / 86400.0).floor();
+    if !days.is_finite() || days.abs() > i32::MAX as f64 {
+        return None;
+    }
+    chrono::TimeDelta::try_days(days as i64)
+}
+
+/// `today` less `duration` in whole days, as the start of the `is within` window counts it.
+/// `None` when that date can't be represented
+fn ago_cutoff(duration: &Duration, today: NaiveDate) -> Option<NaiveDate> {
+    today.checked_sub_signed(whole_days(duration)?)
+}
+
+/// `is more than 90 days ago`: whether the date is before `today` less the duration, or on
+/// that date too when `inclusive`, for `is at least`. Unlike `is not within`, which accepts
+/// dates past either end of its window, it only looks back
+fn compare_ago(
+    left: &RuleValue,
+    right: &RuleValue,
+    today: NaiveDate,
+    inclusive: bool,
+) -> Result<bool, RuleError> {
+    let RuleValue::Duration(duration) = right else {
+        return Err(RuleError::TypeError(
+            "Ago operators require a duration as the right operand".to_string(),
+        ));
+    };
+    let date = coerce_to_date(left).ok_or_else(|| {
+        RuleError::TypeError(format!(
+            "Ago operators require a date or convertible value, got {:?}",
+            left
+        ))
+    })?;
+    let cutoff = ago_cutoff(duration, today)
+        .ok_or_else(|| RuleError::EvaluationError(format!("'{} ago' is out of range", duration)))?;
+    Ok(if inclusive {
+        date <= cutoff
+    } else {
+        date < cutoff
+    })
+}
+
+pub fn compare_older_than(
+    left: &RuleValue,
+    right: &RuleValue,
+    today: NaiveDate,
+) -> Result<bool, RuleError> {
     match right {
         RuleValue::Duration(duration) => {
             // Convert date of birth to a date
@@ -2276,8 +4883,7 @@ pub fn compare_older_than(left: &RuleValue, right: &RuleValue) -> Result<bool, R
                 ))
             })?;
 
-            let now = chrono::Utc::now().naive_utc().date();
-            let age_days = (now - date_of_birth).num_days() as f64;
+            let age_days = (today - date_of_birth).num_days() as f64;
             let duration_days = duration.to_seconds() / 86400.0;
 
             // Person is older than the duration if their age is greater than the duration
@@ -2289,7 +4895,11 @@ pub fn compare_older_than(left: &RuleValue, right: &RuleValue) -> Result<bool, R
     }
 }
 
-pub fn compare_younger_than(left: &RuleValue, right: &RuleValue) -> Result<bool, RuleError> {
+pub fn compare_younger_than(
+    left: &RuleValue,
+    right: &RuleValue,
+    today: NaiveDate,
+) -> Result<bool, RuleError> {
     match right {
         RuleValue::Duration(duration) => {
             // Convert date of birth to a date
@@ -2300,8 +4910,7 @@ pub fn compare_younger_than(left: &RuleValue, right: &RuleValue) -> Result<bool,
                 ))
             })?;
 
-            let now = chrono::Utc::now().naive_utc().date();
-            let age_days = (now - date_of_birth).num_days() as f64;
+            let age_days = (today - date_of_birth).num_days() as f64;
             let duration_days = duration.to_seconds() / 86400.0;
 
             // Person is younger than the duration if their age is less than the duration
@@ -0,0 +1,158 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::fingerprint::{
+        canonical_json, canonical_rule_text, fingerprint, fingerprint_with_context,
+    };
+    use crate::runner::outcome::EvaluationOutcome;
+    use crate::runner::parser::parse_rules;
+    use chrono::NaiveDate;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    const RULES: &str = r#"
+    A **user** passes the test
+      if the __age__ of the **user** is greater than 18.
+    "#;
+
+    fn today() -> NaiveDate {
+        chrono::Utc::now().date_naive()
+    }
+
+    fn outcome(result: bool) -> EvaluationOutcome {
+        EvaluationOutcome::decided(result, HashMap::new())
+    }
+
+    #[test]
+    fn test_fingerprint_is_stable() {
+        let rule_set = parse_rules(RULES).unwrap();
+        let data = json!({"user": {"age": 30, "name": "Ann"}});
+
+        let first = fingerprint(&rule_set, &data, &outcome(true));
+        let second = fingerprint(&parse_rules(RULES).unwrap(), &data, &outcome(true));
+
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 64);
+        assert!(first.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_formatting() {
+        let rule_set = parse_rules(RULES).unwrap();
+        let reformatted = parse_rules(
+            "A **user** passes the test if the __age__ of the **user** is greater than 18.",
+        )
+        .unwrap();
+        let data: serde_json::Value =
+            serde_json::from_str(r#"{"user": {"age": 30, "name": "Ann"}}"#).unwrap();
+        let reordered: serde_json::Value =
+            serde_json::from_str(r#"{ "user": { "name": "Ann", "age": 30 } }"#).unwrap();
+
+        assert_eq!(
+            fingerprint(&rule_set, &data, &outcome(true)),
+            fingerprint(&reformatted, &reordered, &outcome(true))
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_changes_with_every_input() {
+        let rule_set = parse_rules(RULES).unwrap();
+        let data = json!({"user": {"age": 30}});
+        let base = fingerprint(&rule_set, &data, &outcome(true));
+
+        let other_rules = parse_rules(
+            "A **user** passes the test if the __age__ of the **user** is greater than 21.",
+        )
+        .unwrap();
+        let mut labelled = outcome(true);
+        labelled.labels.insert("adult".to_string(), true);
+        let mut label_flipped = outcome(true);
+        label_flipped.labels.insert("adult".to_string(), false);
+
+        let variants = [
+            fingerprint(&other_rules, &data, &outcome(true)),
+            fingerprint(&rule_set, &json!({"user": {"age": 31}}), &outcome(true)),
+            fingerprint(&rule_set, &data, &outcome(false)),
+            fingerprint(&rule_set, &data, &labelled),
+            fingerprint(&rule_set, &data, &label_flipped),
+        ];
+
+        for (i, variant) in variants.iter().enumerate() {
+            assert_ne!(
+                &base, variant,
+                "variant {} should change the fingerprint",
+                i
+            );
+        }
+        assert_ne!(variants[3], variants[4]);
+    }
+
+    #[test]
+    fn test_canonical_forms() {
+        assert_eq!(
+            canonical_json(&json!({"b": [1, {"d": null, "c": "x"}], "a": true})),
+            r#"{"a":true,"b":[1,{"c":"x","d":null}]}"#
+        );
+        assert_eq!(
+            canonical_rule_text("  A **user**\n\tpasses   the test.\n"),
+            "A **user** passes the test."
+        );
+    }
+
+    #[test]
+    fn test_context_is_fingerprinted_when_present() {
+        let rule_set = parse_rules(RULES).unwrap();
+        let data = json!({"user": {"age": 30}});
+
+        let without = fingerprint(&rule_set, &data, &outcome(true));
+        assert_eq!(
+            fingerprint_with_context(&rule_set, &data, &json!(null), today(), &outcome(true)),
+            without
+        );
+
+        let web = fingerprint_with_context(
+            &rule_set,
+            &data,
+            &json!({"channel": "web"}),
+            today(),
+            &outcome(true),
+        );
+        let branch = fingerprint_with_context(
+            &rule_set,
+            &data,
+            &json!({"channel": "branch"}),
+            today(),
+            &outcome(true),
+        );
+        assert_ne!(web, without);
+        assert_ne!(web, branch);
+    }
+
+    #[test]
+    fn test_as_of_is_fingerprinted_when_the_rules_read_the_date() {
+        let data = json!({"user": {"age": 30, "joined": "2020-01-01"}});
+        let as_of = |rules: &str, date: &str| {
+            fingerprint_with_context(
+                &parse_rules(rules).unwrap(),
+                &data,
+                &json!(null),
+                NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+                &outcome(true),
+            )
+        };
+
+        // The date can't change a decision of rules that never read it
+        assert_eq!(as_of(RULES, "2024-01-01"), as_of(RULES, "2024-06-01"));
+        assert_eq!(
+            as_of(RULES, "2024-01-01"),
+            fingerprint(&parse_rules(RULES).unwrap(), &data, &outcome(true))
+        );
+
+        for dated in [
+            "A **user** passes the test if the __joined__ of the **user** is older than 2 years.",
+            "A **user** passes the test if the __joined__ of the **user** is earlier than today.",
+        ] {
+            assert_eq!(as_of(dated, "2024-01-01"), as_of(dated, "2024-01-01"));
+            assert_ne!(as_of(dated, "2024-01-01"), as_of(dated, "2024-06-01"));
+        }
+    }
+}
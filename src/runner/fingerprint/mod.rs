@@ -0,0 +1,133 @@
+mod lib;
+
+use crate::runner::model::RuleSet;
+use crate::runner::outcome::EvaluationOutcome;
+use crate::runner::version::LANGUAGE_VERSION;
+use chrono::NaiveDate;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+pub use crate::runner::version::ENGINE_VERSION;
+
+/// SHA-256 over the canonical rule text, canonical data, engine and rule language versions and
+/// decision, hex encoded. Of the outcome only the result and labels are hashed. The engine is
+/// hashed by its crate version without the commit, so builds of one release agree.
+///
+/// Rule text has its whitespace collapsed and JSON objects are written with sorted keys, so
+/// reformatting either doesn't change the fingerprint. Each field is length prefixed. The
+/// text of included documents is hashed with their names, as its own field. Rules that count
+/// from the current date hash today in UTC as well.
+#[allow(dead_code)]
+pub fn fingerprint(rule_set: &RuleSet, data: &Value, outcome: &EvaluationOutcome) -> String {
+    let today = chrono::Utc::now().date_naive();
+    fingerprint_with_context(rule_set, data, &Value::Null, today, outcome)
+}
+
+/// Same as `fingerprint`, for a decision made with a request context and counting dates from
+/// `as_of`, which callers resolve with `EvaluationOptions::today`. The context is hashed as its
+/// own field, and only when there is one, so decisions without a context keep their
+/// fingerprints. The date is too, only when `RuleSet::is_time_dependent`, so a policy that
+/// never reads it keeps its fingerprint from one day to the next
+pub fn fingerprint_with_context(
+    rule_set: &RuleSet,
+    data: &Value,
+    context: &Value,
+    as_of: NaiveDate,
+    outcome: &EvaluationOutcome,
+) -> String {
+    let labels: BTreeMap<&String, &bool> = outcome.labels.iter().collect();
+    let labels = labels
+        .iter()
+        .map(|(label, result)| format!("{}={}", label, result))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut fields = vec![
+        ("rule", canonical_rule_text(&rule_set.source)),
+        ("data", canonical_json(data)),
+    ];
+    if !context.is_null() {
+        fields.push(("context", canonical_json(context)));
+    }
+    if !rule_set.included.is_empty() {
+        let included = rule_set
+            .included
+            .iter()
+            .map(|document| {
+                format!(
+                    "{}={}",
+                    document.name,
+                    canonical_rule_text(&document.source)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        fields.push(("included", included));
+    }
+    if rule_set.is_time_dependent() {
+        fields.push(("as_of", as_of.to_string()));
+    }
+    fields.extend([
+        ("engine", ENGINE_VERSION.to_string()),
+        ("language", LANGUAGE_VERSION.to_string()),
+        ("result", outcome.result.to_string()),
+        ("labels", labels),
+    ]);
+
+    let mut hasher = Sha256::new();
+    for (name, field) in fields {
+        hasher.update(format!("{}:{}:", name, field.len()));
+        hasher.update(field);
+        hasher.update("\n");
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Collapses every run of whitespace to a single space
+pub fn canonical_rule_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Compact JSON with object keys sorted at every level
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+
+            out.push('{');
+            for (i, (key, child)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(child, out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
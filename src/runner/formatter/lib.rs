@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::formatter::{format_rules, format_rules_with_options, FormatOptions};
+    use crate::runner::parser::parse_rule_set;
+
+    const DOCUMENTS: &[&str] = &[
+        "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 65.",
+        r#"# Driving licence policy
+A **driver** gets a driving licence
+  if §driver.test is valid
+  and the **driver** has a clean record.
+
+
+driver.test. A **driver** passes the age test
+  if __age__ of **driver** is greater than or equal to 18.   # legal age
+
+A **driver** has a clean record if the __points__ of the **driver** is less than 12
+    or the __status__ of the **driver** is in ["probation", "full licence"].
+"#,
+        r#"A **order** is valid
+  if the number of __line__ of the **order** is at least 1
+  and the length of __reference__ of the **order** is equal to 12
+  and the __amount__ of each __line__ of the **order** is greater than 0
+  and the __placed__ of the **order** is older than 3 days
+  and the __shipped__ of the **order** is later than date(2024-01-01)
+  and the __notes__ of the **order** is not empty."#,
+        "A **user** is allowed if $admin is valid or $manager succeeds.\nadmin. A **user** is admin if __role__ of **user** is equal to \"admin\". manager. A **user** is manager if __role__ of **user** is equal to \"manager\".",
+        "A **app.user** gets access\n  # checked first\n  if the __first  name__ of the **app.user** is equal to \"Ann  Lee\"\n  # then the team\n  and the __team__ of the **app.user** is in __teams__ of the **app**.\n",
+        // Aliases keep their spacing
+        "A **driver** passes the eye test (also known as \"has  passed an eye test\", \"meets the vision requirement\")\n  if the __vision__ of the **driver** is greater than 0.5.",
+        "Tags: kyc, fraud.\nscreening. A **customer** passes the screening\n  if the __risk score__ of the **customer** is less than 50.",
+        "A **user** gets access if the __age__ of the **user** is at least 18\n  and ( the __role__ of the **user** is equal to \"admin\"\n    or not (the **user** passes the check) ).",
+    ];
+
+    #[test]
+    fn test_format_puts_tags_on_their_own_line() {
+        let messy = "tags:kyc ,  Fraud . A **user** gets access if the __age__ of the **user** is at least 18.";
+        assert_eq!(
+            format_rules(messy).unwrap(),
+            "Tags: kyc, Fraud.\nA **user** gets access\n  if the __age__ of the **user** is at least 18.\n"
+        );
+    }
+
+    #[test]
+    fn test_format_canonical_layout() {
+        let messy = "A **user**   gets   access if the __age__ of   the **user**\n\n   is at least 18   and\tthe __role__ of the **user** is in [\"a\",   \"b\"]  .";
+        assert_eq!(
+            format_rules(messy).unwrap(),
+            "A **user** gets access\n  if the __age__ of the **user** is at least 18\n  and the __role__ of the **user** is in [\"a\", \"b\"].\n"
+        );
+    }
+
+    #[test]
+    fn test_format_writes_a_group_on_its_line() {
+        assert_eq!(
+            format_rules(DOCUMENTS[7]).unwrap(),
+            "A **user** gets access\n  if the __age__ of the **user** is at least 18\n  and (the __role__ of the **user** is equal to \"admin\" or not (the **user** passes the check)).\n"
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_comments_and_blank_lines() {
+        let formatted = format_rules(DOCUMENTS[1]).unwrap();
+        assert_eq!(
+            formatted,
+            r#"# Driving licence policy
+A **driver** gets a driving licence
+  if §driver.test is valid
+  and the **driver** has a clean record.
+
+driver.test. A **driver** passes the age test
+  if __age__ of **driver** is greater than or equal to 18. # legal age
+
+A **driver** has a clean record
+  if the __points__ of the **driver** is less than 12
+  or the __status__ of the **driver** is in ["probation", "full licence"].
+"#
+        );
+
+        // Comments inside a rule stay above the condition they preceded, and spacing inside
+        // names and strings is left alone
+        let formatted = format_rules(DOCUMENTS[4]).unwrap();
+        assert!(formatted.contains("  # checked first\n  if the __first  name__"));
+        assert!(formatted.contains("\"Ann  Lee\"\n  # then the team\n  and"));
+    }
+
+    #[test]
+    fn test_format_wraps_long_conditions() {
+        let options = FormatOptions { max_width: 40 };
+        let formatted = format_rules_with_options(DOCUMENTS[0], &options).unwrap();
+        assert_eq!(
+            formatted,
+            "A **Person** gets senior_discount\n  if the __age__ of the **Person**\n    is greater than or equal to 65.\n"
+        );
+        assert!(formatted.lines().all(|line| line.chars().count() <= 40));
+    }
+
+    #[test]
+    fn test_format_is_idempotent_and_keeps_meaning() {
+        for width in [30, 100] {
+            let options = FormatOptions { max_width: width };
+            for document in DOCUMENTS {
+                let once = format_rules_with_options(document, &options).unwrap();
+                let twice = format_rules_with_options(&once, &options).unwrap();
+                assert_eq!(once, twice, "not idempotent for:\n{}", document);
+
+                let before = parse_rule_set(document).unwrap();
+                let after = parse_rule_set(&once).unwrap();
+                assert_eq!(before.stats(), after.stats());
+                let outcomes = |rules: &crate::runner::model::RuleSet| {
+                    rules
+                        .rules
+                        .iter()
+                        .map(|rule| (rule.label.clone(), rule.outcome.clone()))
+                        .collect::<Vec<_>>()
+                };
+                assert_eq!(outcomes(&before), outcomes(&after));
+            }
+        }
+    }
+
+    #[test]
+    fn test_format_rejects_broken_documents() {
+        assert!(format_rules("A **user** gets access if the __age__ of the **user** is.").is_err());
+        assert_eq!(format_rules("  \n\n").unwrap(), "");
+    }
+}
@@ -0,0 +1,335 @@
+//! Rewrites policies in the canonical layout.
+//!
+//! `format_rules` rewrites a document in the canonical layout (header line, then one condition per
+//! line indented two spaces, wrapped at `FormatOptions::max_width`) while keeping comments and
+//! blank lines; formatting is idempotent.
+
+mod lib;
+
+use crate::runner::error::RuleError;
+use crate::runner::parser::{
+    parse_unresolved, syntax_error, ParseLimits, Rule as Grammar, RuleParser,
+};
+use pest::iterators::Pair;
+use pest::Parser;
+
+/// Layout settings for `format_rules_with_options`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Condition lines longer than this are wrapped onto indented continuation lines
+    pub max_width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self { max_width: 100 }
+    }
+}
+
+const INDENT: &str = "  ";
+const CONTINUATION_INDENT: &str = "    ";
+
+/// Rewrites a rule document in the canonical layout, keeping its comments and blank lines
+#[allow(dead_code)]
+pub fn format_rules(input: &str) -> Result<String, RuleError> {
+    format_rules_with_options(input, &FormatOptions::default())
+}
+
+/// Rewrites a rule document in the canonical layout: any `Tags:` line, the header on one
+/// line, then one
+/// condition per line indented by two spaces and opened by `if`, `and` or `or`, with the
+/// period after the last. Comments and single blank lines between rules are kept.
+/// Formatting a formatted document doesn't change it
+pub fn format_rules_with_options(
+    input: &str,
+    options: &FormatOptions,
+) -> Result<String, RuleError> {
+    // Reports every broken statement before anything is rewritten. Includes are kept as
+    // written, between rules
+    parse_unresolved(input, &ParseLimits::default())?;
+    let document =
+        RuleParser::parse(Grammar::rule_set, input).map_err(|e| syntax_error(e, input))?;
+
+    let mut lines = Vec::new();
+    let mut cursor = 0;
+    let rules = document
+        .flat_map(|pair| pair.into_inner())
+        .filter(|pair| pair.as_rule() == Grammar::rule);
+    for rule in rules {
+        let span = rule.as_span();
+        write_gap(&input[cursor..span.start()], &mut lines);
+        write_rule(input, rule, options, &mut lines);
+        cursor = span.end();
+    }
+    write_gap(&input[cursor..], &mut lines);
+
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    if lines.is_empty() {
+        return Ok(String::new());
+    }
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Copies the comments between two rules. A comment on the line a rule ends stays there, and
+/// any run of blank lines becomes a single one
+fn write_gap(gap: &str, lines: &mut Vec<String>) {
+    let mut line_breaks = 0;
+    for (index, line) in gap.split('\n').enumerate() {
+        if index > 0 {
+            line_breaks += 1;
+        }
+        let text = line.trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        match lines.last_mut() {
+            Some(last) if line_breaks == 0 => {
+                last.push(' ');
+                last.push_str(text);
+            }
+            _ => {
+                separate(lines, line_breaks);
+                lines.push(text.to_string());
+            }
+        }
+        line_breaks = 0;
+    }
+    separate(lines, line_breaks);
+}
+
+fn separate(lines: &mut Vec<String>, line_breaks: usize) {
+    if line_breaks > 1 && lines.last().is_some_and(|line| !line.is_empty()) {
+        lines.push(String::new());
+    }
+}
+
+/// A condition line with the comments written above it
+struct ConditionLine {
+    keyword: String,
+    words: Vec<String>,
+    comments: Vec<String>,
+}
+
+fn write_rule(input: &str, rule: Pair<Grammar>, options: &FormatOptions, lines: &mut Vec<String>) {
+    let span = rule.as_span();
+    let mut tags = None;
+    let mut header = Vec::new();
+    let mut conditions: Vec<ConditionLine> = Vec::new();
+    let mut comments = Vec::new();
+    let mut keyword = "if".to_string();
+    let mut cursor = span.start();
+
+    for child in rule.into_inner() {
+        collect_comments(&input[cursor..child.as_span().start()], &mut comments);
+        cursor = child.as_span().end();
+
+        match child.as_rule() {
+            Grammar::rule_tags => {
+                let names: Vec<&str> = child.into_inner().map(|tag| tag.as_str()).collect();
+                tags = Some(format!("Tags: {}.", names.join(", ")));
+            }
+            Grammar::condition_operator => keyword = child.as_str().to_string(),
+            Grammar::condition => {
+                let words = words(child, &mut comments);
+                conditions.push(ConditionLine {
+                    keyword: std::mem::replace(&mut keyword, "and".to_string()),
+                    words,
+                    comments: std::mem::take(&mut comments),
+                });
+            }
+            _ => header.extend(words(child, &mut comments)),
+        }
+    }
+    collect_comments(&input[cursor..span.end()], &mut comments);
+
+    lines.extend(tags);
+    lines.push(header.join(" "));
+    let last = conditions.len().saturating_sub(1);
+    for (index, condition) in conditions.into_iter().enumerate() {
+        for comment in condition.comments {
+            lines.push(format!("{}{}", INDENT, comment));
+        }
+        let suffix = if index == last { "." } else { "" };
+        write_wrapped(
+            format!("{}{}", INDENT, condition.keyword),
+            &condition.words,
+            suffix,
+            options.max_width,
+            lines,
+        );
+    }
+    // Comments left before the closing period move below the rule, where a second pass
+    // finds them between rules
+    lines.extend(comments);
+}
+
+/// A condition's text with whitespace normalized and comments dropped, as the formatter
+/// writes it on one line
+pub fn canonical_condition_text(condition: Pair<Grammar>) -> String {
+    words(condition, &mut Vec::new()).join(" ")
+}
+
+/// Fills lines word by word, starting a continuation line when the next word would pass
+/// `max_width`. A word longer than the width gets a line of its own
+fn write_wrapped(
+    prefix: String,
+    words: &[String],
+    suffix: &str,
+    max_width: usize,
+    lines: &mut Vec<String>,
+) {
+    let mut line = prefix;
+    let mut line_has_word = false;
+    for (index, word) in words.iter().enumerate() {
+        let word = if index + 1 == words.len() {
+            format!("{}{}", word, suffix)
+        } else {
+            word.clone()
+        };
+        let width = line.chars().count() + 1 + word.chars().count();
+        if line_has_word && width > max_width {
+            lines.push(std::mem::replace(
+                &mut line,
+                CONTINUATION_INDENT.to_string(),
+            ));
+            line.push_str(&word);
+        } else {
+            if !line.trim().is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        line_has_word = true;
+    }
+    lines.push(line);
+}
+
+fn collect_comments(text: &str, comments: &mut Vec<String>) {
+    for line in text.lines() {
+        if let Some(start) = line.find('#') {
+            comments.push(line[start..].trim_end().to_string());
+        }
+    }
+}
+
+/// Splits a pair's text into words at whitespace, moving comments out. Literals, property
+/// names, selectors and rule references keep their text as written, and operators such as
+/// `is greater than` stay in one word so wrapping can't break them
+fn words(pair: Pair<Grammar>, comments: &mut Vec<String>) -> Vec<String> {
+    let base = pair.as_span().start();
+    let text = pair.as_str();
+
+    let mut tokens: Vec<(usize, usize, TokenKind)> = pair
+        .clone()
+        .into_inner()
+        .flatten()
+        .filter_map(|inner| {
+            let span = inner.as_span();
+            let kind = token_kind(inner.as_rule())?;
+            Some((span.start() - base, span.end() - base, kind))
+        })
+        .collect();
+    tokens.sort_by_key(|&(start, end, _)| (start, std::cmp::Reverse(end)));
+    let mut tokens = tokens.into_iter().peekable();
+
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut index = 0;
+    while index < text.len() {
+        // Skip tokens nested in one already copied
+        while tokens.peek().is_some_and(|&(start, _, _)| start < index) {
+            tokens.next();
+        }
+        if let Some(&(start, end, kind)) = tokens.peek() {
+            if start == index {
+                let token = &text[start..end];
+                match kind {
+                    TokenKind::Verbatim => word.push_str(token),
+                    TokenKind::Trimmed => {
+                        // The whitespace that ended the word belongs to the token
+                        word.push_str(token.trim_end());
+                        words.push(std::mem::take(&mut word));
+                    }
+                    TokenKind::Phrase => {
+                        word.push_str(&token.split_whitespace().collect::<Vec<_>>().join(" "))
+                    }
+                }
+                index = end;
+                continue;
+            }
+        }
+
+        let Some(c) = text[index..].chars().next() else {
+            break;
+        };
+        if c == '#' {
+            let end = text[index..]
+                .find('\n')
+                .map_or(text.len(), |offset| index + offset);
+            comments.push(text[index..end].trim_end().to_string());
+            index = end;
+            continue;
+        }
+        // Lists are written `[a, b]` and groups `(a or b)` however they were spaced
+        match c {
+            c if c.is_whitespace() => {
+                if !word.is_empty() && !word.ends_with(['[', '(']) {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            ',' | ']' | ')' if word.is_empty() && !words.is_empty() => {
+                if let Some(previous) = words.last_mut() {
+                    previous.push(c);
+                }
+            }
+            ',' => {
+                word.push(c);
+                words.push(std::mem::take(&mut word));
+            }
+            _ => word.push(c),
+        }
+        index += c.len_utf8();
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+#[derive(Clone, Copy)]
+enum TokenKind {
+    /// Copied as written, since its spacing is part of its value
+    Verbatim,
+    /// Copied without trailing whitespace, which the parser ignores
+    Trimmed,
+    /// Several words that must stay on one line, with their spacing normalized
+    Phrase,
+}
+
+fn token_kind(grammar_rule: Grammar) -> Option<TokenKind> {
+    match grammar_rule {
+        Grammar::string_literal
+        | Grammar::property
+        | Grammar::object_selector
+        | Grammar::possessive_selector
+        | Grammar::number
+        | Grammar::date_literal
+        | Grammar::outcome_alias => Some(TokenKind::Verbatim),
+        Grammar::label | Grammar::reference_name => Some(TokenKind::Trimmed),
+        Grammar::comparison_operator
+        | Grammar::list_operator
+        | Grammar::empty_operator
+        | Grammar::between_operator
+        | Grammar::bare_operator
+        | Grammar::ago_operator
+        | Grammar::arithmetic_operator
+        | Grammar::boolean_predicate
+        | Grammar::label_reference
+        | Grammar::outcome_verb => Some(TokenKind::Phrase),
+        _ => None,
+    }
+}
@@ -0,0 +1,238 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::error::RuleError;
+    use crate::runner::evaluator::evaluate;
+    use crate::runner::include::{
+        parse_rule_set_with_loader, parse_rules_with_loader, FileLoader, LoadError, RuleLoader,
+        MAX_INCLUDE_DEPTH,
+    };
+    use crate::runner::options::EvaluationOptions;
+    use crate::runner::parser::{parse_rules, ParseLimits};
+    use crate::runner::validator::validate_rule_set;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::fs;
+
+    struct Documents(HashMap<&'static str, &'static str>);
+
+    impl RuleLoader for Documents {
+        fn load(&self, name: &str) -> Result<String, LoadError> {
+            self.0
+                .get(name)
+                .map(|text| text.to_string())
+                .ok_or_else(|| LoadError::NotFound(name.to_string()))
+        }
+    }
+
+    fn documents(entries: &[(&'static str, &'static str)]) -> Documents {
+        Documents(entries.iter().copied().collect())
+    }
+
+    const LICENCE: &str = r#"Include "common/age-checks".
+
+A **driver** gets a driving licence
+  if the **driver** passes the age test
+  and the __vision__ of the **driver** is greater than 0.5."#;
+
+    const AGE_CHECKS: &str = "\
+A **driver** passes the age test
+  if the __age__ of the **driver** is greater than or equal to 18.";
+
+    #[test]
+    fn test_included_rules_are_evaluated() {
+        let loader = documents(&[("common/age-checks", AGE_CHECKS)]);
+        let rule_set = parse_rules_with_loader(LICENCE, &loader).unwrap();
+
+        assert_eq!(rule_set.rules.len(), 2);
+        assert_eq!(rule_set.rules[0].document, None);
+        assert_eq!(
+            rule_set.rules[1].document.as_deref(),
+            Some("common/age-checks")
+        );
+        assert_eq!(rule_set.included[0].source, AGE_CHECKS);
+
+        let outcome = evaluate(
+            &rule_set,
+            &json!({"driver": {"age": 17, "vision": 0.9}}),
+            &EvaluationOptions::new(),
+        );
+        assert!(!outcome.result);
+        assert_eq!(outcome.outcomes.get("age test"), Some(&false));
+    }
+
+    #[test]
+    fn test_includes_need_a_loader() {
+        match parse_rules(LICENCE) {
+            Err(RuleError::IncludeFailed { name, line, .. }) => {
+                assert_eq!((name.as_str(), line), ("common/age-checks", 1));
+            }
+            other => panic!("Expected an include error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_document_included_twice_is_merged_once() {
+        let loader = documents(&[
+            (
+                "licence",
+                r#"Include "age". Include "vision".
+A **driver** gets a driving licence
+  if the **driver** passes the age test
+  and the **driver** passes the eye test."#,
+            ),
+            ("age", AGE_CHECKS),
+            (
+                "vision",
+                r#"Include "age".
+A **driver** passes the eye test
+  if the __vision__ of the **driver** is greater than 0.5."#,
+            ),
+        ]);
+        let rule_set = parse_rules_with_loader(r#"Include "licence"."#, &loader).unwrap();
+
+        let names: Vec<_> = rule_set
+            .included
+            .iter()
+            .map(|document| document.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["licence", "age", "vision"]);
+        assert_eq!(rule_set.rules.len(), 3);
+    }
+
+    #[test]
+    fn test_include_cycle_is_refused() {
+        let loader = documents(&[("a", "Include \"b\".\n"), ("b", "Include \"a\".\n")]);
+        let error = parse_rule_set_with_loader(r#"Include "a"."#, &ParseLimits::default(), &loader)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "In included 'a': In included 'b': Can't include 'a' on line 1: it includes itself: a -> b -> a"
+        );
+    }
+
+    #[test]
+    fn test_include_depth_is_limited() {
+        struct Nested;
+        impl RuleLoader for Nested {
+            fn load(&self, name: &str) -> Result<String, LoadError> {
+                let depth: usize = name.parse().unwrap();
+                Ok(format!("Include \"{}\".\n", depth + 1))
+            }
+        }
+
+        let error = parse_rule_set_with_loader(r#"Include "1"."#, &ParseLimits::default(), &Nested)
+            .unwrap_err();
+        let mut error = &error;
+        let mut depth = 0;
+        while let RuleError::InIncludedDocument { error: inner, .. } = error {
+            error = inner;
+            depth += 1;
+        }
+        assert_eq!(depth, MAX_INCLUDE_DEPTH);
+        assert!(
+            error.to_string().contains("nest deeper than 16 documents"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_errors_name_the_included_document() {
+        let loader = documents(&[(
+            "common/age-checks",
+            "\
+A **driver** passes the age test
+  if the __age__ of the **driver** is greater than.
+
+A **driver** passes the eye test
+  if the __vision__ of the **driver** is greater than.",
+        )]);
+        let error = parse_rules_with_loader(LICENCE, &loader).unwrap_err();
+        let RuleError::InIncludedDocument { document, error } = &error else {
+            panic!("Expected an included document error, got {:?}", error);
+        };
+        assert_eq!(document, "common/age-checks");
+        let diagnostics = error.parse_diagnostics().unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.document.as_deref() == Some("common/age-checks")));
+        assert_eq!(diagnostics[1].position.as_ref().unwrap().line, 5);
+
+        let missing = parse_rules_with_loader(LICENCE, &documents(&[])).unwrap_err();
+        assert_eq!(
+            missing.to_string(),
+            "Can't include 'common/age-checks' on line 1: no document named 'common/age-checks'"
+        );
+    }
+
+    #[test]
+    fn test_included_outcome_clash_is_refused() {
+        let loader = documents(&[(
+            "common/age-checks",
+            "\
+A **driver** gets a driving licence
+  if the __age__ of the **driver** is greater than or equal to 18.",
+        )]);
+        let error = parse_rules_with_loader(LICENCE, &loader).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "In included 'common/age-checks': Parse error: the outcome 'a driving licence' is already defined in the including document"
+        );
+    }
+
+    #[test]
+    fn test_lint_findings_name_the_included_document() {
+        let loader = documents(&[(
+            "common/age-checks",
+            "\
+A **driver** passes the age test
+  if the __age__ of the **driver** is later than 18.",
+        )]);
+        let rule_set = parse_rules_with_loader(LICENCE, &loader).unwrap();
+        let diagnostics = validate_rule_set(&rule_set);
+        assert!(!diagnostics.is_empty());
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.document.as_deref() == Some("common/age-checks")));
+    }
+
+    #[test]
+    fn test_file_loader_resolves_relative_to_the_including_file() {
+        let root = std::env::temp_dir().join(format!("engine-include-{}", std::process::id()));
+        fs::create_dir_all(root.join("common")).unwrap();
+        fs::write(root.join("licence.txt"), LICENCE).unwrap();
+        fs::write(
+            root.join("common/age-checks.txt"),
+            format!("Include \"../shared/adult\".\n\n{}", AGE_CHECKS),
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("shared")).unwrap();
+        fs::write(
+            root.join("shared/adult.txt"),
+            "\
+A **driver** is an adult
+  if the __age__ of the **driver** is greater than or equal to 18.",
+        )
+        .unwrap();
+
+        let loader = FileLoader::for_file(root.join("licence.txt"));
+        let text = fs::read_to_string(root.join("licence.txt")).unwrap();
+        let rule_set = parse_rule_set_with_loader(&text, &ParseLimits::default(), &loader).unwrap();
+        let names: Vec<_> = rule_set
+            .included
+            .iter()
+            .map(|document| document.name.clone())
+            .collect();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            names,
+            vec![
+                root.join("common/age-checks.txt").to_string_lossy(),
+                root.join("shared/adult.txt").to_string_lossy(),
+            ]
+        );
+        assert_eq!(rule_set.rules.len(), 3);
+    }
+}
@@ -0,0 +1,253 @@
+//! Documents pulled into a policy with `Include`.
+//!
+//! `Include "common/age-checks".` pulls in the rules of another document. `parse_rules_with_loader`
+//! and `parse_rule_set_with_loader` resolve includes through a `RuleLoader` (`load(name)`, and
+//! `resolve(name, from)` to name a document relative to the one including it), depth first, merging
+//! each document once with `RuleSet::merge`, which refuses an outcome or label another document
+//! already defines. A cycle fails, as does nesting deeper than `MAX_INCLUDE_DEPTH`. Included rules
+//! carry `Rule::document`, validator diagnostics carry `document`, and errors in an included
+//! document are wrapped in `RuleError::InIncludedDocument`. The plain parsers fail with
+//! `IncludeFailed`, so `/evaluate`, batch and gRPC reject includes; `POST /policies` resolves them
+//! from the tenant's stored policies by id and evaluations use the stored, resolved set. `policy
+//! check` resolves them from files (`FileLoader`). The fingerprint hashes included documents as
+//! their own field. Replay bundles hold the rule text only, so a policy with includes can't be
+//! replayed yet.
+
+mod lib;
+
+use crate::runner::error::RuleError;
+use crate::runner::model::{IncludedDocument, PositionedValue, RuleSet};
+use crate::runner::parser::{check_outcome_schema, parse_unresolved, ParseLimits};
+use crate::runner::utils::find_global_rule;
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+/// How many documents deep includes may nest, counting from the first included one
+pub const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Finds the documents `Include "name".` statements refer to
+pub trait RuleLoader {
+    /// Text of the document `name`, as returned by `resolve`
+    fn load(&self, name: &str) -> Result<String, LoadError>;
+
+    /// The document `name` refers to when included from the document `from`, `None` being
+    /// the document that was parsed. Includes that resolve to the same name are the same
+    /// document, which is merged once. By default names are taken as written
+    fn resolve(&self, name: &str, from: Option<&str>) -> String {
+        let _ = from;
+        name.to_string()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LoadError {
+    #[error("no document named '{0}'")]
+    NotFound(String),
+
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Loads included documents from files, each name a path relative to the file that includes
+/// it. Names without an extension get `.txt`, the extension policy files have
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct FileLoader {
+    /// Directory names included from the parsed document are relative to
+    pub root: PathBuf,
+}
+
+#[allow(dead_code)]
+impl FileLoader {
+    /// A loader for includes of the policy file at `path`
+    pub fn for_file(path: impl AsRef<Path>) -> Self {
+        let root = path
+            .as_ref()
+            .parent()
+            .map_or_else(PathBuf::new, Path::to_path_buf);
+        FileLoader { root }
+    }
+}
+
+impl RuleLoader for FileLoader {
+    fn load(&self, name: &str) -> Result<String, LoadError> {
+        std::fs::read_to_string(name).map_err(|error| match error.kind() {
+            std::io::ErrorKind::NotFound => LoadError::NotFound(name.to_string()),
+            _ => LoadError::Io(error),
+        })
+    }
+
+    fn resolve(&self, name: &str, from: Option<&str>) -> String {
+        let directory = match from.and_then(|from| Path::new(from).parent()) {
+            Some(directory) => directory.to_path_buf(),
+            None => self.root.clone(),
+        };
+        let mut path = normalize_path(&directory.join(name));
+        if path.extension().is_none() {
+            path.set_extension("txt");
+        }
+        path.to_string_lossy().into_owned()
+    }
+}
+
+/// `path` with its `.` and `..` components worked out, so one file has one name
+#[allow(dead_code)]
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Same as `parser::parse_rules`, resolving `Include "name".` statements with `loader`
+#[allow(dead_code)]
+pub fn parse_rules_with_loader(input: &str, loader: &dyn RuleLoader) -> Result<RuleSet, RuleError> {
+    let rule_set = parse_rule_set_with_loader(input, &ParseLimits::default(), loader)?;
+    find_global_rule(&rule_set.rules)?;
+    Ok(rule_set)
+}
+
+/// Parses a rule set and merges in the rules of every document it includes, and of every
+/// document those include. A document included more than once is merged once. An include
+/// that leads back to a document it was reached through, or nests deeper than
+/// `MAX_INCLUDE_DEPTH`, fails. Errors in an included document are wrapped in
+/// `InIncludedDocument` naming it, and its rules carry its name in `Rule::document`
+pub fn parse_rule_set_with_loader(
+    input: &str,
+    limits: &ParseLimits,
+    loader: &dyn RuleLoader,
+) -> Result<RuleSet, RuleError> {
+    let mut rule_set = parse_unresolved(input, limits)?;
+    let includes = std::mem::take(&mut rule_set.includes);
+    include_documents(&mut rule_set, includes, &mut Vec::new(), limits, loader)?;
+    // Declarations and the rules deciding their outcomes can be in different documents
+    check_outcome_schema(&rule_set)?;
+    Ok(rule_set)
+}
+
+/// Merges the documents `includes` name into `rule_set`, depth first. `chain` holds the
+/// documents the includes were reached through, the innermost last
+fn include_documents(
+    rule_set: &mut RuleSet,
+    includes: Vec<PositionedValue<String>>,
+    chain: &mut Vec<String>,
+    limits: &ParseLimits,
+    loader: &dyn RuleLoader,
+) -> Result<(), RuleError> {
+    for include in includes {
+        let name = loader.resolve(&include.value, chain.last().map(String::as_str));
+        let failed = |reason: String| RuleError::IncludeFailed {
+            name: name.clone(),
+            line: include.pos.as_ref().map_or(0, |pos| pos.line),
+            reason,
+        };
+
+        if chain.contains(&name) {
+            let mut cycle = chain.clone();
+            cycle.push(name.clone());
+            return Err(failed(format!(
+                "it includes itself: {}",
+                cycle.join(" -> ")
+            )));
+        }
+        if rule_set
+            .included
+            .iter()
+            .any(|document| document.name == name)
+        {
+            continue;
+        }
+        if chain.len() >= MAX_INCLUDE_DEPTH {
+            return Err(failed(format!(
+                "includes nest deeper than {} documents",
+                MAX_INCLUDE_DEPTH
+            )));
+        }
+
+        let source = loader
+            .load(&name)
+            .map_err(|error| failed(error.to_string()))?;
+        let mut document =
+            parse_unresolved(&source, limits).map_err(|error| in_document(&name, error))?;
+        rule_set.included.push(IncludedDocument {
+            name: name.clone(),
+            source,
+        });
+
+        chain.push(name.clone());
+        let nested = std::mem::take(&mut document.includes);
+        let merged = include_documents(rule_set, nested, chain, limits, loader);
+        chain.pop();
+        merged.map_err(|error| in_document(&name, error))?;
+
+        rule_set.merge(&name, document)?;
+    }
+    Ok(())
+}
+
+/// Attributes an error to the included document it happened in
+fn in_document(name: &str, mut error: RuleError) -> RuleError {
+    if let RuleError::ParseErrors { diagnostics } = &mut error {
+        for diagnostic in diagnostics {
+            diagnostic.document.get_or_insert_with(|| name.to_string());
+        }
+    }
+    RuleError::InIncludedDocument {
+        document: name.to_string(),
+        error: Box::new(error),
+    }
+}
+
+impl RuleSet {
+    /// Adds the rules and outcome declarations of the document `name` to this set, marking
+    /// each rule with the document it came from. Documents share one set of outcomes and
+    /// labels, so one that defines an outcome or label already defined is refused rather than
+    /// shadowing it
+    pub fn merge(&mut self, name: &str, other: RuleSet) -> Result<(), RuleError> {
+        for rule in &other.rules {
+            let clash = match self.rule_map.get(&rule.outcome) {
+                Some(&index) if self.rules[index].outcome == rule.outcome => {
+                    Some(("outcome", &rule.outcome_display, index))
+                }
+                _ => rule.label.as_ref().and_then(|label| {
+                    self.label_map
+                        .get(label)
+                        .map(|&index| ("label", label, index))
+                }),
+            };
+            if let Some((kind, defined, index)) = clash {
+                let existing = self.rules[index].document.as_deref().map_or_else(
+                    || "the including document".to_string(),
+                    |name| format!("'{}'", name),
+                );
+                return Err(RuleError::InIncludedDocument {
+                    document: name.to_string(),
+                    error: Box::new(RuleError::parse_error(format!(
+                        "the {} '{}' is already defined in {}",
+                        kind, defined, existing
+                    ))),
+                });
+            }
+        }
+
+        for mut rule in other.rules {
+            rule.document.get_or_insert_with(|| name.to_string());
+            self.add_rule(rule);
+        }
+        self.outcome_schema.extend(other.outcome_schema);
+        self.property_catalogue.extend(other.property_catalogue);
+        Ok(())
+    }
+}
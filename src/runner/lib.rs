@@ -1,12 +1,14 @@
 #[cfg(test)]
 mod tests {
     use crate::runner::model::{
-        ComparisonCondition, ComparisonOperator, Condition, PositionedValue, Rule,
-        RuleReferenceCondition, RuleValue,
+        ComparisonCondition, ComparisonOperator, Condition, PositionedValue, PropertyChainElement,
+        Rule, RuleReferenceCondition, RuleValue,
     };
     use crate::runner::utils::{
-        find_global_rule, find_referenced_outcomes, infer_possible_properties,
-        transform_property_name, transform_selector_name,
+        check_duplicate_keys, find_global_rule, find_referenced_outcomes,
+        infer_possible_properties, json_path_from_keys, json_paths_overlap, project_paths,
+        render_json_path, resolve_json_path, set_json_path, transform_property_name,
+        transform_selector_name,
     };
 
     fn create_test_rule(label: Option<&str>, selector: &str, outcome: &str) -> Rule {
@@ -33,6 +35,10 @@ mod tests {
             property_chain: None,
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
         })
     }
 
@@ -172,6 +178,87 @@ mod tests {
             .contains("Multiple global rules found"));
     }
 
+    #[test]
+    fn test_find_global_rule_candidates_carry_positions() {
+        use crate::runner::error::RuleError;
+        use crate::runner::parser::parse_rule_set;
+
+        let input = "A **user** gets access if the __role__ of the **user** is equal to \"admin\".\nA **user** gets premium if the __plan__ of the **user** is equal to \"gold\".";
+        let rule_set = parse_rule_set(input).unwrap();
+
+        match find_global_rule(&rule_set.rules) {
+            Err(RuleError::MultipleGlobalRules { candidates }) => {
+                let outcomes: Vec<_> = candidates.iter().map(|c| c.outcome.as_str()).collect();
+                assert_eq!(outcomes, vec!["access", "premium"]);
+                assert_eq!(candidates[0].position.as_ref().unwrap().line, 1);
+                assert_eq!(candidates[1].position.as_ref().unwrap().line, 2);
+            }
+            other => panic!("Expected MultipleGlobalRules, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_find_global_rule_no_global_lists_every_rule() {
+        use crate::runner::error::RuleError;
+
+        let mut rule1 = create_test_rule(Some("first"), "user", "eligible");
+        rule1.add_condition(create_rule_reference_condition("account", "active"), None);
+
+        let mut rule2 = create_test_rule(None, "account", "active");
+        rule2.add_condition(create_rule_reference_condition("user", "eligible"), None);
+
+        let rules = vec![rule1, rule2];
+        let error = find_global_rule(&rules).unwrap_err();
+        let candidates = error.global_rule_candidates().unwrap();
+
+        assert!(matches!(error, RuleError::NoGlobalRule { .. }));
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].label.as_deref(), Some("first"));
+    }
+
+    #[test]
+    fn test_normalize_outcome_strips_one_leading_article() {
+        use crate::runner::utils::normalize_outcome;
+
+        assert_eq!(normalize_outcome("the test"), "test");
+        assert_eq!(normalize_outcome("a driving licence"), "driving licence");
+        assert_eq!(normalize_outcome("an exemption"), "exemption");
+        assert_eq!(normalize_outcome("The Test"), "Test");
+        assert_eq!(normalize_outcome("senior_discount"), "senior_discount");
+        assert_eq!(normalize_outcome("theory test"), "theory test");
+        assert_eq!(normalize_outcome("the"), "the");
+        assert_eq!(normalize_outcome("the a test"), "a test");
+    }
+
+    #[test]
+    fn test_references_match_outcome_with_or_without_article() {
+        use crate::runner::evaluator::evaluate;
+        use crate::runner::options::EvaluationOptions;
+        use crate::runner::parser::parse_rules;
+        use serde_json::json;
+
+        for reference in ["passes the age check", "passes age check"] {
+            let input = format!(
+                "A **driver** passes the test if the **driver** {}.\nA **driver** passes the age check if the __age__ of the **driver** is at least 17.",
+                reference
+            );
+            let rule_set = parse_rules(&input).unwrap();
+
+            assert_eq!(find_global_rule(&rule_set.rules).unwrap().outcome, "test");
+            assert!(rule_set.get_rule("the age check").is_some());
+            assert!(rule_set.get_rule("age check").is_some());
+
+            let data = json!({"driver": {"age": 18}});
+            let outcome = evaluate(&rule_set, &data, &EvaluationOptions::new());
+            assert!(outcome.outcomes["test"]);
+            assert!(outcome.outcomes["age check"]);
+            assert_eq!(
+                outcome.trace.unwrap().execution[0].outcome.value,
+                "the test"
+            );
+        }
+    }
+
     #[test]
     fn test_transform_property_name_empty() {
         assert_eq!(transform_property_name(""), "");
@@ -408,7 +495,8 @@ mod tests {
 
         let rule = &rule_set.rules[0];
         assert_eq!(rule.selector, "driver");
-        assert_eq!(rule.outcome, "the age test");
+        assert_eq!(rule.outcome, "age test");
+        assert_eq!(rule.outcome_display, "the age test");
     }
 
     #[test]
@@ -471,4 +559,188 @@ mod tests {
         let properties = infer_possible_properties("   passes the test   ");
         assert!(properties.contains(&"test".to_string()));
     }
+
+    #[test]
+    fn test_render_json_path() {
+        let chain = vec![
+            PropertyChainElement::Selector("scores".to_string()),
+            PropertyChainElement::Property("theory".to_string()),
+        ];
+        let first_attempt = vec![
+            PropertyChainElement::Property("attempts".to_string()),
+            PropertyChainElement::Index(0),
+        ];
+        let last_attempt = vec![
+            PropertyChainElement::Property("attempts".to_string()),
+            PropertyChainElement::Last,
+        ];
+        let cases: Vec<(&str, &[PropertyChainElement], &str, &str)> = vec![
+            ("user", &[], "age", "$.user.age"),
+            (
+                "drivingTest.testDates.practical",
+                &[],
+                "center",
+                "$.drivingTest.testDates.practical.center",
+            ),
+            (
+                "driving test",
+                &chain,
+                "passed",
+                "$['driving test'].scores.theory.passed",
+            ),
+            ("person", &[], "date of birth", "$.person['date of birth']"),
+            ("person", &[], "it's", r"$.person['it\'s']"),
+            ("person", &[], r"back\slash", r"$.person['back\\slash']"),
+            ("user", &[], "2fa", "$.user['2fa']"),
+            ("user", &[], "snake_case_1", "$.user.snake_case_1"),
+            (
+                "candidate",
+                &first_attempt,
+                "status",
+                "$.candidate.attempts[0].status",
+            ),
+            (
+                "candidate",
+                &last_attempt,
+                "status",
+                "$.candidate.attempts[-1].status",
+            ),
+        ];
+
+        for (selector, chain, property, expected) in cases {
+            assert_eq!(render_json_path(selector, chain, property), expected);
+        }
+    }
+
+    #[test]
+    fn test_json_path_from_keys_keeps_dotted_keys_whole() {
+        // Literal JSON keys are never split, unlike DSL selectors
+        assert_eq!(
+            json_path_from_keys(&["config", "a.b", "c"]),
+            "$.config['a.b'].c"
+        );
+        assert_eq!(json_path_from_keys::<&str>(&[]), "$");
+    }
+
+    #[test]
+    fn test_project_paths_keeps_only_selected_values() {
+        let data = serde_json::json!({
+            "user": {"age": 30, "name": "Ann", "address": {"city": "Leeds", "zip": "LS1"}},
+            "orders": [{"total": 5}, {"total": 12}, {"total": 7}],
+            "it's": {"odd key": true, "other": false},
+        });
+        let paths = [
+            "$.user.age",
+            "$.user.address.city",
+            "$.orders[1].total",
+            "$['it\\'s']['odd key']",
+            "$.user.missing",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            project_paths(&data, &paths),
+            serde_json::json!({
+                "user": {"age": 30, "address": {"city": "Leeds"}},
+                "orders": [null, {"total": 12}],
+                "it's": {"odd key": true},
+            })
+        );
+        // A path covering another keeps the whole subtree
+        assert_eq!(
+            project_paths(&data, &["$.user.address.city".into(), "$.user".into()]),
+            serde_json::json!({"user": data["user"]})
+        );
+        assert_eq!(
+            project_paths(&data, &["$.nope".into()]),
+            serde_json::Value::Null
+        );
+        assert_eq!(
+            resolve_json_path(&data, "$.orders[2].total"),
+            Some(&serde_json::json!(7))
+        );
+        assert_eq!(resolve_json_path(&data, "user.age"), None);
+
+        // `[-1]` is the last element, kept in its place
+        assert_eq!(
+            resolve_json_path(&data, "$.orders[-1].total"),
+            Some(&serde_json::json!(7))
+        );
+        assert_eq!(
+            project_paths(&data, &["$.orders[-1].total".into()]),
+            serde_json::json!({"orders": [null, null, {"total": 7}]})
+        );
+    }
+
+    #[test]
+    fn test_set_json_path_patches_and_adds_keys() {
+        let mut data = serde_json::json!({"user": {"age": 30}, "orders": [{"total": 5}]});
+        set_json_path(&mut data, "$.user.age", serde_json::json!(31)).unwrap();
+        set_json_path(&mut data, "$.user.address.city", serde_json::json!("Leeds")).unwrap();
+        set_json_path(&mut data, "$.orders[1]", serde_json::json!({"total": 9})).unwrap();
+        assert_eq!(
+            data,
+            serde_json::json!({
+                "user": {"age": 31, "address": {"city": "Leeds"}},
+                "orders": [{"total": 5}, {"total": 9}],
+            })
+        );
+
+        for path in [
+            "$.orders[*].total",
+            "$.orders[-1].total",
+            "$.orders[5]",
+            "$.user.age.years",
+            "user",
+        ] {
+            assert!(set_json_path(&mut data, path, serde_json::json!(1)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_check_duplicate_keys_finds_repeats_at_any_depth() {
+        assert!(check_duplicate_keys(r#"{"age": 20, "Age": 30, "user": {"age": 1}}"#).is_empty());
+        assert_eq!(
+            check_duplicate_keys(r#"{"age": 20, "age": 30, "age": 40}"#),
+            vec!["/age"]
+        );
+        assert_eq!(
+            check_duplicate_keys(
+                r#"{"user": {"name": "Ann", "address": {"city": "Leeds", "city": "York"}, "name": "Bo"}}"#
+            ),
+            vec!["/user/address/city", "/user/name"]
+        );
+
+        // Elements are separate objects, and keys are escaped as RFC 6901 asks
+        assert_eq!(
+            check_duplicate_keys(
+                r#"{"orders": [{"id": 1}, {"id": 2, "lines": [[], {"a/b": 1, "a/b": 2, "~": 1, "~": 2}]}]}"#
+            ),
+            vec!["/orders/1/lines/1/a~1b", "/orders/1/lines/1/~0"]
+        );
+        assert_eq!(
+            check_duplicate_keys(r#"[{"x": true, "x": null}, 1.5, "s"]"#),
+            vec!["/0/x"]
+        );
+
+        // What precedes a syntax error is still reported
+        assert_eq!(
+            check_duplicate_keys(r#"{"a": 1, "a": 2, "b": "#),
+            vec!["/a"]
+        );
+        assert!(check_duplicate_keys("not json").is_empty());
+    }
+
+    #[test]
+    fn test_json_paths_overlap_when_one_holds_the_other() {
+        assert!(json_paths_overlap("$.user.age", "$.user"));
+        assert!(json_paths_overlap("$", "$.user.age"));
+        assert!(json_paths_overlap("$.user.birthDate", "$.user.birth_date"));
+        assert!(json_paths_overlap("$.orders[*].total", "$.orders[2]"));
+        assert!(!json_paths_overlap("$.user.age", "$.user.name"));
+        assert!(!json_paths_overlap("$.orders[1]", "$.orders[2].total"));
+        // Appending to a list changes its last element
+        assert!(json_paths_overlap("$.orders[-1].total", "$.orders[3]"));
+        assert!(!json_paths_overlap("$.orders[-1].total", "$.orders[3].id"));
+    }
 }
@@ -1,7 +1,28 @@
+pub mod compiled;
+pub mod conformance;
+pub mod debug;
+pub mod diff;
 pub mod error;
 pub mod evaluator;
+pub mod fingerprint;
+pub mod formatter;
+pub mod include;
 mod lib;
 pub mod model;
+pub mod options;
+pub mod outcome;
 pub mod parser;
+pub mod plan;
+pub mod projection;
+pub mod replay;
+pub mod schema;
+pub mod sensitivity;
+// Only its tests drive a session so far
+#[cfg(test)]
+pub mod session;
+pub mod stats;
 pub mod trace;
+pub mod usage;
 pub mod utils;
+pub mod validator;
+pub mod version;
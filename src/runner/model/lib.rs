@@ -1,12 +1,15 @@
 #[cfg(test)]
 mod tests {
     use crate::runner::model::{
-        ComparisonCondition, ComparisonOperator, Condition, ConditionGroup, ConditionOperator,
-        PositionedValue, PropertyChainElement, PropertyPath, Rule, RuleReferenceCondition, RuleSet,
-        RuleValue, SourcePosition,
+        ComparisonCondition, ComparisonOperator, Condition, ConditionGroup, ConditionId,
+        ConditionOperator, DateOffset, Duration, PositionedValue, PropertyChainElement,
+        PropertyPath, Rule, RuleReferenceCondition, RuleSet, RuleValue, SourcePosition, TimeUnit,
     };
     use chrono::NaiveDate;
 
+    // Parsed sets are shared between requests and worker threads behind an `Arc`
+    static_assertions::assert_impl_all!(RuleSet: Send, Sync);
+
     #[test]
     fn test_comparison_operator_display() {
         assert_eq!(
@@ -138,6 +141,10 @@ mod tests {
             property_chain: None,
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
         };
 
         rule.add_condition(
@@ -157,6 +164,10 @@ mod tests {
             property_chain: None,
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
         };
 
         rule.add_condition(
@@ -184,6 +195,8 @@ mod tests {
         let property_path = PropertyPath {
             properties: vec!["address".to_string(), "city".to_string()],
             selector: "user".to_string(),
+            fan_out: Vec::new(),
+            elements: Vec::new(),
         };
 
         assert_eq!(property_path.selector, "user");
@@ -245,6 +258,28 @@ mod tests {
         assert!(ruleset.get_rule_by_label("non_existent").is_none());
     }
 
+    #[test]
+    fn test_rule_index_survives_copies() {
+        let mut ruleset = RuleSet::new();
+        ruleset.add_rule(Rule::new(None, "user".to_string(), "adult".to_string()));
+        ruleset.add_rule(Rule::new(
+            Some("minor_check".to_string()),
+            "user".to_string(),
+            "minor".to_string(),
+        ));
+
+        let by_label = ruleset.get_rule_by_label("minor_check").unwrap();
+        assert_eq!(ruleset.index_of(by_label), Some(1));
+        assert_eq!(ruleset.index_of(&by_label.clone()), Some(1));
+        let outsider = Rule::new(None, "user".to_string(), "minor".to_string());
+        assert_eq!(ruleset.index_of(&outsider), None);
+
+        // A rule of another set isn't taken for the rule at its position here
+        let mut other = RuleSet::new();
+        other.add_rule(Rule::new(None, "user".to_string(), "senior".to_string()));
+        assert_eq!(ruleset.index_of(&other.rules[0]), None);
+    }
+
     #[test]
     fn test_condition_group() {
         let comparison_condition = ComparisonCondition {
@@ -255,11 +290,17 @@ mod tests {
             property_chain: None,
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
         };
 
         let condition_group = ConditionGroup {
             condition: Condition::Comparison(comparison_condition),
             operator: Some(ConditionOperator::Or),
+            id: ConditionId::from_ordinal("test", 1),
+            negated: false,
         };
 
         assert_eq!(condition_group.operator, Some(ConditionOperator::Or));
@@ -287,11 +328,15 @@ mod tests {
         let left_path = PropertyPath {
             properties: vec!["account".to_string(), "balance".to_string()],
             selector: "user".to_string(),
+            fan_out: Vec::new(),
+            elements: Vec::new(),
         };
 
         let right_path = PropertyPath {
             properties: vec!["limits".to_string(), "daily_max".to_string()],
             selector: "config".to_string(),
+            fan_out: Vec::new(),
+            elements: Vec::new(),
         };
 
         let property_chain = vec![
@@ -308,6 +353,10 @@ mod tests {
             property_chain: Some(property_chain),
             left_property_path: Some(left_path),
             right_property_path: Some(right_path),
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
         };
 
         assert_eq!(complex_condition.selector.value, "transaction");
@@ -402,6 +451,10 @@ mod tests {
             property_chain: None,
             left_property_path: None,
             right_property_path: None,
+            date_offset: None,
+            today: false,
+            left_expression: None,
+            right_expression: None,
         };
 
         rule.add_condition(Condition::Comparison(comparison), None);
@@ -433,4 +486,117 @@ mod tests {
         }
         assert_eq!(rule.conditions[1].operator, Some(ConditionOperator::And));
     }
+
+    #[test]
+    fn test_date_offsets_use_calendar_months() {
+        let date = |text: &str| NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap();
+        let offset = |subtract: bool, amount: f64, unit: TimeUnit| DateOffset {
+            subtract,
+            duration: Duration::new(amount, unit),
+        };
+
+        let cases = [
+            // Month ends clamp to the shorter month
+            (
+                "2024-01-31",
+                offset(false, 1.0, TimeUnit::Months),
+                "2024-02-29",
+            ),
+            (
+                "2023-01-31",
+                offset(false, 1.0, TimeUnit::Months),
+                "2023-02-28",
+            ),
+            (
+                "2024-03-31",
+                offset(true, 1.0, TimeUnit::Months),
+                "2024-02-29",
+            ),
+            (
+                "2024-08-31",
+                offset(false, 3.0, TimeUnit::Months),
+                "2024-11-30",
+            ),
+            // Leap days
+            (
+                "2024-02-29",
+                offset(false, 1.0, TimeUnit::Years),
+                "2025-02-28",
+            ),
+            (
+                "2024-02-29",
+                offset(false, 4.0, TimeUnit::Years),
+                "2028-02-29",
+            ),
+            (
+                "2024-02-28",
+                offset(false, 1.0, TimeUnit::Days),
+                "2024-02-29",
+            ),
+            (
+                "2023-02-28",
+                offset(false, 1.0, TimeUnit::Days),
+                "2023-03-01",
+            ),
+            (
+                "2024-03-01",
+                offset(true, 1.0, TimeUnit::Days),
+                "2024-02-29",
+            ),
+            (
+                "2008-12-12",
+                offset(false, 30.0, TimeUnit::Days),
+                "2009-01-11",
+            ),
+            (
+                "2008-12-12",
+                offset(true, 2.0, TimeUnit::Weeks),
+                "2008-11-28",
+            ),
+            (
+                "2000-02-29",
+                offset(false, 1.0, TimeUnit::Centuries),
+                "2100-02-28",
+            ),
+        ];
+        for (start, offset, expected) in cases {
+            assert_eq!(
+                offset.apply(date(start)),
+                Some(date(expected)),
+                "{} {}",
+                start,
+                offset
+            );
+        }
+
+        assert_eq!(
+            offset(false, 14.0, TimeUnit::Days).to_string(),
+            "plus 14 days"
+        );
+        assert_eq!(
+            offset(true, 1.0, TimeUnit::Months).to_string(),
+            "minus 1 month"
+        );
+        assert_eq!(
+            offset(false, 1.0, TimeUnit::Hours).apply(date("2024-01-01")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_durations_display_in_their_authored_unit() {
+        let cases = [
+            (Duration::new(2.0, TimeUnit::Years), "2 years"),
+            (Duration::new(1.0, TimeUnit::Days), "1 day"),
+            (Duration::new(30.0, TimeUnit::Days), "30 days"),
+            (Duration::new(1.5, TimeUnit::Hours), "1.5 hours"),
+        ];
+        for (duration, expected) in cases {
+            assert_eq!(duration.to_string(), expected);
+        }
+        assert_eq!(
+            Duration::new(2.0, TimeUnit::Years).to_seconds(),
+            63_113_904.0
+        );
+    }
 }
@@ -1,9 +1,76 @@
+//! The parsed form of a policy: rule sets, rules, conditions and their values.
+//!
+//! Each `ConditionGroup` gets a `ConditionId`, `<outcome slug>/<8 hex digits>` hashed from the
+//! formatter's canonical condition text, so it survives re-parsing and whitespace edits and changes
+//! when the condition does. Trace condition nodes and validator diagnostics carry it as `id` /
+//! `condition`.
+//!
+//! Outcomes are normalized by dropping one leading article ("the", "a", "an"), so `passes the test`
+//! is keyed as `test` in evaluation results. The phrase as written is kept in
+//! `Rule::outcome_display` and shown in traces, and references match either form.
+//!
+//! `A **driver** passes the eye test (also known as "has passed an eye test", "meets the vision
+//! requirement") if ...` gives the rule aliases (`Rule::aliases`, normalized like outcomes) that
+//! are registered in `rule_map`, so a reference written as any alias resolves exactly. An outcome
+//! takes precedence over an alias, and the first rule with an alias keeps it; the validator warns
+//! with `alias-collision` when an alias is another rule's outcome, label or earlier alias.
+//!
+//! Parentheses group conditions: `if A and (B or C).` is `Condition::Group(Vec<ConditionGroup>)` in
+//! the model (`condition_group` in `conditions.pest`), and groups nest. Inside a group `and` still
+//! binds tighter than `or`; `not (A or B)` negates the whole group. Each condition in a group gets
+//! its own id, unique across the rule; `Rule::all_conditions` walks groups and their members for
+//! the analyses (schema, usage, plan, stats, lint), and `ConditionTrace::leaves` /
+//! `RuleTrace::leaves` do the same on traces. `evaluate_conditions` in the evaluator runs a
+//! condition list recursively and `combine_results` folds it; only conditions that aren't groups
+//! spend the budget. The trace nests a `GroupTrace` with its members' traces, its `result` and
+//! `branch`, the ids of the first `or` branch that held. The CSV trace has rows only for the
+//! conditions in a group and the explanation descends into failed groups. A rule reference ends at
+//! `)` as well as `.`, and the formatter keeps a group on its condition's line.
+//!
+//! A comparison's right-hand side can be shifted with `plus` or `minus` a duration (`is earlier
+//! than 2008-12-12 plus 30 days`, `is later than the __invoice date__ of the **invoice** plus 14
+//! days`, `is later than today minus 14 days`), stored as `ComparisonCondition::date_offset`.
+//! `today` is the evaluation's `EvaluationOptions::today()` (`ComparisonCondition::today`) and is
+//! never read as a bare string. Only dates can be shifted, by whole days, weeks, months, years,
+//! decades or centuries: other literals and sub-day or fractional durations fail to parse, and a
+//! property that isn't a date is a type error. `DateOffset::apply` does calendar arithmetic,
+//! clamping to the end of shorter months (Jan 31 plus 1 month is Feb 28 or 29). A shifted literal
+//! is traced as the shifted date; a shifted property keeps its path and the shifted date is in
+//! `evaluation_details`.
+//!
+//! Either side of a comparison can do arithmetic with `plus`, `minus`, `multiplied by` and `divided
+//! by` on properties and numbers (`the __theory__ of the **exam** plus the __practical__ of the
+//! **exam** is at least the __pass mark__ of the **exam** multiplied by 2`), parsed into
+//! `ComparisonCondition::left_expression` and `right_expression` (`model::RuleExpression`). There
+//! is no precedence: steps are taken left to right, so `a plus b multiplied by c` is `(a plus b)
+//! multiplied by c`. A number followed by a duration unit is still a date offset. Arithmetic only
+//! goes with the number comparisons (`ComparisonOperator::compares_numbers`), and one on the left
+//! can't be compared with a literal other than a number; both are parse errors. Every operand must
+//! be a number, anything else is a `TypeError`; dividing by zero is `RuleError::DivisionByZero`
+//! (422) and a result that isn't finite is `NonFiniteNumber`. An operand the data doesn't have, or
+//! has as null, fails the comparison with the trace at that operand's path. The trace keeps each
+//! side's steps in `left_expression` and `right_expression` (`trace::ExpressionTrace`, every
+//! operand with its path and the running result), the left result as the property value with the
+//! expression as its path, and the right result as the value; their sources are `computed`. Schemas
+//! require every operand to be a number, and accessed paths and usage list each operand.
+//!
+//! `is at least 80% of the __total sessions__ of the **course**` compares with a percentage of a
+//! property, `RuleExpression::Percentage` in `right_expression` (`percentage_of` in
+//! `conditions.pest`). Only the operators comparing numbers
+//! (`ComparisonOperator::compares_numbers`) take it; any other fails to parse. The threshold is the
+//! property's value times the percentage over 100, so a value of 0 makes a threshold of 0 and
+//! percentages over 100 go; the property must be a number like any arithmetic operand. The trace's
+//! `right_expression` carries `percentage` (`PercentageTrace`: `percent` and `of`, the value as
+//! read) beside the threshold in `result`, and the value's source reads `computed as 80% of
+//! $.course.totalSessions`.
+
 mod lib;
 
-use chrono::NaiveDate;
-use serde::Serialize;
+use chrono::{Days, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::sync::RwLock;
 
@@ -11,7 +78,21 @@ use std::sync::RwLock;
 pub mod constants {
     pub const LENGTH_OF_MARKER: &str = "__length_of__";
     pub const NUMBER_OF_MARKER: &str = "__number_of__";
+    pub const HASH_BUCKET_MARKER: &str = "__hash_bucket__";
+    pub const SUM_OF_MARKER: &str = "__sum_of__";
+    pub const MINIMUM_OF_MARKER: &str = "__minimum_of__";
+    pub const MAXIMUM_OF_MARKER: &str = "__maximum_of__";
+    pub const AVERAGE_OF_MARKER: &str = "__average_of__";
+    /// The markers of `sum of`, `minimum of`, `maximum of` and `average of`
+    pub const AGGREGATE_MARKERS: [&str; 4] = [
+        SUM_OF_MARKER,
+        MINIMUM_OF_MARKER,
+        MAXIMUM_OF_MARKER,
+        AVERAGE_OF_MARKER,
+    ];
     pub const EMPTY_STRING: &str = "";
+    /// Reserved selector bound to the request context rather than the data
+    pub const CONTEXT_SELECTOR: &str = "context";
 }
 
 // Caching system for performance optimization
@@ -128,10 +209,6 @@ impl EfficientString {
     }
 
     #[allow(dead_code)]
-    pub fn as_str(&self) -> &str {
-        &self.0
-    }
-
     #[allow(dead_code)]
     pub fn into_string(self) -> String {
         self.0.into_owned()
@@ -156,7 +233,7 @@ impl From<String> for EfficientString {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ComparisonOperator {
     GreaterThanOrEqual,
     LessThanOrEqual,
@@ -175,6 +252,96 @@ pub enum ComparisonOperator {
     Within,
     OlderThan,
     YoungerThan,
+    /// `is not within`, the dates `is within` rejects, before or after the window
+    NotWithin,
+    /// `is more than 90 days ago`: dates before the duration counted back from today
+    MoreThanAgo,
+    /// `is at least 90 days ago`: that date or earlier
+    AtLeastAgo,
+    /// `is between`, against a `RuleValue::Range`, bounds included
+    Between,
+    /// `is strictly between`, bounds excluded
+    StrictlyBetween,
+}
+
+impl ComparisonOperator {
+    pub const ALL: [ComparisonOperator; 22] = [
+        ComparisonOperator::GreaterThanOrEqual,
+        ComparisonOperator::LessThanOrEqual,
+        ComparisonOperator::EqualTo,
+        ComparisonOperator::ExactlyEqualTo,
+        ComparisonOperator::NotEqualTo,
+        ComparisonOperator::LaterThan,
+        ComparisonOperator::EarlierThan,
+        ComparisonOperator::GreaterThan,
+        ComparisonOperator::LessThan,
+        ComparisonOperator::In,
+        ComparisonOperator::NotIn,
+        ComparisonOperator::Contains,
+        ComparisonOperator::IsEmpty,
+        ComparisonOperator::IsNotEmpty,
+        ComparisonOperator::Within,
+        ComparisonOperator::OlderThan,
+        ComparisonOperator::YoungerThan,
+        ComparisonOperator::NotWithin,
+        ComparisonOperator::MoreThanAgo,
+        ComparisonOperator::AtLeastAgo,
+        ComparisonOperator::Between,
+        ComparisonOperator::StrictlyBetween,
+    ];
+
+    /// Whether the outcome depends on the current date as well as the data
+    pub fn is_time_dependent(&self) -> bool {
+        matches!(
+            self,
+            ComparisonOperator::Within
+                | ComparisonOperator::OlderThan
+                | ComparisonOperator::YoungerThan
+                | ComparisonOperator::NotWithin
+                | ComparisonOperator::MoreThanAgo
+                | ComparisonOperator::AtLeastAgo
+        )
+    }
+
+    /// The word written after the right operand, `ago` in `is more than 90 days ago`
+    pub fn value_suffix(&self) -> Option<&'static str> {
+        match self {
+            ComparisonOperator::MoreThanAgo | ComparisonOperator::AtLeastAgo => Some("ago"),
+            _ => None,
+        }
+    }
+
+    /// The operators that can compare two numbers, as `80% of ...` needs
+    pub fn compares_numbers(&self) -> bool {
+        matches!(
+            self,
+            ComparisonOperator::GreaterThanOrEqual
+                | ComparisonOperator::LessThanOrEqual
+                | ComparisonOperator::GreaterThan
+                | ComparisonOperator::LessThan
+                | ComparisonOperator::EqualTo
+                | ComparisonOperator::ExactlyEqualTo
+                | ComparisonOperator::NotEqualTo
+        )
+    }
+
+    /// `is empty` and `is not empty`, which take no right operand
+    pub fn checks_emptiness(&self) -> bool {
+        matches!(
+            self,
+            ComparisonOperator::IsEmpty | ComparisonOperator::IsNotEmpty
+        )
+    }
+
+    /// Whether the left operand must be a single value rather than a list
+    pub fn compares_single_values(&self) -> bool {
+        !matches!(
+            self,
+            ComparisonOperator::Contains
+                | ComparisonOperator::IsEmpty
+                | ComparisonOperator::IsNotEmpty
+        )
+    }
 }
 
 impl fmt::Display for ComparisonOperator {
@@ -202,6 +369,12 @@ impl fmt::Display for ComparisonOperator {
             ComparisonOperator::Within => write!(f, "is within"),
             ComparisonOperator::OlderThan => write!(f, "is older than"),
             ComparisonOperator::YoungerThan => write!(f, "is younger than"),
+            ComparisonOperator::NotWithin => write!(f, "is not within"),
+            // Written before the duration, with `value_suffix` after it
+            ComparisonOperator::MoreThanAgo => write!(f, "is more than"),
+            ComparisonOperator::AtLeastAgo => write!(f, "is at least"),
+            ComparisonOperator::Between => write!(f, "is between"),
+            ComparisonOperator::StrictlyBetween => write!(f, "is strictly between"),
         }
     }
 }
@@ -231,17 +404,22 @@ impl ComparisonOperator {
             ComparisonOperator::Within => vec!["is within"],
             ComparisonOperator::OlderThan => vec!["is older than"],
             ComparisonOperator::YoungerThan => vec!["is younger than"],
+            ComparisonOperator::NotWithin => vec!["is not within"],
+            ComparisonOperator::MoreThanAgo => vec!["is more than"],
+            ComparisonOperator::AtLeastAgo => vec!["is at least"],
+            ComparisonOperator::Between => vec!["is between"],
+            ComparisonOperator::StrictlyBetween => vec!["is strictly between"],
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ConditionOperator {
     And,
     Or,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RuleValue {
     Number(f64),
     String(String),
@@ -249,15 +427,17 @@ pub enum RuleValue {
     Boolean(bool),
     List(Vec<RuleValue>),
     Duration(Duration),
+    /// The lower and upper bound of `is between`, both numbers or both dates
+    Range(Box<RuleValue>, Box<RuleValue>),
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Duration {
     pub amount: f64,
     pub unit: TimeUnit,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TimeUnit {
     Seconds,
     Minutes,
@@ -304,6 +484,57 @@ impl Duration {
     // }
 }
 
+/// `plus 14 days` or `minus 1 month` after the right-hand side of a comparison, applied to
+/// the date there before comparing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DateOffset {
+    pub subtract: bool,
+    /// A whole number of days or longer units, checked by the parser
+    pub duration: Duration,
+}
+
+impl DateOffset {
+    /// Calendar arithmetic: months and longer keep the day of the month, clamped to the end
+    /// of a shorter month, so Jan 31 plus 1 month is the last day of February and Feb 29
+    /// plus 1 year is Feb 28. `None` when the result is out of range
+    pub fn apply(&self, date: NaiveDate) -> Option<NaiveDate> {
+        let amount = self.duration.amount as u64;
+        match self.duration.unit {
+            TimeUnit::Days => self.shift_days(date, amount),
+            TimeUnit::Weeks => self.shift_days(date, amount * 7),
+            TimeUnit::Months => self.shift_months(date, amount),
+            TimeUnit::Years => self.shift_months(date, amount * 12),
+            TimeUnit::Decades => self.shift_months(date, amount * 120),
+            TimeUnit::Centuries => self.shift_months(date, amount * 1200),
+            TimeUnit::Seconds | TimeUnit::Minutes | TimeUnit::Hours => None,
+        }
+    }
+
+    fn shift_days(&self, date: NaiveDate, days: u64) -> Option<NaiveDate> {
+        if self.subtract {
+            date.checked_sub_days(Days::new(days))
+        } else {
+            date.checked_add_days(Days::new(days))
+        }
+    }
+
+    fn shift_months(&self, date: NaiveDate, months: u64) -> Option<NaiveDate> {
+        let months = Months::new(u32::try_from(months).ok()?);
+        if self.subtract {
+            date.checked_sub_months(months)
+        } else {
+            date.checked_add_months(months)
+        }
+    }
+}
+
+impl fmt::Display for DateOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.subtract { "minus" } else { "plus" };
+        write!(f, "{} {}", sign, self.duration)
+    }
+}
+
 impl fmt::Display for RuleValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -322,6 +553,7 @@ impl fmt::Display for RuleValue {
                 write!(f, "]")
             }
             RuleValue::Duration(d) => write!(f, "{}", d),
+            RuleValue::Range(low, high) => write!(f, "{} and {}", low, high),
         }
     }
 }
@@ -402,14 +634,21 @@ impl fmt::Display for Duration {
     }
 }
 
-#[derive(Debug, Clone)]
+// Built once per parse and read in place, so the size of comparisons doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Condition {
     Comparison(ComparisonCondition),
     RuleReference(RuleReferenceCondition),
+    Flag(FlagCondition),
+    Quantified(QuantifiedCondition),
+    /// `(A or B)`: conditions combined with each other before the rest of the rule, AND
+    /// binding tighter than OR within the parentheses too. Each has its own id and negation
+    Group(Vec<ConditionGroup>),
 }
 
 // Keep original structure but add support for property chains
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ComparisonCondition {
     pub selector: PositionedValue<String>,
     pub property: PositionedValue<String>,
@@ -420,30 +659,242 @@ pub struct ComparisonCondition {
     // Add support for cross-object comparisons
     pub left_property_path: Option<PropertyPath>,
     pub right_property_path: Option<PropertyPath>,
+    /// Shifts the date on the right-hand side, whether a literal or a property
+    pub date_offset: Option<DateOffset>,
+    /// The right-hand side is `today`, the evaluation's `EvaluationOptions::today`, which
+    /// `date_offset` shifts like a literal date. `value` then holds its text
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub today: bool,
+    /// The left-hand side when it does arithmetic, `left_property_path` then being its first
+    /// property
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left_expression: Option<RuleExpression>,
+    /// The right-hand side when it does arithmetic, in place of `right_property_path`;
+    /// `value` then holds its text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right_expression: Option<RuleExpression>,
 }
 
-#[derive(Debug, Clone)]
+impl ComparisonCondition {
+    /// Either side does arithmetic, so the comparison is of computed numbers
+    pub fn is_arithmetic(&self) -> bool {
+        self.left_expression.is_some() || self.right_expression.is_some()
+    }
+
+    /// Whether the outcome depends on the current date, through its operator or `today`
+    pub fn is_time_dependent(&self) -> bool {
+        self.operator.is_time_dependent() || self.today
+    }
+}
+
+/// A number worked out from properties and literals on one side of a comparison, as in
+/// `the __limit__ of the **customer** multiplied by 1.2`. There is no precedence: steps are
+/// taken left to right as written, so `a plus b multiplied by c` is `(a plus b) multiplied
+/// by c`, and the tree leans left
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleExpression {
+    Property(PropertyPath),
+    Number(f64),
+    Arithmetic {
+        left: Box<RuleExpression>,
+        operator: ArithmeticOperator,
+        right: Box<RuleExpression>,
+    },
+    /// `80% of the __total sessions__ of the **course**`: `percent` hundredths of what `of`
+    /// comes to. Any percentage goes, over 100 too
+    Percentage {
+        percent: f64,
+        of: Box<RuleExpression>,
+    },
+}
+
+impl RuleExpression {
+    /// `first` followed by `steps`, each applied to what came before it
+    pub fn chain(
+        first: RuleExpression,
+        steps: impl IntoIterator<Item = (ArithmeticOperator, RuleExpression)>,
+    ) -> Self {
+        steps.into_iter().fold(first, |left, (operator, right)| {
+            RuleExpression::Arithmetic {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            }
+        })
+    }
+
+    /// The properties the expression reads, in the order written
+    pub fn property_paths(&self) -> Vec<&PropertyPath> {
+        match self {
+            RuleExpression::Property(path) => vec![path],
+            RuleExpression::Number(_) => Vec::new(),
+            RuleExpression::Arithmetic { left, right, .. } => {
+                let mut paths = left.property_paths();
+                paths.extend(right.property_paths());
+                paths
+            }
+            RuleExpression::Percentage { of, .. } => of.property_paths(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArithmeticOperator {
+    Plus,
+    Minus,
+    MultipliedBy,
+    DividedBy,
+}
+
+impl ArithmeticOperator {
+    /// `None` for a division by zero
+    pub fn apply(self, left: f64, right: f64) -> Option<f64> {
+        match self {
+            ArithmeticOperator::Plus => Some(left + right),
+            ArithmeticOperator::Minus => Some(left - right),
+            ArithmeticOperator::MultipliedBy => Some(left * right),
+            ArithmeticOperator::DividedBy => (right != 0.0).then(|| left / right),
+        }
+    }
+}
+
+impl fmt::Display for ArithmeticOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithmeticOperator::Plus => write!(f, "plus"),
+            ArithmeticOperator::Minus => write!(f, "minus"),
+            ArithmeticOperator::MultipliedBy => write!(f, "multiplied by"),
+            ArithmeticOperator::DividedBy => write!(f, "divided by"),
+        }
+    }
+}
+
+/// `all of the __items__ of the **order** have a __price__ less than 100`: the comparison held
+/// by all, any or none of a list's elements. The comparison reads through the list with
+/// `each`, as `the __price__ of each __items__ of the **order**` would
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuantifiedCondition {
+    pub quantifier: Quantifier,
+    /// Its left path's only `each` marks the list
+    pub comparison: ComparisonCondition,
+}
+
+impl QuantifiedCondition {
+    /// The path of the list, `__items__ of the **order**`, and the keys read from each of its
+    /// elements, none when the elements are compared themselves
+    pub fn list_and_keys(&self) -> Option<(PropertyPath, &[String])> {
+        let path = self.comparison.left_property_path.as_ref()?;
+        let &split = path.fan_out.first()?;
+        let list = PropertyPath {
+            properties: path.properties[..=split].to_vec(),
+            selector: path.selector.clone(),
+            fan_out: Vec::new(),
+            elements: path.elements_until(split),
+        };
+        Some((list, &path.properties[split + 1..]))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quantifier {
+    /// Every element, so an empty list passes
+    All,
+    /// At least one element, so an empty list fails
+    Any,
+    /// No element, so an empty list passes
+    None,
+}
+
+impl fmt::Display for Quantifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quantifier::All => write!(f, "all"),
+            Quantifier::Any => write!(f, "any"),
+            Quantifier::None => write!(f, "none"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PropertyPath {
     pub properties: Vec<String>,
     pub selector: String,
+    /// Indices into `properties` marked with `each`: lists whose elements the rest of the
+    /// chain is read from, in traversal order
+    pub fan_out: Vec<usize>,
+    /// Indices into `properties` marked with `the first of`, `the 3rd of` or `the last of`,
+    /// each with the `Index` or `Last` step that picks one element of the list, in traversal
+    /// order. The element is picked before an `each` on the same list fans out over it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub elements: Vec<(usize, PropertyChainElement)>,
+}
+
+impl PropertyPath {
+    /// The element steps on `properties` up to and including `index`
+    pub fn elements_until(&self, index: usize) -> Vec<(usize, PropertyChainElement)> {
+        self.elements
+            .iter()
+            .filter(|(at, _)| *at <= index)
+            .cloned()
+            .collect()
+    }
 }
 
 // Simple enum for property chain elements
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PropertyChainElement {
     #[allow(dead_code)]
     Property(String),
     #[allow(dead_code)]
     Selector(String),
+    /// The element of a list at a zero-based index, `the 3rd of` for `Index(2)`
+    Index(usize),
+    /// The list's last element
+    Last,
 }
 
-#[derive(Debug, Clone)]
+impl PropertyChainElement {
+    /// How a rule writes the step: a key as its name, `Index(0)` as `1st` and `Last` as `last`
+    pub fn ordinal(&self) -> String {
+        match self {
+            PropertyChainElement::Property(name) | PropertyChainElement::Selector(name) => {
+                name.clone()
+            }
+            PropertyChainElement::Index(index) => {
+                let position = index + 1;
+                let suffix = match (position % 10, position % 100) {
+                    (_, 11..=13) => "th",
+                    (1, _) => "st",
+                    (2, _) => "nd",
+                    (3, _) => "rd",
+                    _ => "th",
+                };
+                format!("{}{}", position, suffix)
+            }
+            PropertyChainElement::Last => "last".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RuleReferenceCondition {
     pub selector: PositionedValue<String>,
     pub rule_name: PositionedValue<String>,
 }
 
-#[derive(Debug, Clone)]
+/// `flag "new-checkout" is enabled`: true when the feature flag is in the state named
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlagCondition {
+    /// The flag's name, without its quotes
+    pub flag: PositionedValue<String>,
+    /// True for `is enabled`, false for `is disabled`
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PositionedValue<T> {
     pub value: T,
     pub pos: Option<SourcePosition>,
@@ -490,20 +941,106 @@ impl From<RuleValue> for PositionedValue<RuleValue> {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConditionGroup {
     pub condition: Condition,
     pub operator: Option<ConditionOperator>, // None for the first condition, Some for subsequent ones
+    pub id: ConditionId,
+    /// Written `not ...`, or `does not ...` for a rule reference: the condition holds when
+    /// `condition` doesn't
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub negated: bool,
 }
 
-#[derive(Debug, Clone)]
+/// Stable name for one condition of a rule, shared by traces and diagnostics.
+///
+/// A parsed condition is `<outcome slug>/<hash>`, the hash being the first 8 hex digits of
+/// SHA-256 over the condition's canonical text (the formatter's form, so whitespace and
+/// comments don't count). Re-parsing unchanged text gives the same id and editing the
+/// condition gives a new one; moving it within the rule doesn't. A condition repeated in the
+/// same rule gets `-2`, `-3`... Conditions built in code have no text and are numbered by
+/// position instead, e.g. `senior-discount/1`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ConditionId(pub String);
+
+impl ConditionId {
+    pub fn from_text(outcome: &str, canonical_text: &str) -> Self {
+        let digest = Sha256::digest(canonical_text.as_bytes());
+        let hash: String = digest[..4]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        ConditionId(format!("{}/{}", slug(outcome), hash))
+    }
+
+    #[allow(dead_code)]
+    pub fn from_ordinal(outcome: &str, ordinal: usize) -> Self {
+        ConditionId(format!("{}/{}", slug(outcome), ordinal))
+    }
+
+    /// `from_text`, with `-2`, `-3`... added while `taken` already holds it
+    pub fn unused(outcome: &str, canonical_text: &str, taken: &[ConditionId]) -> Self {
+        let base = Self::from_text(outcome, canonical_text);
+        let mut id = base.clone();
+        let mut repeat = 1;
+        while taken.contains(&id) {
+            repeat += 1;
+            id = ConditionId(format!("{}-{}", base, repeat));
+        }
+        id
+    }
+}
+
+impl fmt::Display for ConditionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Lowercase ASCII letters and digits with runs of anything else turned into one `-`
+fn slug(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "rule".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rule {
     pub label: Option<String>,
     pub selector: String,
     pub selector_pos: Option<SourcePosition>,
+    /// Normalized outcome used as the results key, see `utils::normalize_outcome`
     pub outcome: String,
+    /// Outcome phrase as written in the rule, used for display and traces
+    pub outcome_display: String,
+    /// Other phrasings references can use for the outcome, from `(also known as "...")`,
+    /// normalized like `outcome`
+    pub aliases: Vec<PositionedValue<String>>,
     pub conditions: Vec<ConditionGroup>, // Changed from Vec<Condition>
     pub position: Option<SourcePosition>,
+    /// Name of the included document the rule was written in, `None` for a rule of the
+    /// document that was parsed. Positions are within that document
+    pub document: Option<String>,
+    /// Lowercased names from the `Tags: kyc, fraud.` line above the rule, see
+    /// `EvaluationOptions::excludes`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Position in the `RuleSet` the rule was added to, set by `RuleSet::add_rule`. Copies of
+    /// the rule keep it, so it identifies the rule however it was reached
+    #[serde(skip)]
+    pub index: Option<usize>,
 }
 
 impl Rule {
@@ -512,30 +1049,250 @@ impl Rule {
             label,
             selector,
             selector_pos: None,
+            outcome_display: outcome.clone(),
             outcome,
+            aliases: Vec::new(),
             conditions: Vec::new(),
             position: None,
+            document: None,
+            tags: Vec::new(),
+            index: None,
         }
     }
 
+    /// The label when the rule has one, otherwise the outcome as written
+    pub fn name(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.outcome_display)
+    }
+
+    #[allow(dead_code)]
     pub fn add_condition(&mut self, condition: Condition, operator: Option<ConditionOperator>) {
+        let id = ConditionId::from_ordinal(&self.outcome, self.conditions.len() + 1);
+        self.conditions.push(ConditionGroup {
+            condition,
+            operator,
+            id,
+            negated: false,
+        });
+    }
+
+    /// Adds a condition parsed from `canonical_text`, which its id is derived from. The
+    /// conditions of a group already have theirs, see `condition_ids`
+    pub fn add_parsed_condition(
+        &mut self,
+        condition: Condition,
+        operator: Option<ConditionOperator>,
+        negated: bool,
+        canonical_text: &str,
+    ) {
+        let mut taken = self.condition_ids();
+        if let Condition::Group(members) = &condition {
+            taken.extend(
+                members
+                    .iter()
+                    .flat_map(ConditionGroup::all)
+                    .map(|group| group.id.clone()),
+            );
+        }
+        let id = ConditionId::unused(&self.outcome, canonical_text, &taken);
         self.conditions.push(ConditionGroup {
             condition,
             operator,
+            id,
+            negated,
         });
     }
+
+    /// Every condition of the rule, each group followed by the conditions in it
+    pub fn all_conditions(&self) -> impl Iterator<Item = &ConditionGroup> {
+        self.conditions.iter().flat_map(ConditionGroup::all)
+    }
+
+    /// Ids of every condition of the rule, those in groups included, which a newly parsed
+    /// condition's id must not repeat
+    pub fn condition_ids(&self) -> Vec<ConditionId> {
+        self.all_conditions()
+            .map(|group| group.id.clone())
+            .collect()
+    }
 }
 
-#[derive(Debug, Default)]
+impl ConditionGroup {
+    /// This condition and, when it is a group, every condition in it, depth first
+    pub fn all(&self) -> Vec<&ConditionGroup> {
+        let mut all = vec![self];
+        if let Condition::Group(members) = &self.condition {
+            all.extend(members.iter().flat_map(ConditionGroup::all));
+        }
+        all
+    }
+}
+
+/// Serializes to its rules and documents, without the lookup maps and caches, which are
+/// rebuilt when deserializing, so a parsed set can be stored and loaded without parsing again
+#[derive(Debug, Default, Serialize)]
 pub struct RuleSet {
     pub rules: Vec<Rule>,
+    #[serde(skip)]
     pub(crate) rule_map: HashMap<String, usize>,
+    #[serde(skip)]
     pub(crate) label_map: HashMap<String, usize>,
+    #[serde(skip)]
     pub cache: PerformanceCache,
     // Maps custom object selectors to actual JSON paths
     // e.g., "driver" -> "person"
     #[allow(dead_code)]
     pub selector_mappings: HashMap<String, String>,
+    /// Rule text the set was parsed from, empty when built in code
+    pub source: String,
+    /// `Include "name".` statements not yet resolved, see `include::parse_rules_with_loader`
+    pub includes: Vec<PositionedValue<String>>,
+    /// Documents whose rules were merged in by their includes, in the order they were loaded
+    pub included: Vec<IncludedDocument>,
+    /// `Outcome tier is one of [...].` declarations of what values outcomes may take
+    pub outcome_schema: Vec<OutcomeDeclaration>,
+    /// `The **driving test** has a __scores__ object.` declarations of the data's shape, each
+    /// property with its whole path
+    pub property_catalogue: Vec<DeclaredProperty>,
+}
+
+/// The stored fields of a `RuleSet`
+#[derive(Deserialize)]
+struct StoredRuleSet {
+    rules: Vec<Rule>,
+    selector_mappings: HashMap<String, String>,
+    source: String,
+    includes: Vec<PositionedValue<String>>,
+    included: Vec<IncludedDocument>,
+    outcome_schema: Vec<OutcomeDeclaration>,
+    #[serde(default)]
+    property_catalogue: Vec<DeclaredProperty>,
+}
+
+impl<'de> Deserialize<'de> for RuleSet {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredRuleSet::deserialize(deserializer)?;
+        let mut rule_set = RuleSet::with_capacity(stored.rules.len());
+        for rule in stored.rules {
+            rule_set.add_rule(rule);
+        }
+        rule_set.selector_mappings = stored.selector_mappings;
+        rule_set.source = stored.source;
+        rule_set.includes = stored.includes;
+        rule_set.included = stored.included;
+        rule_set.outcome_schema = stored.outcome_schema;
+        rule_set.property_catalogue = stored.property_catalogue;
+        Ok(rule_set)
+    }
+}
+
+/// A document merged into a rule set by an `Include "name".` statement
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IncludedDocument {
+    /// The name as the loader resolved it
+    pub name: String,
+    pub source: String,
+}
+
+/// `Outcome risk score is a number between 0 and 100.`: the values an outcome may take, part
+/// of a policy's output contract
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutcomeDeclaration {
+    /// Normalized like `Rule::outcome`
+    pub outcome: String,
+    #[serde(flatten)]
+    pub kind: OutcomeType,
+    #[serde(skip)]
+    pub pos: Option<SourcePosition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutcomeType {
+    /// `is one of ["gold", "silver"]`, each value a string, number or boolean
+    OneOf { values: Vec<serde_json::Value> },
+    /// `is a number between 0 and 100`, both bounds included
+    Number { min: f64, max: f64 },
+    /// `is a boolean`, what every rule decides
+    Boolean,
+}
+
+impl OutcomeType {
+    /// Whether an outcome of this type can be `value`
+    pub fn admits(&self, value: &serde_json::Value) -> bool {
+        match self {
+            OutcomeType::OneOf { values } => values.iter().any(|allowed| {
+                allowed == value
+                    || allowed
+                        .as_f64()
+                        .is_some_and(|number| value.as_f64() == Some(number))
+            }),
+            OutcomeType::Number { min, max } => value
+                .as_f64()
+                .is_some_and(|number| *min <= number && number <= *max),
+            OutcomeType::Boolean => value.is_boolean(),
+        }
+    }
+}
+
+impl fmt::Display for OutcomeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutcomeType::OneOf { values } => {
+                let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                write!(f, "one of [{}]", values.join(", "))
+            }
+            OutcomeType::Number { min, max } => write!(f, "a number between {} and {}", min, max),
+            OutcomeType::Boolean => write!(f, "a boolean"),
+        }
+    }
+}
+
+/// A property the data is declared to hold, from `The **driving test** has a __scores__
+/// object.` or `The __scores__ has numeric __hazard perception__.`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeclaredProperty {
+    /// Selector and property names as written, outermost first, a nested selector split at
+    /// its dots
+    pub path: Vec<String>,
+    #[serde(rename = "type")]
+    pub kind: DeclaredType,
+    #[serde(skip)]
+    pub pos: Option<SourcePosition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeclaredType {
+    /// `text` or `string`
+    Text,
+    /// `numeric` or `number`
+    Number,
+    Boolean,
+    /// A string holding a date
+    Date,
+    List,
+    Object,
+}
+
+impl DeclaredType {
+    /// Whether properties can be declared under it: an object's, or its elements' for a list
+    pub fn has_properties(self) -> bool {
+        matches!(self, DeclaredType::List | DeclaredType::Object)
+    }
+}
+
+impl fmt::Display for DeclaredType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeclaredType::Text => write!(f, "text"),
+            DeclaredType::Number => write!(f, "a number"),
+            DeclaredType::Boolean => write!(f, "a boolean"),
+            DeclaredType::Date => write!(f, "a date"),
+            DeclaredType::List => write!(f, "a list"),
+            DeclaredType::Object => write!(f, "an object"),
+        }
+    }
 }
 
 impl RuleSet {
@@ -547,6 +1304,11 @@ impl RuleSet {
             label_map: HashMap::new(),
             cache: PerformanceCache::new(),
             selector_mappings: HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         }
     }
 
@@ -557,16 +1319,27 @@ impl RuleSet {
             label_map: HashMap::with_capacity(capacity),
             cache: PerformanceCache::new(),
             selector_mappings: HashMap::new(),
+            source: String::new(),
+            includes: Vec::new(),
+            included: Vec::new(),
+            outcome_schema: Vec::new(),
+            property_catalogue: Vec::new(),
         }
     }
 
-    pub fn add_rule(&mut self, rule: Rule) {
+    pub fn add_rule(&mut self, mut rule: Rule) {
         let index = self.rules.len();
+        rule.index = Some(index);
         if let Some(label) = &rule.label {
             self.label_map.insert(label.clone(), index);
         }
 
         self.rule_map.insert(rule.outcome.clone(), index);
+        // An outcome takes precedence over an alias, and the first rule over later ones
+        // with the same alias, see the validator's `alias-collision`
+        for alias in &rule.aliases {
+            self.rule_map.entry(alias.value.clone()).or_insert(index);
+        }
         self.rules.push(rule);
     }
 
@@ -583,14 +1356,75 @@ impl RuleSet {
         }
     }
 
+    /// Whether any condition compares with the current date, so the outcome can change from
+    /// one day to the next on the same data
+    pub fn is_time_dependent(&self) -> bool {
+        self.rules
+            .iter()
+            .flat_map(Rule::all_conditions)
+            .any(|group| match &group.condition {
+                Condition::Comparison(comparison)
+                | Condition::Quantified(QuantifiedCondition { comparison, .. }) => {
+                    comparison.is_time_dependent()
+                }
+                _ => false,
+            })
+    }
+
+    /// Looks a rule up by outcome, accepting the phrase with or without a leading article
     pub fn get_rule(&self, outcome: &str) -> Option<&Rule> {
-        self.rule_map.get(outcome).map(|&index| &self.rules[index])
+        self.rule_map
+            .get(outcome)
+            .or_else(|| {
+                self.rule_map
+                    .get(&crate::runner::utils::normalize_outcome(outcome))
+            })
+            .map(|&index| &self.rules[index])
+    }
+
+    /// The declared type of an outcome, accepting the phrase with or without a leading article
+    pub fn outcome_declaration(&self, outcome: &str) -> Option<&OutcomeDeclaration> {
+        let normalized = crate::runner::utils::normalize_outcome(outcome);
+        self.outcome_schema
+            .iter()
+            .find(|declaration| declaration.outcome == normalized)
+    }
+
+    /// The catalogue's declaration of `path`, names matching in any spelling
+    pub fn declared_property(&self, path: &[String]) -> Option<&DeclaredProperty> {
+        self.property_catalogue
+            .iter()
+            .find(|declared| crate::runner::utils::paths_match(&declared.path, path))
+    }
+
+    /// Every tag the rules carry, with the outcomes of the rules carrying it in rule order
+    pub fn tag_inventory(&self) -> BTreeMap<String, Vec<String>> {
+        let mut inventory: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for rule in &self.rules {
+            for tag in &rule.tags {
+                inventory
+                    .entry(tag.clone())
+                    .or_default()
+                    .push(rule.outcome.clone());
+            }
+        }
+        inventory
     }
 
     pub fn get_rule_by_label(&self, label: &str) -> Option<&Rule> {
         self.label_map.get(label).map(|&index| &self.rules[index])
     }
 
+    /// Position of `rule` in `rules`, the rule's identity however it was looked up, see
+    /// `Rule::index`. `None` for a rule that was never added to this set
+    pub fn index_of(&self, rule: &Rule) -> Option<usize> {
+        rule.index.filter(|&index| {
+            self.rules
+                .get(index)
+                .is_some_and(|own| own.index == Some(index) && own.outcome == rule.outcome)
+        })
+    }
+
     /// Add a mapping from a custom selector to an actual JSON path
     /// e.g., map_selector("driver", "person") allows **driver** to reference the "person" object
     #[allow(dead_code)]
@@ -609,7 +1443,7 @@ impl RuleSet {
     }
 }
 
-#[derive(Debug, Serialize, Clone, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct SourcePosition {
     pub line: usize,
     pub start: usize,
@@ -0,0 +1,31 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::error::RuleError;
+    use crate::runner::options::EvaluationOptions;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_default_options_are_never_cancelled() {
+        let options = EvaluationOptions::default();
+        assert!(!options.is_cancelled());
+        assert!(options.check_cancelled().is_ok());
+    }
+
+    #[test]
+    fn test_cancellation_flag_is_shared() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let options = EvaluationOptions::new().with_cancellation(flag.clone());
+        let cloned = options.clone();
+
+        assert!(!options.is_cancelled());
+        flag.store(true, Ordering::Relaxed);
+
+        assert!(options.is_cancelled());
+        assert!(cloned.is_cancelled());
+        assert!(matches!(
+            options.check_cancelled(),
+            Err(RuleError::Cancelled)
+        ));
+    }
+}
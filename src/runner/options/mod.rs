@@ -0,0 +1,475 @@
+//! Per-call options of an evaluation.
+//!
+//! A list on the left of an operator that compares single values (everything but `contains`, `is
+//! empty` and `is not empty`) follows `"list_scalar_mode"`. The default, `"error"`, fails with
+//! `RuleError::ListComparedWithScalar` naming the path. `"any_element"` passes when some element
+//! passes, and `"all_elements"` when every element does; an empty list passes neither. Elements
+//! that can't be compared count as failing. The trace's `evaluation_details` record the `list_mode`
+//! and, when a single element settled it, its index as `deciding_element`.
+//!
+//! `"strict_references": true` (`EvaluationOptions::strict_references`, on `POST /`,
+//! `/policies/{id}/evaluate` and `/evaluate-change`, and in replay bundles and conformance cases)
+//! refuses to evaluate a policy with a reference that resolves to no rule, so a misspelt rule name
+//! can't quietly read as a data property or pass as free text. `RuleSet::unresolved_references`
+//! finds them with `resolve_rule`, so outcomes, aliases, labels and partial outcome matches all
+//! count as resolved, and `RuleSet::validate_references` fails with
+//! `RuleError::UnresolvedReferences` listing each one's `rule_name`, the outcome of the `rule` it
+//! is in, its condition `id` and `position`. Responses answer 422 with them as
+//! `unresolved_references`. A dry run fails the same way and lists them either way. The default
+//! stays lenient.
+//!
+//! A `Tags: kyc, fraud.` line right before a rule tags it (`Rule::tags`, lowercased; there is no
+//! `#kyc` form since `#` starts a comment, and a `kyc, fraud.` prefix would read as a label).
+//! `EvaluationOptions::include_tags` and `exclude_tags` (`"include_tags"` and `"exclude_tags"` on
+//! `POST /`, `/policies/{id}/evaluate`, `/evaluate-change` and in replay bundles) skip rules, see
+//! `EvaluationOptions::excludes`: an excluded tag always skips, and with `include_tags` a tagged
+//! rule without any of them is skipped too, while untagged rules always run. A skipped rule isn't
+//! evaluated, has no entry in the results or labels, and is traced with `skipped: true`. A
+//! reference to it is traced `skipped` and left out of its rule's `and` run; a run left empty drops
+//! out of the `or`, and a rule with no condition left is skipped in turn. A skipped global rule or
+//! entry makes the result false, as a missing one does. Tags show on each rule of `POST /plan`, on
+//! the diagnostics of the rule they belong to, and in `GET /policies/{id}` as `tags`, each with the
+//! outcomes carrying it (`RuleSet::tag_inventory`).
+//!
+//! `flag "new-checkout" is enabled` (or `is disabled`) is a condition on a feature flag. The
+//! evaluator asks `EvaluationOptions::flags`, a `FlagProvider` (`is_enabled(name) ->
+//! Option<bool>`), once per flag per evaluation and records the state in a `FlagTrace` (`flag`,
+//! `expected`, `enabled`, `known`, `result`). A flag the provider doesn't know, or any flag without
+//! a provider, takes `unknown_flag_default` (false) and adds a warning. Every front end builds its
+//! options with `service_options()` in `main.rs`, which reads flags from the flags service through
+//! `ServiceFlags`, blocking on the client from the evaluation thread; the unknown default is
+//! `flags.unknown_default` (`FF_UNKNOWN_DEFAULT`). Replay bundles don't record flag states.
+//!
+//! Each evaluation has a work budget, `EvaluationOptions::max_condition_evaluations`
+//! (`DEFAULT_MAX_CONDITION_EVALUATIONS`, one million). `EvaluationContext::condition_evaluations`
+//! counts every condition evaluated across the rules the evaluation reaches; a memoized rule counts
+//! once. Going over fails with `RuleError::BudgetExceeded`, naming the count and the rule whose
+//! condition tripped it, with the partial trace. `EvaluationOutcome::evaluations_used` carries the
+//! count, failed or not, and responses return it as `evaluations_used` for capacity planning. The
+//! server sets the budget from `limits.max_condition_evaluations` (`MAX_CONDITION_EVALUATIONS`) for
+//! `/`, `/evaluate-change`, stored policies, batch and gRPC, and answers 422 when it is exceeded.
+//!
+//! Rule references nest at most `EvaluationOptions::max_reference_depth`
+//! (`DEFAULT_MAX_REFERENCE_DEPTH`, 32) deep, counting the rule evaluation starts from, which keeps
+//! even a debug build within a 2 MiB thread stack. Going deeper fails with
+//! `RuleError::MaxDepthExceeded`, and a rule reached again through its own references with
+//! `RuleError::InfiniteLoop`; both carry `call_path`, the rules being evaluated outermost first,
+//! which responses return as `call_path`. Messages cut a path longer than `max_call_path_frames`
+//! (`DEFAULT_MAX_CALL_PATH_FRAMES`, 10) in the middle (`a -> b -> … 47 more frames … -> y -> z`,
+//! see `describe_call_path`). The failing rule's trace records the error in `error`, with the whole
+//! path when `trace_verbosity` is verbose. Too deep references answer 422, loops 400.
+//!
+//! `within`, `not within`, `older than`, `younger than` and the `ago` forms count from today unless
+//! the request sets `"as_of": "2025-01-15"` (`EvaluationOptions::reference_date`, also taken by
+//! `/condition`), so a decision can be reproduced later. The comparison's `evaluation_details`
+//! record the date used as `reference_date`, and replay bundles record it even when the request
+//! left it out. When the policy reads the date (`RuleSet::is_time_dependent`) the decision
+//! fingerprint hashes the date used too; other policies keep their fingerprint from day to day.
+//!
+//! `"legacy_flat_data"` keeps callers that send a policy's properties without their selector
+//! working, such as the testcontainers example posting `{"age": 70}` for rules reading the `age` of
+//! the **Person**. `schema::flat_data_selector` picks the selector to read such data under: the
+//! rule set must have exactly one root selector, without a `selector_mappings` entry, and the data
+//! must be an object holding none of its selectors. `"auto"`, the request default for this release,
+//! also needs the data to hold at least one property the rules read of that selector; `true`
+//! doesn't, and `false` evaluates the data as sent. The evaluator then evaluates `{"<selector>":
+//! data}` under any `data_root`, so traced paths include the selector, and adds a warning with code
+//! `legacy_flat_data` (`outcome::LEGACY_FLAT_DATA`). The disjoint data guard sees the wrapped data,
+//! and so does a dry run. `POST /` and `/policies/{id}/evaluate` count these evaluations per tenant
+//! in `engine_legacy_flat_data_total`, listed once there is one, so the callers can be found and
+//! fixed. `/evaluate-change` and gRPC leave it off. Replay bundles record the setting; bundles
+//! without it replay with it off. In the library it is `EvaluationOptions::legacy_flat_data`
+//! (`LegacyFlatData`), off by default.
+//!
+//! Facts about the request rather than the subject (channel, region, time of day) go in a top-level
+//! `"context"` object, also accepted by `/condition` and `/policies/{id}/evaluate`. Rules read it
+//! through the reserved `**context**` selector (`the __channel__ of the **context** is equal to
+//! "web"`), and traces show those paths as `$context.channel`. A `context` key inside `data` is
+//! never read in its place, and without a request context such conditions follow the missing-data
+//! rules. The context is part of the decision fingerprint and the replay bundle, but not of data
+//! validation or the echoed data.
+
+mod lib;
+
+use crate::runner::debug::DebugHook;
+use crate::runner::error::RuleError;
+use crate::runner::model::Rule;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How an operator that compares single values treats a property holding a list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListScalarMode {
+    /// Fail with `RuleError::ListComparedWithScalar`, naming the path
+    #[default]
+    Error,
+    /// Pass when any element passes
+    AnyElement,
+    /// Pass when every element passes. An empty list doesn't
+    AllElements,
+}
+
+/// How much a trace explains beyond what each condition decided
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceVerbosity {
+    #[default]
+    Normal,
+    /// Also record on each property that wasn't found what the lookup tried, see
+    /// `RuleSetTrace::explain_missed_keys`
+    Verbose,
+}
+
+/// Whether to read a payload that has the properties of the rule set's only root selector at
+/// its top level, `{"age": 70}` for rules reading the `age` of the **Person**, as if it were
+/// wrapped under the selector, see `schema::flat_data_selector`. Set as `true`, `false` or
+/// `"auto"`. A compatibility mode for callers written before the disjoint data guard, which
+/// evaluations it applies to warn about with `LEGACY_FLAT_DATA`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LegacyFlatData {
+    /// Evaluate the payload as sent
+    #[default]
+    Off,
+    /// Wrap a payload holding none of the rule set's selectors
+    On,
+    /// Wrap a payload holding none of the rule set's selectors but some of the properties
+    /// the rules read of the one selector
+    Auto,
+}
+
+impl LegacyFlatData {
+    /// What a request that doesn't set it gets, for the release that introduces it
+    pub fn request_default() -> Self {
+        LegacyFlatData::Auto
+    }
+}
+
+impl Serialize for LegacyFlatData {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            LegacyFlatData::Off => serializer.serialize_bool(false),
+            LegacyFlatData::On => serializer.serialize_bool(true),
+            LegacyFlatData::Auto => serializer.serialize_str("auto"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LegacyFlatData {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Setting {
+            Switch(bool),
+            Named(String),
+        }
+
+        match Setting::deserialize(deserializer)? {
+            Setting::Switch(false) => Ok(LegacyFlatData::Off),
+            Setting::Switch(true) => Ok(LegacyFlatData::On),
+            Setting::Named(name) if name == "auto" => Ok(LegacyFlatData::Auto),
+            Setting::Named(name) => Err(serde::de::Error::custom(format!(
+                "expected true, false or \"auto\", not \"{}\"",
+                name
+            ))),
+        }
+    }
+}
+
+/// Where `flag "name" is enabled` conditions read feature flag states. The server backs it
+/// with its flags client; tests and other embedders can supply their own
+pub trait FlagProvider: fmt::Debug + Send + Sync {
+    /// Whether the flag is on, `None` when the provider doesn't know it
+    fn is_enabled(&self, flag: &str) -> Option<bool>;
+}
+
+/// Default of `EvaluationOptions::max_condition_evaluations`, far above what a reasonable
+/// policy needs for one evaluation
+pub const DEFAULT_MAX_CONDITION_EVALUATIONS: usize = 1_000_000;
+
+/// Default of `EvaluationOptions::max_data_depth`, deeper than any document a policy
+/// should need to read
+pub const DEFAULT_MAX_DATA_DEPTH: usize = 64;
+
+/// Default of `EvaluationOptions::max_scanned_keys`
+pub const DEFAULT_MAX_SCANNED_KEYS: usize = 10_000;
+
+/// Default of `EvaluationOptions::max_reference_depth`. Each level takes tens of KiB of stack
+/// in a debug build, so this stays well within a 2 MiB thread
+pub const DEFAULT_MAX_REFERENCE_DEPTH: usize = 32;
+
+/// Default of `EvaluationOptions::max_call_path_frames`
+pub const DEFAULT_MAX_CALL_PATH_FRAMES: usize = 10;
+
+/// Per-call knobs for an evaluation that are not part of the rule text or the data
+#[derive(Debug, Clone)]
+pub struct EvaluationOptions {
+    /// Shared flag the caller can set from another thread to stop the evaluation early
+    pub cancellation: Option<Arc<AtomicBool>>,
+    /// Outcomes or labels to start from instead of the inferred global rule
+    pub entries: Vec<String>,
+    /// Count null as 0 for `length of` / `number of` rather than treating it as missing
+    pub lenient: bool,
+    /// Reject data that doesn't have the paths and types the rules read before evaluating,
+    /// and outcomes their `Outcome ... is ...` declarations don't admit after
+    pub validate_input: bool,
+    /// Fail with `RuleError::DisjointData` rather than evaluate data holding none of the
+    /// selectors the rules read, see `schema::disjoint_data`
+    pub reject_disjoint_data: bool,
+    /// Fail with `RuleError::UnresolvedReferences` before evaluating when a rule reference
+    /// resolves to no rule, see `RuleSet::validate_references`, rather than read it from the
+    /// data or let it pass as free text
+    pub strict_references: bool,
+    /// Whether a payload holding its properties at the top level is read as if wrapped under
+    /// the rule set's one root selector
+    pub legacy_flat_data: LegacyFlatData,
+    /// Request context read through the reserved `**context**` selector, kept apart from the
+    /// data. Null when there is none
+    pub context: Value,
+    /// What single-value comparisons do with a list
+    pub list_scalar_mode: ListScalarMode,
+    pub trace_verbosity: TraceVerbosity,
+    /// Read a `{"min": x, "max": y}` object on the right of a comparison as a numeric range
+    /// rather than a value, see `evaluator::NumericRange`
+    pub range_objects: bool,
+    /// Leave strings shaped like versions, `2.14.3`, as strings rather than order them as
+    /// versions, see `evaluator::Version`. Ordering operators don't compare strings
+    pub versions_as_strings: bool,
+    /// Compare strings minding case with `is equal to`, `is not equal to`, `is in`,
+    /// `is not in` and `contains`, as `is exactly equal to` always does. Traces of those
+    /// comparisons record the mode
+    pub case_sensitive_strings: bool,
+    /// The date `is within`, `is older than` and `is younger than` count from, today in UTC
+    /// when unset. Pinning it makes evaluations of those conditions reproducible
+    pub reference_date: Option<NaiveDate>,
+    /// JSONPath of the object within the data that selectors are looked up from, such as
+    /// `$.envelope.payload`. Traced paths still start at the top of the data
+    pub data_root: Option<String>,
+    /// Feature flag states for `flag "name" is enabled` conditions. Each flag is asked for
+    /// once per evaluation
+    pub flags: Option<Arc<dyn FlagProvider>>,
+    /// State taken for a flag the provider doesn't know, or for every flag without a
+    /// provider. The condition's trace carries a warning when it is used
+    pub unknown_flag_default: bool,
+    /// Conditions one evaluation may evaluate, across every rule it reaches, before it fails
+    /// with `RuleError::BudgetExceeded`. A memoized rule's conditions count once
+    pub max_condition_evaluations: usize,
+    /// How deep into the data a property path may read, counting the keys it walks and the
+    /// nesting of the value it reaches. Reading deeper fails with `RuleError::DataTooDeep`
+    pub max_data_depth: usize,
+    /// Largest object searched key by key for a differently written name. Larger objects
+    /// only match a name as written or camelCased, with a warning when neither is found
+    pub max_scanned_keys: usize,
+    /// How deep rule references may nest, counting the rule evaluation starts from. Going
+    /// deeper fails with `RuleError::MaxDepthExceeded`
+    pub max_reference_depth: usize,
+    /// Most rules of a call path written into an error message or trace. Longer paths are
+    /// cut in the middle, except in a verbose trace, and the error keeps the whole path
+    pub max_call_path_frames: usize,
+    /// Tags a tagged rule needs one of to be evaluated. Empty lets every rule through;
+    /// untagged rules are never filtered out. See `excludes`
+    pub include_tags: Vec<String>,
+    /// Tags that skip every rule carrying one, over `include_tags`
+    pub exclude_tags: Vec<String>,
+    /// Called as each rule starts and each of its conditions is evaluated, to step through
+    /// the evaluation. Without one the evaluator makes no calls
+    pub debug_hook: Option<Arc<dyn DebugHook>>,
+}
+
+impl Default for EvaluationOptions {
+    fn default() -> Self {
+        Self {
+            cancellation: None,
+            entries: Vec::new(),
+            lenient: false,
+            validate_input: false,
+            reject_disjoint_data: false,
+            strict_references: false,
+            legacy_flat_data: LegacyFlatData::Off,
+            context: Value::Null,
+            list_scalar_mode: ListScalarMode::default(),
+            trace_verbosity: TraceVerbosity::default(),
+            range_objects: false,
+            versions_as_strings: false,
+            case_sensitive_strings: false,
+            reference_date: None,
+            data_root: None,
+            flags: None,
+            unknown_flag_default: false,
+            max_condition_evaluations: DEFAULT_MAX_CONDITION_EVALUATIONS,
+            max_data_depth: DEFAULT_MAX_DATA_DEPTH,
+            max_scanned_keys: DEFAULT_MAX_SCANNED_KEYS,
+            max_reference_depth: DEFAULT_MAX_REFERENCE_DEPTH,
+            max_call_path_frames: DEFAULT_MAX_CALL_PATH_FRAMES,
+            include_tags: Vec::new(),
+            exclude_tags: Vec::new(),
+            debug_hook: None,
+        }
+    }
+}
+
+impl EvaluationOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_cancellation(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancellation = Some(flag);
+        self
+    }
+
+    pub fn with_entries(mut self, entries: Vec<String>) -> Self {
+        self.entries = entries;
+        self
+    }
+
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    pub fn with_validate_input(mut self, validate_input: bool) -> Self {
+        self.validate_input = validate_input;
+        self
+    }
+
+    pub fn with_reject_disjoint_data(mut self, reject_disjoint_data: bool) -> Self {
+        self.reject_disjoint_data = reject_disjoint_data;
+        self
+    }
+
+    pub fn with_strict_references(mut self, strict_references: bool) -> Self {
+        self.strict_references = strict_references;
+        self
+    }
+
+    pub fn with_legacy_flat_data(mut self, legacy_flat_data: LegacyFlatData) -> Self {
+        self.legacy_flat_data = legacy_flat_data;
+        self
+    }
+
+    pub fn with_context(mut self, context: Value) -> Self {
+        self.context = context;
+        self
+    }
+
+    pub fn with_list_scalar_mode(mut self, mode: ListScalarMode) -> Self {
+        self.list_scalar_mode = mode;
+        self
+    }
+
+    pub fn with_trace_verbosity(mut self, verbosity: TraceVerbosity) -> Self {
+        self.trace_verbosity = verbosity;
+        self
+    }
+
+    pub fn with_range_objects(mut self, range_objects: bool) -> Self {
+        self.range_objects = range_objects;
+        self
+    }
+
+    pub fn with_versions_as_strings(mut self, versions_as_strings: bool) -> Self {
+        self.versions_as_strings = versions_as_strings;
+        self
+    }
+
+    pub fn with_case_sensitive_strings(mut self, case_sensitive_strings: bool) -> Self {
+        self.case_sensitive_strings = case_sensitive_strings;
+        self
+    }
+
+    pub fn with_reference_date(mut self, reference_date: Option<NaiveDate>) -> Self {
+        self.reference_date = reference_date;
+        self
+    }
+
+    pub fn with_data_root(mut self, data_root: Option<String>) -> Self {
+        self.data_root = data_root;
+        self
+    }
+
+    pub fn with_flags(mut self, flags: Arc<dyn FlagProvider>) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+
+    pub fn with_unknown_flag_default(mut self, enabled: bool) -> Self {
+        self.unknown_flag_default = enabled;
+        self
+    }
+
+    pub fn with_max_condition_evaluations(mut self, max: usize) -> Self {
+        self.max_condition_evaluations = max;
+        self
+    }
+
+    pub fn with_max_data_depth(mut self, max: usize) -> Self {
+        self.max_data_depth = max;
+        self
+    }
+
+    pub fn with_max_scanned_keys(mut self, max: usize) -> Self {
+        self.max_scanned_keys = max;
+        self
+    }
+
+    pub fn with_max_reference_depth(mut self, max: usize) -> Self {
+        self.max_reference_depth = max;
+        self
+    }
+
+    pub fn with_max_call_path_frames(mut self, max: usize) -> Self {
+        self.max_call_path_frames = max;
+        self
+    }
+
+    pub fn with_include_tags(mut self, tags: Vec<String>) -> Self {
+        self.include_tags = tags;
+        self
+    }
+
+    pub fn with_exclude_tags(mut self, tags: Vec<String>) -> Self {
+        self.exclude_tags = tags;
+        self
+    }
+
+    /// Whether the tag filters skip `rule`: it has an excluded tag, or tags that aren't among
+    /// the included ones. Tags compare ignoring case. A skipped rule isn't evaluated, a
+    /// reference to it is left out of its rule's conditions, and it has no outcome
+    pub fn excludes(&self, rule: &Rule) -> bool {
+        let listed = |tags: &[String]| {
+            rule.tags
+                .iter()
+                .any(|tag| tags.iter().any(|listed| listed.eq_ignore_ascii_case(tag)))
+        };
+        listed(&self.exclude_tags)
+            || (!self.include_tags.is_empty()
+                && !rule.tags.is_empty()
+                && !listed(&self.include_tags))
+    }
+
+    /// `reference_date`, or today in UTC without one
+    pub fn today(&self) -> NaiveDate {
+        self.reference_date
+            .unwrap_or_else(|| chrono::Utc::now().date_naive())
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+
+    /// Returns `RuleError::Cancelled` once the cancellation flag has been set
+    pub fn check_cancelled(&self) -> Result<(), RuleError> {
+        if self.is_cancelled() {
+            Err(RuleError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
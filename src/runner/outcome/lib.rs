@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::error::RuleError;
+    use crate::runner::evaluator::evaluate;
+    use crate::runner::options::EvaluationOptions;
+    use crate::runner::outcome::EvalStats;
+    use crate::runner::parser::{parse_rule_set, parse_rules};
+    use serde_json::json;
+
+    const LICENCE: &str = r#"
+A **driver** gets a driving licence
+  if the **driver** passes the age test
+  and §eyes.ok is valid.
+
+A **driver** passes the age test
+  if the __age__ of the **driver** is greater than or equal to 18.
+
+eyes.ok. A **driver** has good eyesight
+  if the __vision__ of the **driver** is greater than 0.5.
+"#;
+
+    #[test]
+    fn test_outcome_collects_results_labels_and_trace() {
+        let rule_set = parse_rules(LICENCE).unwrap();
+        let data = json!({"driver": {"age": 30, "vision": 0.9}});
+
+        let outcome = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        assert!(outcome.error.is_none());
+        assert!(outcome.result);
+        // Rules are listed in the order they ran: the global rule, then its references
+        let outcomes: Vec<_> = outcome
+            .outcomes
+            .iter()
+            .map(|(k, v)| (k.as_str(), *v))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("driving licence", true),
+                ("age test", true),
+                ("good eyesight", true),
+            ]
+        );
+        assert_eq!(outcome.labels.get("eyes.ok"), Some(&true));
+        assert_eq!(outcome.labels.len(), 1);
+        assert!(outcome.warnings.is_empty());
+        assert_eq!(outcome.trace.unwrap().execution.len(), 3);
+    }
+
+    #[test]
+    fn test_outcome_keeps_the_trace_on_failure() {
+        let rule_set = parse_rules(LICENCE).unwrap();
+        // One reading per eye, where the rule expects a single value
+        let data = json!({"driver": {"age": 30, "vision": [0.9, 0.4]}});
+
+        let outcome = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        assert!(!outcome.result);
+        assert!(matches!(
+            outcome.error,
+            Some(RuleError::ListComparedWithScalar { .. })
+        ));
+        assert!(!outcome.trace.unwrap().execution.is_empty());
+    }
+
+    #[test]
+    fn test_outcome_for_entries_and_ambiguous_sets() {
+        let rule_set = parse_rule_set(
+            "A **user** is adult if the __age__ of the **user** is greater than 17.\n\n\
+             A **user** is senior if the __age__ of the **user** is greater than 64.",
+        )
+        .unwrap();
+        let data = json!({"user": {"age": 40}});
+
+        let outcome = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        assert!(matches!(
+            outcome.error,
+            Some(RuleError::MultipleGlobalRules { .. })
+        ));
+        assert!(!outcome.result);
+
+        let adult = EvaluationOptions::new().with_entries(vec!["adult".to_string()]);
+        assert!(evaluate(&rule_set, &data, &adult).result);
+        let both =
+            EvaluationOptions::new().with_entries(vec!["adult".to_string(), "senior".to_string()]);
+        let outcome = evaluate(&rule_set, &data, &both);
+        assert!(!outcome.result);
+        assert_eq!(outcome.outcomes.get("adult"), Some(&true));
+        assert_eq!(outcome.outcomes.get("senior"), Some(&false));
+    }
+
+    #[test]
+    fn test_outcome_lists_lenient_warnings() {
+        let huge = "9".repeat(400);
+        let rule_set = parse_rules(&format!(
+            "A **user** is big if the __size__ of the **user** is less than {}.",
+            huge
+        ))
+        .unwrap();
+        let data = json!({"user": {"size": 3}});
+
+        let outcome = evaluate(
+            &rule_set,
+            &data,
+            &EvaluationOptions::new().with_lenient(true),
+        );
+        assert!(outcome.error.is_none());
+        assert_eq!(outcome.warnings.len(), 1);
+        let warning = &outcome.warnings[0];
+        assert_eq!(warning.path, "$.user.size");
+        assert!(warning.condition.is_some());
+        assert!(
+            warning.message.contains("not a finite number"),
+            "{}",
+            warning.message
+        );
+    }
+
+    #[test]
+    fn test_outcome_counts_what_the_evaluation_did() {
+        let rule_set = parse_rules(
+            r#"
+A **driver** gets a driving licence
+  if the **driver** passes the eyesight test
+  and the **driver** passes the age test.
+
+A **driver** passes the eyesight test
+  if the **driver** passes the age test
+  and the __vision__ of the **driver** is greater than 0.5.
+
+A **driver** passes the age test
+  if the __age__ of the **driver** is greater than or equal to 18.
+"#,
+        )
+        .unwrap();
+        let data = json!({"driver": {"age": 30, "vision": 0.9}});
+
+        let outcome = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        assert!(outcome.result);
+        assert_eq!(
+            EvalStats {
+                wall_time_micros: 0,
+                ..outcome.stats
+            },
+            EvalStats {
+                rules_evaluated: 3,
+                conditions_evaluated: 5,
+                properties_resolved: 2,
+                // The global rule's reference to the age test, evaluated for the eyesight test
+                memo_hits: 1,
+                // The driver for each of the three references, then the driver and the
+                // property for each comparison
+                data_resolver_calls: 7,
+                max_reference_depth: 3,
+                wall_time_micros: 0,
+            }
+        );
+
+        let mut total = outcome.stats;
+        total.merge(&EvalStats {
+            max_reference_depth: 1,
+            ..outcome.stats
+        });
+        assert_eq!(total.rules_evaluated, 6);
+        assert_eq!(total.max_reference_depth, 3);
+    }
+
+    #[test]
+    fn test_outcomes_in_rule_order() {
+        // The practical test is written first but runs after the global rule
+        let rule_set = parse_rules(
+            "A **driver** passes the practical test
+  if the __practical faults__ of the **driver** is less than 16.
+
+A **driver** gets a driving licence
+  if the **driver** passes the age test
+  and the **driver** passes the theory test
+  and the **driver** passes the practical test.
+
+A **driver** passes the age test
+  if the __age__ of the **driver** is greater than or equal to 17.
+
+A **driver** passes the theory test
+  if the __theory score__ of the **driver** is greater than or equal to 43.",
+        )
+        .unwrap();
+        let data = json!({"driver": {"age": 18, "theory score": 45, "practical faults": 20}});
+
+        let outcome = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        assert!(!outcome.result);
+        assert_eq!(
+            outcome.outcomes.keys().next().map(String::as_str),
+            Some("driving licence")
+        );
+        assert_eq!(
+            serde_json::to_string(&outcome.outcomes_in_rule_order(&rule_set)).unwrap(),
+            r#"{"practical test":false,"driving licence":false,"age test":true,"theory test":true}"#
+        );
+    }
+}
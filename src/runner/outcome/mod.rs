@@ -0,0 +1,188 @@
+//! What an evaluation returns.
+//!
+//! `evaluate(rule_set, data, options)` returns an `EvaluationOutcome`: the result, each rule's
+//! result in evaluation order, labels, lenient `warnings`, the trace (kept on failure too) and the
+//! error, if any. Responses list the warnings as `warnings`. `"include_outcomes": true`
+//! (`POST /`, `/policies/{id}/evaluate`, `/evaluate-change`) also answers with `outcomes`, every
+//! rule that ran by outcome in the order the policy declares them
+//! (`EvaluationOutcome::outcomes_in_rule_order`); it is left out otherwise.
+//!
+//! `EvaluationContext::stats` counts what an evaluation does in an `EvalStats`: rules evaluated
+//! (not those taken from the memo), conditions evaluated, properties comparisons found in the data,
+//! memo hits while following references, keys `DataLookup::find_key` looked up, the deepest
+//! reference nesting (counted as `max_reference_depth` is) and wall time in microseconds.
+//! `EvaluationOutcome::stats` carries them on every evaluation; `"stats": true` on `POST /`,
+//! `/policies/{id}/evaluate` and `/evaluate-change` answers them as `execution_stats`. A batch with
+//! `"stats": true` gives each item its `execution_stats` and the summary their total
+//! (`EvalStats::merge`: counts and time summed, depth the deepest). Not to be confused with
+//! `RuleSet::stats`, the policy's static complexity.
+
+mod lib;
+
+use crate::runner::error::RuleError;
+use crate::runner::model::{ConditionId, RuleSet};
+use crate::runner::trace::{ConditionTrace, RuleSetTrace};
+
+/// `EvaluationWarning::code` of a property that held an object where a single value was
+/// compared
+pub const UNEXPECTED_OBJECT: &str = "unexpected_object";
+/// `EvaluationWarning::code` of a payload read as if wrapped under the rule set's selector,
+/// see `LegacyFlatData`
+pub const LEGACY_FLAT_DATA: &str = "legacy_flat_data";
+use indexmap::IndexMap;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Everything an evaluation decided, as returned by `evaluator::evaluate`
+#[derive(Debug, Default)]
+pub struct EvaluationOutcome {
+    /// The global rule's result, or every requested entry passing. False when `error` is set
+    pub result: bool,
+    /// Result of each evaluated rule by outcome, in the order they were evaluated
+    pub outcomes: IndexMap<String, bool>,
+    /// Results of labelled rules
+    pub labels: HashMap<String, bool>,
+    /// Conditions that were decided without comparing their values
+    pub warnings: Vec<EvaluationWarning>,
+    /// Conditions evaluated, against `EvaluationOptions::max_condition_evaluations`. Set on
+    /// failure too
+    pub evaluations_used: usize,
+    /// What the evaluation did and how long it took. Set on failure too
+    pub stats: EvalStats,
+    /// Kept on failure too, up to where evaluation stopped
+    pub trace: Option<RuleSetTrace>,
+    pub error: Option<RuleError>,
+}
+
+/// Counts of the work one evaluation did, answered as `execution_stats` when a request asks
+/// for `"stats": true`. Counted on every evaluation, as adding them up costs next to nothing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct EvalStats {
+    /// Rules evaluated, not counting those taken from the memo
+    pub rules_evaluated: usize,
+    /// Conditions of rules evaluated, as `EvaluationOutcome::evaluations_used` counts them
+    pub conditions_evaluated: usize,
+    /// Properties that comparisons found in the data, each side counted
+    pub properties_resolved: usize,
+    /// References answered from the rules already evaluated
+    pub memo_hits: usize,
+    /// Keys looked up in the data, for selectors and each key of a property path
+    pub data_resolver_calls: usize,
+    /// Most rules being evaluated at once, counting the one evaluation started from, as
+    /// `EvaluationOptions::max_reference_depth` does
+    pub max_reference_depth: usize,
+    /// Microseconds from the start of the evaluation to its end
+    pub wall_time_micros: u64,
+}
+
+impl EvalStats {
+    /// Adds `other` to these, as a batch totals its items: counts and wall time are summed,
+    /// the reference depth is the deepest of either
+    pub fn merge(&mut self, other: &EvalStats) {
+        self.rules_evaluated += other.rules_evaluated;
+        self.conditions_evaluated += other.conditions_evaluated;
+        self.properties_resolved += other.properties_resolved;
+        self.memo_hits += other.memo_hits;
+        self.data_resolver_calls += other.data_resolver_calls;
+        self.max_reference_depth = self.max_reference_depth.max(other.max_reference_depth);
+        self.wall_time_micros += other.wall_time_micros;
+    }
+}
+
+/// A condition that was decided without comparing, e.g. a lenient comparison of a number
+/// that isn't finite, or a flag the provider didn't know
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EvaluationWarning {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<ConditionId>,
+    /// JSONPath of the property the condition read, empty for a flag
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub path: String,
+    pub message: String,
+    /// What kind of warning it is, for the ones callers act on, e.g. `UNEXPECTED_OBJECT`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'static str>,
+}
+
+impl EvaluationOutcome {
+    /// A decision with only its result and labels, such as one a fingerprint attests to
+    #[allow(dead_code)]
+    pub fn decided(result: bool, labels: HashMap<String, bool>) -> Self {
+        Self {
+            result,
+            labels,
+            ..Self::default()
+        }
+    }
+
+    /// A failed evaluation, with the trace of what ran before it failed
+    pub fn failure(error: RuleError, trace: Option<RuleSetTrace>) -> Self {
+        let mut outcome = Self::default();
+        outcome.set_trace(trace);
+        outcome.error = Some(error);
+        outcome
+    }
+
+    /// Keeps `trace`, taking the labels and warnings it records
+    pub(crate) fn set_trace(&mut self, trace: Option<RuleSetTrace>) {
+        let Some(trace) = trace else {
+            return;
+        };
+        for rule in &trace.execution {
+            if let Some(label) = rule.label.as_ref().filter(|_| !rule.skipped) {
+                self.labels.insert(label.clone(), rule.result);
+            }
+            for condition in rule.leaves() {
+                match condition {
+                    ConditionTrace::Comparison(comparison) => {
+                        let lookup = comparison.property.warning.clone().map(|w| (w, None));
+                        let details = comparison.evaluation_details.as_ref().and_then(|details| {
+                            let code = details
+                                .unexpected_object
+                                .as_ref()
+                                .map(|_| UNEXPECTED_OBJECT);
+                            details.warning.clone().map(|warning| (warning, code))
+                        });
+                        self.warnings.extend(lookup.into_iter().chain(details).map(
+                            |(message, code)| EvaluationWarning {
+                                condition: comparison.id.clone(),
+                                path: comparison.property.path.clone(),
+                                message,
+                                code,
+                            },
+                        ));
+                    }
+                    ConditionTrace::Flag(flag) => {
+                        self.warnings.extend(flag.warning.clone().map(|message| {
+                            EvaluationWarning {
+                                condition: flag.id.clone(),
+                                path: String::new(),
+                                message,
+                                code: None,
+                            }
+                        }))
+                    }
+                    ConditionTrace::RuleReference(_) | ConditionTrace::Group(_) => {}
+                }
+            }
+        }
+        self.trace = Some(trace);
+    }
+
+    /// Labelled results, or `None` when no labelled rule ran
+    pub fn labels_if_any(&self) -> Option<HashMap<String, bool>> {
+        (!self.labels.is_empty()).then(|| self.labels.clone())
+    }
+
+    /// `outcomes` in the order `rule_set` declares its rules, as responses list them
+    pub fn outcomes_in_rule_order(&self, rule_set: &RuleSet) -> IndexMap<String, bool> {
+        rule_set
+            .rules
+            .iter()
+            .filter_map(|rule| {
+                let result = self.outcomes.get(&rule.outcome)?;
+                Some((rule.outcome.clone(), *result))
+            })
+            .collect()
+    }
+}
@@ -1,7 +1,15 @@
 #[cfg(test)]
 mod tests {
-    use crate::runner::model::{ComparisonOperator, Condition, ConditionOperator, RuleValue};
-    use crate::runner::parser::parse_rules;
+    use crate::runner::error::RuleError;
+    use crate::runner::formatter::format_rules;
+    use crate::runner::model::{
+        ArithmeticOperator, ComparisonOperator, Condition, ConditionOperator, DeclaredType,
+        OutcomeType, PropertyChainElement, RuleExpression, RuleValue,
+    };
+    use crate::runner::parser::{
+        parse_condition, parse_error_diagnostics, parse_property_reference, parse_rule_set,
+        parse_rule_statement, parse_rules, parse_rules_with_limits, parse_value, ParseLimits,
+    };
     use chrono::NaiveDate;
 
     #[test]
@@ -20,7 +28,8 @@ mod tests {
 
         let rule = &rule_set.rules[0];
         assert_eq!(rule.selector, "user");
-        assert_eq!(rule.outcome, "the test");
+        assert_eq!(rule.outcome, "test");
+        assert_eq!(rule.outcome_display, "the test");
         assert_eq!(rule.conditions.len(), 1);
 
         match &rule.conditions[0].condition {
@@ -157,6 +166,205 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_is_true_and_is_false() {
+        let rule_set = parse_rules(
+            "A **driver** may drive alone\n  if the __provisional__ of the **driver** is false\n  and the __insured__ of the **driver** is true.",
+        )
+        .unwrap();
+        let conditions: Vec<_> = rule_set.rules[0]
+            .conditions
+            .iter()
+            .map(|group| match &group.condition {
+                Condition::Comparison(comp) => (
+                    comp.property.value.as_str(),
+                    comp.operator.clone(),
+                    comp.value.value.clone(),
+                ),
+                _ => panic!("Expected comparison condition"),
+            })
+            .collect();
+        assert_eq!(
+            conditions,
+            vec![
+                (
+                    "provisional",
+                    ComparisonOperator::EqualTo,
+                    RuleValue::Boolean(false)
+                ),
+                (
+                    "insured",
+                    ComparisonOperator::EqualTo,
+                    RuleValue::Boolean(true)
+                ),
+            ]
+        );
+
+        // The shorthand is only for properties, not for counts
+        assert!(parse_rules(
+            "A **driver** is ok if the number of __points__ of the **driver** is true."
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_has_a_property() {
+        for input in [
+            "A **driver** needs a supervisor if the **driver** has a __provisional__.",
+            "A **driver** needs a supervisor if **driver** has an __provisional__.",
+            "A **driver** needs a supervisor if the **driver** has __provisional__.",
+        ] {
+            let rule_set = parse_rules(input).unwrap();
+            match &rule_set.rules[0].conditions[0].condition {
+                Condition::Comparison(comp) => {
+                    assert_eq!(comp.selector.value, "driver");
+                    assert_eq!(comp.property.value, "provisional");
+                    assert_eq!(comp.operator, ComparisonOperator::EqualTo);
+                    assert_eq!(comp.value.value, RuleValue::Boolean(true));
+                    let path = comp.left_property_path.as_ref().unwrap();
+                    assert_eq!(path.properties, vec!["provisional".to_string()]);
+                }
+                _ => panic!("Expected comparison condition for {}", input),
+            }
+        }
+
+        // Followed by more words it is still a rule reference
+        let rule_set = parse_rules(
+            "A **driver** may drive if the **driver** has a valid licence.\n\n\
+             A **driver** has a valid licence if the __licence__ of the **driver** is not empty.",
+        )
+        .unwrap();
+        assert!(matches!(
+            rule_set.rules[0].conditions[0].condition,
+            Condition::RuleReference(_)
+        ));
+        let rule_set = parse_rules(
+            "A **driver** may drive\n  if the **driver** has a __licence__\n  and the **driver** has a __car__.",
+        )
+        .unwrap();
+        assert_eq!(rule_set.rules[0].conditions.len(), 2);
+    }
+
+    /// The parts of a comparison that don't depend on where or how it was written
+    fn comparison_shape(condition: &Condition) -> String {
+        match condition {
+            Condition::Comparison(comp) => format!(
+                "{} {} {:?} {:?} {:?} {:?}",
+                comp.selector.value,
+                comp.property.value,
+                comp.operator,
+                comp.value.value,
+                comp.left_property_path,
+                comp.right_property_path
+            ),
+            _ => panic!("Expected comparison condition"),
+        }
+    }
+
+    #[test]
+    fn test_parse_a_and_an_open_rules_alike() {
+        let canonical = parse_rules(
+            "A **employee** is eligible if the __tenure__ of the **employee** is at least 2.",
+        )
+        .unwrap();
+        for input in [
+            "An **employee** is eligible if the __tenure__ of the **employee** is at least 2.",
+            "An  **employee** is eligible if the __tenure__ of the **employee** is at least 2.",
+            "An\t**employee** is eligible if the __tenure__ of the **employee** is at least 2.",
+            "staff.tenure. An **employee** is eligible if the __tenure__ of the **employee** is at least 2.",
+        ] {
+            let rule_set = parse_rules(input).unwrap();
+            let rule = &rule_set.rules[0];
+            assert_eq!(rule.selector, canonical.rules[0].selector, "{}", input);
+            assert_eq!(rule.outcome, "eligible");
+            assert_eq!(
+                comparison_shape(&rule.conditions[0].condition),
+                comparison_shape(&canonical.rules[0].conditions[0].condition)
+            );
+        }
+
+        // Statement recovery finds rules opened by either article
+        let broken = "An **employee** is eligible if the __tenure__ of the **employee** is bigger than 2.\n\n\
+                      A **employee** is senior if the __tenure__ of the **employee** is bigger than 9.";
+        match parse_rule_set(broken) {
+            Err(RuleError::ParseErrors { diagnostics }) => assert_eq!(diagnostics.len(), 2),
+            other => panic!("Expected a diagnostic per rule, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_possessive_is_the_of_form() {
+        let shape = |input: &str| {
+            let rule_set = parse_rules(input).unwrap();
+            comparison_shape(&rule_set.rules[0].conditions[0].condition)
+        };
+        let pairs = [
+            (
+                "the **employee**'s __manager__ is not empty",
+                "the __manager__ of the **employee** is not empty",
+            ),
+            (
+                "the **employee's** __manager__ is not empty",
+                "the __manager__ of the **employee** is not empty",
+            ),
+            (
+                "the **employee**’s __manager__ is not empty",
+                "the __manager__ of the **employee** is not empty",
+            ),
+            (
+                "the **employee**'s __manager__'s __grade__ is at least 7",
+                "the __grade__ of the __manager__ of the **employee** is at least 7",
+            ),
+            (
+                "the **employees**' __count__ is at least 7",
+                "the __count__ of the **employees** is at least 7",
+            ),
+            (
+                "the **driving test**'s __score__ is greater than **limits**'s __pass mark__",
+                "the __score__ of the **driving test** is greater than __pass mark__ of **limits**",
+            ),
+            (
+                "the length of **employee**'s __name__ is at least 2",
+                "the length of __name__ of the **employee** is at least 2",
+            ),
+        ];
+        for (possessive, canonical) in pairs {
+            let rule = |condition: &str| format!("A **employee** is eligible if {}.", condition);
+            assert_eq!(
+                shape(&rule(possessive)),
+                shape(&rule(canonical)),
+                "{}",
+                possessive
+            );
+        }
+
+        let formatted = format_rules(
+            "A **employee** is eligible if the **employee**'s   __manager__ is not empty.",
+        )
+        .unwrap();
+        assert!(
+            formatted.contains("the **employee**'s __manager__ is not empty."),
+            "{}",
+            formatted
+        );
+    }
+
+    #[test]
+    fn test_parse_plural_selector_reference() {
+        let rule_set = parse_rule_set(
+            "A **team** is ready if the **employees** all satisfy the checks.\n\n\
+             A **team** satisfies the checks if the __size__ of the **team** is at least 2.",
+        )
+        .unwrap();
+        match &rule_set.rules[0].conditions[0].condition {
+            Condition::RuleReference(reference) => {
+                assert_eq!(reference.selector.value, "employees");
+                assert_eq!(reference.rule_name.value, "all satisfy the checks");
+            }
+            _ => panic!("Expected rule reference"),
+        }
+    }
+
     #[test]
     fn test_parse_list_operations() {
         let input =
@@ -251,6 +459,44 @@ A **user** is valid if __id__ of __group__ of **user** is equal to 1.
         }
     }
 
+    #[test]
+    fn test_parse_each_marks_fan_out_steps() {
+        let input = r#"
+A **order** is valid if the number of __sku__ of each __item__ of each __line__ of the __invoice__ of the **order** is greater than 1.
+        "#;
+
+        let rule_set = parse_rules(input).unwrap();
+        match &rule_set.rules[0].conditions[0].condition {
+            Condition::Comparison(comp) => {
+                let path = comp.left_property_path.as_ref().unwrap();
+                assert_eq!(path.selector, "order");
+                assert_eq!(
+                    path.properties,
+                    vec!["invoice", "line", "item", "sku", "__number_of__"]
+                );
+                assert_eq!(path.fan_out, vec![1, 2]);
+            }
+            _ => panic!("Expected comparison condition"),
+        }
+
+        // Without `each` nothing fans out
+        let plain = parse_rules(
+            "A **order** is valid if the __amount__ of the __line__ of the **order** is equal to 1.",
+        )
+        .unwrap();
+        match &plain.rules[0].conditions[0].condition {
+            Condition::Comparison(comp) => {
+                assert!(comp.left_property_path.as_ref().unwrap().fan_out.is_empty())
+            }
+            _ => panic!("Expected comparison condition"),
+        }
+
+        let error =
+            parse_rules("A **order** is valid if the __amount__ of each **order** is equal to 1.")
+                .unwrap_err();
+        assert!(error.to_string().contains("can't mark the root selector"));
+    }
+
     #[test]
     fn test_parse_all_comparison_operators() {
         let operators = vec![
@@ -383,10 +629,10 @@ A **user** is eligible
         assert_eq!(rule_set.rules.len(), 3);
 
         // Check first rule
-        assert_eq!(rule_set.rules[0].outcome, "the age check");
+        assert_eq!(rule_set.rules[0].outcome, "age check");
 
         // Check second rule
-        assert_eq!(rule_set.rules[1].outcome, "the premium check");
+        assert_eq!(rule_set.rules[1].outcome, "premium check");
 
         // Check third rule (should be the global rule)
         assert_eq!(rule_set.rules[2].outcome, "eligible");
@@ -586,7 +832,8 @@ A **user** is valid if __score__ of **user** is in [85, 90, 95, 100].
         assert_eq!(rule_set.rules.len(), 1);
         let rule = &rule_set.rules[0];
         assert_eq!(rule.selector, "user");
-        assert_eq!(rule.outcome, "the test");
+        assert_eq!(rule.outcome, "test");
+        assert_eq!(rule.outcome_display, "the test");
     }
 
     #[test]
@@ -619,6 +866,74 @@ A **user** is valid if __score__ of **user** is in [85, 90, 95, 100].
         }
     }
 
+    #[test]
+    fn test_syntax_error_locates_the_typo() {
+        let input = "A **user** gets access
+  if the __age__ of the **user** is greater than 18
+  and the __role__ of the **user** is equl to \"admin\".";
+
+        let error = parse_rules(input).unwrap_err();
+        let detail = error.parse_detail().expect("the grammar rejected it");
+        // The predicate the misspelt operator starts, or arithmetic on the property
+        assert_eq!((detail.line, detail.column), (3, 36));
+        assert_eq!(
+            detail.snippet,
+            "  and the __role__ of the **user** is equl to \"admin\"."
+        );
+        assert_eq!(detail.expected, vec!["predicate", "arithmetic operator"]);
+        assert_eq!(detail.found, "is");
+        assert!(error.to_string().starts_with("Parse error: "));
+    }
+
+    #[test]
+    fn test_parse_not_within_and_ago() {
+        let input = "\
+A **customer** may apply again
+  if the __last application date__ of the **customer** is not within 90 days
+  and the __first contact__ of the **customer** is more than 2 years ago
+  and the __last refund__ of the **customer** is at least 30 days ago
+  and the __age__ of the **customer** is at least 18.";
+        let rule_set = parse_rules(input).unwrap();
+        let comparisons: Vec<(ComparisonOperator, String)> = rule_set.rules[0]
+            .conditions
+            .iter()
+            .map(|group| match &group.condition {
+                Condition::Comparison(comp) => {
+                    (comp.operator.clone(), comp.value.value.to_string())
+                }
+                _ => panic!("Expected comparison condition"),
+            })
+            .collect();
+        assert_eq!(
+            comparisons,
+            vec![
+                (ComparisonOperator::NotWithin, "90 days".to_string()),
+                (ComparisonOperator::MoreThanAgo, "2 years".to_string()),
+                (ComparisonOperator::AtLeastAgo, "30 days".to_string()),
+                // Without `ago`, `is at least` still compares numbers
+                (ComparisonOperator::GreaterThanOrEqual, "18".to_string()),
+            ]
+        );
+
+        // The duration's position leaves `ago` out
+        let Condition::Comparison(comp) = &rule_set.rules[0].conditions[1].condition else {
+            panic!("Expected comparison condition");
+        };
+        let position = comp.value.pos.as_ref().unwrap();
+        let line = input.lines().nth(position.line - 1).unwrap();
+        assert_eq!(&line[position.start - 1..position.end - 1], "2 years");
+
+        assert_eq!(
+            crate::runner::formatter::format_rules(input).unwrap(),
+            format!("{}\n", input)
+        );
+        // `ago` only follows a duration
+        assert!(parse_rules(
+            "A **customer** is old if the __joined__ of the **customer** is more than 2020-01-01 ago."
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_parse_flexible_naming_conventions() {
         // Test with spaces in object selectors (but using proper property chain syntax)
@@ -849,7 +1164,7 @@ A **driver** has a provisional licence
 
         // The first rule should be the global rule since it references others
         // The other rules are supporting rules that are referenced
-        assert_eq!(rule_set.rules[0].outcome, "a driving licence");
+        assert_eq!(rule_set.rules[0].outcome, "driving licence");
     }
 
     #[test]
@@ -929,10 +1244,10 @@ A **driver** has taken the test in the time period
 
         let rule_set = result.unwrap();
 
-        // Should identify "a driving licence" as the only global rule
+        // Should identify "driving licence" as the only global rule
         let global_rule_result = crate::runner::utils::find_global_rule(&rule_set.rules);
         assert!(global_rule_result.is_ok());
-        assert_eq!(global_rule_result.unwrap().outcome, "a driving licence");
+        assert_eq!(global_rule_result.unwrap().outcome, "driving licence");
     }
 
     #[test]
@@ -981,10 +1296,10 @@ A **driver** has taken the test in the time period
         // Should have all 7 rules
         assert_eq!(rule_set.rules.len(), 7);
 
-        // Should identify "a driving licence" as the only global rule
+        // Should identify "driving licence" as the only global rule
         let global_rule_result = crate::runner::utils::find_global_rule(&rule_set.rules);
         assert!(global_rule_result.is_ok());
-        assert_eq!(global_rule_result.unwrap().outcome, "a driving licence");
+        assert_eq!(global_rule_result.unwrap().outcome, "driving licence");
     }
 
     #[test]
@@ -1029,10 +1344,10 @@ tester. A **driver** passes the age test
         assert!(rule_set.rules[1].label.is_none());
         assert_eq!(rule_set.rules[2].label, Some("tester".to_string()));
 
-        // Should identify "a driving licence" as the only global rule
+        // Should identify "driving licence" as the only global rule
         let global_rule_result = crate::runner::utils::find_global_rule(&rule_set.rules);
         assert!(global_rule_result.is_ok());
-        assert_eq!(global_rule_result.unwrap().outcome, "a driving licence");
+        assert_eq!(global_rule_result.unwrap().outcome, "driving licence");
     }
 
     #[test]
@@ -1192,10 +1507,10 @@ A **driver** has taken the test in the time period
             _ => panic!("Expected label reference condition"),
         }
 
-        // Should identify "a driving licence" as the only global rule
+        // Should identify "driving licence" as the only global rule
         let global_rule_result = crate::runner::utils::find_global_rule(&rule_set.rules);
         assert!(global_rule_result.is_ok());
-        assert_eq!(global_rule_result.unwrap().outcome, "a driving licence");
+        assert_eq!(global_rule_result.unwrap().outcome, "driving licence");
     }
 
     #[test]
@@ -1356,9 +1671,859 @@ A **driver** has taken the test in the time period
         // First rule should have the label
         assert_eq!(rule_set.rules[0].label, Some("tester".to_string()));
 
-        // Should identify "a driving licence" as the only global rule
+        // Should identify "driving licence" as the only global rule
         let global_rule_result = crate::runner::utils::find_global_rule(&rule_set.rules);
         assert!(global_rule_result.is_ok());
-        assert_eq!(global_rule_result.unwrap().outcome, "a driving licence");
+        assert_eq!(global_rule_result.unwrap().outcome, "driving licence");
+    }
+
+    fn rule_with_generated_list(size: usize) -> String {
+        let items: Vec<String> = (0..size).map(|i| format!("\"code-{}\"", i)).collect();
+        format!(
+            r#"A **user** is allowed if __code__ of **user** is in [{}]."#,
+            items.join(", ")
+        )
+    }
+
+    #[test]
+    fn test_huge_list_rejected_by_default_limit() {
+        let error = parse_rules(&rule_with_generated_list(100_000)).unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("100000 elements"), "{}", message);
+        assert!(message.contains("limit of 50000"), "{}", message);
+    }
+
+    #[test]
+    fn test_huge_list_parses_within_budget_when_allowed() {
+        let input = rule_with_generated_list(100_000);
+        let limits = ParseLimits {
+            max_list_elements: 100_000,
+            ..ParseLimits::default()
+        };
+
+        let started = std::time::Instant::now();
+        let rule_set = parse_rules_with_limits(&input, &limits).unwrap();
+        assert!(
+            started.elapsed() < std::time::Duration::from_secs(10),
+            "parsing took {:?}",
+            started.elapsed()
+        );
+
+        match &rule_set.rules[0].conditions[0].condition {
+            Condition::Comparison(comp) => match &comp.value.value {
+                RuleValue::List(items) => assert_eq!(items.len(), 100_000),
+                other => panic!("Expected list, got {:?}", other),
+            },
+            _ => panic!("Expected comparison condition"),
+        }
+    }
+
+    #[test]
+    fn test_parse_rule_statement_entry_point() {
+        let rule = parse_rule_statement(
+            "A **user** passes the check if the __age__ of the **user** is at least 18.",
+        )
+        .unwrap();
+        assert_eq!(rule.selector, "user");
+        assert_eq!(rule.outcome, "check");
+        assert_eq!(rule.conditions.len(), 1);
+
+        // One rule only, and it must be complete
+        let two =
+            "A **user** is a if __x__ of **user** is 1. A **user** is b if __y__ of **user** is 2.";
+        assert!(parse_rule_statement(two).is_err());
+        assert!(parse_rule_statement("A **user** is adult if").is_err());
+    }
+
+    #[test]
+    fn test_parse_negated_conditions() {
+        let rules = r#"A **person** gets a licence
+  if the **person** doesn't qualify for a ban
+  and not the __points__ of the **person** is greater than 12
+  and not flag "strict" is enabled
+  and the **person** passes the eye test."#;
+        let rule = &parse_rules(rules).unwrap().rules[0];
+        let negated: Vec<bool> = rule.conditions.iter().map(|group| group.negated).collect();
+        assert_eq!(negated, vec![true, true, true, false]);
+        match &rule.conditions[0].condition {
+            Condition::RuleReference(reference) => {
+                assert_eq!(reference.rule_name.value, "qualifies for a ban")
+            }
+            _ => panic!("Expected rule reference"),
+        }
+        // `not` is part of the condition, so of its id
+        let positive = parse_rules(&rules.replace("not the __points__", "the __points__")).unwrap();
+        assert_ne!(rule.conditions[1].id, positive.rules[0].conditions[1].id);
+
+        assert_eq!(
+            format_rules(rules).unwrap().lines().nth(2).unwrap(),
+            "  and not the __points__ of the **person** is greater than 12"
+        );
+        assert!(matches!(
+            parse_condition("not the **user** passes the check").unwrap(),
+            (Condition::RuleReference(_), true)
+        ));
+        // A property that happens to start with `not` is no negation
+        assert!(parse_condition("the __notes__ of the **user** is empty").is_ok());
+        assert!(parse_condition("nothe __a__ of the **user** is 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_condition_entry_point() {
+        let (condition, negated) =
+            parse_condition(r#"the __role__ of the **user** is in ["a", "b"]."#).unwrap();
+        assert!(!negated);
+        match condition {
+            Condition::Comparison(comp) => {
+                assert_eq!(comp.operator, ComparisonOperator::In);
+                let pos = comp.value.pos.unwrap();
+                assert_eq!((pos.line, pos.start, pos.end), (1, 36, 46));
+            }
+            _ => panic!("Expected comparison condition"),
+        }
+
+        assert!(matches!(
+            parse_condition("the **user** passes the check").unwrap(),
+            (Condition::RuleReference(_), false)
+        ));
+
+        let error = parse_condition("the __role__ of the **user** is").unwrap_err();
+        assert!(error.to_string().contains(" --> 1:"), "{}", error);
+        // Two conditions are not one
+        assert!(parse_condition(
+            "the __a__ of the **user** is 1 and the __b__ of the **user** is 2"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_property_reference_entry_point() {
+        let path =
+            parse_property_reference("the __amount__ of each __line__ of the **order**").unwrap();
+        assert_eq!(path.selector, "order");
+        assert_eq!(path.properties, vec!["line", "amount"]);
+        assert_eq!(path.fan_out, vec![0]);
+
+        assert!(parse_property_reference("__amount__ of").is_err());
+        assert!(parse_property_reference("the __amount__ of the **order** is 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_value_entry_point() {
+        let value = parse_value("  42.5").unwrap();
+        assert_eq!(value.value, RuleValue::Number(42.5));
+        let pos = value.pos.unwrap();
+        assert_eq!((pos.line, pos.start, pos.end), (1, 3, 7));
+
+        assert_eq!(
+            parse_value("date(2024-01-31)").unwrap().value,
+            RuleValue::Date(NaiveDate::from_ymd_opt(2024, 1, 31).unwrap())
+        );
+        assert_eq!(
+            parse_value(r#"["a", 1, true]"#).unwrap().value,
+            RuleValue::List(vec![
+                RuleValue::String("a".to_string()),
+                RuleValue::Number(1.0),
+                RuleValue::Boolean(true),
+            ])
+        );
+        assert!(matches!(
+            parse_value("3 days").unwrap().value,
+            RuleValue::Duration(_)
+        ));
+
+        assert!(parse_value(r#""unterminated"#).is_err());
+        assert!(parse_value("[1, 2").is_err());
+        assert!(parse_value("1 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_reports_every_broken_statement() {
+        let rules = r#"A **user** gets access
+  if $adult is valid
+  and $member is valid.
+
+adult. A **user** is an adult if the __age__ of the **user** is greater than.
+
+member. A **user** is a member if the __status__ of the **user** is equal to "active".
+A **user** is verified if the __email__ of the **user** is equal to.
+# trailing comment
+A **user** is named if the __name__ of the **user** matches "x".
+"#;
+
+        let error = parse_rules(rules).unwrap_err();
+        let diagnostics = error.parse_diagnostics().expect("every error reported");
+        let positions: Vec<(usize, usize)> = diagnostics
+            .iter()
+            .map(|d| {
+                let pos = d.position.as_ref().unwrap();
+                (pos.line, pos.start)
+            })
+            .collect();
+        assert_eq!(positions, vec![(5, 77), (8, 68), (10, 53)]);
+        assert!(diagnostics.iter().all(|d| d.code == "syntax-error"));
+        assert!(error.to_string().starts_with("Parse errors: line 5:77: "));
+
+        // A single error keeps the parser's own message
+        let one = "A **user** is an adult if the __age__ of the **user** is greater than.";
+        let error = parse_rules(one).unwrap_err();
+        assert!(matches!(
+            error,
+            crate::runner::error::RuleError::ParseError { .. }
+        ));
+        assert!(error.to_string().contains(" --> 1:"), "{}", error);
+    }
+
+    #[test]
+    fn test_recovery_reports_invalid_rules_alongside_syntax_errors() {
+        let rules = "A **user** gets access if the __name__ of **user** is.\n\
+                     A **user** is listed if the __age__ of each **user** is equal to 1.\n";
+
+        let error = parse_rule_set(rules).unwrap_err();
+        let diagnostics = error.parse_diagnostics().unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].code, "syntax-error");
+        assert_eq!(diagnostics[1].code, "invalid-rule");
+        assert_eq!(diagnostics[1].position.as_ref().unwrap().line, 2);
+        assert!(diagnostics[1].message.contains("'each'"));
+    }
+
+    #[test]
+    fn test_condition_ids_survive_whitespace_edits() {
+        let rules = r#"A **user** gets access
+  if the __age__ of the **user** is at least 18
+  and the __role__ of the **user** is in ["admin", "staff"]
+  and the __age__ of the **user** is at least 18."#;
+        let reflowed = "# reformatted\nA **user**   gets access if the __age__ of\n the **user** is at least 18 and the __role__ of the **user**\n   is in [\"admin\",\"staff\"]   and the __age__ of the **user** is at least 18 .";
+        let ids = |text: &str| -> Vec<String> {
+            parse_rules(text).unwrap().rules[0]
+                .conditions
+                .iter()
+                .map(|group| group.id.to_string())
+                .collect()
+        };
+
+        let original = ids(rules);
+        assert_eq!(original.len(), 3);
+        assert!(original[0].starts_with("access/"), "{}", original[0]);
+        assert_eq!(original[0].len(), "access/".len() + 8);
+        // The repeated condition is told apart from the first
+        assert_eq!(original[2], format!("{}-2", original[0]));
+
+        assert_eq!(ids(rules), original);
+        assert_eq!(ids(reflowed), original);
+        assert_eq!(ids(&format_rules(reflowed).unwrap()), original);
+
+        // Editing a condition changes its id only
+        let edited = ids(&rules.replacen("at least 18", "at least 21", 1));
+        assert_ne!(edited[0], original[0]);
+        assert_eq!(edited[1], original[1]);
+    }
+
+    #[test]
+    fn test_parse_error_diagnostics_locate_a_single_error() {
+        // The error is on line 14, after four rules that parse
+        let mut rules = String::new();
+        for i in 0..4 {
+            rules.push_str(&format!(
+                "A **user** passes check {i}\n  if the __score__ of the **user** is greater than {i}.\n\n"
+            ));
+        }
+        rules.push_str(
+            "A **user** is an adult\n  if the __age__ of the **user** is greater than.\n",
+        );
+
+        let error = parse_rule_set(&rules).unwrap_err();
+        assert!(matches!(error, RuleError::ParseError { .. }));
+        let diagnostics = parse_error_diagnostics(&error, &rules, &ParseLimits::default());
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!((diagnostic.line, diagnostic.column), (14, 49));
+        assert_eq!(diagnostic.code, "syntax-error");
+        assert_eq!(
+            diagnostic.snippet,
+            "  if the __age__ of the **user** is greater than."
+        );
+        assert!(
+            diagnostic.message.contains("expected"),
+            "{}",
+            diagnostic.message
+        );
+    }
+
+    #[test]
+    fn test_parse_error_diagnostics_keep_every_recovered_error() {
+        let rules = "A **user** gets access if the __name__ of **user** is.\n\
+                     A **user** is listed if the __age__ of each **user** is equal to 1.\n";
+        let error = parse_rule_set(rules).unwrap_err();
+        let diagnostics = parse_error_diagnostics(&error, rules, &ParseLimits::default());
+        let lines: Vec<_> = diagnostics.iter().map(|d| (d.line, d.code)).collect();
+        assert_eq!(lines, vec![(1, "syntax-error"), (2, "invalid-rule")]);
+        assert!(diagnostics[1].snippet.starts_with("A **user** is listed"));
+    }
+
+    #[test]
+    fn test_rule_tags() {
+        let rules = format!(
+            "Tags: Loyalty, pricing, loyalty.\n{}\n\ntags: vip.\nA **customer** is a vip if the **customer** gets a discount.",
+            LOYALTY
+        );
+        let rule_set = parse_rule_set(&rules).unwrap();
+        assert_eq!(rule_set.rules[0].tags, vec!["loyalty", "pricing"]);
+        assert_eq!(rule_set.rules[1].tags, vec!["vip"]);
+        assert!(parse_rules(LOYALTY).unwrap().rules[0].tags.is_empty());
+        assert!(parse_rules(&format!("Tags: .\n{}", LOYALTY)).is_err());
+
+        // Recovery keeps a tags line with its rule rather than reporting it on its own
+        let broken = format!(
+            "Tags: kyc.\nA **user** is listed if the __age__ of each **user** is equal to 1.\n\n{}.",
+            LOYALTY
+        );
+        let error = parse_rule_set(&broken).unwrap_err();
+        let diagnostics = error.parse_diagnostics().unwrap();
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, vec!["invalid-rule", "syntax-error"]);
+    }
+
+    const LOYALTY: &str =
+        "A **customer** gets a discount\n  if the __years__ of the **customer** is at least 2.";
+
+    #[test]
+    fn test_flag_conditions() {
+        let rule_set = parse_rules(
+            "A **user** gets beta checkout\n  if flag \"new-checkout\" is enabled\n  and flag \"legacy\" is disabled.",
+        )
+        .unwrap();
+        let flags: Vec<_> = rule_set.rules[0]
+            .conditions
+            .iter()
+            .map(|group| match &group.condition {
+                Condition::Flag(flag) => (flag.flag.value.as_str(), flag.enabled),
+                _ => panic!("expected a flag condition"),
+            })
+            .collect();
+        assert_eq!(flags, [("new-checkout", true), ("legacy", false)]);
+        assert!(
+            parse_rules("A **user** gets beta checkout\n  if flag \"new-checkout\" is on.")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_outcome_declarations() {
+        let rules = format!(
+            "Outcome tier is one of [\"gold\", \"silver\", \"bronze\"].\n\
+             Outcome the risk score is a number between 0 and 100.\n\
+             Outcome discount is a boolean.\n\n{}",
+            LOYALTY
+        );
+        let rule_set = parse_rules(&rules).unwrap();
+        let declared: Vec<_> = rule_set
+            .outcome_schema
+            .iter()
+            .map(|declaration| (declaration.outcome.as_str(), &declaration.kind))
+            .collect();
+        assert_eq!(
+            declared,
+            [
+                (
+                    "tier",
+                    &OutcomeType::OneOf {
+                        values: vec!["gold".into(), "silver".into(), "bronze".into()]
+                    }
+                ),
+                (
+                    "risk score",
+                    &OutcomeType::Number {
+                        min: 0.0,
+                        max: 100.0
+                    }
+                ),
+                ("discount", &OutcomeType::Boolean),
+            ]
+        );
+        assert_eq!(rule_set.outcome_schema[1].pos.as_ref().unwrap().line, 2);
+        assert!(rule_set.outcome_declaration("the tier").is_some());
+    }
+
+    #[test]
+    fn test_outcome_declarations_must_admit_what_rules_decide() {
+        let declared_tier = format!(
+            "Outcome discount is one of [\"gold\", \"platinum\"].\n\n{}",
+            LOYALTY
+        );
+        let error = parse_rule_set(&declared_tier).unwrap_err().to_string();
+        assert!(
+            error.contains(
+                "Outcome 'discount' is declared as one of [\"gold\", \"platinum\"] on line 1, \
+                 but the rule on line 3 decides it true or false"
+            ),
+            "{}",
+            error
+        );
+
+        let declared_score = format!("Outcome discount is a number between 0 and 1.\n{}", LOYALTY);
+        assert!(parse_rule_set(&declared_score).is_err());
+
+        let declared_flags = format!("Outcome discount is one of [true, false].\n{}", LOYALTY);
+        assert!(parse_rule_set(&declared_flags).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_outcome_declarations() {
+        let cases = [
+            (
+                "Outcome score is a number between 100 and 0.",
+                "which no number is",
+            ),
+            (
+                "Outcome tier is one of [\"gold\"].\nOutcome tier is a boolean.",
+                "Outcome 'tier' is declared on line 1 and again on line 2",
+            ),
+            (
+                "Outcome due is one of [2024-01-01].",
+                "can be one of strings",
+            ),
+            ("Outcome tier is one of gold.", "expected"),
+        ];
+        for (declarations, expected) in cases {
+            let rules = format!("{}\n\n{}", declarations, LOYALTY);
+            let error = parse_rule_set(&rules).unwrap_err().to_string();
+            assert!(error.contains(expected), "{}: {}", declarations, error);
+        }
+    }
+
+    #[test]
+    fn test_property_declarations() {
+        let rules = format!(
+            "The **user** has numeric __age__, a __country__ text and an __orders__ list.\n\
+             The __orders__ have a numeric __total__ and a __placed on__ date.\n\n{}",
+            LOYALTY
+        );
+        let rule_set = parse_rules(&rules).unwrap();
+        let declared: Vec<_> = rule_set
+            .property_catalogue
+            .iter()
+            .map(|property| (property.path.join("."), property.kind))
+            .collect();
+        assert_eq!(
+            declared,
+            [
+                ("user.age".to_string(), DeclaredType::Number),
+                ("user.country".to_string(), DeclaredType::Text),
+                ("user.orders".to_string(), DeclaredType::List),
+                ("user.orders.total".to_string(), DeclaredType::Number),
+                ("user.orders.placed on".to_string(), DeclaredType::Date),
+            ]
+        );
+        assert_eq!(rule_set.property_catalogue[3].pos.as_ref().unwrap().line, 2);
+        assert!(rule_set
+            .declared_property(&["User".to_string(), "placedOn".to_string()])
+            .is_none());
+        assert_eq!(
+            rule_set
+                .declared_property(&["User".to_string(), "Orders".to_string()])
+                .map(|property| property.kind),
+            Some(DeclaredType::List)
+        );
+
+        let example = format!(
+            "The **driving test** has a __scores__ object, a __testDates__ object, and a __person__ object.\n\
+             The __scores__ has numeric __multiple choice__ and numeric __hazard perception__.\n\n{}",
+            LOYALTY
+        );
+        let catalogue = parse_rules(&example).unwrap().property_catalogue;
+        assert_eq!(catalogue.len(), 5);
+        assert_eq!(
+            catalogue[4].path,
+            ["driving test", "scores", "hazard perception"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_property_declarations() {
+        let cases = [
+            (
+                "The __orders__ have numeric __total__.",
+                "'orders' on line 1 isn't declared as an object or a list before it",
+            ),
+            (
+                "The **user** has numeric __age__.\nThe **user** has an __age__ date.",
+                "Property 'user.age' is declared on line 1 and again on line 2",
+            ),
+            ("The **user** has __age__.", "expected"),
+        ];
+        for (declarations, expected) in cases {
+            let rules = format!("{}\n\n{}", declarations, LOYALTY);
+            let error = parse_rule_set(&rules).unwrap_err().to_string();
+            assert!(error.contains(expected), "{}: {}", declarations, error);
+        }
+    }
+
+    #[test]
+    fn test_parse_grouped_conditions() {
+        let input = r#"A **user** gets access if the __age__ of the **user** is at least 18 and (the __role__ of the **user** is equal to "admin" or not (the **user** passes the check)) and the **user** has a __badge__."#;
+        let rule = &parse_rules(input).unwrap().rules[0];
+        assert_eq!(rule.conditions.len(), 3);
+        assert_eq!(rule.conditions[1].operator, Some(ConditionOperator::And));
+
+        let Condition::Group(members) = &rule.conditions[1].condition else {
+            panic!("expected a group, got {:?}", rule.conditions[1].condition);
+        };
+        assert_eq!(members.len(), 2);
+        assert!(members[0].operator.is_none());
+        assert!(matches!(members[0].condition, Condition::Comparison(_)));
+        assert_eq!(members[1].operator, Some(ConditionOperator::Or));
+        assert!(members[1].negated);
+        let Condition::Group(inner) = &members[1].condition else {
+            panic!("expected a nested group");
+        };
+        let Condition::RuleReference(reference) = &inner[0].condition else {
+            panic!("expected a reference");
+        };
+        assert_eq!(reference.rule_name.value, "passes the check");
+
+        // Every condition has an id of its own, grouped or not
+        let mut ids = rule.condition_ids();
+        assert_eq!(ids.len(), 6);
+        ids.dedup();
+        assert_eq!(ids.len(), 6);
+    }
+
+    #[test]
+    fn test_parse_grouped_conditions_errors() {
+        for input in [
+            r#"A **user** gets access if (the __age__ of the **user** is at least 18."#,
+            r#"A **user** gets access if the __age__ of the **user** is at least 18)."#,
+            r#"A **user** gets access if ()."#,
+        ] {
+            assert!(parse_rules(input).is_err(), "{}", input);
+        }
+
+        let (condition, negated) = parse_condition(
+            "not (the __a__ of the **user** is equal to 1 or the **user** has a __b__)",
+        )
+        .unwrap();
+        assert!(negated);
+        assert!(matches!(condition, Condition::Group(members) if members.len() == 2));
+    }
+
+    #[test]
+    fn test_parse_between_operators() {
+        let rule_set = parse_rules(
+            "A **person** is eligible\n  if the __age__ of the **person** is between 18 and 65\n  and the __expiry date__ of the **person** is strictly between 2024-01-01 and date(2024-12-31).",
+        )
+        .unwrap();
+        let comparisons: Vec<_> = rule_set.rules[0]
+            .conditions
+            .iter()
+            .map(|condition| match &condition.condition {
+                Condition::Comparison(comparison) => comparison,
+                other => panic!("Expected a comparison, got {:?}", other),
+            })
+            .collect();
+
+        // The `and` between the bounds isn't taken for a second condition
+        assert_eq!(comparisons.len(), 2);
+        assert_eq!(comparisons[0].operator, ComparisonOperator::Between);
+        assert_eq!(
+            comparisons[0].value.value,
+            RuleValue::Range(
+                Box::new(RuleValue::Number(18.0)),
+                Box::new(RuleValue::Number(65.0))
+            )
+        );
+        assert_eq!(comparisons[0].value.value.to_string(), "18 and 65");
+        assert_eq!(comparisons[1].operator, ComparisonOperator::StrictlyBetween);
+        let date = |d: &str| {
+            Box::new(RuleValue::Date(
+                NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap(),
+            ))
+        };
+        assert_eq!(
+            comparisons[1].value.value,
+            RuleValue::Range(date("2024-01-01"), date("2024-12-31"))
+        );
+
+        let rule_set = parse_rules(
+            "A **cart** is small if the number of __items__ of the **cart** is between 1 and 3.",
+        )
+        .unwrap();
+        assert!(matches!(
+            &rule_set.rules[0].conditions[0].condition,
+            Condition::Comparison(comparison) if comparison.operator == ComparisonOperator::Between
+        ));
+    }
+
+    #[test]
+    fn test_between_bounds_must_match_and_be_in_order() {
+        let error = |bounds: &str| {
+            let input = format!(
+                "A **person** is eligible if the __age__ of the **person** is between {}.",
+                bounds
+            );
+            parse_rules(&input).unwrap_err().to_string()
+        };
+
+        assert!(error("65 and 18").contains("lower bound comes first"));
+        assert!(error("18 and 2024-01-01").contains("both be numbers or both be dates"));
+        assert!(parse_rules(
+            "A **person** is eligible if the __name__ of the **person** is between \"a\" and \"m\"."
+        )
+        .is_err());
+        // Equal bounds are a range of one value
+        assert!(parse_rules(
+            "A **person** is eligible if the __age__ of the **person** is between 18 and 18."
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_parse_quantified_conditions() {
+        use crate::runner::model::Quantifier;
+
+        let rule_set = parse_rules(
+            "A **order** is cheap\n  if all of the __items__ of the **order** have a __price__ less than 100\n  and any of the __tags__ of the **order** is equal to \"sale\".",
+        )
+        .unwrap();
+        let conditions = &rule_set.rules[0].conditions;
+        assert_eq!(conditions.len(), 2);
+
+        let Condition::Quantified(all) = &conditions[0].condition else {
+            panic!("Expected a quantified condition");
+        };
+        assert_eq!(all.quantifier, Quantifier::All);
+        assert_eq!(all.comparison.operator, ComparisonOperator::LessThan);
+        let path = all.comparison.left_property_path.as_ref().unwrap();
+        assert_eq!(path.selector, "order");
+        assert_eq!(path.properties, ["items", "price"]);
+        assert_eq!(path.fan_out, [0]);
+        let (list, keys) = all.list_and_keys().unwrap();
+        assert_eq!(list.properties, ["items"]);
+        assert_eq!(keys, ["price"]);
+
+        let Condition::Quantified(any) = &conditions[1].condition else {
+            panic!("Expected a quantified condition");
+        };
+        assert_eq!(any.quantifier, Quantifier::Any);
+        assert!(any.list_and_keys().unwrap().1.is_empty());
+
+        for input in [
+            "A **order** is cheap if all of each __items__ of the **order** is less than 100.",
+            "A **order** is cheap if all of the **order** is less than 100.",
+        ] {
+            assert!(parse_rules(input).is_err(), "{}", input);
+        }
+    }
+    #[test]
+    fn test_parse_aggregates_mark_the_list_they_are_taken_of() {
+        let rule_set = parse_rules(
+            "A **order** is large\n  if the sum of __amount__ of each __lines__ of the **order** is greater than 100\n  and minimum of __weights__ of the **order** is at least 1\n  and the maximum of __weights__ of the **order** is less than 30\n  and the average of __weights__ of the **order** is between 2 and 10.",
+        )
+        .unwrap();
+        let conditions = &rule_set.rules[0].conditions;
+        let marked: Vec<_> = conditions
+            .iter()
+            .map(|condition| match &condition.condition {
+                Condition::Comparison(comp) => {
+                    let path = comp.left_property_path.as_ref().unwrap();
+                    assert_eq!(comp.property.value, *path.properties.last().unwrap());
+                    let properties: Vec<&str> =
+                        path.properties.iter().map(String::as_str).collect();
+                    (properties, path.fan_out.clone())
+                }
+                _ => panic!("Expected comparison condition"),
+            })
+            .collect();
+        assert_eq!(
+            marked,
+            vec![
+                (vec!["lines", "amount", "__sum_of__"], vec![0]),
+                (vec!["weights", "__minimum_of__"], vec![]),
+                (vec!["weights", "__maximum_of__"], vec![]),
+                (vec!["weights", "__average_of__"], vec![]),
+            ]
+        );
+
+        let error = parse_rules(
+            "A **order** is large if the sum of __weights__ of the **order** is in [1, 2].",
+        )
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("Sum of comparisons"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_parse_element_steps_mark_the_list_they_read() {
+        let rule_set = parse_rules(
+            "A **candidate** is shortlisted\n  if the __status__ of the first of __attempts__ of the **candidate** is equal to \"passed\"\n  and the __score__ of the 3rd of __attempts__ of the **candidate** is greater than 50\n  and the last of __referees__ of the **candidate** is equal to \"Ana\"\n  and the __name__ of the second of __referees__ of the first of __employers__ of the **candidate** is equal to \"Ben\".",
+        )
+        .unwrap();
+        let elements: Vec<_> = rule_set.rules[0]
+            .conditions
+            .iter()
+            .map(|condition| match &condition.condition {
+                Condition::Comparison(comp) => {
+                    comp.left_property_path.as_ref().unwrap().elements.clone()
+                }
+                _ => panic!("Expected comparison condition"),
+            })
+            .collect();
+        assert_eq!(
+            elements,
+            vec![
+                vec![(0, PropertyChainElement::Index(0))],
+                vec![(0, PropertyChainElement::Index(2))],
+                vec![(0, PropertyChainElement::Last)],
+                vec![
+                    (0, PropertyChainElement::Index(0)),
+                    (1, PropertyChainElement::Index(1)),
+                ],
+            ]
+        );
+
+        for (rule, message) in [
+            (
+                "A **candidate** is shortlisted if the __score__ of the 0th of __attempts__ of the **candidate** is greater than 50.",
+                "isn't a position in a list",
+            ),
+            (
+                "A **candidate** is shortlisted if the __score__ of the first of the **candidate** is greater than 50.",
+                "can't mark the root selector",
+            ),
+        ] {
+            let error = parse_rules(rule).unwrap_err();
+            assert!(error.to_string().contains(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_parse_arithmetic_chains_steps_left_to_right() {
+        let condition = parse_condition(
+            "the __theory__ of the **exam** plus the __practical__ of the **exam** multiplied by 2 is at least the __pass mark__ of the **exam**   minus 5",
+        )
+        .unwrap();
+        let (Condition::Comparison(comp), _) = condition else {
+            panic!("Expected comparison condition");
+        };
+        assert!(comp.is_arithmetic());
+        // The first step is the innermost, so the last one written is applied last
+        let Some(RuleExpression::Arithmetic {
+            left,
+            operator,
+            right,
+        }) = &comp.left_expression
+        else {
+            panic!("Expected arithmetic, got {:?}", comp.left_expression);
+        };
+        assert_eq!(*operator, ArithmeticOperator::MultipliedBy);
+        assert!(matches!(**right, RuleExpression::Number(n) if n == 2.0));
+        assert!(matches!(
+            &**left,
+            RuleExpression::Arithmetic {
+                operator: ArithmeticOperator::Plus,
+                ..
+            }
+        ));
+        let paths: Vec<&str> = comp
+            .left_expression
+            .as_ref()
+            .unwrap()
+            .property_paths()
+            .iter()
+            .map(|path| path.properties[0].as_str())
+            .collect();
+        assert_eq!(paths, vec!["theory", "practical"]);
+        assert_eq!(
+            comp.left_property_path.as_ref().unwrap().properties,
+            vec!["theory"]
+        );
+
+        assert!(comp.right_property_path.is_none());
+        assert_eq!(
+            comp.value.value,
+            RuleValue::String("the __pass mark__ of the **exam** minus 5".to_string())
+        );
+        assert!(matches!(
+            comp.right_expression,
+            Some(RuleExpression::Arithmetic {
+                operator: ArithmeticOperator::Minus,
+                ..
+            })
+        ));
+
+        // A duration after `plus` shifts a date instead
+        let (Condition::Comparison(comp), _) = parse_condition(
+            "the __start__ of the **trip** is later than the __booked__ of the **trip** plus 14 days",
+        )
+        .unwrap() else {
+            panic!("Expected comparison condition");
+        };
+        assert!(!comp.is_arithmetic());
+        assert!(comp.date_offset.is_some());
+    }
+
+    #[test]
+    fn test_parse_arithmetic_only_compares_with_numbers() {
+        for (condition, message) in [
+            (
+                "the __score__ of the **exam** plus 5 contains \"A\"",
+                "is a number, which 'contains' can't compare with",
+            ),
+            (
+                "the __score__ of the **exam** plus 5 is in [10, 20]",
+                "is a number, which 'is in' can't compare with",
+            ),
+            (
+                "the __score__ of the **exam** plus 5 is equal to \"pass\"",
+                "is a number, which can't be compared with \"pass\"",
+            ),
+            (
+                "the __name__ of the **exam** contains the __score__ of the **exam** plus 5",
+                "is a number, which 'contains' can't compare with",
+            ),
+        ] {
+            let rule = format!("A **exam** passes the check if {}.", condition);
+            let error = parse_rules(&rule).unwrap_err().to_string();
+            assert!(error.contains(message), "{}: {}", condition, error);
+        }
+    }
+
+    #[test]
+    fn test_parse_percentage_of_a_property() {
+        let (Condition::Comparison(comp), _) = parse_condition(
+            "the __attended sessions__ of the **student** is at least 12.5% of the __total sessions__ of the **course**",
+        )
+        .unwrap() else {
+            panic!("Expected comparison condition");
+        };
+        assert_eq!(comp.operator, ComparisonOperator::GreaterThanOrEqual);
+        let Some(RuleExpression::Percentage { percent, of }) = &comp.right_expression else {
+            panic!("Expected a percentage, got {:?}", comp.right_expression);
+        };
+        assert_eq!(*percent, 12.5);
+        let RuleExpression::Property(path) = &**of else {
+            panic!("Expected a property, got {:?}", of);
+        };
+        assert_eq!(path.selector, "course");
+        assert_eq!(path.properties, vec!["total sessions"]);
+        assert_eq!(
+            comp.value.value,
+            RuleValue::String("12.5% of the __total sessions__ of the **course**".to_string())
+        );
+
+        let error = parse_rules(
+            "A **student** passes the check if the __name__ of the **student** contains 80% of the __title__ of the **course**.",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            error.contains("is a number, which 'contains' can't compare with"),
+            "{}",
+            error
+        );
     }
 }
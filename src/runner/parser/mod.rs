@@ -1,41 +1,161 @@
+//! Turns policy text into a `RuleSet` with the pest grammar in `pests/`.
+//!
+//! When more than one statement fails, parsing recovers at statement boundaries (blank lines or
+//! lines opening a rule) and returns `RuleError::ParseErrors` with a diagnostic per broken
+//! statement; `POST /parse` returns them as `diagnostics`.
+//!
+//! `parse_rule_statement`, `parse_condition`, `parse_property_reference` and `parse_value` parse a
+//! single piece of a policy through the anchored entry rules in `pests/entries.pest`, with the same
+//! positions and errors as a full parse.
+//!
+//! Rules open with `A` or `An` followed by any spaces or tabs. A property can also be written
+//! possessively, `the **employee**'s __manager__'s __grade__` (or `**employee's**`, and
+//! `**employees**'` for a plural), which parses to the same path as `the __grade__ of the
+//! __manager__ of the **employee**`. Plural selectors need nothing special: `the **employees** all
+//! satisfy the checks` is a rule reference for now, and the validator warns
+//! (`unsupported-quantifier`) when a reference starts with `all`, `each`, `every` or `any`, which
+//! are kept for list quantifiers.
+//!
+//! Boolean properties have two shorthands. `the __provisional__ of the **driver** is true` (or `is
+//! false`) is `is equal to true` (or `false`), and `the **driver** has a __provisional__` (`the`
+//! optional, `a`, `an` or no article) is `the __provisional__ of the **driver** is equal to true`.
+//! The `has` form takes one selector and one property and must end the condition; `the **driver**
+//! has a valid licence` stays a rule reference. Neither applies to `length of`/`number of`. Only a
+//! JSON `true` or `false` matches, and `"validate_data": true` rejects any other type. `GET
+//! /capabilities` lists them under `shorthands`.
+//!
+//! A policy that doesn't parse is answered with `diagnostics`, one per problem with its 1-based
+//! `line` and `column`, `code`, `message` and the source line as `snippet`
+//! (`parser::parse_error_diagnostics`). When the grammar rejected the text, `parse_error` also says
+//! where and why: `RuleError::ParseError` carries a `ParseErrorDetail` (`line`, `column`,
+//! `snippet`, the grammar rules `expected` there with underscores as spaces, and the word `found`),
+//! built by `parser::syntax_error` from the pest error. A rule that parsed but isn't valid has
+//! none; `RuleError::parse_error` makes those. Responses carry a `schema_version`: version 1, the
+//! default, also fabricates a `parse_failed` trace for a parse error as older clients expect, at
+//! the `parse_error` line or the first diagnostic's; sending `"schema_version": 2` leaves it out.
+//! Version 1 also echoes the submitted text split into lines as `rule`; version 2 lists the parsed
+//! `rules` instead, in the order written, each with its `label`, `outcome`, and 1-based
+//! `start_line` and `end_line` (the `Tags:` line through the closing period), plus its canonical
+//! text as `formatted` with `"include_formatted_rules": true`.
+
 mod lib;
 
-use crate::runner::error::RuleError;
+use crate::runner::error::{ParseErrorDetail, RuleError};
+use crate::runner::formatter::canonical_condition_text;
 use crate::runner::model::{
-    constants, ComparisonCondition, ComparisonOperator, Condition, ConditionOperator, Duration,
-    PositionedValue, PropertyPath, RuleReferenceCondition, RuleSet, RuleValue, SourcePosition,
-    TimeUnit,
+    constants, ArithmeticOperator, ComparisonCondition, ComparisonOperator, Condition,
+    ConditionGroup, ConditionId, ConditionOperator, DateOffset, DeclaredProperty, DeclaredType,
+    Duration, FlagCondition, OutcomeDeclaration, OutcomeType, PositionedValue,
+    PropertyChainElement, PropertyPath, QuantifiedCondition, Quantifier, RuleExpression,
+    RuleReferenceCondition, RuleSet, RuleValue, SourcePosition, TimeUnit,
 };
+use crate::runner::utils::{json_path_from_keys, names_match};
+use crate::runner::validator::{Diagnostic, Severity};
 use chrono::NaiveDate;
+use pest::error::{ErrorVariant, InputLocation, LineColLocation};
 use pest::iterators::Pair;
 use pest::Parser;
 use pest_derive::Parser;
+use serde::Serialize;
 
 #[derive(Parser)]
 #[grammar = "pests/grammar.pest"]
 #[allow(dead_code)]
 pub struct RuleParser;
 
+/// Size limits applied to inline literal lists while parsing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Lists longer than this get a `large-list` warning from the validator
+    pub list_warning_elements: usize,
+    /// Lists longer than this fail to parse
+    pub max_list_elements: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            list_warning_elements: 1_000,
+            max_list_elements: 50_000,
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub fn parse_rules(input: &str) -> Result<RuleSet, RuleError> {
-    let pairs = RuleParser::parse(Rule::rule_set, input)
-        .map_err(|e| RuleError::ParseError(e.to_string()))?;
+    parse_rules_with_limits(input, &ParseLimits::default())
+}
+
+pub fn parse_rules_with_limits(input: &str, limits: &ParseLimits) -> Result<RuleSet, RuleError> {
+    let rule_set = parse_rule_set_with_limits(input, limits)?;
+    crate::runner::utils::find_global_rule(&rule_set.rules)?;
+    Ok(rule_set)
+}
+
+/// Parses rules without requiring a single global rule, for callers that pick the entry point
+#[allow(dead_code)]
+pub fn parse_rule_set(input: &str) -> Result<RuleSet, RuleError> {
+    parse_rule_set_with_limits(input, &ParseLimits::default())
+}
+
+/// Parses a rule set. When more than one statement is broken, every one of them is reported
+/// as a `ParseErrors` diagnostic instead of stopping at the first. A document with an
+/// `Include "name".` fails, as nothing here can load it, see `include::parse_rules_with_loader`
+pub fn parse_rule_set_with_limits(input: &str, limits: &ParseLimits) -> Result<RuleSet, RuleError> {
+    let rule_set = parse_unresolved(input, limits)?;
+    if let Some(include) = rule_set.includes.first() {
+        return Err(RuleError::IncludeFailed {
+            name: include.value.clone(),
+            line: include.pos.as_ref().map_or(0, |pos| pos.line),
+            reason: "includes aren't resolved here".to_string(),
+        });
+    }
+    Ok(rule_set)
+}
+
+/// Same as `parse_rule_set_with_limits`, leaving the `Include "name".` statements in
+/// `includes` for the caller to resolve
+pub(crate) fn parse_unresolved(input: &str, limits: &ParseLimits) -> Result<RuleSet, RuleError> {
+    parse_document(input, limits).map_err(|error| {
+        let diagnostics = recover_diagnostics(input, limits);
+        if diagnostics.len() > 1 {
+            RuleError::ParseErrors { diagnostics }
+        } else {
+            error
+        }
+    })
+}
+
+fn parse_document(input: &str, limits: &ParseLimits) -> Result<RuleSet, RuleError> {
+    let pairs = RuleParser::parse(Rule::rule_set, input).map_err(|e| syntax_error(e, input))?;
 
     // Pre-estimate rule count for better allocation
     let estimated_rule_count = input
         .lines()
-        .filter(|line| line.trim_start().starts_with("A ") || line.trim_start().starts_with("An "))
+        .filter(|line| opens_rule(line.trim_start()))
         .count();
 
     let mut rule_set = RuleSet::with_capacity(estimated_rule_count.max(10));
+    rule_set.source = input.to_string();
 
     for pair in pairs {
         match pair.as_rule() {
             Rule::rule_set => {
                 for rule_pair in pair.into_inner() {
-                    if rule_pair.as_rule() == Rule::rule {
-                        let rule = parse_rule(rule_pair)?;
-                        rule_set.add_rule(rule)
+                    match rule_pair.as_rule() {
+                        Rule::rule => {
+                            let rule = parse_rule(rule_pair)?;
+                            check_list_limits(&rule, limits)?;
+                            rule_set.add_rule(rule)
+                        }
+                        Rule::include => rule_set.includes.push(parse_include(rule_pair)),
+                        Rule::outcome_declaration => rule_set
+                            .outcome_schema
+                            .push(parse_outcome_declaration(rule_pair)?),
+                        Rule::property_declaration => {
+                            parse_property_declaration(rule_pair, &mut rule_set)?
+                        }
+                        _ => {}
                     }
                 }
             }
@@ -43,11 +163,552 @@ pub fn parse_rules(input: &str) -> Result<RuleSet, RuleError> {
         }
     }
 
-    crate::runner::utils::find_global_rule(&rule_set.rules)?;
-
+    check_outcome_schema(&rule_set)?;
     Ok(rule_set)
 }
 
+/// The document name of an `Include "name".` statement, without its quotes
+fn parse_include(pair: Pair<Rule>) -> PositionedValue<String> {
+    let (line, start) = pair.as_span().start_pos().line_col();
+    let (_, end) = pair.as_span().end_pos().line_col();
+    let name = pair
+        .into_inner()
+        .find(|pair| pair.as_rule() == Rule::include_name)
+        .map(|pair| pair.as_str().trim_matches('"').trim().to_string())
+        .unwrap_or_default();
+    PositionedValue::with_position(name, Some(SourcePosition { line, start, end }))
+}
+
+/// `Outcome tier is one of ["gold", "silver"].` and the other outcome type declarations
+fn parse_outcome_declaration(pair: Pair<Rule>) -> Result<OutcomeDeclaration, RuleError> {
+    let (line, start) = pair.as_span().start_pos().line_col();
+    let (_, end) = pair.as_span().end_pos().line_col();
+    let mut outcome = String::new();
+    let mut kind = OutcomeType::Boolean;
+
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::declared_outcome => {
+                outcome = crate::runner::utils::normalize_outcome(inner.as_str());
+            }
+            Rule::outcome_type => {
+                let declared = inner
+                    .into_inner()
+                    .next()
+                    .ok_or_else(|| RuleError::parse_error("Missing outcome type".to_string()))?;
+                kind = parse_outcome_type(&outcome, declared)?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(OutcomeDeclaration {
+        outcome,
+        kind,
+        pos: Some(SourcePosition { line, start, end }),
+    })
+}
+
+fn parse_outcome_type(outcome: &str, pair: Pair<Rule>) -> Result<OutcomeType, RuleError> {
+    match pair.as_rule() {
+        Rule::outcome_one_of => {
+            let list = pair
+                .into_inner()
+                .next()
+                .ok_or_else(|| RuleError::parse_error("Missing outcome values".to_string()))?;
+            let RuleValue::List(elements) = parse_list_value(list)? else {
+                unreachable!("parse_list_value returns a list")
+            };
+            let values = elements
+                .into_iter()
+                .map(|element| match element {
+                    RuleValue::String(text) => Ok(serde_json::Value::String(text)),
+                    RuleValue::Number(number) if number.fract() == 0.0 => {
+                        Ok(serde_json::json!(number as i64))
+                    }
+                    RuleValue::Number(number) => Ok(serde_json::json!(number)),
+                    RuleValue::Boolean(flag) => Ok(serde_json::Value::Bool(flag)),
+                    other => Err(RuleError::parse_error(format!(
+                        "Outcome '{}' can be one of strings, numbers and booleans, not {}",
+                        outcome, other
+                    ))),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(OutcomeType::OneOf { values })
+        }
+        Rule::outcome_number_range => {
+            let bounds = pair
+                .into_inner()
+                .map(|bound| bound.as_str().parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| RuleError::parse_error(format!("Invalid number: {}", e)))?;
+            let [min, max] = bounds[..] else {
+                return Err(RuleError::parse_error(
+                    "Missing outcome number bounds".to_string(),
+                ));
+            };
+            if min > max {
+                return Err(RuleError::parse_error(format!(
+                    "Outcome '{}' is declared as a number between {} and {}, which no number is",
+                    outcome, min, max
+                )));
+            }
+            Ok(OutcomeType::Number { min, max })
+        }
+        _ => Ok(OutcomeType::Boolean),
+    }
+}
+
+/// `The **driving test** has a __scores__ object.`, adding each property to the catalogue
+/// under the selector, or under every object or list already declared with the property's
+/// name for `The __scores__ has ...`. A property is declared once
+fn parse_property_declaration(pair: Pair<Rule>, rule_set: &mut RuleSet) -> Result<(), RuleError> {
+    let line = pair.as_span().start_pos().line_col().0;
+    let mut parents: Vec<Vec<String>> = Vec::new();
+    let mut declared = Vec::new();
+
+    for inner in pair.into_inner() {
+        let text = inner.as_str();
+        match inner.as_rule() {
+            Rule::object_selector => {
+                parents.push(
+                    text[2..text.len() - 2]
+                        .split('.')
+                        .map(str::to_string)
+                        .collect(),
+                );
+            }
+            Rule::property => {
+                let name = &text[2..text.len() - 2];
+                parents = rule_set
+                    .property_catalogue
+                    .iter()
+                    .filter(|parent| {
+                        parent.kind.has_properties()
+                            && parent
+                                .path
+                                .last()
+                                .is_some_and(|last| names_match(last, name))
+                    })
+                    .map(|parent| parent.path.clone())
+                    .collect();
+                if parents.is_empty() {
+                    return Err(RuleError::parse_error(format!(
+                        "'{}' on line {} isn't declared as an object or a list before it",
+                        name, line
+                    )));
+                }
+            }
+            Rule::declared_property => declared.push(parse_declared_property(inner)?),
+            _ => {}
+        }
+    }
+
+    for parent in &parents {
+        for (name, kind, pos) in &declared {
+            let mut path = parent.clone();
+            path.push(name.clone());
+            if let Some(earlier) = rule_set.declared_property(&path) {
+                return Err(RuleError::parse_error(format!(
+                    "Property '{}' is declared on line {} and again on line {}",
+                    path.join("."),
+                    earlier.pos.as_ref().map_or(0, |pos| pos.line),
+                    line
+                )));
+            }
+            rule_set.property_catalogue.push(DeclaredProperty {
+                path,
+                kind: *kind,
+                pos: pos.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// `a __scores__ object` or `numeric __hazard perception__`
+fn parse_declared_property(
+    pair: Pair<Rule>,
+) -> Result<(String, DeclaredType, Option<SourcePosition>), RuleError> {
+    let (line, start) = pair.as_span().start_pos().line_col();
+    let (_, end) = pair.as_span().end_pos().line_col();
+    let mut name = None;
+    let mut kind = None;
+    for inner in pair.into_inner() {
+        let text = inner.as_str();
+        match inner.as_rule() {
+            Rule::property => name = Some(text[2..text.len() - 2].to_string()),
+            Rule::declared_type => {
+                kind = Some(match text {
+                    "numeric" | "number" => DeclaredType::Number,
+                    "text" | "string" => DeclaredType::Text,
+                    "date" => DeclaredType::Date,
+                    "boolean" => DeclaredType::Boolean,
+                    "list" => DeclaredType::List,
+                    _ => DeclaredType::Object,
+                })
+            }
+            _ => {}
+        }
+    }
+    let (Some(name), Some(kind)) = (name, kind) else {
+        return Err(RuleError::parse_error(
+            "Missing declared property".to_string(),
+        ));
+    };
+    Ok((name, kind, Some(SourcePosition { line, start, end })))
+}
+
+/// Checks the outcome declarations against the rules: an outcome is declared once, and one a
+/// rule decides must admit both `true` and `false`
+pub(crate) fn check_outcome_schema(rule_set: &RuleSet) -> Result<(), RuleError> {
+    let line = |pos: &Option<SourcePosition>| pos.as_ref().map_or(0, |pos| pos.line);
+
+    for (index, declaration) in rule_set.outcome_schema.iter().enumerate() {
+        if let Some(earlier) = rule_set.outcome_schema[..index]
+            .iter()
+            .find(|earlier| earlier.outcome == declaration.outcome)
+        {
+            return Err(RuleError::parse_error(format!(
+                "Outcome '{}' is declared on line {} and again on line {}",
+                declaration.outcome,
+                line(&earlier.pos),
+                line(&declaration.pos)
+            )));
+        }
+
+        let Some(rule) = rule_set.get_rule(&declaration.outcome) else {
+            continue;
+        };
+        let admits_decisions = [true, false]
+            .iter()
+            .all(|&decision| declaration.kind.admits(&serde_json::Value::Bool(decision)));
+        if !admits_decisions {
+            return Err(RuleError::parse_error(format!(
+                "Outcome '{}' is declared as {} on line {}, but the rule on line {} decides it true or false",
+                declaration.outcome,
+                declaration.kind,
+                line(&declaration.pos),
+                line(&rule.position)
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Where a document failed to parse, one entry per problem, for an API response
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParseDiagnostic {
+    /// 1-based line of the document
+    pub line: usize,
+    /// 1-based column within the line
+    pub column: usize,
+    pub code: &'static str,
+    pub message: String,
+    /// The line the problem is on
+    pub snippet: String,
+}
+
+/// The problems behind a failed parse of `input`, with positions. `ParseErrors` carries them
+/// already; a single failure is located by parsing each statement again
+pub fn parse_error_diagnostics(
+    error: &RuleError,
+    input: &str,
+    limits: &ParseLimits,
+) -> Vec<ParseDiagnostic> {
+    let diagnostics = match error.parse_diagnostics() {
+        Some(diagnostics) => diagnostics.to_vec(),
+        None => recover_diagnostics(input, limits),
+    };
+    diagnostics
+        .into_iter()
+        .filter_map(|diagnostic| {
+            let position = diagnostic.position?;
+            Some(ParseDiagnostic {
+                line: position.line,
+                column: position.start,
+                code: diagnostic.code,
+                message: diagnostic.message,
+                snippet: input
+                    .lines()
+                    .nth(position.line.saturating_sub(1))
+                    .unwrap_or_default()
+                    .trim_end()
+                    .to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parses each statement of a document on its own and collects what fails, so one pass
+/// reports every broken statement. Statements that parse are dropped
+fn recover_diagnostics(input: &str, limits: &ParseLimits) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for (line_offset, statement) in split_statements(input) {
+        let pairs = match RuleParser::parse(Rule::rule_set, statement) {
+            Ok(pairs) => pairs,
+            Err(error) => {
+                let ((line, start), end) = match error.line_col {
+                    LineColLocation::Pos((line, col)) => ((line, col), col),
+                    LineColLocation::Span((line, start), (end_line, end)) => {
+                        ((line, start), if end_line == line { end } else { start })
+                    }
+                };
+                diagnostics.push(syntax_diagnostic(
+                    "syntax-error",
+                    error.variant.message().into_owned(),
+                    SourcePosition {
+                        line: line + line_offset,
+                        start,
+                        end,
+                    },
+                ));
+                continue;
+            }
+        };
+
+        let rule_pairs = pairs
+            .flat_map(|pair| pair.into_inner())
+            .filter(|pair| pair.as_rule() == Rule::rule);
+        for rule_pair in rule_pairs {
+            let (line, start) = rule_pair.as_span().start_pos().line_col();
+            let parsed = parse_rule(rule_pair).and_then(|rule| check_list_limits(&rule, limits));
+            if let Err(error) = parsed {
+                let message = match error {
+                    RuleError::ParseError { message, .. } => message,
+                    other => other.to_string(),
+                };
+                diagnostics.push(syntax_diagnostic(
+                    "invalid-rule",
+                    message,
+                    SourcePosition {
+                        line: line + line_offset,
+                        start,
+                        end: start,
+                    },
+                ));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// The `ParseError` for `input` that the grammar rejected with `error`, located by its
+/// `ParseErrorDetail`
+pub(crate) fn syntax_error(error: pest::error::Error<Rule>, input: &str) -> RuleError {
+    let (line, column) = match error.line_col {
+        LineColLocation::Pos(start) | LineColLocation::Span(start, _) => start,
+    };
+    let offset = match error.location {
+        InputLocation::Pos(offset) | InputLocation::Span((offset, _)) => offset,
+    };
+    let expected = match &error.variant {
+        ErrorVariant::ParsingError { positives, .. } => {
+            positives.iter().copied().map(describe_rule).collect()
+        }
+        ErrorVariant::CustomError { .. } => Vec::new(),
+    };
+    let found = input
+        .get(offset..)
+        .and_then(|rest| rest.split_whitespace().next())
+        .unwrap_or("end of input");
+    let detail = ParseErrorDetail {
+        line,
+        column,
+        snippet: input
+            .lines()
+            .nth(line.saturating_sub(1))
+            .unwrap_or_default()
+            .trim_end()
+            .to_string(),
+        expected,
+        found: found.to_string(),
+    };
+    RuleError::ParseError {
+        message: error.to_string(),
+        detail: Some(Box::new(detail)),
+    }
+}
+
+/// A grammar rule as a syntax error names it, `comparison operator` for `comparison_operator`
+fn describe_rule(grammar_rule: Rule) -> String {
+    match grammar_rule {
+        Rule::EOI => "end of input".to_string(),
+        other => format!("{:?}", other).replace('_', " "),
+    }
+}
+
+fn syntax_diagnostic(code: &'static str, message: String, position: SourcePosition) -> Diagnostic {
+    Diagnostic {
+        severity: Severity::Error,
+        code,
+        message,
+        rule: String::new(),
+        position: Some(position),
+        condition: None,
+        related_position: None,
+        suggestion: None,
+        document: None,
+        tags: Vec::new(),
+    }
+}
+
+/// Splits a document into statements with the number of lines before each. A statement
+/// starts after a blank line, on a `Tags:` line, or on a line that opens a rule, with or
+/// without a label, unless it follows a `Tags:` line
+fn split_statements(input: &str) -> Vec<(usize, &str)> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut start_line = 0;
+    let mut offset = 0;
+    let mut previous_blank = false;
+    // A `Tags:` line belongs to the rule after it
+    let mut previous_tags = false;
+
+    for (index, line) in input.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim();
+        let opens_statement = (previous_blank && !trimmed.is_empty())
+            || opens_tags(trimmed)
+            || (starts_rule(trimmed) && !previous_tags);
+        if opens_statement && offset > start {
+            statements.push((start_line, &input[start..offset]));
+            start = offset;
+            start_line = index;
+        }
+        previous_blank = trimmed.is_empty();
+        if !previous_blank {
+            previous_tags = opens_tags(trimmed);
+        }
+        offset += line.len();
+    }
+    if start < input.len() {
+        statements.push((start_line, &input[start..]));
+    }
+
+    statements
+}
+
+fn starts_rule(line: &str) -> bool {
+    opens_rule(line)
+        || line
+            .match_indices(". ")
+            .any(|(end, _)| opens_rule(&line[end + 2..]) && !line[..end].contains("**"))
+}
+
+fn opens_tags(line: &str) -> bool {
+    ["Tags:", "tags:"]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+}
+
+/// Whether `text` starts with the "A" or "An" that opens a rule, as `rule_start` matches it
+fn opens_rule(text: &str) -> bool {
+    ["An", "A"].iter().any(|article| {
+        text.strip_prefix(article)
+            .is_some_and(|rest| rest.starts_with([' ', '\t']))
+    })
+}
+
+fn check_list_limits(
+    rule: &crate::runner::model::Rule,
+    limits: &ParseLimits,
+) -> Result<(), RuleError> {
+    for group in &rule.conditions {
+        check_condition_list_limits(&group.condition, limits)?;
+    }
+    Ok(())
+}
+
+fn check_condition_list_limits(
+    condition: &Condition,
+    limits: &ParseLimits,
+) -> Result<(), RuleError> {
+    if let Condition::Group(members) = condition {
+        for member in members {
+            check_condition_list_limits(&member.condition, limits)?;
+        }
+    }
+    if let Condition::Comparison(comparison)
+    | Condition::Quantified(QuantifiedCondition { comparison, .. }) = condition
+    {
+        if let RuleValue::List(items) = &comparison.value.value {
+            if items.len() > limits.max_list_elements {
+                let line = comparison.value.pos.as_ref().map_or(0, |pos| pos.line);
+                return Err(RuleError::parse_error(format!(
+                    "List on line {} has {} elements, more than the limit of {}. Put large value sets in the data and compare with 'is in __values__ of **...**' instead",
+                    line,
+                    items.len(),
+                    limits.max_list_elements
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The piece of `input` an entry rule wraps, failing unless it spans the whole input
+fn parse_entry<'i>(
+    entry: Rule,
+    inner: &[Rule],
+    input: &'i str,
+) -> Result<Pair<'i, Rule>, RuleError> {
+    RuleParser::parse(entry, input)
+        .map_err(|e| syntax_error(e, input))?
+        .next()
+        .and_then(|pair| {
+            pair.into_inner()
+                .find(|pair| inner.contains(&pair.as_rule()))
+        })
+        .ok_or_else(|| RuleError::parse_error(format!("Expected {:?}", inner)))
+}
+
+/// Parses one rule without a surrounding rule set, for tools that work a rule at a time
+#[allow(dead_code)]
+pub fn parse_rule_statement(input: &str) -> Result<crate::runner::model::Rule, RuleError> {
+    let rule = parse_rule(parse_entry(Rule::rule_statement, &[Rule::rule], input)?)?;
+    check_list_limits(&rule, &ParseLimits::default())?;
+    Ok(rule)
+}
+
+/// Parses one condition without a surrounding rule, e.g. as an author types it, with whether
+/// it is negated. A closing period is optional and positions are relative to `input`
+pub fn parse_condition(input: &str) -> Result<(Condition, bool), RuleError> {
+    let pair = parse_entry(Rule::condition_fragment, &[Rule::condition], input)?;
+    let (condition, negated) = parse_condition_pair(pair, "", &mut Vec::new())?;
+    check_condition_list_limits(&condition, &ParseLimits::default())?;
+    Ok((condition, negated))
+}
+
+/// Parses a property reference such as `the __age__ of the **user**`
+#[allow(dead_code)]
+pub fn parse_property_reference(input: &str) -> Result<PropertyPath, RuleError> {
+    parse_property_access(parse_entry(
+        Rule::property_reference,
+        &[Rule::property_access],
+        input,
+    )?)
+}
+
+/// Parses a literal value or list, with its position in `input`
+#[allow(dead_code)]
+pub fn parse_value(input: &str) -> Result<PositionedValue<RuleValue>, RuleError> {
+    let pair = parse_entry(Rule::value_literal, &[Rule::value, Rule::list_value], input)?;
+
+    let span = pair.as_span();
+    let (line, start) = span.start_pos().line_col();
+    let (_, end) = span.end_pos().line_col();
+    let value = match pair.as_rule() {
+        Rule::list_value => parse_list_value(pair)?,
+        _ => parse_value_pair(pair)?,
+    };
+    Ok(PositionedValue::with_position(
+        value,
+        Some(SourcePosition { line, start, end }),
+    ))
+}
+
 pub fn parse_rule(pair: Pair<Rule>) -> Result<crate::runner::model::Rule, RuleError> {
     let span = pair.as_span();
     let (line, _) = span.start_pos().line_col();
@@ -55,11 +716,16 @@ pub fn parse_rule(pair: Pair<Rule>) -> Result<crate::runner::model::Rule, RuleEr
     let end = span.end();
     let position = Some(SourcePosition { line, start, end });
 
-    let mut inner_pairs = pair.into_inner();
+    let mut inner_pairs = pair.into_inner().peekable();
+
+    let tags = match inner_pairs.next_if(|pair| pair.as_rule() == Rule::rule_tags) {
+        Some(tags_pair) => parse_rule_tags(tags_pair),
+        None => Vec::new(),
+    };
 
     let header_pair = inner_pairs
         .next()
-        .ok_or_else(|| RuleError::ParseError("Missing rule header".to_string()))?;
+        .ok_or_else(|| RuleError::parse_error("Missing rule header".to_string()))?;
 
     let mut label: Option<String> = None;
     let mut selector = String::new();
@@ -91,19 +757,19 @@ pub fn parse_rule(pair: Pair<Rule>) -> Result<crate::runner::model::Rule, RuleEr
     }
 
     if selector.is_empty() {
-        return Err(RuleError::ParseError(
+        return Err(RuleError::parse_error(
             "Missing selector in rule".to_string(),
         ));
     }
 
     let outcome_pair = inner_pairs
         .next()
-        .ok_or_else(|| RuleError::ParseError("Missing outcome".to_string()))?;
+        .ok_or_else(|| RuleError::parse_error("Missing outcome".to_string()))?;
 
     let mut oi = outcome_pair.into_inner();
     let first = oi
         .next()
-        .ok_or_else(|| RuleError::ParseError("Empty outcome".to_string()))?
+        .ok_or_else(|| RuleError::parse_error("Empty outcome".to_string()))?
         .as_str()
         .trim()
         .to_string();
@@ -113,16 +779,37 @@ pub fn parse_rule(pair: Pair<Rule>) -> Result<crate::runner::model::Rule, RuleEr
         first
     };
 
-    let mut rule =
-        crate::runner::model::Rule::new(label.clone(), selector.clone(), outcome_text.clone());
+    let mut rule = crate::runner::model::Rule::new(
+        label.clone(),
+        selector.clone(),
+        crate::runner::utils::normalize_outcome(&outcome_text),
+    );
+    rule.outcome_display = outcome_text;
     rule.position = position;
     rule.selector_pos = selector_pos;
+    rule.tags = tags;
 
     let remaining_pairs: Vec<_> = inner_pairs.collect();
+    if let Some(aliases) = remaining_pairs
+        .iter()
+        .find(|pair| pair.as_rule() == Rule::outcome_aliases)
+    {
+        rule.aliases = aliases
+            .clone()
+            .into_inner()
+            .map(parse_outcome_alias)
+            .collect();
+    }
+
     let mut i = 0;
     while i < remaining_pairs.len() {
         if remaining_pairs[i].as_rule() == Rule::condition {
-            let cond = parse_condition(remaining_pairs[i].clone())?;
+            let canonical_text = canonical_condition_text(remaining_pairs[i].clone());
+            let (cond, negated) = parse_condition_pair(
+                remaining_pairs[i].clone(),
+                &rule.outcome,
+                &mut rule.condition_ids(),
+            )?;
 
             let op = if rule.conditions.is_empty() {
                 None
@@ -137,7 +824,7 @@ pub fn parse_rule(pair: Pair<Rule>) -> Result<crate::runner::model::Rule, RuleEr
                 found.or(Some(ConditionOperator::And))
             };
 
-            rule.add_condition(cond, op);
+            rule.add_parsed_condition(cond, op, negated, &canonical_text);
         }
         i += 1;
     }
@@ -145,48 +832,144 @@ pub fn parse_rule(pair: Pair<Rule>) -> Result<crate::runner::model::Rule, RuleEr
     Ok(rule)
 }
 
+/// `Tags: KYC, fraud.` as `["kyc", "fraud"]`, each once
+fn parse_rule_tags(pair: Pair<Rule>) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in pair.into_inner() {
+        let tag = tag.as_str().to_lowercase();
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// `flag "new-checkout" is enabled`
+fn parse_flag_condition(pair: Pair<Rule>) -> FlagCondition {
+    let mut flag = PositionedValue::new(String::new());
+    let mut enabled = true;
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::flag_name => {
+                let span = inner.as_span();
+                let (line, start) = span.start_pos().line_col();
+                let (_, end) = span.end_pos().line_col();
+                let text = inner.as_str();
+                flag = PositionedValue::with_position(
+                    text[1..text.len() - 1].trim().to_string(),
+                    Some(SourcePosition { line, start, end }),
+                );
+            }
+            Rule::flag_state => enabled = inner.as_str() == "enabled",
+            _ => {}
+        }
+    }
+    FlagCondition { flag, enabled }
+}
+
+/// A quoted alias, normalized like the outcome so references find it the same way
+fn parse_outcome_alias(pair: Pair<Rule>) -> PositionedValue<String> {
+    let span = pair.as_span();
+    let (line, start) = span.start_pos().line_col();
+    let (_, end) = span.end_pos().line_col();
+    let text = pair.as_str();
+    PositionedValue::with_position(
+        crate::runner::utils::normalize_outcome(&text[1..text.len() - 1]),
+        Some(SourcePosition { line, start, end }),
+    )
+}
+
 fn parse_condition_operator(pair: Pair<Rule>) -> Result<ConditionOperator, RuleError> {
     match pair.as_str() {
         "and" => Ok(ConditionOperator::And),
         "or" => Ok(ConditionOperator::Or),
-        _ => Err(RuleError::ParseError(format!(
+        _ => Err(RuleError::parse_error(format!(
             "Unknown condition operator: {}",
             pair.as_str()
         ))),
     }
 }
 
-fn parse_condition(pair: Pair<Rule>) -> Result<Condition, RuleError> {
-    let inner_pair = pair
-        .into_inner()
+/// The condition, and whether it is negated: by a leading `not`, or a reference by `does
+/// not`. Both cancel out. The conditions of a group get ids for `outcome` that `taken`
+/// doesn't hold yet, which are added to it
+fn parse_condition_pair(
+    pair: Pair<Rule>,
+    outcome: &str,
+    taken: &mut Vec<ConditionId>,
+) -> Result<(Condition, bool), RuleError> {
+    let mut inner = pair.into_inner().peekable();
+    let negated = inner
+        .next_if(|inner_pair| inner_pair.as_rule() == Rule::negation)
+        .is_some();
+    let inner_pair = inner
         .next()
-        .ok_or_else(|| RuleError::ParseError("Empty condition".to_string()))?;
+        .ok_or_else(|| RuleError::parse_error("Empty condition".to_string()))?;
 
     match inner_pair.as_rule() {
         Rule::property_condition => {
             let comparison = parse_property_condition(inner_pair)?;
-            Ok(Condition::Comparison(comparison))
+            Ok((Condition::Comparison(comparison), negated))
         }
         Rule::rule_reference => {
-            let reference = parse_rule_reference(inner_pair)?;
-            Ok(Condition::RuleReference(reference))
+            let (reference, reference_negated) = parse_rule_reference(inner_pair)?;
+            Ok((
+                Condition::RuleReference(reference),
+                negated != reference_negated,
+            ))
         }
         Rule::label_reference => {
             let reference = parse_label_reference(inner_pair)?;
-            Ok(Condition::RuleReference(reference))
+            Ok((Condition::RuleReference(reference), negated))
         }
-        _ => Err(RuleError::ParseError(format!(
+        Rule::flag_condition => Ok((Condition::Flag(parse_flag_condition(inner_pair)), negated)),
+        Rule::quantified_condition => Ok((
+            Condition::Quantified(parse_quantified_condition(inner_pair)?),
+            negated,
+        )),
+        Rule::condition_group => Ok((
+            Condition::Group(parse_condition_group(inner_pair, outcome, taken)?),
+            negated,
+        )),
+        _ => Err(RuleError::parse_error(format!(
             "Unknown condition type: {:?}",
             inner_pair.as_rule()
         ))),
     }
 }
 
+/// The conditions between a group's parentheses, each with the operator before it
+fn parse_condition_group(
+    pair: Pair<Rule>,
+    outcome: &str,
+    taken: &mut Vec<ConditionId>,
+) -> Result<Vec<ConditionGroup>, RuleError> {
+    let mut members = Vec::new();
+    let mut operator = None;
+    for inner in pair.into_inner() {
+        if inner.as_rule() == Rule::condition_operator {
+            operator = Some(parse_condition_operator(inner)?);
+            continue;
+        }
+        let canonical_text = canonical_condition_text(inner.clone());
+        let (condition, negated) = parse_condition_pair(inner, outcome, taken)?;
+        let id = ConditionId::unused(outcome, &canonical_text, taken);
+        taken.push(id.clone());
+        members.push(ConditionGroup {
+            condition,
+            operator: operator.take(),
+            id,
+            negated,
+        });
+    }
+    Ok(members)
+}
+
 fn parse_label_reference(pair: Pair<Rule>) -> Result<RuleReferenceCondition, RuleError> {
     let mut inner_parts = pair.into_inner();
     let label_name_pair = inner_parts
         .next()
-        .ok_or_else(|| RuleError::ParseError("Missing label name".to_string()))?;
+        .ok_or_else(|| RuleError::parse_error("Missing label name".to_string()))?;
 
     let span = label_name_pair.as_span();
     let (line, start_col) = span.start_pos().line_col();
@@ -211,116 +994,177 @@ fn parse_property_condition(pair: Pair<Rule>) -> Result<ComparisonCondition, Rul
     // Parse the left side - could be property_access or length_expr
     let left_access_pair = inner_pairs
         .next()
-        .ok_or_else(|| RuleError::ParseError("Missing property access".to_string()))?;
+        .ok_or_else(|| RuleError::parse_error("Missing property access".to_string()))?;
 
     // Check what type of left side we have
     match left_access_pair.as_rule() {
-        Rule::length_of_expr => {
-            // Handle length expression
-            parse_length_of_condition(left_access_pair, inner_pairs)
+        Rule::length_of_expr => parse_marked_condition(
+            left_access_pair,
+            inner_pairs,
+            constants::LENGTH_OF_MARKER,
+            "length of",
+        ),
+        Rule::number_of_expr => parse_marked_condition(
+            left_access_pair,
+            inner_pairs,
+            constants::NUMBER_OF_MARKER,
+            "number of",
+        ),
+        Rule::hash_bucket_expr => parse_marked_condition(
+            left_access_pair,
+            inner_pairs,
+            constants::HASH_BUCKET_MARKER,
+            "hash bucket of",
+        ),
+        Rule::aggregate_expr => {
+            let (marker, phrase) = match left_access_pair
+                .clone()
+                .into_inner()
+                .find(|pair| pair.as_rule() == Rule::aggregate)
+                .map(|pair| pair.as_str())
+            {
+                Some("sum") => (constants::SUM_OF_MARKER, "sum of"),
+                Some("minimum") => (constants::MINIMUM_OF_MARKER, "minimum of"),
+                Some("maximum") => (constants::MAXIMUM_OF_MARKER, "maximum of"),
+                _ => (constants::AVERAGE_OF_MARKER, "average of"),
+            };
+            parse_marked_condition(left_access_pair, inner_pairs, marker, phrase)
         }
-        Rule::number_of_expr => parse_number_of_condition(left_access_pair, inner_pairs),
+        Rule::has_property => parse_has_property_condition(left_access_pair),
+        Rule::in_sample => parse_in_sample_condition(left_access_pair),
         Rule::property_access => {
             // Handle regular property access (existing logic)
             parse_regular_property_condition(left_access_pair, inner_pairs)
         }
-        _ => Err(RuleError::ParseError(
+        _ => Err(RuleError::parse_error(
             "Expected property access or length expression".to_string(),
         )),
     }
 }
 
-fn parse_length_of_condition(
-    length_of_expr_pair: Pair<Rule>,
-    mut remaining_pairs: pest::iterators::Pairs<Rule>,
-) -> Result<ComparisonCondition, RuleError> {
-    // Parse the length expression to get the property path
-    let property_path = parse_length_of_expression(length_of_expr_pair)?;
-
-    // Parse the predicate
-    let predicate_pair = remaining_pairs.next().ok_or_else(|| {
-        RuleError::ParseError("Missing predicate after length of expression".to_string())
-    })?;
-
-    let mut predicate_inner = predicate_pair.into_inner();
+/// `all of the __items__ of the **order** have a __price__ less than 100`, whose comparison
+/// reads `the __price__ of each __items__ of the **order**`
+fn parse_quantified_condition(pair: Pair<Rule>) -> Result<QuantifiedCondition, RuleError> {
+    let mut inner = pair.into_inner();
+    let quantifier = match inner.next().map(|pair| pair.as_str()) {
+        Some("all") => Quantifier::All,
+        Some("any") => Quantifier::Any,
+        Some("none") => Quantifier::None,
+        other => {
+            return Err(RuleError::parse_error(format!(
+                "Expected all, any or none, got {:?}",
+                other
+            )))
+        }
+    };
 
-    // Parse operator
-    let operator_pair = predicate_inner
+    let list_pair = inner
         .next()
-        .ok_or_else(|| RuleError::ParseError("Missing operator".to_string()))?;
+        .ok_or_else(|| RuleError::parse_error("Missing list".to_string()))?;
+    let list_text = list_pair.as_str().to_string();
+    let list_span = list_pair.as_span();
+    let mut path = parse_property_access(list_pair)?;
+    if !path.fan_out.is_empty() {
+        return Err(RuleError::parse_error(format!(
+            "'{}' of {} already reads every element; leave out 'each'",
+            quantifier, list_text
+        )));
+    }
+    if path.properties.is_empty() {
+        return Err(RuleError::parse_error(format!(
+            "'{} of' needs a property holding the list, not {} on its own",
+            quantifier, list_text
+        )));
+    }
+    path.fan_out.push(path.properties.len() - 1);
 
-    let operator = match operator_pair.as_rule() {
-        Rule::comparison_operator => match operator_pair.as_str() {
-            "is greater than or equal to" => ComparisonOperator::GreaterThanOrEqual,
-            "is at least" => ComparisonOperator::GreaterThanOrEqual,
-            "is less than or equal to" => ComparisonOperator::LessThanOrEqual,
-            "is no more than" => ComparisonOperator::LessThanOrEqual,
-            "is exactly equal to" => ComparisonOperator::ExactlyEqualTo,
-            "is equal to" => ComparisonOperator::EqualTo,
-            "is the same as" => ComparisonOperator::EqualTo,
-            "is not equal to" => ComparisonOperator::NotEqualTo,
-            "is not the same as" => ComparisonOperator::NotEqualTo,
-            "is greater than" => ComparisonOperator::GreaterThan,
-            "is less than" => ComparisonOperator::LessThan,
-            _ => {
-                return Err(RuleError::ParseError(format!(
-                    "Unsupported operator for length of comparison: {}",
-                    operator_pair.as_str()
-                )))
-            }
-        },
-        _ => {
-            return Err(RuleError::ParseError(
-                "Length of comparisons require comparison operators".to_string(),
-            ))
-        }
+    // The element's property when there is one, which the comparison is positioned at
+    let mut next = inner
+        .next()
+        .ok_or_else(|| RuleError::parse_error("Missing predicate".to_string()))?;
+    let span = if next.as_rule() == Rule::property {
+        let text = next.as_str();
+        path.properties.push(text[2..text.len() - 2].to_string());
+        let span = next.as_span();
+        next = inner
+            .next()
+            .ok_or_else(|| RuleError::parse_error("Missing predicate".to_string()))?;
+        span
+    } else {
+        list_span
     };
+    let (line, start) = span.start_pos().line_col();
+    let (_, end) = span.end_pos().line_col();
+    let left_pos = Some(SourcePosition { line, start, end });
 
-    // Parse right operand (should be a number for length comparisons)
-    let right_pair = predicate_inner
-        .next()
-        .ok_or_else(|| RuleError::ParseError("Missing right operand".to_string()))?;
+    Ok(QuantifiedCondition {
+        quantifier,
+        comparison: parse_predicate(path, left_pos, next)?,
+    })
+}
 
-    let right_value = match right_pair.as_rule() {
-        Rule::value => {
-            let value_span = right_pair.as_span();
-            let (value_line, start_col) = value_span.start_pos().line_col();
-            let (_, end_col) = value_span.end_pos().line_col();
-            let val_pos = Some(SourcePosition {
-                line: value_line,
-                start: start_col,
-                end: end_col,
-            });
-            PositionedValue::with_position(parse_value(right_pair)?, val_pos)
-        }
-        _ => {
-            return Err(RuleError::ParseError(
-                "Length of comparisons require a numeric value".to_string(),
-            ))
+/// `the **driver** has a __provisional__`, read as `the __provisional__ of the **driver** is
+/// equal to true`
+fn parse_has_property_condition(pair: Pair<Rule>) -> Result<ComparisonCondition, RuleError> {
+    let span = pair.as_span();
+    let mut selector = None;
+    let mut property = None;
+    for inner in pair.into_inner() {
+        let text = inner.as_str();
+        match inner.as_rule() {
+            Rule::object_selector => selector = Some(text[2..text.len() - 2].to_string()),
+            Rule::property => property = Some(text[2..text.len() - 2].to_string()),
+            _ => {}
         }
+    }
+    let (Some(selector), Some(property)) = (selector, property) else {
+        return Err(RuleError::parse_error(
+            "Expected a selector and a property around 'has'".to_string(),
+        ));
     };
 
+    // The condition's text stands in for the `true` it implies
+    let (line, start_col) = span.start_pos().line_col();
+    let (_, end_col) = span.end_pos().line_col();
+    let pos = Some(SourcePosition {
+        line,
+        start: start_col,
+        end: end_col,
+    });
+
     Ok(ComparisonCondition {
-        selector: PositionedValue::new(property_path.selector.clone()),
-        property: PositionedValue::from_static(constants::LENGTH_OF_MARKER), // Special marker for length
-        operator,
-        value: right_value,
+        selector: PositionedValue::new(selector.clone()),
+        property: PositionedValue::new(property.clone()),
+        operator: ComparisonOperator::EqualTo,
+        value: PositionedValue::with_position(RuleValue::Boolean(true), pos),
         property_chain: None,
-        left_property_path: Some(property_path),
+        left_property_path: Some(PropertyPath {
+            properties: vec![property],
+            selector,
+            fan_out: Vec::new(),
+            elements: Vec::new(),
+        }),
         right_property_path: None,
+        date_offset: None,
+        today: false,
+        left_expression: None,
+        right_expression: None,
     })
 }
 
-fn parse_number_of_condition(
-    number_of_expr_pair: Pair<Rule>,
+/// `length of`, `number of` and `hash bucket of` conditions: the path ends in the operation's
+/// marker and the predicate must compare the computed number
+fn parse_marked_condition(
+    expr_pair: Pair<Rule>,
     mut remaining_pairs: pest::iterators::Pairs<Rule>,
+    marker: &'static str,
+    phrase: &str,
 ) -> Result<ComparisonCondition, RuleError> {
-    // Parse the length expression to get the property path
-    let property_path = parse_number_of_expression(number_of_expr_pair)?;
+    let property_path = parse_marked_expression(expr_pair, marker)?;
 
     // Parse the predicate
     let predicate_pair = remaining_pairs.next().ok_or_else(|| {
-        RuleError::ParseError("Missing predicate after number of expression".to_string())
+        RuleError::parse_error(format!("Missing predicate after {} expression", phrase))
     })?;
 
     let mut predicate_inner = predicate_pair.into_inner();
@@ -328,7 +1172,10 @@ fn parse_number_of_condition(
     // Parse operator
     let operator_pair = predicate_inner
         .next()
-        .ok_or_else(|| RuleError::ParseError("Missing operator".to_string()))?;
+        .ok_or_else(|| RuleError::parse_error("Missing operator".to_string()))?;
+
+    let mut capitalized = phrase.to_string();
+    capitalized[..1].make_ascii_uppercase();
 
     let operator = match operator_pair.as_rule() {
         Rule::comparison_operator => match operator_pair.as_str() {
@@ -344,23 +1191,26 @@ fn parse_number_of_condition(
             "is greater than" => ComparisonOperator::GreaterThan,
             "is less than" => ComparisonOperator::LessThan,
             _ => {
-                return Err(RuleError::ParseError(format!(
-                    "Unsupported operator for number of comparison: {}",
+                return Err(RuleError::parse_error(format!(
+                    "Unsupported operator for {} comparison: {}",
+                    phrase,
                     operator_pair.as_str()
                 )))
             }
         },
+        Rule::between_operator => parse_between_operator(&operator_pair)?,
         _ => {
-            return Err(RuleError::ParseError(
-                "Number of comparisons require comparison operators".to_string(),
-            ))
+            return Err(RuleError::parse_error(format!(
+                "{} comparisons require comparison operators",
+                capitalized
+            )))
         }
     };
 
-    // Parse right operand (should be a number for length comparisons)
+    // Parse right operand (should be a number for computed comparisons)
     let right_pair = predicate_inner
         .next()
-        .ok_or_else(|| RuleError::ParseError("Missing right operand".to_string()))?;
+        .ok_or_else(|| RuleError::parse_error("Missing right operand".to_string()))?;
 
     let right_value = match right_pair.as_rule() {
         Rule::value => {
@@ -372,50 +1222,112 @@ fn parse_number_of_condition(
                 start: start_col,
                 end: end_col,
             });
-            PositionedValue::with_position(parse_value(right_pair)?, val_pos)
+            PositionedValue::with_position(parse_value_pair(right_pair)?, val_pos)
+        }
+        Rule::range_value => {
+            let value_span = right_pair.as_span();
+            let (value_line, start_col) = value_span.start_pos().line_col();
+            let (_, end_col) = value_span.end_pos().line_col();
+            let val_pos = Some(SourcePosition {
+                line: value_line,
+                start: start_col,
+                end: end_col,
+            });
+            PositionedValue::with_position(parse_range_value(right_pair)?, val_pos)
         }
         _ => {
-            return Err(RuleError::ParseError(
-                "Number of comparisons require a numeric value".to_string(),
-            ))
+            return Err(RuleError::parse_error(format!(
+                "{} comparisons require a numeric value",
+                capitalized
+            )))
         }
     };
 
     Ok(ComparisonCondition {
         selector: PositionedValue::new(property_path.selector.clone()),
-        property: PositionedValue::from_static(constants::NUMBER_OF_MARKER), // Special marker for number_of
+        property: PositionedValue::from_static(marker),
         operator,
         value: right_value,
         property_chain: None,
         left_property_path: Some(property_path),
         right_property_path: None,
+        date_offset: None,
+        today: false,
+        left_expression: None,
+        right_expression: None,
     })
 }
 
-fn parse_length_of_expression(pair: Pair<Rule>) -> Result<PropertyPath, RuleError> {
-    let mut inner_pairs = pair.into_inner();
-
-    let property_access_pair = inner_pairs
-        .next()
-        .ok_or_else(|| RuleError::ParseError("Missing property access".to_string()))?;
+fn parse_marked_expression(pair: Pair<Rule>, marker: &str) -> Result<PropertyPath, RuleError> {
+    let property_access_pair = pair
+        .into_inner()
+        .find(|pair| pair.as_rule() == Rule::property_access)
+        .ok_or_else(|| RuleError::parse_error("Missing property access".to_string()))?;
 
     let mut path = parse_property_access(property_access_pair)?;
-    path.properties.push("__length_of__".to_string());
+    path.properties.push(marker.to_string());
 
     Ok(path)
 }
 
-fn parse_number_of_expression(pair: Pair<Rule>) -> Result<PropertyPath, RuleError> {
-    let mut inner_pairs = pair.into_inner();
-
-    let property_access_pair = inner_pairs
-        .next()
-        .ok_or_else(|| RuleError::ParseError("Missing property access".to_string()))?;
+/// `the **user** is in the 5% sample by __user id__`, read as `the hash bucket of
+/// __user id__ of the **user** is less than 5`
+fn parse_in_sample_condition(pair: Pair<Rule>) -> Result<ComparisonCondition, RuleError> {
+    let mut selector = None;
+    let mut property = None;
+    let mut percentage = None;
+    for inner in pair.into_inner() {
+        let text = inner.as_str();
+        match inner.as_rule() {
+            Rule::object_selector => selector = Some(text[2..text.len() - 2].to_string()),
+            Rule::property => property = Some(text[2..text.len() - 2].to_string()),
+            Rule::number => percentage = Some(inner),
+            _ => {}
+        }
+    }
+    let (Some(selector), Some(property), Some(percentage)) = (selector, property, percentage)
+    else {
+        return Err(RuleError::parse_error(
+            "Expected a selector, a percentage and a property in a sample condition".to_string(),
+        ));
+    };
 
-    let mut path = parse_property_access(property_access_pair)?;
-    path.properties.push("__number_of__".to_string());
+    let size = match percentage.as_str().parse::<u8>() {
+        Ok(size) if size <= 100 => size,
+        _ => {
+            return Err(RuleError::parse_error(format!(
+                "Sample sizes are whole percentages from 0 to 100, not {}%",
+                percentage.as_str()
+            )))
+        }
+    };
+    let span = percentage.as_span();
+    let (line, start_col) = span.start_pos().line_col();
+    let (_, end_col) = span.end_pos().line_col();
+    let pos = Some(SourcePosition {
+        line,
+        start: start_col,
+        end: end_col,
+    });
 
-    Ok(path)
+    Ok(ComparisonCondition {
+        selector: PositionedValue::new(selector.clone()),
+        property: PositionedValue::from_static(constants::HASH_BUCKET_MARKER),
+        operator: ComparisonOperator::LessThan,
+        value: PositionedValue::with_position(RuleValue::Number(size as f64), pos),
+        property_chain: None,
+        left_property_path: Some(PropertyPath {
+            properties: vec![property, constants::HASH_BUCKET_MARKER.to_string()],
+            selector,
+            fan_out: Vec::new(),
+            elements: Vec::new(),
+        }),
+        right_property_path: None,
+        date_offset: None,
+        today: false,
+        left_expression: None,
+        right_expression: None,
+    })
 }
 
 #[allow(dead_code)]
@@ -445,10 +1357,11 @@ fn parse_comparison_operator(pair: Pair<Rule>) -> Result<ComparisonOperator, Rul
         "contains" => Ok(ComparisonOperator::Contains),
         "is empty" => Ok(ComparisonOperator::IsEmpty),
         "is not empty" => Ok(ComparisonOperator::IsNotEmpty),
+        "is not within" => Ok(ComparisonOperator::NotWithin),
         "is within" => Ok(ComparisonOperator::Within),
         "is older than" => Ok(ComparisonOperator::OlderThan),
         "is younger than" => Ok(ComparisonOperator::YoungerThan),
-        _ => Err(RuleError::ParseError(format!(
+        _ => Err(RuleError::parse_error(format!(
             "Unknown operator: {}",
             pair.as_str()
         ))),
@@ -460,19 +1373,147 @@ fn parse_regular_property_condition(
     mut remaining_pairs: pest::iterators::Pairs<Rule>,
 ) -> Result<ComparisonCondition, RuleError> {
     // This is the EXISTING logic from the original parse_property_condition function
+    let left_span = property_access_pair.as_span();
+    let (left_line, left_start) = left_span.start_pos().line_col();
+    let mut left_end = left_span.end_pos().line_col().1;
+    let mut left_text = vec![property_access_pair.as_str()];
     let left_path = parse_property_access(property_access_pair)?;
 
-    // Parse the predicate
-    let predicate_pair = remaining_pairs
+    // Arithmetic on the property, then the predicate
+    let mut steps = Vec::new();
+    let predicate_pair = loop {
+        let pair = remaining_pairs
+            .next()
+            .ok_or_else(|| RuleError::parse_error("Missing predicate".to_string()))?;
+        if pair.as_rule() != Rule::arithmetic_step {
+            break pair;
+        }
+        left_end = pair.as_span().end_pos().line_col().1;
+        left_text.push(pair.as_str());
+        steps.push(parse_arithmetic_step(pair)?);
+    };
+    let left_pos = Some(SourcePosition {
+        line: left_line,
+        start: left_start,
+        end: left_end,
+    });
+
+    let left_expression = (!steps.is_empty())
+        .then(|| RuleExpression::chain(RuleExpression::Property(left_path.clone()), steps));
+    let mut condition = parse_predicate(left_path, left_pos, predicate_pair)?;
+    if left_expression.is_some() {
+        // Arithmetic yields a number, so it can only be compared with another
+        let left_text = left_text.join(" ");
+        if !condition.operator.compares_numbers() {
+            return Err(RuleError::parse_error(format!(
+                "'{}' on line {} is a number, which '{}' can't compare with",
+                left_text, left_line, condition.operator
+            )));
+        }
+        let right_is_number = condition.right_expression.is_some()
+            || condition.right_property_path.is_some()
+            || matches!(condition.value.value, RuleValue::Number(_));
+        if !right_is_number {
+            return Err(RuleError::parse_error(format!(
+                "'{}' on line {} is a number, which can't be compared with {}",
+                left_text, left_line, condition.value.value
+            )));
+        }
+    }
+    condition.left_expression = left_expression;
+    Ok(condition)
+}
+
+/// `the __limit__ of the **customer** multiplied by 1.2` on the right of a comparison
+fn parse_arithmetic_expression(pair: Pair<Rule>) -> Result<RuleExpression, RuleError> {
+    let mut inner = pair.into_inner();
+    let first = inner
         .next()
-        .ok_or_else(|| RuleError::ParseError("Missing predicate".to_string()))?;
+        .ok_or_else(|| RuleError::parse_error("Missing arithmetic operand".to_string()))?;
+    let first = parse_arithmetic_operand(first)?;
+    let steps = inner
+        .map(parse_arithmetic_step)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(RuleExpression::chain(first, steps))
+}
 
+/// `80% of the __total sessions__ of the **course**`, which only the operators comparing
+/// numbers take
+fn parse_percentage_of(pair: Pair<Rule>) -> Result<RuleExpression, RuleError> {
+    let mut inner = pair.into_inner();
+    let percent = match inner.next().map(parse_value_pair).transpose()? {
+        Some(RuleValue::Number(percent)) => percent,
+        _ => return Err(RuleError::parse_error("Missing percentage".to_string())),
+    };
+    let of = inner
+        .next()
+        .ok_or_else(|| RuleError::parse_error("Missing property of percentage".to_string()))?;
+    Ok(RuleExpression::Percentage {
+        percent,
+        of: Box::new(RuleExpression::Property(parse_property_access(of)?)),
+    })
+}
+
+/// `multiplied by 1.2`: an operator and the operand it applies to what came before
+fn parse_arithmetic_step(
+    pair: Pair<Rule>,
+) -> Result<(ArithmeticOperator, RuleExpression), RuleError> {
+    let mut inner = pair.into_inner();
+    let operator = match inner.next().map(|pair| pair.as_str()) {
+        Some("plus") => ArithmeticOperator::Plus,
+        Some("minus") => ArithmeticOperator::Minus,
+        Some("multiplied by") => ArithmeticOperator::MultipliedBy,
+        Some("divided by") => ArithmeticOperator::DividedBy,
+        other => {
+            return Err(RuleError::parse_error(format!(
+                "Unknown arithmetic operator: {}",
+                other.unwrap_or_default()
+            )))
+        }
+    };
+    let operand = inner
+        .next()
+        .ok_or_else(|| RuleError::parse_error("Missing arithmetic operand".to_string()))?;
+    Ok((operator, parse_arithmetic_operand(operand)?))
+}
+
+fn parse_arithmetic_operand(pair: Pair<Rule>) -> Result<RuleExpression, RuleError> {
+    let inner = pair
+        .into_inner()
+        .next()
+        .ok_or_else(|| RuleError::parse_error("Missing arithmetic operand".to_string()))?;
+    match inner.as_rule() {
+        Rule::property_access => Ok(RuleExpression::Property(parse_property_access(inner)?)),
+        Rule::number => match parse_value_pair(inner)? {
+            RuleValue::Number(number) => Ok(RuleExpression::Number(number)),
+            other => Err(RuleError::parse_error(format!(
+                "Arithmetic takes numbers, not {}",
+                other
+            ))),
+        },
+        other => Err(RuleError::parse_error(format!(
+            "Unknown arithmetic operand: {:?}",
+            other
+        ))),
+    }
+}
+
+/// The comparison `predicate_pair` makes of the property at `left_path`
+fn parse_predicate(
+    left_path: PropertyPath,
+    left_pos: Option<SourcePosition>,
+    predicate_pair: Pair<Rule>,
+) -> Result<ComparisonCondition, RuleError> {
     let mut predicate_inner = predicate_pair.into_inner();
 
     // Parse operator
     let operator_pair = predicate_inner
         .next()
-        .ok_or_else(|| RuleError::ParseError("Missing operator".to_string()))?;
+        .ok_or_else(|| RuleError::parse_error("Missing operator".to_string()))?;
+
+    if operator_pair.as_rule() == Rule::boolean_predicate {
+        return parse_boolean_predicate(left_path, left_pos, operator_pair);
+    }
 
     let operator = match operator_pair.as_rule() {
         Rule::comparison_operator => match operator_pair.as_str() {
@@ -500,11 +1541,12 @@ fn parse_regular_property_condition(
             "contains" => ComparisonOperator::Contains,
             "is empty" => ComparisonOperator::IsEmpty,
             "is not empty" => ComparisonOperator::IsNotEmpty,
+            "is not within" => ComparisonOperator::NotWithin,
             "is within" => ComparisonOperator::Within,
             "is older than" => ComparisonOperator::OlderThan,
             "is younger than" => ComparisonOperator::YoungerThan,
             _ => {
-                return Err(RuleError::ParseError(format!(
+                return Err(RuleError::parse_error(format!(
                     "Unknown operator: {}",
                     operator_pair.as_str()
                 )))
@@ -514,7 +1556,7 @@ fn parse_regular_property_condition(
             "is in" => ComparisonOperator::In,
             "is not in" => ComparisonOperator::NotIn,
             _ => {
-                return Err(RuleError::ParseError(format!(
+                return Err(RuleError::parse_error(format!(
                     "Unknown list operator: {}",
                     operator_pair.as_str()
                 )))
@@ -524,16 +1566,45 @@ fn parse_regular_property_condition(
             "is empty" => ComparisonOperator::IsEmpty,
             "is not empty" => ComparisonOperator::IsNotEmpty,
             _ => {
-                return Err(RuleError::ParseError(format!(
+                return Err(RuleError::parse_error(format!(
                     "Unknown empty operator: {}",
                     operator_pair.as_str()
                 )))
             }
         },
-        _ => return Err(RuleError::ParseError("Expected operator".to_string())),
+        Rule::between_operator => parse_between_operator(&operator_pair)?,
+        Rule::ago_operator => match operator_pair.as_str() {
+            "is more than" => ComparisonOperator::MoreThanAgo,
+            "is at least" => ComparisonOperator::AtLeastAgo,
+            _ => {
+                return Err(RuleError::parse_error(format!(
+                    "Unknown operator: {}",
+                    operator_pair.as_str()
+                )))
+            }
+        },
+        // `have a __price__ less than 100`: the operator without its `is`
+        Rule::bare_operator => match operator_pair.as_str() {
+            "greater than or equal to" | "at least" => ComparisonOperator::GreaterThanOrEqual,
+            "less than or equal to" | "no more than" => ComparisonOperator::LessThanOrEqual,
+            "greater than" => ComparisonOperator::GreaterThan,
+            "less than" => ComparisonOperator::LessThan,
+            "equal to" => ComparisonOperator::EqualTo,
+            "later than" => ComparisonOperator::LaterThan,
+            "earlier than" => ComparisonOperator::EarlierThan,
+            _ => {
+                return Err(RuleError::parse_error(format!(
+                    "Unknown operator: {}",
+                    operator_pair.as_str()
+                )))
+            }
+        },
+        _ => return Err(RuleError::parse_error("Expected operator".to_string())),
     };
 
     // Parse right operand (empty operators don't need one)
+    let mut right_expression = None;
+    let mut today = false;
     let (right_value, right_property_path) = if matches!(
         operator,
         ComparisonOperator::IsEmpty | ComparisonOperator::IsNotEmpty
@@ -546,7 +1617,7 @@ fn parse_regular_property_condition(
     } else {
         let right_pair = predicate_inner
             .next()
-            .ok_or_else(|| RuleError::ParseError("Missing right operand".to_string()))?;
+            .ok_or_else(|| RuleError::parse_error("Missing right operand".to_string()))?;
         let rp = right_pair.clone();
 
         match right_pair.as_rule() {
@@ -560,11 +1631,9 @@ fn parse_regular_property_condition(
                     start: start_col,
                     end: end_col,
                 });
-                let property_path_string = format!(
-                    "$.{}.{}",
-                    right_path.selector,
-                    right_path.properties.join(".")
-                );
+                let mut property_keys: Vec<&str> = right_path.selector.split('.').collect();
+                property_keys.extend(right_path.properties.iter().map(String::as_str));
+                let property_path_string = json_path_from_keys(&property_keys);
                 (
                     PositionedValue::with_position(
                         RuleValue::String(property_path_string),
@@ -573,6 +1642,57 @@ fn parse_regular_property_condition(
                     Some(right_path),
                 )
             }
+            // The expression's text stands for it where a value is shown
+            Rule::arithmetic_expression | Rule::percentage_of => {
+                let value_span = right_pair.as_span();
+                let (value_line, start_col) = value_span.start_pos().line_col();
+                let (_, end_col) = value_span.end_pos().line_col();
+                if !operator.compares_numbers() {
+                    return Err(RuleError::parse_error(format!(
+                        "'{}' on line {} is a number, which '{}' can't compare with",
+                        right_pair.as_str(),
+                        value_line,
+                        operator
+                    )));
+                }
+                let val_pos = Some(SourcePosition {
+                    line: value_line,
+                    start: start_col,
+                    end: end_col,
+                });
+                let text = right_pair
+                    .as_str()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                right_expression = Some(match right_pair.as_rule() {
+                    Rule::percentage_of => parse_percentage_of(right_pair)?,
+                    _ => parse_arithmetic_expression(right_pair)?,
+                });
+                (
+                    PositionedValue::with_position(RuleValue::String(text), val_pos),
+                    None,
+                )
+            }
+            // Dated when evaluated, see `ComparisonCondition::today`
+            Rule::today => {
+                let value_span = right_pair.as_span();
+                let (value_line, start_col) = value_span.start_pos().line_col();
+                let (_, end_col) = value_span.end_pos().line_col();
+                let val_pos = Some(SourcePosition {
+                    line: value_line,
+                    start: start_col,
+                    end: end_col,
+                });
+                today = true;
+                (
+                    PositionedValue::with_position(
+                        RuleValue::String(right_pair.as_str().to_string()),
+                        val_pos,
+                    ),
+                    None,
+                )
+            }
             Rule::list_value => {
                 let value_span = right_pair.as_span();
                 let (value_line, start_col) = value_span.start_pos().line_col();
@@ -587,7 +1707,22 @@ fn parse_regular_property_condition(
                     None,
                 )
             }
-            Rule::value => {
+            // The duration of `is more than 90 days ago` isn't wrapped in a `value`
+            Rule::value | Rule::duration_literal => {
+                let value_span = right_pair.as_span();
+                let (value_line, start_col) = value_span.start_pos().line_col();
+                let (_, end_col) = value_span.end_pos().line_col();
+                let val_pos = Some(SourcePosition {
+                    line: value_line,
+                    start: start_col,
+                    end: end_col,
+                });
+                (
+                    PositionedValue::with_position(parse_value_pair(right_pair)?, val_pos),
+                    None,
+                )
+            }
+            Rule::range_value => {
                 let value_span = right_pair.as_span();
                 let (value_line, start_col) = value_span.start_pos().line_col();
                 let (_, end_col) = value_span.end_pos().line_col();
@@ -597,12 +1732,12 @@ fn parse_regular_property_condition(
                     end: end_col,
                 });
                 (
-                    PositionedValue::with_position(parse_value(right_pair)?, val_pos),
+                    PositionedValue::with_position(parse_range_value(right_pair)?, val_pos),
                     None,
                 )
             }
             _ => {
-                return Err(RuleError::ParseError(format!(
+                return Err(RuleError::parse_error(format!(
                     "Unknown right operand type: {:?}",
                     right_pair.as_rule()
                 )))
@@ -610,31 +1745,133 @@ fn parse_regular_property_condition(
         }
     };
 
+    let date_offset = match predicate_inner.next() {
+        Some(offset_pair) if offset_pair.as_rule() == Rule::date_offset => {
+            if right_property_path.is_none()
+                && !today
+                && !matches!(right_value.value, RuleValue::Date(_))
+            {
+                return Err(RuleError::parse_error(format!(
+                    "Only dates can be shifted by '{}', not {}",
+                    offset_pair.as_str(),
+                    right_value.value
+                )));
+            }
+            Some(parse_date_offset(offset_pair)?)
+        }
+        _ => None,
+    };
+
     Ok(ComparisonCondition {
         selector: PositionedValue::new(left_path.selector.clone()),
-        property: PositionedValue::new(
+        property: PositionedValue::with_position(
             left_path
                 .properties
                 .last()
                 .map(|s| s.clone())
                 .unwrap_or_else(|| constants::EMPTY_STRING.to_string()),
+            left_pos,
         ),
         operator,
         value: right_value,
         property_chain: None,
         left_property_path: Some(left_path),
         right_property_path,
+        date_offset,
+        today,
+        left_expression: None,
+        right_expression,
     })
 }
 
+/// `is true` / `is false`, the same condition as `is equal to true` / `is equal to false`
+fn parse_boolean_predicate(
+    left_path: PropertyPath,
+    left_pos: Option<SourcePosition>,
+    predicate: Pair<Rule>,
+) -> Result<ComparisonCondition, RuleError> {
+    let span = predicate.as_span();
+    let (value_line, _) = span.start_pos().line_col();
+    let (_, end_col) = span.end_pos().line_col();
+    let value = predicate.as_str().ends_with("true");
+    // Just the `true` or `false` at the end
+    let width = if value { "true".len() } else { "false".len() };
+    let val_pos = Some(SourcePosition {
+        line: value_line,
+        start: end_col - width,
+        end: end_col,
+    });
+
+    Ok(ComparisonCondition {
+        selector: PositionedValue::new(left_path.selector.clone()),
+        property: PositionedValue::with_position(
+            left_path
+                .properties
+                .last()
+                .cloned()
+                .unwrap_or_else(|| constants::EMPTY_STRING.to_string()),
+            left_pos,
+        ),
+        operator: ComparisonOperator::EqualTo,
+        value: PositionedValue::with_position(RuleValue::Boolean(value), val_pos),
+        property_chain: None,
+        left_property_path: Some(left_path),
+        right_property_path: None,
+        date_offset: None,
+        today: false,
+        left_expression: None,
+        right_expression: None,
+    })
+}
+
+/// `the **employee**'s __manager__'s __name__`: the selector comes first and each property
+/// reads from the one before, so the path is already in traversal order
+fn parse_possessive_access(pair: Pair<Rule>) -> PropertyPath {
+    let mut selector = String::new();
+    let mut properties = Vec::new();
+    for inner in pair.into_inner() {
+        let text = inner.as_str();
+        match inner.as_rule() {
+            Rule::object_selector => selector = text[2..text.len() - 2].to_string(),
+            Rule::possessive_selector => {
+                let name = &text[2..text.len() - 2];
+                let name = name.strip_suffix('s').unwrap_or(name);
+                let name = name.strip_suffix(['\'', '’']).unwrap_or(name);
+                selector = name.to_string();
+            }
+            Rule::property => properties.push(text[2..text.len() - 2].to_string()),
+            _ => {}
+        }
+    }
+    PropertyPath {
+        properties,
+        selector,
+        fan_out: Vec::new(),
+        elements: Vec::new(),
+    }
+}
+
 fn parse_property_access(
     pair: Pair<Rule>,
 ) -> Result<crate::runner::model::PropertyPath, RuleError> {
+    let mut inner_pairs = pair.into_inner().peekable();
+    if let Some(possessive) =
+        inner_pairs.next_if(|inner| inner.as_rule() == Rule::possessive_access)
+    {
+        return Ok(parse_possessive_access(possessive));
+    }
+
     // Track elements with their types for proper chaining
     let mut elements = Vec::with_capacity(3);
+    // Set by `each` for the element that follows it
+    let mut each = false;
+    // Set by `the first of` and the like for the element that follows it
+    let mut element_of = None;
 
-    for inner in pair.into_inner() {
+    for inner in inner_pairs {
         match inner.as_rule() {
+            Rule::each => each = true,
+            Rule::element_of => element_of = Some(parse_element_of(inner)?),
             Rule::property_or_selector => {
                 // Parse the inner property or object_selector
                 for sub_inner in inner.into_inner() {
@@ -643,28 +1880,30 @@ fn parse_property_access(
                             let property_text = sub_inner.as_str();
                             let property_name =
                                 property_text[2..property_text.len() - 2].to_string();
-                            elements.push(("property", property_name));
+                            elements.push(("property", property_name, each, element_of.clone()));
                         }
                         Rule::object_selector => {
                             let selector_text = sub_inner.as_str();
                             let selector_name =
                                 selector_text[2..selector_text.len() - 2].to_string(); // Extract content between **
-                            elements.push(("object", selector_name));
+                            elements.push(("object", selector_name, each, element_of.clone()));
                         }
                         _ => {}
                     }
                 }
+                each = false;
+                element_of = None;
             }
             // Backward compatibility: handle direct property/object_selector rules
             Rule::property => {
                 let property_text = inner.as_str();
                 let property_name = property_text[2..property_text.len() - 2].to_string();
-                elements.push(("property", property_name));
+                elements.push(("property", property_name, false, None));
             }
             Rule::object_selector => {
                 let selector_text = inner.as_str();
                 let selector_name = selector_text[2..selector_text.len() - 2].to_string(); // Extract content between **
-                elements.push(("object", selector_name));
+                elements.push(("object", selector_name, false, None));
             }
             _ => {}
         }
@@ -685,7 +1924,7 @@ fn parse_property_access(
     // Subsequent object selectors extend the path
     // The final property element becomes the property to access
 
-    for (i, (element_type, name)) in elements.iter().enumerate() {
+    for (i, (element_type, name, _, _)) in elements.iter().enumerate() {
         match &**element_type {
             "object" => {
                 object_chain.push(name.clone());
@@ -703,6 +1942,36 @@ fn parse_property_access(
         }
     }
 
+    // Every element but the root selector lands in `properties` in the same order, so an
+    // element's index there is one less than its index here
+    let mut element_steps = Vec::new();
+    for (i, (_, name, _, position)) in elements.iter().enumerate() {
+        let Some(position) = position else {
+            continue;
+        };
+        if i == 0 {
+            return Err(RuleError::parse_error(format!(
+                "'the {} of' can't mark the root selector '{}'; it goes before a list inside it",
+                position.ordinal(),
+                name
+            )));
+        }
+        element_steps.push((i - 1, position.clone()));
+    }
+    let mut fan_out = Vec::new();
+    for (i, (_, name, each, _)) in elements.iter().enumerate() {
+        if !each {
+            continue;
+        }
+        if i == 0 {
+            return Err(RuleError::parse_error(format!(
+                "'each' can't mark the root selector '{}'; it goes before a list inside it",
+                name
+            )));
+        }
+        fan_out.push(i - 1);
+    }
+
     // Determine the selector and properties
     let selector = if object_chain.is_empty() {
         // If no object chain, use empty selector for backward compatibility
@@ -721,12 +1990,45 @@ fn parse_property_access(
     Ok(crate::runner::model::PropertyPath {
         properties,
         selector,
+        fan_out,
+        elements: element_steps,
     })
 }
 
-fn parse_rule_reference(pair: Pair<Rule>) -> Result<RuleReferenceCondition, RuleError> {
+/// `the first of`, `the 3rd of` or `the last of`: the step picking that element of a list
+fn parse_element_of(pair: Pair<Rule>) -> Result<PropertyChainElement, RuleError> {
+    let ordinal = pair
+        .into_inner()
+        .find(|inner| inner.as_rule() == Rule::ordinal)
+        .ok_or_else(|| RuleError::parse_error("Missing ordinal".to_string()))?;
+    let index = match ordinal.as_str() {
+        "last" => return Ok(PropertyChainElement::Last),
+        "first" => 0,
+        "second" => 1,
+        "third" => 2,
+        "fourth" => 3,
+        "fifth" => 4,
+        numbered => numbered
+            .trim_end_matches(char::is_alphabetic)
+            .parse::<usize>()
+            .ok()
+            .and_then(|position| position.checked_sub(1))
+            .ok_or_else(|| {
+                RuleError::parse_error(format!(
+                    "'{}' isn't a position in a list; they count from '1st'",
+                    numbered
+                ))
+            })?,
+    };
+    Ok(PropertyChainElement::Index(index))
+}
+
+/// The reference, and whether it was written `does not`. A negated reference names the rule
+/// as a positive one would, `does not pass the test` as `passes the test`
+fn parse_rule_reference(pair: Pair<Rule>) -> Result<(RuleReferenceCondition, bool), RuleError> {
     let mut selector = None;
     let mut rule_name = None;
+    let mut negated = false;
 
     for inner in pair.into_inner() {
         match inner.as_rule() {
@@ -752,21 +2054,57 @@ fn parse_rule_reference(pair: Pair<Rule>) -> Result<RuleReferenceCondition, Rule
                     start: start_col,
                     end: end_col,
                 });
-                let name = inner.as_str().trim().to_string();
+                let name = inner.as_str().trim();
+                let name = if negated {
+                    third_person(name)
+                } else {
+                    name.to_string()
+                };
                 rule_name = Some(PositionedValue::with_position(name, pos));
             }
+            Rule::reference_negation => negated = true,
             _ => {}
         }
     }
 
     let selector =
-        selector.ok_or_else(|| RuleError::ParseError("Missing selector in rule‐ref".into()))?;
+        selector.ok_or_else(|| RuleError::parse_error("Missing selector in rule‐ref"))?;
     let rule_name = rule_name.unwrap_or_else(|| PositionedValue::new("requirement".to_string()));
 
-    Ok(RuleReferenceCondition {
-        selector,
-        rule_name,
-    })
+    Ok((
+        RuleReferenceCondition {
+            selector,
+            rule_name,
+        },
+        negated,
+    ))
+}
+
+/// `pass the test` as `passes the test`: the first word conjugated after `does not`
+fn third_person(phrase: &str) -> String {
+    let (verb, rest) = phrase.split_once(' ').unwrap_or((phrase, ""));
+    let conjugated = match verb {
+        "have" => "has".to_string(),
+        "do" | "go" => format!("{}es", verb),
+        _ if verb
+            .strip_suffix('y')
+            .is_some_and(|stem| !stem.ends_with(['a', 'e', 'i', 'o', 'u'])) =>
+        {
+            format!("{}ies", &verb[..verb.len() - 1])
+        }
+        _ if ["s", "sh", "ch", "x", "z"]
+            .iter()
+            .any(|ending| verb.ends_with(ending)) =>
+        {
+            format!("{}es", verb)
+        }
+        _ => format!("{}s", verb),
+    };
+    if rest.is_empty() {
+        conjugated
+    } else {
+        format!("{} {}", conjugated, rest)
+    }
 }
 
 fn parse_list_value(pair: Pair<Rule>) -> Result<RuleValue, RuleError> {
@@ -775,7 +2113,7 @@ fn parse_list_value(pair: Pair<Rule>) -> Result<RuleValue, RuleError> {
     let mut values = Vec::with_capacity(5);
 
     for value_pair in inner_pairs {
-        values.push(parse_value(value_pair)?);
+        values.push(parse_value_pair(value_pair)?);
     }
 
     // Shrink to fit to save memory
@@ -784,20 +2122,68 @@ fn parse_list_value(pair: Pair<Rule>) -> Result<RuleValue, RuleError> {
     Ok(RuleValue::List(values))
 }
 
-fn parse_value(pair: Pair<Rule>) -> Result<RuleValue, RuleError> {
+fn parse_between_operator(pair: &Pair<Rule>) -> Result<ComparisonOperator, RuleError> {
+    match pair.as_str() {
+        "is between" => Ok(ComparisonOperator::Between),
+        "is strictly between" => Ok(ComparisonOperator::StrictlyBetween),
+        _ => Err(RuleError::parse_error(format!(
+            "Unknown between operator: {}",
+            pair.as_str()
+        ))),
+    }
+}
+
+/// `18 and 65`: two numbers or two dates, the lower first
+fn parse_range_value(pair: Pair<Rule>) -> Result<RuleValue, RuleError> {
+    let text = pair.as_str();
+    let mut bounds = pair.into_inner().map(|bound| {
+        let inner = bound
+            .into_inner()
+            .next()
+            .ok_or_else(|| RuleError::parse_error("Empty range bound".to_string()))?;
+        parse_value_pair(inner)
+    });
+    let (Some(low), Some(high)) = (bounds.next(), bounds.next()) else {
+        return Err(RuleError::parse_error(format!(
+            "Expected two bounds in '{}'",
+            text
+        )));
+    };
+    let (low, high) = (low?, high?);
+
+    let in_order = match (&low, &high) {
+        (RuleValue::Number(l), RuleValue::Number(h)) => l <= h,
+        (RuleValue::Date(l), RuleValue::Date(h)) => l <= h,
+        _ => {
+            return Err(RuleError::parse_error(format!(
+                "The bounds in '{}' must both be numbers or both be dates",
+                text
+            )))
+        }
+    };
+    if !in_order {
+        return Err(RuleError::parse_error(format!(
+            "The lower bound comes first in '{}'",
+            text
+        )));
+    }
+    Ok(RuleValue::Range(Box::new(low), Box::new(high)))
+}
+
+fn parse_value_pair(pair: Pair<Rule>) -> Result<RuleValue, RuleError> {
     match pair.as_rule() {
         Rule::value => {
             let inner = pair
                 .into_inner()
                 .next()
-                .ok_or_else(|| RuleError::ParseError("Empty value".to_string()))?;
-            parse_value(inner)
+                .ok_or_else(|| RuleError::parse_error("Empty value".to_string()))?;
+            parse_value_pair(inner)
         }
         Rule::number => {
             let num = pair
                 .as_str()
                 .parse::<f64>()
-                .map_err(|e| RuleError::ParseError(format!("Invalid number: {}", e)))?;
+                .map_err(|e| RuleError::parse_error(format!("Invalid number: {}", e)))?;
             Ok(RuleValue::Number(num))
         }
         Rule::string_literal => {
@@ -814,7 +2200,7 @@ fn parse_value(pair: Pair<Rule>) -> Result<RuleValue, RuleError> {
             };
 
             let date = NaiveDate::parse_from_str(date_part, "%Y-%m-%d")
-                .map_err(|e| RuleError::ParseError(format!("Invalid date: {}", e)))?;
+                .map_err(|e| RuleError::parse_error(format!("Invalid date: {}", e)))?;
 
             Ok(RuleValue::Date(date))
         }
@@ -823,7 +2209,7 @@ fn parse_value(pair: Pair<Rule>) -> Result<RuleValue, RuleError> {
             Ok(RuleValue::Boolean(b))
         }
         Rule::duration_literal => parse_duration(pair),
-        _ => Err(RuleError::ParseError(format!(
+        _ => Err(RuleError::parse_error(format!(
             "Unknown value type: {:?}",
             pair.as_rule()
         ))),
@@ -838,7 +2224,7 @@ fn parse_duration(pair: Pair<Rule>) -> Result<RuleValue, RuleError> {
         match inner.as_rule() {
             Rule::number => {
                 amount = Some(inner.as_str().parse::<f64>().map_err(|e| {
-                    RuleError::ParseError(format!("Invalid duration amount: {}", e))
+                    RuleError::parse_error(format!("Invalid duration amount: {}", e))
                 })?);
             }
             Rule::time_unit => {
@@ -849,13 +2235,47 @@ fn parse_duration(pair: Pair<Rule>) -> Result<RuleValue, RuleError> {
     }
 
     let amount =
-        amount.ok_or_else(|| RuleError::ParseError("Missing duration amount".to_string()))?;
-    let unit = unit.ok_or_else(|| RuleError::ParseError("Missing duration unit".to_string()))?;
+        amount.ok_or_else(|| RuleError::parse_error("Missing duration amount".to_string()))?;
+    let unit = unit.ok_or_else(|| RuleError::parse_error("Missing duration unit".to_string()))?;
 
     let duration = Duration::new(amount, unit);
     Ok(RuleValue::Duration(duration))
 }
 
+/// `plus 14 days`. Dates have no time of day, so the duration must be a whole number of
+/// days or longer units
+fn parse_date_offset(pair: Pair<Rule>) -> Result<DateOffset, RuleError> {
+    let text = pair.as_str();
+    let mut subtract = false;
+    let mut duration = None;
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::date_offset_sign => subtract = inner.as_str() == "minus",
+            Rule::duration_literal => {
+                if let RuleValue::Duration(parsed) = parse_duration(inner)? {
+                    duration = Some(parsed);
+                }
+            }
+            _ => {}
+        }
+    }
+    let duration = duration
+        .ok_or_else(|| RuleError::parse_error("Missing date offset duration".to_string()))?;
+
+    let sub_day = matches!(
+        duration.unit,
+        TimeUnit::Seconds | TimeUnit::Minutes | TimeUnit::Hours
+    );
+    if sub_day || duration.amount.fract() != 0.0 {
+        return Err(RuleError::parse_error(format!(
+            "Dates are shifted by whole days, weeks, months or years, not '{}'",
+            text
+        )));
+    }
+
+    Ok(DateOffset { subtract, duration })
+}
+
 fn parse_time_unit(pair: Pair<Rule>) -> Result<TimeUnit, RuleError> {
     match pair.as_str() {
         "second" | "seconds" => Ok(TimeUnit::Seconds),
@@ -867,7 +2287,7 @@ fn parse_time_unit(pair: Pair<Rule>) -> Result<TimeUnit, RuleError> {
         "year" | "years" => Ok(TimeUnit::Years),
         "decade" | "decades" => Ok(TimeUnit::Decades),
         "century" | "centuries" => Ok(TimeUnit::Centuries),
-        _ => Err(RuleError::ParseError(format!(
+        _ => Err(RuleError::parse_error(format!(
             "Unknown time unit: {}",
             pair.as_str()
         ))),
@@ -0,0 +1,180 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::parser::parse_rules;
+    use crate::runner::plan::{compile_plan, FuzzyKey, PlannedCondition, SampleLookup};
+    use crate::runner::trace::ReferenceResolution;
+    use serde_json::json;
+
+    const DRIVING_TEST: &str = r#"
+A **Person** gets a full driving licence
+  if the __age__ of the **Person** is greater than or equal to 17
+  and the **Person** passes the practical driving test
+  and §practical is valid.
+
+practical. A **Person** passes the practical driving test
+  if the __driving test__ of the **scores** is greater than or equal to 60
+  and the __test date__ of the **scores** is within 2 years.
+"#;
+
+    const UNIVERSITY: &str = r#"
+A **student** gets university admission
+  if the **student** meets basic eligibility requirements
+  and the **student** qualifies for their chosen program.
+
+A **student** meets basic eligibility requirements
+  if the __age__ of the **applicant** in the **student** is at least 16
+  and the __application date__ of the **submission** in the **student** is within 90 days.
+
+A **student** qualifies for their chosen program
+  if the **student** meets undergraduate program requirements
+  or the **student** meets graduate program requirements.
+
+A **student** meets undergraduate program requirements
+  if the __sat score__ of the **standardized tests** in the **student** is at least 1200.
+
+A **student** meets graduate program requirements
+  if the __gre score__ of the **standardized tests** in the **student** is at least 310.
+"#;
+
+    fn outcomes(plan: &crate::runner::plan::EvaluationPlan) -> Vec<&str> {
+        plan.order
+            .iter()
+            .map(|rule| rule.outcome.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_driving_test_plan() {
+        let plan = compile_plan(&parse_rules(DRIVING_TEST).unwrap());
+        assert_eq!(plan.global_rule.as_deref(), Some("full driving licence"));
+        assert_eq!(
+            outcomes(&plan),
+            vec!["practical driving test", "full driving licence"]
+        );
+
+        // Referenced by outcome and by label, so evaluated once and reused
+        let practical = &plan.order[0];
+        assert_eq!(practical.referenced_by, 2);
+        assert!(practical.memoizable);
+        assert!(!plan.order[1].memoizable);
+
+        let resolutions: Vec<_> = plan.order[1]
+            .conditions
+            .iter()
+            .filter_map(|condition| match condition {
+                PlannedCondition::RuleReference { resolution, .. } => Some(resolution.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            resolutions,
+            vec![
+                ReferenceResolution::FuzzyOutcome {
+                    matched: "practical driving test".to_string()
+                },
+                ReferenceResolution::Label,
+            ]
+        );
+
+        let time_dependent: Vec<bool> = practical
+            .conditions
+            .iter()
+            .map(|condition| match condition {
+                PlannedCondition::Comparison { time_dependent, .. } => *time_dependent,
+                _ => panic!("Expected a comparison"),
+            })
+            .collect();
+        assert_eq!(time_dependent, vec![false, true]);
+    }
+
+    #[test]
+    fn test_sample_shows_fuzzy_and_missing_keys() {
+        let mut plan = compile_plan(&parse_rules(DRIVING_TEST).unwrap());
+        plan.resolve_sample(&json!({
+            "Person": {"age": 18},
+            "scores": {"drivingTest": 61},
+        }));
+
+        let PlannedCondition::Comparison { paths, .. } = &plan.order[0].conditions[0] else {
+            panic!("Expected a comparison");
+        };
+        assert_eq!(
+            paths[0].sample,
+            Some(SampleLookup {
+                path: "$.scores.drivingTest".to_string(),
+                found: true,
+                fuzzy_keys: vec![FuzzyKey {
+                    name: "driving test".to_string(),
+                    key: "drivingTest".to_string(),
+                }],
+            })
+        );
+
+        let PlannedCondition::Comparison { paths, .. } = &plan.order[0].conditions[1] else {
+            panic!("Expected a comparison");
+        };
+        let sample = paths[0].sample.as_ref().unwrap();
+        assert!(!sample.found);
+        assert_eq!(sample.path, "$.scores['test date']");
+
+        let PlannedCondition::Comparison { paths, .. } = &plan.order[1].conditions[0] else {
+            panic!("Expected a comparison");
+        };
+        let sample = paths[0].sample.as_ref().unwrap();
+        assert!(sample.found && sample.fuzzy_keys.is_empty());
+    }
+
+    #[test]
+    fn test_university_plan_orders_references_first() {
+        let plan = compile_plan(&parse_rules(UNIVERSITY).unwrap());
+        assert_eq!(
+            outcomes(&plan),
+            vec![
+                "basic eligibility requirements",
+                "undergraduate program requirements",
+                "graduate program requirements",
+                "their chosen program",
+                "university admission",
+            ]
+        );
+        assert!(plan.order.iter().all(|rule| !rule.memoizable));
+
+        let time_dependent = plan
+            .order
+            .iter()
+            .flat_map(|rule| &rule.conditions)
+            .filter(|condition| {
+                matches!(
+                    condition,
+                    PlannedCondition::Comparison {
+                        time_dependent: true,
+                        ..
+                    }
+                )
+            })
+            .count();
+        assert_eq!(time_dependent, 1);
+
+        let plan = serde_json::to_value(&plan).unwrap();
+        let eligibility = &plan["order"][0]["conditions"][0];
+        assert_eq!(eligibility["kind"], "comparison");
+        assert_eq!(
+            eligibility["paths"][0]["names"],
+            json!(["student", "applicant", "age"])
+        );
+    }
+
+    #[test]
+    fn test_plan_lists_each_rules_tags() {
+        let rules = "A **customer** is onboarded if the **customer** passes the screening.\n\nTags: fraud, kyc.\nA **customer** passes the screening if the __risk__ of the **customer** is less than 50.";
+        let rule_set = parse_rules(rules).unwrap();
+        let plan = compile_plan(&rule_set);
+
+        assert_eq!(plan.order[0].tags, vec!["fraud", "kyc"]);
+        assert!(plan.order[1].tags.is_empty());
+        assert_eq!(
+            serde_json::to_value(rule_set.tag_inventory()).unwrap(),
+            json!({"fraud": ["screening"], "kyc": ["screening"]})
+        );
+    }
+}
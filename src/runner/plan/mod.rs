@@ -0,0 +1,352 @@
+//! The order rules evaluate in and how their references resolve.
+//!
+//! `compile_plan` orders a rule set's rules after the rules they reference and notes which are
+//! memoizable (referenced more than once), how each reference resolves (`fuzzy_outcome` is a loose
+//! name match) and which comparisons are time dependent (`ComparisonOperator::is_time_dependent`).
+//! `EvaluationPlan::resolve_sample` dry-runs the property lookups against a sample payload, listing
+//! names that only matched a differently written key. Served by `POST /plan` with `{"rule": ...,
+//! "sample_data": ...}`.
+
+mod lib;
+
+use crate::runner::error::RuleError;
+use crate::runner::evaluator::resolve_rule;
+use crate::runner::model::{
+    constants, ComparisonOperator, Condition, ConditionGroup, ConditionId, QuantifiedCondition,
+    Rule, RuleSet, SourcePosition,
+};
+use crate::runner::schema::{condition_requirements, lookup};
+use crate::runner::trace::ReferenceResolution;
+use crate::runner::utils::{find_global_rule, json_path_from_keys, names_match};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// How a rule set will run, worked out without evaluating it, see `compile_plan`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct EvaluationPlan {
+    /// Outcome of the global rule, when the set has exactly one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global_rule: Option<String>,
+    /// Every rule after the rules it references, so each is decided once what it refers to
+    /// is. A reference back into a cycle doesn't order anything
+    pub order: Vec<PlannedRule>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PlannedRule {
+    pub outcome: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// From the rule's `Tags:` line, see `EvaluationOptions::excludes`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Conditions of other rules that reference this one
+    pub referenced_by: usize,
+    /// Referenced more than once, so later references reuse the first result, see
+    /// `evaluator::RuleMemo`
+    pub memoizable: bool,
+    pub conditions: Vec<PlannedCondition>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PlannedCondition {
+    Comparison {
+        id: ConditionId,
+        operator: ComparisonOperator,
+        /// The outcome also depends on the current date, see
+        /// `ComparisonCondition::is_time_dependent`
+        time_dependent: bool,
+        /// The data paths the condition reads
+        paths: Vec<PlannedPath>,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        negated: bool,
+    },
+    RuleReference {
+        id: ConditionId,
+        rule_name: String,
+        /// How the reference finds its rule. `fuzzy_outcome` is a name matched loosely
+        resolution: ReferenceResolution,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        outcome: Option<String>,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        negated: bool,
+    },
+    Flag {
+        id: ConditionId,
+        flag: String,
+        /// The state the condition asks for, a negated condition asking for the other
+        enabled: bool,
+    },
+    /// `(A or B)`, planned condition by condition
+    Group {
+        id: ConditionId,
+        conditions: Vec<PlannedCondition>,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        negated: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PlannedPath {
+    /// Selector and property names as written in the rule, outermost first
+    pub names: Vec<String>,
+    /// Where the names were found in a sample payload, see `EvaluationPlan::resolve_sample`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample: Option<SampleLookup>,
+}
+
+/// How a path's names matched the keys of a sample payload
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SampleLookup {
+    /// JSONPath of the keys matched, up to and including the first name that wasn't found
+    pub path: String,
+    pub found: bool,
+    /// Names that only matched a key written another way, such as `date of birth` for
+    /// `dateOfBirth`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fuzzy_keys: Vec<FuzzyKey>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FuzzyKey {
+    pub name: String,
+    pub key: String,
+}
+
+/// A rule reference that names no rule of the set, see `RuleSet::unresolved_references`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UnresolvedReference {
+    /// Outcome of the rule the reference is in
+    pub rule: String,
+    pub id: ConditionId,
+    pub rule_name: String,
+    /// Where the reference names its rule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<SourcePosition>,
+}
+
+/// Orders a rule set for evaluation and notes, per rule and condition, what the evaluator
+/// will do: which rules it memoizes, how references resolve and which comparisons read the
+/// current date. References resolve the way the evaluator resolves them
+pub fn compile_plan(rule_set: &RuleSet) -> EvaluationPlan {
+    let mut referenced_by = vec![0; rule_set.rules.len()];
+    for rule in &rule_set.rules {
+        for referenced in referenced_rules(rule_set, rule) {
+            if let Some(index) = rule_set.index_of(referenced) {
+                referenced_by[index] += 1;
+            }
+        }
+    }
+
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    for index in 0..rule_set.rules.len() {
+        visit(rule_set, index, &mut visited, &mut order);
+    }
+
+    EvaluationPlan {
+        global_rule: find_global_rule(&rule_set.rules)
+            .ok()
+            .map(|rule| rule.outcome.clone()),
+        order: order
+            .into_iter()
+            .map(|index| plan_rule(rule_set, &rule_set.rules[index], referenced_by[index]))
+            .collect(),
+    }
+}
+
+/// Post-order walk of the references from the rule at `index`
+fn visit(rule_set: &RuleSet, index: usize, visited: &mut HashSet<usize>, order: &mut Vec<usize>) {
+    if !visited.insert(index) {
+        return;
+    }
+    for referenced in referenced_rules(rule_set, &rule_set.rules[index]) {
+        if let Some(referenced) = rule_set.index_of(referenced) {
+            visit(rule_set, referenced, visited, order);
+        }
+    }
+    order.push(index);
+}
+
+fn referenced_rules<'a>(rule_set: &'a RuleSet, rule: &Rule) -> Vec<&'a Rule> {
+    rule.all_conditions()
+        .filter_map(|group| match &group.condition {
+            Condition::RuleReference(reference) => {
+                resolve_rule(reference.rule_name.value.trim(), rule_set).map(|(rule, _)| rule)
+            }
+            Condition::Comparison(_)
+            | Condition::Flag(_)
+            | Condition::Quantified(_)
+            | Condition::Group(_) => None,
+        })
+        .collect()
+}
+
+fn plan_rule(rule_set: &RuleSet, rule: &Rule, referenced_by: usize) -> PlannedRule {
+    let conditions = rule
+        .conditions
+        .iter()
+        .map(|group| plan_condition(rule_set, group))
+        .collect();
+
+    PlannedRule {
+        outcome: rule.outcome.clone(),
+        label: rule.label.clone(),
+        tags: rule.tags.clone(),
+        referenced_by,
+        memoizable: referenced_by > 1,
+        conditions,
+    }
+}
+
+fn plan_condition(rule_set: &RuleSet, group: &ConditionGroup) -> PlannedCondition {
+    match &group.condition {
+        Condition::Comparison(comparison)
+        | Condition::Quantified(QuantifiedCondition { comparison, .. }) => {
+            PlannedCondition::Comparison {
+                id: group.id.clone(),
+                operator: comparison.operator.clone(),
+                time_dependent: comparison.is_time_dependent(),
+                paths: condition_requirements(comparison)
+                    .into_iter()
+                    .filter(|(names, _)| {
+                        !names
+                            .first()
+                            .is_some_and(|name| names_match(name, constants::CONTEXT_SELECTOR))
+                    })
+                    .map(|(names, _)| PlannedPath {
+                        names,
+                        sample: None,
+                    })
+                    .collect(),
+                negated: group.negated,
+            }
+        }
+        Condition::RuleReference(reference) => {
+            let rule_name = reference.rule_name.value.trim();
+            let (resolution, outcome) = match resolve_rule(rule_name, rule_set) {
+                Some((rule, resolution)) => (resolution, Some(rule.outcome.clone())),
+                None => (ReferenceResolution::Unresolved, None),
+            };
+            PlannedCondition::RuleReference {
+                id: group.id.clone(),
+                rule_name: rule_name.to_string(),
+                resolution,
+                outcome,
+                negated: group.negated,
+            }
+        }
+        Condition::Flag(flag) => PlannedCondition::Flag {
+            id: group.id.clone(),
+            flag: flag.flag.value.clone(),
+            enabled: flag.enabled != group.negated,
+        },
+        Condition::Group(members) => PlannedCondition::Group {
+            id: group.id.clone(),
+            conditions: members
+                .iter()
+                .map(|member| plan_condition(rule_set, member))
+                .collect(),
+            negated: group.negated,
+        },
+    }
+}
+
+impl RuleSet {
+    /// The references that name no rule by outcome, alias, label or a partial outcome match,
+    /// resolved as the evaluator resolves them, in the order written. The evaluator looks
+    /// each up as a property of the data instead and lets it pass as free text when there
+    /// is none
+    pub fn unresolved_references(&self) -> Vec<UnresolvedReference> {
+        let mut found = Vec::new();
+        for rule in &self.rules {
+            for group in rule.all_conditions() {
+                let Condition::RuleReference(reference) = &group.condition else {
+                    continue;
+                };
+                let rule_name = reference.rule_name.value.trim();
+                if resolve_rule(rule_name, self).is_none() {
+                    found.push(UnresolvedReference {
+                        rule: rule.outcome.clone(),
+                        id: group.id.clone(),
+                        rule_name: rule_name.to_string(),
+                        position: reference.rule_name.pos.clone(),
+                    });
+                }
+            }
+        }
+        found
+    }
+
+    /// Fails with `RuleError::UnresolvedReferences` listing every reference that names no
+    /// rule, for `EvaluationOptions::strict_references`
+    pub fn validate_references(&self) -> Result<(), RuleError> {
+        let references = self.unresolved_references();
+        if references.is_empty() {
+            Ok(())
+        } else {
+            Err(RuleError::UnresolvedReferences { references })
+        }
+    }
+}
+
+impl EvaluationPlan {
+    /// Looks every planned path up in `sample` the way the evaluator looks up properties,
+    /// recording where each was found and which names only matched loosely
+    pub fn resolve_sample(&mut self, sample: &Value) {
+        for condition in self.order.iter_mut().flat_map(|rule| &mut rule.conditions) {
+            condition.resolve_sample(sample);
+        }
+    }
+}
+
+impl PlannedCondition {
+    fn resolve_sample(&mut self, sample: &Value) {
+        match self {
+            PlannedCondition::Comparison { paths, .. } => {
+                for path in paths {
+                    path.sample = Some(sample_lookup(sample, &path.names));
+                }
+            }
+            PlannedCondition::Group { conditions, .. } => {
+                for condition in conditions {
+                    condition.resolve_sample(sample);
+                }
+            }
+            PlannedCondition::RuleReference { .. } | PlannedCondition::Flag { .. } => {}
+        }
+    }
+}
+
+fn sample_lookup(sample: &Value, names: &[String]) -> SampleLookup {
+    let mut current = sample;
+    let mut keys = Vec::new();
+    let mut fuzzy_keys = Vec::new();
+
+    for name in names {
+        let Some((key, value)) = lookup(current, name) else {
+            keys.push(name.clone());
+            return SampleLookup {
+                path: json_path_from_keys(&keys),
+                found: false,
+                fuzzy_keys,
+            };
+        };
+        if key != *name {
+            fuzzy_keys.push(FuzzyKey {
+                name: name.clone(),
+                key: key.clone(),
+            });
+        }
+        keys.push(key);
+        current = value;
+    }
+
+    SampleLookup {
+        path: json_path_from_keys(&keys),
+        found: true,
+        fuzzy_keys,
+    }
+}
@@ -0,0 +1,169 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::evaluator::evaluate;
+    use crate::runner::options::{EvaluationOptions, DEFAULT_MAX_SCANNED_KEYS};
+    use crate::runner::outcome::EvaluationOutcome;
+    use crate::runner::parser::parse_rules;
+    use serde::de::DeserializeSeed;
+    use serde_json::{json, Value};
+
+    const RULES: &str = r#"
+A **customer** gets the loan
+  if the **customer** passes the credit check
+  and the **customer** passes the account check.
+
+A **customer** passes the credit check
+  if the __credit score__ of the **customer.profile** is at least 600
+  and the __birth date__ of the **customer** is earlier than 2006-01-01
+  and the number of __defaults__ of the **customer** is less than 2
+  and the length of __nickname__ of the **customer** is less than or equal to 12.
+
+A **customer** passes the account check
+  if the __opened on__ of the **account** is earlier than the __applied on__ of the **customer** minus 30 days
+  and the number of __balance__ of each __holdings__ of the **account** is at least 2
+  and the __channel__ of the **context** is equal to "branch".
+"#;
+
+    /// A payload with the fields `RULES` read, spelled the way payloads spell them, among
+    /// fields they don't
+    fn payload(score: u32) -> Value {
+        json!({
+            "customer": {
+                "profile": {"creditScore": score, "photo": "x".repeat(1000), "tags": ["a", "b"]},
+                "birth_date": "1990-04-01",
+                "defaults": [{"year": 2019}],
+                "nickname": "Sam",
+                "appliedOn": "2024-06-01",
+                "history": (0..200).map(|i| json!({"event": i, "notes": "..."})).collect::<Vec<_>>()
+            },
+            "account": {
+                "Opened On": "2020-01-01",
+                "holdings": [{"balance": 10, "isin": "GB00"}, {"balance": 5}],
+                "statements": {"2023": [1, 2, 3]}
+            },
+            "context": {"channel": "web"},
+            "audit": {"trail": [{"who": "ops"}]}
+        })
+    }
+
+    fn projected(rules: &str, text: &str, max_scanned_keys: usize) -> Value {
+        let projection = parse_rules(rules).unwrap().data_projection().unwrap();
+        let mut deserializer = serde_json::Deserializer::from_str(text);
+        let value = projection
+            .seed(max_scanned_keys)
+            .deserialize(&mut deserializer)
+            .unwrap();
+        deserializer.end().unwrap();
+        value
+    }
+
+    fn decide(rules: &str, data: &Value, options: &EvaluationOptions) -> EvaluationOutcome {
+        evaluate(&parse_rules(rules).unwrap(), data, options)
+    }
+
+    fn assert_same_decision(rules: &str, data: &Value, options: &EvaluationOptions) {
+        let pruned = projected(rules, &data.to_string(), options.max_scanned_keys);
+        let full = decide(rules, data, options);
+        let projected = decide(rules, &pruned, options);
+
+        assert_eq!(projected.result, full.result, "for {}", data);
+        assert_eq!(projected.outcomes, full.outcomes);
+        assert_eq!(projected.labels, full.labels);
+        assert_eq!(
+            projected.error.map(|error| error.to_string()),
+            full.error.map(|error| error.to_string())
+        );
+        assert_eq!(
+            serde_json::to_value(projected.trace).unwrap(),
+            serde_json::to_value(full.trace).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_projection_keeps_only_what_the_rules_read() {
+        let pruned = projected(RULES, &payload(700).to_string(), DEFAULT_MAX_SCANNED_KEYS);
+        assert_eq!(
+            pruned,
+            json!({
+                "customer": {
+                    "profile": {"creditScore": 700},
+                    "birth_date": "1990-04-01",
+                    "defaults": [{"year": 2019}],
+                    "nickname": "Sam",
+                    "appliedOn": "2024-06-01"
+                },
+                "account": {
+                    "Opened On": "2020-01-01",
+                    "holdings": [{"balance": 10, "isin": "GB00"}, {"balance": 5}]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_pruning_never_changes_the_decision() {
+        let mut payloads = vec![payload(700), payload(500)];
+
+        let mut missing = payload(700);
+        missing["customer"]
+            .as_object_mut()
+            .unwrap()
+            .remove("nickname");
+        payloads.push(missing);
+
+        let mut wrong_type = payload(700);
+        wrong_type["customer"]["defaults"] = json!("none");
+        payloads.push(wrong_type);
+
+        let mut unpriced = payload(700);
+        unpriced["account"]["holdings"][1] = json!({"isin": "US00"});
+        payloads.push(unpriced);
+
+        let mut scalar_on_the_way = payload(700);
+        scalar_on_the_way["customer"]["profile"] = json!("hidden");
+        payloads.push(scalar_on_the_way);
+
+        payloads.push(json!([1, 2, 3]));
+        payloads.push(json!({}));
+
+        let options = [
+            EvaluationOptions::new(),
+            EvaluationOptions::new().with_context(json!({"channel": "branch"})),
+            EvaluationOptions::new().with_lenient(true),
+        ];
+        for data in &payloads {
+            for options in &options {
+                assert_same_decision(RULES, data, options);
+            }
+        }
+        assert!(decide(RULES, &payloads[0], &options[1]).result);
+    }
+
+    #[test]
+    fn test_large_objects_keep_only_keys_looked_up_as_written() {
+        let rules =
+            "A **user** is adult if the __birth date__ of the **user** is earlier than 2006-01-01.";
+        let data = json!({"user": {"birth_date": "1990-01-01", "a": 1, "b": 2, "c": 3}});
+        let options = EvaluationOptions::new().with_max_scanned_keys(3);
+
+        // Too many keys to find `birth_date` for `birth date`, with or without the others
+        let pruned = projected(rules, &data.to_string(), 3);
+        assert_eq!(pruned, json!({"user": {}}));
+        assert!(!decide(rules, &data, &options).result);
+        assert!(!decide(rules, &pruned, &options).result);
+
+        let data = json!({"user": {"birthDate": "1990-01-01", "a": 1, "b": 2, "c": 3}});
+        assert_same_decision(rules, &data, &options);
+    }
+
+    #[test]
+    fn test_rules_that_read_unknown_paths_have_no_projection() {
+        // `has a passport` resolves to no rule, so it is looked for in the user's properties
+        let rules = "A **user** may travel if the **user** has a passport.";
+        assert_eq!(parse_rules(rules).unwrap().data_projection(), None);
+
+        let rules = "A **user** may travel if the **user** passes the check.\n\n\
+                     A **user** passes the check if the __age__ of the **user** is at least 18.";
+        assert!(parse_rules(rules).unwrap().data_projection().is_some());
+    }
+}
@@ -0,0 +1,236 @@
+//! The payload paths a rule set can read.
+//!
+//! `RuleSet::data_projection()` is the tree of payload paths a rule set can read (from
+//! `data_requirements`), or `None` when the rules alone don't tell: selector mappings, property
+//! chains, or a rule reference that resolves to no rule and is looked for among the data's
+//! properties. `DataProjection::seed` deserializes a payload keeping only those paths, skipping the
+//! rest unbuilt. Keys are kept when they match the way the evaluator looks names up, including
+//! `max_scanned_keys`; lists on the way and values read whole are kept as they are.
+//! `benches/projection.rs` compares its peak memory with a full parse on a 50 MB payload.
+
+mod lib;
+
+use crate::runner::evaluator::resolve_rule;
+use crate::runner::model::{Condition, Rule, RuleSet};
+use crate::runner::schema::data_requirements;
+use crate::runner::utils::{names_match, transform_property_name};
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+use std::borrow::Cow;
+use std::fmt;
+
+/// The parts of a payload a rule set can read: the names each level's keys are kept for, down
+/// to the values the rules read whole. See `RuleSet::data_projection`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DataProjection {
+    /// Names as the rules write them, with what is kept under a key matching each
+    children: Vec<(String, DataProjection)>,
+    /// Kept with everything under it
+    whole: bool,
+}
+
+impl RuleSet {
+    /// What of a payload the rules can read, so the rest can be dropped as it is parsed.
+    ///
+    /// `None` when the rules alone don't tell: a rule reference that resolves to no rule is
+    /// checked against properties of its selector or of every top-level object, a property
+    /// chain is read key by key, and `selector_mappings` rename selectors
+    pub fn data_projection(&self) -> Option<DataProjection> {
+        if !self.selector_mappings.is_empty() {
+            return None;
+        }
+        for group in self.rules.iter().flat_map(Rule::all_conditions) {
+            match &group.condition {
+                Condition::Comparison(comparison) if comparison.property_chain.is_some() => {
+                    return None;
+                }
+                Condition::RuleReference(reference) => {
+                    resolve_rule(reference.rule_name.value.trim(), self)?;
+                }
+                Condition::Comparison(_)
+                | Condition::Flag(_)
+                | Condition::Quantified(_)
+                | Condition::Group(_) => {}
+            }
+        }
+
+        let mut projection = DataProjection::default();
+        for requirement in data_requirements(self) {
+            projection.insert(&requirement.path);
+        }
+        Some(projection)
+    }
+}
+
+impl DataProjection {
+    fn insert(&mut self, path: &[String]) {
+        if self.whole {
+            return;
+        }
+        let Some((name, rest)) = path.split_first() else {
+            self.whole = true;
+            self.children.clear();
+            return;
+        };
+        let index = match self.children.iter().position(|(child, _)| child == name) {
+            Some(index) => index,
+            None => {
+                self.children
+                    .push((name.clone(), DataProjection::default()));
+                self.children.len() - 1
+            }
+        };
+        self.children[index].1.insert(rest);
+    }
+
+    fn merge(&mut self, other: &DataProjection) {
+        if other.whole {
+            self.whole = true;
+            self.children.clear();
+            return;
+        }
+        for (name, child) in &other.children {
+            if self.whole {
+                return;
+            }
+            match self
+                .children
+                .iter_mut()
+                .find(|(existing, _)| existing == name)
+            {
+                Some((_, existing)) => existing.merge(child),
+                None => self.children.push((name.clone(), child.clone())),
+            }
+        }
+    }
+
+    /// Deserializes a payload keeping only the projected values, skipping the rest without
+    /// building it. Lists and scalars on the way to a projected value are kept as they are.
+    ///
+    /// Keys are kept when they match a name the way the evaluator looks names up. An object
+    /// with more than `max_scanned_keys` keys is only looked up as written and camelCased, so
+    /// only keys matching those are kept from it
+    pub fn seed(&self, max_scanned_keys: usize) -> ProjectionSeed<'_> {
+        ProjectionSeed {
+            projection: self,
+            max_scanned_keys,
+        }
+    }
+
+    /// What to keep under `key`, and whether `key` is a name as written or camelCased
+    fn child_for(&self, key: &str) -> Option<(Cow<'_, DataProjection>, bool)> {
+        let mut found: Option<(Cow<'_, DataProjection>, bool)> = None;
+        for (name, child) in &self.children {
+            let transformed = transform_property_name(name);
+            let direct = key == name || key == transformed;
+            if !direct && !names_match(name, key) && !names_match(&transformed, key) {
+                continue;
+            }
+            match &mut found {
+                // Names the rules spell differently can match the same key
+                Some((kept, kept_direct)) => {
+                    kept.to_mut().merge(child);
+                    *kept_direct |= direct;
+                }
+                None => found = Some((Cow::Borrowed(child), direct)),
+            }
+        }
+        found
+    }
+}
+
+/// See `DataProjection::seed`
+#[derive(Clone, Copy)]
+pub struct ProjectionSeed<'a> {
+    projection: &'a DataProjection,
+    max_scanned_keys: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for ProjectionSeed<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        if self.projection.whole {
+            Value::deserialize(deserializer)
+        } else {
+            deserializer.deserialize_any(self)
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for ProjectionSeed<'_> {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any valid JSON value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut kept = Map::new();
+        let mut loose = Vec::new();
+        let mut keys = 0;
+        while let Some(key) = map.next_key::<String>()? {
+            keys += 1;
+            match self.projection.child_for(&key) {
+                Some((child, direct)) => {
+                    let value = map.next_value_seed(child.seed(self.max_scanned_keys))?;
+                    if !direct {
+                        loose.push(key.clone());
+                    }
+                    kept.insert(key, value);
+                }
+                None => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        // The evaluator wouldn't have looked for these in an object this large
+        if keys > self.max_scanned_keys {
+            for key in loose {
+                kept.remove(&key);
+            }
+        }
+        Ok(Value::Object(kept))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element::<Value>()? {
+            items.push(item);
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::Bool(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Value, E> {
+        Ok(Value::Number(value.into()))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Value, E> {
+        Ok(Value::Number(value.into()))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Value, E> {
+        Ok(Number::from_f64(value).map_or(Value::Null, Value::Number))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::String(value.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<Value, E> {
+        Ok(Value::String(value))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+}
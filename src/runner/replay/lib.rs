@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::replay::{Decision, ReplayBundle, ReplayOptions};
+    use crate::runner::version::{ENGINE_VERSION, LANGUAGE_VERSION};
+    use serde_json::{json, Value};
+
+    const RULES: &str = r#"
+    A **user** passes the check
+      if the __age__ of the **user** is greater than 18
+      and the **user** passes the membership.
+
+    A **user** passes the membership
+      if the __plan__ of the **user** is equal to "gold".
+    "#;
+
+    fn recorded(data: Value) -> ReplayBundle {
+        let mut bundle = ReplayBundle::record(
+            RULES,
+            data,
+            false,
+            ReplayOptions::default(),
+            Decision::default(),
+        );
+        bundle.decision = bundle.replay();
+        bundle
+    }
+
+    #[test]
+    fn test_bundle_round_trips_and_replays_identically() {
+        let bundle = recorded(json!({"user": {"age": 30, "plan": "gold"}}));
+        assert!(bundle.decision.result);
+        assert!(bundle.decision.decision_fingerprint.is_some());
+
+        let json = serde_json::to_string(&bundle).unwrap();
+        let bundle: ReplayBundle = serde_json::from_str(&json).unwrap();
+        let report = bundle.compare(&bundle.replay());
+
+        assert!(report.matches(), "{}", report);
+        assert_eq!(report.to_string(), "Replay matches the recorded decision\n");
+    }
+
+    #[test]
+    fn test_divergence_reports_result_and_trace_diff() {
+        let mut bundle = recorded(json!({"user": {"age": 30, "plan": "gold"}}));
+        // As if production had seen different data than the bundle carries
+        bundle.data = json!({"user": {"age": 16, "plan": "gold"}});
+        let report = bundle.compare(&bundle.replay());
+
+        assert!(!report.matches());
+        let result = report.result.as_ref().unwrap();
+        assert!(result.before && !result.after);
+        assert!(report.decision_fingerprint.is_some());
+        let text = report.to_string();
+        assert!(text.starts_with("result: true -> false\n"), "{}", text);
+        assert!(text.contains("rule 'the check': true -> false"), "{}", text);
+    }
+
+    #[test]
+    fn test_projected_data_and_other_engine_versions() {
+        let mut bundle = recorded(json!({"user": {"age": 30, "plan": "gold", "name": "Ann"}}));
+        // The fingerprint covered the whole payload, so a projection can't reproduce it
+        bundle.data = json!({"user": {"age": 30, "plan": "gold"}});
+        bundle.data_projected = true;
+        bundle.engine_version = "0.0.1".to_string();
+        let report = bundle.compare(&bundle.replay());
+
+        assert!(report.matches(), "{}", report);
+        assert_eq!(report.engine_version.as_ref().unwrap().before, "0.0.1");
+        assert!(report
+            .to_string()
+            .starts_with("recorded with engine 0.0.1, replayed with "));
+    }
+
+    #[test]
+    fn test_bundles_record_the_rule_language() {
+        let mut bundle = recorded(json!({"user": {"age": 30, "plan": "gold"}}));
+        assert_eq!(bundle.engine_version, ENGINE_VERSION);
+        assert_eq!(bundle.language_version.as_deref(), Some(LANGUAGE_VERSION));
+        assert!(bundle.compare(&bundle.replay()).language_version.is_none());
+
+        // A bundle recorded before languages were versioned still replays, without comparing
+        // its fingerprint
+        bundle.language_version = None;
+        bundle.decision.decision_fingerprint = Some("recorded elsewhere".to_string());
+        let report = bundle.compare(&bundle.replay());
+        assert!(report.matches(), "{}", report);
+        assert_eq!(
+            report.language_version.as_ref().unwrap().after.as_deref(),
+            Some(LANGUAGE_VERSION)
+        );
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "recorded in rule language none, replayed in {}\nReplay matches the recorded decision\n",
+                LANGUAGE_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn test_entries_and_errors_are_replayed() {
+        let options = ReplayOptions {
+            entries: vec!["membership".to_string()],
+            ..ReplayOptions::default()
+        };
+        let bundle = ReplayBundle::record(
+            RULES,
+            json!({"user": {"plan": "gold"}}),
+            false,
+            options,
+            Decision::default(),
+        );
+        let decision = bundle.replay();
+        assert!(decision.result, "{:?}", decision.error);
+
+        let broken = ReplayBundle::record(
+            "not a rule",
+            Value::Null,
+            false,
+            ReplayOptions::default(),
+            Decision::default(),
+        );
+        let report = broken.compare(&broken.replay());
+        assert!(report.error.unwrap().after.is_some());
+    }
+}
@@ -0,0 +1,328 @@
+//! Recorded decisions and replaying them.
+//!
+//! `"record_replay": true` adds a `replay` bundle to the response: the rule text, the data, the
+//! options that affect the decision, the engine version, commit and rule language version and the
+//! recorded result, labels, fingerprint and trace. With `"echo_data": "accessed"` the bundle
+//! carries the projection instead of the whole payload and the fingerprint isn't compared on
+//! replay, nor is it when the bundle was recorded by another engine or rule language version.
+//! `policy replay` accepts the bundle or the whole response.
+
+mod lib;
+
+use crate::runner::diff::{diff_traces, Change, TraceDiff};
+use crate::runner::evaluator::evaluate;
+use crate::runner::fingerprint::fingerprint_with_context;
+use crate::runner::options::{EvaluationOptions, LegacyFlatData, ListScalarMode};
+use crate::runner::parser::{parse_rule_set, parse_rules};
+use crate::runner::trace::RuleSetTrace;
+use crate::runner::version::{ENGINE_VERSION, GIT_SHA, LANGUAGE_VERSION};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Everything needed to re-run a decision on another machine, as a single JSON document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayBundle {
+    /// Engine version that made the recorded decision
+    pub engine_version: String,
+    /// Commit of that engine, when its build knew it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_sha: Option<String>,
+    /// Rule language the decision was made in, missing from bundles recorded before
+    /// languages were versioned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language_version: Option<String>,
+    pub rule: String,
+    pub data: Value,
+    /// Set when `data` holds only the values the evaluation read, not the whole payload
+    #[serde(default)]
+    pub data_projected: bool,
+    #[serde(default)]
+    pub options: ReplayOptions,
+    pub decision: Decision,
+}
+
+/// The request options that change a decision
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ReplayOptions {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<String>,
+    #[serde(default)]
+    pub lenient: bool,
+    #[serde(default)]
+    pub validate_data: bool,
+    #[serde(default)]
+    pub reject_disjoint_data: bool,
+    #[serde(default)]
+    pub strict_references: bool,
+    #[serde(default)]
+    pub legacy_flat_data: LegacyFlatData,
+    #[serde(default)]
+    pub list_scalar_mode: ListScalarMode,
+    #[serde(default)]
+    pub range_objects: bool,
+    #[serde(default)]
+    pub versions_as_strings: bool,
+    #[serde(default)]
+    pub case_sensitive_strings: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub as_of: Option<chrono::NaiveDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_root: Option<String>,
+    /// Request context the rules read through `**context**`
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub context: Value,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_tags: Vec<String>,
+}
+
+impl ReplayOptions {
+    #[allow(dead_code)]
+    pub fn to_evaluation_options(&self) -> EvaluationOptions {
+        EvaluationOptions::new()
+            .with_entries(self.entries.clone())
+            .with_lenient(self.lenient)
+            .with_validate_input(self.validate_data)
+            .with_reject_disjoint_data(self.reject_disjoint_data)
+            .with_strict_references(self.strict_references)
+            .with_legacy_flat_data(self.legacy_flat_data)
+            .with_context(self.context.clone())
+            .with_list_scalar_mode(self.list_scalar_mode)
+            .with_range_objects(self.range_objects)
+            .with_versions_as_strings(self.versions_as_strings)
+            .with_case_sensitive_strings(self.case_sensitive_strings)
+            .with_reference_date(self.as_of)
+            .with_data_root(self.data_root.clone())
+            .with_include_tags(self.include_tags.clone())
+            .with_exclude_tags(self.exclude_tags.clone())
+    }
+}
+
+/// What an evaluation decided
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Decision {
+    pub result: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision_fingerprint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<RuleSetTrace>,
+}
+
+/// How a replayed decision differs from the recorded one
+#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ReplayReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub engine_version: Option<Change<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_version: Option<Change<Option<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Change<bool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Change<Option<String>>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, Change<Option<bool>>>,
+    /// Only compared when the whole payload was recorded on the same engine and rule language
+    /// versions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decision_fingerprint: Option<Change<Option<String>>>,
+    pub trace: TraceDiff,
+}
+
+impl ReplayBundle {
+    /// Bundles a decision made by this engine
+    pub fn record(
+        rule: &str,
+        data: Value,
+        data_projected: bool,
+        options: ReplayOptions,
+        decision: Decision,
+    ) -> Self {
+        Self {
+            engine_version: ENGINE_VERSION.to_string(),
+            git_sha: GIT_SHA.map(str::to_string),
+            language_version: Some(LANGUAGE_VERSION.to_string()),
+            rule: rule.to_string(),
+            data,
+            data_projected,
+            options,
+            decision,
+        }
+    }
+
+    /// Re-runs the evaluation with this engine
+    #[allow(dead_code)]
+    pub fn replay(&self) -> Decision {
+        let options = self.options.to_evaluation_options();
+        let parsed = if options.entries.is_empty() {
+            parse_rules(&self.rule)
+        } else {
+            parse_rule_set(&self.rule)
+        };
+        let rule_set = match parsed {
+            Ok(rule_set) => rule_set,
+            Err(error) => {
+                return Decision {
+                    error: Some(error.to_string()),
+                    ..Decision::default()
+                }
+            }
+        };
+
+        let outcome = evaluate(&rule_set, &self.data, &options);
+        let labels: BTreeMap<String, bool> = outcome.labels.clone().into_iter().collect();
+        let decision_fingerprint = match outcome.error {
+            None => Some(fingerprint_with_context(
+                &rule_set,
+                &self.data,
+                &options.context,
+                options.today(),
+                &outcome,
+            )),
+            Some(_) => None,
+        };
+
+        Decision {
+            result: outcome.result,
+            error: outcome.error.map(|error| error.to_string()),
+            labels,
+            decision_fingerprint,
+            trace: outcome.trace,
+        }
+    }
+
+    /// Compares a replayed decision against the recorded one
+    #[allow(dead_code)]
+    pub fn compare(&self, replayed: &Decision) -> ReplayReport {
+        let recorded = &self.decision;
+
+        let mut labels = BTreeMap::new();
+        for label in recorded.labels.keys().chain(replayed.labels.keys()) {
+            let before = recorded.labels.get(label).copied();
+            let after = replayed.labels.get(label).copied();
+            if let Some(change) = change(before, after) {
+                labels.insert(label.clone(), change);
+            }
+        }
+
+        let fingerprint_comparable = !self.data_projected
+            && self.engine_version == ENGINE_VERSION
+            && self.language_version.as_deref() == Some(LANGUAGE_VERSION);
+        let trace = match (&recorded.trace, &replayed.trace) {
+            (Some(before), Some(after)) => diff_traces(before, after),
+            _ => TraceDiff::default(),
+        };
+
+        ReplayReport {
+            engine_version: change(self.engine_version.clone(), ENGINE_VERSION.to_string()),
+            language_version: change(
+                self.language_version.clone(),
+                Some(LANGUAGE_VERSION.to_string()),
+            ),
+            result: change(recorded.result, replayed.result),
+            error: change(recorded.error.clone(), replayed.error.clone()),
+            labels,
+            decision_fingerprint: if fingerprint_comparable {
+                change(
+                    recorded.decision_fingerprint.clone(),
+                    replayed.decision_fingerprint.clone(),
+                )
+            } else {
+                None
+            },
+            trace,
+        }
+    }
+}
+
+impl ReplayReport {
+    /// Whether the replay reached the same decision. A different engine or rule language
+    /// version alone is not a divergence
+    #[allow(dead_code)]
+    pub fn matches(&self) -> bool {
+        self.result.is_none()
+            && self.error.is_none()
+            && self.labels.is_empty()
+            && self.decision_fingerprint.is_none()
+            && self.trace.is_empty()
+    }
+}
+
+#[allow(dead_code)]
+fn change<T: PartialEq>(before: T, after: T) -> Option<Change<T>> {
+    if before == after {
+        None
+    } else {
+        Some(Change { before, after })
+    }
+}
+
+#[allow(dead_code)]
+fn show<T: fmt::Display>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map_or_else(|| "none".to_string(), ToString::to_string)
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(version) = &self.engine_version {
+            writeln!(
+                f,
+                "recorded with engine {}, replayed with {}",
+                version.before, version.after
+            )?;
+        }
+        if let Some(version) = &self.language_version {
+            writeln!(
+                f,
+                "recorded in rule language {}, replayed in {}",
+                show(&version.before),
+                show(&version.after)
+            )?;
+        }
+        if self.matches() {
+            return writeln!(f, "Replay matches the recorded decision");
+        }
+
+        if let Some(result) = &self.result {
+            writeln!(f, "result: {} -> {}", result.before, result.after)?;
+        }
+        if let Some(error) = &self.error {
+            writeln!(
+                f,
+                "error: {} -> {}",
+                show(&error.before),
+                show(&error.after)
+            )?;
+        }
+        for (label, change) in &self.labels {
+            writeln!(
+                f,
+                "label '{}': {} -> {}",
+                label,
+                show(&change.before),
+                show(&change.after)
+            )?;
+        }
+        if let Some(fingerprint) = &self.decision_fingerprint {
+            writeln!(
+                f,
+                "decision_fingerprint: {} -> {}",
+                show(&fingerprint.before),
+                show(&fingerprint.after)
+            )?;
+        }
+        if !self.trace.is_empty() {
+            write!(f, "{}", self.trace)?;
+        }
+        Ok(())
+    }
+}
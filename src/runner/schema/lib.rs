@@ -0,0 +1,398 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::error::RuleError;
+    use crate::runner::evaluator::evaluate;
+    use crate::runner::model::{OutcomeDeclaration, OutcomeType};
+    use crate::runner::options::{EvaluationOptions, LegacyFlatData};
+    use crate::runner::outcome::LEGACY_FLAT_DATA;
+    use crate::runner::parser::parse_rules;
+    use crate::runner::schema::{
+        data_requirements, disjoint_data, flat_data_selector, policy_contract, root_selectors,
+        DisjointData, JsonType, ViolationKind,
+    };
+    use serde_json::json;
+
+    const RULES: &str = r#"
+    A **user** passes the check
+      if the __age__ of the **user.profile** is greater than 18
+      and the __country__ of the **user** is in ["GB", "IE"]
+      and the __birth date__ of the **user** is earlier than 2000-01-01
+      and number of __orders__ in **user** is at least 1.
+    "#;
+
+    fn valid_data() -> serde_json::Value {
+        json!({
+            "user": {
+                "profile": {"age": 30},
+                "country": "GB",
+                "birthDate": "1990-05-01",
+                "orders": [{"id": 1}]
+            }
+        })
+    }
+
+    #[test]
+    fn test_requirements_follow_operators() {
+        let rule_set = parse_rules(RULES).unwrap();
+        let requirements = data_requirements(&rule_set);
+
+        let expected: Vec<(Vec<&str>, Vec<JsonType>)> = vec![
+            (vec!["user", "profile", "age"], vec![JsonType::Number]),
+            (vec!["user", "country"], vec![JsonType::String]),
+            (vec!["user", "birth date"], vec![JsonType::Date]),
+            (
+                vec!["user", "orders"],
+                vec![JsonType::Array, JsonType::Object],
+            ),
+        ];
+        let actual: Vec<(Vec<&str>, Vec<JsonType>)> = requirements
+            .iter()
+            .map(|r| {
+                (
+                    r.path.iter().map(String::as_str).collect(),
+                    r.expected.clone(),
+                )
+            })
+            .collect();
+
+        assert_eq!(actual, expected);
+        assert!(requirements.iter().all(|r| r.rule == "the check"));
+    }
+
+    #[test]
+    fn test_valid_data_has_no_violations() {
+        let rule_set = parse_rules(RULES).unwrap();
+        assert!(rule_set.validate_data(&valid_data()).is_empty());
+    }
+
+    #[test]
+    fn test_wrong_type_reports_exact_path() {
+        let rule_set = parse_rules(RULES).unwrap();
+        let mut data = valid_data();
+        data["user"]["profile"]["age"] = json!("thirty");
+
+        let violations = rule_set.validate_data(&data);
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].kind, ViolationKind::WrongType);
+        assert_eq!(violations[0].path, "$.user.profile.age");
+        assert_eq!(violations[0].actual.as_deref(), Some("string"));
+        assert_eq!(
+            violations[0].to_string(),
+            "$.user.profile.age should be number but is string"
+        );
+    }
+
+    #[test]
+    fn test_missing_path_uses_payload_keys() {
+        let rule_set = parse_rules(RULES).unwrap();
+        let mut data = valid_data();
+        data["user"].as_object_mut().unwrap().remove("birthDate");
+        data["user"]["orders"] = json!(null);
+
+        let violations = rule_set.validate_data(&data);
+        let described: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+
+        assert_eq!(
+            described,
+            vec![
+                "$.user['birth date'] is missing",
+                "$.user.orders should be array or object but is null",
+            ]
+        );
+        assert_eq!(violations[0].kind, ViolationKind::Missing);
+    }
+
+    #[test]
+    fn test_evaluation_rejects_invalid_data_when_enabled() {
+        let rule_set = parse_rules(RULES).unwrap();
+        let mut data = valid_data();
+        data["user"]["profile"]["age"] = json!("30");
+
+        let lax = evaluate(&rule_set, &data, &EvaluationOptions::new());
+        assert!(!matches!(lax.error, Some(RuleError::InvalidData { .. })));
+
+        let strict = evaluate(
+            &rule_set,
+            &data,
+            &EvaluationOptions::new().with_validate_input(true),
+        );
+        match strict.error {
+            Some(RuleError::InvalidData { violations }) => {
+                assert_eq!(violations[0].path, "$.user.profile.age");
+            }
+            other => panic!("expected invalid data, got {:?}", other),
+        }
+        assert!(strict.trace.unwrap().execution.is_empty());
+    }
+
+    #[test]
+    fn test_contract_lists_inputs_and_declared_outputs() {
+        let rules = format!(
+            "Outcome the check is a boolean.\nOutcome tier is one of [\"gold\", \"silver\"].\n\
+             Outcome risk score is a number between 0 and 100.\n{}",
+            RULES
+        );
+        let rule_set = parse_rules(&rules).unwrap();
+        let contract = serde_json::to_value(policy_contract(&rule_set)).unwrap();
+
+        assert_eq!(contract["inputs"].as_array().unwrap().len(), 4);
+        assert_eq!(
+            contract["outputs"],
+            json!([
+                {"outcome": "check", "type": "boolean"},
+                {"outcome": "tier", "type": "one_of", "values": ["gold", "silver"]},
+                {"outcome": "risk score", "type": "number", "min": 0.0, "max": 100.0}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_strict_evaluation_checks_declared_outcomes() {
+        let strict = EvaluationOptions::new().with_validate_input(true);
+        let declared = |kind: OutcomeType| {
+            let mut rule_set = parse_rules(RULES).unwrap();
+            rule_set.outcome_schema.push(OutcomeDeclaration {
+                outcome: "check".to_string(),
+                kind,
+                pos: None,
+            });
+            rule_set
+        };
+
+        let boolean = declared(OutcomeType::Boolean);
+        let outcome = evaluate(&boolean, &valid_data(), &strict);
+        assert!(outcome.error.is_none() && outcome.result);
+
+        let tiers = declared(OutcomeType::OneOf {
+            values: vec![json!("gold"), json!(false)],
+        });
+        assert!(evaluate(&tiers, &valid_data(), &EvaluationOptions::new())
+            .error
+            .is_none());
+        let outcome = evaluate(&tiers, &valid_data(), &strict);
+        assert_eq!(
+            outcome.error.unwrap().to_string(),
+            "Outcome 'check' is true, but it is declared as one of [\"gold\", false]"
+        );
+        assert!(!outcome.trace.unwrap().execution.is_empty());
+
+        let score = declared(OutcomeType::Number {
+            min: 0.0,
+            max: 100.0,
+        });
+        assert!(matches!(
+            evaluate(&score, &valid_data(), &strict).error,
+            Some(RuleError::InvalidOutcome { .. })
+        ));
+    }
+
+    const LICENCE: &str = r#"
+    A **driver** passes the practical test
+      if the __score__ of the **driving test** is at least 40
+      and the __age__ of the **driver** is at least 17.
+    "#;
+
+    #[test]
+    fn test_disjoint_data_names_what_was_expected() {
+        let rule_set = parse_rules(LICENCE).unwrap();
+        assert_eq!(root_selectors(&rule_set), vec!["driving test", "driver"]);
+
+        let order = json!({"customer": {"id": 7}, "order": {"total": 40}});
+        assert_eq!(
+            disjoint_data(&rule_set, &order),
+            Some(DisjointData {
+                expected: vec!["drivingTest".to_string(), "driver".to_string()],
+                found: vec!["customer".to_string(), "order".to_string()],
+            })
+        );
+
+        // Off unless asked for, when every condition just fails
+        let lax = evaluate(&rule_set, &order, &EvaluationOptions::new());
+        assert!(lax.error.is_none() && !lax.result);
+
+        let guarded = EvaluationOptions::new().with_reject_disjoint_data(true);
+        let outcome = evaluate(&rule_set, &order, &guarded);
+        assert_eq!(
+            outcome.error.unwrap().to_string(),
+            "No selectors matched: expected one of [drivingTest, driver]; data contains [customer, order]"
+        );
+        assert!(!outcome.result);
+    }
+
+    #[test]
+    fn test_disjoint_data_matches_selectors_as_the_evaluator_does() {
+        let rule_set = parse_rules(LICENCE).unwrap();
+        let guarded = EvaluationOptions::new().with_reject_disjoint_data(true);
+
+        for data in [
+            json!({"drivingTest": {"score": 45}}),
+            json!({"driving_test": {"score": 45}}),
+            json!({"Driving Test": {"score": 45}}),
+            // One selector is enough; the other is only missing
+            json!({"driver": {"age": 30}, "order": {}}),
+        ] {
+            assert_eq!(disjoint_data(&rule_set, &data), None, "{}", data);
+            assert!(evaluate(&rule_set, &data, &guarded).error.is_none());
+        }
+
+        let mut mapped = parse_rules(LICENCE).unwrap();
+        mapped.map_selector("driver", "person");
+        assert_eq!(
+            disjoint_data(&mapped, &json!({"person": {"age": 30}})),
+            None
+        );
+        assert_eq!(
+            disjoint_data(&mapped, &json!({})).unwrap().expected,
+            vec!["drivingTest", "person"]
+        );
+    }
+
+    #[test]
+    fn test_rules_reading_no_data_take_any_payload() {
+        let from_context = parse_rules(
+            r#"A **request** is allowed if the __channel__ of the **context** is equal to "web"."#,
+        )
+        .unwrap();
+        assert!(root_selectors(&from_context).is_empty());
+        assert_eq!(disjoint_data(&from_context, &json!({"order": {}})), None);
+
+        // A reference to no rule is read as a property of its selector
+        let free_text = parse_rules("A **user** gets access if the **user** is verified.").unwrap();
+        assert_eq!(root_selectors(&free_text), vec!["user"]);
+        assert_eq!(
+            disjoint_data(&free_text, &json!({"user": {"verified": true}})),
+            None
+        );
+        assert_eq!(
+            disjoint_data(&free_text, &json!([1, 2])),
+            Some(DisjointData {
+                expected: vec!["user".to_string()],
+                found: vec![],
+            })
+        );
+    }
+
+    /// The rules and payloads of `examples/testcontainers/go`, which send the properties
+    /// without their selector
+    const SENIOR_DISCOUNT: &str = "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 65.";
+    const ADMIN_ACCESS: &str =
+        "A **User** gets access if the __role__ of the **User** is equal to \"admin\".";
+
+    #[test]
+    fn test_legacy_flat_data_reads_the_example_payloads_under_their_selector() {
+        let legacy = EvaluationOptions::new()
+            .with_reject_disjoint_data(true)
+            .with_legacy_flat_data(LegacyFlatData::Auto);
+
+        for (rule, data, selector, expected) in [
+            (SENIOR_DISCOUNT, json!({"age": 70}), "Person", true),
+            (SENIOR_DISCOUNT, json!({"age": 30}), "Person", false),
+            (ADMIN_ACCESS, json!({"role": "admin"}), "User", true),
+        ] {
+            let rule_set = parse_rules(rule).unwrap();
+            assert_eq!(
+                flat_data_selector(&rule_set, &data, false).as_deref(),
+                Some(selector)
+            );
+            let outcome = evaluate(&rule_set, &data, &legacy);
+            assert!(outcome.error.is_none(), "{:?}", outcome.error);
+            assert_eq!(outcome.result, expected, "{}", data);
+            assert_eq!(outcome.warnings.len(), 1);
+            assert_eq!(outcome.warnings[0].code, Some(LEGACY_FLAT_DATA));
+            assert_eq!(outcome.warnings[0].path, "$");
+
+            // Off, the disjoint data guard refuses it as before
+            let guarded = EvaluationOptions::new().with_reject_disjoint_data(true);
+            assert!(matches!(
+                evaluate(&rule_set, &data, &guarded).error,
+                Some(RuleError::DisjointData(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_legacy_flat_data_leaves_other_payloads_alone() {
+        let legacy = EvaluationOptions::new()
+            .with_reject_disjoint_data(true)
+            .with_legacy_flat_data(LegacyFlatData::Auto);
+
+        // Two root selectors, one of them sent: evaluated as sent
+        let shipping = parse_rules(
+            r#"An **Order** gets expedited_shipping if the __total__ of the **Order** is greater than 100 and the __membership_level__ of the **Customer** is in ["gold", "platinum"]."#,
+        )
+        .unwrap();
+        let data = json!({"total": 150.0, "Customer": {"membership_level": "gold"}});
+        assert_eq!(flat_data_selector(&shipping, &data, true), None);
+        let outcome = evaluate(&shipping, &data, &legacy);
+        assert!(!outcome.result && outcome.warnings.is_empty());
+
+        // Already under its selector
+        let senior = parse_rules(SENIOR_DISCOUNT).unwrap();
+        let outcome = evaluate(&senior, &json!({"Person": {"age": 70}}), &legacy);
+        assert!(outcome.result && outcome.warnings.is_empty());
+
+        // None of the properties the rules read: auto leaves it to the guard, on wraps it
+        let unrelated = json!({"height": 180});
+        assert_eq!(flat_data_selector(&senior, &unrelated, false), None);
+        assert!(matches!(
+            evaluate(&senior, &unrelated, &legacy).error,
+            Some(RuleError::DisjointData(_))
+        ));
+        let on = legacy.with_legacy_flat_data(LegacyFlatData::On);
+        let outcome = evaluate(&senior, &unrelated, &on);
+        assert!(outcome.error.is_none() && !outcome.result);
+        assert_eq!(outcome.warnings[0].code, Some(LEGACY_FLAT_DATA));
+    }
+
+    #[test]
+    fn test_property_catalogue_feeds_requirements_and_data_schema() {
+        let rules = "The **user** has a __profile__ object, a __nickname__ text and an __orders__ list.\n\
+             The __profile__ has numeric __age__.\nThe __orders__ have a __placed on__ date.\n\
+             A **user** passes the check\n  if the __age__ of the **user.profile** is greater than 18\n  \
+             and the __nickname__ of the **user** is not empty.";
+        let rule_set = parse_rules(rules).unwrap();
+        let nickname = data_requirements(&rule_set)
+            .into_iter()
+            .find(|requirement| requirement.path == ["user", "nickname"])
+            .unwrap();
+        assert_eq!(nickname.expected, vec![JsonType::String]);
+
+        let contract = serde_json::to_value(policy_contract(&rule_set)).unwrap();
+        assert_eq!(
+            contract["data_schema"],
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "required": ["user"],
+                "properties": {
+                    "user": {
+                        "type": "object",
+                        "required": ["profile", "nickname", "orders"],
+                        "properties": {
+                            "profile": {
+                                "type": "object",
+                                "required": ["age"],
+                                "properties": {"age": {"type": "number"}}
+                            },
+                            "nickname": {"type": "string"},
+                            "orders": {
+                                "type": "array",
+                                "items": {
+                                    "type": "object",
+                                    "required": ["placedOn"],
+                                    "properties": {
+                                        "placedOn": {"type": "string", "format": "date"}
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            })
+        );
+
+        let undeclared = serde_json::to_value(policy_contract(&parse_rules(RULES).unwrap()));
+        assert!(undeclared.unwrap().get("data_schema").is_none());
+    }
+}
@@ -0,0 +1,652 @@
+//! The data and outcomes a policy reads and decides.
+//!
+//! `Outcome tier is one of ["gold", "silver"].`, `Outcome risk score is a number between 0 and
+//! 100.` and `Outcome licence is a boolean.` declare what values an outcome may take, parsed into
+//! `RuleSet::outcome_schema` (`OutcomeDeclaration`, `OutcomeType`). Parsing fails when an outcome
+//! is declared twice or when a rule decides a declared outcome its declaration doesn't admit; rules
+//! decide `true` or `false`, so only `is a boolean` or a list holding both fits a rule's outcome.
+//! `schema::policy_contract` pairs the declarations with `data_requirements`, and `POST /parse`
+//! returns it as `contract`. With `validate_input` (`validate_data` in requests), evaluation also
+//! fails with `RuleError::InvalidOutcome` when an evaluated outcome isn't admitted.
+//!
+//! `The **driving test** has a __scores__ object, a __person__ object and a __taken on__ date.`
+//! declares the data a policy reads, parsed into `RuleSet::property_catalogue` (`DeclaredProperty`,
+//! `DeclaredType`: `numeric`/`number`, `text`/`string`, `date`, `boolean`, `object`, `list`, before
+//! or after the name). `The __scores__ has numeric __hazard perception__.` declares the properties
+//! of every object or list declared before it under that name; a list's properties are those of its
+//! elements. Parsing fails when the subject isn't declared yet or a property is declared twice.
+//! With a catalogue the validator reports each path a comparison reads that isn't declared as
+//! `undeclared-property`, naming what is declared beside it, and a declared type the condition
+//! can't use, or a step read into a declared text, number, date or boolean, as
+//! `declared-type-mismatch`, both errors at the condition; the request context is never checked.
+//! `data_requirements` takes the declared type where a condition accepts any value, and
+//! `schema::data_schema` turns the catalogue into a JSON Schema (every declared key camelCased and
+//! required), returned in the contract as `data_schema`. Included documents' catalogues are merged;
+//! declaration positions aren't stored.
+//!
+//! Setting `"validate_data": true` checks the data against the paths and types the rules read
+//! before evaluating. Every referenced path is treated as required, even behind an `or`; expected
+//! types come from the operator and literal (numbers for numeric literals, dates for date
+//! operators, and so on). A payload that doesn't fit is rejected with 422 and a `violations` list
+//! naming each JSONPath.
+//!
+//! Data holding none of the selectors the rules read is refused with 422, a `No selectors matched:
+//! expected one of [drivingTest, driver]; data contains [order, customer]` error and a
+//! `disjoint_data` object with both lists, rather than failing every condition. This catches
+//! payloads meant for another policy. The root selectors (`schema::root_selectors`) are the first
+//! name of every path read, plus the selector of each rule reference that names no rule;
+//! `**context**` is left out. They match keys in any spelling, and a selector with a
+//! `selector_mappings` entry also matches its target. One match is enough, and rules that read no
+//! data take any payload. `"allow_disjoint_data": true` turns the check off for `POST /`,
+//! `/policies/{id}/evaluate` and the batch endpoints, where a refused item errors on its own.
+//! `/evaluate-change` and gRPC never check, since a change can leave a document out. In the library
+//! it is `EvaluationOptions::reject_disjoint_data`, off by default.
+
+mod lib;
+
+use crate::runner::evaluator::resolve_rule;
+use crate::runner::model::{
+    constants, ComparisonCondition, ComparisonOperator, Condition, DeclaredType,
+    OutcomeDeclaration, PropertyPath, QuantifiedCondition, RuleExpression, RuleSet, RuleValue,
+};
+use crate::runner::utils::{json_path_from_keys, names_match, transform_property_name};
+use chrono::NaiveDate;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt;
+
+/// JSON types a property can be required to have. `Date` is a string holding a date
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonType {
+    String,
+    Number,
+    Boolean,
+    Date,
+    Array,
+    Object,
+}
+
+impl JsonType {
+    pub fn matches(self, value: &Value) -> bool {
+        match (self, value) {
+            (JsonType::String, Value::String(_)) => true,
+            (JsonType::Number, Value::Number(_)) => true,
+            (JsonType::Boolean, Value::Bool(_)) => true,
+            (JsonType::Date, Value::String(s)) => s
+                .get(..10)
+                .is_some_and(|day| NaiveDate::parse_from_str(day, "%Y-%m-%d").is_ok()),
+            (JsonType::Array, Value::Array(_)) => true,
+            (JsonType::Object, Value::Object(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether a value declared in the property catalogue as `declared` can be of this type.
+    /// A date is a string too
+    pub fn admits(self, declared: DeclaredType) -> bool {
+        self == JsonType::from(declared)
+            || (self, declared) == (JsonType::String, DeclaredType::Date)
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            JsonType::String => "string",
+            JsonType::Number => "number",
+            JsonType::Boolean => "boolean",
+            JsonType::Date => "date",
+            JsonType::Array => "array",
+            JsonType::Object => "object",
+        }
+    }
+}
+
+impl From<DeclaredType> for JsonType {
+    fn from(declared: DeclaredType) -> Self {
+        match declared {
+            DeclaredType::Text => JsonType::String,
+            DeclaredType::Number => JsonType::Number,
+            DeclaredType::Boolean => JsonType::Boolean,
+            DeclaredType::Date => JsonType::Date,
+            DeclaredType::List => JsonType::Array,
+            DeclaredType::Object => JsonType::Object,
+        }
+    }
+}
+
+/// A property path a rule reads, with the types that can satisfy its condition
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DataRequirement {
+    /// Selector and property names as written in the rules, outermost first
+    pub path: Vec<String>,
+    /// Empty when any present value will do
+    pub expected: Vec<JsonType>,
+    /// Outcome of the rule reading the path
+    pub rule: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    Missing,
+    WrongType,
+}
+
+/// A way a payload fails the shape the rule set expects
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DataViolation {
+    pub kind: ViolationKind,
+    /// JSONPath of the offending value, using the payload's own keys where they were found
+    pub path: String,
+    pub expected: Vec<JsonType>,
+    /// JSON type found, for wrong types
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual: Option<String>,
+    pub rule: String,
+}
+
+impl fmt::Display for DataViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expected: Vec<&str> = self.expected.iter().map(|t| t.name()).collect();
+        // Requirements with no expected type only ever produce Missing
+        match self.kind {
+            ViolationKind::Missing => write!(f, "{} is missing", self.path),
+            ViolationKind::WrongType => write!(
+                f,
+                "{} should be {} but is {}",
+                self.path,
+                expected.join(" or "),
+                self.actual.as_deref().unwrap_or("unknown")
+            ),
+        }
+    }
+}
+
+/// A payload that holds none of the selectors the rules read from, most likely one meant for
+/// another policy. See `disjoint_data`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct DisjointData {
+    /// The selectors the rules read from, camelCased as the evaluator looks them up
+    pub expected: Vec<String>,
+    /// The top-level keys of the payload
+    pub found: Vec<String>,
+}
+
+impl fmt::Display for DisjointData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const SHOWN: usize = 10;
+        let mut found = self.found.iter().take(SHOWN).cloned().collect::<Vec<_>>();
+        if self.found.len() > SHOWN {
+            found.push(format!("{} more", self.found.len() - SHOWN));
+        }
+        write!(
+            f,
+            "No selectors matched: expected one of [{}]; data contains [{}]",
+            self.expected.join(", "),
+            found.join(", ")
+        )
+    }
+}
+
+impl RuleSet {
+    /// Checks a payload against the paths and types the rules read.
+    ///
+    /// Every path a condition reads counts as required, including ones behind an `or`.
+    pub fn validate_data(&self, data: &Value) -> Vec<DataViolation> {
+        validate_data(self, data)
+    }
+}
+
+/// Lists every property path the rule set reads from the data, in rule order. Paths under the
+/// request context aren't part of the data and are left out. A path whose condition takes any
+/// value requires the type the property catalogue declares it as, if it does
+pub fn data_requirements(rule_set: &RuleSet) -> Vec<DataRequirement> {
+    let mut requirements = Vec::new();
+
+    for rule in &rule_set.rules {
+        for group in rule.all_conditions() {
+            if let Condition::Comparison(comparison)
+            | Condition::Quantified(QuantifiedCondition { comparison, .. }) = &group.condition
+            {
+                for (path, expected) in condition_requirements(comparison) {
+                    if path
+                        .first()
+                        .is_some_and(|name| names_match(name, constants::CONTEXT_SELECTOR))
+                    {
+                        continue;
+                    }
+                    let expected = match rule_set.declared_property(&path) {
+                        Some(declared) if expected.is_empty() => vec![declared.kind.into()],
+                        _ => expected,
+                    };
+                    requirements.push(DataRequirement {
+                        path,
+                        expected,
+                        rule: rule.outcome_display.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    requirements
+}
+
+/// The selectors the rules read the data from: the first name of every path a comparison
+/// reads, and the selector of every rule reference that names no rule, which is looked up as
+/// a property of it. Names are as written, once each however they are spelled; the request
+/// context isn't part of the data and is left out
+pub fn root_selectors(rule_set: &RuleSet) -> Vec<String> {
+    let unresolved = rule_set.rules.iter().flat_map(|rule| {
+        rule.all_conditions()
+            .filter_map(|group| match &group.condition {
+                Condition::RuleReference(reference)
+                    if resolve_rule(reference.rule_name.value.trim(), rule_set).is_none() =>
+                {
+                    Some(reference.selector.value.clone())
+                }
+                _ => None,
+            })
+    });
+    let read = data_requirements(rule_set)
+        .into_iter()
+        .filter_map(|requirement| requirement.path.into_iter().next());
+
+    let mut roots: Vec<String> = Vec::new();
+    for name in read.chain(unresolved) {
+        if names_match(&name, constants::CONTEXT_SELECTOR) {
+            continue;
+        }
+        if !roots.iter().any(|root| names_match(root, &name)) {
+            roots.push(name);
+        }
+    }
+    roots
+}
+
+/// `Some` when `data` holds none of the rule set's root selectors (see `root_selectors`), so
+/// every condition would fail for want of data rather than on its merits. Selectors match
+/// keys as the evaluator matches them, in any spelling, and a selector with a
+/// `selector_mappings` entry also matches the key it maps to. A rule set that reads no data
+/// matches any payload
+pub fn disjoint_data(rule_set: &RuleSet, data: &Value) -> Option<DisjointData> {
+    let roots = root_selectors(rule_set);
+    if roots.is_empty() {
+        return None;
+    }
+
+    let mut expected: Vec<String> = Vec::new();
+    for root in &roots {
+        if holds_selector(rule_set, data, root) {
+            return None;
+        }
+        let name = transform_property_name(mapped_key(rule_set, root).unwrap_or(root));
+        if !expected.contains(&name) {
+            expected.push(name);
+        }
+    }
+
+    let found = data
+        .as_object()
+        .map(|object| object.keys().cloned().collect())
+        .unwrap_or_default();
+    Some(DisjointData { expected, found })
+}
+
+/// The key to read `data` under when it holds the properties of the rule set's only root
+/// selector at its top level rather than under it, e.g. `Person` for `{"age": 70}` and rules
+/// reading the `age` of the **Person**. `None` when the rules read more than one root selector
+/// or one with a `selector_mappings` entry, or `data` isn't an object or holds the selector;
+/// unless `any_properties`, also when `data` holds none of the properties the rules read of it
+pub fn flat_data_selector(
+    rule_set: &RuleSet,
+    data: &Value,
+    any_properties: bool,
+) -> Option<String> {
+    data.as_object()?;
+    let roots = root_selectors(rule_set);
+    let [root] = roots.as_slice() else {
+        return None;
+    };
+    if mapped_key(rule_set, root).is_some() || holds_selector(rule_set, data, root) {
+        return None;
+    }
+    let holds_property = || {
+        data_requirements(rule_set).iter().any(|requirement| {
+            matches!(requirement.path.as_slice(), [selector, property, ..]
+                if names_match(selector, root) && lookup(data, property).is_some())
+        })
+    };
+    (any_properties || holds_property()).then(|| transform_property_name(root))
+}
+
+/// Whether `data` has a key for the root selector `root`, in any spelling, or for the key its
+/// `selector_mappings` entry maps it to
+pub fn holds_selector(rule_set: &RuleSet, data: &Value, root: &str) -> bool {
+    lookup(data, root).is_some()
+        || mapped_key(rule_set, root).is_some_and(|key| lookup(data, key).is_some())
+}
+
+/// The top-level key of the path a `selector_mappings` entry maps `root` to
+fn mapped_key<'a>(rule_set: &'a RuleSet, root: &str) -> Option<&'a str> {
+    rule_set
+        .selector_mappings
+        .iter()
+        .find(|(selector, _)| names_match(selector, root))
+        .and_then(|(_, path)| path.split('.').next())
+}
+
+/// What a policy reads and what it decides: the data paths its rules read and its outcome
+/// declarations
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PolicyContract {
+    pub inputs: Vec<DataRequirement>,
+    pub outputs: Vec<OutcomeDeclaration>,
+    /// The property catalogue as a JSON Schema, when the policy declares one, see
+    /// `data_schema`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_schema: Option<Value>,
+}
+
+/// The input and output contract of a rule set, see `data_requirements` and
+/// `RuleSet::outcome_schema`
+pub fn policy_contract(rule_set: &RuleSet) -> PolicyContract {
+    PolicyContract {
+        inputs: data_requirements(rule_set),
+        outputs: rule_set.outcome_schema.clone(),
+        data_schema: data_schema(rule_set),
+    }
+}
+
+/// The data the property catalogue declares as a JSON Schema (draft 2020-12), `None` without
+/// a catalogue. Keys are camelCased as the evaluator looks them up first, and every declared
+/// property is required. A list's declared properties are those of its elements
+pub fn data_schema(rule_set: &RuleSet) -> Option<Value> {
+    if rule_set.property_catalogue.is_empty() {
+        return None;
+    }
+    let mut root = json!({"type": "object"});
+    for declared in &rule_set.property_catalogue {
+        let mut node = &mut root;
+        for name in &declared.path {
+            if node["type"] == "array" {
+                node = node
+                    .as_object_mut()?
+                    .entry("items")
+                    .or_insert_with(|| json!({"type": "object"}));
+            }
+            let key = transform_property_name(name);
+            let object = node.as_object_mut()?;
+            let required = object.entry("required").or_insert_with(|| json!([]));
+            if !required.as_array()?.contains(&json!(key)) {
+                required.as_array_mut()?.push(json!(key));
+            }
+            node = object
+                .entry("properties")
+                .or_insert_with(|| json!({}))
+                .as_object_mut()?
+                .entry(key)
+                .or_insert_with(|| json!({"type": "object"}));
+        }
+        *node = match declared.kind {
+            DeclaredType::Text => json!({"type": "string"}),
+            DeclaredType::Number => json!({"type": "number"}),
+            DeclaredType::Boolean => json!({"type": "boolean"}),
+            DeclaredType::Date => json!({"type": "string", "format": "date"}),
+            DeclaredType::List => json!({"type": "array"}),
+            DeclaredType::Object => json!({"type": "object"}),
+        };
+    }
+    root.as_object_mut()?.insert(
+        "$schema".to_string(),
+        json!("https://json-schema.org/draft/2020-12/schema"),
+    );
+    Some(root)
+}
+
+pub fn validate_data(rule_set: &RuleSet, data: &Value) -> Vec<DataViolation> {
+    let mut violations: Vec<DataViolation> = Vec::new();
+
+    for requirement in data_requirements(rule_set) {
+        let Some(violation) = check_requirement(&requirement, data) else {
+            continue;
+        };
+        // The same path is often read by several conditions
+        let seen = violations
+            .iter()
+            .any(|v| v.path == violation.path && v.kind == violation.kind);
+        if !seen {
+            violations.push(violation);
+        }
+    }
+
+    violations
+}
+
+fn check_requirement(requirement: &DataRequirement, data: &Value) -> Option<DataViolation> {
+    let mut current = data;
+    let mut keys: Vec<String> = Vec::new();
+
+    for name in &requirement.path {
+        match lookup(current, name) {
+            Some((key, value)) => {
+                keys.push(key);
+                current = value;
+            }
+            None => {
+                keys.push(name.clone());
+                return Some(DataViolation {
+                    kind: ViolationKind::Missing,
+                    path: json_path_from_keys(&keys),
+                    expected: requirement.expected.clone(),
+                    actual: None,
+                    rule: requirement.rule.clone(),
+                });
+            }
+        }
+    }
+
+    let type_ok =
+        requirement.expected.is_empty() || requirement.expected.iter().any(|t| t.matches(current));
+    if type_ok {
+        return None;
+    }
+
+    Some(DataViolation {
+        kind: ViolationKind::WrongType,
+        path: json_path_from_keys(&keys),
+        expected: requirement.expected.clone(),
+        actual: Some(json_type_of(current).to_string()),
+        rule: requirement.rule.clone(),
+    })
+}
+
+/// Finds a key the way the evaluator does: exact, then any naming variant
+pub(crate) fn lookup<'a>(value: &'a Value, name: &str) -> Option<(String, &'a Value)> {
+    let object = value.as_object()?;
+    if let Some(found) = object.get(name) {
+        return Some((name.to_string(), found));
+    }
+
+    let transformed = transform_property_name(name);
+    object
+        .iter()
+        .find(|(key, _)| names_match(name, key) || names_match(&transformed, key))
+        .map(|(key, found)| (key.clone(), found))
+}
+
+fn json_type_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn split_names(name: &str) -> impl Iterator<Item = String> + '_ {
+    name.split('.').map(str::to_string)
+}
+
+/// The paths a comparison reads and the types each must have
+pub(crate) fn condition_requirements(
+    condition: &ComparisonCondition,
+) -> Vec<(Vec<String>, Vec<JsonType>)> {
+    let mut requirements = Vec::new();
+
+    match &condition.left_property_path {
+        _ if condition.left_expression.is_some() => {
+            requirements.extend(expression_requirements(condition.left_expression.as_ref()))
+        }
+        Some(left) if reads_list_elements(left) => requirements.push(list_requirement(left)),
+        Some(left) => {
+            let mut path: Vec<String> = split_names(&left.selector).collect();
+            let last = left.properties.last().map(String::as_str);
+            let counted = matches!(
+                last,
+                Some(constants::LENGTH_OF_MARKER)
+                    | Some(constants::NUMBER_OF_MARKER)
+                    | Some(constants::HASH_BUCKET_MARKER)
+            ) || last
+                .is_some_and(|marker| constants::AGGREGATE_MARKERS.contains(&marker));
+            let properties = if counted {
+                &left.properties[..left.properties.len() - 1]
+            } else {
+                &left.properties[..]
+            };
+            path.extend(properties.iter().flat_map(|p| split_names(p)));
+
+            let expected = match last {
+                Some(constants::LENGTH_OF_MARKER) => {
+                    vec![JsonType::String, JsonType::Array, JsonType::Object]
+                }
+                Some(constants::NUMBER_OF_MARKER) => vec![JsonType::Array, JsonType::Object],
+                Some(constants::HASH_BUCKET_MARKER) => {
+                    vec![JsonType::String, JsonType::Number, JsonType::Boolean]
+                }
+                Some(marker) if constants::AGGREGATE_MARKERS.contains(&marker) => {
+                    vec![JsonType::Array]
+                }
+                _ => expected_types(condition),
+            };
+            requirements.push((path, expected));
+        }
+        None => {
+            let mut path: Vec<String> = split_names(&condition.selector.value).collect();
+            path.push(condition.property.value.clone());
+            requirements.push((path, expected_types(condition)));
+        }
+    }
+
+    if condition.right_expression.is_some() {
+        requirements.extend(expression_requirements(condition.right_expression.as_ref()));
+    } else if let Some(right) = &condition.right_property_path {
+        if reads_list_elements(right) {
+            requirements.push(list_requirement(right));
+        } else {
+            let mut path: Vec<String> = split_names(&right.selector).collect();
+            path.extend(right.properties.iter().flat_map(|p| split_names(p)));
+            // Only a date can be shifted by `plus`/`minus`
+            let expected = match condition.date_offset {
+                Some(_) => vec![JsonType::Date],
+                None => Vec::new(),
+            };
+            requirements.push((path, expected));
+        }
+    }
+
+    requirements
+}
+
+/// Every property arithmetic reads must be a number
+fn expression_requirements(
+    expression: Option<&RuleExpression>,
+) -> impl Iterator<Item = (Vec<String>, Vec<JsonType>)> + '_ {
+    expression
+        .into_iter()
+        .flat_map(RuleExpression::property_paths)
+        .map(|path| {
+            let mut keys: Vec<String> = split_names(&path.selector).collect();
+            keys.extend(path.properties.iter().flat_map(|p| split_names(p)));
+            (keys, vec![JsonType::Number])
+        })
+}
+
+/// Whether the path reads on from inside a list, with `each` or `the first of` and the like
+fn reads_list_elements(path: &PropertyPath) -> bool {
+    !path.fan_out.is_empty() || !path.elements.is_empty()
+}
+
+/// A path with `each` or element steps needs a list at the first one. What elements hold isn't
+/// required, since elements without the rest of the chain are skipped or fail the condition
+fn list_requirement(path: &PropertyPath) -> (Vec<String>, Vec<JsonType>) {
+    let first = path
+        .fan_out
+        .iter()
+        .chain(path.elements.iter().map(|(at, _)| at))
+        .min()
+        .copied()
+        .unwrap_or_default();
+    let mut keys: Vec<String> = split_names(&path.selector).collect();
+    keys.extend(
+        path.properties[..=first]
+            .iter()
+            .flat_map(|p| split_names(p)),
+    );
+    (keys, vec![JsonType::Array])
+}
+
+/// Types of the left value that can satisfy the operator against its literal
+fn expected_types(condition: &ComparisonCondition) -> Vec<JsonType> {
+    use ComparisonOperator::*;
+
+    // Compared with another property: only its presence can be checked
+    if condition.right_property_path.is_some() {
+        return Vec::new();
+    }
+    // Compared with what arithmetic came to, a number
+    if condition.right_expression.is_some() {
+        return vec![JsonType::Number];
+    }
+
+    let literal = &condition.value.value;
+    match &condition.operator {
+        GreaterThanOrEqual | LessThanOrEqual | GreaterThan | LessThan | EqualTo
+        | ExactlyEqualTo | NotEqualTo => scalar_type(literal).into_iter().collect(),
+        LaterThan | EarlierThan | Within | OlderThan | YoungerThan | NotWithin | MoreThanAgo
+        | AtLeastAgo => vec![JsonType::Date],
+        Between | StrictlyBetween => match literal {
+            RuleValue::Range(low, _) => scalar_type(low).into_iter().collect(),
+            _ => Vec::new(),
+        },
+        In | NotIn => match literal {
+            RuleValue::List(items) => {
+                let mut types: Vec<JsonType> = items.iter().filter_map(scalar_type).collect();
+                types.dedup();
+                if types.len() == 1 {
+                    types
+                } else {
+                    Vec::new()
+                }
+            }
+            _ => Vec::new(),
+        },
+        Contains => vec![JsonType::String, JsonType::Array],
+        IsEmpty | IsNotEmpty => Vec::new(),
+    }
+}
+
+fn scalar_type(value: &RuleValue) -> Option<JsonType> {
+    match value {
+        RuleValue::Number(_) => Some(JsonType::Number),
+        RuleValue::Boolean(_) => Some(JsonType::Boolean),
+        RuleValue::Date(_) => Some(JsonType::Date),
+        RuleValue::String(s) if NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok() => {
+            Some(JsonType::Date)
+        }
+        RuleValue::String(_) => Some(JsonType::String),
+        RuleValue::List(_) | RuleValue::Duration(_) | RuleValue::Range(..) => None,
+    }
+}
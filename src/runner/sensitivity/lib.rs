@@ -0,0 +1,151 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::parser::parse_rules;
+    use crate::runner::sensitivity::{
+        sensitivity, Flip, PathPerturbation, PerturbationSpec, SensitivityReport,
+    };
+    use serde_json::{json, Value};
+
+    /// The driving test of `fixtures/rule-references.case.json`, with the age test reading a
+    /// date of birth
+    const DRIVING_TEST: &str = "\
+A **Person** gets a full driving licence
+  if the **Person** passes the age test
+  and the **Person** passes the practical driving test.
+
+A **Person** passes the age test
+  if the __date of birth__ of the **Person** is earlier than 2008-12-12.
+
+A **Person** passes the practical driving test
+  if the __driving test score__ of the **Person** is at least 60.";
+
+    fn perturb(path: &str, deltas: &[f64]) -> PathPerturbation {
+        PathPerturbation {
+            path: path.to_string(),
+            deltas: deltas.to_vec(),
+            step: 1.0,
+        }
+    }
+
+    fn analyse(data: Value, paths: Vec<PathPerturbation>) -> SensitivityReport {
+        let rule_set = parse_rules(DRIVING_TEST).unwrap();
+        sensitivity(&rule_set, &data, &PerturbationSpec { paths })
+    }
+
+    #[test]
+    fn test_sensitivity_recovers_the_driving_test_thresholds() {
+        let report = analyse(
+            json!({"person": {"dateOfBirth": "2008-12-01", "drivingTestScore": 65}}),
+            vec![
+                perturb("$.person.drivingTestScore", &[1.0, 5.0]),
+                perturb("$.person.dateOfBirth", &[1.0, 30.0]),
+            ],
+        );
+        assert!(report.result);
+        assert_eq!(report.error, None);
+
+        let score = &report.paths[0];
+        assert_eq!(score.value, json!(65));
+        assert_eq!(score.thresholds.len(), 1);
+        assert_eq!(score.thresholds[0].value, json!(60.0));
+        // Down to 60 still passes
+        let tried: Vec<(f64, Value, bool)> = score
+            .perturbations
+            .iter()
+            .map(|p| (p.delta, p.value.clone(), p.flipped))
+            .collect();
+        assert_eq!(
+            tried,
+            vec![
+                (1.0, json!(66), false),
+                (-1.0, json!(64), false),
+                (5.0, json!(70), false),
+                (-5.0, json!(60), false),
+            ]
+        );
+        assert_eq!(
+            score.smallest_flip,
+            Some(Flip {
+                delta: -6.0,
+                value: json!(59),
+                threshold: json!(60.0),
+            })
+        );
+
+        // Born 11 days later is born on the cut-off, no longer earlier than it
+        let birth = &report.paths[1];
+        assert_eq!(birth.thresholds[0].value, json!("2008-12-12"));
+        assert!(birth.perturbations[2].flipped);
+        assert_eq!(birth.perturbations[2].value, json!("2008-12-31"));
+        assert_eq!(
+            birth.smallest_flip,
+            Some(Flip {
+                delta: 11.0,
+                value: json!("2008-12-12"),
+                threshold: json!("2008-12-12"),
+            })
+        );
+        assert!(report.flipped());
+    }
+
+    #[test]
+    fn test_sensitivity_of_a_failing_payload_looks_up_to_the_threshold() {
+        let report = analyse(
+            json!({"person": {"dateOfBirth": "1990-01-01", "drivingTestScore": 59.5}}),
+            vec![PathPerturbation {
+                step: 0.25,
+                ..perturb("$.person.drivingTestScore", &[0.25])
+            }],
+        );
+        assert!(!report.result);
+        let score = &report.paths[0];
+        assert!(!score.perturbations[0].flipped);
+        assert_eq!(
+            score.smallest_flip,
+            Some(Flip {
+                delta: 0.5,
+                value: json!(60.0),
+                threshold: json!(60.0),
+            })
+        );
+        assert!(!report.flipped());
+    }
+
+    #[test]
+    fn test_sensitivity_reports_paths_it_cannot_perturb() {
+        let report = analyse(
+            json!({"person": {"dateOfBirth": "1990-01-01", "drivingTestScore": 65, "name": "Ana"}}),
+            vec![
+                perturb("$.person.age", &[1.0]),
+                perturb("$.person.name", &[1.0]),
+                PathPerturbation {
+                    step: 0.0,
+                    ..perturb("$.person.drivingTestScore", &[1.0])
+                },
+            ],
+        );
+        let errors: Vec<_> = report
+            .paths
+            .iter()
+            .map(|path| path.error.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "not in the payload",
+                "not a number or a YYYY-MM-DD date",
+                "step must be above 0, not 0",
+            ]
+        );
+        assert_eq!(report.evaluations, 1);
+
+        // A path no condition compares with a fixed value has no threshold to flip at
+        let report = analyse(
+            json!({"person": {"dateOfBirth": "1990-01-01", "drivingTestScore": 65, "height": 180}}),
+            vec![perturb("$.person.height", &[10.0])],
+        );
+        assert!(report.paths[0].thresholds.is_empty());
+        assert_eq!(report.paths[0].smallest_flip, None);
+        assert_eq!(report.evaluations, 3);
+    }
+}
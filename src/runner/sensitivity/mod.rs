@@ -0,0 +1,407 @@
+//! Which changes to a payload flip the decision.
+//!
+//! `sensitivity(&RuleSet, &Value, &PerturbationSpec)` evaluates the payload, then with each listed
+//! path (a number or a `YYYY-MM-DD` date, moved in days) moved up and down by each of its `deltas`,
+//! reporting which changes flip the global result. For each path it also reads from the trace the
+//! fixed values conditions compared it with, tries one `step` past each and binary-searches in
+//! `step`s for `smallest_flip`. Served as `POST /sensitivity` with `{"rule", "data", "paths":
+//! [{"path", "deltas", "step"}]}`, and as `policy sensitivity <rules> <data> <perturbations.json>`,
+//! which exits 1 when a listed delta flips the result.
+
+mod lib;
+
+use crate::runner::evaluator::evaluate;
+use crate::runner::model::{ComparisonOperator, ConditionId, RuleSet};
+use crate::runner::options::EvaluationOptions;
+use crate::runner::trace::{ConditionTrace, RuleSetTrace};
+use crate::runner::utils::{
+    json_paths_overlap, rebase_json_path, resolve_json_path, set_json_path,
+};
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+
+/// The values of a payload to move, and how far, to see whether the decision holds
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PerturbationSpec {
+    pub paths: Vec<PathPerturbation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathPerturbation {
+    /// JSONPath from the top of the data to a number or a `YYYY-MM-DD` date, as traces write
+    /// it
+    pub path: String,
+    /// Changes to try, each added and taken away. Days for a date
+    #[serde(default)]
+    pub deltas: Vec<f64>,
+    /// Smallest change the search for a flip considers, so the smallest flip is a multiple
+    /// of it
+    #[serde(default = "default_step")]
+    pub step: f64,
+}
+
+fn default_step() -> f64 {
+    1.0
+}
+
+/// How sensitive the global outcome of a payload is to its numbers and dates, see
+/// `sensitivity`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensitivityReport {
+    /// The global outcome of the payload as sent
+    pub result: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub paths: Vec<PathSensitivity>,
+    /// Payloads evaluated, the one as sent included
+    pub evaluations: usize,
+}
+
+#[allow(dead_code)]
+impl SensitivityReport {
+    /// Whether any of the listed deltas flips the outcome
+    pub fn flipped(&self) -> bool {
+        self.paths
+            .iter()
+            .flat_map(|path| &path.perturbations)
+            .any(|perturbation| perturbation.flipped)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathSensitivity {
+    pub path: String,
+    /// The value as sent, null when the path isn't in the payload
+    pub value: Value,
+    /// Why the path couldn't be perturbed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The fixed numbers or dates conditions compared the path with, read from the trace of
+    /// the payload as sent
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub thresholds: Vec<Threshold>,
+    /// Each delta added, then taken away
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub perturbations: Vec<Perturbation>,
+    /// The smallest change, in `step`s, that flips the outcome by crossing a threshold, up or
+    /// down whichever is nearer. `None` when crossing none of them does
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smallest_flip: Option<Flip>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Threshold {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<ConditionId>,
+    pub operator: ComparisonOperator,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Perturbation {
+    pub delta: f64,
+    /// The value the payload was evaluated with
+    pub value: Value,
+    pub result: bool,
+    /// Whether `result` differs from the outcome of the payload as sent
+    pub flipped: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Flip {
+    pub delta: f64,
+    pub value: Value,
+    /// The threshold the change crosses
+    pub threshold: Value,
+}
+
+/// What a perturbed value is, as a position on a line: a number, or a date as its day count
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scale {
+    Number { integer: bool },
+    Date,
+}
+
+impl Scale {
+    fn read(value: &Value) -> Option<(Scale, f64)> {
+        match value {
+            Value::Number(number) => {
+                let integer = number.is_i64() || number.is_u64();
+                Some((Scale::Number { integer }, number.as_f64()?))
+            }
+            Value::String(text) => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .map(|date| (Scale::Date, date.num_days_from_ce() as f64)),
+            _ => None,
+        }
+    }
+
+    /// `position` as a value of this scale, a whole number staying one
+    fn value(self, position: f64) -> Value {
+        match self {
+            Scale::Number { integer: true } if position.fract() == 0.0 => json!(position as i64),
+            Scale::Number { .. } => json!(position),
+            Scale::Date => NaiveDate::from_num_days_from_ce_opt(position.round() as i32)
+                .map_or(Value::Null, |date| {
+                    json!(date.format("%Y-%m-%d").to_string())
+                }),
+        }
+    }
+
+    fn same_kind(self, other: Scale) -> bool {
+        matches!(
+            (self, other),
+            (Scale::Number { .. }, Scale::Number { .. }) | (Scale::Date, Scale::Date)
+        )
+    }
+}
+
+impl fmt::Display for SensitivityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "result: {}", self.result)?;
+        if let Some(error) = &self.error {
+            writeln!(f, "error: {}", error)?;
+        }
+        for path in &self.paths {
+            writeln!(f, "{} = {}", path.path, path.value)?;
+            if let Some(error) = &path.error {
+                writeln!(f, "  error: {}", error)?;
+            }
+            for perturbation in &path.perturbations {
+                writeln!(
+                    f,
+                    "  {:+} -> {}: {}{}",
+                    perturbation.delta,
+                    perturbation.value,
+                    perturbation.result,
+                    if perturbation.flipped {
+                        " (flipped)"
+                    } else {
+                        ""
+                    }
+                )?;
+            }
+            match &path.smallest_flip {
+                Some(flip) => writeln!(
+                    f,
+                    "  smallest flip: {:+} -> {}, crossing {}",
+                    flip.delta, flip.value, flip.threshold
+                )?,
+                None if path.error.is_none() => writeln!(f, "  no threshold flips it")?,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `sensitivity_with_options` with the default options
+#[allow(dead_code)]
+pub fn sensitivity(rule_set: &RuleSet, data: &Value, spec: &PerturbationSpec) -> SensitivityReport {
+    sensitivity_with_options(rule_set, data, spec, &EvaluationOptions::default())
+}
+
+/// Evaluates `data`, then `data` with each path of `spec` moved by each of its deltas, one
+/// path at a time, and reports which changes flip the global outcome. For each path it also
+/// takes the fixed values conditions compared it with and, from the nearest outwards in each
+/// direction, tries the value one `step` past each until the outcome flips, searching back
+/// in `step`s for the smallest change that does
+pub fn sensitivity_with_options(
+    rule_set: &RuleSet,
+    data: &Value,
+    spec: &PerturbationSpec,
+    options: &EvaluationOptions,
+) -> SensitivityReport {
+    let baseline = evaluate(rule_set, data, options);
+    let mut report = SensitivityReport {
+        result: baseline.result,
+        error: baseline.error.as_ref().map(ToString::to_string),
+        paths: Vec::new(),
+        evaluations: 1,
+    };
+    for perturbation in &spec.paths {
+        let path = perturb_path(
+            rule_set,
+            data,
+            perturbation,
+            options,
+            baseline.trace.as_ref(),
+            &mut report,
+        );
+        report.paths.push(path);
+    }
+    report
+}
+
+fn perturb_path(
+    rule_set: &RuleSet,
+    data: &Value,
+    perturbation: &PathPerturbation,
+    options: &EvaluationOptions,
+    trace: Option<&RuleSetTrace>,
+    report: &mut SensitivityReport,
+) -> PathSensitivity {
+    let value = resolve_json_path(data, &perturbation.path).cloned();
+    let mut sensitivity = PathSensitivity {
+        path: perturbation.path.clone(),
+        value: value.clone().unwrap_or(Value::Null),
+        error: None,
+        thresholds: Vec::new(),
+        perturbations: Vec::new(),
+        smallest_flip: None,
+    };
+    let step = perturbation.step;
+    let read = value.as_ref().and_then(Scale::read);
+    let (scale, origin) = match read {
+        _ if !step.is_finite() || step <= 0.0 => {
+            sensitivity.error = Some(format!("step must be above 0, not {}", step));
+            return sensitivity;
+        }
+        Some(read) => read,
+        None if value.is_none() => {
+            sensitivity.error = Some("not in the payload".to_string());
+            return sensitivity;
+        }
+        None => {
+            sensitivity.error = Some("not a number or a YYYY-MM-DD date".to_string());
+            return sensitivity;
+        }
+    };
+
+    let baseline = report.result;
+    let mut evaluate_at = |position: f64| {
+        let mut perturbed = data.clone();
+        let value = scale.value(position);
+        // The path resolved, so it can be set
+        let _ = set_json_path(&mut perturbed, &perturbation.path, value.clone());
+        let outcome = evaluate(rule_set, &perturbed, options);
+        report.evaluations += 1;
+        (
+            value,
+            outcome.result,
+            outcome.error.map(|error| error.to_string()),
+        )
+    };
+
+    for delta in perturbation.deltas.iter().filter(|delta| delta.is_finite()) {
+        for delta in [delta.abs(), -delta.abs()] {
+            let (value, result, error) = evaluate_at(origin + delta);
+            sensitivity.perturbations.push(Perturbation {
+                delta,
+                value,
+                result,
+                flipped: result != baseline,
+                error,
+            });
+        }
+    }
+
+    let thresholds: Vec<(Threshold, f64)> = trace
+        .map(|trace| {
+            thresholds(
+                trace,
+                &perturbation.path,
+                options.data_root.as_deref(),
+                scale,
+            )
+        })
+        .unwrap_or_default();
+    let mut flips_at = |delta: f64| evaluate_at(origin + delta).1 != baseline;
+    let mut flips = Vec::new();
+    for direction in [1.0, -1.0] {
+        let mut distances: Vec<(f64, &Value)> = thresholds
+            .iter()
+            .map(|(threshold, position)| ((position - origin) * direction, &threshold.value))
+            .filter(|(distance, _)| *distance >= 0.0)
+            .collect();
+        distances.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Steps known not to flip the outcome, and the first known to
+        let mut unflipped = 0u64;
+        for (distance, threshold) in distances {
+            let past = (distance / step).floor() as u64 + 1;
+            if past <= unflipped {
+                continue;
+            }
+            if !flips_at(direction * past as f64 * step) {
+                unflipped = past;
+                continue;
+            }
+            let mut flipped = past;
+            while flipped - unflipped > 1 {
+                let middle = unflipped + (flipped - unflipped) / 2;
+                if flips_at(direction * middle as f64 * step) {
+                    flipped = middle;
+                } else {
+                    unflipped = middle;
+                }
+            }
+            let delta = direction * flipped as f64 * step;
+            flips.push(Flip {
+                delta,
+                value: scale.value(origin + delta),
+                threshold: threshold.clone(),
+            });
+            break;
+        }
+    }
+    sensitivity.smallest_flip = flips
+        .into_iter()
+        .min_by(|a, b| a.delta.abs().total_cmp(&b.delta.abs()));
+    sensitivity.thresholds = thresholds
+        .into_iter()
+        .map(|(threshold, _)| threshold)
+        .collect();
+    sensitivity
+}
+
+/// The fixed values of `scale`'s kind that the traced comparisons compared `path` with, each
+/// with its position, once each. Both bounds of a `between` count
+fn thresholds(
+    trace: &RuleSetTrace,
+    path: &str,
+    root: Option<&str>,
+    scale: Scale,
+) -> Vec<(Threshold, f64)> {
+    // Traces write paths from the data root
+    let reads = |read: &str| match root {
+        Some(root) => json_paths_overlap(&rebase_json_path(root, read), path),
+        None => json_paths_overlap(read, path),
+    };
+    let mut found: Vec<(Threshold, f64)> = Vec::new();
+    let leaves = trace.execution.iter().flat_map(|rule| rule.leaves());
+    for condition in leaves {
+        let ConditionTrace::Comparison(comparison) = condition else {
+            continue;
+        };
+        let compared = if reads(&comparison.property.path) && comparison.value.path.is_none() {
+            &comparison.value.value
+        } else if comparison.value.path.as_deref().is_some_and(reads) {
+            &comparison.property.value
+        } else {
+            continue;
+        };
+        let bounds = match compared {
+            Value::Array(bounds) => bounds.iter().collect(),
+            value => vec![value],
+        };
+        for bound in bounds {
+            let Some((kind, position)) = Scale::read(bound) else {
+                continue;
+            };
+            let threshold = Threshold {
+                condition: comparison.id.clone(),
+                operator: comparison.operator.clone(),
+                value: bound.clone(),
+            };
+            if kind.same_kind(scale) && !found.iter().any(|(known, _)| *known == threshold) {
+                found.push((threshold, position));
+            }
+        }
+    }
+    found
+}
@@ -0,0 +1,148 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::evaluator::evaluate;
+    use crate::runner::options::EvaluationOptions;
+    use crate::runner::parser::parse_rules;
+    use crate::runner::session::{EvaluationSession, OutcomeChange};
+    use serde_json::{json, Value};
+
+    const LICENCE: &str = "\
+A **driver** gets a driving licence
+  if the **driver** passes the age test
+  and the **driver** passes the theory test
+  and the **driver** passes the practical test.
+
+A **driver** passes the age test
+  if the __age__ of the **driver** is greater than or equal to 17
+  or the __supervised__ of the **driver** is equal to true
+  and the __age__ of the **driver** is greater than or equal to 16.
+
+A **driver** passes the theory test
+  if the __theory score__ of the **driver** is greater than or equal to 43
+  and the number of __attempts__ of the **driver** is less than 4.
+
+A **driver** passes the practical test
+  if the __practical faults__ of the **driver** is less than 16.";
+
+    fn assert_matches_fresh(session: &EvaluationSession, options: &EvaluationOptions) {
+        let fresh = evaluate(
+            parse_rules(LICENCE).as_ref().unwrap(),
+            session.data(),
+            options,
+        );
+        let outcome = session.outcome();
+        assert_eq!(outcome.result, fresh.result);
+        assert_eq!(outcome.outcomes, fresh.outcomes);
+        assert_eq!(
+            outcome.error.as_ref().map(ToString::to_string),
+            fresh.error.as_ref().map(ToString::to_string)
+        );
+    }
+
+    fn change(outcome: &str, before: bool, after: bool) -> OutcomeChange {
+        OutcomeChange {
+            outcome: outcome.to_string(),
+            before: Some(before),
+            after: Some(after),
+        }
+    }
+
+    #[test]
+    fn test_update_evaluates_only_the_rules_that_read_the_path() {
+        let rule_set = parse_rules(LICENCE).unwrap();
+        let data = json!({"driver": {
+            "age": 18, "theory score": 45, "attempts": [1], "practical faults": 20
+        }});
+        let mut session = EvaluationSession::new(&rule_set, data);
+        assert!(!session.outcome().result);
+        assert_eq!(session.outcome().evaluations_used, 9);
+
+        let changes = session
+            .update("$.driver['practical faults']", json!(3))
+            .unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                change("driving licence", false, true),
+                change("practical test", false, true),
+            ]
+        );
+        // The practical test and the licence referencing it, not the age or theory test
+        assert_eq!(session.outcome().evaluations_used, 4);
+        assert!(session.outcome().result);
+        assert_matches_fresh(&session, &EvaluationOptions::default());
+
+        // A value no rule reads changes nothing
+        let changes = session.update("$.driver.name", json!("Ana")).unwrap();
+        assert!(changes.is_empty());
+        assert_eq!(session.outcome().evaluations_used, 0);
+        assert_matches_fresh(&session, &EvaluationOptions::default());
+    }
+
+    #[test]
+    fn test_updates_always_match_a_fresh_evaluation() {
+        let rule_set = parse_rules(LICENCE).unwrap();
+        let options = EvaluationOptions::default();
+        let mut session =
+            EvaluationSession::with_options(&rule_set, json!({"driver": {}}), options.clone());
+        assert_matches_fresh(&session, &options);
+
+        let updates: Vec<(&str, Value)> = vec![
+            ("$.driver.age", json!(17)),
+            ("$.driver['theory score']", json!(50)),
+            ("$.driver.attempts", json!([1, 2])),
+            ("$.driver['practical faults']", json!(2)),
+            // From the first branch of the age test to the second
+            ("$.driver.age", json!(16)),
+            ("$.driver.supervised", json!(true)),
+            // A list grown by an element changes the count read of it
+            ("$.driver.attempts[2]", json!(3)),
+            ("$.driver.attempts[3]", json!(4)),
+            ("$.driver.attempts", json!([])),
+            ("$.driver.supervised", json!(false)),
+            ("$.driver.Age", json!(40)),
+            (
+                "$.driver",
+                json!({"age": 30, "theory score": 43, "practical faults": 0}),
+            ),
+            ("$", json!({"driver": {"age": "old"}})),
+        ];
+        for (path, value) in updates {
+            session.update(path, value).unwrap();
+            assert_matches_fresh(&session, &options);
+        }
+    }
+
+    #[test]
+    fn test_update_reads_under_the_data_root() {
+        let rule_set = parse_rules(LICENCE).unwrap();
+        let options = EvaluationOptions::new().with_data_root(Some("$.form".to_string()));
+        let data = json!({"form": {"driver": {
+            "age": 18, "theory score": 45, "attempts": [], "practical faults": 20
+        }}});
+        let mut session = EvaluationSession::with_options(&rule_set, data, options.clone());
+
+        let changes = session
+            .update("$.form.driver['practical faults']", json!(0))
+            .unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(session.outcome().result);
+        assert_matches_fresh(&session, &options);
+    }
+
+    #[test]
+    fn test_update_refuses_paths_it_cannot_set() {
+        let rule_set = parse_rules(LICENCE).unwrap();
+        let data = json!({"driver": {"age": 18, "attempts": [1]}});
+        let mut session = EvaluationSession::new(&rule_set, data.clone());
+
+        for path in [
+            "$.driver.attempts[*]",
+            "$.driver.attempts[5]",
+            "$.driver.age.years",
+        ] {
+            assert!(session.update(path, json!(1)).is_err(), "{}", path);
+        }
+        assert_eq!(session.data(), &data);
+    }
+}
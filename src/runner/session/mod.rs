@@ -0,0 +1,189 @@
+//! Evaluations that follow edits to their data.
+//!
+//! `EvaluationSession::new(&rule_set, data)` evaluates once and keeps each rule's result and trace
+//! in its `RuleMemo`. `update(path, value)` patches the data (`utils::set_json_path`), drops the
+//! kept rules whose traced paths overlap the changed one (`utils::json_paths_overlap`; counts read
+//! the property they count, and a reference answered by no rule reads everything) and the rules
+//! that referenced those, transitively, then evaluates again through `evaluate_with_memo`. It
+//! returns the `OutcomeChange`s; the outcome matches a fresh `evaluate` apart from
+//! `evaluations_used`, which counts only the conditions evaluated again.
+
+mod lib;
+
+use crate::runner::error::RuleError;
+use crate::runner::evaluator::{evaluate_with_memo, RuleMemo};
+use crate::runner::model::RuleSet;
+use crate::runner::options::EvaluationOptions;
+use crate::runner::outcome::EvaluationOutcome;
+use crate::runner::trace::{ConditionTrace, ReferenceResolution, RuleTrace, COMPUTED_SUFFIXES};
+use crate::runner::utils::{json_paths_overlap, rebase_json_path, set_json_path};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// A rule set kept evaluated against data that changes a path at a time, such as a form
+/// re-evaluated on every edit. Each rule's result is kept with the paths its trace read, and
+/// an update evaluates again only the rules that read the changed path and the rules that
+/// referenced them, taking the rest as they were.
+///
+/// The outcome is the one `evaluate` would give for the data as it stands, except that
+/// `evaluations_used` counts only the conditions the last update evaluated and flags keep
+/// the state a rule read them in until the rule is evaluated again
+#[derive(Debug)]
+pub struct EvaluationSession<'a> {
+    rule_set: &'a RuleSet,
+    data: Value,
+    options: EvaluationOptions,
+    memo: RuleMemo,
+    outcome: EvaluationOutcome,
+}
+
+/// An outcome whose result an update changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutcomeChange {
+    pub outcome: String,
+    /// `None` when the rule wasn't evaluated, e.g. no longer reached after a failure
+    pub before: Option<bool>,
+    pub after: Option<bool>,
+}
+
+impl<'a> EvaluationSession<'a> {
+    /// Evaluates `rule_set` against `data` with the default options
+    pub fn new(rule_set: &'a RuleSet, data: Value) -> Self {
+        Self::with_options(rule_set, data, EvaluationOptions::default())
+    }
+
+    pub fn with_options(rule_set: &'a RuleSet, data: Value, options: EvaluationOptions) -> Self {
+        let mut memo = RuleMemo::new();
+        let outcome = evaluate_with_memo(rule_set, &data, &options, &mut memo);
+        Self {
+            rule_set,
+            data,
+            options,
+            memo,
+            outcome,
+        }
+    }
+
+    /// The outcome for the data as it stands
+    pub fn outcome(&self) -> &EvaluationOutcome {
+        &self.outcome
+    }
+
+    pub fn data(&self) -> &Value {
+        &self.data
+    }
+
+    /// Sets the value at `path`, a JSONPath of keys and indices from the top of the data, and
+    /// evaluates again what read it. Returns the outcomes whose result changed, in evaluation
+    /// order; an evaluation that fails shows in `outcome().error`
+    pub fn update(&mut self, path: &str, value: Value) -> Result<Vec<OutcomeChange>, RuleError> {
+        set_json_path(&mut self.data, path, value)
+            .map_err(|problem| RuleError::EvaluationError(format!("Can't update: {}", problem)))?;
+
+        for index in self.stale_rules(path) {
+            self.memo.remove(&index);
+        }
+        let before = std::mem::take(&mut self.outcome.outcomes);
+        self.outcome = evaluate_with_memo(self.rule_set, &self.data, &self.options, &mut self.memo);
+
+        let after = &self.outcome.outcomes;
+        let mut changes: Vec<OutcomeChange> = after
+            .iter()
+            .filter(|(outcome, result)| before.get(*outcome) != Some(*result))
+            .map(|(outcome, result)| OutcomeChange {
+                outcome: outcome.clone(),
+                before: before.get(outcome).copied(),
+                after: Some(*result),
+            })
+            .collect();
+        changes.extend(
+            before
+                .iter()
+                .filter(|(outcome, _)| !after.contains_key(*outcome))
+                .map(|(outcome, result)| OutcomeChange {
+                    outcome: outcome.clone(),
+                    before: Some(*result),
+                    after: None,
+                }),
+        );
+        Ok(changes)
+    }
+
+    /// The kept rules that read `path`, and every kept rule referencing one of them in turn
+    fn stale_rules(&self, path: &str) -> HashSet<usize> {
+        let root = self.options.data_root.as_deref();
+        let mut dependents: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut pending = Vec::new();
+        for (&index, (_, trace)) in &self.memo {
+            if rule_reads(trace, root)
+                .iter()
+                .any(|read| json_paths_overlap(read, path))
+            {
+                pending.push(index);
+            }
+            for outcome in referenced_outcomes(trace) {
+                if let Some(referenced) = self
+                    .rule_set
+                    .get_rule(outcome)
+                    .and_then(|rule| self.rule_set.index_of(rule))
+                {
+                    dependents.entry(referenced).or_default().push(index);
+                }
+            }
+        }
+
+        let mut stale = HashSet::new();
+        while let Some(index) = pending.pop() {
+            if stale.insert(index) {
+                pending.extend(dependents.get(&index).into_iter().flatten());
+            }
+        }
+        stale
+    }
+}
+
+/// The paths of the data `trace` read, from the top of the data. A reference that no rule
+/// answered looked through the data for a property named like it, so it reads all of it.
+/// The request context isn't part of the data and is left out
+fn rule_reads(trace: &RuleTrace, root: Option<&str>) -> Vec<String> {
+    let mut reads = Vec::new();
+    for condition in trace.leaves() {
+        match condition {
+            ConditionTrace::Comparison(comparison) => {
+                reads.extend(
+                    comparison
+                        .read_paths()
+                        .into_iter()
+                        .filter(|path| !path.starts_with("$context"))
+                        .map(|path| {
+                            // A count, bucket or aggregate reads the property it is taken of
+                            let path = COMPUTED_SUFFIXES
+                                .iter()
+                                .find_map(|suffix| path.strip_suffix(suffix))
+                                .unwrap_or(path);
+                            match root {
+                                Some(root) => rebase_json_path(root, path),
+                                None => path.to_string(),
+                            }
+                        }),
+                );
+            }
+            ConditionTrace::RuleReference(reference) => match reference.resolution {
+                ReferenceResolution::InferredProperty { .. } | ReferenceResolution::Unresolved => {
+                    reads.push("$".to_string())
+                }
+                _ => {}
+            },
+            ConditionTrace::Flag(_) | ConditionTrace::Group(_) => {}
+        }
+    }
+    reads
+}
+
+/// Outcomes of the rules `trace` took results from
+fn referenced_outcomes(trace: &RuleTrace) -> impl Iterator<Item = &String> {
+    trace.leaves().filter_map(|condition| match condition {
+        ConditionTrace::RuleReference(reference) => reference.referenced_rule_outcome.as_ref(),
+        _ => None,
+    })
+}
@@ -0,0 +1,90 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::parser::{parse_rule_set, parse_rules};
+    use crate::runner::stats::{ComplexityLimits, LimitViolation, RuleSetStats};
+
+    const UNIVERSITY: &str = r#"
+    A **student** gets university admission
+      if the **student** meets basic eligibility requirements
+      and the **student** qualifies for their chosen program
+      and the **student** has completed application requirements.
+
+    A **student** meets basic eligibility requirements
+      if the __age__ of the **applicant** in the **student** is at least 16
+      and the __application date__ of the **submission** in the **student** is within 90 days
+      and the __country of origin__ of the **applicant** in the **student** is not in ["restricted_country_1", "restricted_country_2"].
+
+    A **student** qualifies for their chosen program
+      if the **student** meets undergraduate program requirements
+      or the **student** meets graduate program requirements.
+
+    A **student** meets undergraduate program requirements
+      if the __program type__ of the **application.program** is in ["bachelor_arts", "bachelor_science", "bachelor_engineering"]
+      and the __sat score__ of the **standardized tests** in the **student** is at least 1200.
+
+    A **student** meets graduate program requirements
+      if the __program type__ of the **application.program** is in ["master_arts", "master_science", "master_business"]
+      and the __gre score__ of the **standardized tests** in the **student** is at least 310
+      and the **student** has relevant work experience.
+
+    A **student** has relevant work experience
+      if the __years of experience__ of the **employment.history** in the **student** is at least 2.
+
+    A **student** has completed application requirements
+      if the __payment date__ of the **finances.payment** in the **student** is within 30 days
+      and number of __recommendation letters__ in **student** is at least 3.
+    "#;
+
+    #[test]
+    fn test_university_stats() {
+        let stats = parse_rules(UNIVERSITY).unwrap().stats();
+
+        assert_eq!(
+            stats,
+            RuleSetStats {
+                rules: 7,
+                conditions: 16,
+                max_reference_depth: 3,
+                max_property_chain: 4,
+                list_literals: 3,
+                list_elements: 8,
+                time_dependent_conditions: 2,
+                cost: 35,
+            }
+        );
+    }
+
+    #[test]
+    fn test_reference_cycles_terminate() {
+        let rule_set = parse_rule_set(
+            r#"
+            A **user** passes the check if the **user** passes the loop.
+            A **user** passes the loop if the **user** passes the check.
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(rule_set.stats().max_reference_depth, 2);
+    }
+
+    #[test]
+    fn test_limits_name_the_first_exceeded() {
+        let stats = parse_rules(UNIVERSITY).unwrap().stats();
+
+        assert_eq!(ComplexityLimits::default().check(&stats), Ok(()));
+        assert_eq!(
+            ComplexityLimits {
+                max_rules: Some(10),
+                max_reference_depth: Some(2),
+                max_cost: Some(10),
+                ..ComplexityLimits::default()
+            }
+            .check(&stats),
+            Err(LimitViolation {
+                limit: "max_reference_depth",
+                value: 3,
+                max: 2,
+            })
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Policy size and evaluation cost.
+//!
+//! `RuleSet::stats()` sizes a policy and scores its evaluation cost, returned as `stats` by `POST
+//! /parse` and checked against the server's complexity limits.
+
+mod lib;
+
+use crate::runner::evaluator::resolve_rule;
+use crate::runner::model::{constants, Condition, QuantifiedCondition, Rule, RuleSet, RuleValue};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Size and shape of a rule set, used to score how expensive a policy is to evaluate
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct RuleSetStats {
+    pub rules: usize,
+    pub conditions: usize,
+    /// Longest chain of rule references; a rule with no references has depth 0
+    pub max_reference_depth: usize,
+    /// Most selector and property segments read by a single condition
+    pub max_property_chain: usize,
+    pub list_literals: usize,
+    /// Elements across every list literal
+    pub list_elements: usize,
+    /// Conditions relative to the current date (`within`, `older than`, `younger than`, `today`)
+    pub time_dependent_conditions: usize,
+    /// `conditions + list_elements / 10 + 2 * time_dependent_conditions + 5 * max_reference_depth`
+    pub cost: usize,
+}
+
+impl RuleSet {
+    pub fn stats(&self) -> RuleSetStats {
+        let mut stats = RuleSetStats {
+            rules: self.rules.len(),
+            ..RuleSetStats::default()
+        };
+
+        for rule in &self.rules {
+            for group in rule.all_conditions() {
+                // A group counts as the conditions in it
+                if matches!(group.condition, Condition::Group(_)) {
+                    continue;
+                }
+                stats.conditions += 1;
+                let (Condition::Comparison(comparison)
+                | Condition::Quantified(QuantifiedCondition { comparison, .. })) = &group.condition
+                else {
+                    continue;
+                };
+
+                let chain = match &comparison.left_property_path {
+                    Some(path) => {
+                        path.selector.split('.').count()
+                            + path
+                                .properties
+                                .iter()
+                                .filter(|p| {
+                                    *p != constants::LENGTH_OF_MARKER
+                                        && *p != constants::NUMBER_OF_MARKER
+                                        && *p != constants::HASH_BUCKET_MARKER
+                                        && !constants::AGGREGATE_MARKERS.contains(&p.as_str())
+                                })
+                                .map(|p| p.split('.').count())
+                                .sum::<usize>()
+                    }
+                    None => comparison.selector.value.split('.').count() + 1,
+                };
+                stats.max_property_chain = stats.max_property_chain.max(chain);
+
+                if let RuleValue::List(items) = &comparison.value.value {
+                    stats.list_literals += 1;
+                    stats.list_elements += items.len();
+                }
+
+                if comparison.is_time_dependent() {
+                    stats.time_dependent_conditions += 1;
+                }
+            }
+        }
+
+        let mut depths = HashMap::new();
+        stats.max_reference_depth = self
+            .rules
+            .iter()
+            .map(|rule| reference_depth(self, rule, &mut depths, &mut HashSet::new()))
+            .max()
+            .unwrap_or(0);
+
+        stats.cost = stats.conditions
+            + stats.list_elements / 10
+            + 2 * stats.time_dependent_conditions
+            + 5 * stats.max_reference_depth;
+        stats
+    }
+}
+
+/// Depth of the reference tree below `rule`, resolving references the way the evaluator does.
+/// Rules are keyed by their index in the set, as on the evaluator's stack, so a reference by
+/// label and one by outcome reach the same entry. A reference back into the current path is
+/// not followed.
+fn reference_depth(
+    rule_set: &RuleSet,
+    rule: &Rule,
+    depths: &mut HashMap<usize, usize>,
+    visiting: &mut HashSet<usize>,
+) -> usize {
+    let Some(index) = rule_set.index_of(rule) else {
+        return 0;
+    };
+    if let Some(&depth) = depths.get(&index) {
+        return depth;
+    }
+    if !visiting.insert(index) {
+        return 0;
+    }
+
+    let mut depth = 0;
+    for group in rule.all_conditions() {
+        let Condition::RuleReference(reference) = &group.condition else {
+            continue;
+        };
+        let name = reference.rule_name.value.trim();
+        if let Some((referenced, _)) = resolve_rule(name, rule_set) {
+            depth = depth.max(1 + reference_depth(rule_set, referenced, depths, visiting));
+        }
+    }
+
+    visiting.remove(&index);
+    depths.insert(index, depth);
+    depth
+}
+
+/// Upper bounds a server places on the policies it accepts. `None` means unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct ComplexityLimits {
+    pub max_rules: Option<usize>,
+    pub max_conditions: Option<usize>,
+    pub max_reference_depth: Option<usize>,
+    pub max_cost: Option<usize>,
+}
+
+/// The first limit a rule set exceeds
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LimitViolation {
+    /// Name of the `RuleSetStats` field over its limit
+    pub limit: &'static str,
+    pub value: usize,
+    pub max: usize,
+}
+
+impl ComplexityLimits {
+    pub fn check(&self, stats: &RuleSetStats) -> Result<(), LimitViolation> {
+        let checks = [
+            ("rules", stats.rules, self.max_rules),
+            ("conditions", stats.conditions, self.max_conditions),
+            (
+                "max_reference_depth",
+                stats.max_reference_depth,
+                self.max_reference_depth,
+            ),
+            ("cost", stats.cost, self.max_cost),
+        ];
+
+        for (limit, value, max) in checks {
+            if let Some(max) = max {
+                if value > max {
+                    return Err(LimitViolation { limit, value, max });
+                }
+            }
+        }
+        Ok(())
+    }
+}
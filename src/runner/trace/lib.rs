@@ -1,10 +1,13 @@
 #[cfg(test)]
 mod tests {
-    use crate::runner::model::{ComparisonOperator, RuleValue, SourcePosition};
+    use crate::runner::evaluator::{evaluate, evaluate_condition};
+    use crate::runner::model::{ComparisonOperator, Duration, RuleValue, SourcePosition, TimeUnit};
+    use crate::runner::options::{EvaluationOptions, TraceVerbosity};
+    use crate::runner::parser::parse_rules;
     use crate::runner::trace::{
-        ComparisonEvaluationTrace, ComparisonTrace, ConditionTrace, OutcomeTrace,
-        PropertyCheckTrace, PropertyTrace, RuleReferenceTrace, RuleSetTrace, RuleTrace,
-        SelectorTrace, TypedValue, ValueTrace,
+        ComparisonEvaluationTrace, ComparisonTrace, ConditionTrace, MissedKey, OutcomeTrace,
+        PropertyCheckTrace, PropertyTrace, ReferenceResolution, RuleReferenceTrace, RuleSetTrace,
+        RuleTrace, SelectorTrace, TypedValue, ValueSource, ValueTrace,
     };
     use chrono::NaiveDate;
     use serde_json;
@@ -72,6 +75,9 @@ mod tests {
         let trace = PropertyTrace {
             value: serde_json::json!({"status": "active"}),
             path: "$.user.status".to_string(),
+            document: None,
+            missed_key: None,
+            warning: None,
         };
 
         let json = serde_json::to_value(&trace).unwrap();
@@ -168,18 +174,43 @@ mod tests {
         assert!(value_trace.pos.is_none());
     }
 
+    #[test]
+    fn test_duration_value_trace_keeps_the_authored_unit() {
+        let value_trace =
+            RuleValue::Duration(Duration::new(2.0, TimeUnit::Years)).to_value_trace(None);
+
+        assert_eq!(value_trace.value_type, "duration");
+        assert_eq!(value_trace.value, serde_json::json!("2 years"));
+        assert_eq!(value_trace.seconds, Some(63_113_904.0));
+        // Other values have no seconds to show
+        assert_eq!(RuleValue::Number(2.0).to_value_trace(None).seconds, None);
+    }
+
     #[test]
     fn test_comparison_evaluation_trace_serialization() {
         let trace = ComparisonEvaluationTrace {
             left_value: TypedValue {
                 value: serde_json::json!(25),
                 value_type: "number".to_string(),
+                source: None,
             },
             right_value: TypedValue {
                 value: serde_json::json!(18),
                 value_type: "number".to_string(),
+                source: None,
             },
             comparison_result: true,
+            warning: None,
+            list_mode: None,
+            deciding_element: None,
+            duration_seconds: None,
+            window: None,
+            cutoff: None,
+            reference_date: None,
+            quantifier: None,
+            matched_elements: None,
+            case_sensitive: None,
+            unexpected_object: None,
         };
 
         let json = serde_json::to_value(&trace).unwrap();
@@ -205,6 +236,7 @@ mod tests {
     #[test]
     fn test_rule_reference_trace_serialization() {
         let trace = RuleReferenceTrace {
+            id: None,
             selector: SelectorTrace {
                 value: "account".to_string(),
                 pos: None,
@@ -212,7 +244,10 @@ mod tests {
             rule_name: "active".to_string(),
             referenced_rule_outcome: Some("account is active".to_string()),
             property_check: None,
+            resolution: ReferenceResolution::ExactOutcome,
             result: true,
+            skipped: false,
+            inner_result: None,
         };
 
         let json = serde_json::to_value(&trace).unwrap();
@@ -220,6 +255,7 @@ mod tests {
         assert_eq!(json["rule_name"], "active");
         assert_eq!(json["referenced_rule_outcome"], "account is active");
         assert!(json.get("property_check").is_none());
+        assert_eq!(json["resolution"]["kind"], "exact_outcome");
         assert_eq!(json["result"], true);
     }
 
@@ -231,6 +267,7 @@ mod tests {
         };
 
         let trace = RuleReferenceTrace {
+            id: None,
             selector: SelectorTrace {
                 value: "user".to_string(),
                 pos: None,
@@ -238,7 +275,12 @@ mod tests {
             rule_name: "background check".to_string(),
             referenced_rule_outcome: None,
             property_check: Some(property_check),
+            resolution: ReferenceResolution::InferredProperty {
+                property: "status".to_string(),
+            },
             result: false,
+            skipped: false,
+            inner_result: None,
         };
 
         let json = serde_json::to_value(&trace).unwrap();
@@ -247,12 +289,17 @@ mod tests {
         assert!(json.get("referenced_rule_outcome").is_none());
         assert!(json["property_check"].is_object());
         assert_eq!(json["property_check"]["property_name"], "status");
+        assert_eq!(
+            json["resolution"],
+            serde_json::json!({"kind": "inferred_property", "property": "status"})
+        );
         assert_eq!(json["result"], false);
     }
 
     #[test]
     fn test_comparison_trace_serialization() {
         let trace = ComparisonTrace {
+            id: None,
             selector: SelectorTrace {
                 value: "user".to_string(),
                 pos: Some(SourcePosition {
@@ -264,25 +311,48 @@ mod tests {
             property: PropertyTrace {
                 value: serde_json::json!(25),
                 path: "$.user.age".to_string(),
+                document: None,
+                missed_key: None,
+                warning: None,
             },
             operator: ComparisonOperator::GreaterThanOrEqual,
             value: ValueTrace {
                 value: serde_json::json!(18),
                 value_type: "number".to_string(),
                 pos: None,
+                path: None,
+                document: None,
+                seconds: None,
+                source: None,
             },
             evaluation_details: Some(ComparisonEvaluationTrace {
                 left_value: TypedValue {
                     value: serde_json::json!(25),
                     value_type: "number".to_string(),
+                    source: None,
                 },
                 right_value: TypedValue {
                     value: serde_json::json!(18),
                     value_type: "number".to_string(),
+                    source: None,
                 },
                 comparison_result: true,
+                warning: None,
+                list_mode: None,
+                deciding_element: None,
+                duration_seconds: None,
+                window: None,
+                cutoff: None,
+                reference_date: None,
+                quantifier: None,
+                matched_elements: None,
+                case_sensitive: None,
+                unexpected_object: None,
             }),
             result: true,
+            inner_result: None,
+            left_expression: None,
+            right_expression: None,
         };
 
         let json = serde_json::to_value(&trace).unwrap();
@@ -297,6 +367,7 @@ mod tests {
     #[test]
     fn test_condition_trace_comparison_variant() {
         let comparison_trace = ComparisonTrace {
+            id: None,
             selector: SelectorTrace {
                 value: "user".to_string(),
                 pos: None,
@@ -304,18 +375,28 @@ mod tests {
             property: PropertyTrace {
                 value: serde_json::json!("active"),
                 path: "$.user.status".to_string(),
+                document: None,
+                missed_key: None,
+                warning: None,
             },
             operator: ComparisonOperator::EqualTo,
             value: ValueTrace {
                 value: serde_json::json!("active"),
                 value_type: "string".to_string(),
                 pos: None,
+                path: None,
+                document: None,
+                seconds: None,
+                source: None,
             },
             evaluation_details: None,
             result: true,
+            inner_result: None,
+            left_expression: None,
+            right_expression: None,
         };
 
-        let condition_trace = ConditionTrace::Comparison(comparison_trace);
+        let condition_trace = ConditionTrace::Comparison(Box::new(comparison_trace));
         let json = serde_json::to_value(&condition_trace).unwrap();
 
         // Should serialize as the inner ComparisonTrace due to #[serde(untagged)]
@@ -327,6 +408,7 @@ mod tests {
     #[test]
     fn test_condition_trace_rule_reference_variant() {
         let rule_ref_trace = RuleReferenceTrace {
+            id: None,
             selector: SelectorTrace {
                 value: "account".to_string(),
                 pos: None,
@@ -334,7 +416,10 @@ mod tests {
             rule_name: "is active".to_string(),
             referenced_rule_outcome: Some("account active".to_string()),
             property_check: None,
+            resolution: ReferenceResolution::ExactOutcome,
             result: true,
+            skipped: false,
+            inner_result: None,
         };
 
         let condition_trace = ConditionTrace::RuleReference(rule_ref_trace);
@@ -360,6 +445,8 @@ mod tests {
             },
             conditions: vec![],
             result: true,
+            skipped: false,
+            error: None,
         };
 
         let json = serde_json::to_value(&rule_trace).unwrap();
@@ -384,6 +471,8 @@ mod tests {
             },
             conditions: vec![],
             result: false,
+            skipped: false,
+            error: None,
         };
 
         let json = serde_json::to_value(&rule_trace).unwrap();
@@ -408,6 +497,8 @@ mod tests {
                 },
                 conditions: vec![],
                 result: true,
+                skipped: false,
+                error: None,
             }],
         };
 
@@ -416,4 +507,478 @@ mod tests {
         assert_eq!(json["execution"].as_array().unwrap().len(), 1);
         assert_eq!(json["execution"][0]["outcome"]["value"], "eligible");
     }
+
+    #[test]
+    fn test_non_finite_numbers_are_labelled() {
+        let trace = TypedValue::from(&RuleValue::Number(f64::NAN));
+        assert_eq!(trace.value, serde_json::json!("NaN"));
+        assert_eq!(trace.value_type, "number");
+
+        let list = TypedValue::from(&RuleValue::List(vec![
+            RuleValue::Number(f64::INFINITY),
+            RuleValue::Number(f64::NEG_INFINITY),
+            RuleValue::Number(1.5),
+        ]));
+        assert_eq!(
+            list.value,
+            serde_json::json!(["Infinity", "-Infinity", 1.5])
+        );
+    }
+
+    const DRIVING_TEST: &str = r#"A **driving test** gets a driving licence
+  if the **driving test** passes the age test
+  and the **driving test** passes the test requirements.
+
+A **driving test** passes the age test
+  if the __date of birth__ of the **person** of the **driving test** is earlier than 2008-12-12.
+
+A **driving test** passes the test requirements
+  if **driving test** passes the theory test
+  and the **driving test** passes the practical test.
+
+A **driving test** passes the theory test
+  if the __multiple choice__ of the **theory** of the **scores** of the **driving test** is at least 43
+  and the __hazard perception__ of the **theory** of the **scores** of the **driving test** is at least 44.
+
+A **driving test** passes the practical test
+  if the __minor__ of the __practical__ of the __scores__ of the **driving test** is no more than 15
+  and the __major__ of the __practical__ of the __scores__ of the **driving test** is equal to false."#;
+
+    #[test]
+    fn test_trace_to_csv_snapshot() {
+        let rule_set = parse_rules(DRIVING_TEST).unwrap();
+        let data = serde_json::json!({
+            "drivingTest": {
+                "person": {"dateOfBirth": "1990-01-01", "name": "Bob"},
+                "scores": {
+                    "practical": {"major": true, "minor": 13},
+                    "theory": {"hazardPerception": 75, "multipleChoice": 45}
+                }
+            }
+        });
+        let trace = evaluate(&rule_set, &data, &EvaluationOptions::new())
+            .trace
+            .unwrap();
+
+        let csv = trace.to_csv();
+        assert!(csv.ends_with("\r\n"));
+        let rows: Vec<&str> = csv.split_terminator("\r\n").collect();
+        assert_eq!(
+            rows,
+            vec![
+                "rule,label,condition,path,operator,expected,expected_source,actual,result,failing_path",
+                "a driving licence,,driving-licence/4a0d39c5,,references,passes the age test,,,true,false",
+                "a driving licence,,driving-licence/59b3bd4d,,references,passes the test requirements,,,false,true",
+                "the age test,,age-test/0d67d5c8,$.drivingTest.person.dateOfBirth,is earlier than,2008-12-12,literal at line 6,1990-01-01,true,false",
+                "the test requirements,,test-requirements/dfe9e4c6,,references,passes the theory test,,,true,false",
+                "the test requirements,,test-requirements/49bf99bf,,references,passes the practical test,,,false,true",
+                "the theory test,,theory-test/2ab2003f,$.drivingTest.scores.theory.multipleChoice,is greater than or equal to,43.0,literal at line 13,45,true,false",
+                "the theory test,,theory-test/c763c4ae,$.drivingTest.scores.theory.hazardPerception,is greater than or equal to,44.0,literal at line 14,75,true,false",
+                "the practical test,,practical-test/29cde625,$.drivingTest.scores.practical.minor,is less than or equal to,15.0,literal at line 17,13,true,false",
+                "the practical test,,practical-test/b32f4fd8,$.drivingTest.scores.practical.major,is equal to,false,literal at line 18,true,false,true",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_ndjson_reads_back_as_the_trace() {
+        let rule_set = parse_rules(DRIVING_TEST).unwrap();
+        let data = serde_json::json!({
+            "drivingTest": {
+                "person": {"dateOfBirth": "1990-01-01"},
+                "scores": {
+                    "practical": {"major": true, "minor": 13},
+                    "theory": {"hazardPerception": 75, "multipleChoice": 45}
+                }
+            }
+        });
+        let trace = evaluate(&rule_set, &data, &EvaluationOptions::new())
+            .trace
+            .unwrap();
+
+        let mut ndjson = Vec::new();
+        trace.write_ndjson(&mut ndjson).unwrap();
+        let ndjson = String::from_utf8(ndjson).unwrap();
+        assert!(ndjson.ends_with('\n'));
+        let mut lines: Vec<serde_json::Value> = ndjson
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let summary = lines.pop().unwrap();
+        assert_eq!(
+            summary,
+            serde_json::json!({"line": "summary", "rules": 5, "conditions": 9, "failed": 3})
+        );
+        let parents: Vec<serde_json::Value> = lines
+            .iter()
+            .map(|line| line.get("parents").cloned().unwrap_or_default())
+            .collect();
+        assert_eq!(
+            parents,
+            serde_json::json!([null, [0], [0], [2], [2]])
+                .as_array()
+                .unwrap()
+                .clone()
+        );
+
+        let execution = lines
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut line)| {
+                let fields = line.as_object_mut().unwrap();
+                assert_eq!(fields.remove("line").unwrap(), "rule");
+                assert_eq!(fields.remove("id").unwrap(), index);
+                fields.remove("parents");
+                serde_json::from_value::<RuleTrace>(line).unwrap()
+            })
+            .collect();
+        let read = RuleSetTrace { execution };
+        assert_eq!(
+            serde_json::to_value(&read).unwrap(),
+            serde_json::to_value(&trace).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_trace_to_csv_escapes_cells() {
+        let rule_set = parse_rules(
+            r#"staff. A **user** is staff if the __role__ of the **user** is in ["admin, ops", "owner"]."#,
+        )
+        .unwrap();
+        let data = serde_json::json!({"user": {"role": "the \"boss\""}});
+        let trace = evaluate(&rule_set, &data, &EvaluationOptions::new())
+            .trace
+            .unwrap();
+
+        let csv = trace.to_csv();
+        let row = csv.split_terminator("\r\n").nth(1).unwrap();
+        let id = trace.execution[0].conditions[0].id().unwrap();
+        assert_eq!(
+            row,
+            format!(
+                r#"staff,staff,{},$.user.role,is in,"[""admin, ops"",""owner""]",literal at line 1,"the ""boss""",false,true"#,
+                id
+            )
+        );
+    }
+
+    #[test]
+    fn test_trace_explanation_follows_the_failing_path() {
+        let rule_set = parse_rules(DRIVING_TEST).unwrap();
+        let mut data = serde_json::json!({
+            "drivingTest": {
+                "person": {"dateOfBirth": "1990-01-01"},
+                "scores": {
+                    "practical": {"major": true, "minor": 13},
+                    "theory": {"hazardPerception": 75}
+                }
+            }
+        });
+        let explain = |data: &serde_json::Value| {
+            evaluate(&rule_set, data, &EvaluationOptions::new())
+                .trace
+                .unwrap()
+                .explanation()
+        };
+
+        assert_eq!(
+            explain(&data).unwrap(),
+            "the theory test: $.drivingTest.scores.theory['multiple choice'] is greater than or equal to 43.0, was missing; \
+             the practical test: $.drivingTest.scores.practical.major is equal to false, was true"
+        );
+
+        data["drivingTest"]["scores"]["practical"]["major"] = serde_json::json!(false);
+        data["drivingTest"]["scores"]["theory"]["multipleChoice"] = serde_json::json!(45);
+        assert_eq!(explain(&data), None);
+    }
+
+    #[test]
+    fn test_negated_conditions_in_csv_and_explanation() {
+        let rule_set = parse_rules(
+            "A **user** is trusted if not the __role__ of the **user** is equal to \"guest\".",
+        )
+        .unwrap();
+        let data = serde_json::json!({"user": {"role": "guest"}});
+        let trace = evaluate(&rule_set, &data, &EvaluationOptions::new())
+            .trace
+            .unwrap();
+
+        let csv = trace.to_csv();
+        let row = csv.split_terminator("\r\n").nth(1).unwrap();
+        assert!(
+            row.contains(",$.user.role,not is equal to,guest,literal at line 1,guest,false,true"),
+            "{}",
+            row
+        );
+        assert_eq!(
+            trace.explanation().unwrap(),
+            "trusted: not $.user.role is equal to guest, was guest"
+        );
+    }
+
+    #[test]
+    fn test_grouped_conditions_in_csv_explanation_and_round_trip() {
+        let rule_set = parse_rules(
+            "A **user** gets access if the __age__ of the **user** is at least 18 and (the __role__ of the **user** is equal to \"admin\" or the __vip__ of the **user** is equal to true).",
+        )
+        .unwrap();
+        let data = serde_json::json!({"user": {"age": 30, "role": "guest", "vip": false}});
+        let trace = evaluate(&rule_set, &data, &EvaluationOptions::new())
+            .trace
+            .unwrap();
+
+        // A row per condition in the group, none for the group itself
+        let csv = trace.to_csv();
+        let paths: Vec<&str> = csv
+            .split_terminator("\r\n")
+            .skip(1)
+            .map(|row| row.split(',').nth(3).unwrap())
+            .collect();
+        assert_eq!(paths, ["$.user.age", "$.user.role", "$.user.vip"]);
+        assert_eq!(
+            trace.explanation().unwrap(),
+            "access: $.user.role is equal to admin, was guest; access: $.user.vip is equal to true, was false"
+        );
+
+        let json = serde_json::to_string(&trace).unwrap();
+        let read: RuleSetTrace = serde_json::from_str(&json).unwrap();
+        let ConditionTrace::Group(group) = &read.execution[0].conditions[1] else {
+            panic!("Expected a group");
+        };
+        assert!(!group.result);
+        assert!(group.branch.is_none());
+        assert_eq!(group.conditions.len(), 2);
+        assert_eq!(serde_json::to_string(&read).unwrap(), json);
+    }
+
+    fn missed_key(rule: &str, data: serde_json::Value) -> Option<MissedKey> {
+        let rule_set = parse_rules(rule).unwrap();
+        let options = EvaluationOptions::new().with_trace_verbosity(TraceVerbosity::Verbose);
+        let trace = evaluate(&rule_set, &data, &options).trace.unwrap();
+        match &trace.execution[0].conditions[0] {
+            ConditionTrace::Comparison(comparison) => comparison.property.missed_key.clone(),
+            _ => panic!("Expected a comparison"),
+        }
+    }
+
+    #[test]
+    fn test_verbose_trace_explains_missed_keys() {
+        let rule = "A **user** is adult if the __date of birth__ of the **user** is earlier than 2008-01-01.";
+
+        let missed = missed_key(
+            rule,
+            serde_json::json!({"user": {"dob": "1990-01-01", "name": "Ana"}}),
+        );
+        assert_eq!(
+            missed,
+            Some(MissedKey {
+                key: "date of birth".to_string(),
+                attempted_keys: vec![
+                    "date of birth".to_string(),
+                    "dateOfBirth".to_string(),
+                    "date_of_birth".to_string(),
+                ],
+                available_keys: vec!["dob".to_string(), "name".to_string()],
+            })
+        );
+
+        // The selector is missing, so the lookup stops at the top level
+        let missed = missed_key(rule, serde_json::json!({"customer": {}})).unwrap();
+        assert_eq!(missed.key, "user");
+        assert_eq!(missed.available_keys, vec!["customer".to_string()]);
+
+        // Found under another spelling, or present but null, isn't a miss
+        assert!(missed_key(
+            rule,
+            serde_json::json!({"User": {"DateOfBirth": "1990-01-01"}})
+        )
+        .is_none());
+        assert!(missed_key(rule, serde_json::json!({"user": {"date_of_birth": null}})).is_none());
+    }
+
+    #[test]
+    fn test_missed_key_lists_at_most_twenty_keys() {
+        let user: serde_json::Map<_, _> = (0..30)
+            .map(|i| (format!("key{:02}", i), serde_json::json!(i)))
+            .collect();
+        let missed = missed_key(
+            "A **user** is adult if the __age__ of the **user** is greater than 17.",
+            serde_json::json!({ "user": user }),
+        )
+        .unwrap();
+        assert_eq!(missed.available_keys.len(), 20);
+        assert_eq!(missed.available_keys[0], "key00");
+    }
+
+    #[test]
+    fn test_normal_trace_leaves_missed_keys_out() {
+        let rule_set =
+            parse_rules("A **user** is adult if the __age__ of the **user** is greater than 17.")
+                .unwrap();
+        let data = serde_json::json!({"user": {}});
+        let trace = evaluate(&rule_set, &data, &EvaluationOptions::new())
+            .trace
+            .unwrap();
+        let json = serde_json::to_value(&trace).unwrap();
+        let property = &json["execution"][0]["conditions"][0]["property"];
+        assert_eq!(property["path"], "$.user.age");
+        assert!(property.get("missed_key").is_none());
+    }
+
+    #[test]
+    fn test_within_trace_snapshot() {
+        let rule_set = parse_rules(
+            "A **user** is recent if the __joined__ of the **user** is within 2 years.",
+        )
+        .unwrap();
+        let today = chrono::Utc::now().date_naive();
+        let joined = today - chrono::Days::new(10);
+        let data = serde_json::json!({"user": {"joined": joined.to_string()}});
+        let trace = evaluate(&rule_set, &data, &EvaluationOptions::new())
+            .trace
+            .unwrap();
+
+        let ConditionTrace::Comparison(comparison) = &trace.execution[0].conditions[0] else {
+            panic!("expected a comparison");
+        };
+        let json = serde_json::to_value(comparison).unwrap();
+        assert_eq!(
+            json["value"],
+            serde_json::json!({
+                "value": "2 years",
+                "type": "duration",
+                "seconds": 63113904.0,
+                "pos": {"line": 1, "start": 66, "end": 73},
+                "source": {
+                    "kind": "literal",
+                    "position": {"line": 1, "start": 66, "end": 73},
+                },
+            })
+        );
+        assert_eq!(
+            json["evaluation_details"],
+            serde_json::json!({
+                "left_value": {
+                    "value": joined.to_string(),
+                    "type": "date",
+                    "source": {
+                        "kind": "coerced",
+                        "from": "string",
+                        "source": {"kind": "data", "path": "$.user.joined"},
+                    },
+                },
+                "right_value": {
+                    "value": "2 years",
+                    "type": "duration",
+                    "source": {
+                        "kind": "literal",
+                        "position": {"line": 1, "start": 66, "end": 73},
+                    },
+                },
+                "comparison_result": true,
+                "duration_seconds": 63113904.0,
+                "window": {
+                    "start": (today - chrono::Days::new(730)).to_string(),
+                    "end": (today + chrono::Days::new(730)).to_string(),
+                },
+                "reference_date": today.to_string(),
+            })
+        );
+    }
+
+    fn traced_sources(
+        condition: &str,
+        data: serde_json::Value,
+        options: &EvaluationOptions,
+    ) -> Vec<ValueSource> {
+        let (_, trace) = evaluate_condition(condition, &data, options).unwrap();
+        let ConditionTrace::Comparison(comparison) = trace else {
+            panic!("expected a comparison");
+        };
+        let details = comparison.evaluation_details.unwrap();
+        [
+            comparison.value.source,
+            details.left_value.source,
+            details.right_value.source,
+        ]
+        .into_iter()
+        .map(Option::unwrap)
+        .collect()
+    }
+
+    #[test]
+    fn test_trace_records_literal_and_data_sources() {
+        let sources = traced_sources(
+            "the __age__ of the **user** is at least 18",
+            serde_json::json!({"user": {"age": 20}}),
+            &EvaluationOptions::new(),
+        );
+        let literal = ValueSource::Literal {
+            position: Some(SourcePosition {
+                line: 1,
+                start: 41,
+                end: 43,
+            }),
+        };
+        assert_eq!(
+            sources,
+            vec![
+                literal.clone(),
+                ValueSource::Data {
+                    path: "$.user.age".to_string()
+                },
+                literal.clone(),
+            ]
+        );
+        assert_eq!(literal.to_string(), "literal at line 1");
+    }
+
+    #[test]
+    fn test_trace_records_property_and_context_sources() {
+        let condition =
+            "the __age__ of the **user** is greater than the __allowed age__ of the **config**";
+        let data = serde_json::json!({"user": {"age": 20}, "config": {"allowed age": 18}});
+        let config = ValueSource::Data {
+            path: "$.config['allowed age']".to_string(),
+        };
+        let sources = traced_sources(condition, data, &EvaluationOptions::new());
+        assert_eq!(sources[0], config);
+        assert_eq!(sources[2], config);
+
+        let options = EvaluationOptions::new().with_context(serde_json::json!({"region": "eu"}));
+        let sources = traced_sources(
+            r#"the __region__ of the **context** is equal to "eu""#,
+            serde_json::json!({}),
+            &options,
+        );
+        assert_eq!(
+            sources[1],
+            ValueSource::Context {
+                path: "$context.region".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_trace_marks_coerced_and_rebased_sources() {
+        let options =
+            EvaluationOptions::new().with_data_root(Some("$.envelope.payload".to_string()));
+        let sources = traced_sources(
+            "the __birth date__ of the **user** is earlier than 2008-01-01",
+            serde_json::json!({"envelope": {"payload": {"user": {"birth date": "1990-01-01"}}}}),
+            &options,
+        );
+        let coerced = ValueSource::Coerced {
+            from: "string".to_string(),
+            source: Box::new(ValueSource::Data {
+                path: "$.envelope.payload.user['birth date']".to_string(),
+            }),
+        };
+        assert_eq!(sources[1], coerced);
+        assert_eq!(
+            coerced.to_string(),
+            "data at $.envelope.payload.user['birth date'], coerced from string"
+        );
+    }
 }
@@ -1,14 +1,492 @@
+//! The trace of an evaluation and the formats it is written in.
+//!
+//! Each comparison's traced values carry a `source` (`ValueSource`, tagged by `kind`): `data` or
+//! `context` with the `path` they were read at, `literal` with the `position` in the rule, or
+//! `coerced` with the JSON type the value was read `from` (a date the data wrote as a string) and
+//! its original `source`. `ComparisonTrace::record_sources` fills them once the comparison is
+//! traced and again after `rebase_paths`, so they follow the data root. The CSV trace writes the
+//! expected value's source in `expected_source` ("literal at line 6", "data at $.config.limit").
+//! The tree has no constants, named sets, resolvers or defaults yet; each gets its variant when it
+//! lands.
+//!
+//! `"trace_format": "csv"` (on `POST /`, `/policies/{id}/evaluate` and `/evaluate-change`) answers
+//! with the trace alone as `text/csv`: `RuleSetTrace::to_csv` writes one RFC 4180 row per condition
+//! with the rule, label, condition id, path, operator, expected value and its source, actual value,
+//! result and `failing_path`, which marks the failed conditions the failed decision traces back to.
+//! Lists and objects are JSON in their cell. A policy that doesn't parse has no trace and still
+//! gets the JSON error. `policy trace <response.json> --csv` does the same for a saved trace.
+//!
+//! `"trace_format": "ndjson"` (same endpoints) streams the response as `application/x-ndjson`
+//! instead, so a large trace is never held as one string: first a `"line": "result"` object with
+//! everything but the trace, then `RuleSetTrace::write_ndjson`'s lines — a `"line": "rule"` object
+//! per rule in execution order with the `RuleTrace` flattened into it, its `id` (index in the
+//! execution) and `parents` (ids of the rules that referenced it, omitted when none did) — and a
+//! closing `"line": "summary"` with the rule, condition and failed rule counts. Dropping `line`,
+//! `id` and `parents` from the rule lines gives back the JSON trace's `execution`. The lines are
+//! written on a blocking thread feeding the body through a channel.
+//!
+//! `"trace_verbosity": "verbose"` (same endpoints) explains properties the data doesn't have:
+//! `RuleSetTrace::explain_missed_keys` adds `missed_key` to the comparison's `property` with the
+//! key no object key matched, the forms of it the lookup tried in order (as written, camelCase,
+//! snake_case, spaced, each ignoring case) and up to 20 keys the object has instead. Normal traces
+//! leave it out.
+
 mod lib;
 
-use crate::runner::model::{ComparisonOperator, RuleValue, SourcePosition};
-use serde::Serialize;
+use crate::runner::model::{
+    constants, ArithmeticOperator, ComparisonOperator, ConditionId, Quantifier, RuleValue,
+    SourcePosition,
+};
+use crate::runner::options::ListScalarMode;
+use crate::runner::utils::{
+    find_missing_key, json_path_from_keys, normalize_name, normalize_outcome, push_json_path_key,
+    rebase_json_path, resolve_json_path,
+};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{self, Write};
+
+/// How a response carries its trace
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceFormat {
+    /// The whole response as JSON, trace included
+    #[default]
+    Json,
+    /// Only the trace, as `RuleSetTrace::to_csv` rows
+    Csv,
+    /// The response streamed a line at a time, the trace as `RuleSetTrace::write_ndjson`
+    /// lines after a line with the rest
+    Ndjson,
+}
+
+/// Header row of `RuleSetTrace::to_csv`
+pub const CSV_COLUMNS: [&str; 10] = [
+    "rule",
+    "label",
+    "condition",
+    "path",
+    "operator",
+    "expected",
+    "expected_source",
+    "actual",
+    "result",
+    "failing_path",
+];
 
-#[derive(Debug, Serialize, Clone)]
+/// Appended to a property's path in the trace of `length of`, `number of`, `hash bucket of`
+/// and the aggregates, which trace the number they compute rather than the property
+pub const COMPUTED_SUFFIXES: [&str; 7] = [
+    ".length",
+    ".number",
+    ".hash_bucket",
+    ".sum",
+    ".minimum",
+    ".maximum",
+    ".average",
+];
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct RuleSetTrace {
     pub(crate) execution: Vec<RuleTrace>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl RuleSetTrace {
+    /// JSONPaths of the data the evaluation read, in the order first read.
+    ///
+    /// Counted, bucketed and aggregated properties are traced as `$.user.items.length`; those
+    /// report the property itself unless `data` really has a key named like the suffix there.
+    pub fn accessed_paths(&self, data: &serde_json::Value) -> Vec<String> {
+        let mut paths: Vec<String> = Vec::new();
+        let comparisons = self
+            .execution
+            .iter()
+            .flat_map(RuleTrace::leaves)
+            .filter_map(|condition| match condition {
+                ConditionTrace::Comparison(comparison) => Some(comparison),
+                ConditionTrace::RuleReference(_)
+                | ConditionTrace::Flag(_)
+                | ConditionTrace::Group(_) => None,
+            });
+
+        for comparison in comparisons {
+            for path in comparison.read_paths() {
+                let path = match COMPUTED_SUFFIXES
+                    .iter()
+                    .find_map(|suffix| path.strip_suffix(suffix))
+                {
+                    Some(counted) if resolve_json_path(data, path).is_none() => counted,
+                    _ => path.as_str(),
+                };
+                if !paths.iter().any(|seen| seen == path) {
+                    paths.push(path.to_string());
+                }
+            }
+        }
+        paths
+    }
+
+    /// Marks each property and property value read from under one of the top-level
+    /// `documents` with that document's name, so a trace of merged payloads shows which one a
+    /// value came from
+    pub fn tag_documents(&mut self, documents: &[&str]) {
+        let comparisons = self
+            .execution
+            .iter_mut()
+            .flat_map(RuleTrace::leaves_mut)
+            .filter_map(|condition| match condition {
+                ConditionTrace::Comparison(comparison) => Some(comparison),
+                ConditionTrace::RuleReference(_)
+                | ConditionTrace::Flag(_)
+                | ConditionTrace::Group(_) => None,
+            });
+
+        for comparison in comparisons {
+            comparison.property.document = path_document(&comparison.property.path, documents);
+            if let Some(path) = &comparison.value.path {
+                comparison.value.document = path_document(path, documents);
+            }
+        }
+    }
+
+    /// Rewrites the paths read from under a data root as paths from the document the root was
+    /// resolved in, see `ComparisonTrace::rebase_paths`
+    pub fn rebase_paths(&mut self, root: &str) {
+        for condition in self.execution.iter_mut().flat_map(RuleTrace::leaves_mut) {
+            if let ConditionTrace::Comparison(comparison) = condition {
+                comparison.rebase_paths(root);
+            }
+        }
+    }
+
+    /// Records on each property that `data` doesn't have which key the lookup missed, what
+    /// it tried for it and which keys were there instead
+    pub fn explain_missed_keys(&mut self, data: &Value) {
+        let comparisons = self
+            .execution
+            .iter_mut()
+            .flat_map(RuleTrace::leaves_mut)
+            .filter_map(|condition| match condition {
+                ConditionTrace::Comparison(comparison) => Some(comparison),
+                ConditionTrace::RuleReference(_)
+                | ConditionTrace::Flag(_)
+                | ConditionTrace::Group(_) => None,
+            });
+
+        for comparison in comparisons {
+            let property = &mut comparison.property;
+            property.missed_key =
+                find_missing_key(data, &property.path).map(|(object, key)| MissedKey {
+                    attempted_keys: normalize_name(&key),
+                    available_keys: object
+                        .as_object()
+                        .map(|object| object.keys().take(MAX_AVAILABLE_KEYS).cloned().collect())
+                        .unwrap_or_default(),
+                    key,
+                });
+        }
+    }
+
+    /// One row per condition as RFC 4180 CSV, header first, for reading in a spreadsheet. A
+    /// group has no row of its own, the conditions in it have theirs.
+    ///
+    /// `expected` is the literal or the other property's value (see
+    /// `ComparisonTrace::expected_value`), `expected_source` where it came from (see
+    /// `ValueSource`), `actual` the property's value; lists and objects
+    /// are written as JSON. A rule reference has no path, names the rule as `expected` and
+    /// the inferred property's value, if any, as `actual`.
+    /// `failing_path` marks the failed conditions that made the decision fail, following
+    /// failed references from the rules nothing refers to.
+    pub fn to_csv(&self) -> String {
+        let failing = self.failing_rules();
+        let mut csv = String::new();
+        write_csv_row(&mut csv, CSV_COLUMNS.map(String::from));
+
+        for (rule, rule_failing) in self.execution.iter().zip(failing) {
+            for condition in rule.leaves() {
+                let (path, operator, expected, expected_source, actual, result) = match condition {
+                    ConditionTrace::Comparison(comparison) => (
+                        comparison.property.path.clone(),
+                        comparison.operator.to_string(),
+                        csv_value(comparison.expected_value()),
+                        comparison
+                            .expected_source()
+                            .map_or_else(String::new, ToString::to_string),
+                        csv_value(&comparison.property.value),
+                        comparison.result,
+                    ),
+                    ConditionTrace::RuleReference(reference) => (
+                        String::new(),
+                        "references".to_string(),
+                        reference.rule_name.clone(),
+                        String::new(),
+                        reference
+                            .property_check
+                            .as_ref()
+                            .map_or_else(String::new, |check| csv_value(&check.property_value)),
+                        reference.result,
+                    ),
+                    ConditionTrace::Flag(flag) => (
+                        String::new(),
+                        "flag".to_string(),
+                        format!("{} {}", flag.flag, flag_state(flag.expected)),
+                        String::new(),
+                        flag_state(flag.enabled).to_string(),
+                        flag.result,
+                    ),
+                    ConditionTrace::Group(_) => unreachable!("leaves aren't groups"),
+                };
+                let operator = if condition.is_negated() {
+                    format!("not {}", operator)
+                } else {
+                    operator
+                };
+                write_csv_row(
+                    &mut csv,
+                    [
+                        rule.outcome.value.clone(),
+                        rule.label.clone().unwrap_or_default(),
+                        condition.id().map_or_else(String::new, ToString::to_string),
+                        path,
+                        operator,
+                        expected,
+                        expected_source,
+                        actual,
+                        result.to_string(),
+                        (rule_failing && !result).to_string(),
+                    ],
+                );
+            }
+        }
+        csv
+    }
+
+    /// The trace as newline-delimited JSON, written a rule at a time so the whole of it is
+    /// never held as one string: a `{"line": "rule"}` object per rule in execution order,
+    /// the `RuleTrace` flattened into it, then a `{"line": "summary"}` with the counts.
+    ///
+    /// Each rule line has `id`, its index in the execution, and `parents`, the ids of the
+    /// rules that referenced it, left out for the rules nothing refers to. Read the rule
+    /// lines back in order, without `line`, `id` and `parents`, and they are the trace
+    pub fn write_ndjson(&self, mut out: impl Write) -> io::Result<()> {
+        let outcomes: Vec<String> = self
+            .execution
+            .iter()
+            .map(|rule| normalize_outcome(&rule.outcome.value))
+            .collect();
+        let mut parents: Vec<Vec<usize>> = vec![Vec::new(); self.execution.len()];
+        for (index, rule) in self.execution.iter().enumerate() {
+            for condition in rule.leaves() {
+                let ConditionTrace::RuleReference(reference) = condition else {
+                    continue;
+                };
+                let Some(target) = reference
+                    .referenced_rule_outcome
+                    .as_ref()
+                    .and_then(|outcome| outcomes.iter().position(|o| o == outcome))
+                else {
+                    continue;
+                };
+                if !parents[target].contains(&index) {
+                    parents[target].push(index);
+                }
+            }
+        }
+
+        for (id, (trace, parents)) in self.execution.iter().zip(parents).enumerate() {
+            let line = NdjsonLine::Rule { id, parents, trace };
+            serde_json::to_writer(&mut out, &line)?;
+            out.write_all(b"\n")?;
+        }
+        let summary = NdjsonLine::Summary {
+            rules: self.execution.len(),
+            conditions: self.execution.iter().flat_map(RuleTrace::leaves).count(),
+            failed: self.execution.iter().filter(|rule| !rule.result).count(),
+        };
+        serde_json::to_writer(&mut out, &summary)?;
+        out.write_all(b"\n")?;
+        out.flush()
+    }
+
+    /// A sentence per failed comparison or flag on the failing path (see `to_csv`), naming
+    /// the rule, the check and the value it read, e.g. `adult: $.user.age is greater than or
+    /// equal to 18.0, was 16`, with `not` in front of a negated check. `None` when nothing on
+    /// the path failed, as for a passed decision
+    pub fn explanation(&self) -> Option<String> {
+        let failing = self.failing_rules();
+        let reasons: Vec<String> = self
+            .execution
+            .iter()
+            .zip(failing)
+            .filter(|(_, failing)| *failing)
+            .flat_map(|(rule, _)| {
+                rule.conditions
+                    .iter()
+                    .flat_map(failed_checks)
+                    .map(move |check| format!("{}: {}", rule.outcome.value, check))
+            })
+            .collect();
+        (!reasons.is_empty()).then(|| reasons.join("; "))
+    }
+
+    /// For each traced rule, whether it failed on the way to a failed decision: a failed rule
+    /// nothing refers to, or one a failed reference on that path resolved to
+    fn failing_rules(&self) -> Vec<bool> {
+        let outcomes: Vec<String> = self
+            .execution
+            .iter()
+            .map(|rule| normalize_outcome(&rule.outcome.value))
+            .collect();
+        let references = |rule: &RuleTrace| -> Vec<(String, bool)> {
+            rule.leaves()
+                .filter_map(|condition| match condition {
+                    ConditionTrace::RuleReference(reference) => {
+                        Some((reference.referenced_rule_outcome.clone()?, reference.result))
+                    }
+                    ConditionTrace::Comparison(_)
+                    | ConditionTrace::Flag(_)
+                    | ConditionTrace::Group(_) => None,
+                })
+                .collect()
+        };
+        let referenced: Vec<String> = self
+            .execution
+            .iter()
+            .flat_map(references)
+            .map(|(outcome, _)| outcome)
+            .collect();
+
+        let mut failing = vec![false; self.execution.len()];
+        let mut pending: Vec<usize> = (0..self.execution.len())
+            .filter(|&index| {
+                !self.execution[index].result && !referenced.contains(&outcomes[index])
+            })
+            .collect();
+        while let Some(index) = pending.pop() {
+            if failing[index] {
+                continue;
+            }
+            failing[index] = true;
+            for (outcome, result) in references(&self.execution[index]) {
+                if result {
+                    continue;
+                }
+                if let Some(target) = outcomes.iter().position(|o| *o == outcome) {
+                    if !self.execution[target].result {
+                        pending.push(target);
+                    }
+                }
+            }
+        }
+        failing
+    }
+}
+
+/// Why a failed condition failed, see `RuleSetTrace::explanation`
+fn failed_checks(condition: &ConditionTrace) -> Vec<String> {
+    let check = match condition {
+        ConditionTrace::Comparison(comparison) if !comparison.result => {
+            let mut expected = csv_value(comparison.expected_value());
+            if let Some(suffix) = comparison.operator.value_suffix() {
+                expected = format!("{} {}", expected, suffix);
+            }
+            format!(
+                "{}{} {}{}{}, was {}",
+                if condition.is_negated() { "not " } else { "" },
+                comparison.property.path,
+                comparison.operator,
+                if expected.is_empty() { "" } else { " " },
+                expected,
+                match &comparison.property.value {
+                    serde_json::Value::Null => "missing".to_string(),
+                    value => csv_value(value),
+                }
+            )
+        }
+        ConditionTrace::Flag(flag) if !flag.result => format!(
+            "flag {} is {}, was {}",
+            flag.flag,
+            flag_state(flag.expected != condition.is_negated()),
+            flag_state(flag.enabled)
+        ),
+        // A failed reference that isn't negated is explained by its rule
+        ConditionTrace::RuleReference(reference) if !reference.result && condition.is_negated() => {
+            format!("not {}, which holds", reference.rule_name)
+        }
+        // A failed group is explained by the conditions in it
+        ConditionTrace::Group(group) if !group.result && !condition.is_negated() => {
+            return group.conditions.iter().flat_map(failed_checks).collect()
+        }
+        _ => return Vec::new(),
+    };
+    vec![check]
+}
+
+fn flag_state(enabled: bool) -> &'static str {
+    if enabled {
+        "enabled"
+    } else {
+        "disabled"
+    }
+}
+
+/// A CSV cell for a traced value: text as it is, null as an empty cell, anything else as
+/// JSON
+fn csv_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Appends one CRLF-terminated row, quoting cells that hold a comma, quote or line break
+fn write_csv_row<const N: usize>(csv: &mut String, cells: [String; N]) {
+    for (index, cell) in cells.iter().enumerate() {
+        if index > 0 {
+            csv.push(',');
+        }
+        if cell.contains([',', '"', '\r', '\n']) {
+            csv.push('"');
+            csv.push_str(&cell.replace('"', "\"\""));
+            csv.push('"');
+        } else {
+            csv.push_str(cell);
+        }
+    }
+    csv.push_str("\r\n");
+}
+
+/// The document among `documents` whose top-level key `path` starts at
+fn path_document(path: &str, documents: &[&str]) -> Option<String> {
+    documents.iter().find_map(|document| {
+        let root = json_path_from_keys(&[document]);
+        let rest = path.strip_prefix(&root)?;
+        (rest.is_empty() || rest.starts_with(['.', '['])).then(|| document.to_string())
+    })
+}
+
+/// A line of `RuleSetTrace::write_ndjson`
+#[derive(Serialize)]
+#[serde(tag = "line", rename_all = "lowercase")]
+enum NdjsonLine<'a> {
+    Rule {
+        id: usize,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        parents: Vec<usize>,
+        #[serde(flatten)]
+        trace: &'a RuleTrace,
+    },
+    Summary {
+        rules: usize,
+        conditions: usize,
+        /// Rules whose result is false, the skipped ones included
+        failed: usize,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RuleTrace {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub label: Option<String>,
@@ -16,126 +494,751 @@ pub struct RuleTrace {
     pub outcome: OutcomeTrace,
     pub conditions: Vec<ConditionTrace>,
     pub result: bool,
+    /// Not evaluated, because the tag filters exclude the rule, every condition it has
+    /// references a skipped rule or the debug hook skipped it, see
+    /// `EvaluationOptions::excludes` and `DebugAction::SkipRule`. Its `result` is false
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skipped: bool,
+    /// Why evaluation stopped in this rule, when it failed there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl RuleTrace {
+    /// The conditions traced, with those in groups in place of their group, see
+    /// `ConditionTrace::leaves`
+    pub fn leaves(&self) -> impl Iterator<Item = &ConditionTrace> {
+        self.conditions.iter().flat_map(ConditionTrace::leaves)
+    }
+
+    /// `leaves`, to change
+    pub fn leaves_mut(&mut self) -> impl Iterator<Item = &mut ConditionTrace> {
+        self.conditions
+            .iter_mut()
+            .flat_map(ConditionTrace::leaves_mut)
+    }
+}
+
+// Most conditions are comparisons, so boxing them would only add an allocation each
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
 pub enum ConditionTrace {
-    Comparison(ComparisonTrace),
+    Comparison(Box<ComparisonTrace>),
     RuleReference(RuleReferenceTrace),
+    Flag(FlagTrace),
+    Group(GroupTrace),
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl ConditionTrace {
+    #[allow(dead_code)]
+    pub fn id(&self) -> Option<&ConditionId> {
+        match self {
+            ConditionTrace::Comparison(trace) => trace.id.as_ref(),
+            ConditionTrace::RuleReference(trace) => trace.id.as_ref(),
+            ConditionTrace::Flag(trace) => trace.id.as_ref(),
+            ConditionTrace::Group(trace) => trace.id.as_ref(),
+        }
+    }
+
+    pub fn set_id(&mut self, id: ConditionId) {
+        match self {
+            ConditionTrace::Comparison(trace) => trace.id = Some(id),
+            ConditionTrace::RuleReference(trace) => trace.id = Some(id),
+            ConditionTrace::Flag(trace) => trace.id = Some(id),
+            ConditionTrace::Group(trace) => trace.id = Some(id),
+        }
+    }
+
+    /// Whether the condition references a rule the tag filters skipped, or is a group whose
+    /// every condition does
+    pub fn is_skipped(&self) -> bool {
+        match self {
+            ConditionTrace::RuleReference(trace) => trace.skipped,
+            ConditionTrace::Group(trace) => trace.skipped,
+            ConditionTrace::Comparison(_) | ConditionTrace::Flag(_) => false,
+        }
+    }
+
+    /// Whether the condition was negated, see `negate`
+    pub fn is_negated(&self) -> bool {
+        match self {
+            ConditionTrace::Comparison(trace) => trace.inner_result.is_some(),
+            ConditionTrace::RuleReference(trace) => trace.inner_result.is_some(),
+            ConditionTrace::Flag(trace) => trace.inner_result.is_some(),
+            ConditionTrace::Group(trace) => trace.inner_result.is_some(),
+        }
+    }
+
+    /// What the condition came to, negation included
+    pub fn result(&self) -> bool {
+        match self {
+            ConditionTrace::Comparison(trace) => trace.result,
+            ConditionTrace::RuleReference(trace) => trace.result,
+            ConditionTrace::Flag(trace) => trace.result,
+            ConditionTrace::Group(trace) => trace.result,
+        }
+    }
+
+    /// Turns the trace of a condition into that of its negation, keeping what it came to in
+    /// `inner_result`
+    pub fn negate(&mut self) {
+        let (result, inner_result) = match self {
+            ConditionTrace::Comparison(trace) => (&mut trace.result, &mut trace.inner_result),
+            ConditionTrace::RuleReference(trace) => (&mut trace.result, &mut trace.inner_result),
+            ConditionTrace::Flag(trace) => (&mut trace.result, &mut trace.inner_result),
+            ConditionTrace::Group(trace) => (&mut trace.result, &mut trace.inner_result),
+        };
+        *inner_result = Some(*result);
+        *result = !*result;
+    }
+
+    /// The condition itself or, for a group, the conditions in it that aren't groups, in order
+    pub fn leaves(&self) -> Vec<&ConditionTrace> {
+        match self {
+            ConditionTrace::Group(group) => group
+                .conditions
+                .iter()
+                .flat_map(ConditionTrace::leaves)
+                .collect(),
+            leaf => vec![leaf],
+        }
+    }
+
+    /// `leaves`, to change
+    pub fn leaves_mut(&mut self) -> Vec<&mut ConditionTrace> {
+        match self {
+            ConditionTrace::Group(group) => group
+                .conditions
+                .iter_mut()
+                .flat_map(ConditionTrace::leaves_mut)
+                .collect(),
+            leaf => vec![leaf],
+        }
+    }
+}
+
+/// What a parenthesised group of conditions came to, each condition in it traced in order
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GroupTrace {
+    /// Id of the rule condition this traces, see `ConditionId`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<ConditionId>,
+    pub conditions: Vec<ConditionTrace>,
+    /// Ids of the conditions of the first branch between `or`s that held, when one did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<Vec<ConditionId>>,
+    pub result: bool,
+    /// Every condition in the group was skipped, so it is left out of its rule's result
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skipped: bool,
+    /// Set on a negated group to what the group itself came to, `result` being its
+    /// opposite, see `ConditionGroup::negated`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inner_result: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ComparisonTrace {
+    /// Id of the rule condition this traces, see `ConditionId`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<ConditionId>,
     pub selector: SelectorTrace,
     pub property: PropertyTrace,
     pub operator: ComparisonOperator,
     pub value: ValueTrace,
     pub evaluation_details: Option<ComparisonEvaluationTrace>,
     pub result: bool,
+    /// Set on a negated condition to what the condition itself came to, `result` being its
+    /// opposite, see `ConditionGroup::negated`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inner_result: Option<bool>,
+    /// How the left-hand side was worked out when it does arithmetic, `property` holding
+    /// what it came to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left_expression: Option<ExpressionTrace>,
+    /// How the right-hand side was worked out when it does arithmetic, `value` holding what
+    /// it came to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right_expression: Option<ExpressionTrace>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+/// One side of a comparison worked out step by step, see `RuleExpression`: its first
+/// operand, then each step with the running result after it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExpressionTrace {
+    pub first: OperandTrace,
+    pub steps: Vec<ArithmeticStepTrace>,
+    /// The percentage taken of what the steps came to, for `80% of ...`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percentage: Option<PercentageTrace>,
+    pub result: f64,
+}
+
+/// `80% of` a value: `of` is the value as read, the expression's `result` the threshold
+/// worked out from it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PercentageTrace {
+    pub percent: f64,
+    pub of: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ArithmeticStepTrace {
+    pub operator: ArithmeticOperator,
+    pub operand: OperandTrace,
+    /// What the side came to after this step
+    pub result: f64,
+}
+
+impl ExpressionTrace {
+    /// The operands in the order written
+    pub fn operands(&self) -> impl Iterator<Item = &OperandTrace> {
+        std::iter::once(&self.first).chain(self.steps.iter().map(|step| &step.operand))
+    }
+
+    /// The paths of the properties it read, in the order written
+    pub fn paths(&self) -> impl Iterator<Item = &String> {
+        self.operands().filter_map(|operand| operand.path.as_ref())
+    }
+
+    fn operands_mut(&mut self) -> impl Iterator<Item = &mut OperandTrace> {
+        std::iter::once(&mut self.first).chain(self.steps.iter_mut().map(|step| &mut step.operand))
+    }
+}
+
+/// The expression as written, each property as its JSONPath, e.g. `$.exam.theory plus 10`
+impl std::fmt::Display for ExpressionTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(percentage) = &self.percentage {
+            write!(f, "{}% of ", percentage.percent)?;
+        }
+        write!(f, "{}", self.first)?;
+        for step in &self.steps {
+            write!(f, " {} {}", step.operator, step.operand)?;
+        }
+        Ok(())
+    }
+}
+
+/// A number an expression read: a literal, or a property at `path`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OperandTrace {
+    pub value: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+impl std::fmt::Display for OperandTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.path, self.value.as_f64()) {
+            (Some(path), _) => write!(f, "{}", path),
+            // As the rule wrote it, `2` rather than `2.0`
+            (None, Some(number)) => write!(f, "{}", number),
+            (None, None) => write!(f, "{}", self.value),
+        }
+    }
+}
+
+impl ComparisonTrace {
+    /// Puts `root` in front of the paths of the property and property value, except those
+    /// read from the request context, which isn't under the root
+    pub fn rebase_paths(&mut self, root: &str) {
+        let from_context =
+            |path: &str| path_document(path, &[constants::CONTEXT_SELECTOR]).is_some();
+        if self.left_expression.is_none() && !from_context(&self.property.path) {
+            self.property.path = rebase_json_path(root, &self.property.path);
+        }
+        if let Some(path) = self.value.path.as_mut().filter(|path| !from_context(path)) {
+            *path = rebase_json_path(root, path);
+        }
+        let operands = self
+            .left_expression
+            .iter_mut()
+            .chain(self.right_expression.as_mut())
+            .flat_map(ExpressionTrace::operands_mut);
+        for operand in operands {
+            if let Some(path) = operand.path.as_mut().filter(|path| !from_context(path)) {
+                *path = rebase_json_path(root, path);
+            }
+        }
+        if let Some(expression) = &self.left_expression {
+            self.property.path = expression.to_string();
+        }
+        self.record_sources();
+    }
+
+    /// JSONPaths the comparison read: its property and the property it compared with, or
+    /// the properties each side's arithmetic read in their place
+    pub fn read_paths(&self) -> Vec<&String> {
+        let mut paths: Vec<&String> = match &self.left_expression {
+            Some(expression) => expression.paths().collect(),
+            None => vec![&self.property.path],
+        };
+        match &self.right_expression {
+            Some(expression) => paths.extend(expression.paths()),
+            None => paths.extend(&self.value.path),
+        }
+        paths
+    }
+
+    /// Properties the comparison found in the data: its own, and the one it compared with
+    /// when the value is another property, or those each side's arithmetic read
+    pub fn properties_found(&self) -> usize {
+        let found = |expression: &ExpressionTrace| {
+            expression
+                .operands()
+                .filter(|operand| operand.path.is_some() && !operand.value.is_null())
+                .count()
+        };
+        let left = match &self.left_expression {
+            Some(expression) => found(expression),
+            None => usize::from(!self.property.value.is_null()),
+        };
+        let right = match &self.right_expression {
+            Some(expression) => found(expression),
+            None => usize::from(self.value.path.is_some() && !self.value.value.is_null()),
+        };
+        left + right
+    }
+
+    /// Marks where the property value and both compared values came from: the data or the
+    /// request context at their path, or the literal the rule wrote. A value read from the
+    /// data that the comparison took as another type, such as a date written as a string, is
+    /// marked coerced from the type it was read as
+    pub fn record_sources(&mut self) {
+        let literal = ValueSource::Literal {
+            position: self.value.pos.clone(),
+        };
+        let read = |path: &str, value: &TypedValue| {
+            let source = ValueSource::read_from(path);
+            match json_kind(&value.value) {
+                kind if kind != value.value_type => ValueSource::Coerced {
+                    from: kind.to_string(),
+                    source: Box::new(source),
+                },
+                _ => source,
+            }
+        };
+
+        let computed = |expression: &ExpressionTrace| ValueSource::Computed {
+            expression: expression.to_string(),
+        };
+
+        self.value.source = Some(match (&self.right_expression, &self.value.path) {
+            (Some(expression), _) => computed(expression),
+            (None, Some(path)) => ValueSource::read_from(path),
+            (None, None) => literal.clone(),
+        });
+        if let Some(details) = &mut self.evaluation_details {
+            details.left_value.source = Some(match &self.left_expression {
+                Some(expression) => computed(expression),
+                None => read(&self.property.path, &details.left_value),
+            });
+            details.right_value.source = Some(match (&self.right_expression, &self.value.path) {
+                (Some(expression), _) => computed(expression),
+                (None, Some(path)) => read(path, &details.right_value),
+                (None, None) => literal,
+            });
+        }
+    }
+
+    /// What the property was compared with: the literal, or the value read from the other
+    /// property when the right-hand side is one
+    pub fn expected_value(&self) -> &serde_json::Value {
+        match (&self.value.path, &self.evaluation_details) {
+            (Some(_), Some(details)) => &details.right_value.value,
+            _ => &self.value.value,
+        }
+    }
+
+    /// Where `expected_value` came from, once `record_sources` has run
+    pub fn expected_source(&self) -> Option<&ValueSource> {
+        match (&self.value.path, &self.evaluation_details) {
+            (Some(_), Some(details)) => details.right_value.source.as_ref(),
+            _ => self.value.source.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SelectorTrace {
     pub value: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pos: Option<SourcePosition>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OutcomeTrace {
     pub value: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pos: Option<SourcePosition>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PropertyTrace {
     pub value: serde_json::Value,
     pub path: String,
+    /// The change document the path reads, see `RuleSetTrace::tag_documents`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<String>,
+    /// Why the lookup found nothing, with verbose traces only. See
+    /// `RuleSetTrace::explain_missed_keys`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub missed_key: Option<MissedKey>,
+    /// Set when the lookup couldn't search an object for a differently written key, see
+    /// `EvaluationOptions::max_scanned_keys`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/// Most keys of the object `MissedKey::available_keys` lists
+pub const MAX_AVAILABLE_KEYS: usize = 20;
+
+/// A key of a property path that no key of its object matched
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MissedKey {
+    /// The key as the rule wrote it
+    pub key: String,
+    /// Keys tried in order: as written, then its camelCase, snake_case and spaced forms, each
+    /// also ignoring case
+    pub attempted_keys: Vec<String>,
+    /// Keys the object does have, at most `MAX_AVAILABLE_KEYS`. Empty when the value at the
+    /// parent path isn't an object
+    pub available_keys: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ValueTrace {
     pub value: serde_json::Value,
     #[serde(rename = "type")]
     pub value_type: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pos: Option<SourcePosition>,
+    /// Where the value was read from when it is another property
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// The change document `path` reads, see `RuleSetTrace::tag_documents`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document: Option<String>,
+    /// A duration's length in seconds, `value` being the duration as written (`2 years`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seconds: Option<f64>,
+    /// Where the value came from, see `ComparisonTrace::record_sources`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ValueSource>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ComparisonEvaluationTrace {
     pub left_value: TypedValue,
     pub right_value: TypedValue,
     pub comparison_result: bool,
+    /// Why the comparison was decided without comparing, e.g. a number that isn't finite
+    /// in lenient mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    /// How a list on the left was compared, when it was one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub list_mode: Option<ListScalarMode>,
+    /// Index of the element that decided an any or all comparison, when a single one did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deciding_element: Option<usize>,
+    /// The right-hand duration in seconds, as the comparison used it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<f64>,
+    /// The dates `is within` accepted and `is not within` rejected, `reference_date` give or
+    /// take the duration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<DateWindow>,
+    /// `reference_date` less the duration of `is more than … ago`, which accepts the dates
+    /// before it, or `is at least … ago`, which accepts it too
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cutoff: Option<NaiveDate>,
+    /// The date a duration comparison (`is within`, `is older than`, `is more than … ago`
+    /// and the like) counted from, see `EvaluationOptions::reference_date`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_date: Option<NaiveDate>,
+    /// `all`, `any` or `none` for a quantified condition, whose `deciding_element` is the
+    /// first element to fail `all` or pass `any` or `none`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantifier: Option<Quantifier>,
+    /// How many of the list's elements held the comparison, for a quantified condition
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched_elements: Option<usize>,
+    /// Whether strings were compared minding case, for an operator that can ignore it
+    /// comparing a string. See `EvaluationOptions::case_sensitive_strings`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case_sensitive: Option<bool>,
+    /// The object the left side held where the comparison needed a single value, in lenient
+    /// mode. `warning` says so
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unexpected_object: Option<UnexpectedObject>,
+}
+
+/// Most keys of an unexpected object that are listed
+pub const MAX_LISTED_KEYS: usize = 10;
+
+/// A compared property that held an object, as when a path stops one property short of the
+/// value, e.g. `degree` rather than `degree.completion_status`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UnexpectedObject {
+    /// The object's first keys, at most `MAX_LISTED_KEYS`
+    pub keys: Vec<String>,
+    /// Keys not listed
+    #[serde(default)]
+    pub more_keys: usize,
+    /// The key whose value is of the kind the condition compares with, the one holding the
+    /// comparison first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+impl UnexpectedObject {
+    /// What was found at `path`
+    pub fn describe(&self, path: &str) -> String {
+        if self.keys.is_empty() {
+            return format!("{} is an empty object, not a single value", path);
+        }
+        let mut keys = self.keys.join(", ");
+        if self.more_keys > 0 {
+            keys.push_str(&format!(" and {} more", self.more_keys));
+        }
+        format!(
+            "{} is an object with the keys {}, not a single value",
+            path, keys
+        )
+    }
+
+    /// `; did you mean <path to the suggested key>?`, empty without a suggestion
+    pub fn hint(&self, path: &str) -> String {
+        match &self.suggestion {
+            Some(key) => {
+                let mut suggested = path.to_string();
+                push_json_path_key(&mut suggested, key);
+                format!("; did you mean {}?", suggested)
+            }
+            None => String::new(),
+        }
+    }
+}
+
+/// Dates from `start` to `end`, both included
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct DateWindow {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TypedValue {
     pub value: serde_json::Value,
     #[serde(rename = "type")]
     pub value_type: String,
+    /// Where the value came from, see `ComparisonTrace::record_sources`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ValueSource>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+/// Where a traced value came from
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ValueSource {
+    /// Read from the data at `path`
+    Data { path: String },
+    /// Read from the request context at `path`
+    Context { path: String },
+    /// Written in the rule, at `position` when the parser recorded one
+    Literal { position: Option<SourcePosition> },
+    /// Read as `from`, a JSON type, and compared as the value's type
+    Coerced {
+        from: String,
+        source: Box<ValueSource>,
+    },
+    /// Worked out by arithmetic, see `ExpressionTrace`
+    Computed { expression: String },
+}
+
+impl ValueSource {
+    /// The data or the request context, whichever `path` reads
+    fn read_from(path: &str) -> Self {
+        let path = path.to_string();
+        match path_document(&path, &[constants::CONTEXT_SELECTOR]) {
+            Some(_) => ValueSource::Context { path },
+            None => ValueSource::Data { path },
+        }
+    }
+}
+
+impl std::fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueSource::Data { path } => write!(f, "data at {}", path),
+            ValueSource::Context { path } => write!(f, "context at {}", path),
+            ValueSource::Literal {
+                position: Some(position),
+            } => write!(f, "literal at line {}", position.line),
+            ValueSource::Literal { position: None } => write!(f, "literal"),
+            ValueSource::Coerced { from, source } => {
+                write!(f, "{}, coerced from {}", source, from)
+            }
+            ValueSource::Computed { expression } => write!(f, "computed as {}", expression),
+        }
+    }
+}
+
+/// A JSON value's type in the names the trace gives types
+fn json_kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "list",
+        Value::Object(_) => "object",
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RuleReferenceTrace {
+    /// Id of the rule condition this traces, see `ConditionId`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<ConditionId>,
     pub selector: SelectorTrace,
     pub rule_name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub referenced_rule_outcome: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub property_check: Option<PropertyCheckTrace>,
+    /// Which lookup the reference resolved through
+    #[serde(default)]
+    pub resolution: ReferenceResolution,
+    pub result: bool,
+    /// The referenced rule was skipped, so the condition is left out of its rule's result
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skipped: bool,
+    /// Set on a negated condition to what the condition itself came to, `result` being its
+    /// opposite, see `ConditionGroup::negated`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inner_result: Option<bool>,
+}
+
+/// What a `flag "name" is enabled` condition found
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FlagTrace {
+    /// Id of the rule condition this traces, see `ConditionId`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<ConditionId>,
+    pub flag: String,
+    /// The state the condition asks for
+    pub expected: bool,
+    /// The state the flag was taken to be in
+    pub enabled: bool,
+    /// False when the provider didn't know the flag and `unknown_flag_default` was used
+    pub known: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
     pub result: bool,
+    /// Set on a negated condition to what the condition itself came to, `result` being its
+    /// opposite, see `ConditionGroup::negated`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inner_result: Option<bool>,
+}
+
+/// How a rule reference found what it checks, tried in this order
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReferenceResolution {
+    /// A rule whose outcome is the referenced name, with or without a leading article
+    ExactOutcome,
+    /// A rule with the referenced name among its `(also known as ...)` aliases.
+    /// `referenced_rule_outcome` is the outcome it stands for
+    Alias,
+    /// A rule labelled with the referenced name
+    Label,
+    /// A rule whose outcome only partly matches the referenced name
+    FuzzyOutcome { matched: String },
+    /// No rule matched, so a property of the data named like the reference was checked
+    InferredProperty { property: String },
+    /// Nothing matched. A label reference fails, any other reference passes as free text
+    #[default]
+    Unresolved,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PropertyCheckTrace {
     pub property_name: String,
     pub property_value: serde_json::Value,
 }
 
+/// A number as JSON. NaN and the infinities, which JSON can't hold, are written as the
+/// strings "NaN", "Infinity" and "-Infinity"
+pub fn number_value(n: f64) -> serde_json::Value {
+    if n.is_nan() {
+        serde_json::json!("NaN")
+    } else if n.is_infinite() {
+        serde_json::json!(if n > 0.0 { "Infinity" } else { "-Infinity" })
+    } else {
+        serde_json::json!(n)
+    }
+}
+
 // Helper functions to convert RuleValue to TypedValue and ValueTrace
 impl From<&RuleValue> for TypedValue {
     fn from(rule_value: &RuleValue) -> Self {
         match rule_value {
             RuleValue::Number(n) => TypedValue {
-                value: serde_json::json!(n),
+                value: number_value(*n),
                 value_type: "number".to_string(),
+                source: None,
             },
             RuleValue::String(s) => TypedValue {
                 value: serde_json::json!(s),
                 value_type: "string".to_string(),
+                source: None,
             },
             RuleValue::Date(d) => TypedValue {
                 value: serde_json::json!(d.format("%Y-%m-%d").to_string()),
                 value_type: "date".to_string(),
+                source: None,
             },
             RuleValue::Boolean(b) => TypedValue {
                 value: serde_json::json!(b),
                 value_type: "boolean".to_string(),
+                source: None,
             },
             RuleValue::Duration(d) => TypedValue {
                 value: serde_json::json!(d.to_string()),
                 value_type: "duration".to_string(),
+                source: None,
             },
             RuleValue::List(items) => {
                 let json_items: Vec<serde_json::Value> = items
                     .iter()
                     .map(|item| match item {
-                        RuleValue::Number(n) => serde_json::json!(n),
+                        RuleValue::Number(n) => number_value(*n),
                         RuleValue::String(s) => serde_json::json!(s),
                         RuleValue::Date(d) => serde_json::json!(d.format("%Y-%m-%d").to_string()),
                         RuleValue::Boolean(b) => serde_json::json!(b),
                         RuleValue::Duration(d) => serde_json::json!(d.to_string()),
-                        RuleValue::List(_) => serde_json::json!(null), // nested lists not shown in example
+                        RuleValue::List(_) | RuleValue::Range(..) => serde_json::json!(null), // nested lists not shown in example
                     })
                     .collect();
                 TypedValue {
                     value: serde_json::json!(json_items),
                     value_type: "list".to_string(),
+                    source: None,
                 }
             }
+            // Shown like a range another property holds, see `NumericRange`
+            RuleValue::Range(low, high) => TypedValue {
+                value: serde_json::json!({
+                    "min": TypedValue::from(low.as_ref()).value,
+                    "max": TypedValue::from(high.as_ref()).value,
+                }),
+                value_type: "range".to_string(),
+                source: None,
+            },
         }
     }
 }
@@ -147,6 +1250,13 @@ impl RuleValue {
             value: typed_value.value,
             value_type: typed_value.value_type,
             pos,
+            path: None,
+            document: None,
+            seconds: match self {
+                RuleValue::Duration(duration) => Some(duration.to_seconds()),
+                _ => None,
+            },
+            source: None,
         }
     }
 }
@@ -0,0 +1,139 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::model::RuleSet;
+    use crate::runner::parser::parse_rule_set;
+    use crate::runner::usage::Usage;
+
+    const DRIVING_TEST: &str = r#"
+A **Person** gets a full driving licence
+  if the __age__ of the **Person** is greater than or equal to 17
+  and the **Person** passes the practical driving test
+  and §practical is valid.
+
+practical. A **Person** passes the practical driving test (also known as "has passed practical")
+  if the __driving test__ of the **scores** is greater than or equal to 60
+  and the __test date__ of the **scores** is within 2 years
+  and the __driving_test__ of the **scores** is less than the __pass mark__ of the **Person**.
+
+A **Person** gets a provisional licence
+  if the __age__ of the **Person** is at least 16
+  and the **Person** has passed practical.
+"#;
+
+    /// (rule, condition, line, start column) of each usage
+    fn references(usages: &[Usage]) -> Vec<(&str, &str, usize, usize)> {
+        usages
+            .iter()
+            .map(|usage| {
+                let position = usage.position.as_ref().unwrap();
+                (
+                    usage.rule.as_str(),
+                    usage.condition.0.as_str(),
+                    position.line,
+                    position.start,
+                )
+            })
+            .collect()
+    }
+
+    fn id(rule_set: &RuleSet, rule: usize, condition: usize) -> &str {
+        &rule_set.rules[rule].conditions[condition].id.0
+    }
+
+    #[test]
+    fn test_property_references_over_every_spelling() {
+        let rule_set = parse_rule_set(DRIVING_TEST).unwrap();
+        let index = rule_set.usage_index();
+
+        assert_eq!(
+            index.properties.keys().collect::<Vec<_>>(),
+            [
+                "$.person.age",
+                "$.person.passMark",
+                "$.scores.drivingTest",
+                "$.scores.testDate"
+            ]
+        );
+        assert_eq!(
+            references(&index.properties["$.scores.drivingTest"]),
+            [
+                ("practical driving test", id(&rule_set, 1, 0), 8, 10),
+                ("practical driving test", id(&rule_set, 1, 2), 10, 11),
+            ]
+        );
+        assert_eq!(
+            references(&index.properties["$.person.age"]),
+            [
+                ("full driving licence", id(&rule_set, 0, 0), 3, 10),
+                ("provisional licence", id(&rule_set, 2, 0), 13, 10),
+            ]
+        );
+        // The right-hand property is found where it's written
+        assert_eq!(
+            references(&index.properties["$.person.passMark"]),
+            [("practical driving test", id(&rule_set, 1, 2), 10, 63)]
+        );
+    }
+
+    #[test]
+    fn test_outcome_references_by_name_label_and_alias() {
+        let rule_set = parse_rule_set(DRIVING_TEST).unwrap();
+        let index = rule_set.usage_index();
+
+        assert_eq!(
+            index.outcomes.keys().collect::<Vec<_>>(),
+            ["practical driving test"]
+        );
+        assert_eq!(
+            references(&index.outcomes["practical driving test"]),
+            [
+                ("full driving licence", id(&rule_set, 0, 1), 4, 22),
+                ("full driving licence", id(&rule_set, 0, 2), 5, 8),
+                ("provisional licence", id(&rule_set, 2, 1), 14, 22),
+            ]
+        );
+        assert_eq!(index.names["practical"], "practical driving test");
+        assert_eq!(
+            index.names["has passed practical"],
+            "practical driving test"
+        );
+    }
+
+    #[test]
+    fn test_unresolved_reference_is_kept_under_its_name() {
+        let rule_set =
+            parse_rule_set("A **driver** gets a licence\n  if the **driver** passes the eye test.")
+                .unwrap();
+        let index = rule_set.usage_index();
+        assert_eq!(
+            index.outcomes.keys().collect::<Vec<_>>(),
+            ["passes the eye test"]
+        );
+        assert_eq!(index.outcomes["passes the eye test"][0].rule, "licence");
+    }
+
+    #[test]
+    fn test_selector_mappings_share_the_mapped_path() {
+        let mut rule_set = parse_rule_set(
+            "A **driver** gets a licence\n  if the __age__ of the **driver** is at least 18\n  and the __age__ of the **person** is no more than 80.",
+        )
+        .unwrap();
+        assert_eq!(rule_set.usage_index().properties.len(), 2);
+
+        rule_set
+            .selector_mappings
+            .insert("driver".to_string(), "person".to_string());
+        let index = rule_set.usage_index();
+        assert_eq!(
+            index.properties.keys().collect::<Vec<_>>(),
+            ["$.person.age"]
+        );
+        assert_eq!(index.properties["$.person.age"].len(), 2);
+
+        let json = serde_json::to_value(&index).unwrap();
+        assert_eq!(json["properties"]["$.person.age"][0]["rule"], "licence");
+        assert!(json["properties"]["$.person.age"][0]
+            .get("document")
+            .is_none());
+    }
+}
@@ -0,0 +1,205 @@
+//! Where each property and rule is used, for "find references" while authoring.
+//!
+//! `RuleSet::usage_index()` lists, for "find references" while authoring, the conditions reading
+//! each property (keyed by `canonical_path`: the JSONPath with `selector_mappings` applied and each
+//! name camelCased, so `driving test` and `driving_test` share a key) and the conditions
+//! referencing each rule, keyed by the outcome the reference resolves to whether written by
+//! outcome, label or alias. Each usage has the rule's outcome, the `ConditionId` and the
+//! `SourcePosition`. Served by `POST /index` with `{"rule": ...}`.
+
+mod lib;
+
+use crate::runner::evaluator::resolve_rule;
+use crate::runner::model::{
+    constants, ComparisonCondition, Condition, ConditionId, QuantifiedCondition, RuleExpression,
+    RuleSet, SourcePosition,
+};
+use crate::runner::utils::{
+    json_path_from_keys, names_match, normalize_outcome, transform_property_name,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Where a policy reads each property and references each rule, for "find references" in the
+/// authoring UI, see `RuleSet::usage_index`
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct UsageIndex {
+    /// Conditions reading each property, keyed by canonical JSONPath (see `canonical_path`)
+    pub properties: BTreeMap<String, Vec<Usage>>,
+    /// Conditions referencing each rule, keyed by the referenced rule's outcome. A reference
+    /// that resolves to no rule is kept under the name it was written with
+    pub outcomes: BTreeMap<String, Vec<Usage>>,
+    /// The outcome each label and alias stands for, so a rule found by either can be looked
+    /// up in `outcomes`
+    pub names: BTreeMap<String, String>,
+}
+
+/// One condition reading a property or referencing a rule
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Usage {
+    /// Outcome of the rule the condition belongs to
+    pub rule: String,
+    pub condition: ConditionId,
+    /// The property or reference as written in the condition
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<SourcePosition>,
+    /// Included document the rule was written in, which `position` is within
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document: Option<String>,
+}
+
+impl RuleSet {
+    /// Every property read and every rule referenced, with the conditions doing it, in rule
+    /// and condition order. References resolve the way the evaluator resolves them, so one by
+    /// label, alias or a loosely matched outcome is listed under the rule it reaches
+    pub fn usage_index(&self) -> UsageIndex {
+        let mut index = UsageIndex::default();
+
+        for rule in &self.rules {
+            if let Some(label) = &rule.label {
+                index.names.insert(label.clone(), rule.outcome.clone());
+            }
+            for alias in &rule.aliases {
+                index
+                    .names
+                    .insert(alias.value.clone(), rule.outcome.clone());
+            }
+
+            for group in rule.all_conditions() {
+                let usage = |position: &Option<SourcePosition>| Usage {
+                    rule: rule.outcome.clone(),
+                    condition: group.id.clone(),
+                    position: position.clone(),
+                    document: rule.document.clone(),
+                };
+
+                match &group.condition {
+                    Condition::Comparison(comparison)
+                    | Condition::Quantified(QuantifiedCondition { comparison, .. }) => {
+                        for (names, position) in read_paths(comparison) {
+                            let key = self.canonical_path(&names);
+                            let entry = index.properties.entry(key).or_default();
+                            // A path read on both sides of one condition is one usage
+                            if !entry.iter().any(|u| u.condition == group.id) {
+                                entry.push(usage(&position));
+                            }
+                        }
+                    }
+                    Condition::RuleReference(reference) => {
+                        let name = reference.rule_name.value.trim();
+                        let outcome = match resolve_rule(name, self) {
+                            Some((referenced, _)) => referenced.outcome.clone(),
+                            None => normalize_outcome(name),
+                        };
+                        index
+                            .outcomes
+                            .entry(outcome)
+                            .or_default()
+                            .push(usage(&reference.rule_name.pos));
+                    }
+                    Condition::Flag(_) | Condition::Group(_) => {}
+                }
+            }
+        }
+
+        index
+    }
+
+    /// The key `usage_index` files a path under: a selector with a `selector_mappings` entry
+    /// is replaced by the path it maps to, and each name is written the way the evaluator
+    /// would look it up camelCased (`driving test` and `driving_test` are `drivingTest`), so
+    /// every spelling reading the same data shares one key
+    pub fn canonical_path(&self, names: &[String]) -> String {
+        let mut keys = Vec::with_capacity(names.len());
+        let mut names = names.iter();
+        if let Some(first) = names.next() {
+            let mapped = self
+                .selector_mappings
+                .iter()
+                .find(|(selector, _)| names_match(selector, first));
+            match mapped {
+                Some((_, path)) => keys.extend(path.split('.').map(canonical_name)),
+                None => keys.push(canonical_name(first)),
+            }
+        }
+        keys.extend(names.map(|name| canonical_name(name)));
+        json_path_from_keys(&keys)
+    }
+}
+
+/// The whole paths a comparison reads, `each` steps included, with where each is written:
+/// the left-hand path, then a property on the right, or the properties arithmetic reads on
+/// either side
+fn read_paths(comparison: &ComparisonCondition) -> Vec<(Vec<String>, Option<SourcePosition>)> {
+    let split = |selector: &str, properties: &[String]| -> Vec<String> {
+        selector
+            .split('.')
+            .chain(properties.iter().flat_map(|p| p.split('.')))
+            .map(str::to_string)
+            .collect()
+    };
+
+    let left = match &comparison.left_property_path {
+        Some(path) => {
+            // `length of`, `number of`, `hash bucket of` and the aggregates end the path in a
+            // marker
+            let properties = match path.properties.last().map(String::as_str) {
+                Some(constants::LENGTH_OF_MARKER)
+                | Some(constants::NUMBER_OF_MARKER)
+                | Some(constants::HASH_BUCKET_MARKER) => {
+                    &path.properties[..path.properties.len() - 1]
+                }
+                Some(marker) if constants::AGGREGATE_MARKERS.contains(&marker) => {
+                    &path.properties[..path.properties.len() - 1]
+                }
+                _ => &path.properties[..],
+            };
+            split(&path.selector, properties)
+        }
+        None => split(
+            &comparison.selector.value,
+            std::slice::from_ref(&comparison.property.value),
+        ),
+    };
+    let left_position = comparison
+        .property
+        .pos
+        .clone()
+        .or_else(|| comparison.value.pos.clone());
+
+    // A side doing arithmetic reads each property in it
+    let expression_paths = |expression: &RuleExpression| -> Vec<Vec<String>> {
+        expression
+            .property_paths()
+            .into_iter()
+            .map(|path| split(&path.selector, &path.properties))
+            .collect()
+    };
+    let mut paths = match &comparison.left_expression {
+        Some(expression) => expression_paths(expression)
+            .into_iter()
+            .map(|path| (path, left_position.clone()))
+            .collect(),
+        None => vec![(left, left_position)],
+    };
+    if let Some(expression) = &comparison.right_expression {
+        for path in expression_paths(expression) {
+            paths.push((path, comparison.value.pos.clone()));
+        }
+    } else if let Some(right) = &comparison.right_property_path {
+        paths.push((
+            split(&right.selector, &right.properties),
+            comparison.value.pos.clone(),
+        ));
+    }
+    paths
+}
+
+fn canonical_name(name: &str) -> String {
+    let camel = transform_property_name(name);
+    let mut chars = camel.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => camel,
+    }
+}
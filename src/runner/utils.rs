@@ -1,12 +1,27 @@
-use crate::runner::error::RuleError;
-use crate::runner::model::{Condition, Rule};
+//! JSON path helpers shared by the evaluator and the server.
+//!
+//! `"data_root": "$.envelope.payload"` evaluates against the object at that JSONPath of keys and
+//! indices (`utils::resolve_data_root`), so selectors are looked up from there and the payload
+//! doesn't have to be unwrapped first. Traced paths, warnings and violations are rebased to start
+//! at the top of `data` again, except those read through `**context**`. A root that isn't a path,
+//! isn't in the data or isn't an object fails with `RuleError::InvalidDataRoot`: a 400 whose
+//! `invalid_data_root` holds the `root` and the `problem` (`invalid_path`, `not_found` or
+//! `not_an_object` with what was `found`).
+//!
+//! Responses echo the request `data` unless `"echo_data"` says otherwise: `"none"` leaves it out
+//! and `"accessed"` sends back only the values the evaluation read. Those paths come from the trace
+//! (`RuleSetTrace::accessed_paths`) and are projected with `runner::utils::project_paths`, so
+//! sibling keys the rules never touched don't appear.
+
+use crate::runner::error::{DataRootError, DataRootProblem, OutcomeCandidate, RuleError};
+use crate::runner::model::{constants, Condition, PropertyChainElement, Rule};
 
 #[allow(dead_code)]
 pub fn find_referenced_outcomes(rules: &[Rule]) -> std::collections::HashSet<String> {
     let mut referenced = std::collections::HashSet::new();
 
     for rule in rules {
-        for condition_group in &rule.conditions {
+        for condition_group in rule.all_conditions() {
             match &condition_group.condition {
                 Condition::RuleReference(ref_condition) => {
                     let rule_name = &ref_condition.rule_name.value;
@@ -19,8 +34,15 @@ pub fn find_referenced_outcomes(rules: &[Rule]) -> std::collections::HashSet<Str
                             .as_ref()
                             .map_or(false, |label| label == rule_name);
 
-                        // Check if this rule matches by exact outcome
-                        let outcome_match = other_rule.outcome == *rule_name;
+                        // Check if this rule matches by exact outcome, with or without articles,
+                        // or by one of its aliases
+                        let outcome_match = other_rule.outcome == *rule_name
+                            || other_rule.outcome_display == *rule_name
+                            || other_rule.outcome == normalize_outcome(rule_name)
+                            || other_rule
+                                .aliases
+                                .iter()
+                                .any(|alias| alias.value == normalize_outcome(rule_name.trim()));
 
                         // Check if this rule matches by partial outcome (case insensitive)
                         // Improve matching logic to be more precise
@@ -97,8 +119,12 @@ pub fn find_referenced_outcomes(rules: &[Rule]) -> std::collections::HashSet<Str
                         }
                     }
                 }
-                Condition::Comparison(_) => {
-                    // Comparison conditions don't reference other rules
+                Condition::Comparison(_)
+                | Condition::Flag(_)
+                | Condition::Quantified(_)
+                | Condition::Group(_) => {
+                    // Comparison and flag conditions don't reference other rules, and the
+                    // conditions in a group are visited on their own
                 }
             }
         }
@@ -121,23 +147,104 @@ pub fn find_global_rule(rules: &[Rule]) -> Result<&Rule, RuleError> {
 
     match globals.len() {
         1 => Ok(globals[0]),
-        0 => Err(RuleError::ParseError("No global rule found".to_string())),
-        _ => {
-            let rule_list: Vec<String> = globals
-                .iter()
-                .map(|r| {
-                    if let Some(label) = &r.label {
-                        format!("'{}' ({})", label, r.outcome)
-                    } else {
-                        format!("'{}'", r.outcome)
-                    }
-                })
-                .collect();
-            Err(RuleError::ParseError(format!(
-                "Multiple global rules found: {}. There should be only one golden rule that is not referenced by other rules.",
-                rule_list.join(", ")
-            )))
+        // Every rule is referenced, so any of them could be the intended entry point
+        0 => Err(RuleError::NoGlobalRule {
+            candidates: rules.iter().map(OutcomeCandidate::from_rule).collect(),
+        }),
+        _ => Err(RuleError::MultipleGlobalRules {
+            candidates: globals
+                .into_iter()
+                .map(OutcomeCandidate::from_rule)
+                .collect(),
+        }),
+    }
+}
+
+/// Strips a single leading article ("the", "a", "an") from an outcome phrase.
+///
+/// Outcomes are stored in this form so results keys don't depend on how the verb
+/// was phrased: "passes the test" and "gets test" both produce the key "test".
+/// The phrase as written is kept on `Rule::outcome_display`.
+pub fn normalize_outcome(phrase: &str) -> String {
+    let trimmed = phrase.trim();
+    for article in ["the ", "a ", "an "] {
+        let has_article = trimmed
+            .get(..article.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(article));
+        if has_article && trimmed.len() > article.len() {
+            return trimmed[article.len()..].trim_start().to_string();
+        }
+    }
+    trimmed.to_string()
+}
+
+/// Renders the JSONPath of a property access the way traces report it.
+///
+/// Dots in `selector` separate nested selectors (`drivingTest.testDates`), as they do
+/// in the DSL. `chain` is walked in order after the selector and `property` is the final
+/// key. See `json_path_from_keys` for how each key is written.
+pub fn render_json_path(selector: &str, chain: &[PropertyChainElement], property: &str) -> String {
+    let mut path = json_path_from_keys(&selector.split('.').collect::<Vec<_>>());
+    for element in chain {
+        push_json_path_step(&mut path, element);
+    }
+    push_json_path_key(&mut path, property);
+    path
+}
+
+/// Renders a JSONPath from literal JSON keys, outermost first.
+///
+/// Keys that are plain identifiers use dot notation (`$.user.age`). Any other key,
+/// e.g. one containing dots, spaces or quotes, uses bracket notation with `\` and `'`
+/// backslash-escaped: `$['date of birth']`, `$['it\'s']`.
+///
+/// Paths under the reserved context selector start at `$context` instead (`$context.channel`),
+/// since the context isn't part of the data.
+pub fn json_path_from_keys<S: AsRef<str>>(keys: &[S]) -> String {
+    let (mut path, keys) = match keys.split_first() {
+        Some((first, rest)) if first.as_ref() == constants::CONTEXT_SELECTOR => {
+            (String::from("$context"), rest)
+        }
+        _ => (String::from("$"), keys),
+    };
+    for key in keys {
+        push_json_path_key(&mut path, key.as_ref());
+    }
+    path
+}
+
+/// Appends one step of a property chain to a JSONPath: a key as `push_json_path_key` writes
+/// it, `Index(2)` as `[2]` and `Last` as `[-1]`, the last element as RFC 9535 writes it
+pub fn push_json_path_step(path: &mut String, step: &PropertyChainElement) {
+    match step {
+        PropertyChainElement::Property(name) | PropertyChainElement::Selector(name) => {
+            push_json_path_key(path, name)
+        }
+        PropertyChainElement::Index(index) => path.push_str(&format!("[{}]", index)),
+        PropertyChainElement::Last => path.push_str("[-1]"),
+    }
+}
+
+/// Appends one key to a JSONPath, written as `json_path_from_keys` writes it
+pub fn push_json_path_key(path: &mut String, key: &str) {
+    let is_identifier = key
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if is_identifier {
+        path.push('.');
+        path.push_str(key);
+    } else {
+        path.push_str("['");
+        for c in key.chars() {
+            if c == '\\' || c == '\'' {
+                path.push('\\');
+            }
+            path.push(c);
         }
+        path.push_str("']");
     }
 }
 
@@ -273,6 +380,16 @@ pub fn normalize_name(name: &str) -> Vec<String> {
         }
     }
 
+    // Include the article-free form so "the test" and "test" match
+    let without_article = normalize_outcome(name);
+    if without_article != name.trim() {
+        for variant in normalize_name(&without_article) {
+            if !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+    }
+
     variants
 }
 
@@ -295,3 +412,419 @@ pub fn names_match(name1: &str, name2: &str) -> bool {
 
     false
 }
+
+/// Whether two paths of names have the same names, each matching as `names_match` does
+pub fn paths_match<S: AsRef<str>, T: AsRef<str>>(path1: &[S], path2: &[T]) -> bool {
+    path1.len() == path2.len()
+        && path1
+            .iter()
+            .zip(path2)
+            .all(|(name1, name2)| names_match(name1.as_ref(), name2.as_ref()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum JsonPathSegment {
+    Key(String),
+    Index(usize),
+    /// `[*]`, every element of a list
+    Wildcard,
+    /// `[-1]`, the last element of a list
+    Last,
+}
+
+/// Splits a JSONPath written by `json_path_from_keys`, plus `[n]` indices, `[-1]` and `[*]`,
+/// into its segments. Returns `None` for anything else.
+fn parse_json_path(path: &str) -> Option<Vec<JsonPathSegment>> {
+    let mut chars = path.strip_prefix('$')?.chars().peekable();
+    let mut segments = Vec::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                let mut key = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == '.' || c == '[' {
+                        break;
+                    }
+                    key.push(c);
+                    chars.next();
+                }
+                if key.is_empty() {
+                    return None;
+                }
+                segments.push(JsonPathSegment::Key(key));
+            }
+            '[' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                let mut key = String::new();
+                loop {
+                    match chars.next()? {
+                        '\\' => key.push(chars.next()?),
+                        '\'' => break,
+                        c => key.push(c),
+                    }
+                }
+                if chars.next()? != ']' {
+                    return None;
+                }
+                segments.push(JsonPathSegment::Key(key));
+            }
+            '[' => {
+                let mut inner = String::new();
+                loop {
+                    match chars.next()? {
+                        ']' => break,
+                        c => inner.push(c),
+                    }
+                }
+                segments.push(match inner.as_str() {
+                    "*" => JsonPathSegment::Wildcard,
+                    "-1" => JsonPathSegment::Last,
+                    digits => JsonPathSegment::Index(digits.parse().ok()?),
+                });
+            }
+            _ => return None,
+        }
+    }
+    Some(segments)
+}
+
+/// The value at a JSONPath written by `json_path_from_keys`, if there is exactly one
+pub fn resolve_json_path<'a>(
+    data: &'a serde_json::Value,
+    path: &str,
+) -> Option<&'a serde_json::Value> {
+    parse_json_path(path)?
+        .iter()
+        .try_fold(data, |value, segment| match segment {
+            JsonPathSegment::Key(key) => value.get(key),
+            JsonPathSegment::Index(index) => value.get(index),
+            JsonPathSegment::Last => value.as_array()?.last(),
+            JsonPathSegment::Wildcard => None,
+        })
+}
+
+/// Sets the value at a JSONPath of keys and indices, adding the keys `data` doesn't have yet.
+/// An index may be one past the end of its list to append. `$` replaces the whole of `data`
+#[allow(dead_code)]
+pub fn set_json_path(
+    data: &mut serde_json::Value,
+    path: &str,
+    value: serde_json::Value,
+) -> Result<(), String> {
+    let segments = parse_json_path(path)
+        .filter(|segments| {
+            !segments.contains(&JsonPathSegment::Wildcard)
+                && !segments.contains(&JsonPathSegment::Last)
+        })
+        .ok_or_else(|| format!("'{}' is not a path of keys and indices", path))?;
+    let mut target = data;
+    for segment in segments {
+        target = match segment {
+            JsonPathSegment::Key(key) => {
+                if target.is_null() {
+                    *target = serde_json::Value::Object(serde_json::Map::new());
+                }
+                target
+                    .as_object_mut()
+                    .ok_or_else(|| format!("'{}' goes through a value that isn't an object", path))?
+                    .entry(key)
+                    .or_insert(serde_json::Value::Null)
+            }
+            JsonPathSegment::Index(index) => {
+                let items = target
+                    .as_array_mut()
+                    .ok_or_else(|| format!("'{}' goes through a value that isn't a list", path))?;
+                if index == items.len() {
+                    items.push(serde_json::Value::Null);
+                }
+                items
+                    .get_mut(index)
+                    .ok_or_else(|| format!("'{}' indexes past the end of a list", path))?
+            }
+            JsonPathSegment::Wildcard | JsonPathSegment::Last => {
+                unreachable!("wildcards and last elements are refused above")
+            }
+        };
+    }
+    *target = value;
+    Ok(())
+}
+
+/// Whether one JSONPath is within the other, so changing the value at one changes what
+/// reading the other finds. Keys match as property lookups do, and `[*]` and `[-1]` match any
+/// element, since a list that grows has another last element. Paths that can't be parsed are
+/// taken to overlap
+#[allow(dead_code)]
+pub fn json_paths_overlap(path: &str, other: &str) -> bool {
+    let (Some(path), Some(other)) = (parse_json_path(path), parse_json_path(other)) else {
+        return true;
+    };
+    path.iter().zip(&other).all(|pair| match pair {
+        (JsonPathSegment::Wildcard, _) | (_, JsonPathSegment::Wildcard) => true,
+        (JsonPathSegment::Last, JsonPathSegment::Index(_) | JsonPathSegment::Last)
+        | (JsonPathSegment::Index(_), JsonPathSegment::Last) => true,
+        (JsonPathSegment::Key(key), JsonPathSegment::Key(other)) => names_match(key, other),
+        (JsonPathSegment::Index(index), JsonPathSegment::Index(other)) => index == other,
+        _ => false,
+    })
+}
+
+/// The object at `root`, a JSONPath of keys and indices, that rules read their selectors
+/// from in place of the whole of `data`. `$` is `data` itself
+pub fn resolve_data_root<'a>(
+    data: &'a serde_json::Value,
+    root: &str,
+) -> Result<&'a serde_json::Value, DataRootError> {
+    let error = |problem| DataRootError {
+        root: root.to_string(),
+        problem,
+    };
+    let segments = parse_json_path(root)
+        .filter(|segments| !segments.contains(&JsonPathSegment::Wildcard))
+        .ok_or_else(|| error(DataRootProblem::InvalidPath))?;
+    let node = segments
+        .iter()
+        .try_fold(data, |value, segment| match segment {
+            JsonPathSegment::Key(key) => value.get(key),
+            JsonPathSegment::Index(index) => value.get(index),
+            JsonPathSegment::Last => value.as_array()?.last(),
+            JsonPathSegment::Wildcard => None,
+        })
+        .ok_or_else(|| error(DataRootProblem::NotFound))?;
+
+    let found = match node {
+        serde_json::Value::Object(_) => return Ok(node),
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+    };
+    Err(error(DataRootProblem::NotAnObject {
+        found: found.to_string(),
+    }))
+}
+
+/// `path`, a JSONPath from a data root, as a JSONPath from the document `root` was resolved in
+pub fn rebase_json_path(root: &str, path: &str) -> String {
+    match path.strip_prefix('$') {
+        Some(rest) => format!("{}{}", root, rest),
+        None => path.to_string(),
+    }
+}
+
+/// Where a JSONPath written by `json_path_from_keys` stops resolving: the value whose key
+/// was missing and that key. Keys match as property lookups do, ignoring case and
+/// camelCase/snake_case differences. `None` when the path resolves or stops at an index
+pub fn find_missing_key<'a>(
+    data: &'a serde_json::Value,
+    path: &str,
+) -> Option<(&'a serde_json::Value, String)> {
+    let mut current = data;
+    for segment in parse_json_path(path)? {
+        current = match segment {
+            JsonPathSegment::Key(key) => {
+                let found = current.as_object().and_then(|object| {
+                    object.get(&key).or_else(|| {
+                        object
+                            .iter()
+                            .find(|(name, _)| names_match(&key, name))
+                            .map(|(_, value)| value)
+                    })
+                });
+                match found {
+                    Some(value) => value,
+                    None => return Some((current, key)),
+                }
+            }
+            JsonPathSegment::Index(index) => current.get(index)?,
+            JsonPathSegment::Last => current.as_array()?.last()?,
+            JsonPathSegment::Wildcard => return None,
+        };
+    }
+    None
+}
+
+/// Rebuilds the smallest document holding the value at each of `paths`.
+///
+/// Objects keep only the keys on a path. Arrays keep their positions: elements before a
+/// selected index that were not selected themselves become `null`, and elements after the
+/// last selected one are dropped; `[*]` selects every element. Paths that don't resolve in
+/// `data` contribute nothing, and `null` is returned when none do.
+pub fn project_paths(data: &serde_json::Value, paths: &[String]) -> serde_json::Value {
+    let mut projected = serde_json::Value::Null;
+    for segments in paths.iter().filter_map(|path| parse_json_path(path)) {
+        if let Some(projection) = project_segments(data, &segments) {
+            merge_projection(&mut projected, projection);
+        }
+    }
+    projected
+}
+
+/// The part of `data` one path selects, or `None` when it doesn't resolve
+fn project_segments(
+    data: &serde_json::Value,
+    segments: &[JsonPathSegment],
+) -> Option<serde_json::Value> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return Some(data.clone());
+    };
+
+    match segment {
+        JsonPathSegment::Key(key) => {
+            let child = project_segments(data.as_object()?.get(key)?, rest)?;
+            Some(serde_json::json!({ key.as_str(): child }))
+        }
+        JsonPathSegment::Index(index) => {
+            let child = project_segments(data.as_array()?.get(*index)?, rest)?;
+            let mut items = vec![serde_json::Value::Null; *index];
+            items.push(child);
+            Some(serde_json::Value::Array(items))
+        }
+        JsonPathSegment::Last => {
+            let index = data.as_array()?.len().checked_sub(1)?;
+            project_segments(data, &[&[JsonPathSegment::Index(index)], rest].concat())
+        }
+        JsonPathSegment::Wildcard => {
+            let items: Vec<Option<serde_json::Value>> = data
+                .as_array()?
+                .iter()
+                .map(|item| project_segments(item, rest))
+                .collect();
+            if items.iter().all(Option::is_none) && !items.is_empty() {
+                return None;
+            }
+            Some(serde_json::Value::Array(
+                items.into_iter().map(Option::unwrap_or_default).collect(),
+            ))
+        }
+    }
+}
+
+fn merge_projection(target: &mut serde_json::Value, projection: serde_json::Value) {
+    match (target, projection) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(projection)) => {
+            for (key, value) in projection {
+                match target.get_mut(&key) {
+                    Some(existing) => merge_projection(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (serde_json::Value::Array(target), serde_json::Value::Array(projection)) => {
+            for (index, value) in projection.into_iter().enumerate() {
+                match target.get_mut(index) {
+                    Some(existing) => merge_projection(existing, value),
+                    None => target.push(value),
+                }
+            }
+        }
+        // Placeholders for unselected elements never replace anything
+        (_, serde_json::Value::Null) => {}
+        (target, projection) => *target = projection,
+    }
+}
+
+/// The JSON pointers of keys that appear more than once in the same object of `json`, such as
+/// `/user/age` for `{"user": {"age": 20, "age": 30}}`, each once per object and in the order
+/// they repeat. serde_json keeps the last of them, so a payload holding any reads differently
+/// depending on who parsed it. The search stops at a syntax error, reporting what it found
+pub fn check_duplicate_keys(json: &str) -> Vec<String> {
+    let mut duplicates = Vec::new();
+    let seed = DuplicateKeys {
+        pointer: String::new(),
+        duplicates: &mut duplicates,
+    };
+    let _ = serde::de::DeserializeSeed::deserialize(
+        seed,
+        &mut serde_json::Deserializer::from_str(json),
+    );
+    duplicates
+}
+
+/// Walks the value at `pointer`, pushing the pointers of repeated keys to `duplicates`
+struct DuplicateKeys<'a> {
+    pointer: String,
+    duplicates: &'a mut Vec<String>,
+}
+
+impl<'de> serde::de::DeserializeSeed<'de> for DuplicateKeys<'_> {
+    type Value = ();
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for DuplicateKeys<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a JSON value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let duplicates = self.duplicates;
+        let mut index = 0;
+        while seq
+            .next_element_seed(DuplicateKeys {
+                pointer: format!("{}/{}", self.pointer, index),
+                duplicates: &mut *duplicates,
+            })?
+            .is_some()
+        {
+            index += 1;
+        }
+        Ok(())
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let duplicates = self.duplicates;
+        let mut seen = std::collections::HashMap::new();
+        while let Some(key) = map.next_key::<String>()? {
+            // RFC 6901 escapes `~` before `/`, so `~1` in a key isn't read back as `/`
+            let pointer = format!(
+                "{}/{}",
+                self.pointer,
+                key.replace('~', "~0").replace('/', "~1")
+            );
+            let count = seen.entry(key).or_insert(0);
+            *count += 1;
+            if *count == 2 {
+                duplicates.push(pointer.clone());
+            }
+            map.next_value_seed(DuplicateKeys {
+                pointer,
+                duplicates: &mut *duplicates,
+            })?;
+        }
+        Ok(())
+    }
+}
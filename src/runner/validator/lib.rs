@@ -0,0 +1,678 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::model::{
+        ComparisonCondition, ComparisonOperator, Condition, Duration, PositionedValue, Rule,
+        RuleSet, RuleValue, TimeUnit,
+    };
+    use crate::runner::parser::{parse_rule_set, parse_rules, ParseLimits};
+    use crate::runner::validator::{
+        validate_rule_set, validate_rule_set_with_limits, validate_rules, Severity,
+    };
+    use chrono::NaiveDate;
+
+    fn rule_set_with(operator: ComparisonOperator, value: RuleValue) -> RuleSet {
+        let mut rule = Rule::new(None, "user".to_string(), "check".to_string());
+        rule.add_condition(
+            Condition::Comparison(ComparisonCondition {
+                selector: PositionedValue::new("user".to_string()),
+                property: PositionedValue::new("field".to_string()),
+                operator,
+                value: PositionedValue::new(value),
+                property_chain: None,
+                left_property_path: None,
+                right_property_path: None,
+                date_offset: None,
+                today: false,
+                left_expression: None,
+                right_expression: None,
+            }),
+            None,
+        );
+
+        let mut rule_set = RuleSet::new();
+        rule_set.add_rule(rule);
+        rule_set
+    }
+
+    fn mismatch_count(operator: ComparisonOperator, value: RuleValue) -> usize {
+        validate_rule_set(&rule_set_with(operator, value))
+            .iter()
+            .filter(|d| d.code == "operator-type-mismatch")
+            .count()
+    }
+
+    fn text(s: &str) -> RuleValue {
+        RuleValue::String(s.to_string())
+    }
+
+    fn days(amount: f64) -> RuleValue {
+        RuleValue::Duration(Duration::new(amount, TimeUnit::Days))
+    }
+
+    #[test]
+    fn test_every_operator_rejects_wrong_literal() {
+        use ComparisonOperator::*;
+
+        let wrong = vec![
+            (GreaterThanOrEqual, text("hello")),
+            (LessThanOrEqual, RuleValue::Boolean(true)),
+            (GreaterThan, text("hello")),
+            (LessThan, days(5.0)),
+            (LaterThan, RuleValue::Number(5.0)),
+            (EarlierThan, text("yesterday")),
+            (EqualTo, RuleValue::List(vec![RuleValue::Number(1.0)])),
+            (ExactlyEqualTo, days(1.0)),
+            (NotEqualTo, RuleValue::List(vec![])),
+            (In, text("admin")),
+            (NotIn, RuleValue::Number(3.0)),
+            (Contains, days(2.0)),
+            (Within, RuleValue::Number(5.0)),
+            (OlderThan, text("5 years")),
+            (YoungerThan, RuleValue::Boolean(false)),
+        ];
+
+        for (operator, value) in wrong {
+            let label = format!("{} {:?}", operator, value);
+            assert_eq!(mismatch_count(operator, value), 1, "{}", label);
+        }
+    }
+
+    #[test]
+    fn test_matching_literals_are_accepted() {
+        use ComparisonOperator::*;
+
+        let date = RuleValue::Date(NaiveDate::from_ymd_opt(2020, 1, 1).unwrap());
+        let right = vec![
+            (GreaterThan, RuleValue::Number(18.0)),
+            (GreaterThan, text("18")),
+            (GreaterThanOrEqual, text("2.14.3")),
+            (LessThan, date.clone()),
+            (LaterThan, date.clone()),
+            (EarlierThan, text("2020-01-01")),
+            (EqualTo, text("active")),
+            (In, RuleValue::List(vec![text("a"), text("b")])),
+            (Contains, text("admin")),
+            (Within, days(30.0)),
+        ];
+
+        for (operator, value) in right {
+            let label = format!("{} {:?}", operator, value);
+            assert_eq!(mismatch_count(operator, value), 0, "{}", label);
+        }
+
+        assert!(validate_rule_set(&rule_set_with(IsEmpty, text("ignored"))).is_empty());
+    }
+
+    #[test]
+    fn test_parsed_mismatch_has_position() {
+        let input = r#"A **user** passes the test if __age__ of **user** is greater than "hello"."#;
+        let rule_set = parse_rules(input).unwrap();
+        let diagnostics = validate_rule_set(&rule_set);
+
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.rule, "test");
+        assert!(diagnostic.message.contains("is greater than"));
+        assert!(diagnostic.message.contains("age"));
+
+        assert_eq!(
+            diagnostic.condition.as_ref(),
+            Some(&rule_set.rules[0].conditions[0].id)
+        );
+
+        let position = diagnostic.position.as_ref().expect("position");
+        assert_eq!(position.line, 1);
+        // Columns are 1-based
+        assert_eq!(&input[position.start - 1..position.end - 1], r#""hello""#);
+    }
+
+    #[test]
+    fn test_duration_operator_with_number_is_flagged() {
+        let input = r#"A **user** passes the test if __signup__ of **user** is within 5."#;
+        let rule_set = parse_rules(input).unwrap();
+        let diagnostics = validate_rule_set(&rule_set);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("duration"));
+    }
+
+    #[test]
+    fn test_mixed_list_is_a_warning() {
+        let input = r#"A **user** passes the test if __role__ of **user** is in ["admin", 3]."#;
+        let rule_set = parse_rules(input).unwrap();
+        let diagnostics = validate_rule_set(&rule_set);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].code, "mixed-list-types");
+        assert!(diagnostics[0].message.contains("string, number"));
+    }
+
+    #[test]
+    fn test_property_to_property_comparison_is_skipped() {
+        let input = r#"A **user** passes the test if __age__ of **user** is greater than __limit__ of **policy**."#;
+        let rule_set = parse_rules(input).unwrap();
+
+        assert!(validate_rule_set(&rule_set).is_empty());
+    }
+
+    #[test]
+    fn test_large_list_warns_above_threshold() {
+        let items: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let input = format!(
+            r#"A **user** passes the test if __id__ of **user** is in [{}]."#,
+            items.join(", ")
+        );
+        let rule_set = parse_rules(&input).unwrap();
+        assert!(validate_rule_set(&rule_set).is_empty());
+
+        let limits = ParseLimits {
+            list_warning_elements: 10,
+            ..ParseLimits::default()
+        };
+        let diagnostics = validate_rule_set_with_limits(&rule_set, &limits);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].code, "large-list");
+        assert!(diagnostics[0].message.contains("20 elements"));
+    }
+
+    #[test]
+    fn test_quantified_reference_warns() {
+        let rule_set = parse_rules(
+            "A **team** is ready\n  if the **employees** all satisfy the checks\n  and the **team** passes the review.",
+        )
+        .unwrap();
+        let diagnostics = validate_rule_set(&rule_set);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.code, "unsupported-quantifier");
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert!(diagnostic.message.contains("'all' over 'employees'"));
+        assert_eq!(diagnostic.position.as_ref().unwrap().line, 2);
+        assert_eq!(
+            diagnostic.condition,
+            Some(rule_set.rules[0].conditions[0].id.clone())
+        );
+    }
+
+    #[test]
+    fn test_alias_collisions_warn() {
+        let rule_set = parse_rules(
+            r#"A **driver** gets a licence
+  if the **driver** has passed an eye test
+  and the **driver** passes the theory test.
+
+A **driver** passes the eye test (also known as "has passed an eye test", "the theory test")
+  if the __vision__ of the **driver** is greater than 0.5.
+
+A **driver** passes the theory test (also known as "has passed an eye test")
+  if the __theory score__ of the **driver** is at least 43."#,
+        )
+        .unwrap();
+        let diagnostics = validate_rule_set(&rule_set);
+        let collisions: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.code == "alias-collision")
+            .collect();
+        assert_eq!(collisions.len(), 2, "{:?}", diagnostics);
+
+        assert_eq!(collisions[0].rule, "eye test");
+        assert_eq!(
+            collisions[0].message,
+            "Alias 'theory test' of 'eye test' is the outcome of 'the theory test', so references to it don't reach this rule"
+        );
+        let position = collisions[0].position.as_ref().unwrap();
+        assert_eq!((position.line, position.start), (5, 75));
+
+        // The first rule keeps a shared alias
+        assert_eq!(collisions[1].rule, "theory test");
+        assert!(collisions[1]
+            .message
+            .contains("already an alias of 'the eye test'"));
+    }
+
+    fn conflicts(input: &str) -> Vec<crate::runner::validator::Diagnostic> {
+        let rule_set = parse_rules(input).unwrap();
+        validate_rule_set(&rule_set)
+            .into_iter()
+            .filter(|d| d.code == "contradictory-conditions" || d.code == "redundant-condition")
+            .collect()
+    }
+
+    #[test]
+    fn test_contradictory_conditions_warn_with_both_positions() {
+        let input = "A **user** gets the pension\n  if the __age__ of the **user** is greater than 65\n  and the __age__ of the **user** is less than 18.";
+        let rule_set = parse_rules(input).unwrap();
+        let diagnostics = conflicts(input);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.code, "contradictory-conditions");
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(
+            diagnostic.message,
+            "'age' is less than 18 contradicts 'age' is greater than 65 on line 2, so the two are never true together"
+        );
+        assert_eq!(
+            diagnostic.condition,
+            Some(rule_set.rules[0].conditions[1].id.clone())
+        );
+        assert_eq!(diagnostic.position.as_ref().unwrap().line, 3);
+        assert_eq!(diagnostic.related_position.as_ref().unwrap().line, 2);
+    }
+
+    #[test]
+    fn test_contradictions_between_equality_and_membership() {
+        let contradictory = [
+            r#"__role__ of **user** is equal to "admin" and __role__ of **user** is not equal to "Admin""#,
+            r#"__role__ of **user** is equal to "admin" and __role__ of **user** is equal to "guest""#,
+            r#"__role__ of **user** is in ["admin", "owner"] and __role__ of **user** is not in ["owner", "admin", "guest"]"#,
+            r#"__role__ of **user** is in ["admin"] and __role__ of **user** is in ["guest", "owner"]"#,
+            r#"__age__ of **user** is equal to 30 and __age__ of **user** is at least 40"#,
+            r#"__age__ of **user** is at least 40 and __age__ of **user** is less than 40"#,
+            r#"__joined__ of **user** is later than 2024-01-01 and __joined__ of **user** is earlier than 2023-06-01"#,
+        ];
+        for conditions in contradictory {
+            let diagnostics = conflicts(&format!("A **user** passes the test if {}.", conditions));
+            let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+            assert_eq!(codes, ["contradictory-conditions"], "{}", conditions);
+        }
+    }
+
+    #[test]
+    fn test_subsumed_conditions_are_redundant() {
+        let redundant = [
+            (
+                "__age__ of **user** is greater than 65 and __age__ of **user** is greater than 18",
+                "'age' is greater than 18 is implied by 'age' is greater than 65 on line 1, so it never changes the outcome",
+            ),
+            (
+                "__age__ of **user** is greater than 18 and __age__ of **user** is greater than 65",
+                "'age' is greater than 18 is implied by 'age' is greater than 65 on line 1, so it never changes the outcome",
+            ),
+            (
+                r#"__role__ of **user** is equal to "admin" and __role__ of **user** is in ["admin", "owner"]"#,
+                r#"'role' is in ["admin", "owner"] is implied by 'role' is equal to "admin" on line 1, so it never changes the outcome"#,
+            ),
+            (
+                r#"__role__ of **user** is not in ["guest", "banned"] and __role__ of **user** is not equal to "guest""#,
+                r#"'role' is not equal to "guest" is implied by 'role' is not in ["guest", "banned"] on line 1, so it never changes the outcome"#,
+            ),
+        ];
+        for (conditions, message) in redundant {
+            let diagnostics = conflicts(&format!("A **user** passes the test if {}.", conditions));
+            assert_eq!(diagnostics.len(), 1, "{}", conditions);
+            assert_eq!(diagnostics[0].code, "redundant-condition");
+            assert_eq!(diagnostics[0].message, message);
+        }
+    }
+
+    #[test]
+    fn test_compatible_conditions_are_fine() {
+        let fine = [
+            "__age__ of **user** is at least 18 and __age__ of **user** is less than 65",
+            "__age__ of **user** is at least 18 and __age__ of **user** is no more than 18",
+            "__age__ of **user** is greater than 65 or __age__ of **user** is less than 18",
+            "__age__ of **user** is greater than 65 and __score__ of **user** is less than 18",
+            "__age__ of **user** is greater than 65 and __age__ of **partner** is less than 18",
+            r#"__role__ of **user** is not equal to "guest" and __role__ of **user** is not equal to "banned""#,
+            r#"__role__ of **user** is in ["admin", "owner"] and __role__ of **user** is not in ["owner"]"#,
+            r#"__code__ of **user** is equal to "18" and __code__ of **user** is greater than 20"#,
+            "__age__ of **user** is greater than __limit__ of **policy** and __age__ of **user** is less than 18",
+        ];
+        for conditions in fine {
+            let diagnostics = conflicts(&format!("A **user** passes the test if {}.", conditions));
+            assert!(diagnostics.is_empty(), "{}: {:?}", conditions, diagnostics);
+        }
+    }
+
+    #[test]
+    fn test_conflicts_only_within_one_and_branch() {
+        // `and` binds tighter, so the contradiction is in the second branch alone
+        let diagnostics = conflicts(
+            "A **user** gets the discount\n  if the __age__ of the **user** is greater than 65\n  or the __age__ of the **user** is less than 18\n  and the __age__ of the **user** is greater than 30.",
+        );
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].position.as_ref().unwrap().line, 4);
+        assert_eq!(diagnostics[0].related_position.as_ref().unwrap().line, 3);
+    }
+
+    #[test]
+    fn test_property_spellings_share_a_key() {
+        let diagnostics = conflicts(
+            "A **user** passes the test if __date of birth__ of **user** is later than 2000-01-01 and __dateOfBirth__ of **user** is earlier than 1990-01-01.",
+        );
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].code, "contradictory-conditions");
+    }
+
+    #[test]
+    fn test_redundant_pairs_table() {
+        // The two conditions, and which of them is redundant if either is
+        let table: [(&str, &str, Option<usize>); 24] = [
+            // Numbers
+            ("is greater than 18", "is greater than 16", Some(1)),
+            ("is greater than 16", "is greater than 18", Some(0)),
+            (
+                "is greater than or equal to 18",
+                "is greater than 18",
+                Some(0),
+            ),
+            (
+                "is greater than 18",
+                "is greater than or equal to 18",
+                Some(1),
+            ),
+            ("is less than 10", "is less than or equal to 10", Some(1)),
+            ("is equal to 12", "is less than 20", Some(1)),
+            ("is equal to 12", "is not equal to 13", Some(1)),
+            ("is at least 10", "is no more than 20", None),
+            ("is at least 10", "is not equal to 15", None),
+            // Dates
+            (
+                "is earlier than 2020-01-01",
+                "is earlier than 2021-01-01",
+                Some(1),
+            ),
+            (
+                "is later than 2020-01-01",
+                "is earlier than 2021-01-01",
+                None,
+            ),
+            (
+                "is equal to 2020-06-01",
+                "is later than 2020-01-01",
+                Some(1),
+            ),
+            // Lists
+            (r#"is in ["a", "b"]"#, r#"is equal to "a""#, Some(0)),
+            (r#"is in ["a"]"#, r#"is in ["a", "b"]"#, Some(1)),
+            (r#"is in ["a", "b"]"#, r#"is in ["b", "c"]"#, None),
+            (r#"is not in ["a", "b"]"#, r#"is not in ["a"]"#, Some(1)),
+            (r#"is in [1, 2]"#, "is less than 5", Some(1)),
+            (r#"is in [1, 8]"#, "is less than 5", None),
+            // Strings, where only an exact comparison cares about case
+            (r#"is equal to "Admin""#, r#"is equal to "admin""#, Some(1)),
+            (
+                r#"is exactly equal to "Admin""#,
+                r#"is equal to "admin""#,
+                Some(1),
+            ),
+            (
+                r#"is equal to "admin""#,
+                r#"is exactly equal to "Admin""#,
+                Some(0),
+            ),
+            (
+                r#"is exactly equal to "Admin""#,
+                r#"is in ["ADMIN", "owner"]"#,
+                Some(1),
+            ),
+            (
+                r#"is in ["admin", "owner"]"#,
+                r#"is exactly equal to "admin""#,
+                Some(0),
+            ),
+            (
+                r#"is exactly equal to "Admin""#,
+                r#"is not equal to "guest""#,
+                Some(1),
+            ),
+        ];
+
+        for (first, second, redundant) in table {
+            let input = format!(
+                "A **user** passes the test\n  if the __field__ of the **user** {}\n  and the __field__ of the **user** {}.",
+                first, second
+            );
+            let rule_set = parse_rules(&input).unwrap();
+            let lints = rule_set.lint();
+            let label = format!("{} / {}: {:?}", first, second, lints);
+
+            match redundant {
+                None => assert!(lints.is_empty(), "{}", label),
+                Some(index) => {
+                    assert_eq!(lints.len(), 1, "{}", label);
+                    let lint = &lints[0];
+                    assert_eq!(lint.code, "redundant-condition", "{}", label);
+                    let group = &rule_set.rules[0].conditions[index];
+                    assert_eq!(lint.condition.as_ref(), Some(&group.id), "{}", label);
+                    let (line, other_line) = (index + 2, 3 - index);
+                    assert_eq!(lint.position.as_ref().unwrap().line, line, "{}", label);
+                    assert_eq!(
+                        lint.related_position.as_ref().unwrap().line,
+                        other_line,
+                        "{}",
+                        label
+                    );
+                    let removed = if index == 0 { first } else { second };
+                    assert_eq!(
+                        lint.suggestion.as_deref(),
+                        Some(format!("Remove 'field' {}", removed).as_str()),
+                        "{}",
+                        label
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_exact_string_contradictions() {
+        let table = [
+            (
+                r#"is exactly equal to "Admin""#,
+                r#"is exactly equal to "admin""#,
+                true,
+            ),
+            (
+                r#"is exactly equal to "Admin""#,
+                r#"is not equal to "ADMIN""#,
+                true,
+            ),
+            (r#"is exactly equal to "Admin""#, r#"is in ["owner"]"#, true),
+            (
+                r#"is exactly equal to "Admin""#,
+                r#"is not in ["owner"]"#,
+                false,
+            ),
+        ];
+        for (first, second, contradictory) in table {
+            let input = format!(
+                "A **user** passes the test if __role__ of **user** {} and __role__ of **user** {}.",
+                first, second
+            );
+            let lints = parse_rules(&input).unwrap().lint();
+            let codes: Vec<_> = lints.iter().map(|d| d.code).collect();
+            if contradictory {
+                assert_eq!(codes, ["contradictory-conditions"], "{}", input);
+                assert_eq!(lints[0].suggestion, None);
+            } else {
+                assert_eq!(codes, ["redundant-condition"], "{}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_diagnostics_carry_the_rules_tags() {
+        let input = "Tags: pricing.\nA **order** gets a discount\n  if the __total__ of the **order** is greater than 100\n  and the __total__ of the **order** is less than 50.\n\nA **order** ships if the __weight__ of the **order** is less than 10\n  and the __weight__ of the **order** is greater than 20.";
+        let rule_set = parse_rule_set(input).unwrap();
+
+        let lints = rule_set.lint();
+        assert_eq!(lints.len(), 2);
+        assert_eq!(lints[0].tags, vec!["pricing"]);
+        assert!(lints[1].tags.is_empty());
+        let diagnostics = validate_rule_set(&rule_set);
+        assert!(diagnostics.iter().any(|d| d.tags == vec!["pricing"]));
+        assert!(serde_json::to_value(&lints[1])
+            .unwrap()
+            .get("tags")
+            .is_none());
+    }
+
+    #[test]
+    fn test_validate_rules_accepts_a_clean_document() {
+        let input = "A **driver** passes the licence check\n  if the **driver** passes the eye test.\n\nA **driver** passes the eye test\n  if the __vision__ of the **driver** is greater than 5.";
+        let report = validate_rules(input);
+
+        assert!(report.valid);
+        assert!(report.errors.is_empty());
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    }
+
+    #[test]
+    fn test_validate_rules_warns_about_unresolved_references() {
+        let input = "A **driver** passes the licence check\n  if the **driver** passes the eye tset\n  and §medical is valid.";
+        let report = validate_rules(input);
+
+        assert!(report.valid);
+        let unresolved: Vec<_> = report
+            .warnings
+            .iter()
+            .filter(|d| d.code == "unresolved-reference")
+            .collect();
+        assert_eq!(unresolved.len(), 2);
+        assert_eq!(
+            unresolved[0].message,
+            "Rule 'licence check' references outcome 'passes the eye tset' which no rule produces"
+        );
+        assert_eq!(unresolved[0].rule, "licence check");
+        assert_eq!(unresolved[0].position.as_ref().unwrap().line, 2);
+        assert!(unresolved[0].condition.is_some());
+        assert!(unresolved[1].message.contains("label 'medical'"));
+        assert_eq!(unresolved[1].position.as_ref().unwrap().line, 3);
+    }
+
+    #[test]
+    fn test_validate_rules_reports_every_global_rule() {
+        let input = "A **driver** passes the licence check\n  if the __age__ of the **driver** is greater than 17.\n\nA **driver** gets a discount\n  if the __age__ of the **driver** is greater than 65.";
+        let report = validate_rules(input);
+
+        assert!(!report.valid);
+        assert_eq!(report.errors.len(), 1);
+        let error = &report.errors[0];
+        assert_eq!(error.code, "multiple-global-rules");
+        assert!(error.message.contains("licence check, discount"));
+        assert_eq!(error.position.as_ref().unwrap().line, 1);
+        assert_eq!(error.related_position.as_ref().unwrap().line, 4);
+
+        let unreferenced: Vec<_> = report
+            .warnings
+            .iter()
+            .filter(|d| d.code == "unreferenced-rule")
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(
+            unreferenced,
+            [
+                "Rule 'licence check' is defined but never referenced",
+                "Rule 'discount' is defined but never referenced"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_rules_reports_reference_cycles() {
+        let input = "A **driver** passes the licence check\n  if the **driver** passes the eye test.\n\nA **driver** passes the eye test\n  if the **driver** passes the medical.\n\nA **driver** passes the medical\n  if the **driver** passes the eye test.";
+        let report = validate_rules(input);
+
+        assert!(!report.valid);
+        let cycles: Vec<_> = report
+            .errors
+            .iter()
+            .filter(|d| d.code == "reference-cycle")
+            .collect();
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(
+            cycles[0].message,
+            "Rule 'eye test' references itself through eye test -> medical -> eye test, which never finishes evaluating"
+        );
+        assert_eq!(cycles[0].rule, "medical");
+        assert_eq!(cycles[0].position.as_ref().unwrap().line, 8);
+        // The cycle doesn't hide the global rule
+        assert!(report.errors.iter().all(|d| d.code == "reference-cycle"));
+    }
+
+    #[test]
+    fn test_validate_rules_locates_parse_errors() {
+        let report = validate_rules(
+            "A **driver** passes the test\n  if the __age__ of the **driver** is bigger than 17.",
+        );
+
+        assert!(!report.valid);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].severity, Severity::Error);
+        assert_eq!(report.errors[0].position.as_ref().unwrap().line, 2);
+        assert!(report.warnings.is_empty());
+    }
+
+    const CATALOGUE: &str =
+        "The **user** has numeric __age__, a __country__ text and an __orders__ list.\n\
+                             The __orders__ have numeric __total__.\n\n";
+
+    fn catalogue_diagnostics(rule: &str) -> Vec<(&'static str, String, usize)> {
+        let rule_set = parse_rules(&format!("{}{}", CATALOGUE, rule)).unwrap();
+        validate_rule_set(&rule_set)
+            .into_iter()
+            .filter(|d| d.code == "undeclared-property" || d.code == "declared-type-mismatch")
+            .map(|d| (d.code, d.message, d.position.map_or(0, |pos| pos.line)))
+            .collect()
+    }
+
+    #[test]
+    fn test_declared_properties_pass_the_catalogue_check() {
+        let diagnostics = catalogue_diagnostics(
+            "A **user** gets access\n  if the __age__ of the **user** is at least 18\n  and the __country__ of the **user** is in [\"GB\", \"IE\"]\n  and number of __orders__ in **user** is at least 1\n  and the __requester__ of the **context** is equal to \"app\".",
+        );
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        // Without a catalogue nothing is checked
+        let rule_set = parse_rules(
+            "A **user** gets access if the __postcode__ of the **user** is equal to \"N1\".",
+        )
+        .unwrap();
+        assert!(validate_rule_set(&rule_set)
+            .iter()
+            .all(|d| d.code != "undeclared-property"));
+    }
+
+    #[test]
+    fn test_undeclared_property_names_what_is_declared() {
+        let diagnostics = catalogue_diagnostics(
+            "A **user** gets access\n  if the __age__ of the **user** is at least 18\n  and the __post code__ of the **user** is equal to \"N1\".",
+        );
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        let (code, message, line) = &diagnostics[0];
+        assert_eq!(*code, "undeclared-property");
+        assert_eq!(
+            message,
+            "'$.user.postCode' isn't declared in the property catalogue; declared there: 'age', 'country', 'orders'"
+        );
+        assert_eq!(*line, 6);
+    }
+
+    #[test]
+    fn test_declared_type_mismatch() {
+        let diagnostics = catalogue_diagnostics(
+            "A **user** gets access\n  if the __country__ of the **user** is greater than 3\n  and the __age__ of the **user** is later than 2000-01-01.",
+        );
+        let codes: Vec<_> = diagnostics.iter().map(|(code, _, _)| *code).collect();
+        assert_eq!(codes, ["declared-type-mismatch", "declared-type-mismatch"]);
+        assert_eq!(
+            diagnostics[0].1,
+            "'$.user.country' is declared as text, but this condition needs a number"
+        );
+        assert!(diagnostics[1].1.contains("declared as a number"));
+
+        let diagnostics = catalogue_diagnostics(
+            "A **user** gets access if the __code__ of the **user.age** is equal to 1.",
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].1,
+            "'$.user.age' is declared as a number, so '$.user.age.code' can't be read from it"
+        );
+    }
+}
@@ -0,0 +1,1256 @@
+//! Authoring checks on a parsed rule set, reported as `diagnostics` by `POST /parse`.
+//!
+//! Authoring checks on a parsed rule set (e.g. literal types that can't match their operator),
+//! reported as `diagnostics` by `POST /parse`.
+//!
+//! `validate_rules(text)` checks a document without data for an authoring UI, returning a
+//! `ValidationReport` of `errors` and `warnings`, each a positioned `Diagnostic`. Errors are the
+//! parse's diagnostics, `multiple-global-rules` / `no-global-rule` (`find_global_rule`) and
+//! `reference-cycle`, found statically by following references resolved as
+//! `evaluator::resolve_rule` does. Warnings are `unresolved-reference` (a reference no rule
+//! produces), `unreferenced-rule` (outside `find_referenced_outcomes`), `unreachable-rule`
+//! (referenced, but not from the global rule's references) and the `validate_rule_set` checks.
+//! Served as `POST /validate` with `{"rule": ...}`, which answers 200 even when the report is
+//! invalid.
+//!
+//! The validator compares the conditions of each run of `and`s (never across `or`) that compare the
+//! same property, keyed with the names camel-cased and lowercased, against literals of matching
+//! kinds. A pair no value satisfies is `contradictory-conditions`, one implied by another (`is
+//! greater than 18` after `is greater than 65`, `is in [...]` after an `is equal to` in the list)
+//! is `redundant-condition`. Both are warnings about the later or implied condition, with the
+//! other's position as `related_position`; a redundant one also carries a `suggestion` naming the
+//! condition to remove. Strings compare ignoring case except under `is exactly equal to`, so `is
+//! exactly equal to "Admin"` implies `is equal to "admin"` but not the other way round.
+//! `RuleSet::lint()` runs these pair checks alone.
+
+mod lib;
+
+use crate::runner::error::RuleError;
+use crate::runner::evaluator::{resolve_rule, Version};
+use crate::runner::model::{
+    constants, ComparisonCondition, ComparisonOperator, Condition, ConditionGroup, ConditionId,
+    ConditionOperator, QuantifiedCondition, Rule, RuleReferenceCondition, RuleSet, RuleValue,
+    SourcePosition,
+};
+use crate::runner::parser::{parse_rule_set_with_limits, ParseLimits};
+use crate::runner::schema::condition_requirements;
+use crate::runner::utils::{
+    find_global_rule, find_referenced_outcomes, json_path_from_keys, names_match, paths_match,
+    push_json_path_step, transform_property_name,
+};
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found in a parsed rule set that doesn't stop it from parsing
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    /// Outcome of the rule the diagnostic belongs to, empty when the rule didn't parse
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub rule: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<SourcePosition>,
+    /// The condition the diagnostic is about, when it is about one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<ConditionId>,
+    /// The other condition of a diagnostic about a pair of conditions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub related_position: Option<SourcePosition>,
+    /// An edit that resolves the diagnostic, such as the condition to remove
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+    /// Included document the position is in, `None` for the document that was parsed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document: Option<String>,
+    /// Tags of the rule the diagnostic belongs to, so diagnostics can be sorted by domain
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// Runs the authoring checks over every rule and returns what they found, in rule order
+#[allow(dead_code)]
+pub fn validate_rule_set(rule_set: &RuleSet) -> Vec<Diagnostic> {
+    validate_rule_set_with_limits(rule_set, &ParseLimits::default())
+}
+
+pub fn validate_rule_set_with_limits(rule_set: &RuleSet, limits: &ParseLimits) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for rule in &rule_set.rules {
+        let rule_start = diagnostics.len();
+        check_alias_collisions(rule_set, rule, &mut diagnostics);
+        for group in rule.all_conditions() {
+            let found = diagnostics.len();
+            match &group.condition {
+                Condition::Comparison(comparison)
+                | Condition::Quantified(QuantifiedCondition { comparison, .. }) => {
+                    check_operator_value_type(rule, comparison, &mut diagnostics);
+                    check_list_size(rule, comparison, limits, &mut diagnostics);
+                    check_property_catalogue(rule_set, rule, comparison, &mut diagnostics);
+                }
+                Condition::RuleReference(reference) => {
+                    check_quantified_reference(rule, reference, &mut diagnostics)
+                }
+                Condition::Flag(_) | Condition::Group(_) => {}
+            }
+            for diagnostic in &mut diagnostics[found..] {
+                diagnostic.condition = Some(group.id.clone());
+            }
+        }
+        check_conflicting_conditions(rule, &mut diagnostics);
+        for diagnostic in &mut diagnostics[rule_start..] {
+            diagnostic.document = rule.document.clone();
+            diagnostic.tags = rule.tags.clone();
+        }
+    }
+
+    diagnostics
+}
+
+impl RuleSet {
+    /// The checks that compare conditions with each other, `contradictory-conditions` and
+    /// `redundant-condition`, without the rest of `validate_rule_set`
+    pub fn lint(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for rule in &self.rules {
+            let rule_start = diagnostics.len();
+            check_conflicting_conditions(rule, &mut diagnostics);
+            for diagnostic in &mut diagnostics[rule_start..] {
+                diagnostic.document = rule.document.clone();
+                diagnostic.tags = rule.tags.clone();
+            }
+        }
+        diagnostics
+    }
+}
+
+/// What `validate_rules` found in a document, errors apart from warnings
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ValidationReport {
+    /// No errors: the document parses, has a single global rule and no reference cycle
+    pub valid: bool,
+    pub errors: Vec<Diagnostic>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl ValidationReport {
+    fn from_diagnostics(diagnostics: Vec<Diagnostic>) -> Self {
+        let (errors, warnings): (Vec<_>, Vec<_>) = diagnostics
+            .into_iter()
+            .partition(|diagnostic| diagnostic.severity == Severity::Error);
+        Self {
+            valid: errors.is_empty(),
+            errors,
+            warnings,
+        }
+    }
+}
+
+/// Checks a rule document without any data, for an authoring UI checking as the user types:
+/// the parse, the global rule, references that resolve to no rule, rules nothing references
+/// or the global rule never reaches, reference cycles, and the checks of `validate_rule_set`
+#[allow(dead_code)]
+pub fn validate_rules(rule_text: &str) -> ValidationReport {
+    validate_rules_with_limits(rule_text, &ParseLimits::default())
+}
+
+pub fn validate_rules_with_limits(rule_text: &str, limits: &ParseLimits) -> ValidationReport {
+    let rule_set = match parse_rule_set_with_limits(rule_text, limits) {
+        Ok(rule_set) => rule_set,
+        Err(error) => return ValidationReport::from_diagnostics(failure_diagnostics(&error)),
+    };
+
+    let mut diagnostics = Vec::new();
+    let references = resolve_references(&rule_set, &mut diagnostics);
+    check_reference_cycles(&rule_set, &references, &mut diagnostics);
+    check_global_rule(&rule_set, &references, &mut diagnostics);
+    diagnostics.extend(validate_rule_set_with_limits(&rule_set, limits));
+    ValidationReport::from_diagnostics(diagnostics)
+}
+
+/// The diagnostics of a parse that failed. A failure in one place is located by its
+/// `ParseErrorDetail`, when the grammar rejected the text
+fn failure_diagnostics(error: &RuleError) -> Vec<Diagnostic> {
+    if let Some(diagnostics) = error.parse_diagnostics() {
+        return diagnostics.to_vec();
+    }
+    let position = error.parse_detail().map(|detail| SourcePosition {
+        line: detail.line,
+        start: detail.column,
+        end: detail.column,
+    });
+    vec![Diagnostic {
+        severity: Severity::Error,
+        code: "parse-error",
+        message: error.to_string(),
+        rule: String::new(),
+        position,
+        condition: None,
+        related_position: None,
+        suggestion: None,
+        document: None,
+        tags: Vec::new(),
+    }]
+}
+
+/// A rule reference resolved the way the evaluator resolves it
+struct ResolvedEdge<'a> {
+    target: usize,
+    reference: &'a RuleReferenceCondition,
+    condition: &'a ConditionId,
+}
+
+/// The rules each rule's references resolve to, by index, warning about the references that
+/// resolve to none
+fn resolve_references<'a>(
+    rule_set: &'a RuleSet,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<Vec<ResolvedEdge<'a>>> {
+    let mut references = Vec::with_capacity(rule_set.rules.len());
+    for rule in &rule_set.rules {
+        let mut edges = Vec::new();
+        for group in rule.all_conditions() {
+            let Condition::RuleReference(reference) = &group.condition else {
+                continue;
+            };
+            let name = reference.rule_name.value.trim();
+            let resolved =
+                resolve_rule(name, rule_set).and_then(|(rule, _)| rule_set.index_of(rule));
+            if let Some(target) = resolved {
+                edges.push(ResolvedEdge {
+                    target,
+                    reference,
+                    condition: &group.id,
+                });
+                continue;
+            }
+
+            // A label reference is the one written without a selector
+            let message = if reference.selector.value.is_empty() {
+                format!(
+                    "Rule '{}' references label '{}' which no rule carries",
+                    rule.outcome, name
+                )
+            } else {
+                format!(
+                    "Rule '{}' references outcome '{}' which no rule produces",
+                    rule.outcome, name
+                )
+            };
+            diagnostics.push(rule_diagnostic(
+                rule,
+                Severity::Warning,
+                "unresolved-reference",
+                message,
+                reference.rule_name.pos.clone(),
+                Some(group.id.clone()),
+            ));
+        }
+        references.push(edges);
+    }
+    references
+}
+
+/// Reports every cycle of references once, at the reference that closes it. The evaluator
+/// fails with `RuleError::InfiniteLoop` when it reaches one
+fn check_reference_cycles(
+    rule_set: &RuleSet,
+    references: &[Vec<ResolvedEdge>],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Visit {
+        New,
+        OnPath,
+        Done,
+    }
+
+    fn walk(
+        index: usize,
+        rule_set: &RuleSet,
+        references: &[Vec<ResolvedEdge>],
+        visits: &mut [Visit],
+        path: &mut Vec<usize>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        visits[index] = Visit::OnPath;
+        path.push(index);
+        for edge in &references[index] {
+            match visits[edge.target] {
+                Visit::New => walk(edge.target, rule_set, references, visits, path, diagnostics),
+                Visit::OnPath => {
+                    let start = path.iter().position(|&rule| rule == edge.target);
+                    let cycle: Vec<&str> = path[start.unwrap_or_default()..]
+                        .iter()
+                        .chain(std::iter::once(&edge.target))
+                        .map(|&rule| rule_set.rules[rule].outcome.as_str())
+                        .collect();
+                    let rule = &rule_set.rules[index];
+                    diagnostics.push(rule_diagnostic(
+                        rule,
+                        Severity::Error,
+                        "reference-cycle",
+                        format!(
+                            "Rule '{}' references itself through {}, which never finishes evaluating",
+                            rule_set.rules[edge.target].outcome,
+                            cycle.join(" -> ")
+                        ),
+                        edge.reference.rule_name.pos.clone(),
+                        Some(edge.condition.clone()),
+                    ));
+                }
+                Visit::Done => {}
+            }
+        }
+        path.pop();
+        visits[index] = Visit::Done;
+    }
+
+    let mut visits = vec![Visit::New; rule_set.rules.len()];
+    for index in 0..rule_set.rules.len() {
+        if visits[index] == Visit::New {
+            walk(
+                index,
+                rule_set,
+                references,
+                &mut visits,
+                &mut Vec::new(),
+                diagnostics,
+            );
+        }
+    }
+}
+
+/// `find_global_rule` as an error when there isn't exactly one global rule, and a warning for
+/// each rule no other rule references, or that the global rule's references never reach
+fn check_global_rule(
+    rule_set: &RuleSet,
+    references: &[Vec<ResolvedEdge>],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if rule_set.rules.is_empty() {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            code: "no-rules",
+            message: "The document defines no rule".to_string(),
+            rule: String::new(),
+            position: None,
+            condition: None,
+            related_position: None,
+            suggestion: None,
+            document: None,
+            tags: Vec::new(),
+        });
+        return;
+    }
+
+    let global = match find_global_rule(&rule_set.rules) {
+        Ok(rule) => rule_set.index_of(rule),
+        Err(RuleError::MultipleGlobalRules { candidates }) => {
+            let outcomes: Vec<&str> = candidates.iter().map(|c| c.outcome.as_str()).collect();
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                code: "multiple-global-rules",
+                message: format!(
+                    "No other rule references {}, so there is more than one global rule",
+                    outcomes.join(", ")
+                ),
+                rule: String::new(),
+                position: candidates.first().and_then(|c| c.position.clone()),
+                condition: None,
+                related_position: candidates.get(1).and_then(|c| c.position.clone()),
+                suggestion: None,
+                document: None,
+                tags: Vec::new(),
+            });
+            None
+        }
+        Err(error) => {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                code: "no-global-rule",
+                message: error.to_string(),
+                rule: String::new(),
+                position: rule_set.rules[0].position.clone(),
+                condition: None,
+                related_position: None,
+                suggestion: None,
+                document: None,
+                tags: Vec::new(),
+            });
+            None
+        }
+    };
+
+    let referenced = find_referenced_outcomes(&rule_set.rules);
+    let mut reached = vec![false; rule_set.rules.len()];
+    if let Some(global) = global {
+        let mut pending = vec![global];
+        while let Some(index) = pending.pop() {
+            if std::mem::replace(&mut reached[index], true) {
+                continue;
+            }
+            pending.extend(references[index].iter().map(|edge| edge.target));
+        }
+    }
+
+    for (index, rule) in rule_set.rules.iter().enumerate() {
+        if Some(index) == global {
+            continue;
+        }
+        if !referenced.contains(&rule.outcome) {
+            diagnostics.push(rule_diagnostic(
+                rule,
+                Severity::Warning,
+                "unreferenced-rule",
+                format!("Rule '{}' is defined but never referenced", rule.outcome),
+                rule.position.clone(),
+                None,
+            ));
+        } else if let Some(global) = global.filter(|_| !reached[index]) {
+            diagnostics.push(rule_diagnostic(
+                rule,
+                Severity::Warning,
+                "unreachable-rule",
+                format!(
+                    "Rule '{}' is never reached from the global rule '{}'",
+                    rule.outcome, rule_set.rules[global].outcome
+                ),
+                rule.position.clone(),
+                None,
+            ));
+        }
+    }
+}
+
+fn rule_diagnostic(
+    rule: &Rule,
+    severity: Severity,
+    code: &'static str,
+    message: String,
+    position: Option<SourcePosition>,
+    condition: Option<ConditionId>,
+) -> Diagnostic {
+    Diagnostic {
+        severity,
+        code,
+        message,
+        rule: rule.outcome.clone(),
+        position,
+        condition,
+        related_position: None,
+        suggestion: None,
+        document: rule.document.clone(),
+        tags: rule.tags.clone(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    Number,
+    String,
+    Date,
+    Boolean,
+    List,
+    Duration,
+    Range,
+    Version,
+}
+
+impl ValueKind {
+    fn of(value: &RuleValue) -> Self {
+        match value {
+            RuleValue::Number(_) => ValueKind::Number,
+            RuleValue::String(_) => ValueKind::String,
+            RuleValue::Date(_) => ValueKind::Date,
+            RuleValue::Boolean(_) => ValueKind::Boolean,
+            RuleValue::List(_) => ValueKind::List,
+            RuleValue::Duration(_) => ValueKind::Duration,
+            RuleValue::Range(..) => ValueKind::Range,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ValueKind::Number => "number",
+            ValueKind::String => "string",
+            ValueKind::Date => "date",
+            ValueKind::Boolean => "boolean",
+            ValueKind::List => "list",
+            ValueKind::Duration => "duration",
+            ValueKind::Range => "range",
+            ValueKind::Version => "version",
+        }
+    }
+}
+
+/// Value kinds an operator can meaningfully compare against, `None` when it takes no value
+fn permitted_kinds(operator: &ComparisonOperator) -> Option<&'static [ValueKind]> {
+    use ComparisonOperator::*;
+
+    match operator {
+        GreaterThanOrEqual | LessThanOrEqual | GreaterThan | LessThan => {
+            Some(&[ValueKind::Number, ValueKind::Date, ValueKind::Version])
+        }
+        LaterThan | EarlierThan => Some(&[ValueKind::Date]),
+        EqualTo | ExactlyEqualTo | NotEqualTo => Some(&[
+            ValueKind::Number,
+            ValueKind::String,
+            ValueKind::Date,
+            ValueKind::Boolean,
+        ]),
+        In | NotIn => Some(&[ValueKind::List]),
+        // A string is searched for a word, a list for an element
+        Contains => Some(&[
+            ValueKind::String,
+            ValueKind::Number,
+            ValueKind::Date,
+            ValueKind::Boolean,
+        ]),
+        Within | OlderThan | YoungerThan | NotWithin | MoreThanAgo | AtLeastAgo => {
+            Some(&[ValueKind::Duration])
+        }
+        Between | StrictlyBetween => Some(&[ValueKind::Range]),
+        IsEmpty | IsNotEmpty => None,
+    }
+}
+
+/// String literals holding a date or number are coerced by the evaluator, and ones holding a
+/// version are ordered as one, so they count as those kinds
+fn effective_kinds(value: &RuleValue) -> Vec<ValueKind> {
+    let mut kinds = vec![ValueKind::of(value)];
+    if let RuleValue::String(s) = value {
+        if NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok() {
+            kinds.push(ValueKind::Date);
+        }
+        if s.trim().parse::<f64>().is_ok() {
+            kinds.push(ValueKind::Number);
+        }
+        if Version::parse(s).is_some() {
+            kinds.push(ValueKind::Version);
+        }
+    }
+    kinds
+}
+
+fn check_operator_value_type(
+    rule: &Rule,
+    condition: &ComparisonCondition,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    // The right side is another property or arithmetic, its type is only known at
+    // evaluation time
+    if condition.right_property_path.is_some() || condition.right_expression.is_some() {
+        return;
+    }
+
+    let Some(permitted) = permitted_kinds(&condition.operator) else {
+        return;
+    };
+
+    let value = &condition.value.value;
+    let position = condition
+        .value
+        .pos
+        .clone()
+        .or_else(|| condition.property.pos.clone());
+
+    // `today` is a date, whatever its text
+    let kinds = if condition.today {
+        vec![ValueKind::Date]
+    } else {
+        effective_kinds(value)
+    };
+    if !kinds.iter().any(|kind| permitted.contains(kind)) {
+        let expected: Vec<&str> = permitted.iter().map(|kind| kind.name()).collect();
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            code: "operator-type-mismatch",
+            message: format!(
+                "'{}' of '{}' expects a {} value but got a {}",
+                condition.operator,
+                condition.property.value,
+                expected.join(" or "),
+                kinds[0].name()
+            ),
+            rule: rule.outcome.clone(),
+            position: position.clone(),
+            condition: None,
+            related_position: None,
+            suggestion: None,
+            document: None,
+            tags: Vec::new(),
+        });
+    }
+
+    if let RuleValue::List(items) = value {
+        let mut kinds: Vec<ValueKind> = Vec::new();
+        for item in items {
+            let kind = ValueKind::of(item);
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+
+        if kinds.len() > 1 {
+            let names: Vec<&str> = kinds.iter().map(|kind| kind.name()).collect();
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                code: "mixed-list-types",
+                message: format!(
+                    "List compared with '{}' mixes {} elements",
+                    condition.property.value,
+                    names.join(", ")
+                ),
+                rule: rule.outcome.clone(),
+                position,
+                condition: None,
+                related_position: None,
+                suggestion: None,
+                document: None,
+                tags: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Words that would quantify over a plural selector, as in `the **employees** all satisfy the
+/// checks`. Quantifiers only go before a list property, see `QuantifiedCondition`, so such a
+/// condition is a plain rule reference
+const QUANTIFIERS: [&str; 4] = ["all", "each", "every", "any"];
+
+fn check_quantified_reference(
+    rule: &Rule,
+    reference: &RuleReferenceCondition,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let name = reference.rule_name.value.trim();
+    let Some(quantifier) = QUANTIFIERS.iter().find(|quantifier| {
+        name.split_whitespace()
+            .next()
+            .is_some_and(|word| word.eq_ignore_ascii_case(quantifier))
+    }) else {
+        return;
+    };
+
+    diagnostics.push(Diagnostic {
+        severity: Severity::Warning,
+        code: "unsupported-quantifier",
+        message: format!(
+            "'{}' over '{}' isn't supported yet; this reads as a reference to a rule named '{}'",
+            quantifier, reference.selector.value, name
+        ),
+        rule: rule.outcome.clone(),
+        position: reference.rule_name.pos.clone(),
+        condition: None,
+        related_position: None,
+        suggestion: None,
+        document: None,
+        tags: Vec::new(),
+    });
+}
+
+/// An alias that is another rule's outcome, alias or label never resolves to this rule, since
+/// outcomes take precedence and the first rule with an alias keeps it
+fn check_alias_collisions(rule_set: &RuleSet, rule: &Rule, diagnostics: &mut Vec<Diagnostic>) {
+    let index = rule_set.index_of(rule);
+    for alias in &rule.aliases {
+        let collision = rule_set
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|&(other, _)| Some(other) != index)
+            .find_map(|(other, other_rule)| {
+                if other_rule.outcome == alias.value {
+                    Some(("the outcome", other_rule))
+                } else if other_rule.label.as_deref() == Some(alias.value.as_str()) {
+                    Some(("the label", other_rule))
+                } else if Some(other) < index
+                    && other_rule.aliases.iter().any(|a| a.value == alias.value)
+                {
+                    Some(("already an alias", other_rule))
+                } else {
+                    None
+                }
+            });
+        let Some((what, other_rule)) = collision else {
+            continue;
+        };
+
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            code: "alias-collision",
+            message: format!(
+                "Alias '{}' of '{}' is {} of '{}', so references to it don't reach this rule",
+                alias.value,
+                rule.outcome,
+                what,
+                other_rule.name()
+            ),
+            rule: rule.outcome.clone(),
+            position: alias.pos.clone(),
+            condition: None,
+            related_position: None,
+            suggestion: None,
+            document: None,
+            tags: Vec::new(),
+        });
+    }
+}
+
+fn check_list_size(
+    rule: &Rule,
+    condition: &ComparisonCondition,
+    limits: &ParseLimits,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if let RuleValue::List(items) = &condition.value.value {
+        if items.len() > limits.list_warning_elements {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                code: "large-list",
+                message: format!(
+                    "List compared with '{}' has {} elements; lists over {} are rejected, consider passing the values in the data",
+                    condition.property.value,
+                    items.len(),
+                    limits.max_list_elements
+                ),
+                rule: rule.outcome.clone(),
+                position: condition.value.pos.clone(),
+                condition: None,
+                related_position: None,
+                suggestion: None,
+                document: None,
+                tags: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Paths a comparison reads that the property catalogue doesn't declare, or declares as a type
+/// the comparison can't use. Without a catalogue nothing is declared and nothing is checked.
+/// The request context isn't data and is never declared
+fn check_property_catalogue(
+    rule_set: &RuleSet,
+    rule: &Rule,
+    comparison: &ComparisonCondition,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let catalogue = &rule_set.property_catalogue;
+    if catalogue.is_empty() {
+        return;
+    }
+    let position = comparison
+        .property
+        .pos
+        .clone()
+        .or_else(|| comparison.value.pos.clone());
+
+    for (path, expected) in condition_requirements(comparison) {
+        if path
+            .first()
+            .is_some_and(|name| names_match(name, constants::CONTEXT_SELECTOR))
+        {
+            continue;
+        }
+
+        // A text, number, date or boolean has no properties to read on into
+        if let Some(scalar) = (1..path.len())
+            .filter_map(|depth| rule_set.declared_property(&path[..depth]))
+            .find(|declared| !declared.kind.has_properties())
+        {
+            diagnostics.push(rule_diagnostic(
+                rule,
+                Severity::Error,
+                "declared-type-mismatch",
+                format!(
+                    "'{}' is declared as {}, so '{}' can't be read from it",
+                    catalogue_json_path(&scalar.path),
+                    scalar.kind,
+                    catalogue_json_path(&path)
+                ),
+                position.clone(),
+                None,
+            ));
+            continue;
+        }
+
+        // Each step must be declared, either itself or as the parent of something declared
+        let undeclared = (1..=path.len()).find(|&depth| {
+            let prefix = &path[..depth];
+            rule_set.declared_property(prefix).is_none()
+                && !catalogue.iter().any(|declared| {
+                    declared.path.len() > depth && paths_match(&declared.path[..depth], prefix)
+                })
+        });
+        if let Some(depth) = undeclared {
+            let siblings: Vec<String> = catalogue
+                .iter()
+                .filter(|declared| {
+                    declared.path.len() == depth
+                        && paths_match(&declared.path[..depth - 1], &path[..depth - 1])
+                })
+                .map(|declared| format!("'{}'", declared.path[depth - 1]))
+                .collect();
+            let declared = match siblings.is_empty() {
+                true => String::new(),
+                false => format!("; declared there: {}", siblings.join(", ")),
+            };
+            diagnostics.push(rule_diagnostic(
+                rule,
+                Severity::Error,
+                "undeclared-property",
+                format!(
+                    "'{}' isn't declared in the property catalogue{}",
+                    catalogue_json_path(&path[..depth]),
+                    declared
+                ),
+                position.clone(),
+                None,
+            ));
+            continue;
+        }
+
+        let Some(declared) = rule_set.declared_property(&path) else {
+            continue;
+        };
+        if !expected.is_empty() && !expected.iter().any(|json| json.admits(declared.kind)) {
+            let wanted: Vec<&str> = expected.iter().map(|json| json.name()).collect();
+            diagnostics.push(rule_diagnostic(
+                rule,
+                Severity::Error,
+                "declared-type-mismatch",
+                format!(
+                    "'{}' is declared as {}, but this condition needs a {}",
+                    catalogue_json_path(&path),
+                    declared.kind,
+                    wanted.join(" or ")
+                ),
+                position.clone(),
+                None,
+            ));
+        }
+    }
+}
+
+fn catalogue_json_path(path: &[String]) -> String {
+    let keys: Vec<String> = path
+        .iter()
+        .map(|name| transform_property_name(name))
+        .collect();
+    json_path_from_keys(&keys)
+}
+
+/// Comparisons of the same property joined by `and` that no value satisfies together, or of
+/// which one already implies the other. `and` binds tighter than `or`, so only conditions in
+/// the same run of `and`s are compared. Only literals are considered, and only pairs whose
+/// kinds match without coercion, so whatever is reported holds for every input. The
+/// conditions in a group are compared among themselves
+fn check_conflicting_conditions(rule: &Rule, diagnostics: &mut Vec<Diagnostic>) {
+    check_conflicting_in(rule, &rule.conditions, diagnostics);
+}
+
+fn check_conflicting_in(
+    rule: &Rule,
+    conditions: &[ConditionGroup],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut branch: Vec<(&ConditionId, &ComparisonCondition, String, Constraint)> = Vec::new();
+    for group in conditions {
+        if group.operator == Some(ConditionOperator::Or) {
+            branch.clear();
+        }
+        if let Condition::Group(members) = &group.condition {
+            check_conflicting_in(rule, members, diagnostics);
+        }
+        let Condition::Comparison(comparison) = &group.condition else {
+            continue;
+        };
+        // The constraint a negated comparison puts on the value isn't modelled
+        if group.negated {
+            continue;
+        }
+        let (Some(key), Some(constraint)) = (property_key(comparison), Constraint::of(comparison))
+        else {
+            continue;
+        };
+
+        for (earlier_id, earlier, earlier_key, earlier_constraint) in &branch {
+            if *earlier_key != key {
+                continue;
+            }
+            if constraint.contradicts(earlier_constraint) {
+                diagnostics.push(pair_diagnostic(
+                    rule,
+                    "contradictory-conditions",
+                    (&group.id, comparison),
+                    earlier,
+                    "contradicts",
+                    "so the two are never true together",
+                ));
+            } else if earlier_constraint.implies(&constraint) {
+                diagnostics.push(redundant_diagnostic(rule, (&group.id, comparison), earlier));
+            } else if constraint.implies(earlier_constraint) {
+                diagnostics.push(redundant_diagnostic(
+                    rule,
+                    (earlier_id, earlier),
+                    comparison,
+                ));
+            }
+        }
+        branch.push((&group.id, comparison, key, constraint));
+    }
+}
+
+/// `redundant` is implied by `subsuming`, so it can go
+fn redundant_diagnostic(
+    rule: &Rule,
+    redundant: (&ConditionId, &ComparisonCondition),
+    subsuming: &ComparisonCondition,
+) -> Diagnostic {
+    Diagnostic {
+        suggestion: Some(format!("Remove {}", describe_comparison(redundant.1))),
+        ..pair_diagnostic(
+            rule,
+            "redundant-condition",
+            redundant,
+            subsuming,
+            "is implied by",
+            "so it never changes the outcome",
+        )
+    }
+}
+
+/// A warning about `subject` that points at `other` as its related position
+fn pair_diagnostic(
+    rule: &Rule,
+    code: &'static str,
+    (id, subject): (&ConditionId, &ComparisonCondition),
+    other: &ComparisonCondition,
+    relation: &str,
+    consequence: &str,
+) -> Diagnostic {
+    let related_position = comparison_position(other);
+    let location = match &related_position {
+        Some(pos) => format!(" on line {}", pos.line),
+        None => String::new(),
+    };
+    Diagnostic {
+        severity: Severity::Warning,
+        code,
+        message: format!(
+            "{} {} {}{}, {}",
+            describe_comparison(subject),
+            relation,
+            describe_comparison(other),
+            location,
+            consequence
+        ),
+        rule: rule.outcome.clone(),
+        position: comparison_position(subject),
+        condition: Some(id.clone()),
+        related_position,
+        suggestion: None,
+        document: None,
+        tags: Vec::new(),
+    }
+}
+
+fn comparison_position(comparison: &ComparisonCondition) -> Option<SourcePosition> {
+    comparison
+        .value
+        .pos
+        .clone()
+        .or_else(|| comparison.property.pos.clone())
+}
+
+/// `'age' is greater than 65`, with `length of`, `number of`, `hash bucket of` and the
+/// aggregates spelled out
+fn describe_comparison(comparison: &ComparisonCondition) -> String {
+    let phrase = match comparison.property.value.as_str() {
+        constants::LENGTH_OF_MARKER => "length of",
+        constants::NUMBER_OF_MARKER => "number of",
+        constants::HASH_BUCKET_MARKER => "hash bucket of",
+        constants::SUM_OF_MARKER => "sum of",
+        constants::MINIMUM_OF_MARKER => "minimum of",
+        constants::MAXIMUM_OF_MARKER => "maximum of",
+        constants::AVERAGE_OF_MARKER => "average of",
+        property => property,
+    };
+    let properties = comparison
+        .left_property_path
+        .as_ref()
+        .map(|path| path.properties.as_slice())
+        .unwrap_or_default();
+    let subject = match properties {
+        [.., property, marker] if marker.starts_with("__") => format!("{} {}", phrase, property),
+        _ => phrase.to_string(),
+    };
+
+    let value = match &comparison.value.value {
+        RuleValue::Date(date) => date.format("%Y-%m-%d").to_string(),
+        RuleValue::String(today) if comparison.today => today.clone(),
+        value => value.to_string(),
+    };
+    let value = match (&comparison.date_offset, comparison.operator.value_suffix()) {
+        (Some(offset), _) => format!("{} {}", value, offset),
+        (None, Some(suffix)) => format!("{} {}", value, suffix),
+        (None, None) => value,
+    };
+    format!("'{}' {} {}", subject, comparison.operator, value)
+}
+
+/// The property a comparison reads however its names are spelled, so `date of birth` and
+/// `dateOfBirth` are the same. `None` when the comparison may read more than one value
+fn property_key(comparison: &ComparisonCondition) -> Option<String> {
+    if comparison
+        .property_chain
+        .as_ref()
+        .is_some_and(|chain| !chain.is_empty())
+    {
+        return None;
+    }
+    let (selector, properties, elements) = match &comparison.left_property_path {
+        Some(path) if !path.fan_out.is_empty() => return None,
+        Some(path) => (
+            path.selector.as_str(),
+            path.properties.as_slice(),
+            path.elements.as_slice(),
+        ),
+        None => (
+            comparison.selector.value.as_str(),
+            std::slice::from_ref(&comparison.property.value),
+            &[][..],
+        ),
+    };
+
+    let mut key = transform_property_name(selector).to_lowercase();
+    for (i, property) in properties.iter().enumerate() {
+        key.push('.');
+        if property.starts_with("__") {
+            key.push_str(property);
+        } else {
+            key.push_str(&transform_property_name(property).to_lowercase());
+        }
+        // The first and the last element of a list are different values
+        for (_, element) in elements.iter().filter(|(at, _)| *at == i) {
+            push_json_path_step(&mut key, element);
+        }
+    }
+    Some(key)
+}
+
+/// A literal as the evaluator compares it, strings ignoring case
+#[derive(Debug, Clone, PartialEq)]
+enum Scalar {
+    Number(f64),
+    Date(NaiveDate),
+    Text(String),
+    Boolean(bool),
+}
+
+impl Scalar {
+    fn of(value: &RuleValue) -> Option<Self> {
+        match value {
+            RuleValue::Number(n) => Some(Scalar::Number(*n)),
+            RuleValue::Date(d) => Some(Scalar::Date(*d)),
+            RuleValue::String(s) => Some(Scalar::Text(s.to_lowercase())),
+            RuleValue::Boolean(b) => Some(Scalar::Boolean(*b)),
+            RuleValue::List(_) | RuleValue::Duration(_) | RuleValue::Range(..) => None,
+        }
+    }
+
+    /// `None` when the two are of different kinds, which coercion may still make equal
+    fn same(&self, other: &Scalar) -> Option<bool> {
+        match (self, other) {
+            (Scalar::Number(a), Scalar::Number(b)) => Some(a == b),
+            (Scalar::Date(a), Scalar::Date(b)) => Some(a == b),
+            (Scalar::Text(a), Scalar::Text(b)) => Some(a == b),
+            (Scalar::Boolean(a), Scalar::Boolean(b)) => Some(a == b),
+            _ => None,
+        }
+    }
+
+    /// `None` unless both are numbers or both are dates
+    fn order(&self, other: &Scalar) -> Option<Ordering> {
+        match (self, other) {
+            (Scalar::Number(a), Scalar::Number(b)) => a.partial_cmp(b),
+            (Scalar::Date(a), Scalar::Date(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+/// What a comparison against a literal requires of the property's value
+#[derive(Debug, Clone, PartialEq)]
+enum Constraint {
+    /// One end of a range, above or below `limit`
+    Bound {
+        limit: Scalar,
+        above: bool,
+        inclusive: bool,
+    },
+    /// `is in`, and `is equal to` as a list of one
+    In(Vec<Scalar>),
+    /// `is not in`, and `is not equal to` as a list of one
+    NotIn(Vec<Scalar>),
+    /// `is exactly equal to` a string, the one comparison where case matters
+    Exactly(String),
+}
+
+impl Constraint {
+    fn of(comparison: &ComparisonCondition) -> Option<Self> {
+        use ComparisonOperator::*;
+
+        // Arithmetic bounds no single property, and `today` no property for good
+        if comparison.right_property_path.is_some()
+            || comparison.is_arithmetic()
+            || comparison.today
+        {
+            return None;
+        }
+        let value = match (&comparison.value.value, &comparison.date_offset) {
+            (RuleValue::Date(date), Some(offset)) => RuleValue::Date(offset.apply(*date)?),
+            (_, Some(_)) => return None,
+            (value, None) => value.clone(),
+        };
+        let bound = |above, inclusive| match Scalar::of(&value)? {
+            limit @ (Scalar::Number(_) | Scalar::Date(_)) => Some(Constraint::Bound {
+                limit,
+                above,
+                inclusive,
+            }),
+            _ => None,
+        };
+        let list = || match &value {
+            RuleValue::List(items) => items.iter().map(Scalar::of).collect::<Option<Vec<_>>>(),
+            _ => None,
+        };
+
+        match comparison.operator {
+            GreaterThan | LaterThan => bound(true, false),
+            GreaterThanOrEqual => bound(true, true),
+            LessThan | EarlierThan => bound(false, false),
+            LessThanOrEqual => bound(false, true),
+            EqualTo => Some(Constraint::In(vec![Scalar::of(&value)?])),
+            ExactlyEqualTo => match &value {
+                RuleValue::String(text) => Some(Constraint::Exactly(text.clone())),
+                value => Some(Constraint::In(vec![Scalar::of(value)?])),
+            },
+            NotEqualTo => Some(Constraint::NotIn(vec![Scalar::of(&value)?])),
+            In => list().map(Constraint::In),
+            NotIn => list().map(Constraint::NotIn),
+            _ => None,
+        }
+    }
+
+    /// Whether every string equal to `value` ignoring case satisfies the constraint, or none
+    /// does. `None` when its kind doesn't match, or when it depends on the case
+    fn admits(&self, value: &Scalar) -> Option<bool> {
+        let listed = |items: &[Scalar]| {
+            let mut found = false;
+            for item in items {
+                found |= value.same(item)?;
+            }
+            Some(found)
+        };
+        match self {
+            Constraint::Bound {
+                limit,
+                above,
+                inclusive,
+            } => Some(match value.order(limit)? {
+                Ordering::Greater => *above,
+                Ordering::Less => !*above,
+                Ordering::Equal => *inclusive,
+            }),
+            Constraint::In(items) => listed(items),
+            Constraint::NotIn(items) => listed(items).map(|found| !found),
+            Constraint::Exactly(text) => match value {
+                Scalar::Text(lowercase) if *lowercase != text.to_lowercase() => Some(false),
+                _ => None,
+            },
+        }
+    }
+
+    /// Whether the string `text`, as written, satisfies the constraint
+    fn admits_exactly(&self, text: &str) -> Option<bool> {
+        match self {
+            Constraint::Exactly(other) => Some(text == other),
+            constraint => constraint.admits(&Scalar::Text(text.to_lowercase())),
+        }
+    }
+
+    /// Whether no value satisfies both
+    fn contradicts(&self, other: &Constraint) -> bool {
+        match (self, other) {
+            (Constraint::Exactly(text), other) | (other, Constraint::Exactly(text)) => {
+                other.admits_exactly(text) == Some(false)
+            }
+            (Constraint::In(items), other) | (other, Constraint::In(items)) => {
+                !items.is_empty() && items.iter().all(|item| other.admits(item) == Some(false))
+            }
+            (
+                Constraint::Bound {
+                    limit: a,
+                    above: a_above,
+                    inclusive: a_inclusive,
+                },
+                Constraint::Bound {
+                    limit: b,
+                    above: b_above,
+                    inclusive: b_inclusive,
+                },
+            ) if a_above != b_above => {
+                let (lower, upper) = if *a_above { (a, b) } else { (b, a) };
+                match lower.order(upper) {
+                    Some(Ordering::Greater) => true,
+                    Some(Ordering::Equal) => !(*a_inclusive && *b_inclusive),
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether every value that satisfies this also satisfies `other`
+    fn implies(&self, other: &Constraint) -> bool {
+        match (self, other) {
+            (Constraint::Exactly(text), other) => other.admits_exactly(text) == Some(true),
+            (Constraint::In(items), other) => {
+                !items.is_empty() && items.iter().all(|item| other.admits(item) == Some(true))
+            }
+            (this, Constraint::NotIn(items)) => {
+                !items.is_empty() && items.iter().all(|item| this.admits(item) == Some(false))
+            }
+            (
+                Constraint::Bound {
+                    limit: a,
+                    above,
+                    inclusive: a_inclusive,
+                },
+                Constraint::Bound {
+                    limit: b,
+                    above: b_above,
+                    inclusive: b_inclusive,
+                },
+            ) if above == b_above => match a.order(b) {
+                Some(Ordering::Equal) => !*a_inclusive || *b_inclusive,
+                Some(Ordering::Greater) => *above,
+                Some(Ordering::Less) => !*above,
+                None => false,
+            },
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,46 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::version::{
+        language_compatible, version, ENGINE_VERSION, GIT_SHA, LANGUAGE_VERSION,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_version_has_every_field() {
+        let version = version();
+        assert_eq!(version.engine_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(version.language_version, LANGUAGE_VERSION);
+        assert_eq!(version.git_sha, GIT_SHA);
+
+        let serialized = serde_json::to_value(version).unwrap();
+        assert_eq!(serialized["engine_version"], json!(ENGINE_VERSION));
+        assert_eq!(serialized["language_version"], json!(LANGUAGE_VERSION));
+        assert_eq!(serialized.get("git_sha").is_some(), GIT_SHA.is_some());
+
+        let shown = version.to_string();
+        assert!(shown.starts_with(ENGINE_VERSION), "{}", shown);
+        assert!(
+            shown.ends_with(&format!(" (rule language {})", LANGUAGE_VERSION)),
+            "{}",
+            shown
+        );
+    }
+
+    #[test]
+    fn test_language_compatibility() {
+        let (major, minor) = LANGUAGE_VERSION.split_once('.').unwrap();
+        let major: u32 = major.parse().unwrap();
+        let minor: u32 = minor.parse().unwrap();
+
+        assert!(language_compatible(LANGUAGE_VERSION));
+        // Newer minor versions may use forms this engine doesn't parse
+        assert!(!language_compatible(&format!("{}.{}", major, minor + 1)));
+        assert!(!language_compatible(&format!("{}.0", major + 1)));
+        if minor > 0 {
+            assert!(language_compatible(&format!("{}.0", major)));
+        }
+        for unreadable in ["", "1", "one.zero", "unversioned"] {
+            assert!(!language_compatible(unreadable), "{}", unreadable);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! The engine and rule language versions.
+//!
+//! `ENGINE_VERSION` (the crate version), `GIT_SHA` (the commit, set by `build.rs` from git or the
+//! `ENGINE_GIT_SHA` build environment, which the Dockerfile takes as a build arg) and
+//! `LANGUAGE_VERSION`, the rule language's `major.minor`: the major is bumped when a policy that
+//! parsed before parses or decides differently, the minor when the language only gains forms.
+//! `engine::version()` returns them as an `EngineVersion`.
+//!
+//! Every evaluation response, `POST /`, `/policies/{id}/evaluate` and the gRPC `EvaluationResult`
+//! alike, carries `engine_version`, `git_sha` (when the build knew it) and `language_version`, as
+//! `GET /version` returns them; `GET /capabilities` lists `language_version` too. Shadow audit
+//! records carry the same three. The decision fingerprint hashes the crate version and the rule
+//! language version, not the commit, so builds of one release agree.
+
+mod lib;
+
+use serde::Serialize;
+use std::fmt;
+
+/// Version of this crate, folded into every fingerprint and stamped on replay bundles and
+/// compiled policies so upgrades are visible in audits
+pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Commit the engine was built from, set by `build.rs` when it could ask git or was given
+/// `ENGINE_GIT_SHA`
+pub const GIT_SHA: Option<&str> = option_env!("ENGINE_GIT_SHA");
+
+/// Version of the rule language this engine parses and how it evaluates it. The major
+/// version is bumped when a policy that parsed before parses differently or decides
+/// differently, the minor one when the language only gains forms
+pub const LANGUAGE_VERSION: &str = "1.0";
+
+/// Which engine made a decision, as returned by `GET /version` and stamped on responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct EngineVersion {
+    pub engine_version: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_sha: Option<&'static str>,
+    pub language_version: &'static str,
+}
+
+/// The version of this build
+pub fn version() -> EngineVersion {
+    EngineVersion {
+        engine_version: ENGINE_VERSION,
+        git_sha: GIT_SHA,
+        language_version: LANGUAGE_VERSION,
+    }
+}
+
+/// Whether a policy compiled for rule language `version` means the same to this engine: the
+/// same major version, no newer than `LANGUAGE_VERSION`
+pub fn language_compatible(version: &str) -> bool {
+    fn parts(version: &str) -> Option<(u32, u32)> {
+        let (major, minor) = version.split_once('.')?;
+        Some((major.parse().ok()?, minor.parse().ok()?))
+    }
+    match (parts(version), parts(LANGUAGE_VERSION)) {
+        (Some((major, minor)), Some((current_major, current_minor))) => {
+            major == current_major && minor <= current_minor
+        }
+        _ => false,
+    }
+}
+
+impl fmt::Display for EngineVersion {
+    /// `1.0.0+3f2a9c1d0b7e (rule language 1.0)`, in semver's build metadata form
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.engine_version)?;
+        if let Some(sha) = self.git_sha {
+            write!(f, "+{}", sha)?;
+        }
+        write!(f, " (rule language {})", self.language_version)
+    }
+}
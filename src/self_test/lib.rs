@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::ServerConfig;
+    use crate::self_test::{requested, run};
+
+    fn config(policy_dir: &str) -> ServerConfig {
+        ServerConfig {
+            policy_dir: Some(format!(
+                "{}/tests/fixtures/self_test/{}",
+                env!("CARGO_MANIFEST_DIR"),
+                policy_dir
+            )),
+            ..ServerConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_requested_by_flag_or_env() {
+        let unset = |_: &str| None;
+        assert!(requested(&["--self-test".to_string()], unset));
+        assert!(requested(&[], |_| Some("1".to_string())));
+        assert!(!requested(&[], |_| Some("0".to_string())));
+        assert!(!requested(&["--config=engine.toml".to_string()], unset));
+    }
+
+    #[test]
+    fn test_report_lists_each_policy_in_name_order() {
+        let report = run(&config("")).unwrap();
+        let names: Vec<_> = report.policies.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["broken.txt", "driving_licence.txt"]);
+        assert!(!report.passed());
+
+        let broken = &report.policies[0];
+        assert!(!broken.passed());
+        assert!(broken.errors[0].contains("2:49"), "{:?}", broken.errors);
+        assert!(report.policies[1].passed());
+
+        let summary = report.to_string();
+        assert!(
+            summary.starts_with("FAIL broken.txt\n  error: Parse error"),
+            "{}",
+            summary
+        );
+        assert!(summary.contains("1 of 2 policies passed\n"), "{}", summary);
+    }
+
+    #[test]
+    fn test_policies_over_the_complexity_limits_fail() {
+        let mut config = config("");
+        config.limits.max_rules = Some(1);
+        let report = run(&config).unwrap();
+        let licence = &report.policies[1];
+        assert!(!licence.passed());
+        assert!(licence.errors[0].contains("rules"), "{:?}", licence.errors);
+    }
+
+    #[test]
+    fn test_passing_policies_are_compiled_to_the_cache_dir() {
+        let cache_dir =
+            std::env::temp_dir().join(format!("engine-self-test-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let mut config = config("");
+        config.policy_cache_dir = Some(cache_dir.to_string_lossy().into_owned());
+
+        let first = run(&config).unwrap();
+        assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 1);
+        assert!(first.policies.iter().all(|policy| !policy.cached));
+        let second = run(&config).unwrap();
+        let cached: Vec<_> = second.policies.iter().map(|policy| policy.cached).collect();
+        assert_eq!(cached, [false, true]);
+        assert!(second
+            .to_string()
+            .contains("PASS driving_licence.txt (cached)"));
+        for (again, before) in second.policies.iter().zip(&first.policies) {
+            assert_eq!(
+                (&again.errors, &again.warnings),
+                (&before.errors, &before.warnings)
+            );
+        }
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+    }
+
+    #[test]
+    fn test_conformance_cases_beside_the_policies_are_run() {
+        let report = run(&config("")).unwrap();
+        let cases = report.cases.as_ref().unwrap();
+        assert_eq!(cases.cases.len(), 1);
+        assert!(cases.passed(), "{}", cases);
+        assert!(report
+            .to_string()
+            .contains("PASS driving_licence.case.json\n1 of 1 cases passed"));
+    }
+
+    #[test]
+    fn test_missing_directory_is_an_error() {
+        let error = run(&config("missing")).unwrap_err();
+        assert!(error.contains("missing"), "{}", error);
+        assert!(run(&ServerConfig::default()).is_err());
+    }
+}
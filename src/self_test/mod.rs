@@ -0,0 +1,183 @@
+mod lib;
+
+use crate::config::ServerConfig;
+use crate::runner::compiled::PolicyCache;
+use crate::runner::conformance::{run_directory, ConformanceReport};
+use crate::runner::error::RuleError;
+use crate::runner::parser::parse_rule_set_with_limits;
+use crate::runner::validator::{validate_rule_set_with_limits, Diagnostic, Severity};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Env var that runs the self-test instead of serving, like `--self-test`
+pub const SELF_TEST_VAR: &str = "SELF_TEST";
+
+/// Whether the server was asked to self-test and exit
+pub fn requested(args: &[String], var: impl Fn(&str) -> Option<String>) -> bool {
+    args.iter().any(|arg| arg == "--self-test")
+        || var(SELF_TEST_VAR).is_some_and(|value| matches!(value.trim(), "1" | "true"))
+}
+
+/// What the self-test found in one policy file
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyCheck {
+    /// File name within the policy directory
+    pub name: String,
+    /// Anything that would make the server reject the policy, empty when it passed
+    pub errors: Vec<String>,
+    /// Lint findings that don't fail the policy
+    pub warnings: Vec<String>,
+    /// Loaded from its compiled artifact in `policy_cache_dir` rather than parsed
+    pub cached: bool,
+}
+
+impl PolicyCheck {
+    pub fn passed(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Per-policy results of a self-test, in file name order
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfTestReport {
+    pub policies: Vec<PolicyCheck>,
+    /// The conformance cases (`*.case.json`) shipped beside the policies, if there are any
+    pub cases: Option<ConformanceReport>,
+}
+
+impl SelfTestReport {
+    /// True when every policy and case passed. A directory without policies fails, as it is
+    /// more likely a wrong path than an intentionally empty bundle
+    pub fn passed(&self) -> bool {
+        !self.policies.is_empty()
+            && self.policies.iter().all(PolicyCheck::passed)
+            && self.cases.as_ref().is_none_or(ConformanceReport::passed)
+    }
+}
+
+/// Checks every `.txt` policy in the configured `policy_dir` the way the server would accept
+/// it: it must parse within the parse limits, have no validator errors and stay within the
+/// complexity limits. With `policy_cache_dir` set the policies are compiled there, so the
+/// server's next cold start loads them. Conformance cases in the directory are run as well,
+/// so a bundle can ship the decisions its policies must make
+pub fn run(config: &ServerConfig) -> Result<SelfTestReport, String> {
+    let dir = config
+        .policy_dir
+        .as_deref()
+        .ok_or_else(|| "Set policy_dir (or POLICY_DIR) to the policies to self-test".to_string())?;
+    let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir, e))?;
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| format!("{}: {}", dir, e))?.path();
+        if path.is_file() && path.extension().is_some_and(|extension| extension == "txt") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let policies = paths
+        .iter()
+        .map(|path| check_policy(path, config))
+        .collect();
+    let cases = run_directory(Path::new(dir)).map_err(|e| format!("{}: {}", dir, e))?;
+    Ok(SelfTestReport {
+        policies,
+        cases: (!cases.cases.is_empty()).then_some(cases),
+    })
+}
+
+fn check_policy(path: &Path, config: &ServerConfig) -> PolicyCheck {
+    let name = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    let mut check = PolicyCheck {
+        name,
+        errors: Vec::new(),
+        warnings: Vec::new(),
+        cached: false,
+    };
+
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) => {
+            check.errors.push(error.to_string());
+            return check;
+        }
+    };
+
+    let limits = config.parse_limits();
+    let parsed = match &config.policy_cache_dir {
+        Some(dir) => PolicyCache::new(dir)
+            .parse_rule_set_cached(&text, &limits)
+            .map(|parsed| {
+                check.warnings.extend(parsed.warning);
+                check.cached = parsed.hit;
+                parsed.rule_set
+            }),
+        None => parse_rule_set_with_limits(&text, &limits),
+    };
+    let diagnostics = match parsed {
+        Ok(rule_set) => {
+            if let Err(violation) = config.complexity_limits().check(&rule_set.stats()) {
+                check
+                    .errors
+                    .push(RuleError::ComplexityLimitExceeded(violation).to_string());
+            }
+            validate_rule_set_with_limits(&rule_set, &limits)
+        }
+        Err(RuleError::ParseErrors { diagnostics }) => diagnostics,
+        Err(error) => {
+            check.errors.push(error.to_string());
+            Vec::new()
+        }
+    };
+    for diagnostic in &diagnostics {
+        match diagnostic.severity {
+            Severity::Error => check.errors.push(describe(diagnostic)),
+            Severity::Warning => check.warnings.push(describe(diagnostic)),
+        }
+    }
+    check
+}
+
+fn describe(diagnostic: &Diagnostic) -> String {
+    match &diagnostic.position {
+        Some(pos) => format!(
+            "{}:{}: [{}] {}",
+            pos.line, pos.start, diagnostic.code, diagnostic.message
+        ),
+        None => format!("[{}] {}", diagnostic.code, diagnostic.message),
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for policy in &self.policies {
+            let status = if policy.passed() { "PASS" } else { "FAIL" };
+            let cached = if policy.cached { " (cached)" } else { "" };
+            writeln!(f, "{} {}{}", status, policy.name, cached)?;
+            for error in &policy.errors {
+                writeln!(f, "  error: {}", error)?;
+            }
+            for warning in &policy.warnings {
+                writeln!(f, "  warning: {}", warning)?;
+            }
+        }
+        let passed = self
+            .policies
+            .iter()
+            .filter(|policy| policy.passed())
+            .count();
+        if self.policies.is_empty() {
+            writeln!(f, "No policies found")?;
+        } else {
+            writeln!(f, "{} of {} policies passed", passed, self.policies.len())?;
+        }
+        match &self.cases {
+            Some(cases) => write!(f, "{}", cases),
+            None => Ok(()),
+        }
+    }
+}
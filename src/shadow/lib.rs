@@ -0,0 +1,168 @@
+#[cfg(test)]
+mod tests {
+    use crate::runner::version::{ENGINE_VERSION, LANGUAGE_VERSION};
+    use crate::shadow::{AuditSink, PolicyVersion, ShadowRecord};
+    use crate::tenants::{routes, tenant_layer, Tenants, TENANT_HEADER};
+    use axum::middleware;
+    use serde_json::{json, Value};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const SENIOR_65: &str = "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 65.";
+    const SENIOR_60: &str = "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 60.";
+
+    #[derive(Debug, Default)]
+    struct CapturingSink(Mutex<Vec<ShadowRecord>>);
+
+    impl AuditSink for CapturingSink {
+        fn record(&self, record: &ShadowRecord) {
+            self.0.lock().unwrap().push(record.clone());
+        }
+    }
+
+    impl CapturingSink {
+        /// Waits for the background shadow task to record `count` evaluations
+        async fn records(&self, count: usize) -> Vec<ShadowRecord> {
+            for _ in 0..100 {
+                if self.0.lock().unwrap().len() >= count {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    async fn start_server(tenants: Arc<Tenants>) -> String {
+        let app = routes()
+            .route_layer(middleware::from_fn_with_state(
+                tenants.clone(),
+                tenant_layer,
+            ))
+            .with_state(tenants);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn post(url: String, body: Value) -> (u16, Value) {
+        let response = reqwest::Client::new()
+            .post(url)
+            .header(TENANT_HEADER, "default")
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let text = response.text().await.unwrap();
+        (status, serde_json::from_str(&text).unwrap_or(Value::Null))
+    }
+
+    fn version(id: &str, version: u32) -> PolicyVersion {
+        PolicyVersion {
+            id: id.to_string(),
+            version,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shadow_divergence_is_recorded_not_returned() {
+        let sink = Arc::new(CapturingSink::default());
+        let tenants = Arc::new(Tenants::single().with_audit_sink(sink.clone()));
+        let base = start_server(tenants.clone()).await;
+
+        for (rule, expected_version) in [(SENIOR_60, 1), (SENIOR_65, 2)] {
+            let (status, body) = post(
+                format!("{}/policies", base),
+                json!({"id": "senior", "rule": rule}),
+            )
+            .await;
+            assert_eq!(status, 201, "{}", body);
+            assert_eq!(body["version"], expected_version);
+        }
+
+        let evaluate = |age: u32| {
+            post(
+                format!("{}/policies/senior/evaluate", base),
+                json!({
+                    "data": {"Person": {"age": age}},
+                    "shadow": {"id": "senior", "version": 1},
+                }),
+            )
+        };
+        // The latest version answers; the shadow only shows up in the audit record
+        let (status, body) = evaluate(62).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["result"], false);
+        assert!(body.get("shadow").is_none());
+        let (_, body) = evaluate(70).await;
+        assert_eq!(body["result"], true);
+
+        let records = sink.records(2).await;
+        assert_eq!(records.len(), 2);
+        let diverged = records.iter().find(|r| r.diverged).unwrap();
+        assert_eq!(diverged.tenant, "default");
+        assert_eq!(diverged.policy, version("senior", 2));
+        assert_eq!(diverged.shadow, version("senior", 1));
+        assert_eq!((diverged.result, diverged.shadow_result), (false, true));
+        // Audits can tell which engine decided
+        let line = serde_json::to_value(diverged).unwrap();
+        assert_eq!(line["engine_version"], ENGINE_VERSION);
+        assert_eq!(line["language_version"], LANGUAGE_VERSION);
+        assert!(records
+            .iter()
+            .any(|r| !r.diverged && r.result && r.shadow_result));
+
+        let metrics = tenants.shadows().metrics();
+        assert!(
+            metrics.contains("engine_shadow_evaluations_total 2\n"),
+            "{}",
+            metrics
+        );
+        assert!(
+            metrics.contains("engine_shadow_divergence_total 1\n"),
+            "{}",
+            metrics
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_shadow_version_is_rejected() {
+        let sink = Arc::new(CapturingSink::default());
+        let tenants = Arc::new(Tenants::single().with_audit_sink(sink.clone()));
+        let base = start_server(tenants).await;
+        post(
+            format!("{}/policies", base),
+            json!({"id": "senior", "rule": SENIOR_65}),
+        )
+        .await;
+
+        for shadow in [
+            json!({"id": "senior", "version": 2}),
+            json!({"id": "senior", "version": 0}),
+        ] {
+            let (status, body) = post(
+                format!("{}/policies/senior/evaluate", base),
+                json!({"data": {"Person": {"age": 70}}, "shadow": shadow}),
+            )
+            .await;
+            assert_eq!(status, 404, "{}", body);
+            assert_eq!(body["code"], "policy_not_found");
+        }
+
+        // Evaluating without a shadow records nothing
+        let (status, _) = post(
+            format!("{}/policies/senior/evaluate", base),
+            json!({"data": {"Person": {"age": 70}}}),
+        )
+        .await;
+        assert_eq!(status, 200);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(sink.0.lock().unwrap().is_empty());
+    }
+}
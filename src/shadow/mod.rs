@@ -0,0 +1,178 @@
+//! Stored policy versions evaluated in the shadow of the latest.
+//!
+//! Storing a policy under an id it already has adds a new `version` (counting from 1); evaluation
+//! always uses the latest. `POST /policies/{id}/evaluate` accepts `"shadow": {"id": "...",
+//! "version": N}` to run another stored version on the same request: the caller only gets the
+//! latest version's result, and the shadow is evaluated on a background task after responding. Each
+//! shadow run goes to the audit sink (JSON lines on stdout by default, see
+//! `Tenants::with_audit_sink`) with a `diverged` flag, and is counted in
+//! `engine_shadow_evaluations_total` and `engine_shadow_divergence_total`. `DELETE /policies/{id}`
+//! removes every version of a policy; policies that included it keep the text they were stored
+//! with.
+
+mod lib;
+
+use crate::events::Event;
+use crate::runner::error::RuleError;
+use crate::runner::evaluator::evaluate;
+use crate::runner::model::RuleSet;
+use crate::runner::options::EvaluationOptions;
+use crate::runner::version::{version, EngineVersion};
+use crate::tenants::Tenants;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A stored policy version, as named in `"shadow": {"id": "driving", "version": 4}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyVersion {
+    pub id: String,
+    pub version: u32,
+}
+
+/// What a shadow evaluation decided next to the decision the caller got
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShadowRecord {
+    pub tenant: String,
+    /// The policy that answered the request
+    pub policy: PolicyVersion,
+    pub shadow: PolicyVersion,
+    pub result: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub shadow_result: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow_error: Option<String>,
+    /// The results differ, or only one of the two evaluations failed
+    pub diverged: bool,
+    /// The engine that evaluated both
+    #[serde(flatten)]
+    pub engine: EngineVersion,
+}
+
+/// Where shadow records go. Records are written from a background task, after the response
+pub trait AuditSink: fmt::Debug + Send + Sync {
+    fn record(&self, record: &ShadowRecord);
+}
+
+/// Writes each record to stdout as one JSON line
+#[derive(Debug, Default)]
+pub struct StdoutAuditSink;
+
+impl AuditSink for StdoutAuditSink {
+    fn record(&self, record: &ShadowRecord) {
+        if let Ok(line) = serde_json::to_string(record) {
+            println!("shadow {}", line);
+        }
+    }
+}
+
+/// The audit sink shadow records go to and the counters kept for them
+#[derive(Debug)]
+pub struct Shadows {
+    sink: Arc<dyn AuditSink>,
+    evaluations: AtomicU64,
+    divergences: AtomicU64,
+}
+
+impl Default for Shadows {
+    fn default() -> Self {
+        Self::new(Arc::new(StdoutAuditSink))
+    }
+}
+
+impl Shadows {
+    pub fn new(sink: Arc<dyn AuditSink>) -> Self {
+        Self {
+            sink,
+            evaluations: AtomicU64::new(0),
+            divergences: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, record: &ShadowRecord) {
+        self.evaluations.fetch_add(1, Ordering::Relaxed);
+        if record.diverged {
+            self.divergences.fetch_add(1, Ordering::Relaxed);
+        }
+        self.sink.record(record);
+    }
+
+    /// Shadow counters in the Prometheus text format
+    pub fn metrics(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE engine_shadow_evaluations_total counter");
+        let _ = writeln!(
+            out,
+            "engine_shadow_evaluations_total {}",
+            self.evaluations.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(out, "# TYPE engine_shadow_divergence_total counter");
+        let _ = writeln!(
+            out,
+            "engine_shadow_divergence_total {}",
+            self.divergences.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
+
+/// A shadow evaluation waiting for the primary decision, owning everything it reads so it
+/// can outlive the request
+pub struct ShadowRun {
+    pub tenant: String,
+    pub policy: PolicyVersion,
+    pub shadow: PolicyVersion,
+    pub rule_set: Arc<RuleSet>,
+    pub data: Value,
+    pub options: EvaluationOptions,
+}
+
+impl ShadowRun {
+    fn evaluate(&self) -> Result<bool, RuleError> {
+        let outcome = evaluate(&self.rule_set, &self.data, &self.options);
+        match outcome.error {
+            Some(error) => Err(error),
+            None => Ok(outcome.result),
+        }
+    }
+
+    /// Evaluates the shadow policy on a background task and records it against the primary
+    /// decision. The caller's response never waits for it
+    pub fn spawn(self, tenants: Arc<Tenants>, result: bool, error: Option<String>) {
+        tokio::spawn(async move {
+            let Ok((run, evaluated)) = tokio::task::spawn_blocking(move || {
+                let evaluated = self.evaluate();
+                (self, evaluated)
+            })
+            .await
+            else {
+                return;
+            };
+
+            let (shadow_result, shadow_error) = match evaluated {
+                Ok(result) => (result, None),
+                Err(error) => (false, Some(error.to_string())),
+            };
+            let diverged = result != shadow_result || error.is_some() != shadow_error.is_some();
+            let record = ShadowRecord {
+                tenant: run.tenant,
+                policy: run.policy,
+                shadow: run.shadow,
+                result,
+                error,
+                shadow_result,
+                shadow_error,
+                diverged,
+                engine: version(),
+            };
+            tenants.shadows().record(&record);
+            if let Some(event) = Event::shadow_diverged(&record) {
+                tenants.send_event(&event);
+            }
+        });
+    }
+}
@@ -0,0 +1,469 @@
+#[cfg(test)]
+mod tests {
+    use crate::ingest::INGEST_HEADER;
+    use crate::tenants::{
+        routes, tenant_layer, Quota, Tenants, TenantsConfig, API_KEY_HEADER, TENANT_HEADER,
+    };
+    use axum::{extract::State, middleware, routing::get};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    const CONFIG: &str = r#"
+        [[tenants]]
+        id = "team-a"
+        api_keys = ["key-a"]
+        max_policies = 2
+        max_rule_bytes = 200
+
+        [[tenants]]
+        id = "team-b"
+        requests_per_minute = 3
+    "#;
+
+    const SENIOR_RULE: &str = "A **Person** gets senior_discount if the __age__ of the **Person** is greater than or equal to 65.";
+
+    async fn start_server(tenants: Tenants) -> String {
+        let tenants = Arc::new(tenants);
+        let app = routes()
+            .route_layer(middleware::from_fn_with_state(
+                tenants.clone(),
+                tenant_layer,
+            ))
+            .route(
+                "/metrics",
+                get(|State(tenants): State<Arc<Tenants>>| async move { tenants.metrics() }),
+            )
+            .with_state(tenants);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn send(
+        method: reqwest::Method,
+        url: String,
+        tenant: Option<(&str, &str)>,
+        body: Option<Value>,
+    ) -> (u16, Value) {
+        let mut request = reqwest::Client::new().request(method, url);
+        if let Some((header, value)) = tenant {
+            request = request.header(header, value);
+        }
+        if let Some(body) = body {
+            request = request
+                .header("content-type", "application/json")
+                .body(body.to_string());
+        }
+
+        let response = request.send().await.unwrap();
+        let status = response.status().as_u16();
+        let text = response.text().await.unwrap();
+        (status, serde_json::from_str(&text).unwrap_or(Value::Null))
+    }
+
+    fn as_tenant(id: &str) -> Option<(&str, &str)> {
+        Some((TENANT_HEADER, id))
+    }
+
+    async fn json(response: reqwest::Response) -> Value {
+        serde_json::from_str(&response.text().await.unwrap()).unwrap()
+    }
+
+    async fn store(base: &str, tenant: &str, id: &str, rule: &str) -> (u16, Value) {
+        send(
+            reqwest::Method::POST,
+            format!("{}/policies", base),
+            as_tenant(tenant),
+            Some(json!({"id": id, "rule": rule})),
+        )
+        .await
+    }
+
+    #[test]
+    fn test_config_from_toml() {
+        let config = TenantsConfig::from_toml(CONFIG).unwrap();
+
+        assert_eq!(config.tenants.len(), 2);
+        assert_eq!(config.tenants[0].api_keys, vec!["key-a".to_string()]);
+        assert_eq!(
+            config.tenants[0].quota,
+            Quota {
+                max_policies: Some(2),
+                max_rule_bytes: Some(200),
+                requests_per_minute: None,
+            }
+        );
+        assert!(TenantsConfig::from_toml("[[tenants]]\nname = 1").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tenants_cannot_see_each_others_policies() {
+        let base = start_server(Tenants::new(TenantsConfig::from_toml(CONFIG).unwrap())).await;
+
+        let (status, body) = store(&base, "team-a", "senior", SENIOR_RULE).await;
+        assert_eq!(status, 201, "{}", body);
+        assert_eq!(body["stats"]["rules"], 1);
+
+        let evaluate = |tenant: &'static str| {
+            send(
+                reqwest::Method::POST,
+                format!("{}/policies/senior/evaluate", base),
+                as_tenant(tenant),
+                Some(json!({"data": {"Person": {"age": 70}}})),
+            )
+        };
+        let (status, body) = evaluate("team-a").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["result"], true);
+
+        let (status, body) = evaluate("team-b").await;
+        assert_eq!(status, 404);
+        assert_eq!(body["code"], "policy_not_found");
+
+        let (status, _) = send(
+            reqwest::Method::GET,
+            format!("{}/policies/senior", base),
+            as_tenant("team-b"),
+            None,
+        )
+        .await;
+        assert_eq!(status, 404);
+
+        let list = |tenant: Option<(&'static str, &'static str)>| {
+            send(
+                reqwest::Method::GET,
+                format!("{}/policies", base),
+                tenant,
+                None,
+            )
+        };
+        assert_eq!(list(as_tenant("team-b")).await.1, json!({"policies": []}));
+        // The API key resolves to the same tenant as the header
+        assert_eq!(
+            list(Some((API_KEY_HEADER, "key-a"))).await.1,
+            json!({"policies": ["senior"]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_includes_resolve_from_the_tenants_policies() {
+        let base = start_server(Tenants::new(TenantsConfig::from_toml(CONFIG).unwrap())).await;
+        let discount = "Include \"senior\".\n\nA **Person** gets a fare discount\n  if the **Person** gets senior_discount.";
+
+        // Another tenant's policy of the same id isn't visible
+        store(&base, "team-b", "senior", SENIOR_RULE).await;
+        let (status, body) = store(&base, "team-a", "discount", discount).await;
+        assert_eq!(status, 400);
+        assert_eq!(
+            body["error"],
+            "Can't include 'senior' on line 1: no document named 'senior'"
+        );
+
+        store(&base, "team-a", "senior", SENIOR_RULE).await;
+        let (status, body) = store(&base, "team-a", "discount", discount).await;
+        assert_eq!(status, 201, "{}", body);
+        assert_eq!(body["stats"]["rules"], 2);
+
+        let (status, body) = send(
+            reqwest::Method::POST,
+            format!("{}/policies/discount/evaluate", base),
+            as_tenant("team-a"),
+            Some(json!({"data": {"Person": {"age": 70}}})),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["result"], true);
+    }
+
+    #[tokio::test]
+    async fn test_stored_policies_read_flags_from_the_flags_service() {
+        // The flags client takes FLAGS_ variables as local flags
+        std::env::set_var("FLAGS_STORED_POLICY_BETA", "true");
+        let base = start_server(Tenants::new(TenantsConfig::from_toml(CONFIG).unwrap())).await;
+        let rule = "A **user** gets beta checkout\n  if flag \"stored-policy-beta\" is enabled.";
+        let (status, body) = store(&base, "team-a", "beta", rule).await;
+        assert_eq!(status, 201, "{}", body);
+
+        let (status, body) = send(
+            reqwest::Method::POST,
+            format!("{}/policies/beta/evaluate", base),
+            as_tenant("team-a"),
+            Some(json!({"data": {"user": {}}})),
+        )
+        .await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["result"], true, "{}", body);
+    }
+
+    #[tokio::test]
+    async fn test_requests_without_a_known_tenant_are_rejected() {
+        let base = start_server(Tenants::new(TenantsConfig::from_toml(CONFIG).unwrap())).await;
+        let url = format!("{}/policies", base);
+
+        let (status, body) = send(reqwest::Method::GET, url.clone(), None, None).await;
+        assert_eq!(status, 401);
+        assert_eq!(body["code"], "tenant_required");
+
+        let (status, body) =
+            send(reqwest::Method::GET, url.clone(), as_tenant("team-c"), None).await;
+        assert_eq!(status, 403);
+        assert_eq!(body["code"], "unknown_tenant");
+
+        let (status, body) = send(
+            reqwest::Method::GET,
+            url,
+            Some((API_KEY_HEADER, "stolen")),
+            None,
+        )
+        .await;
+        assert_eq!(status, 401);
+        assert_eq!(body["code"], "unknown_api_key");
+    }
+
+    #[tokio::test]
+    async fn test_quotas_are_enforced_per_tenant() {
+        let base = start_server(Tenants::new(TenantsConfig::from_toml(CONFIG).unwrap())).await;
+
+        let long_rule = format!("{} {}", SENIOR_RULE, " ".repeat(200));
+        let (status, body) = store(&base, "team-a", "long", &long_rule).await;
+        assert_eq!(status, 413);
+        assert_eq!(body["code"], "rule_too_large");
+        assert_eq!(body["limit"], 200);
+
+        assert_eq!(store(&base, "team-a", "one", SENIOR_RULE).await.0, 201);
+        assert_eq!(store(&base, "team-a", "two", SENIOR_RULE).await.0, 201);
+        // Replacing a stored policy doesn't count against the quota
+        assert_eq!(store(&base, "team-a", "two", SENIOR_RULE).await.0, 201);
+        let (status, body) = store(&base, "team-a", "three", SENIOR_RULE).await;
+        assert_eq!(status, 429);
+        assert_eq!(body["code"], "policy_quota_exceeded");
+
+        // team-b has a rate limit but no policy quota
+        for id in ["one", "two", "three"] {
+            assert_eq!(store(&base, "team-b", id, SENIOR_RULE).await.0, 201);
+        }
+        let (status, body) = store(&base, "team-b", "four", SENIOR_RULE).await;
+        assert_eq!(status, 429);
+        assert_eq!(body["code"], "rate_limited");
+        // team-a has its own window
+        assert_eq!(
+            send(
+                reqwest::Method::GET,
+                format!("{}/policies", base),
+                as_tenant("team-a"),
+                None
+            )
+            .await
+            .0,
+            200
+        );
+    }
+
+    #[tokio::test]
+    async fn test_single_tenant_mode_and_metrics() {
+        let base = start_server(Tenants::single()).await;
+
+        // Without a config every request runs as the default tenant
+        assert_eq!(store(&base, "ignored", "senior", SENIOR_RULE).await.0, 201);
+        let (status, body) = send(
+            reqwest::Method::GET,
+            format!("{}/policies/missing", base),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(status, 404, "{}", body);
+
+        let metrics = reqwest::get(format!("{}/metrics", base))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(
+            metrics,
+            "# TYPE engine_requests_total counter\n\
+             engine_requests_total{tenant=\"default\",route=\"/policies\",status=\"201\"} 1\n\
+             engine_requests_total{tenant=\"default\",route=\"/policies/{id}\",status=\"404\"} 1\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flat_data_is_read_under_its_selector_and_counted() {
+        let base = start_server(Tenants::single()).await;
+        store(&base, "default", "senior", SENIOR_RULE).await;
+        let evaluate = |body: Value| {
+            send(
+                reqwest::Method::POST,
+                format!("{}/policies/senior/evaluate", base),
+                None,
+                Some(body),
+            )
+        };
+
+        // The testcontainers example's payloads, sent without `Person`
+        let (status, body) = evaluate(json!({"data": {"age": 70}})).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["result"], true);
+        assert_eq!(body["warnings"][0]["code"], "legacy_flat_data");
+        let (_, body) = evaluate(json!({"data": {"age": 30}})).await;
+        assert_eq!(body["result"], false);
+        assert_eq!(body["warnings"][0]["code"], "legacy_flat_data");
+
+        // Turned off, the disjoint data guard refuses it
+        let (status, body) =
+            evaluate(json!({"data": {"age": 70}, "legacy_flat_data": false})).await;
+        assert_eq!(status, 422, "{}", body);
+        assert!(body.get("disjoint_data").is_some());
+        let (status, _) = evaluate(json!({"data": {"age": 70}, "legacy_flat_data": "yes"})).await;
+        assert_eq!(status, 422);
+
+        let metrics = reqwest::get(format!("{}/metrics", base))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(
+            metrics.ends_with(
+                "# TYPE engine_legacy_flat_data_total counter\n\
+                 engine_legacy_flat_data_total{tenant=\"default\"} 2\n"
+            ),
+            "{}",
+            metrics
+        );
+    }
+
+    #[tokio::test]
+    async fn test_projected_ingest_keeps_the_decision() {
+        let base = start_server(Tenants::single()).await;
+        store(&base, "default", "senior", SENIOR_RULE).await;
+        // `has a passport` is looked for among the data's properties
+        let passport = "A **Person** may travel if the **Person** has a passport.";
+        store(&base, "default", "travel", passport).await;
+
+        let evaluate = |id: &str, query: &str, body: String| {
+            reqwest::Client::new()
+                .post(format!("{}/policies/{}/evaluate{}", base, id, query))
+                .header("content-type", "application/json")
+                .body(body)
+                .send()
+        };
+        let data = json!({"Person": {"age": 70, "photo": "x".repeat(1000)}, "audit": [1, 2]});
+        let body = json!({"data": data}).to_string();
+
+        let full = evaluate("senior", "", body.clone()).await.unwrap();
+        assert_eq!(full.headers().get(INGEST_HEADER), None);
+        let full: Value = json(full).await;
+        let projected = evaluate("senior", "?ingest=projected", body).await.unwrap();
+        assert_eq!(projected.headers()[INGEST_HEADER], "projected");
+        let projected: Value = json(projected).await;
+        assert_eq!(full["result"], true);
+        assert_eq!(projected["result"], full["result"]);
+        assert_eq!(projected["trace"], full["trace"]);
+        // The echoed data is what was kept
+        assert_eq!(projected["data"], json!({"Person": {"age": 70}}));
+
+        let body = json!({"data": {"Person": {"passport": true}}}).to_string();
+        let response = evaluate("travel", "?ingest=projected", body).await.unwrap();
+        assert_eq!(response.headers()[INGEST_HEADER], "full");
+        assert_eq!(json(response).await["result"], true);
+
+        let body = json!({"data": data, "data_root": "$.Person"}).to_string();
+        let response = evaluate("senior", "?ingest=projected", body).await.unwrap();
+        assert_eq!(response.status(), 400);
+        let body: Value = json(response).await;
+        assert_eq!(body["code"], "projection_unsupported");
+
+        let response = evaluate("senior", "?ingest=projected", "{\"data\":".to_string())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        let body: Value = json(response).await;
+        assert_eq!(body["code"], "invalid_json");
+    }
+
+    #[tokio::test]
+    async fn test_stored_policies_list_and_filter_by_tags() {
+        let base = start_server(Tenants::single()).await;
+        let rule = "A **Person** gets a fare discount\n  if the **Person** gets senior_discount\n  or the **Person** is a student.\n\n\
+                    Tags: age.\n{}\n\n\
+                    Tags: education, age.\nA **Person** is a student if the __enrolled__ of the **Person** is equal to true."
+            .replace("{}", SENIOR_RULE);
+        let (status, body) = store(&base, "default", "fares", &rule).await;
+        assert_eq!(status, 201, "{}", body);
+
+        let (_, policy) = send(
+            reqwest::Method::GET,
+            format!("{}/policies/fares", base),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(
+            policy["tags"],
+            json!({"age": ["senior_discount", "student"], "education": ["student"]})
+        );
+
+        let evaluate = |filters: Value| {
+            let mut body = json!({"data": {"Person": {"age": 30, "enrolled": true}}});
+            body.as_object_mut()
+                .unwrap()
+                .extend(filters.as_object().unwrap().clone());
+            send(
+                reqwest::Method::POST,
+                format!("{}/policies/fares/evaluate", base),
+                None,
+                Some(body),
+            )
+        };
+        assert_eq!(evaluate(json!({})).await.1["result"], true);
+        assert_eq!(
+            evaluate(json!({"exclude_tags": ["education"]})).await.1["result"],
+            false
+        );
+        assert_eq!(
+            evaluate(json!({"include_tags": ["education"]})).await.1["result"],
+            true
+        );
+    }
+
+    #[tokio::test]
+    async fn test_payload_for_another_policy_is_refused() {
+        let base = start_server(Tenants::single()).await;
+        assert_eq!(store(&base, "ignored", "senior", SENIOR_RULE).await.0, 201);
+
+        let evaluate = |body: Value| {
+            send(
+                reqwest::Method::POST,
+                format!("{}/policies/senior/evaluate", base),
+                None,
+                Some(body),
+            )
+        };
+        let order = json!({"order": {"total": 40}});
+        let (status, body) = evaluate(json!({ "data": order })).await;
+        assert_eq!(status, 422, "{}", body);
+        assert_eq!(
+            body["error"],
+            "No selectors matched: expected one of [Person]; data contains [order]"
+        );
+        assert_eq!(
+            body["disjoint_data"],
+            json!({"expected": ["Person"], "found": ["order"]})
+        );
+
+        let (status, body) = evaluate(json!({"data": order, "allow_disjoint_data": true})).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["result"], false);
+
+        // Selectors match however the payload spells them
+        let (status, body) = evaluate(json!({"data": {"person": {"age": 70}}})).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["result"], true);
+    }
+}
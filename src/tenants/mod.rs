@@ -0,0 +1,849 @@
+mod lib;
+
+use crate::config;
+use crate::decision::DecisionMap;
+use crate::dry_run;
+use crate::events::{Event, EventKind, EventSink, WebhookTarget};
+use crate::ingest::{read_projected, Ingest, IngestError, IngestQuery, INGEST_HEADER};
+use crate::runner::include::{parse_rule_set_with_loader, LoadError, RuleLoader};
+use crate::runner::model::RuleSet;
+use crate::runner::options::{LegacyFlatData, ListScalarMode, TraceVerbosity};
+use crate::runner::outcome::{EvaluationWarning, LEGACY_FLAT_DATA};
+use crate::runner::stats::RuleSetStats;
+use crate::runner::trace::TraceFormat;
+use crate::runner::utils::find_global_rule;
+use crate::shadow::{AuditSink, PolicyVersion, ShadowRun, Shadows};
+use crate::traces::TraceDelivery;
+use crate::{
+    complexity_limits, error_status, evaluate_parsed_package, parse_limits, policy_cache,
+    service_options, CancelOnDrop, EchoData, RuleDataPackage,
+};
+use axum::{
+    extract::{FromRef, FromRequest, Json, MatchedPath, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+pub const TENANT_HEADER: &str = "x-tenant-id";
+pub const API_KEY_HEADER: &str = "x-api-key";
+/// Tenant every request belongs to when multitenancy is off
+pub const DEFAULT_TENANT: &str = "default";
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Tenants and their quotas, read from the TOML file named by `TENANTS_CONFIG`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TenantsConfig {
+    #[serde(default)]
+    pub tenants: Vec<TenantConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TenantConfig {
+    pub id: String,
+    /// Keys sent in `x-api-key` that identify this tenant
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    #[serde(flatten)]
+    pub quota: Quota,
+    /// Receives this tenant's events instead of the global `webhook`
+    pub webhook: Option<WebhookTarget>,
+}
+
+/// Per-tenant limits; a missing field is unlimited
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Quota {
+    pub max_policies: Option<usize>,
+    pub max_rule_bytes: Option<usize>,
+    pub requests_per_minute: Option<u32>,
+}
+
+impl TenantsConfig {
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::from_toml(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+/// The tenant a request was resolved to, added to the request extensions by `tenant_layer`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tenant {
+    pub id: String,
+    pub quota: Quota,
+}
+
+impl Tenant {
+    pub fn check_rule_size(&self, rule: &str) -> Result<(), TenantError> {
+        match self.quota.max_rule_bytes {
+            Some(limit) if rule.len() > limit => Err(TenantError::RuleTooLarge {
+                bytes: rule.len(),
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks a rule against the tenant's size quota when the request went through `tenant_layer`
+pub fn check_rule_size(tenant: &Option<Extension<Tenant>>, rule: &str) -> Result<(), TenantError> {
+    match tenant {
+        Some(Extension(tenant)) => tenant.check_rule_size(rule),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TenantError {
+    Missing,
+    UnknownTenant(String),
+    UnknownApiKey,
+    RateLimited { limit: u32, retry_after: u64 },
+    RuleTooLarge { bytes: usize, limit: usize },
+    TooManyPolicies { limit: usize },
+    PolicyNotFound(String),
+    PolicyVersionNotFound(PolicyVersion),
+}
+
+impl TenantError {
+    fn status(&self) -> StatusCode {
+        match self {
+            TenantError::Missing | TenantError::UnknownApiKey => StatusCode::UNAUTHORIZED,
+            TenantError::UnknownTenant(_) => StatusCode::FORBIDDEN,
+            TenantError::RateLimited { .. } | TenantError::TooManyPolicies { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            TenantError::RuleTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            TenantError::PolicyNotFound(_) | TenantError::PolicyVersionNotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            TenantError::Missing => "tenant_required",
+            TenantError::UnknownTenant(_) => "unknown_tenant",
+            TenantError::UnknownApiKey => "unknown_api_key",
+            TenantError::RateLimited { .. } => "rate_limited",
+            TenantError::RuleTooLarge { .. } => "rule_too_large",
+            TenantError::TooManyPolicies { .. } => "policy_quota_exceeded",
+            TenantError::PolicyNotFound(_) | TenantError::PolicyVersionNotFound(_) => {
+                "policy_not_found"
+            }
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            TenantError::Missing => format!(
+                "Requests must name a tenant with {} or {}",
+                TENANT_HEADER, API_KEY_HEADER
+            ),
+            TenantError::UnknownTenant(id) => format!("Unknown tenant '{}'", id),
+            TenantError::UnknownApiKey => "Unknown API key".to_string(),
+            TenantError::RateLimited { limit, .. } => {
+                format!("Rate limit of {} requests per minute exceeded", limit)
+            }
+            TenantError::RuleTooLarge { bytes, limit } => {
+                format!("Rule is {} bytes, more than the limit of {}", bytes, limit)
+            }
+            TenantError::TooManyPolicies { limit } => {
+                format!("Tenant already stores the maximum of {} policies", limit)
+            }
+            TenantError::PolicyNotFound(id) => format!("No policy '{}'", id),
+            TenantError::PolicyVersionNotFound(policy) => {
+                format!("No version {} of policy '{}'", policy.version, policy.id)
+            }
+        }
+    }
+}
+
+impl IntoResponse for TenantError {
+    fn into_response(self) -> Response {
+        let mut body = serde_json::json!({
+            "error": self.message(),
+            "code": self.code(),
+        });
+        match &self {
+            TenantError::RateLimited { limit, .. } => body["limit"] = (*limit).into(),
+            TenantError::RuleTooLarge { limit, .. } | TenantError::TooManyPolicies { limit } => {
+                body["limit"] = (*limit).into()
+            }
+            _ => {}
+        }
+
+        let mut response = (self.status(), Json(body)).into_response();
+        if let TenantError::RateLimited { retry_after, .. } = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
+    }
+}
+
+/// One version of a stored policy. Storing under an existing id adds a version, and the
+/// latest is the one evaluated
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredPolicy {
+    pub id: String,
+    /// Counts from 1, assigned by `Tenants::store_policy`
+    pub version: u32,
+    pub rule: String,
+    pub stats: RuleSetStats,
+    /// The outcomes of the rules carrying each tag, see `RuleSet::tag_inventory`
+    pub tags: BTreeMap<String, Vec<String>>,
+    /// The rule parsed once when stored, shared with shadow evaluations
+    #[serde(skip)]
+    pub rule_set: Arc<RuleSet>,
+}
+
+/// Tenant configuration plus the state partitioned by tenant: stored policies, rate windows
+/// and request counters
+#[derive(Debug, Default)]
+pub struct Tenants {
+    /// `None` when multitenancy is off and everything runs as `DEFAULT_TENANT`
+    config: Option<TenantsConfig>,
+    /// Every version of each policy, oldest first
+    policies: RwLock<HashMap<String, BTreeMap<String, Vec<StoredPolicy>>>>,
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+    /// Responses by (tenant, route, status)
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    /// Evaluations that read flat data as if under its selector, by tenant, see
+    /// `LegacyFlatData`
+    legacy_flat_data: Mutex<BTreeMap<String, u64>>,
+    shadows: Shadows,
+    /// Where policy and shadow events go, dropped when `None`
+    events: Option<Arc<dyn EventSink>>,
+}
+
+impl Tenants {
+    pub fn single() -> Self {
+        Self::default()
+    }
+
+    pub fn new(config: TenantsConfig) -> Self {
+        Self {
+            config: Some(config),
+            ..Self::default()
+        }
+    }
+
+    /// Sends shadow evaluation records to `sink` instead of stdout
+    #[allow(dead_code)]
+    pub fn with_audit_sink(mut self, sink: Arc<dyn AuditSink>) -> Self {
+        self.shadows = Shadows::new(sink);
+        self
+    }
+
+    pub fn shadows(&self) -> &Shadows {
+        &self.shadows
+    }
+
+    /// Sends policy and shadow events to `sink`
+    pub fn with_event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.events = Some(sink);
+        self
+    }
+
+    pub fn send_event(&self, event: &Event) {
+        if let Some(sink) = &self.events {
+            sink.send(event);
+        }
+    }
+
+    /// Counters of the event sink in the Prometheus text format
+    pub fn event_metrics(&self) -> String {
+        self.events
+            .as_ref()
+            .map(|sink| sink.metrics())
+            .unwrap_or_default()
+    }
+
+    /// The webhook of each tenant that names one in the tenants file
+    pub fn webhooks(&self) -> HashMap<String, WebhookTarget> {
+        self.config
+            .iter()
+            .flat_map(|config| &config.tenants)
+            .filter_map(|tenant| Some((tenant.id.clone(), tenant.webhook.clone()?)))
+            .collect()
+    }
+
+    /// The tenant id of every API key in the tenants file
+    pub fn api_keys(&self) -> HashMap<String, String> {
+        self.config
+            .iter()
+            .flat_map(|config| &config.tenants)
+            .flat_map(|tenant| {
+                tenant
+                    .api_keys
+                    .iter()
+                    .map(|key| (key.clone(), tenant.id.clone()))
+            })
+            .collect()
+    }
+
+    /// Multitenant when `tenants_config` names a config file, single tenant otherwise
+    pub fn from_config(path: Option<&str>) -> Self {
+        match path {
+            Some(path) => Self::new(
+                TenantsConfig::load(path)
+                    .unwrap_or_else(|e| panic!("Failed to load tenants config {}", e)),
+            ),
+            None => Self::single(),
+        }
+    }
+
+    /// An API key takes precedence over the tenant header
+    pub fn resolve(&self, headers: &HeaderMap) -> Result<Tenant, TenantError> {
+        let Some(config) = &self.config else {
+            return Ok(Tenant {
+                id: DEFAULT_TENANT.to_string(),
+                quota: Quota::default(),
+            });
+        };
+
+        let header_value = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        let found = if let Some(key) = header_value(API_KEY_HEADER) {
+            config
+                .tenants
+                .iter()
+                .find(|t| t.api_keys.iter().any(|k| k == key))
+                .ok_or(TenantError::UnknownApiKey)?
+        } else if let Some(id) = header_value(TENANT_HEADER) {
+            config
+                .tenants
+                .iter()
+                .find(|t| t.id == id)
+                .ok_or_else(|| TenantError::UnknownTenant(id.to_string()))?
+        } else {
+            return Err(TenantError::Missing);
+        };
+
+        Ok(Tenant {
+            id: found.id.clone(),
+            quota: found.quota.clone(),
+        })
+    }
+
+    /// Counts the request against the tenant's fixed one-minute window
+    pub fn check_rate(&self, tenant: &Tenant) -> Result<(), TenantError> {
+        let Some(limit) = tenant.quota.requests_per_minute else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows.entry(tenant.id.clone()).or_insert((now, 0));
+        let elapsed = now.duration_since(window.0);
+        if elapsed >= RATE_WINDOW {
+            *window = (now, 0);
+        }
+        if window.1 >= limit {
+            let retry_after = RATE_WINDOW.saturating_sub(elapsed).as_secs().max(1);
+            return Err(TenantError::RateLimited { limit, retry_after });
+        }
+        window.1 += 1;
+        Ok(())
+    }
+
+    /// Stores the policy as the next version of its id and returns the version
+    pub fn store_policy(
+        &self,
+        tenant: &Tenant,
+        mut policy: StoredPolicy,
+    ) -> Result<u32, TenantError> {
+        let mut policies = self.policies.write().unwrap();
+        let stored = policies.entry(tenant.id.clone()).or_default();
+        if let Some(limit) = tenant.quota.max_policies {
+            // A new version of a policy doesn't use up quota
+            if !stored.contains_key(&policy.id) && stored.len() >= limit {
+                return Err(TenantError::TooManyPolicies { limit });
+            }
+        }
+        let versions = stored.entry(policy.id.clone()).or_default();
+        policy.version = versions.len() as u32 + 1;
+        versions.push(policy);
+        Ok(versions.len() as u32)
+    }
+
+    /// The latest version of a policy
+    pub fn policy(&self, tenant: &Tenant, id: &str) -> Result<StoredPolicy, TenantError> {
+        self.policies
+            .read()
+            .unwrap()
+            .get(&tenant.id)
+            .and_then(|stored| stored.get(id))
+            .and_then(|versions| versions.last())
+            .cloned()
+            .ok_or_else(|| TenantError::PolicyNotFound(id.to_string()))
+    }
+
+    pub fn policy_version(
+        &self,
+        tenant: &Tenant,
+        policy: &PolicyVersion,
+    ) -> Result<StoredPolicy, TenantError> {
+        self.policies
+            .read()
+            .unwrap()
+            .get(&tenant.id)
+            .and_then(|stored| stored.get(&policy.id))
+            .and_then(|versions| versions.get((policy.version as usize).checked_sub(1)?))
+            .cloned()
+            .ok_or_else(|| TenantError::PolicyVersionNotFound(policy.clone()))
+    }
+
+    /// Removes every version of a policy and returns how many there were
+    pub fn delete_policy(&self, tenant: &Tenant, id: &str) -> Result<u32, TenantError> {
+        self.policies
+            .write()
+            .unwrap()
+            .get_mut(&tenant.id)
+            .and_then(|stored| stored.remove(id))
+            .map(|versions| versions.len() as u32)
+            .ok_or_else(|| TenantError::PolicyNotFound(id.to_string()))
+    }
+
+    pub fn policy_ids(&self, tenant: &Tenant) -> Vec<String> {
+        self.policies
+            .read()
+            .unwrap()
+            .get(&tenant.id)
+            .map(|stored| stored.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn record(&self, tenant: &str, route: &str, status: StatusCode) {
+        let mut requests = self.requests.lock().unwrap();
+        *requests
+            .entry((tenant.to_string(), route.to_string(), status.as_u16()))
+            .or_default() += 1;
+    }
+
+    /// Counts an evaluation of `tenant` whose `warnings` say it read flat data as if under its
+    /// selector, so the callers still sending it can be found
+    pub fn record_legacy_flat_data(&self, tenant: &str, warnings: &[EvaluationWarning]) {
+        if warnings
+            .iter()
+            .any(|warning| warning.code == Some(LEGACY_FLAT_DATA))
+        {
+            *self
+                .legacy_flat_data
+                .lock()
+                .unwrap()
+                .entry(tenant.to_string())
+                .or_default() += 1;
+        }
+    }
+
+    /// Request counters in the Prometheus text format, and the flat data counters once
+    /// there are any
+    pub fn metrics(&self) -> String {
+        let mut out = String::from("# TYPE engine_requests_total counter\n");
+        for ((tenant, route, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "engine_requests_total{{tenant=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                tenant, route, status, count
+            );
+        }
+        let legacy_flat_data = self.legacy_flat_data.lock().unwrap();
+        if !legacy_flat_data.is_empty() {
+            out.push_str("# TYPE engine_legacy_flat_data_total counter\n");
+            for (tenant, count) in legacy_flat_data.iter() {
+                let _ = writeln!(
+                    out,
+                    "engine_legacy_flat_data_total{{tenant=\"{}\"}} {}",
+                    tenant, count
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Resolves the tenant, applies its rate limit and records the response in its counters.
+///
+/// Requests that can't be tied to a tenant are counted under `unresolved`.
+pub async fn tenant_layer(
+    State(tenants): State<Arc<Tenants>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let tenant = match tenants.resolve(request.headers()) {
+        Ok(tenant) => tenant,
+        Err(error) => {
+            let response = error.into_response();
+            tenants.record("unresolved", &route, response.status());
+            return response;
+        }
+    };
+
+    let response = match tenants.check_rate(&tenant) {
+        Ok(()) => {
+            request.extensions_mut().insert(tenant.clone());
+            next.run(request).await
+        }
+        Err(error) => error.into_response(),
+    };
+    tenants.record(&tenant.id, &route, response.status());
+    response
+}
+
+/// Stored-policy routes, to be wrapped in `tenant_layer`
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Arc<Tenants>: FromRef<S>,
+{
+    Router::new()
+        .route("/policies", get(handle_list).post(handle_store))
+        .route("/policies/{id}", get(handle_get).delete(handle_delete))
+        .route("/policies/{id}/evaluate", post(handle_evaluate))
+}
+
+#[derive(Deserialize)]
+pub struct StorePolicyRequest {
+    pub id: String,
+    pub rule: String,
+}
+
+/// Evaluation options for a stored policy, as accepted by `POST /` minus the rule
+#[derive(Deserialize)]
+pub struct PolicyEvaluationRequest {
+    pub data: Value,
+    #[serde(default)]
+    pub context: Value,
+    pub entry: Option<String>,
+    pub outcomes: Option<Vec<String>>,
+    #[serde(default)]
+    pub include_outcomes: bool,
+    #[serde(default)]
+    pub include_formatted_rules: bool,
+    #[serde(default)]
+    pub lenient: bool,
+    #[serde(default)]
+    pub validate_data: bool,
+    #[serde(default)]
+    pub allow_disjoint_data: bool,
+    #[serde(default)]
+    pub strict_references: bool,
+    #[serde(default = "LegacyFlatData::request_default")]
+    pub legacy_flat_data: LegacyFlatData,
+    #[serde(default)]
+    pub list_scalar_mode: ListScalarMode,
+    #[serde(default)]
+    pub range_objects: bool,
+    #[serde(default)]
+    pub versions_as_strings: bool,
+    #[serde(default)]
+    pub case_sensitive_strings: bool,
+    #[serde(default)]
+    pub as_of: Option<chrono::NaiveDate>,
+    pub data_root: Option<String>,
+    #[serde(default)]
+    pub echo_data: EchoData,
+    #[serde(default)]
+    pub record_replay: bool,
+    #[serde(default)]
+    pub trace_format: TraceFormat,
+    #[serde(default)]
+    pub trace_verbosity: TraceVerbosity,
+    #[serde(default)]
+    pub stats: bool,
+    pub schema_version: Option<u32>,
+    #[serde(default)]
+    pub include_tags: Vec<String>,
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+    pub decision_map: Option<DecisionMap>,
+    /// Another stored version to evaluate alongside, reported to the audit sink only
+    pub shadow: Option<PolicyVersion>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Resolves the includes of a policy being stored from the tenant's stored policies, an
+/// include naming a policy id. The latest version of the policy is included
+struct StoredPolicies<'a> {
+    tenants: &'a Tenants,
+    tenant: &'a Tenant,
+}
+
+impl RuleLoader for StoredPolicies<'_> {
+    fn load(&self, id: &str) -> Result<String, LoadError> {
+        self.tenants
+            .policy(self.tenant, id)
+            .map(|policy| policy.rule)
+            .map_err(|_| LoadError::NotFound(id.to_string()))
+    }
+}
+
+/// `POST /policies` - parses, checks and stores a policy under the caller's tenant. Its
+/// includes are other policies of the tenant, by id
+async fn handle_store(
+    State(tenants): State<Arc<Tenants>>,
+    Extension(tenant): Extension<Tenant>,
+    Json(request): Json<StorePolicyRequest>,
+) -> Result<(StatusCode, Json<Value>), Response> {
+    tenant
+        .check_rule_size(&request.rule)
+        .map_err(IntoResponse::into_response)?;
+
+    let loader = StoredPolicies {
+        tenants: &tenants,
+        tenant: &tenant,
+    };
+    // Compiled through the policy cache when there is one, as evaluation requests are
+    let parsed = match policy_cache() {
+        Some(cache) => cache
+            .parse_rule_set_with_loader_cached(&request.rule, parse_limits(), &loader)
+            .map(|parsed| {
+                if let Some(warning) = parsed.warning {
+                    eprintln!("{}", warning);
+                }
+                parsed.rule_set
+            }),
+        None => parse_rule_set_with_loader(&request.rule, parse_limits(), &loader),
+    }
+    .and_then(|rule_set| {
+        find_global_rule(&rule_set.rules)?;
+        Ok(rule_set)
+    });
+    let rule_set = parsed.map_err(|error| {
+        let body = serde_json::json!({ "error": error.to_string() });
+        (error_status(&error), Json(body)).into_response()
+    })?;
+    let stats = rule_set.stats();
+    if let Err(violation) = complexity_limits().check(&stats) {
+        let error = crate::runner::error::RuleError::ComplexityLimitExceeded(violation.clone());
+        let body = serde_json::json!({ "error": error.to_string(), "limit_exceeded": violation });
+        return Err((error_status(&error), Json(body)).into_response());
+    }
+
+    let policy = StoredPolicy {
+        id: request.id,
+        version: 0,
+        rule: request.rule,
+        stats,
+        tags: rule_set.tag_inventory(),
+        rule_set: rule_set.freeze(),
+    };
+    let mut body = serde_json::json!({ "id": policy.id, "stats": policy.stats });
+    let id = policy.id.clone();
+    let version = tenants
+        .store_policy(&tenant, policy)
+        .map_err(IntoResponse::into_response)?;
+    body["version"] = version.into();
+    tenants.send_event(&Event::new(
+        &tenant.id,
+        EventKind::PolicyRegistered {
+            policy: PolicyVersion { id, version },
+        },
+    ));
+    Ok((StatusCode::CREATED, Json(body)))
+}
+
+/// `GET /policies` - ids of the caller's stored policies
+async fn handle_list(
+    State(tenants): State<Arc<Tenants>>,
+    Extension(tenant): Extension<Tenant>,
+) -> Json<Value> {
+    Json(serde_json::json!({ "policies": tenants.policy_ids(&tenant) }))
+}
+
+/// `GET /policies/{id}` - a stored policy with its statistics and tags
+async fn handle_get(
+    State(tenants): State<Arc<Tenants>>,
+    Extension(tenant): Extension<Tenant>,
+    Path(id): Path<String>,
+) -> Result<Json<StoredPolicy>, TenantError> {
+    tenants.policy(&tenant, &id).map(Json)
+}
+
+/// `DELETE /policies/{id}` - removes every version of a stored policy. Policies that included
+/// it keep the text they were stored with
+async fn handle_delete(
+    State(tenants): State<Arc<Tenants>>,
+    Extension(tenant): Extension<Tenant>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, TenantError> {
+    let versions = tenants.delete_policy(&tenant, &id)?;
+    let body = serde_json::json!({ "id": id, "versions": versions });
+    tenants.send_event(&Event::new(
+        &tenant.id,
+        EventKind::PolicyDeleted { id, versions },
+    ));
+    Ok(Json(body))
+}
+
+/// `POST /policies/{id}/evaluate` - evaluates the latest version of a stored policy like
+/// `POST /`. With `shadow` set, the named version is evaluated on the same request after the
+/// response and only its audit record shows the result. A dry run, asked for as on `POST /`,
+/// explains the evaluation instead and runs no shadow.
+///
+/// With `?ingest=projected`, the payload is parsed as it streams in and only the paths the
+/// policy reads are kept, unless the policy's reads can't be told from its rules. The
+/// `x-data-ingest` header says which it was
+async fn handle_evaluate(
+    State(tenants): State<Arc<Tenants>>,
+    Extension(tenant): Extension<Tenant>,
+    Path(id): Path<String>,
+    Query(query): Query<IngestQuery>,
+    body: Request,
+) -> Result<Response, Response> {
+    let policy = tenants
+        .policy(&tenant, &id)
+        .map_err(IntoResponse::into_response)?;
+    let dry_run_header = dry_run::requested(body.headers(), false);
+    let projection = match query.ingest {
+        Ingest::Projected => policy.rule_set.data_projection(),
+        Ingest::Full => None,
+    };
+    let projected = projection.is_some();
+    let request = match projection {
+        Some(projection) => {
+            let limit = config::current().max_body_bytes;
+            let request: PolicyEvaluationRequest = read_projected(
+                body.into_body(),
+                projection,
+                config::current().limits.max_scanned_keys,
+                limit,
+            )
+            .await
+            .map_err(IntoResponse::into_response)?;
+            // Both read data the projection of this policy doesn't keep
+            if request.data_root.is_some() {
+                return Err(IngestError::Unsupported("data_root").into_response());
+            }
+            if request.shadow.is_some() {
+                return Err(IngestError::Unsupported("shadow").into_response());
+            }
+            request
+        }
+        None => {
+            Json::<PolicyEvaluationRequest>::from_request(body, &())
+                .await
+                .map_err(IntoResponse::into_response)?
+                .0
+        }
+    };
+    let shadow = match &request.shadow {
+        Some(shadow) => Some(
+            tenants
+                .policy_version(&tenant, shadow)
+                .map_err(IntoResponse::into_response)?,
+        ),
+        None => None,
+    };
+    let package = RuleDataPackage {
+        rule: policy.rule,
+        data: request.data,
+        context: request.context,
+        entry: request.entry,
+        outcomes: request.outcomes,
+        include_outcomes: request.include_outcomes,
+        include_formatted_rules: request.include_formatted_rules,
+        lenient: request.lenient,
+        validate_data: request.validate_data,
+        allow_disjoint_data: request.allow_disjoint_data,
+        strict_references: request.strict_references,
+        legacy_flat_data: request.legacy_flat_data,
+        list_scalar_mode: request.list_scalar_mode,
+        range_objects: request.range_objects,
+        versions_as_strings: request.versions_as_strings,
+        case_sensitive_strings: request.case_sensitive_strings,
+        as_of: request.as_of,
+        data_root: request.data_root,
+        echo_data: request.echo_data,
+        record_replay: request.record_replay,
+        trace: TraceDelivery::Inline,
+        trace_format: request.trace_format,
+        trace_verbosity: request.trace_verbosity,
+        stats: request.stats,
+        schema_version: request.schema_version,
+        include_tags: request.include_tags,
+        exclude_tags: request.exclude_tags,
+        decision_map: request.decision_map,
+        dry_run: request.dry_run,
+    };
+
+    let ingest_header = |mut response: Response| {
+        if query.ingest == Ingest::Projected {
+            let ingest = if projected { "projected" } else { "full" };
+            response
+                .headers_mut()
+                .insert(INGEST_HEADER, HeaderValue::from_static(ingest));
+        }
+        response
+    };
+    if dry_run_header || package.dry_run {
+        let stored = PolicyVersion {
+            id: policy.id,
+            version: policy.version,
+        };
+        let (status, report) = dry_run::explain(
+            &package,
+            Some((stored, &policy.rule_set)),
+            &service_options(),
+        );
+        return Ok(ingest_header(report.into_http(status)));
+    }
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let _guard = CancelOnDrop(cancelled.clone());
+    let options = service_options().with_cancellation(cancelled);
+    let shadow_run = shadow.map(|shadow| ShadowRun {
+        tenant: tenant.id.clone(),
+        policy: PolicyVersion {
+            id: policy.id,
+            version: policy.version,
+        },
+        shadow: PolicyVersion {
+            id: shadow.id,
+            version: shadow.version,
+        },
+        rule_set: shadow.rule_set,
+        data: package.data.clone(),
+        // Not cancelled with the request, which has been answered by the time it runs
+        options: package.evaluation_options(&service_options()),
+    });
+
+    let format = package.trace_format;
+    let decision_map = package.decision_map.clone();
+    let (status, response) = tokio::task::spawn_blocking(move || {
+        evaluate_parsed_package(&package, Some(&policy.rule_set), &options)
+    })
+    .await
+    .expect("evaluation task panicked");
+    tenants.record_legacy_flat_data(&tenant.id, &response.warnings);
+    if let Some(run) = shadow_run {
+        run.spawn(tenants, response.result, response.error.clone());
+    }
+    Ok(ingest_header(response.into_http(
+        status,
+        format,
+        decision_map.as_ref(),
+    )))
+}
@@ -0,0 +1,204 @@
+#[cfg(test)]
+mod tests {
+    use crate::config::{JobSettings, ServerConfig};
+    use crate::jobs::Jobs;
+    use crate::rate_limit::RateLimiter;
+    use crate::runner::trace::RuleSetTrace;
+    use crate::tenants::Tenants;
+    use crate::traces::{
+        routes, FileTraceStore, MemoryTraceStore, StoredTrace, TraceRef, TraceStore, Traces,
+    };
+    use crate::{handle_run, AppState};
+    use axum::routing::post;
+    use axum::Router;
+    use serde_json::{json, Value};
+    use std::io;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    const ADULT_RULE: &str =
+        "A **user** is an adult if the __age__ of the **user** is greater than or equal to 18.";
+
+    /// A store that can't store, as a full disk or an unreachable bucket would be
+    #[derive(Debug)]
+    struct FailingStore;
+
+    impl TraceStore for FailingStore {
+        fn store(&self, _: &str, _: &RuleSetTrace) -> io::Result<TraceRef> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn load(&self, _: &TraceRef) -> io::Result<Option<StoredTrace>> {
+            Ok(None)
+        }
+
+        fn expire(&self, _: SystemTime) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("engine-traces-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn sample_trace() -> RuleSetTrace {
+        serde_json::from_value(json!({"execution": []})).unwrap()
+    }
+
+    async fn start_server(traces: Traces) -> String {
+        let state = AppState {
+            tenants: Arc::new(Tenants::single()),
+            rate_limiter: Arc::new(RateLimiter::new(None)),
+            config: Arc::new(ServerConfig::default()),
+            jobs: Arc::new(Jobs::from_settings(&JobSettings::default())),
+            traces: Arc::new(traces),
+        };
+        let app = Router::new()
+            .route("/", post(handle_run))
+            .merge(routes())
+            .with_state(state);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn run(base: &str, body: Value) -> (u16, Value) {
+        let response = reqwest::Client::new()
+            .post(format!("{}/", base))
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let text = response.text().await.unwrap();
+        (status, serde_json::from_str(&text).unwrap())
+    }
+
+    async fn fetch(base: &str, url: &str) -> (u16, Value) {
+        let response = reqwest::get(format!("{}{}", base, url)).await.unwrap();
+        let status = response.status().as_u16();
+        let text = response.text().await.unwrap();
+        (status, serde_json::from_str(&text).unwrap())
+    }
+
+    #[test]
+    fn test_stores_give_back_what_they_stored() {
+        let dir = scratch_dir("roundtrip");
+        let stores: Vec<Box<dyn TraceStore>> = vec![
+            Box::new(MemoryTraceStore::default()),
+            Box::new(FileTraceStore::new(&dir)),
+        ];
+        for store in stores {
+            let first = store.store("abc", &sample_trace()).unwrap();
+            let second = store.store("abc", &sample_trace()).unwrap();
+            assert_ne!(first, second, "every trace gets its own ref");
+
+            let stored = store.load(&first).unwrap().unwrap();
+            assert_eq!(stored.fingerprint, "abc");
+            assert!(stored.trace.execution.is_empty());
+            assert!(store
+                .load(&TraceRef("0123456789abcdef".to_string()))
+                .unwrap()
+                .is_none());
+
+            store
+                .expire(SystemTime::now() + Duration::from_secs(1))
+                .unwrap();
+            assert!(store.load(&first).unwrap().is_none());
+        }
+
+        // A ref can't reach outside the directory
+        let store = FileTraceStore::new(dir.join("inner"));
+        std::fs::write(dir.join("secret.json"), "{}").unwrap();
+        assert!(store
+            .load(&TraceRef("../secret".to_string()))
+            .unwrap()
+            .is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_traces_are_kept_for_their_tenant_until_they_expire() {
+        let traces = Traces::new(
+            Arc::new(MemoryTraceStore::default()),
+            Duration::from_millis(200),
+        );
+        let trace_ref = traces
+            .store(Some("acme"), Some("f00d"), &sample_trace())
+            .unwrap();
+        let stored = traces.get(Some("acme"), &trace_ref.0).unwrap().unwrap();
+        assert_eq!(stored.fingerprint, "acme:f00d");
+        assert!(traces.get(Some("other"), &trace_ref.0).unwrap().is_none());
+        assert!(traces.get(None, &trace_ref.0).unwrap().is_none());
+
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(traces.get(Some("acme"), &trace_ref.0).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stored_trace_is_answered_by_reference_and_served_back() {
+        let dir = scratch_dir("served");
+        let base = start_server(Traces::new(
+            Arc::new(FileTraceStore::new(&dir)),
+            Duration::from_secs(60),
+        ))
+        .await;
+        let request = json!({"rule": ADULT_RULE, "data": {"user": {"age": 30}}});
+
+        let (_, inline) = run(&base, request.clone()).await;
+        let mut stored_request = request;
+        stored_request["trace"] = json!("stored");
+        let (status, body) = run(&base, stored_request).await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["result"], true);
+        assert!(body.get("trace").is_none());
+        let trace_ref = body["trace_ref"].as_str().unwrap();
+        assert_eq!(body["trace_url"], format!("/traces/{}", trace_ref));
+
+        let (status, served) = fetch(&base, body["trace_url"].as_str().unwrap()).await;
+        assert_eq!(status, 200, "{}", served);
+        assert_eq!(served["trace_ref"], trace_ref);
+        assert_eq!(served["decision_fingerprint"], body["decision_fingerprint"]);
+        assert_eq!(served["trace"], inline["trace"]);
+
+        let (status, missing) = fetch(&base, "/traces/0123456789abcdef").await;
+        assert_eq!(status, 404);
+        assert_eq!(missing["code"], "trace_not_found");
+
+        // Older clients' `"trace": true` still gets the trace inline
+        let (_, body) = run(
+            &base,
+            json!({"rule": ADULT_RULE, "data": {"user": {"age": 30}}, "trace": true}),
+        )
+        .await;
+        assert!(body["trace"].is_object());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_trace_that_cannot_be_stored_is_sent_inline() {
+        let base = start_server(Traces::new(Arc::new(FailingStore), Duration::from_secs(60))).await;
+        let (status, body) = run(
+            &base,
+            json!({"rule": ADULT_RULE, "data": {"user": {"age": 12}}, "trace": "stored"}),
+        )
+        .await;
+        assert_eq!(status, 200, "{}", body);
+        assert_eq!(body["result"], false);
+        assert!(body["trace"].is_object());
+        assert!(body.get("trace_ref").is_none());
+        assert_eq!(body["warnings"][0]["code"], "trace_not_stored");
+        assert!(body["warnings"][0]["message"]
+            .as_str()
+            .unwrap()
+            .ends_with("disk full"));
+    }
+}
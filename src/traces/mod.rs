@@ -0,0 +1,375 @@
+//! Traces stored instead of sent with the response.
+//!
+//! `"trace": "stored"` on `POST /` keeps the trace out of the response for clients that can't take
+//! a large one: the server stores it through the `traces::TraceStore` on `AppState`
+//! (`FileTraceStore`, one JSON file per trace under `TRACE_STORE_DIR`, or `MemoryTraceStore`
+//! without one) and answers with `"trace_ref"` and `"trace_url": "/traces/{ref}"` in place of
+//! `trace`. `GET /traces/{ref}` serves `{"trace_ref", "decision_fingerprint", "trace"}` back,
+//! behind the tenant layer like other policy routes, only to the tenant that stored it and for
+//! `TRACE_TTL_SECS`; after that, or for an unknown ref, it answers 404 `trace_not_found`. Refs are
+//! random, so they can't be guessed from the decision. A trace the store fails to take is sent
+//! inline as usual with a `trace_not_stored` warning, so storage trouble never loses the decision.
+//! `"trace": "inline"` is the default, and the `true`/`false` older clients send means the same.
+//! Decision-mapped responses ignore it.
+
+mod lib;
+
+use crate::config::TraceSettings;
+use crate::runner::trace::RuleSetTrace;
+use crate::tenants::Tenant;
+use axum::{
+    extract::{FromRef, Json, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Code of the warning a response gets when its trace couldn't be stored and is sent inline
+pub const TRACE_NOT_STORED: &str = "trace_not_stored";
+
+/// Where a response's trace goes. Older clients send `"trace": true` or `false`, which always
+/// got the trace inline and still do
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceDelivery {
+    /// In the response
+    #[default]
+    Inline,
+    /// In the trace store, with the response naming it in `trace_ref`
+    Stored,
+}
+
+impl<'de> Deserialize<'de> for TraceDelivery {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Setting {
+            Switch(bool),
+            Named(String),
+        }
+
+        match Setting::deserialize(deserializer)? {
+            Setting::Switch(true | false) => Ok(TraceDelivery::Inline),
+            Setting::Named(name) if name == "inline" => Ok(TraceDelivery::Inline),
+            Setting::Named(name) if name == "stored" => Ok(TraceDelivery::Stored),
+            Setting::Named(name) => Err(serde::de::Error::custom(format!(
+                "expected \"inline\" or \"stored\", not \"{}\"",
+                name
+            ))),
+        }
+    }
+}
+
+/// Names a stored trace, as `GET /traces/{ref}` takes it
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct TraceRef(pub String);
+
+impl fmt::Display for TraceRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A trace as a `TraceStore` keeps it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StoredTrace {
+    /// What the trace was stored under, see `Traces::store`
+    pub fingerprint: String,
+    /// When it was stored, which its time to live counts from
+    pub stored_at: SystemTime,
+    pub trace: RuleSetTrace,
+}
+
+/// Where traces answered by reference are kept. Anyone holding a ref can ask for its trace,
+/// so the refs a store hands out must be hard to guess
+pub trait TraceStore: fmt::Debug + Send + Sync {
+    fn store(&self, fingerprint: &str, trace: &RuleSetTrace) -> io::Result<TraceRef>;
+    /// The trace stored under `trace_ref`, `None` when there is none
+    fn load(&self, trace_ref: &TraceRef) -> io::Result<Option<StoredTrace>>;
+    /// Drops the traces stored before `cutoff`
+    fn expire(&self, cutoff: SystemTime) -> io::Result<()>;
+}
+
+/// Keeps traces in memory, so they are lost on restart
+#[derive(Debug, Default)]
+pub struct MemoryTraceStore {
+    traces: DashMap<String, StoredTrace>,
+}
+
+impl TraceStore for MemoryTraceStore {
+    fn store(&self, fingerprint: &str, trace: &RuleSetTrace) -> io::Result<TraceRef> {
+        let trace_ref = new_trace_ref();
+        let stored = StoredTrace {
+            fingerprint: fingerprint.to_string(),
+            stored_at: SystemTime::now(),
+            trace: trace.clone(),
+        };
+        self.traces.insert(trace_ref.0.clone(), stored);
+        Ok(trace_ref)
+    }
+
+    fn load(&self, trace_ref: &TraceRef) -> io::Result<Option<StoredTrace>> {
+        Ok(self.traces.get(&trace_ref.0).map(|stored| stored.clone()))
+    }
+
+    fn expire(&self, cutoff: SystemTime) -> io::Result<()> {
+        self.traces.retain(|_, stored| stored.stored_at >= cutoff);
+        Ok(())
+    }
+}
+
+/// Writes each trace to a JSON file of a directory, named after its ref, so traces outlive a
+/// restart and can be shared by instances mounting the same directory
+#[derive(Debug, Clone)]
+pub struct FileTraceStore {
+    dir: PathBuf,
+}
+
+impl FileTraceStore {
+    /// The directory is created when the first trace is written
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `None` for anything that isn't a ref this store hands out, so a ref can't name a file
+    /// outside the directory
+    fn path(&self, trace_ref: &TraceRef) -> Option<PathBuf> {
+        let valid = !trace_ref.0.is_empty() && trace_ref.0.bytes().all(|b| b.is_ascii_hexdigit());
+        valid.then(|| self.dir.join(format!("{}.json", trace_ref.0)))
+    }
+}
+
+impl TraceStore for FileTraceStore {
+    /// Writes to a temporary file in the directory and renames it into place, so a trace is
+    /// never read half written
+    fn store(&self, fingerprint: &str, trace: &RuleSetTrace) -> io::Result<TraceRef> {
+        #[derive(Serialize)]
+        struct Written<'a> {
+            fingerprint: &'a str,
+            stored_at: SystemTime,
+            trace: &'a RuleSetTrace,
+        }
+
+        let trace_ref = new_trace_ref();
+        let json = serde_json::to_vec(&Written {
+            fingerprint,
+            stored_at: SystemTime::now(),
+            trace,
+        })?;
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("{}.json", trace_ref.0));
+        let temporary = path.with_extension("json.tmp");
+        let written = fs::write(&temporary, &json).and_then(|_| fs::rename(&temporary, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        written.map(|_| trace_ref)
+    }
+
+    fn load(&self, trace_ref: &TraceRef) -> io::Result<Option<StoredTrace>> {
+        let Some(path) = self.path(trace_ref) else {
+            return Ok(None);
+        };
+        match fs::read(&path) {
+            Ok(json) => Ok(Some(serde_json::from_slice(&json)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Goes by when each file was last written, without reading it
+    fn expire(&self, cutoff: SystemTime) -> io::Result<()> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let modified = entry.metadata().and_then(|metadata| metadata.modified());
+            if modified.is_ok_and(|modified| modified < cutoff) {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The trace store and how long its traces can be fetched. A trace is stored with the tenant
+/// that stored it, and only that tenant fetches it
+#[derive(Debug)]
+pub struct Traces {
+    store: Arc<dyn TraceStore>,
+    ttl: Duration,
+    /// When the store last dropped expired traces, so a large directory isn't scanned on
+    /// every request
+    last_expired: Mutex<Option<Instant>>,
+}
+
+impl Traces {
+    pub fn new(store: Arc<dyn TraceStore>, ttl: Duration) -> Self {
+        Self {
+            store,
+            ttl,
+            last_expired: Mutex::new(None),
+        }
+    }
+
+    /// Writes traces to the `dir` of `traces` in the server config, or keeps them in memory
+    /// without one
+    pub fn from_settings(settings: &TraceSettings) -> Self {
+        let store: Arc<dyn TraceStore> = match &settings.dir {
+            Some(dir) => Arc::new(FileTraceStore::new(dir)),
+            None => Arc::new(MemoryTraceStore::default()),
+        };
+        Self::new(store, Duration::from_secs(settings.ttl_secs))
+    }
+
+    /// Stores `trace` under the decision fingerprint, empty for a failed evaluation, behind
+    /// the tenant's id and a colon when a tenant stored it
+    pub fn store(
+        &self,
+        tenant: Option<&str>,
+        fingerprint: Option<&str>,
+        trace: &RuleSetTrace,
+    ) -> io::Result<TraceRef> {
+        self.expire();
+        let fingerprint = fingerprint.unwrap_or_default();
+        let key = match tenant {
+            Some(tenant) => format!("{}:{}", tenant, fingerprint),
+            None => fingerprint.to_string(),
+        };
+        self.store.store(&key, trace)
+    }
+
+    /// The trace, when `tenant` stored it and it hasn't expired
+    pub fn get(&self, tenant: Option<&str>, trace_ref: &str) -> io::Result<Option<StoredTrace>> {
+        self.expire();
+        let Some(stored) = self.store.load(&TraceRef(trace_ref.to_string()))? else {
+            return Ok(None);
+        };
+        let owner = stored.fingerprint.rsplit_once(':').map(|(owner, _)| owner);
+        let live = stored
+            .stored_at
+            .elapsed()
+            .map_or(true, |age| age < self.ttl);
+        Ok((owner == tenant && live).then_some(stored))
+    }
+
+    /// Drops expired traces at most once per time to live or minute, whichever is shorter.
+    /// `get` checks the age of what it loads, so a trace is never served past its time to
+    /// live in between
+    fn expire(&self) {
+        let interval = self.ttl.min(Duration::from_secs(60));
+        {
+            let mut last = self.last_expired.lock().unwrap();
+            if last.is_some_and(|last| last.elapsed() < interval) {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+        if let Some(cutoff) = SystemTime::now().checked_sub(self.ttl) {
+            if let Err(e) = self.store.expire(cutoff) {
+                eprintln!("Failed to drop expired traces: {}", e);
+            }
+        }
+    }
+}
+
+/// Unique within the process and hard to guess across it
+fn new_trace_ref() -> TraceRef {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    let digest = Sha256::new()
+        .chain_update(b"trace")
+        .chain_update(nanos.to_le_bytes())
+        .chain_update(COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes())
+        .chain_update(std::process::id().to_le_bytes())
+        .finalize();
+    TraceRef(
+        digest[..16]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect(),
+    )
+}
+
+/// Where `GET /traces/{ref}` serves a stored trace
+pub fn trace_url(trace_ref: &TraceRef) -> String {
+    format!("/traces/{}", trace_ref)
+}
+
+/// A stored trace as `GET /traces/{ref}` serves it
+#[derive(Serialize, Debug)]
+struct TraceResponse {
+    trace_ref: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decision_fingerprint: Option<String>,
+    trace: RuleSetTrace,
+}
+
+fn trace_not_found(trace_ref: &str) -> Response {
+    let body = serde_json::json!({
+        "error": format!("No trace with ref '{}'", trace_ref),
+        "code": "trace_not_found",
+    });
+    (StatusCode::NOT_FOUND, Json(body)).into_response()
+}
+
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+    Arc<Traces>: FromRef<S>,
+{
+    Router::new().route("/traces/{trace_ref}", get(handle_get))
+}
+
+/// `GET /traces/{ref}` - a trace stored by a `"trace": "stored"` request of the same tenant,
+/// until its time to live runs out
+async fn handle_get(
+    State(traces): State<Arc<Traces>>,
+    tenant: Option<Extension<Tenant>>,
+    Path(trace_ref): Path<String>,
+) -> Result<Json<TraceResponse>, Response> {
+    let tenant = tenant.map(|Extension(tenant)| tenant.id);
+    let lookup = trace_ref.clone();
+    let loaded = tokio::task::spawn_blocking(move || traces.get(tenant.as_deref(), &lookup))
+        .await
+        .expect("trace store task panicked");
+    match loaded {
+        Ok(Some(stored)) => {
+            let fingerprint = match stored.fingerprint.rsplit_once(':') {
+                Some((_, fingerprint)) => fingerprint,
+                None => stored.fingerprint.as_str(),
+            };
+            Ok(Json(TraceResponse {
+                trace_ref,
+                decision_fingerprint: (!fingerprint.is_empty()).then(|| fingerprint.to_string()),
+                trace: stored.trace,
+            }))
+        }
+        Ok(None) => Err(trace_not_found(&trace_ref)),
+        Err(e) => {
+            let body = serde_json::json!({
+                "error": format!("Failed to read trace '{}': {}", trace_ref, e),
+                "code": "trace_store_unavailable",
+            });
+            Err((StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response())
+        }
+    }
+}
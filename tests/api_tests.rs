@@ -1,3 +1,6 @@
+//! Runs against the server, so only with the `server` feature
+#![cfg(feature = "server")]
+
 use axum::{
     body::Body,
     http::{Request, StatusCode},
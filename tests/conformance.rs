@@ -0,0 +1,12 @@
+//! Runs the conformance kit in `fixtures/` through the library, the same cases other
+//! implementations of the language run. See `fixtures/README.md` for the case format.
+
+use engine::runner::conformance::run_directory;
+use std::path::Path;
+
+#[test]
+fn test_every_fixture_passes() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    let report = run_directory(&dir).unwrap();
+    assert!(report.passed(), "{}", report);
+}
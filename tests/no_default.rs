@@ -0,0 +1,51 @@
+//! Built with and without the default features: the library has to evaluate policies with
+//! nothing but its core dependencies, as when embedded in a lambda or compiled to WASM.
+//! Run `cargo test --no-default-features` to check the minimal build.
+
+use engine::runner::evaluator::evaluate;
+use engine::runner::options::EvaluationOptions;
+use engine::runner::parser::parse_rules;
+use engine::runner::trace::RuleSetTrace;
+use serde_json::json;
+
+const LICENCE: &str = "\
+A **driver** gets a driving licence
+  if the **driver** passes the age test
+  and the __vision__ of the **driver** is greater than 0.5.
+
+A **driver** passes the age test
+  if the __age__ of the **driver** is greater than or equal to 18.";
+
+#[test]
+fn test_policy_evaluates_with_the_core_library() {
+    let rule_set = parse_rules(LICENCE).unwrap();
+
+    let outcome = evaluate(
+        &rule_set,
+        &json!({"driver": {"age": 30, "vision": 0.9}}),
+        &EvaluationOptions::new(),
+    );
+    assert!(outcome.result, "{:?}", outcome.error);
+    assert_eq!(outcome.outcomes.get("age test"), Some(&true));
+
+    let outcome = evaluate(
+        &rule_set,
+        &json!({"driver": {"age": 16, "vision": 0.9}}),
+        &EvaluationOptions::new(),
+    );
+    assert!(!outcome.result);
+}
+
+#[test]
+fn test_trace_round_trips_through_json() {
+    let rule_set = parse_rules(LICENCE).unwrap();
+    let outcome = evaluate(
+        &rule_set,
+        &json!({"driver": {"age": 30, "vision": 0.4}}),
+        &EvaluationOptions::new(),
+    );
+
+    let trace = serde_json::to_string(&outcome.trace.unwrap()).unwrap();
+    let trace: RuleSetTrace = serde_json::from_str(&trace).unwrap();
+    assert!(trace.to_csv().contains(",$.driver.vision,is greater than,"));
+}
@@ -0,0 +1,42 @@
+//! Runs the `engine` binary, which needs the `server` feature
+#![cfg(feature = "server")]
+
+use std::path::Path;
+use std::process::Command;
+
+fn fixtures() -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/self_test")
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn test_self_test_reports_each_policy_and_fails_on_a_broken_one() {
+    let output = Command::new(env!("CARGO_BIN_EXE_engine"))
+        .arg("--self-test")
+        .env_remove("CONFIG_PATH")
+        .env("POLICY_DIR", fixtures())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.contains("FAIL broken.txt"), "{}", stdout);
+    assert!(stdout.contains("PASS driving_licence.txt"), "{}", stdout);
+    assert!(stdout.contains("1 of 2 policies passed"), "{}", stdout);
+}
+
+#[test]
+fn test_self_test_without_a_policy_dir_fails() {
+    let output = Command::new(env!("CARGO_BIN_EXE_engine"))
+        .env_remove("CONFIG_PATH")
+        .env_remove("POLICY_DIR")
+        .env("SELF_TEST", "1")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("Set policy_dir"), "{}", stderr);
+}